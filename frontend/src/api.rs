@@ -0,0 +1,65 @@
+//! Where the node's API is.
+//!
+//! The host page can point the app at a node by setting
+//! `window.SIERPCHAIN_API_BASE_URL` before loading it. Failing that, the
+//! `API_BASE_URL` environment variable at compile time is used, and failing
+//! that the local node on its default port.
+
+use web_sys::wasm_bindgen::JsValue;
+
+/// The node the app talks to if nothing else is configured.
+pub const DEFAULT_API_BASE_URL: &str = "http://127.0.0.1:8081";
+
+/// The `window` property the host page can set the base URL in.
+pub const WINDOW_BASE_URL_KEY: &str = "SIERPCHAIN_API_BASE_URL";
+
+/// Returns the configured base URL, as described in the module docs.
+pub fn base_url() -> String {
+    window_base_url()
+        .or_else(|| option_env!("API_BASE_URL").filter(|url| !url.is_empty()).map(str::to_string))
+        .unwrap_or_else(|| DEFAULT_API_BASE_URL.to_string())
+}
+
+fn window_base_url() -> Option<String> {
+    let window = web_sys::window()?;
+    let value = js_sys::Reflect::get(window.as_ref(), &JsValue::from_str(WINDOW_BASE_URL_KEY)).ok()?;
+    value.as_string().filter(|url| !url.is_empty())
+}
+
+/// Joins `path`, which may carry a query, onto `base` with exactly one
+/// slash between them.
+pub fn api_url(base: &str, path: &str) -> String {
+    format!("{}/{}", base.trim_end_matches('/'), path.trim_start_matches('/'))
+}
+
+/// Returns the WebSocket URL for `path` on the node at `base`: `ws://` for
+/// an `http://` base and `wss://` for an `https://` one.
+pub fn ws_url(base: &str, path: &str) -> String {
+    let url = api_url(base, path);
+    if let Some(rest) = url.strip_prefix("https://") {
+        format!("wss://{}", rest)
+    } else if let Some(rest) = url.strip_prefix("http://") {
+        format!("ws://{}", rest)
+    } else {
+        url
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_api_url_joins_with_one_slash() {
+        assert_eq!(api_url("http://127.0.0.1:8081", "/mine"), "http://127.0.0.1:8081/mine");
+        assert_eq!(api_url("http://127.0.0.1:8081/", "/mine"), "http://127.0.0.1:8081/mine");
+        assert_eq!(api_url("https://node.example/api/", "blocks?limit=20"), "https://node.example/api/blocks?limit=20");
+    }
+
+    #[test]
+    fn test_ws_url_follows_the_base_scheme() {
+        assert_eq!(ws_url("http://127.0.0.1:8081", "/ws?format=binary"), "ws://127.0.0.1:8081/ws?format=binary");
+        assert_eq!(ws_url("https://node.example/api", "/ws"), "wss://node.example/api/ws");
+        assert_eq!(ws_url("ws://already.example", "/ws"), "ws://already.example/ws");
+    }
+}
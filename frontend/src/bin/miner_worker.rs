@@ -0,0 +1,6 @@
+use frontend::miner::{JsonCodec, MinerWorker};
+use gloo_worker::Registrable;
+
+fn main() {
+    MinerWorker::registrar().encoding::<JsonCodec>().register();
+}
@@ -0,0 +1,114 @@
+//! Layout for the block tree from `/chain/graph`.
+//!
+//! Blocks are placed in columns by height, oldest on the left. The active
+//! chain runs along the top row and each side-chain block drops to the
+//! first row below it not taken at its height, so a branch stays beside the
+//! blocks it competed with.
+
+use serde::Deserialize;
+use std::collections::HashMap;
+
+/// The width of a column, including the gap to the next, in pixels.
+pub const COLUMN_WIDTH: f64 = 120.0;
+/// The height of a row, including the gap below it, in pixels.
+pub const ROW_HEIGHT: f64 = 56.0;
+/// The size of a block's box, in pixels.
+pub const NODE_WIDTH: f64 = 100.0;
+pub const NODE_HEIGHT: f64 = 40.0;
+/// How many heights the graph asks the node for.
+pub const GRAPH_DEPTH: u64 = 20;
+
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+pub struct GraphNode {
+    pub hash: String,
+    pub short_hash: String,
+    pub height: u64,
+    pub miner: Option<String>,
+    pub fractal_type: String,
+    pub active: bool,
+}
+
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+pub struct GraphEdge {
+    pub from: String,
+    pub to: String,
+}
+
+#[derive(Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct ChainGraph {
+    pub nodes: Vec<GraphNode>,
+    pub edges: Vec<GraphEdge>,
+}
+
+/// Where a node's box goes: the top-left corner, in pixels.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Position {
+    pub x: f64,
+    pub y: f64,
+}
+
+/// A laid-out graph: each node's position by hash, and the overall size.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Layout {
+    pub positions: HashMap<String, Position>,
+    pub width: f64,
+    pub height: f64,
+}
+
+/// Places `graph`'s nodes as described in the module docs.
+pub fn layout(graph: &ChainGraph) -> Layout {
+    let Some(lowest) = graph.nodes.iter().map(|node| node.height).min() else {
+        return Layout::default();
+    };
+    let mut rows_taken: HashMap<u64, usize> = HashMap::new();
+    let mut positions = HashMap::new();
+    let (mut columns, mut rows) = (0, 0);
+    // Active blocks first, so they claim the top row.
+    for node in graph.nodes.iter().filter(|node| node.active).chain(graph.nodes.iter().filter(|node| !node.active)) {
+        let column = (node.height - lowest) as usize;
+        let taken = rows_taken.entry(node.height).or_default();
+        let row = if node.active { 0 } else { (*taken).max(1) };
+        *taken = row + 1;
+        columns = columns.max(column + 1);
+        rows = rows.max(row + 1);
+        positions.insert(node.hash.clone(), Position { x: column as f64 * COLUMN_WIDTH, y: row as f64 * ROW_HEIGHT });
+    }
+    Layout {
+        positions,
+        width: columns as f64 * COLUMN_WIDTH - (COLUMN_WIDTH - NODE_WIDTH),
+        height: rows as f64 * ROW_HEIGHT - (ROW_HEIGHT - NODE_HEIGHT),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wasm_bindgen_test::*;
+
+    fn node(hash: &str, height: u64, active: bool) -> GraphNode {
+        GraphNode {
+            hash: hash.to_string(),
+            short_hash: hash.to_string(),
+            height,
+            miner: None,
+            fractal_type: "Sierpinski".to_string(),
+            active,
+        }
+    }
+
+    #[wasm_bindgen_test]
+    fn test_side_chain_sits_below_the_active_chain() {
+        let graph = ChainGraph {
+            nodes: vec![node("a", 5, true), node("b", 6, true), node("x", 6, false), node("c", 7, true), node("y", 7, false)],
+            edges: vec![],
+        };
+        let layout = layout(&graph);
+        assert_eq!(layout.positions["a"], Position { x: 0.0, y: 0.0 });
+        assert_eq!(layout.positions["c"], Position { x: 2.0 * COLUMN_WIDTH, y: 0.0 });
+        assert_eq!(layout.positions["x"], Position { x: COLUMN_WIDTH, y: ROW_HEIGHT });
+        assert_eq!(layout.positions["y"].y, ROW_HEIGHT);
+        assert_eq!(layout.width, 2.0 * COLUMN_WIDTH + NODE_WIDTH);
+        assert_eq!(layout.height, ROW_HEIGHT + NODE_HEIGHT);
+        assert_eq!(super::layout(&ChainGraph::default()), Layout::default());
+    }
+}
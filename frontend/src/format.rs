@@ -0,0 +1,167 @@
+//! Formatting helpers shared by the views.
+//!
+//! Amounts on chain are integer base units; they are displayed as a decimal
+//! number of coins using the number of decimals advertised by the node's
+//! `/status` endpoint.
+
+use crate::{Transaction, TxInput, TxOutput};
+
+/// The name of the coin, shown after formatted amounts.
+pub const COIN_UNIT: &str = "SIERP";
+
+/// The number of decimals to display if the node hasn't told us otherwise.
+pub const DEFAULT_DISPLAY_DECIMALS: u32 = 8;
+
+/// How often relative ages are refreshed, in milliseconds.
+pub const AGE_REFRESH_INTERVAL_MS: u32 = 15_000;
+
+/// Formats an integer with a comma between every group of three digits.
+pub fn format_thousands(value: u64) -> String {
+    let digits = value.to_string();
+    let mut formatted = String::with_capacity(digits.len() + digits.len() / 3);
+    for (i, digit) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i).is_multiple_of(3) {
+            formatted.push(',');
+        }
+        formatted.push(digit);
+    }
+    formatted
+}
+
+/// Formats an amount of base units as coins, e.g. `1,234.50000000 SIERP`.
+pub fn format_amount(base_units: u64, decimals: u32) -> String {
+    let scale = 10u64.checked_pow(decimals);
+    match scale {
+        Some(scale) if decimals > 0 => format!(
+            "{}.{:0width$} {}",
+            format_thousands(base_units / scale),
+            base_units % scale,
+            COIN_UNIT,
+            width = decimals as usize,
+        ),
+        // More decimals than a u64 can hold: every amount is a fraction of a coin.
+        None => format!("0.{:0>width$} {}", base_units, COIN_UNIT, width = decimals as usize),
+        _ => format!("{} {}", format_thousands(base_units), COIN_UNIT),
+    }
+}
+
+/// Describes how long ago `timestamp` was, relative to `now`, both in Unix
+/// seconds. Timestamps in the future are treated as happening now.
+pub fn format_age(timestamp: i64, now: i64) -> String {
+    let seconds = now.saturating_sub(timestamp);
+    match seconds {
+        i64::MIN..=4 => "just now".to_string(),
+        5..=59 => format!("{} s ago", seconds),
+        60..=3_599 => format!("{} min ago", seconds / 60),
+        3_600..=86_399 => format!("{} h ago", seconds / 3_600),
+        86_400..=172_799 => "1 day ago".to_string(),
+        _ => format!("{} days ago", seconds / 86_400),
+    }
+}
+
+/// The txid a coinbase transaction's single input points at.
+pub const COINBASE_TXID: &str = "0000000000000000000000000000000000000000000000000000000000000000";
+
+/// Whether `tx` mints new coins rather than spending earlier outputs.
+pub fn is_coinbase(tx: &Transaction) -> bool {
+    matches!(tx.inputs.as_slice(), [input] if input.txid == COINBASE_TXID)
+}
+
+/// The message a miner left in a coinbase transaction, if any. It follows
+/// the `coinbase:` tag in the input's script.
+pub fn coinbase_message(tx: &Transaction) -> Option<&str> {
+    if !is_coinbase(tx) {
+        return None;
+    }
+    tx.inputs[0].script_sig.strip_prefix("coinbase:")
+}
+
+/// Describes the output an input spends, as `txid:vout`.
+pub fn format_input(input: &TxInput) -> String {
+    if input.txid == COINBASE_TXID {
+        "Coinbase (newly minted)".to_string()
+    } else {
+        format!("{}:{}", input.txid, input.vout)
+    }
+}
+
+/// Describes an output as its amount and the address it pays.
+pub fn format_output(output: &TxOutput, decimals: u32) -> String {
+    format!("{} → {}", format_amount(output.value, decimals), output.script_pub_key)
+}
+
+/// Formats a Unix timestamp as a date and time in the browser's locale.
+pub fn format_datetime(timestamp: i64) -> String {
+    let date = js_sys::Date::new(&(timestamp as f64 * 1000.0).into());
+    String::from(date.to_locale_string("default", &js_sys::Object::new()))
+}
+
+/// Returns the current time in Unix seconds, according to the browser.
+pub fn now() -> i64 {
+    (js_sys::Date::now() / 1000.0) as i64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_thousands() {
+        assert_eq!(format_thousands(0), "0");
+        assert_eq!(format_thousands(999), "999");
+        assert_eq!(format_thousands(1_000), "1,000");
+        assert_eq!(format_thousands(123_456_789), "123,456,789");
+        assert_eq!(format_thousands(u64::MAX), "18,446,744,073,709,551,615");
+    }
+
+    #[test]
+    fn test_format_amount() {
+        assert_eq!(format_amount(0, 8), "0.00000000 SIERP");
+        assert_eq!(format_amount(50, 8), "0.00000050 SIERP");
+        assert_eq!(format_amount(123_456_789_012, 8), "1,234.56789012 SIERP");
+        assert_eq!(format_amount(u64::MAX, 8), "184,467,440,737.09551615 SIERP");
+        assert_eq!(format_amount(1_234, 0), "1,234 SIERP");
+        assert_eq!(format_amount(u64::MAX, 0), "18,446,744,073,709,551,615 SIERP");
+        assert_eq!(format_amount(12, 20), "0.00000000000000000012 SIERP");
+    }
+
+    #[test]
+    fn test_format_inputs_and_outputs() {
+        let input = |txid: &str, vout| TxInput {
+            txid: txid.to_string(),
+            vout,
+            script_sig: String::new(),
+            pub_key: String::new(),
+            sequence: 0,
+        };
+        let spend = input("ab12", 1);
+        assert_eq!(format_input(&spend), "ab12:1");
+        let mint = input(COINBASE_TXID, 0);
+        assert_eq!(format_input(&mint), "Coinbase (newly minted)");
+
+        let output = TxOutput { value: 150_000_000, script_pub_key: "sierp1recipient".to_string() };
+        assert_eq!(format_output(&output, 8), "1.50000000 SIERP → sierp1recipient");
+        assert_eq!(format_output(&output, 0), "150,000,000 SIERP → sierp1recipient");
+
+        let tx = |inputs| Transaction { id: "tx".to_string(), timestamp: 0, inputs, outputs: vec![output.clone()] };
+        assert!(is_coinbase(&tx(vec![mint.clone()])));
+        assert!(!is_coinbase(&tx(vec![spend.clone()])));
+        assert!(!is_coinbase(&tx(vec![mint.clone(), spend])));
+
+        assert_eq!(coinbase_message(&tx(vec![mint.clone()])), None);
+        let signed = TxInput { script_sig: "coinbase:gm".to_string(), ..mint };
+        assert_eq!(coinbase_message(&tx(vec![signed])), Some("gm"));
+    }
+
+    #[test]
+    fn test_format_age() {
+        assert_eq!(format_age(1_000, 1_000), "just now");
+        assert_eq!(format_age(1_010, 1_000), "just now");
+        assert_eq!(format_age(990, 1_000), "10 s ago");
+        assert_eq!(format_age(0, 150), "2 min ago");
+        assert_eq!(format_age(0, 7_200), "2 h ago");
+        assert_eq!(format_age(0, 86_400), "1 day ago");
+        assert_eq!(format_age(0, 3 * 86_400), "3 days ago");
+        assert_eq!(format_age(i64::MIN, i64::MAX), format!("{} days ago", i64::MAX / 86_400));
+    }
+}
@@ -10,11 +10,72 @@ use gloo_net::websocket::futures::WebSocket;
 use futures::stream::StreamExt;
 use web_sys::wasm_bindgen::{JsCast, Clamped};
 use serde_json;
+use std::collections::HashMap;
+use std::rc::Rc;
+use gloo_timers::callback::{Interval, Timeout};
+use gloo_events::EventListener;
+use gloo_worker::{Spawnable, WorkerBridge};
+use std::cell::RefCell;
+
+pub mod api;
+pub mod chain_graph;
+pub mod format;
+pub mod miner;
+pub mod theme;
+pub mod virtual_list;
+use api::{api_url, base_url, ws_url};
+use chain_graph::{layout, ChainGraph, GRAPH_DEPTH, NODE_HEIGHT, NODE_WIDTH};
+use miner::{mined_block, BrowserMining, BrowserMiningAction, JsonCodec, MinerOutput, MinerWorker, MINER_WORKER_PATH};
+use sierpchain_shared::fractal::sierpinski as shared;
+use sierpchain_shared::fractal::utils::RngVersion;
+use sierpchain_shared::mining::MiningTemplate;
+use theme::ColorTheme;
+use virtual_list::{next_page_before, should_load_more, visible_window, BLOCK_CARD_HEIGHT, LOAD_MORE_THRESHOLD, PAGE_SIZE, WINDOW_BUFFER};
+use format::{format_age, format_amount, format_datetime, format_input, format_output, format_thousands, coinbase_message, is_coinbase, AGE_REFRESH_INTERVAL_MS, DEFAULT_DISPLAY_DECIMALS};
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
 struct TransactRequest {
     to: String,
-    amount: u64,
+    /// A decimal number of coins; the node converts it to base units exactly.
+    amount_coins: String,
+}
+
+/// The node's proof-of-work target, from `/mining/target`.
+#[derive(Clone, PartialEq, Deserialize, Debug)]
+pub struct MiningTarget {
+    pub difficulty: usize,
+    pub target_prefix: String,
+    pub estimated_hashes: u64,
+    pub estimated_seconds_at_current_hashrate: Option<f64>,
+}
+
+/// The subset of `/status` the frontend cares about.
+#[derive(Clone, PartialEq, Deserialize, Debug)]
+pub struct NodeStatus {
+    pub display_decimals: u32,
+    /// `mainnet`, `testnet` or `regtest`; missing from older nodes.
+    #[serde(default)]
+    pub network: String,
+}
+
+impl NodeStatus {
+    /// Returns true if the node runs a test network, where its faucet is enabled.
+    pub fn is_test_network(&self) -> bool {
+        matches!(self.network.as_str(), "testnet" | "regtest")
+    }
+}
+
+/// Body of `POST /faucet`.
+#[derive(Serialize)]
+pub struct FaucetRequest {
+    pub address: String,
+    pub amount: u64,
+}
+
+/// Body of `POST /nft/claim`. Without a private key the node's wallet claims.
+#[derive(Serialize)]
+pub struct NftClaimRequest {
+    pub fractal_type: serde_json::Value,
 }
 
 #[derive(Clone, PartialEq, Deserialize, Debug)]
@@ -23,21 +84,50 @@ pub struct WalletInfo {
     pub balance: u64,
 }
 
+/// A block mined by the wallet, as listed by `/address/{address}/fractals`.
+#[derive(Clone, PartialEq, Deserialize, Debug)]
+pub struct MinerFractalEntry {
+    pub block_index: u64,
+    pub timestamp: i64,
+    pub fractal_type: String,
+    pub quality_score: f64,
+    pub fractal_thumbnail_url: String,
+}
+
 #[derive(Clone, PartialEq, Deserialize, Debug)]
 pub struct Sierpinski {
     pub depth: usize,
     pub seed: u64,
+    /// The generator the seed was fed to; missing for the LCG.
+    #[serde(default)]
+    pub rng_version: RngVersion,
+    /// Only present if the node was asked to include vertices; otherwise they
+    /// are regenerated from `depth`, `seed` and `rng_version`.
+    #[serde(default)]
     pub vertices: Vec<(f64, f64)>,
 }
 
+impl Sierpinski {
+    /// Returns the triangle vertices, regenerating them with the node's own
+    /// generator if they weren't sent.
+    pub fn vertices(&self) -> Vec<(f64, f64)> {
+        if !self.vertices.is_empty() {
+            return self.vertices.clone();
+        }
+        shared::Sierpinski::generate(self.depth, self.seed, self.rng_version).vertices().to_vec()
+    }
+}
+
 #[derive(Clone, PartialEq, Deserialize, Debug)]
 pub struct Mandelbrot {
     pub width: usize,
     pub height: usize,
-    pub x_min: f64,
-    pub x_max: f64,
-    pub y_min: f64,
-    pub y_max: f64,
+    /// Coordinates are in the node's fixed point, 1e-12 steps; see
+    /// `coord_to_f64`.
+    pub x_min: i64,
+    pub x_max: i64,
+    pub y_min: i64,
+    pub y_max: i64,
     pub max_iterations: u32,
     pub seed: u64,
     pub data: Vec<u32>,
@@ -47,17 +137,27 @@ pub struct Mandelbrot {
 pub struct Julia {
     pub width: usize,
     pub height: usize,
-    pub x_min: f64,
-    pub x_max: f64,
-    pub y_min: f64,
-    pub y_max: f64,
-    pub c_real: f64,
-    pub c_imag: f64,
+    /// Fixed point, like `Mandelbrot`'s.
+    pub x_min: i64,
+    pub x_max: i64,
+    pub y_min: i64,
+    pub y_max: i64,
+    pub c_real: i64,
+    pub c_imag: i64,
     pub max_iterations: u32,
     pub seed: u64,
     pub data: Vec<u32>,
 }
 
+/// How many fixed-point steps the node stores per 1.0 of a fractal
+/// coordinate.
+pub const COORD_SCALE: f64 = 1e12;
+
+/// A fractal coordinate as the node stores it, converted back to a float.
+pub fn coord_to_f64(steps: i64) -> f64 {
+    steps as f64 / COORD_SCALE
+}
+
 #[derive(Clone, PartialEq, Deserialize, Debug)]
 #[serde(tag = "type", content = "data")]
 pub enum FractalData {
@@ -66,6 +166,27 @@ pub enum FractalData {
     Julia(Julia),
 }
 
+impl FractalData {
+    /// The node's `FractalType` for this fractal, which identifies it when
+    /// claimed as an NFT.
+    pub fn fractal_type(&self) -> serde_json::Value {
+        match self {
+            FractalData::Sierpinski(s) => serde_json::json!({ "Sierpinski": { "depth": s.depth, "seed": s.seed } }),
+            FractalData::Mandelbrot(m) => serde_json::json!({ "Mandelbrot": {
+                "width": m.width, "height": m.height,
+                "x_min": m.x_min, "x_max": m.x_max, "y_min": m.y_min, "y_max": m.y_max,
+                "max_iterations": m.max_iterations, "seed": m.seed,
+            } }),
+            FractalData::Julia(j) => serde_json::json!({ "Julia": {
+                "width": j.width, "height": j.height,
+                "x_min": j.x_min, "x_max": j.x_max, "y_min": j.y_min, "y_max": j.y_max,
+                "c_real": j.c_real, "c_imag": j.c_imag,
+                "max_iterations": j.max_iterations, "seed": j.seed,
+            } }),
+        }
+    }
+}
+
 /// Represents a block in the SierpChain.
 #[derive(Clone, PartialEq, Deserialize, Debug)]
 pub struct Block {
@@ -76,6 +197,9 @@ pub struct Block {
     pub previous_hash: String,
     pub hash: String,
     pub nonce: u64,
+    /// The miner's style seed; 0 if none was chosen.
+    #[serde(default)]
+    pub style_seed: u64,
 }
 
 /// Represents a transaction.
@@ -111,7 +235,7 @@ pub struct SierpinskiProps {
 /// A Yew component for rendering a `Sierpinski` as an SVG.
 #[function_component(SierpinskiComponent)]
 fn sierpinski_component(props: &SierpinskiProps) -> Html {
-    let points_list = props.sierpinski.vertices.chunks(3).map(|chunk| {
+    let points_list = props.sierpinski.vertices().chunks(3).map(|chunk| {
         format!("{},{} {},{} {},{}", chunk[0].0, chunk[0].1, chunk[1].0, chunk[1].1, chunk[2].0, chunk[2].1)
     }).collect::<Vec<String>>();
 
@@ -128,32 +252,11 @@ fn sierpinski_component(props: &SierpinskiProps) -> Html {
     }
 }
 
-// HSL to RGB conversion function
-fn hsl_to_rgb(h: f64, s: f64, l: f64) -> (u8, u8, u8) {
-    let c = (1.0 - (2.0 * l - 1.0).abs()) * s;
-    let x = c * (1.0 - ((h / 60.0) % 2.0 - 1.0).abs());
-    let m = l - c / 2.0;
-    let (r_prime, g_prime, b_prime) = if h >= 0.0 && h < 60.0 {
-        (c, x, 0.0)
-    } else if h >= 60.0 && h < 120.0 {
-        (x, c, 0.0)
-    } else if h >= 120.0 && h < 180.0 {
-        (0.0, c, x)
-    } else if h >= 180.0 && h < 240.0 {
-        (0.0, x, c)
-    } else if h >= 240.0 && h < 300.0 {
-        (x, 0.0, c)
-    } else {
-        (c, 0.0, x)
-    };
-    (((r_prime + m) * 255.0) as u8,
-    ((g_prime + m) * 255.0) as u8,
-    ((b_prime + m) * 255.0) as u8)
-}
-
 #[derive(Properties, PartialEq)]
 pub struct MandelbrotProps {
     pub mandelbrot: Mandelbrot,
+    #[prop_or_default]
+    pub theme: ColorTheme,
 }
 
 #[function_component(MandelbrotComponent)]
@@ -162,8 +265,9 @@ fn mandelbrot_component(props: &MandelbrotProps) -> Html {
 
     {
         let mandelbrot = props.mandelbrot.clone();
+        let colorize = props.theme.colorize();
         let node_ref = node_ref.clone();
-        use_effect_with((mandelbrot.clone(),), move |_| {
+        use_effect_with((mandelbrot.clone(), props.theme), move |_| {
             let canvas = node_ref.cast::<web_sys::HtmlCanvasElement>().unwrap();
             let context = canvas
                 .get_context("2d")
@@ -178,14 +282,8 @@ fn mandelbrot_component(props: &MandelbrotProps) -> Html {
             canvas.set_height(height as u32);
             let mut image_data_vec = vec![0u8; (width * height * 4) as usize];
             for i in 0..(width * height) {
-                let iteration = mandelbrot.data[i];
-                let color = if iteration == mandelbrot.max_iterations {
-                    (0, 0, 0, 255) // Black for points in the set
-                } else {
-                    let hue = (iteration as f64 * 10.0) % 360.0;
-                    let (r, g, b) = hsl_to_rgb(hue, 1.0, 0.5);
-                    (r, g, b, 255)
-                };
+                let (r, g, b) = colorize(mandelbrot.data[i], mandelbrot.max_iterations);
+                let color = (r, g, b, 255);
                 let offset = i * 4;
                 image_data_vec[offset] = color.0;
                 image_data_vec[offset + 1] = color.1;
@@ -213,6 +311,8 @@ fn mandelbrot_component(props: &MandelbrotProps) -> Html {
 #[derive(Properties, PartialEq)]
 pub struct JuliaProps {
     pub julia: Julia,
+    #[prop_or_default]
+    pub theme: ColorTheme,
 }
 
 #[function_component(JuliaComponent)]
@@ -221,8 +321,9 @@ fn julia_component(props: &JuliaProps) -> Html {
 
     {
         let julia = props.julia.clone();
+        let colorize = props.theme.colorize();
         let node_ref = node_ref.clone();
-        use_effect_with((julia.clone(),), move |_| {
+        use_effect_with((julia.clone(), props.theme), move |_| {
             let canvas = node_ref.cast::<web_sys::HtmlCanvasElement>().unwrap();
             let context = canvas
                 .get_context("2d")
@@ -237,14 +338,8 @@ fn julia_component(props: &JuliaProps) -> Html {
             canvas.set_height(height as u32);
             let mut image_data_vec = vec![0u8; (width * height * 4) as usize];
             for i in 0..(width * height) {
-                let iteration = julia.data[i];
-                let color = if iteration == julia.max_iterations {
-                    (0, 0, 0, 255) // Black for points in the set
-                } else {
-                    let hue = (iteration as f64 * 10.0) % 360.0;
-                    let (r, g, b) = hsl_to_rgb(hue, 1.0, 0.5);
-                    (r, g, b, 255)
-                };
+                let (r, g, b) = colorize(julia.data[i], julia.max_iterations);
+                let color = (r, g, b, 255);
                 let offset = i * 4;
                 image_data_vec[offset] = color.0;
                 image_data_vec[offset + 1] = color.1;
@@ -274,6 +369,8 @@ fn julia_component(props: &JuliaProps) -> Html {
 #[derive(Properties, PartialEq)]
 pub struct FractalProps {
     pub fractal: FractalData,
+    #[prop_or_default]
+    pub theme: ColorTheme,
 }
 
 /// A Yew component for rendering a `FractalData` enum.
@@ -281,8 +378,40 @@ pub struct FractalProps {
 fn fractal_component(props: &FractalProps) -> Html {
     match &props.fractal {
         FractalData::Sierpinski(s) => html! { <SierpinskiComponent sierpinski={s.clone()} /> },
-        FractalData::Mandelbrot(m) => html! { <MandelbrotComponent mandelbrot={m.clone()} /> },
-        FractalData::Julia(j) => html! { <JuliaComponent julia={j.clone()} /> },
+        FractalData::Mandelbrot(m) => html! { <MandelbrotComponent mandelbrot={m.clone()} theme={props.theme} /> },
+        FractalData::Julia(j) => html! { <JuliaComponent julia={j.clone()} theme={props.theme} /> },
+    }
+}
+
+/// Properties for the `ColorThemeSelector`.
+#[derive(Properties, PartialEq)]
+pub struct ColorThemeSelectorProps {
+    pub theme: ColorTheme,
+    pub on_change: Callback<ColorTheme>,
+}
+
+/// A dropdown for choosing the colour theme of an escape-time fractal.
+#[function_component(ColorThemeSelector)]
+fn color_theme_selector(props: &ColorThemeSelectorProps) -> Html {
+    let on_change = {
+        let on_change = props.on_change.clone();
+        Callback::from(move |e: Event| {
+            let value = e.target_unchecked_into::<web_sys::HtmlSelectElement>().value();
+            if let Some(theme) = ColorTheme::from_name(&value) {
+                on_change.emit(theme);
+            }
+        })
+    };
+
+    html! {
+        <div class="theme-selector">
+            <label>{ "Theme: " }</label>
+            <select onchange={on_change}>
+                { for ColorTheme::ALL.iter().map(|theme| html! {
+                    <option value={theme.name()} selected={*theme == props.theme}>{ theme.name() }</option>
+                })}
+            </select>
+        </div>
     }
 }
 
@@ -291,6 +420,7 @@ fn fractal_component(props: &FractalProps) -> Html {
 pub enum MineRequestParams {
     Sierpinski {
         depth: usize,
+        style_seed: u64,
     },
     Mandelbrot {
         width: usize,
@@ -300,6 +430,7 @@ pub enum MineRequestParams {
         y_min: f64,
         y_max: f64,
         max_iterations: u32,
+        style_seed: u64,
     },
     Julia {
         width: usize,
@@ -311,11 +442,81 @@ pub enum MineRequestParams {
         c_real: f64,
         c_imag: f64,
         max_iterations: u32,
+        style_seed: u64,
     },
 }
 
+/// The number of hex digits in a block hash.
+pub const HASH_HEX_DIGITS: usize = 64;
+/// How often the free cells of the hash display change, in milliseconds.
+pub const HASH_ANIMATION_INTERVAL_MS: u32 = 500;
+/// How long the prefix flashes after the difficulty changes, in milliseconds.
+pub const DIFFICULTY_FLASH_MS: u32 = 800;
+
+pub fn random_hex_digit() -> char {
+    char::from_digit((js_sys::Math::random() * 16.0) as u32 % 16, 16).unwrap()
+}
+
+#[derive(Properties, PartialEq)]
+pub struct HashTargetProps {
+    pub difficulty: usize,
+}
+
+/// Draws a block hash as a row of hex cells: the first `difficulty` must be
+/// zero and are highlighted, the rest are free and keep changing to suggest
+/// the miner trying nonces.
+#[function_component(HashTargetDisplay)]
+fn hash_target_display(props: &HashTargetProps) -> Html {
+    let digits = use_state(|| (0..HASH_HEX_DIGITS).map(|_| random_hex_digit()).collect::<Vec<char>>());
+    let flashing = use_state(|| false);
+    let previous_difficulty = use_mut_ref(|| props.difficulty);
+
+    {
+        let digits = digits.clone();
+        use_effect_with((), move |_| {
+            let interval = Interval::new(HASH_ANIMATION_INTERVAL_MS, move || {
+                digits.set((0..HASH_HEX_DIGITS).map(|_| random_hex_digit()).collect());
+            });
+            move || drop(interval)
+        });
+    }
+
+    {
+        let flashing = flashing.clone();
+        use_effect_with(props.difficulty, move |difficulty| {
+            let changed = *previous_difficulty.borrow() != *difficulty;
+            *previous_difficulty.borrow_mut() = *difficulty;
+            let timeout = changed.then(|| {
+                flashing.set(true);
+                Timeout::new(DIFFICULTY_FLASH_MS, move || flashing.set(false))
+            });
+            move || drop(timeout)
+        });
+    }
+
+    html! {
+        <div class={classes!("hash-grid", flashing.then_some("flash"))}>
+            { for digits.iter().enumerate().map(|(i, digit)| {
+                if i < props.difficulty {
+                    html! { <span class="hash-cell zero">{ "0" }</span> }
+                } else {
+                    html! { <span class="hash-cell free">{ *digit }</span> }
+                }
+            }) }
+        </div>
+    }
+}
+
+#[derive(Properties, PartialEq)]
+pub struct MiningProps {
+    pub target: Option<MiningTarget>,
+    /// The hash of the newest block; the in-browser miner starts on a new
+    /// template whenever it changes.
+    pub tip: Option<String>,
+}
+
 #[function_component(MiningComponent)]
-fn mining_component() -> Html {
+fn mining_component(props: &MiningProps) -> Html {
     let fractal_type = use_state(|| "Sierpinski".to_string());
     let sierpinski_depth = use_state(|| 5);
     let mandelbrot_width = use_state(|| 50);
@@ -326,6 +527,7 @@ fn mining_component() -> Html {
     let julia_width = use_state(|| 50);
     let julia_height = use_state(|| 50);
     let julia_max_iter = use_state(|| 100);
+    let style_seed = use_state(|| 0u64);
 
     let on_fractal_type_change = {
         let fractal_type = fractal_type.clone();
@@ -346,11 +548,13 @@ fn mining_component() -> Html {
         let julia_width = julia_width.clone();
         let julia_height = julia_height.clone();
         let julia_max_iter = julia_max_iter.clone();
+        let style_seed = style_seed.clone();
 
         Callback::from(move |_| {
             let params = match (*fractal_type).as_str() {
                 "Sierpinski" => MineRequestParams::Sierpinski {
                     depth: *sierpinski_depth,
+                    style_seed: *style_seed,
                 },
                 "Mandelbrot" => MineRequestParams::Mandelbrot {
                     width: *mandelbrot_width,
@@ -358,6 +562,7 @@ fn mining_component() -> Html {
                     x_min: -2.0, x_max: 1.0,
                     y_min: -1.5, y_max: 1.5,
                     max_iterations: *mandelbrot_max_iter,
+                    style_seed: *style_seed,
                 },
                 "Julia" => MineRequestParams::Julia {
                     width: *julia_width,
@@ -367,11 +572,12 @@ fn mining_component() -> Html {
                     c_real: *julia_c_real,
                     c_imag: *julia_c_imag,
                     max_iterations: *julia_max_iter,
+                    style_seed: *style_seed,
                 },
                 _ => unreachable!(),
             };
             spawn_local(async move {
-                if let Ok(response) = Request::post("http://127.0.0.1:8081/mine")
+                if let Ok(response) = Request::post(&api_url(&base_url(), "/mine"))
                     .json(&params)
                     .unwrap()
                     .send()
@@ -388,6 +594,16 @@ fn mining_component() -> Html {
     html! {
         <div class="mining-card">
             <h2>{ "Mine a New Block" }</h2>
+            if let Some(target) = &props.target {
+                <HashTargetDisplay difficulty={target.difficulty} />
+                <p>
+                    <strong>{ "Target: " }</strong>{ format!("{} leading zeros", target.difficulty) }
+                    <br/><strong>{ "Expected hashes: " }</strong>{ format_thousands(target.estimated_hashes) }
+                    if let Some(seconds) = target.estimated_seconds_at_current_hashrate {
+                        <br/><strong>{ "Expected time: " }</strong>{ format!("{:.1} s", seconds) }
+                    }
+                </p>
+            }
             <div>
                 <label for="fractal_type">{ "Fractal Type:" }</label>
                 <select id="fractal_type" onchange={on_fractal_type_change}>
@@ -459,14 +675,14 @@ fn mining_component() -> Html {
                                 <label for="julia_c_real">{ "C (Real):" }</label>
                                 <input type="number" step="0.01" id="julia_c_real" value={julia_c_real.to_string()} onchange={Callback::from(move |e: Event| {
                                     let value = e.target_unchecked_into::<web_sys::HtmlInputElement>().value();
-                                    julia_c_real.set(value.parse().unwrap_or(-0.8));
+                                    julia_c_real.set(value.parse().ok().filter(|c: &f64| c.is_finite()).unwrap_or(-0.8));
                                 })}/>
                             </div>
                             <div>
                                 <label for="julia_c_imag">{ "C (Imaginary):" }</label>
                                 <input type="number" step="0.001" id="julia_c_imag" value={julia_c_imag.to_string()} onchange={Callback::from(move |e: Event| {
                                     let value = e.target_unchecked_into::<web_sys::HtmlInputElement>().value();
-                                    julia_c_imag.set(value.parse().unwrap_or(0.156));
+                                    julia_c_imag.set(value.parse().ok().filter(|c: &f64| c.is_finite()).unwrap_or(0.156));
                                 })}/>
                             </div>
                         </>
@@ -474,30 +690,232 @@ fn mining_component() -> Html {
                     _ => html! {}
                 }
             }
+            <div>
+                <label for="style_seed" title="Shapes the fractal along with the nonce the miner finds">{ "Style Seed:" }</label>
+                <input type="number" min="0" id="style_seed" value={style_seed.to_string()} onchange={Callback::from(move |e: Event| {
+                    let value = e.target_unchecked_into::<web_sys::HtmlInputElement>().value();
+                    style_seed.set(value.parse().unwrap_or(0));
+                })}/>
+            </div>
             <button onclick={on_mine_click}>{ "Mine Block" }</button>
+            <BrowserMinerComponent tip={props.tip.clone()} />
+        </div>
+    }
+}
+
+/// The in-browser miner's worker and the template it is searching, shared
+/// by the callbacks that drive it.
+#[derive(Clone)]
+struct BrowserMiner {
+    enabled: UseStateHandle<bool>,
+    bridge: Rc<RefCell<Option<WorkerBridge<MinerWorker>>>>,
+    /// The `/mining/template` response being searched, until a nonce is found.
+    template: Rc<RefCell<Option<serde_json::Value>>>,
+    progress: UseReducerDispatcher<BrowserMining>,
+}
+
+impl BrowserMiner {
+    /// Starts the worker, or ends it if `enabled` is false.
+    fn set_running(&self, enabled: bool) {
+        if !enabled {
+            // Dropping the only bridge ends the worker.
+            self.bridge.borrow_mut().take();
+            self.template.borrow_mut().take();
+            return;
+        }
+        let miner = self.clone();
+        let bridge = MinerWorker::spawner()
+            .callback(move |output| miner.on_output(output))
+            .encoding::<JsonCodec>()
+            .spawn(MINER_WORKER_PATH);
+        *self.bridge.borrow_mut() = Some(bridge);
+        self.mine_next_template();
+    }
+
+    /// Fetches a fresh `/mining/template` and sets the worker on it.
+    fn mine_next_template(&self) {
+        let miner = self.clone();
+        spawn_local(async move {
+            let template = match Request::get(&api_url(&base_url(), "/mining/template")).send().await {
+                Ok(response) if response.ok() => response.json::<serde_json::Value>().await.map_err(|e| e.to_string()),
+                Ok(response) => Err(response.text().await.unwrap_or_default()),
+                Err(e) => Err(e.to_string()),
+            };
+            let parsed = template.and_then(|json| Ok((serde_json::from_value::<MiningTemplate>(json.clone()).map_err(|e| e.to_string())?, json)));
+            let (parsed, json) = match parsed {
+                Ok(template) => template,
+                Err(e) => return miner.fail(format!("Couldn't fetch a mining template: {}", e)),
+            };
+            if let Some(bridge) = miner.bridge.borrow().as_ref() {
+                *miner.template.borrow_mut() = Some(json);
+                miner.progress.dispatch(BrowserMiningAction::Started { at: js_sys::Date::now() });
+                bridge.send(parsed);
+            }
+        });
+    }
+
+    /// Returns true if the template being searched builds on a block other
+    /// than `tip`, so whatever it finds would be refused.
+    fn is_stale(&self, tip: Option<&str>) -> bool {
+        self.template.borrow().as_ref().is_some_and(|template| template["block"]["previous_hash"].as_str() != tip)
+    }
+
+    fn on_output(&self, output: MinerOutput) {
+        let mined = match output {
+            MinerOutput::Progress { attempts } => {
+                self.progress.dispatch(BrowserMiningAction::Progress { attempts, at: js_sys::Date::now() });
+                return;
+            }
+            MinerOutput::Found(mined) => mined,
+        };
+        let Some(template) = self.template.borrow_mut().take() else {
+            return;
+        };
+        let block = mined_block(&template, &mined);
+        let miner = self.clone();
+        spawn_local(async move {
+            match Request::post(&api_url(&base_url(), "/mining/submit")).json(&block).unwrap().send().await {
+                Ok(response) if response.ok() => {
+                    log::info!("Mined block {} in the browser", mined.hash);
+                    miner.progress.dispatch(BrowserMiningAction::Accepted);
+                    miner.mine_next_template();
+                }
+                // Another block got in first; mine on top of it instead.
+                Ok(response) if response.status() == 409 => miner.mine_next_template(),
+                Ok(response) => miner.fail(format!("The node refused the block: {}", response.text().await.unwrap_or_default())),
+                Err(e) => miner.fail(e.to_string()),
+            }
+        });
+    }
+
+    fn fail(&self, error: String) {
+        log::error!("In-browser mining stopped: {}", error);
+        self.progress.dispatch(BrowserMiningAction::Failed(error));
+        self.enabled.set(false);
+    }
+}
+
+#[derive(Properties, PartialEq)]
+pub struct BrowserMinerProps {
+    /// The hash of the newest block.
+    pub tip: Option<String>,
+}
+
+/// A toggle that mines blocks for the node's wallet in a Web Worker, so the
+/// page stays responsive, and shows how it is getting on.
+#[function_component(BrowserMinerComponent)]
+fn browser_miner_component(props: &BrowserMinerProps) -> Html {
+    let enabled = use_state(|| false);
+    let progress = use_reducer(BrowserMining::default);
+    let miner = BrowserMiner {
+        enabled: enabled.clone(),
+        bridge: use_mut_ref(|| None),
+        template: use_mut_ref(|| None),
+        progress: progress.dispatcher(),
+    };
+
+    {
+        let miner = miner.clone();
+        use_effect_with(*enabled, move |enabled| {
+            miner.set_running(*enabled);
+            move || miner.set_running(false)
+        });
+    }
+
+    {
+        let miner = miner.clone();
+        let enabled = *enabled;
+        use_effect_with(props.tip.clone(), move |tip| {
+            if enabled && miner.is_stale(tip.as_deref()) {
+                miner.mine_next_template();
+            }
+            || ()
+        });
+    }
+
+    // Stop before the page goes away rather than leave the worker behind,
+    // e.g. in the back/forward cache.
+    {
+        let miner = miner.clone();
+        use_effect_with((), move |_| {
+            let window = web_sys::window().unwrap();
+            let listener = EventListener::new(&window, "pagehide", move |_| {
+                miner.set_running(false);
+                miner.enabled.set(false);
+            });
+            move || drop(listener)
+        });
+    }
+
+    let on_toggle = {
+        let enabled = enabled.clone();
+        Callback::from(move |e: Event| enabled.set(e.target_unchecked_into::<web_sys::HtmlInputElement>().checked()))
+    };
+
+    html! {
+        <div class="browser-miner">
+            <label>
+                <input type="checkbox" checked={*enabled} onchange={on_toggle} />
+                { " Mine in browser" }
+            </label>
+            if *enabled {
+                <p>
+                    { format!("Tried {} nonces", format_thousands(progress.attempts)) }
+                    if let Some(rate) = progress.hashes_per_second {
+                        { format!(" at {:.0} H/s", rate) }
+                    }
+                </p>
+            }
+            if progress.accepted > 0 {
+                <p>{ format!("Blocks mined here: {}", format_thousands(progress.accepted)) }</p>
+            }
+            if let Some(error) = &progress.error {
+                <p class="error">{ error }</p>
+            }
         </div>
     }
 }
 
+#[derive(Properties, PartialEq)]
+pub struct WalletProps {
+    pub display_decimals: u32,
+    pub now: i64,
+    /// Shows the "Get test coins" button.
+    pub test_network: bool,
+}
+
 #[function_component(WalletComponent)]
-fn wallet_component() -> Html {
+fn wallet_component(props: &WalletProps) -> Html {
     let wallet_info = use_state(|| None);
     let to_address = use_state(String::new);
-    let amount = use_state(|| 0);
+    let amount = use_state(String::new);
+    let show_fractals = use_state(|| false);
+    let my_fractals = use_state(Vec::<MinerFractalEntry>::new);
 
     {
         let wallet_info = wallet_info.clone();
         use_effect_with((), move |_| {
-            let wallet_info = wallet_info.clone();
-            spawn_local(async move {
-                if let Ok(response) = Request::get("http://127.0.0.1:8081/wallet/info").send().await {
-                    if response.ok() {
-                        if let Ok(info) = response.json::<WalletInfo>().await {
-                            wallet_info.set(Some(info));
+            fetch_wallet_info(wallet_info);
+            || ()
+        });
+    }
+
+    {
+        let my_fractals = my_fractals.clone();
+        let address = (*wallet_info).as_ref().map(|info: &WalletInfo| info.address.clone());
+        use_effect_with((address, *show_fractals), move |(address, show_fractals)| {
+            if let (Some(address), true) = (address.clone(), *show_fractals) {
+                spawn_local(async move {
+                    let url = api_url(&base_url(), &format!("/address/{}/fractals?limit=100", address));
+                    if let Ok(response) = Request::get(&url).send().await {
+                        if response.ok() {
+                            if let Ok(entries) = response.json::<Vec<MinerFractalEntry>>().await {
+                                my_fractals.set(entries);
+                            }
                         }
                     }
-                }
-            });
+                });
+            }
             || ()
         });
     }
@@ -508,10 +926,10 @@ fn wallet_component() -> Html {
         Callback::from(move |e: SubmitEvent| {
             e.prevent_default();
             let to = (*to_address).clone();
-            let amnt = *amount;
+            let amount_coins = (*amount).clone();
             spawn_local(async move {
-                let req = TransactRequest { to, amount: amnt };
-                if let Ok(response) = Request::post("http://127.0.0.1:8081/transact").json(&req).unwrap().send().await {
+                let req = TransactRequest { to, amount_coins };
+                if let Ok(response) = Request::post(&api_url(&base_url(), "/transact")).json(&req).unwrap().send().await {
                     if response.ok() {
                         log::info!("Transaction successful");
                     } else {
@@ -534,28 +952,80 @@ fn wallet_component() -> Html {
         let amount = amount.clone();
         Callback::from(move |e: Event| {
             let value = e.target_unchecked_into::<web_sys::HtmlInputElement>().value();
-            amount.set(value.parse().unwrap_or(0));
+            amount.set(value);
+        })
+    };
+
+    let on_get_test_coins = {
+        let wallet_info = wallet_info.clone();
+        let display_decimals = props.display_decimals;
+        Callback::from(move |_| {
+            let Some(info) = (*wallet_info).clone() else { return };
+            let wallet_info = wallet_info.clone();
+            // One whole coin.
+            let req = FaucetRequest { address: info.address, amount: 10u64.pow(display_decimals) };
+            spawn_local(async move {
+                if let Ok(response) = Request::post(&api_url(&base_url(), "/faucet")).json(&req).unwrap().send().await {
+                    if response.ok() {
+                        log::info!("Test coins requested");
+                        fetch_wallet_info(wallet_info);
+                    } else {
+                        log::error!("Faucet request failed: {}", response.text().await.unwrap_or_default());
+                    }
+                }
+            });
         })
     };
 
+    let on_send_tab = {
+        let show_fractals = show_fractals.clone();
+        Callback::from(move |_| show_fractals.set(false))
+    };
+
+    let on_fractals_tab = {
+        let show_fractals = show_fractals.clone();
+        Callback::from(move |_| show_fractals.set(true))
+    };
+
     if let Some(info) = &*wallet_info {
         html! {
             <div class="wallet-card">
                 <h2>{ "My Wallet" }</h2>
                 <p><strong>{ "Address: " }</strong>{ &info.address }</p>
-                <p><strong>{ "Balance: " }</strong>{ info.balance }</p>
-                <form onsubmit={on_submit}>
-                    <h3>{ "Send Funds" }</h3>
-                    <div>
-                        <label for="to_address">{ "To Address:" }</label>
-                        <input type="text" id="to_address" value={(*to_address).clone()} onchange={on_to_address_change} />
-                    </div>
-                    <div>
-                        <label for="amount">{ "Amount:" }</label>
-                        <input type="number" id="amount" value={amount.to_string()} onchange={on_amount_change} />
+                <p><strong>{ "Balance: " }</strong>{ format_amount(info.balance, props.display_decimals) }</p>
+                if props.test_network {
+                    <button onclick={on_get_test_coins}>{ "Get test coins" }</button>
+                }
+                <div class="wallet-tabs">
+                    <button onclick={on_send_tab} disabled={!*show_fractals}>{ "Send Funds" }</button>
+                    <button onclick={on_fractals_tab} disabled={*show_fractals}>{ "My Fractals" }</button>
+                </div>
+                if *show_fractals {
+                    <div class="fractal-grid">
+                        { for my_fractals.iter().map(|entry| html! {
+                            <figure key={entry.block_index}>
+                                <img src={api_url(&base_url(), &entry.fractal_thumbnail_url)} alt={entry.fractal_type.clone()} width="128" />
+                                <figcaption title={format_datetime(entry.timestamp)}>
+                                    { format!("#{} {} ({:.2})", format_thousands(entry.block_index), entry.fractal_type, entry.quality_score) }
+                                    <br/>{ format_age(entry.timestamp, props.now) }
+                                </figcaption>
+                            </figure>
+                        }) }
                     </div>
-                    <button type="submit">{ "Send" }</button>
-                </form>
+                } else {
+                    <form onsubmit={on_submit}>
+                        <h3>{ "Send Funds" }</h3>
+                        <div>
+                            <label for="to_address">{ "To Address:" }</label>
+                            <input type="text" id="to_address" value={(*to_address).clone()} onchange={on_to_address_change} />
+                        </div>
+                        <div>
+                            <label for="amount">{ "Amount:" }</label>
+                            <input type="text" inputmode="decimal" id="amount" placeholder="0.005" value={(*amount).clone()} onchange={on_amount_change} />
+                        </div>
+                        <button type="submit">{ "Send" }</button>
+                    </form>
+                }
             </div>
         }
     } else {
@@ -563,21 +1033,415 @@ fn wallet_component() -> Html {
     }
 }
 
+/// Decompresses a gzip-compressed binary WebSocket frame into its JSON text.
+pub fn gunzip(bytes: &[u8]) -> Option<String> {
+    // The node writes a bare 10-byte header (no file name or comment) and an
+    // 8-byte CRC and length trailer around a raw deflate stream.
+    if bytes.len() < 18 || bytes[..3] != [0x1f, 0x8b, 8] || bytes[3] != 0 {
+        return None;
+    }
+    let inflated = miniz_oxide::inflate::decompress_to_vec(&bytes[10..bytes.len() - 8]).ok()?;
+    String::from_utf8(inflated).ok()
+}
+
+/// Fetches `/wallet/info` into `state`.
+pub fn fetch_wallet_info(state: UseStateHandle<Option<WalletInfo>>) {
+    spawn_local(async move {
+        if let Ok(response) = Request::get(&api_url(&base_url(), "/wallet/info")).send().await {
+            if response.ok() {
+                if let Ok(info) = response.json::<WalletInfo>().await {
+                    state.set(Some(info));
+                }
+            }
+        }
+    });
+}
+
+/// Claims a block's fractal for the node's wallet.
+pub fn claim_fractal(fractal: &FractalData) {
+    let req = NftClaimRequest { fractal_type: fractal.fractal_type() };
+    spawn_local(async move {
+        if let Ok(response) = Request::post(&api_url(&base_url(), "/nft/claim")).json(&req).unwrap().send().await {
+            if response.ok() {
+                log::info!("Fractal claimed");
+            } else {
+                log::error!("Fractal claim failed: {}", response.text().await.unwrap_or_default());
+            }
+        }
+    });
+}
+
+/// Fetches `/mining/target` into `state`.
+pub fn fetch_mining_target(state: UseStateHandle<Option<MiningTarget>>) {
+    spawn_local(async move {
+        if let Ok(response) = Request::get(&api_url(&base_url(), "/mining/target")).send().await {
+            if response.ok() {
+                if let Ok(target) = response.json::<MiningTarget>().await {
+                    state.set(Some(target));
+                }
+            }
+        }
+    });
+}
+
+/// The blocks loaded so far, oldest first. Older pages are prepended as the
+/// user scrolls down and new blocks from the WebSocket are appended.
+#[derive(Clone, Default, PartialEq)]
+pub struct BlockList {
+    pub blocks: Vec<Block>,
+    /// A page of older blocks is being fetched.
+    pub loading: bool,
+    /// The genesis block has been loaded, so there is nothing older to fetch.
+    pub complete: bool,
+}
+
+pub enum BlockListAction {
+    /// A page request has been sent.
+    Loading,
+    /// A page of blocks below the lowest loaded one arrived, oldest first.
+    Older(Vec<Block>),
+    /// A page request failed; it may be retried.
+    Failed,
+    /// A block was announced over the WebSocket.
+    New(Block),
+}
+
+impl Reducible for BlockList {
+    type Action = BlockListAction;
+
+    fn reduce(self: Rc<Self>, action: Self::Action) -> Rc<Self> {
+        let mut list = (*self).clone();
+        match action {
+            BlockListAction::Loading => list.loading = true,
+            BlockListAction::Failed => list.loading = false,
+            BlockListAction::Older(page) => {
+                let lowest = list.blocks.first().map_or(u64::MAX, |block| block.index);
+                let mut blocks: Vec<Block> = page.into_iter().filter(|block| block.index < lowest).collect();
+                blocks.append(&mut list.blocks);
+                list.complete = blocks.first().is_none_or(|block| next_page_before(block.index).is_none());
+                list.blocks = blocks;
+                list.loading = false;
+            }
+            BlockListAction::New(block) => {
+                if list.blocks.last().is_some_and(|tip| block.index <= tip.index) {
+                    return self;
+                }
+                list.blocks.push(block);
+            }
+        }
+        Rc::new(list)
+    }
+}
+
+/// Fetches the page of blocks just below the lowest one loaded, or the
+/// newest page if none are loaded yet.
+pub fn fetch_older_blocks(list: UseReducerHandle<BlockList>) {
+    if list.loading || list.complete {
+        return;
+    }
+    let url = match list.blocks.first() {
+        Some(lowest) => match next_page_before(lowest.index) {
+            Some(before) => api_url(&base_url(), &format!("/blocks?before={}&limit={}", before, PAGE_SIZE)),
+            None => return,
+        },
+        None => api_url(&base_url(), &format!("/blocks?limit={}", PAGE_SIZE)),
+    };
+    list.dispatch(BlockListAction::Loading);
+    spawn_local(async move {
+        match Request::get(&url).send().await {
+            Ok(response) if response.ok() => match response.json::<Vec<Block>>().await {
+                Ok(page) => list.dispatch(BlockListAction::Older(page)),
+                Err(_) => list.dispatch(BlockListAction::Failed),
+            },
+            _ => list.dispatch(BlockListAction::Failed),
+        }
+    });
+}
+
+/// The page title, which `Notifications::title` prefixes with the unread
+/// block count while the tab is in the background.
+pub const APP_TITLE: &str = "SierpChain 🔺⛓️";
+/// The most wallet toasts shown at once; older ones are dropped.
+pub const MAX_TOASTS: usize = 5;
+
+/// The views the nav switches between.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum View {
+    #[default]
+    Blocks,
+    Wallet,
+}
+
+/// A transaction in a new block that pays the local wallet.
+#[derive(Clone, Debug, PartialEq)]
+pub struct WalletToast {
+    pub id: u64,
+    pub txid: String,
+    pub block_index: u64,
+    /// The sum of its outputs to the wallet, in base units.
+    pub received: u64,
+}
+
+/// What the user hasn't seen yet: blocks that arrived while the list was
+/// out of sight and payments to the wallet since it was last opened.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Notifications {
+    pub view: View,
+    /// The tab is in the background.
+    pub hidden: bool,
+    pub unread_blocks: u32,
+    pub toasts: Vec<WalletToast>,
+    /// The local wallet's address, once `/wallet/info` has answered.
+    pub address: Option<String>,
+    /// The highest block counted, so that repeats aren't counted twice.
+    pub tip: Option<u64>,
+    next_toast_id: u64,
+}
+
+pub enum NotificationAction {
+    /// The local wallet's address is known.
+    Wallet(String),
+    /// A block was announced over the WebSocket.
+    NewBlock(Block),
+    /// The user switched views.
+    Show(View),
+    /// The tab went to the background or came back.
+    Visibility { hidden: bool },
+    /// The user closed a toast.
+    Dismiss(u64),
+}
+
+impl Notifications {
+    /// The document title: `APP_TITLE`, prefixed with the unread block
+    /// count while the tab is in the background.
+    pub fn title(&self) -> String {
+        if self.hidden && self.unread_blocks > 0 {
+            format!("({}) {}", self.unread_blocks, APP_TITLE)
+        } else {
+            APP_TITLE.to_string()
+        }
+    }
+
+    /// Clears whatever the view on screen shows.
+    fn mark_seen(&mut self) {
+        if self.hidden {
+            return;
+        }
+        match self.view {
+            View::Blocks => self.unread_blocks = 0,
+            View::Wallet => self.toasts.clear(),
+        }
+    }
+}
+
+impl Reducible for Notifications {
+    type Action = NotificationAction;
+
+    fn reduce(self: Rc<Self>, action: Self::Action) -> Rc<Self> {
+        let mut notifications = (*self).clone();
+        match action {
+            NotificationAction::Wallet(address) => notifications.address = Some(address),
+            NotificationAction::NewBlock(block) => {
+                if notifications.tip.is_some_and(|tip| block.index <= tip) {
+                    return self;
+                }
+                notifications.tip = Some(block.index);
+                if notifications.hidden || notifications.view != View::Blocks {
+                    notifications.unread_blocks += 1;
+                }
+                let Some(address) = notifications.address.clone() else {
+                    return Rc::new(notifications);
+                };
+                for tx in &block.transactions {
+                    let received: u64 = tx.outputs.iter().filter(|output| output.script_pub_key == address).map(|output| output.value).sum();
+                    if received > 0 {
+                        let id = notifications.next_toast_id;
+                        notifications.next_toast_id += 1;
+                        notifications.toasts.push(WalletToast { id, txid: tx.id.clone(), block_index: block.index, received });
+                    }
+                }
+                let excess = notifications.toasts.len().saturating_sub(MAX_TOASTS);
+                notifications.toasts.drain(..excess);
+            }
+            NotificationAction::Show(view) => {
+                notifications.view = view;
+                notifications.mark_seen();
+            }
+            NotificationAction::Visibility { hidden } => {
+                notifications.hidden = hidden;
+                notifications.mark_seen();
+            }
+            NotificationAction::Dismiss(id) => notifications.toasts.retain(|toast| toast.id != id),
+        }
+        Rc::new(notifications)
+    }
+}
+
 /// The main application component.
+/// Properties for the `BlockDetailModal`.
+#[derive(Properties, PartialEq)]
+pub struct BlockDetailModalProps {
+    pub block: Block,
+    pub display_decimals: u32,
+    pub on_close: Callback<()>,
+}
+
+/// Lists every transaction in a block, with its inputs and outputs, over the
+/// rest of the page. Closes on its close button, a click outside it, or Escape.
+#[function_component(BlockDetailModal)]
+fn block_detail_modal(props: &BlockDetailModalProps) -> Html {
+    {
+        let on_close = props.on_close.clone();
+        use_effect_with((), move |_| {
+            let on_keydown = web_sys::wasm_bindgen::closure::Closure::<dyn Fn(web_sys::KeyboardEvent)>::new(move |event: web_sys::KeyboardEvent| {
+                if event.key() == "Escape" {
+                    on_close.emit(());
+                }
+            });
+            let window = web_sys::window().unwrap();
+            window.add_event_listener_with_callback("keydown", on_keydown.as_ref().unchecked_ref()).unwrap();
+            move || {
+                window.remove_event_listener_with_callback("keydown", on_keydown.as_ref().unchecked_ref()).unwrap();
+            }
+        });
+    }
+
+    let on_close = {
+        let on_close = props.on_close.clone();
+        Callback::from(move |_: MouseEvent| on_close.emit(()))
+    };
+    let block = &props.block;
+
+    html! {
+        <div class="modal-backdrop" onclick={on_close.clone()}>
+            <div class="modal" role="dialog" aria-modal="true" onclick={Callback::from(|event: MouseEvent| event.stop_propagation())}>
+                <div class="modal-header">
+                    <h2>{ format!("Block #{} transactions", format_thousands(block.index)) }</h2>
+                    <button class="modal-close" aria-label="Close" onclick={on_close}>{ "✕" }</button>
+                </div>
+                { for block.transactions.iter().map(|tx| html! {
+                    <div key={tx.id.clone()} class="tx-detail">
+                        <p>
+                            <strong>{ "Transaction: " }</strong>{ &tx.id }
+                            if is_coinbase(tx) {
+                                <span class="tx-label">{ "Coinbase" }</span>
+                            }
+                        </p>
+                        if let Some(message) = coinbase_message(tx) {
+                            <p><strong>{ "Coinbase message: " }</strong>{ message }</p>
+                        }
+                        <p><strong>{ "Inputs:" }</strong></p>
+                        <ul>
+                            { for tx.inputs.iter().map(|input| html! { <li>{ format_input(input) }</li> }) }
+                        </ul>
+                        <p><strong>{ "Outputs:" }</strong></p>
+                        <ul>
+                            { for tx.outputs.iter().map(|output| html! { <li>{ format_output(output, props.display_decimals) }</li> }) }
+                        </ul>
+                    </div>
+                }) }
+            </div>
+        </div>
+    }
+}
+
+/// Fetches `/chain/graph` into `state`.
+pub fn fetch_chain_graph(state: UseStateHandle<ChainGraph>) {
+    spawn_local(async move {
+        let url = api_url(&base_url(), &format!("/chain/graph?depth={}", GRAPH_DEPTH));
+        if let Ok(response) = Request::get(&url).send().await {
+            if response.ok() {
+                if let Ok(graph) = response.json::<ChainGraph>().await {
+                    state.set(graph);
+                }
+            }
+        }
+    });
+}
+
+#[derive(Properties, PartialEq)]
+pub struct ChainGraphProps {
+    /// The hash of the newest block shown; the graph is fetched again
+    /// whenever it changes.
+    pub tip: Option<String>,
+}
+
+/// Draws the recent block tree as an SVG, the active chain highlighted and
+/// side chains below it.
+#[function_component(ChainGraphView)]
+fn chain_graph_view(props: &ChainGraphProps) -> Html {
+    let graph = use_state(ChainGraph::default);
+
+    {
+        let graph = graph.clone();
+        use_effect_with(props.tip.clone(), move |_| {
+            fetch_chain_graph(graph);
+            || ()
+        });
+    }
+
+    if graph.nodes.is_empty() {
+        return html! {};
+    }
+    let layout = layout(&graph);
+    let view_box = format!("0 0 {} {}", layout.width, layout.height);
+
+    html! {
+        <div class="chain-graph">
+            <h2>{ "Block Tree" }</h2>
+            <svg viewBox={view_box} width={layout.width.to_string()} height={layout.height.to_string()}>
+                { for graph.edges.iter().filter_map(|edge| {
+                    let (child, parent) = (layout.positions.get(&edge.from)?, layout.positions.get(&edge.to)?);
+                    Some(html! {
+                        <line
+                            x1={child.x.to_string()} y1={(child.y + NODE_HEIGHT / 2.0).to_string()}
+                            x2={(parent.x + NODE_WIDTH).to_string()} y2={(parent.y + NODE_HEIGHT / 2.0).to_string()}
+                            class="graph-edge"
+                        />
+                    })
+                }) }
+                { for graph.nodes.iter().map(|node| {
+                    let position = layout.positions[&node.hash];
+                    let title = format!("{}\n{} by {}", node.hash, node.fractal_type, node.miner.as_deref().unwrap_or("unknown"));
+                    html! {
+                        <g key={node.hash.clone()} class={classes!("graph-node", node.active.then_some("active"))}>
+                            <title>{ title }</title>
+                            <rect x={position.x.to_string()} y={position.y.to_string()} width={NODE_WIDTH.to_string()} height={NODE_HEIGHT.to_string()} rx="4" />
+                            <text x={(position.x + 6.0).to_string()} y={(position.y + 16.0).to_string()}>{ format!("#{}", format_thousands(node.height)) }</text>
+                            <text x={(position.x + 6.0).to_string()} y={(position.y + 32.0).to_string()}>{ node.short_hash.chars().take(10).collect::<String>() }</text>
+                        </g>
+                    }
+                }) }
+            </svg>
+        </div>
+    }
+}
+
 #[function_component(App)]
 fn app() -> Html {
-    let blocks = use_state(|| vec![]);
+    let block_list = use_reducer(BlockList::default);
+    let blocks_ref = use_node_ref();
+    // The list's scroll offset and viewport height, in pixels.
+    let scroll = use_state(|| (0.0f64, BLOCK_CARD_HEIGHT * 4.0));
     let _ws_task = use_state(|| None);
+    let display_decimals = use_state(|| DEFAULT_DISPLAY_DECIMALS);
+    let test_network = use_state(|| false);
+    let now = use_state(format::now);
+    // The colour theme chosen for each block, by block index.
+    let themes = use_state(HashMap::<u64, ColorTheme>::new);
+    let mining_target = use_state(|| None);
+    // The block whose transactions are shown in the detail modal, if any.
+    let selected_block = use_state(|| None::<Block>);
+    let notifications = use_reducer(Notifications::default);
 
     {
-        let blocks = blocks.clone();
+        let notifications = notifications.dispatcher();
         use_effect_with((), move |_| {
-            let blocks = blocks.clone();
             spawn_local(async move {
-                if let Ok(response) = Request::get("http://127.0.0.1:8081/blocks").send().await {
+                if let Ok(response) = Request::get(&api_url(&base_url(), "/wallet/info")).send().await {
                     if response.ok() {
-                        if let Ok(fetched_blocks) = response.json::<Vec<Block>>().await {
-                            blocks.set(fetched_blocks);
+                        if let Ok(info) = response.json::<WalletInfo>().await {
+                            notifications.dispatch(NotificationAction::Wallet(info.address));
                         }
                     }
                 }
@@ -587,18 +1451,121 @@ fn app() -> Html {
     }
 
     {
-        let blocks = blocks.clone();
+        let notifications = notifications.dispatcher();
+        use_effect_with((), move |_| {
+            let document = web_sys::window().unwrap().document().unwrap();
+            notifications.dispatch(NotificationAction::Visibility { hidden: document.hidden() });
+            let on_visibility_change = {
+                let document = document.clone();
+                web_sys::wasm_bindgen::closure::Closure::<dyn Fn()>::new(move || {
+                    notifications.dispatch(NotificationAction::Visibility { hidden: document.hidden() });
+                })
+            };
+            document.add_event_listener_with_callback("visibilitychange", on_visibility_change.as_ref().unchecked_ref()).unwrap();
+            move || {
+                document.remove_event_listener_with_callback("visibilitychange", on_visibility_change.as_ref().unchecked_ref()).unwrap();
+            }
+        });
+    }
+
+    use_effect_with(notifications.title(), |title| {
+        web_sys::window().unwrap().document().unwrap().set_title(title);
+        || ()
+    });
+
+    {
+        let mining_target = mining_target.clone();
+        use_effect_with((), move |_| {
+            fetch_mining_target(mining_target);
+            || ()
+        });
+    }
+
+    {
+        let display_decimals = display_decimals.clone();
+        let test_network = test_network.clone();
+        use_effect_with((), move |_| {
+            spawn_local(async move {
+                if let Ok(response) = Request::get(&api_url(&base_url(), "/status")).send().await {
+                    if response.ok() {
+                        if let Ok(status) = response.json::<NodeStatus>().await {
+                            display_decimals.set(status.display_decimals);
+                            test_network.set(status.is_test_network());
+                        }
+                    }
+                }
+            });
+            || ()
+        });
+    }
+
+    {
+        let now = now.clone();
+        use_effect_with((), move |_| {
+            let interval = Interval::new(AGE_REFRESH_INTERVAL_MS, move || now.set(format::now()));
+            move || drop(interval)
+        });
+    }
+
+    {
+        let block_list = block_list.clone();
+        use_effect_with((), move |_| {
+            fetch_older_blocks(block_list);
+            || ()
+        });
+    }
+
+    // Keep fetching while the loaded blocks don't reach the bottom of the
+    // viewport, e.g. on a tall screen or after the first page.
+    {
+        let block_list = block_list.clone();
+        let (scroll_top, viewport_height) = *scroll;
+        let content_height = block_list.blocks.len() as f64 * BLOCK_CARD_HEIGHT;
+        use_effect_with((block_list.blocks.len(), block_list.loading, scroll_top.to_bits(), viewport_height.to_bits()), move |_| {
+            if !block_list.blocks.is_empty() && should_load_more(scroll_top, viewport_height, content_height, LOAD_MORE_THRESHOLD) {
+                fetch_older_blocks(block_list);
+            }
+            || ()
+        });
+    }
+
+    {
+        let blocks_ref = blocks_ref.clone();
+        let scroll = scroll.clone();
+        use_effect_with((block_list.blocks.is_empty(), notifications.view), move |_| {
+            if let Some(element) = blocks_ref.cast::<web_sys::Element>() {
+                scroll.set((element.scroll_top() as f64, element.client_height() as f64));
+            }
+            || ()
+        });
+    }
+
+    {
+        let block_list = block_list.clone();
+        let mining_target = mining_target.clone();
+        let notifications = notifications.dispatcher();
         let ws_task_handle = _ws_task.clone();
         use_effect_with((), move |_| {
-            let ws_conn = WebSocket::open("ws://127.0.0.1:8081/ws").unwrap();
+            let ws_conn = WebSocket::open(&ws_url(&base_url(), "/ws?format=binary")).unwrap();
             let (mut _write, mut read) = ws_conn.split();
 
             let ws_task = spawn_local(async move {
-                while let Some(Ok(WsMessage::Text(data))) = read.next().await {
+                while let Some(Ok(message)) = read.next().await {
+                    let data = match message {
+                        WsMessage::Text(data) => data,
+                        WsMessage::Bytes(bytes) => match gunzip(&bytes) {
+                            Some(data) => data,
+                            None => {
+                                log::error!("Failed to decode binary WebSocket frame");
+                                continue;
+                            }
+                        },
+                    };
                     if let Ok(new_block) = serde_json::from_str::<Block>(&data) {
-                        let mut updated_blocks = (*blocks).clone();
-                        updated_blocks.push(new_block);
-                        blocks.set(updated_blocks);
+                        notifications.dispatch(NotificationAction::NewBlock(new_block.clone()));
+                        block_list.dispatch(BlockListAction::New(new_block));
+                        // A new block may have moved the difficulty.
+                        fetch_mining_target(mining_target.clone());
                     }
                 }
             });
@@ -607,57 +1574,250 @@ fn app() -> Html {
         });
     }
 
+    let on_scroll = {
+        let scroll = scroll.clone();
+        Callback::from(move |event: Event| {
+            if let Some(element) = event.target_dyn_into::<web_sys::Element>() {
+                scroll.set((element.scroll_top() as f64, element.client_height() as f64));
+            }
+        })
+    };
+    let on_show = |view: View| {
+        let notifications = notifications.dispatcher();
+        Callback::from(move |_: MouseEvent| notifications.dispatch(NotificationAction::Show(view)))
+    };
+    let window = {
+        let (scroll_top, viewport_height) = *scroll;
+        visible_window(scroll_top, viewport_height, BLOCK_CARD_HEIGHT, WINDOW_BUFFER, block_list.blocks.len())
+    };
+
     html! {
         <div>
-            <h1>{ "SierpChain 🔺⛓️" }</h1>
+            <h1>{ APP_TITLE }</h1>
+            <nav class="app-nav">
+                <button onclick={on_show(View::Blocks)} disabled={notifications.view == View::Blocks}>
+                    { "Blocks" }
+                    if notifications.unread_blocks > 0 {
+                        <span class="badge">{ notifications.unread_blocks }</span>
+                    }
+                </button>
+                <button onclick={on_show(View::Wallet)} disabled={notifications.view == View::Wallet}>
+                    { "Wallet" }
+                    if !notifications.toasts.is_empty() {
+                        <span class="unread-dot"></span>
+                    }
+                </button>
+            </nav>
             <div class="app-container">
                 <div class="sidebar">
-                    <MiningComponent />
-                    <WalletComponent />
+                    <MiningComponent target={(*mining_target).clone()} tip={block_list.blocks.last().map(|block| block.hash.clone())} />
                 </div>
                 <div class="main-content">
-                    if blocks.is_empty() {
-                        <p>{ "Loading blocks..." }</p>
+                    if notifications.view == View::Wallet {
+                        <WalletComponent display_decimals={*display_decimals} now={*now} test_network={*test_network} />
                     } else {
-                        <div class="blocks-container">
-                            { for blocks.iter().rev().map(|block| html! {
-                                <div class="block-card">
-                                    <FractalComponent fractal={block.fractal.clone()} />
-                                    <div class="block-details">
-                                        <h2>{ format!("Block #{}", block.index) }</h2>
-                                        <p><strong>{ "Hash: " }</strong>{ &block.hash }</p>
-                                        <p><strong>{ "Prev. Hash: " }</strong>{ &block.previous_hash }</p>
-                                        <p><strong>{ "Nonce: " }</strong>{ block.nonce }</p>
-                                        <p><strong>{ "Transactions: " }</strong>{ block.transactions.len() }</p>
-                                        {
-                                            match &block.fractal {
-                                                FractalData::Sierpinski(s) => html!{<p><strong>{ "Fractal Type: " }</strong>{ "Sierpinski" }<br/><strong>{ "Depth: " }</strong>{ s.depth }</p>},
-                                                FractalData::Mandelbrot(m) => html!{<p><strong>{ "Fractal Type: " }</strong>{ "Mandelbrot" }<br/><strong>{ "Max Iterations: " }</strong>{ m.max_iterations }</p>},
-                                                FractalData::Julia(j) => html!{<p><strong>{ "Fractal Type: " }</strong>{ "Julia" }<br/><strong>{ "Max Iterations: " }</strong>{ j.max_iterations }<br/><strong>{ "C: " }</strong>{ format!("{:.3} + {:.3}i", j.c_real, j.c_imag) }</p>},
+                        <ChainGraphView tip={block_list.blocks.last().map(|block| block.hash.clone())} />
+                        if block_list.blocks.is_empty() {
+                            <p>{ "Loading blocks..." }</p>
+                        } else {
+                            <div class="blocks-container" ref={blocks_ref.clone()} onscroll={on_scroll}>
+                                { for block_list.blocks.iter().rev().enumerate().map(|(position, block)| {
+                                    let slot_style = format!("height: {}px;", BLOCK_CARD_HEIGHT);
+                                    if !window.contains(position) {
+                                        return html! {
+                                            <div key={block.hash.clone()} class="block-slot" style={slot_style}>
+                                                <div class="block-placeholder"></div>
+                                            </div>
+                                        };
+                                    }
+                                    let theme = themes.get(&block.index).copied().unwrap_or_default();
+                                    let on_theme_change = {
+                                        let themes = themes.clone();
+                                        let index = block.index;
+                                        Callback::from(move |theme: ColorTheme| {
+                                            let mut updated_themes = (*themes).clone();
+                                            updated_themes.insert(index, theme);
+                                            themes.set(updated_themes);
+                                        })
+                                    };
+                                    let on_claim = {
+                                        let fractal = block.fractal.clone();
+                                        Callback::from(move |_| claim_fractal(&fractal))
+                                    };
+                                    let on_open = {
+                                        let selected_block = selected_block.clone();
+                                        let block = block.clone();
+                                        Callback::from(move |event: MouseEvent| {
+                                            // The card's own controls keep their behaviour.
+                                            let on_control = event
+                                                .target_dyn_into::<web_sys::Element>()
+                                                .and_then(|target| target.closest("button, select").ok().flatten())
+                                                .is_some();
+                                            if !on_control {
+                                                selected_block.set(Some(block.clone()));
                                             }
-                                        }
-                                    </div>
-                                </div>
-                            })}
-                        </div>
+                                        })
+                                    };
+                                    html! {
+                                        <div key={block.hash.clone()} class="block-slot" style={slot_style}>
+                                            <div class="block-card" onclick={on_open}>
+                                                <FractalComponent fractal={block.fractal.clone()} theme={theme} />
+                                                if !matches!(block.fractal, FractalData::Sierpinski(_)) {
+                                                    <ColorThemeSelector theme={theme} on_change={on_theme_change} />
+                                                }
+                                                <div class="block-details">
+                                                    <h2>{ format!("Block #{}", format_thousands(block.index)) }</h2>
+                                                    <p><strong>{ "Mined: " }</strong>{ format!("{} ({})", format_datetime(block.timestamp), format_age(block.timestamp, *now)) }</p>
+                                                    <p><strong>{ "Hash: " }</strong>{ &block.hash }</p>
+                                                    <p><strong>{ "Prev. Hash: " }</strong>{ &block.previous_hash }</p>
+                                                    <p><strong>{ "Nonce: " }</strong>{ format_thousands(block.nonce) }</p>
+                                                    if block.style_seed != 0 {
+                                                        <p><strong>{ "Style Seed: " }</strong>{ block.style_seed }</p>
+                                                    }
+                                                    <p><strong>{ "Transactions: " }</strong>{ format_thousands(block.transactions.len() as u64) }</p>
+                                                    <p><strong>{ "Value: " }</strong>{ format_amount(block.transactions.iter().flat_map(|tx| &tx.outputs).map(|output| output.value).sum(), *display_decimals) }</p>
+                                                    {
+                                                        match &block.fractal {
+                                                            FractalData::Sierpinski(s) => html!{<p><strong>{ "Fractal Type: " }</strong>{ "Sierpinski" }<br/><strong>{ "Depth: " }</strong>{ s.depth }</p>},
+                                                            FractalData::Mandelbrot(m) => html!{<p><strong>{ "Fractal Type: " }</strong>{ "Mandelbrot" }<br/><strong>{ "Max Iterations: " }</strong>{ m.max_iterations }</p>},
+                                                            FractalData::Julia(j) => html!{<p><strong>{ "Fractal Type: " }</strong>{ "Julia" }<br/><strong>{ "Max Iterations: " }</strong>{ j.max_iterations }<br/><strong>{ "C: " }</strong>{ format!("{:.3} + {:.3}i", coord_to_f64(j.c_real), coord_to_f64(j.c_imag)) }</p>},
+                                                        }
+                                                    }
+                                                    <button onclick={on_claim}>{ "Claim this Fractal 🎨" }</button>
+                                                </div>
+                                            </div>
+                                        </div>
+                                    }
+                                })}
+                                if block_list.loading {
+                                    <p>{ "Loading older blocks..." }</p>
+                                }
+                            </div>
+                        }
                     }
                 </div>
             </div>
+            if let Some(block) = (*selected_block).clone() {
+                <BlockDetailModal
+                    block={block}
+                    display_decimals={*display_decimals}
+                    on_close={let selected_block = selected_block.clone(); Callback::from(move |_| selected_block.set(None))}
+                />
+            }
+            <div class="toasts">
+                { for notifications.toasts.iter().map(|toast| {
+                    let on_dismiss = {
+                        let notifications = notifications.dispatcher();
+                        let id = toast.id;
+                        Callback::from(move |_: MouseEvent| notifications.dispatch(NotificationAction::Dismiss(id)))
+                    };
+                    html! {
+                        <div key={toast.id} class="toast" role="status" title={toast.txid.clone()} onclick={on_dismiss}>
+                            { format!("Block #{} paid {} to your wallet", format_thousands(toast.block_index), format_amount(toast.received, *display_decimals)) }
+                        </div>
+                    }
+                }) }
+            </div>
         </div>
     }
 }
 
-fn main() {
+/// Starts the app; the `app` binary trunk builds calls this.
+pub fn run() {
     wasm_logger::init(wasm_logger::Config::default());
     yew::Renderer::<App>::new().render();
 }
 
 #[cfg(test)]
 mod tests {
+    use super::*;
     use wasm_bindgen_test::*;
 
+    fn block(index: u64, outputs: &[(&str, u64)]) -> Block {
+        Block {
+            index,
+            timestamp: 0,
+            fractal: FractalData::Sierpinski(Sierpinski { depth: 0, seed: 0, rng_version: RngVersion::SplitMix64, vertices: vec![] }),
+            transactions: vec![Transaction {
+                id: format!("tx{}", index),
+                timestamp: 0,
+                inputs: vec![],
+                outputs: outputs.iter().map(|(address, value)| TxOutput { value: *value, script_pub_key: address.to_string() }).collect(),
+            }],
+            previous_hash: String::new(),
+            hash: format!("hash{}", index),
+            nonce: 0,
+            style_seed: 0,
+        }
+    }
+
+    fn apply(notifications: Rc<Notifications>, actions: Vec<NotificationAction>) -> Rc<Notifications> {
+        actions.into_iter().fold(notifications, |notifications, action| notifications.reduce(action))
+    }
+
     #[wasm_bindgen_test]
     fn pass() {
         assert_eq!(1, 1);
     }
+
+    #[wasm_bindgen_test]
+    fn gunzip_rejects_non_gzip_frames() {
+        assert_eq!(super::gunzip(b"{\"index\":0}"), None);
+    }
+
+    #[wasm_bindgen_test]
+    fn blocks_are_unread_until_the_list_is_shown() {
+        let notifications = apply(Rc::default(), vec![
+            NotificationAction::Show(View::Wallet),
+            NotificationAction::NewBlock(block(1, &[])),
+            NotificationAction::NewBlock(block(2, &[])),
+            // Repeats aren't counted again.
+            NotificationAction::NewBlock(block(2, &[])),
+        ]);
+        assert_eq!(notifications.unread_blocks, 2);
+
+        let notifications = apply(notifications, vec![NotificationAction::Show(View::Blocks), NotificationAction::NewBlock(block(3, &[]))]);
+        assert_eq!(notifications.unread_blocks, 0);
+    }
+
+    #[wasm_bindgen_test]
+    fn backgrounded_tab_counts_blocks_in_its_title() {
+        let notifications = apply(Rc::default(), vec![
+            NotificationAction::Visibility { hidden: true },
+            NotificationAction::NewBlock(block(1, &[])),
+            NotificationAction::NewBlock(block(2, &[])),
+            NotificationAction::NewBlock(block(3, &[])),
+        ]);
+        assert_eq!(notifications.title(), format!("(3) {}", APP_TITLE));
+
+        let notifications = notifications.reduce(NotificationAction::Visibility { hidden: false });
+        assert_eq!(notifications.unread_blocks, 0);
+        assert_eq!(notifications.title(), APP_TITLE);
+    }
+
+    #[wasm_bindgen_test]
+    fn payments_to_the_wallet_toast_until_it_is_opened() {
+        let notifications = apply(Rc::default(), vec![
+            NotificationAction::NewBlock(block(1, &[("mine", 5)])),
+            NotificationAction::Wallet("mine".to_string()),
+            NotificationAction::NewBlock(block(2, &[("mine", 5), ("someone", 3), ("mine", 2)])),
+            NotificationAction::NewBlock(block(3, &[("someone", 3)])),
+        ]);
+        assert_eq!(notifications.toasts.len(), 1);
+        assert_eq!((notifications.toasts[0].block_index, notifications.toasts[0].received), (2, 7));
+
+        let id = notifications.toasts[0].id;
+        let notifications = notifications.reduce(NotificationAction::Dismiss(id));
+        assert!(notifications.toasts.is_empty());
+
+        let notifications = apply(notifications, (4..4 + MAX_TOASTS as u64 + 2).map(|index| NotificationAction::NewBlock(block(index, &[("mine", 1)]))).collect());
+        assert_eq!(notifications.toasts.len(), MAX_TOASTS);
+        assert_eq!(notifications.toasts[0].block_index, 6);
+
+        // Hidden, the wallet view doesn't count as seen.
+        let notifications = apply(notifications, vec![NotificationAction::Visibility { hidden: true }, NotificationAction::Show(View::Wallet)]);
+        assert_eq!(notifications.toasts.len(), MAX_TOASTS);
+        let notifications = notifications.reduce(NotificationAction::Visibility { hidden: false });
+        assert!(notifications.toasts.is_empty());
+    }
 }
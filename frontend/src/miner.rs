@@ -0,0 +1,241 @@
+//! Mining in the browser. A Web Worker searches a `/mining/template`'s
+//! nonces with the node's own miner from `sierpchain-shared`, a chunk at a
+//! time, and reports its progress after each chunk so the page can show it.
+//! Dropping the page's bridge to the worker ends it.
+
+use gloo_timers::callback::Timeout;
+use gloo_worker::{Codec, HandlerId, Worker, WorkerScope};
+use serde::{Deserialize, Serialize};
+use sierpchain_shared::mining::{MinedNonce, MiningTemplate};
+use std::rc::Rc;
+use web_sys::wasm_bindgen::JsValue;
+use yew::Reducible;
+
+/// Where trunk puts the worker's loader, next to the app's.
+pub const MINER_WORKER_PATH: &str = "/miner_worker.js";
+
+/// How many nonces the worker tries between progress reports.
+pub const NONCES_PER_CHUNK: u64 = 64;
+
+/// What the worker tells the page.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum MinerOutput {
+    /// `attempts` nonces of the current template have been tried without
+    /// meeting its target.
+    Progress { attempts: u64 },
+    /// A nonce met the target; the worker has stopped.
+    Found(MinedNonce),
+}
+
+/// The search the worker is running and whom to report it to.
+struct Job {
+    template: MiningTemplate,
+    next_nonce: u64,
+    handler: HandlerId,
+}
+
+pub struct MinerWorker {
+    job: Option<Job>,
+    /// Counts the jobs started, so a chunk scheduled for a replaced one is
+    /// dropped.
+    generation: u64,
+}
+
+/// Searches the next chunk of the job started as `generation`.
+pub struct NextChunk(u64);
+
+impl MinerWorker {
+    /// Schedules the next chunk as a task of its own. `send_message` would
+    /// run it as a microtask, and back-to-back microtasks never let the
+    /// worker receive a fresh template.
+    fn schedule(&self, scope: &WorkerScope<Self>) {
+        let scope = scope.clone();
+        let generation = self.generation;
+        Timeout::new(0, move || scope.send_message(NextChunk(generation))).forget();
+    }
+}
+
+impl Worker for MinerWorker {
+    type Message = NextChunk;
+    /// A template to search from nonce 0, replacing the current search.
+    type Input = MiningTemplate;
+    type Output = MinerOutput;
+
+    fn create(_scope: &WorkerScope<Self>) -> Self {
+        Self { job: None, generation: 0 }
+    }
+
+    fn update(&mut self, scope: &WorkerScope<Self>, NextChunk(generation): NextChunk) {
+        if generation != self.generation {
+            return;
+        }
+        let Some(job) = &mut self.job else {
+            return;
+        };
+        match job.template.mine(job.next_nonce, NONCES_PER_CHUNK) {
+            Some(mined) => {
+                scope.respond(job.handler, MinerOutput::Found(mined));
+                self.job = None;
+            }
+            None => {
+                job.next_nonce = job.next_nonce.saturating_add(NONCES_PER_CHUNK);
+                scope.respond(job.handler, MinerOutput::Progress { attempts: job.next_nonce });
+                self.schedule(scope);
+            }
+        }
+    }
+
+    fn received(&mut self, scope: &WorkerScope<Self>, template: MiningTemplate, handler: HandlerId) {
+        self.generation += 1;
+        self.job = Some(Job { template, next_nonce: 0, handler });
+        self.schedule(scope);
+    }
+}
+
+/// Passes worker messages as JSON text. gloo-worker's default bincode can't
+/// carry the tagged enums and optional fields blocks are made of.
+pub struct JsonCodec;
+
+impl Codec for JsonCodec {
+    fn encode<I: Serialize>(input: I) -> JsValue {
+        JsValue::from_str(&serde_json::to_string(&input).expect("worker messages serialize"))
+    }
+
+    fn decode<O: for<'de> Deserialize<'de>>(input: JsValue) -> O {
+        let text = input.as_string().expect("worker messages are JSON text");
+        serde_json::from_str(&text).expect("worker messages deserialize")
+    }
+}
+
+/// How the in-browser miner is getting on.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct BrowserMining {
+    /// Nonces tried on the current template.
+    pub attempts: u64,
+    /// When the current template's search started, in milliseconds since
+    /// the epoch.
+    pub started_at: f64,
+    /// Nonces tried per second on the current template, once there is a
+    /// report to measure it by.
+    pub hashes_per_second: Option<f64>,
+    /// Blocks mined here that the node accepted.
+    pub accepted: u64,
+    /// Why mining stopped, if it failed.
+    pub error: Option<String>,
+}
+
+pub enum BrowserMiningAction {
+    /// The worker was given a fresh template at the time given.
+    Started { at: f64 },
+    /// The worker reported `attempts` at the time given.
+    Progress { attempts: u64, at: f64 },
+    /// The node accepted a block mined here.
+    Accepted,
+    /// Mining stopped for the reason given.
+    Failed(String),
+}
+
+impl Reducible for BrowserMining {
+    type Action = BrowserMiningAction;
+
+    fn reduce(self: Rc<Self>, action: Self::Action) -> Rc<Self> {
+        let mut mining = (*self).clone();
+        match action {
+            BrowserMiningAction::Started { at } => {
+                mining.attempts = 0;
+                mining.started_at = at;
+                mining.hashes_per_second = None;
+                mining.error = None;
+            }
+            BrowserMiningAction::Progress { attempts, at } => {
+                mining.attempts = attempts;
+                let seconds = (at - mining.started_at) / 1000.0;
+                mining.hashes_per_second = (seconds > 0.0).then(|| attempts as f64 / seconds);
+            }
+            BrowserMiningAction::Accepted => mining.accepted += 1,
+            BrowserMiningAction::Failed(error) => mining.error = Some(error),
+        }
+        Rc::new(mining)
+    }
+}
+
+/// Fills `mined` in to the block of a `/mining/template` response, ready to
+/// POST to `/mining/submit`. The block stays JSON so that its transactions
+/// go back exactly as the node sent them.
+pub fn mined_block(template: &serde_json::Value, mined: &MinedNonce) -> serde_json::Value {
+    let mut block = template["block"].clone();
+    block["nonce"] = mined.nonce.into();
+    block["fractal"] = serde_json::to_value(&mined.fractal).expect("fractals serialize");
+    block["hash"] = mined.hash.clone().into();
+    block
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sierpchain_shared::fractal::FractalType;
+    use sierpchain_shared::target::Target;
+    use wasm_bindgen_test::*;
+
+    /// A template like a regtest node's at difficulty 0, where the first
+    /// nonce already meets the target.
+    fn regtest_template() -> serde_json::Value {
+        let fractal_type = FractalType::Sierpinski { depth: 5, seed: 0 };
+        serde_json::json!({
+            "block": {
+                "index": 1,
+                "timestamp": 1_700_000_000,
+                "fractal": fractal_type.generate(),
+                "transactions": [],
+                "previous_hash": "ab".repeat(32),
+                "hash": "",
+                "nonce": 0,
+                "bits": Target::MAX.to_compact(),
+            },
+            "fractal_type": fractal_type,
+            "work_factor": 1,
+            "effective_target": Target::MAX.to_string(),
+            "encoded_transactions": "[]",
+        })
+    }
+
+    #[wasm_bindgen_test]
+    fn mine_template_grinds_a_regtest_template() {
+        let template = regtest_template();
+        let mined = sierpchain_shared::wasm::mine_template(&template.to_string(), 0, 10).unwrap().unwrap();
+        let mined: MinedNonce = serde_json::from_str(&mined).unwrap();
+        assert_eq!(mined.nonce, 0);
+        assert!(Target::MAX.is_met_by(&mined.hash));
+
+        let block = mined_block(&template, &mined);
+        assert_eq!(block["hash"], mined.hash);
+        assert_eq!(block["transactions"], template["block"]["transactions"]);
+        assert_eq!(serde_json::from_value::<MinedNonce>(serde_json::json!({
+            "nonce": block["nonce"],
+            "fractal": block["fractal"],
+            "hash": block["hash"],
+        })).unwrap(), mined);
+    }
+
+    #[wasm_bindgen_test]
+    fn progress_is_measured_from_the_latest_template() {
+        let mining = Rc::new(BrowserMining::default()).reduce(BrowserMiningAction::Started { at: 1_000.0 });
+        let mining = mining.reduce(BrowserMiningAction::Progress { attempts: 64, at: 1_000.0 });
+        assert_eq!(mining.hashes_per_second, None);
+        let mining = mining.reduce(BrowserMiningAction::Progress { attempts: 128, at: 3_000.0 });
+        assert_eq!(mining.hashes_per_second, Some(64.0));
+
+        let mining = mining.reduce(BrowserMiningAction::Accepted).reduce(BrowserMiningAction::Started { at: 5_000.0 });
+        assert_eq!((mining.attempts, mining.hashes_per_second, mining.accepted), (0, None, 1));
+        let mining = mining.reduce(BrowserMiningAction::Failed("refused".to_string()));
+        assert_eq!(mining.error.as_deref(), Some("refused"));
+    }
+
+    #[wasm_bindgen_test]
+    fn worker_messages_round_trip_through_the_codec() {
+        let template: MiningTemplate = serde_json::from_value(regtest_template()).unwrap();
+        let found = MinerOutput::Found(template.mine(0, 1).unwrap());
+        assert_eq!(JsonCodec::decode::<MinerOutput>(JsonCodec::encode(&found)), found);
+        assert_eq!(JsonCodec::decode::<MiningTemplate>(JsonCodec::encode(&template)), template);
+    }
+}
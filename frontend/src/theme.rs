@@ -0,0 +1,164 @@
+//! Colour maps for escape-time fractals.
+//!
+//! Each theme maps an iteration count to a colour. Points that never escaped
+//! (`iteration == max_iter`) are drawn black by every theme.
+
+/// A colour map for rendering Mandelbrot and Julia sets.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum ColorTheme {
+    #[default]
+    Classic,
+    Grayscale,
+    Fire,
+    Ice,
+    Electric,
+}
+
+impl ColorTheme {
+    pub const ALL: [ColorTheme; 5] = [
+        ColorTheme::Classic,
+        ColorTheme::Grayscale,
+        ColorTheme::Fire,
+        ColorTheme::Ice,
+        ColorTheme::Electric,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            ColorTheme::Classic => "Classic",
+            ColorTheme::Grayscale => "Grayscale",
+            ColorTheme::Fire => "Fire",
+            ColorTheme::Ice => "Ice",
+            ColorTheme::Electric => "Electric",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|theme| theme.name() == name)
+    }
+
+    /// Returns the function that colours a point for this theme.
+    pub fn colorize(self) -> fn(u32, u32) -> (u8, u8, u8) {
+        match self {
+            ColorTheme::Classic => classic,
+            ColorTheme::Grayscale => grayscale,
+            ColorTheme::Fire => fire,
+            ColorTheme::Ice => ice,
+            ColorTheme::Electric => electric,
+        }
+    }
+}
+
+/// Returns how far `iteration` is towards `max_iter`, from 0.0 to 1.0.
+fn escape_fraction(iteration: u32, max_iter: u32) -> f64 {
+    if max_iter == 0 {
+        return 0.0;
+    }
+    (iteration as f64 / max_iter as f64).clamp(0.0, 1.0)
+}
+
+fn channel(value: f64) -> u8 {
+    (value.clamp(0.0, 1.0) * 255.0) as u8
+}
+
+/// Cycles through the hue wheel, 10 degrees per iteration.
+pub fn classic(iteration: u32, max_iter: u32) -> (u8, u8, u8) {
+    if iteration >= max_iter {
+        return (0, 0, 0);
+    }
+    hsl_to_rgb((iteration as f64 * 10.0) % 360.0, 1.0, 0.5)
+}
+
+/// Fades from dark grey to white as points take longer to escape.
+pub fn grayscale(iteration: u32, max_iter: u32) -> (u8, u8, u8) {
+    if iteration >= max_iter {
+        return (0, 0, 0);
+    }
+    let v = channel(0.1 + escape_fraction(iteration, max_iter) * 0.9);
+    (v, v, v)
+}
+
+/// Runs from deep red through orange to yellow.
+pub fn fire(iteration: u32, max_iter: u32) -> (u8, u8, u8) {
+    if iteration >= max_iter {
+        return (0, 0, 0);
+    }
+    let t = escape_fraction(iteration, max_iter);
+    (channel(0.5 + t * 1.5), channel(t * 2.0 - 0.2), channel(t * 3.0 - 2.0))
+}
+
+/// Runs from deep blue to white.
+pub fn ice(iteration: u32, max_iter: u32) -> (u8, u8, u8) {
+    if iteration >= max_iter {
+        return (0, 0, 0);
+    }
+    let t = escape_fraction(iteration, max_iter);
+    (channel(t), channel(0.2 + t * 0.8), channel(0.5 + t * 0.5))
+}
+
+/// Blends from cyan to magenta.
+pub fn electric(iteration: u32, max_iter: u32) -> (u8, u8, u8) {
+    if iteration >= max_iter {
+        return (0, 0, 0);
+    }
+    let t = escape_fraction(iteration, max_iter);
+    (channel(t), channel(1.0 - t), 255)
+}
+
+/// Converts a colour from HSL (hue in degrees, saturation and lightness from
+/// 0.0 to 1.0) to RGB.
+fn hsl_to_rgb(h: f64, s: f64, l: f64) -> (u8, u8, u8) {
+    let c = (1.0 - (2.0 * l - 1.0).abs()) * s;
+    let x = c * (1.0 - ((h / 60.0) % 2.0 - 1.0).abs());
+    let m = l - c / 2.0;
+    let (r_prime, g_prime, b_prime) = if (0.0..60.0).contains(&h) {
+        (c, x, 0.0)
+    } else if (60.0..120.0).contains(&h) {
+        (x, c, 0.0)
+    } else if (120.0..180.0).contains(&h) {
+        (0.0, c, x)
+    } else if (180.0..240.0).contains(&h) {
+        (0.0, x, c)
+    } else if (240.0..300.0).contains(&h) {
+        (x, 0.0, c)
+    } else {
+        (c, 0.0, x)
+    };
+    (((r_prime + m) * 255.0) as u8,
+    ((g_prime + m) * 255.0) as u8,
+    ((b_prime + m) * 255.0) as u8)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wasm_bindgen_test::*;
+
+    #[wasm_bindgen_test]
+    fn theme_names_round_trip() {
+        for theme in ColorTheme::ALL {
+            assert_eq!(ColorTheme::from_name(theme.name()), Some(theme));
+        }
+    }
+
+    #[wasm_bindgen_test]
+    fn themes_handle_boundary_iterations() {
+        for theme in ColorTheme::ALL {
+            let colorize = theme.colorize();
+            // Points inside the set are black in every theme.
+            assert_eq!(colorize(100, 100), (0, 0, 0), "{} at max_iter", theme.name());
+            // The first and last escaping points are distinguishable from it.
+            assert_ne!(colorize(0, 100), (0, 0, 0), "{} at 0", theme.name());
+            assert_ne!(colorize(99, 100), (0, 0, 0), "{} at max_iter - 1", theme.name());
+            // With no iterations allowed, every point counts as inside.
+            assert_eq!(colorize(0, 0), (0, 0, 0), "{} with max_iter 0", theme.name());
+            let _ = colorize(u32::MAX - 1, u32::MAX);
+        }
+    }
+
+    #[wasm_bindgen_test]
+    fn classic_starts_at_red() {
+        // Hue 0 at full saturation and half lightness is pure red.
+        assert_eq!(classic(0, 100), (255, 0, 0));
+    }
+}
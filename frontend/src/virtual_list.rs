@@ -0,0 +1,114 @@
+//! Windowed rendering for the block list.
+//!
+//! Every block card is laid out at the same fixed height, so which cards are
+//! on screen follows from the scroll position alone. Only those, plus a few
+//! either side, mount their fractal; the rest are empty placeholders of the
+//! same height, which keeps the scrollbar honest without drawing hundreds of
+//! SVGs and canvases.
+
+/// The height of a block card, including the gap below it, in pixels.
+pub const BLOCK_CARD_HEIGHT: f64 = 320.0;
+
+/// How many cards above and below the viewport stay mounted.
+pub const WINDOW_BUFFER: usize = 3;
+
+/// How many older blocks are fetched from `/blocks` at a time.
+pub const PAGE_SIZE: usize = 20;
+
+/// Older blocks are fetched once the viewport is this close to the bottom of
+/// the list, in pixels.
+pub const LOAD_MORE_THRESHOLD: f64 = 2.0 * BLOCK_CARD_HEIGHT;
+
+/// The range of list positions whose cards are mounted; `end` is exclusive.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Window {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Window {
+    pub fn contains(&self, position: usize) -> bool {
+        (self.start..self.end).contains(&position)
+    }
+}
+
+/// Returns the positions in a list of `len` cards of `item_height` pixels that
+/// intersect a viewport `viewport_height` tall scrolled to `scroll_top`,
+/// widened by `buffer` cards on each side.
+pub fn visible_window(scroll_top: f64, viewport_height: f64, item_height: f64, buffer: usize, len: usize) -> Window {
+    if len == 0 || item_height <= 0.0 {
+        return Window::default();
+    }
+    let scroll_top = scroll_top.max(0.0);
+    let first = (scroll_top / item_height).floor() as usize;
+    let last = ((scroll_top + viewport_height.max(0.0)) / item_height).ceil() as usize;
+    let start = first.saturating_sub(buffer).min(len);
+    let end = last.saturating_add(buffer).min(len).max(start);
+    Window { start, end }
+}
+
+/// Whether the viewport has come within `threshold` pixels of the bottom of
+/// content `content_height` tall.
+pub fn should_load_more(scroll_top: f64, viewport_height: f64, content_height: f64, threshold: f64) -> bool {
+    scroll_top + viewport_height + threshold >= content_height
+}
+
+/// The `before` parameter for the next page of older blocks, given the
+/// lowest index already loaded. `None` once the genesis block is loaded.
+pub fn next_page_before(lowest_loaded: u64) -> Option<u64> {
+    (lowest_loaded > 0).then_some(lowest_loaded)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wasm_bindgen_test::*;
+
+    #[wasm_bindgen_test]
+    fn window_at_top_covers_viewport_and_buffer() {
+        // Three cards fit in the viewport, plus two buffered below.
+        let window = visible_window(0.0, 300.0, 100.0, 2, 500);
+        assert_eq!(window, Window { start: 0, end: 5 });
+    }
+
+    #[wasm_bindgen_test]
+    fn window_follows_scroll_position() {
+        let window = visible_window(1050.0, 300.0, 100.0, 2, 500);
+        // Cards 10 to 13 are partly visible.
+        assert_eq!(window, Window { start: 8, end: 16 });
+        assert!(window.contains(8));
+        assert!(!window.contains(16));
+    }
+
+    #[wasm_bindgen_test]
+    fn window_is_clamped_to_the_list() {
+        assert_eq!(visible_window(49_000.0, 1000.0, 100.0, 3, 500), Window { start: 487, end: 500 });
+        // Scrolled past the end, e.g. just after the list shrank.
+        assert_eq!(visible_window(90_000.0, 1000.0, 100.0, 3, 500), Window { start: 500, end: 500 });
+        assert_eq!(visible_window(-50.0, 300.0, 100.0, 1, 2), Window { start: 0, end: 2 });
+        assert_eq!(visible_window(0.0, 300.0, 100.0, 1, 0), Window::default());
+    }
+
+    #[wasm_bindgen_test]
+    fn window_size_is_bounded_by_viewport() {
+        // However long the chain, only a viewport's worth of cards mount.
+        for len in [10, 500, 100_000] {
+            let window = visible_window(500.0, 1000.0, BLOCK_CARD_HEIGHT, WINDOW_BUFFER, len);
+            assert!(window.end - window.start <= 1000 / BLOCK_CARD_HEIGHT as usize + 2 + 2 * WINDOW_BUFFER);
+        }
+    }
+
+    #[wasm_bindgen_test]
+    fn loads_more_near_the_bottom() {
+        assert!(!should_load_more(0.0, 800.0, 5000.0, 600.0));
+        assert!(should_load_more(3600.0, 800.0, 5000.0, 600.0));
+        // A list shorter than the viewport always wants more.
+        assert!(should_load_more(0.0, 800.0, 300.0, 0.0));
+    }
+
+    #[wasm_bindgen_test]
+    fn paging_stops_at_genesis() {
+        assert_eq!(next_page_before(480), Some(480));
+        assert_eq!(next_page_before(0), None);
+    }
+}
@@ -0,0 +1,52 @@
+use sha2::{Digest, Sha256};
+
+use crate::canonical::{self, Canonical};
+use crate::fractal::FractalData;
+
+/// The fields of a block that its hash commits to, and exactly those: the
+/// proof of work is computed over this struct's canonical encoding, never
+/// over the block itself, so a field added to a block stays out of the hash
+/// unless it is also added here.
+///
+/// The encoding still has the block's `hash`, always empty, so that it
+/// matches blocks hashed before this struct existed.
+///
+/// The node hashes its blocks with their transactions; a miner that only
+/// has them canonically encoded hashes it with `canonical::Encoded`.
+pub struct BlockHashableData<'a, T: ?Sized> {
+    pub index: u64,
+    pub timestamp: i64,
+    pub fractal: &'a FractalData,
+    pub transactions: &'a T,
+    pub previous_hash: &'a str,
+    pub nonce: u64,
+    pub bits: u32,
+    pub style_seed: u64,
+}
+
+impl<T: Canonical + ?Sized> BlockHashableData<'_, T> {
+    /// Calculates the hex-encoded SHA-256 hash of the canonical encoding.
+    pub fn hash(&self) -> String {
+        format!("{:x}", Sha256::digest(canonical::to_bytes(self)))
+    }
+}
+
+/// Derives the fractal seed for a block at `index` on top of `previous_hash`
+/// trying `nonce` in the style of `style_seed`. Binding the seed to the
+/// block's position means a fractal found for one block can't be reused in
+/// another, and binding it to the nonce means the style seed shapes the
+/// fractal without choosing it outright.
+///
+/// A style seed of 0 is left out, so blocks mined before there were style
+/// seeds keep their fractals.
+pub fn derive_fractal_seed(previous_hash: &str, index: u64, nonce: u64, style_seed: u64) -> u64 {
+    let mut hasher = Sha256::new();
+    hasher.update(previous_hash.as_bytes());
+    hasher.update(index.to_le_bytes());
+    hasher.update(nonce.to_le_bytes());
+    if style_seed != 0 {
+        hasher.update(style_seed.to_le_bytes());
+    }
+    let digest = hasher.finalize();
+    u64::from_le_bytes(digest[..8].try_into().unwrap())
+}
@@ -0,0 +1,254 @@
+use std::fmt::Write;
+
+use crate::block::BlockHashableData;
+use crate::fractal::coord::Coord;
+use crate::fractal::julia::Julia;
+use crate::fractal::mandelbrot::Mandelbrot;
+use crate::fractal::sierpinski::Sierpinski;
+use crate::fractal::utils::RngVersion;
+use crate::fractal::FractalData;
+
+/// The bytes block and transaction hashes are computed over.
+///
+/// The layout is spelled out here field by field rather than left to serde,
+/// so that renaming, reordering or adding a field to a type, or a change in
+/// how a serde_json version formats a value, can't change a hash. It is
+/// compact JSON with fields in the order written below, numbers as plain
+/// integers (fractal coordinates are fixed-point `Coord`s, so there are no
+/// floats) and strings escaped as JSON requires and no further, which is
+/// exactly what blocks and transactions were hashed over before this
+/// existed.
+pub trait Canonical {
+    fn encode(&self, out: &mut Encoder);
+}
+
+/// Returns the canonical encoding of `value`.
+pub fn to_bytes(value: &(impl Canonical + ?Sized)) -> Vec<u8> {
+    to_string(value).into_bytes()
+}
+
+/// Returns the canonical encoding of `value` as text, which it always is.
+pub fn to_string(value: &(impl Canonical + ?Sized)) -> String {
+    let mut out = Encoder::default();
+    value.encode(&mut out);
+    out.buf
+}
+
+/// Accumulates a canonical encoding.
+#[derive(Default)]
+pub struct Encoder {
+    buf: String,
+}
+
+impl Encoder {
+    fn integer(&mut self, value: impl std::fmt::Display) {
+        write!(self.buf, "{}", value).unwrap();
+    }
+
+    fn string(&mut self, value: &str) {
+        self.buf.push('"');
+        for c in value.chars() {
+            match c {
+                '"' => self.buf.push_str("\\\""),
+                '\\' => self.buf.push_str("\\\\"),
+                '\u{8}' => self.buf.push_str("\\b"),
+                '\u{c}' => self.buf.push_str("\\f"),
+                '\n' => self.buf.push_str("\\n"),
+                '\r' => self.buf.push_str("\\r"),
+                '\t' => self.buf.push_str("\\t"),
+                c if c < ' ' => write!(self.buf, "\\u{:04x}", c as u32).unwrap(),
+                c => self.buf.push(c),
+            }
+        }
+        self.buf.push('"');
+    }
+
+    /// Encodes an object whose fields `fields` adds in order.
+    pub fn object(&mut self, fields: impl FnOnce(&mut Fields<'_>)) {
+        self.buf.push('{');
+        fields(&mut Fields { out: self, first: true });
+        self.buf.push('}');
+    }
+}
+
+/// The fields of an object being encoded.
+pub struct Fields<'a> {
+    out: &'a mut Encoder,
+    first: bool,
+}
+
+impl Fields<'_> {
+    pub fn field(&mut self, name: &str, value: &(impl Canonical + ?Sized)) -> &mut Self {
+        if !self.first {
+            self.out.buf.push(',');
+        }
+        self.first = false;
+        self.out.string(name);
+        self.out.buf.push(':');
+        value.encode(self.out);
+        self
+    }
+}
+
+macro_rules! canonical_integer {
+    ($($t:ty),*) => {
+        $(impl Canonical for $t {
+            fn encode(&self, out: &mut Encoder) {
+                out.integer(self);
+            }
+        })*
+    };
+}
+
+canonical_integer!(u8, u32, u64, i64, usize);
+
+impl Canonical for RngVersion {
+    fn encode(&self, out: &mut Encoder) {
+        u8::from(*self).encode(out);
+    }
+}
+
+impl Canonical for Coord {
+    fn encode(&self, out: &mut Encoder) {
+        out.integer(self.steps());
+    }
+}
+
+impl Canonical for str {
+    fn encode(&self, out: &mut Encoder) {
+        out.string(self);
+    }
+}
+
+impl Canonical for String {
+    fn encode(&self, out: &mut Encoder) {
+        out.string(self);
+    }
+}
+
+impl<T: Canonical> Canonical for [T] {
+    fn encode(&self, out: &mut Encoder) {
+        out.buf.push('[');
+        for (i, item) in self.iter().enumerate() {
+            if i > 0 {
+                out.buf.push(',');
+            }
+            item.encode(out);
+        }
+        out.buf.push(']');
+    }
+}
+
+impl<T: Canonical> Canonical for Vec<T> {
+    fn encode(&self, out: &mut Encoder) {
+        self.as_slice().encode(out);
+    }
+}
+
+/// Text already in the canonical encoding, copied as it is. Miners encode a
+/// block's transactions once rather than for every nonce they try.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Encoded<'a>(pub &'a str);
+
+impl Canonical for Encoded<'_> {
+    fn encode(&self, out: &mut Encoder) {
+        out.buf.push_str(self.0);
+    }
+}
+
+// Where they can, the impls below destructure their type exhaustively, so
+// adding a field fails to compile until it is either encoded or left out
+// on purpose.
+
+impl<T: Canonical + ?Sized> Canonical for BlockHashableData<'_, T> {
+    fn encode(&self, out: &mut Encoder) {
+        let BlockHashableData { index, timestamp, fractal, transactions, previous_hash, nonce, bits, style_seed } = self;
+        out.object(|o| {
+            o.field("index", index)
+                .field("timestamp", timestamp)
+                .field("fractal", *fractal)
+                .field("transactions", *transactions)
+                .field("previous_hash", *previous_hash)
+                // Always empty; kept from when the whole block was hashed.
+                .field("hash", "")
+                .field("nonce", nonce);
+            // Blocks from before targets were stored have none.
+            if *bits != 0 {
+                o.field("bits", bits);
+            }
+            // Nor do blocks from before style seeds.
+            if *style_seed != 0 {
+                o.field("style_seed", style_seed);
+            }
+        });
+    }
+}
+
+impl Canonical for FractalData {
+    fn encode(&self, out: &mut Encoder) {
+        out.object(|o| {
+            o.field("type", self.type_name());
+            match self {
+                FractalData::Sierpinski(sierpinski) => o.field("data", sierpinski),
+                FractalData::Mandelbrot(mandelbrot) => o.field("data", mandelbrot),
+                FractalData::Julia(julia) => o.field("data", julia),
+            };
+        });
+    }
+}
+
+impl Canonical for Sierpinski {
+    fn encode(&self, out: &mut Encoder) {
+        // The vertices follow from the depth, seed and RNG version.
+        out.object(|o| {
+            o.field("depth", &self.depth).field("seed", &self.seed);
+            // Fractals from before RNG versions have none.
+            if !self.rng_version.is_legacy() {
+                o.field("rng_version", &self.rng_version);
+            }
+        });
+    }
+}
+
+impl Canonical for Mandelbrot {
+    fn encode(&self, out: &mut Encoder) {
+        let Mandelbrot { width, height, x_min, x_max, y_min, y_max, max_iterations, seed, data, rng_version } = self;
+        out.object(|o| {
+            o.field("width", width)
+                .field("height", height)
+                .field("x_min", x_min)
+                .field("x_max", x_max)
+                .field("y_min", y_min)
+                .field("y_max", y_max)
+                .field("max_iterations", max_iterations)
+                .field("seed", seed)
+                .field("data", data);
+            if !rng_version.is_legacy() {
+                o.field("rng_version", rng_version);
+            }
+        });
+    }
+}
+
+impl Canonical for Julia {
+    fn encode(&self, out: &mut Encoder) {
+        let Julia { width, height, x_min, x_max, y_min, y_max, c_real, c_imag, max_iterations, seed, data, rng_version } = self;
+        out.object(|o| {
+            o.field("width", width)
+                .field("height", height)
+                .field("x_min", x_min)
+                .field("x_max", x_max)
+                .field("y_min", y_min)
+                .field("y_max", y_max)
+                .field("c_real", c_real)
+                .field("c_imag", c_imag)
+                .field("max_iterations", max_iterations)
+                .field("seed", seed)
+                .field("data", data);
+            if !rng_version.is_legacy() {
+                o.field("rng_version", rng_version);
+            }
+        });
+    }
+}
+
@@ -0,0 +1,98 @@
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// How many `Coord` steps make 1.0.
+pub const COORD_SCALE: f64 = 1e12;
+
+/// A coordinate or constant of an escape-time fractal, in fixed point: a
+/// whole number of 1e-12 steps, serialized as that integer.
+///
+/// Fractal parameters are part of every block hash. As floats they could be
+/// NaN or infinite, which JSON can't encode, or -0.0, which hashes
+/// differently from 0.0, and nodes would have to agree on how each one's
+/// decimal text rounds. An integer has exactly one encoding. Generation
+/// works on `to_f64`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Coord(i64);
+
+/// Why a float can't be a `Coord`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CoordError {
+    /// NaN or an infinity.
+    NotFinite,
+    /// Too large to count in 1e-12 steps in an `i64`, about 9.2 million.
+    OutOfRange,
+}
+
+impl fmt::Display for CoordError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CoordError::NotFinite => write!(f, "must be a finite number"),
+            CoordError::OutOfRange => write!(f, "must be between -9.2e6 and 9.2e6"),
+        }
+    }
+}
+
+impl Coord {
+    /// Rounds `value` to the nearest step. -0.0 and denormals become 0.
+    pub fn from_f64(value: f64) -> Result<Self, CoordError> {
+        if !value.is_finite() {
+            return Err(CoordError::NotFinite);
+        }
+        let steps = (value * COORD_SCALE).round();
+        // `i64::MAX as f64` rounds up to 2^63, which doesn't fit.
+        if steps < i64::MIN as f64 || steps >= i64::MAX as f64 {
+            return Err(CoordError::OutOfRange);
+        }
+        Ok(Coord(steps as i64))
+    }
+
+    /// The coordinate with `steps` steps of 1e-12, as it is serialized.
+    pub const fn from_steps(steps: i64) -> Self {
+        Coord(steps)
+    }
+
+    pub fn steps(self) -> i64 {
+        self.0
+    }
+
+    pub fn to_f64(self) -> f64 {
+        self.0 as f64 / COORD_SCALE
+    }
+}
+
+impl fmt::Display for Coord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.to_f64())
+    }
+}
+
+/// Converts the `field` parameter of a request to a `Coord`, naming it in
+/// the error.
+pub fn parse_coord(field: &str, value: f64) -> Result<Coord, String> {
+    Coord::from_f64(value).map_err(|e| format!("{} {}", field, e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_floats_are_normalized_or_rejected() {
+        assert_eq!(Coord::from_f64(-2.0), Ok(Coord::from_steps(-2_000_000_000_000)));
+        assert_eq!(Coord::from_f64(0.156).unwrap().to_f64(), 0.156);
+        assert_eq!(Coord::from_f64(-0.0), Coord::from_f64(0.0));
+        assert_eq!(Coord::from_f64(f64::MIN_POSITIVE / 2.0), Ok(Coord::default()));
+        assert_eq!(Coord::from_f64(1e-13), Ok(Coord::default()));
+        for value in [f64::NAN, -f64::NAN, f64::INFINITY, f64::NEG_INFINITY] {
+            assert_eq!(Coord::from_f64(value), Err(CoordError::NotFinite));
+        }
+        for value in [1e7, -1e7, f64::MAX, f64::MIN] {
+            assert_eq!(Coord::from_f64(value), Err(CoordError::OutOfRange));
+        }
+        assert!(Coord::from_f64(9.2e6).is_ok());
+        assert_eq!(serde_json::to_string(&Coord::from_f64(-1.5).unwrap()).unwrap(), "-1500000000000");
+        assert!(serde_json::from_str::<Coord>("-1.5").is_err());
+    }
+}
@@ -1,46 +1,54 @@
 use serde::{Serialize, Deserialize};
-use super::utils::Lcg;
+use super::coord::Coord;
+use super::utils::RngVersion;
 
 #[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
 pub struct Julia {
     pub width: usize,
     pub height: usize,
-    pub x_min: f64,
-    pub x_max: f64,
-    pub y_min: f64,
-    pub y_max: f64,
-    pub c_real: f64,
-    pub c_imag: f64,
+    pub x_min: Coord,
+    pub x_max: Coord,
+    pub y_min: Coord,
+    pub y_max: Coord,
+    pub c_real: Coord,
+    pub c_imag: Coord,
     pub max_iterations: u32,
     pub seed: u64,
     pub data: Vec<u32>,
+    /// The generator the seed was fed to.
+    #[serde(default, skip_serializing_if = "RngVersion::is_legacy")]
+    pub rng_version: RngVersion,
 }
 
 impl Julia {
+    #[allow(clippy::too_many_arguments)]
     pub fn generate(
         width: usize,
         height: usize,
-        x_min: f64,
-        x_max: f64,
-        y_min: f64,
-        y_max: f64,
-        c_real: f64,
-        c_imag: f64,
+        x_min: Coord,
+        x_max: Coord,
+        y_min: Coord,
+        y_max: Coord,
+        c_real: Coord,
+        c_imag: Coord,
         max_iterations: u32,
         seed: u64,
+        rng_version: RngVersion,
     ) -> Self {
         let mut data = vec![0; width * height];
-        let mut rng = Lcg::new(seed);
+        let mut rng = rng_version.rng(seed);
         let perturbation_scale = 0.001 / (max_iterations as f64);
+        let (x_lo, x_hi, y_lo, y_hi) = (x_min.to_f64(), x_max.to_f64(), y_min.to_f64(), y_max.to_f64());
+        let (c_re, c_im) = (c_real.to_f64(), c_imag.to_f64());
 
         for py in 0..height {
             for px in 0..width {
-                let mut x = x_min + (px as f64 / width as f64) * (x_max - x_min);
-                let mut y = y_min + (py as f64 / height as f64) * (y_max - y_min);
+                let mut x = x_lo + (px as f64 / width as f64) * (x_hi - x_lo);
+                let mut y = y_lo + (py as f64 / height as f64) * (y_hi - y_lo);
                 let mut iteration = 0;
                 while x * x + y * y <= 4.0 && iteration < max_iterations {
-                    let xtemp = x * x - y * y + c_real + rng.next_float() * perturbation_scale;
-                    y = 2.0 * x * y + c_imag + rng.next_float() * perturbation_scale;
+                    let xtemp = x * x - y * y + c_re + rng.next_float() * perturbation_scale;
+                    y = 2.0 * x * y + c_im + rng.next_float() * perturbation_scale;
                     x = xtemp;
                     iteration += 1;
                 }
@@ -59,6 +67,7 @@ impl Julia {
             max_iterations,
             seed,
             data,
+            rng_version,
         }
     }
 }
@@ -70,7 +79,8 @@ mod tests {
     #[test]
     fn test_julia_generation() {
         // c = -0.8 + 0.156i
-        let julia = Julia::generate(10, 10, -1.5, 1.5, -1.5, 1.5, -0.8, 0.156, 100, 0);
+        let c = |value| Coord::from_f64(value).unwrap();
+        let julia = Julia::generate(10, 10, c(-1.5), c(1.5), c(-1.5), c(1.5), c(-0.8), c(0.156), 100, 0, RngVersion::Lcg);
         assert_eq!(julia.data.len(), 100);
 
         // Test a point that should escape quickly
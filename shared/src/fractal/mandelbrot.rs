@@ -1,38 +1,45 @@
 use serde::{Serialize, Deserialize};
-use super::utils::Lcg;
+use super::coord::Coord;
+use super::utils::RngVersion;
 
 #[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
 pub struct Mandelbrot {
     pub width: usize,
     pub height: usize,
-    pub x_min: f64,
-    pub x_max: f64,
-    pub y_min: f64,
-    pub y_max: f64,
+    pub x_min: Coord,
+    pub x_max: Coord,
+    pub y_min: Coord,
+    pub y_max: Coord,
     pub max_iterations: u32,
     pub seed: u64,
     pub data: Vec<u32>,
+    /// The generator the seed was fed to.
+    #[serde(default, skip_serializing_if = "RngVersion::is_legacy")]
+    pub rng_version: RngVersion,
 }
 
 impl Mandelbrot {
+    #[allow(clippy::too_many_arguments)]
     pub fn generate(
         width: usize,
         height: usize,
-        x_min: f64,
-        x_max: f64,
-        y_min: f64,
-        y_max: f64,
+        x_min: Coord,
+        x_max: Coord,
+        y_min: Coord,
+        y_max: Coord,
         max_iterations: u32,
         seed: u64,
+        rng_version: RngVersion,
     ) -> Self {
         let mut data = vec![0; width * height];
-        let mut rng = Lcg::new(seed);
+        let mut rng = rng_version.rng(seed);
         let perturbation_scale = 0.001 / (max_iterations as f64);
+        let (x_lo, x_hi, y_lo, y_hi) = (x_min.to_f64(), x_max.to_f64(), y_min.to_f64(), y_max.to_f64());
 
         for py in 0..height {
             for px in 0..width {
-                let x0 = x_min + (px as f64 / width as f64) * (x_max - x_min);
-                let y0 = y_min + (py as f64 / height as f64) * (y_max - y_min);
+                let x0 = x_lo + (px as f64 / width as f64) * (x_hi - x_lo);
+                let y0 = y_lo + (py as f64 / height as f64) * (y_hi - y_lo);
                 let mut x = 0.0;
                 let mut y = 0.0;
                 let mut iteration = 0;
@@ -55,6 +62,7 @@ impl Mandelbrot {
             max_iterations,
             seed,
             data,
+            rng_version,
         }
     }
 }
@@ -65,7 +73,8 @@ mod tests {
 
     #[test]
     fn test_mandelbrot_generation() {
-        let mandelbrot = Mandelbrot::generate(10, 10, -2.0, 1.0, -1.5, 1.5, 100, 0);
+        let c = |value| Coord::from_f64(value).unwrap();
+        let mandelbrot = Mandelbrot::generate(10, 10, c(-2.0), c(1.0), c(-1.5), c(1.5), 100, 0, RngVersion::Lcg);
         assert_eq!(mandelbrot.data.len(), 100);
 
         // Test a point in the set (center of the main cardioid)
@@ -0,0 +1,312 @@
+use serde::{Serialize, Deserialize};
+pub mod coord;
+pub mod sierpinski;
+pub mod mandelbrot;
+pub mod julia;
+pub mod utils;
+
+use self::coord::{parse_coord, Coord};
+use self::sierpinski::Sierpinski;
+use self::mandelbrot::Mandelbrot;
+use self::julia::Julia;
+use self::utils::RngVersion;
+
+/// An enum to hold the data for different fractal types.
+/// This will be stored in the block.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+#[serde(tag = "type", content = "data")]
+pub enum FractalData {
+    Sierpinski(Sierpinski),
+    Mandelbrot(Mandelbrot),
+    Julia(Julia),
+}
+
+/// Every name `FractalData::type_name` can return.
+pub const FRACTAL_TYPE_NAMES: [&str; 3] = ["Sierpinski", "Mandelbrot", "Julia"];
+
+impl FractalData {
+    /// Returns the name of the fractal's type, e.g. `"Sierpinski"`.
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            FractalData::Sierpinski(_) => "Sierpinski",
+            FractalData::Mandelbrot(_) => "Mandelbrot",
+            FractalData::Julia(_) => "Julia",
+        }
+    }
+
+    /// Returns the seed the fractal was generated from.
+    pub fn seed(&self) -> u64 {
+        match self {
+            FractalData::Sierpinski(s) => s.seed,
+            FractalData::Mandelbrot(m) => m.seed,
+            FractalData::Julia(j) => j.seed,
+        }
+    }
+
+    /// Returns the generator the fractal was generated with.
+    pub fn rng_version(&self) -> RngVersion {
+        match self {
+            FractalData::Sierpinski(s) => s.rng_version,
+            FractalData::Mandelbrot(m) => m.rng_version,
+            FractalData::Julia(j) => j.rng_version,
+        }
+    }
+
+    /// Returns the parameters that, with `rng_version`, regenerate this fractal.
+    pub fn fractal_type(&self) -> FractalType {
+        match self {
+            FractalData::Sierpinski(s) => FractalType::Sierpinski { depth: s.depth, seed: s.seed },
+            FractalData::Mandelbrot(m) => FractalType::Mandelbrot {
+                width: m.width,
+                height: m.height,
+                x_min: m.x_min,
+                x_max: m.x_max,
+                y_min: m.y_min,
+                y_max: m.y_max,
+                max_iterations: m.max_iterations,
+                seed: m.seed,
+            },
+            FractalData::Julia(j) => FractalType::Julia {
+                width: j.width,
+                height: j.height,
+                x_min: j.x_min,
+                x_max: j.x_max,
+                y_min: j.y_min,
+                y_max: j.y_max,
+                c_real: j.c_real,
+                c_imag: j.c_imag,
+                max_iterations: j.max_iterations,
+                seed: j.seed,
+            },
+        }
+    }
+
+    /// Scores how visually rich the fractal is, from 0.0 to 1.0.
+    ///
+    /// Escape-time fractals are scored by the normalized entropy of their
+    /// iteration counts, so a flat image scores 0 and one using every
+    /// iteration count equally often scores 1. Sierpinski triangles are
+    /// scored by depth, approaching 1 as they get deeper.
+    pub fn quality_score(&self) -> f64 {
+        match self {
+            FractalData::Sierpinski(s) => 1.0 - 1.0 / (s.depth as f64 + 1.0),
+            FractalData::Mandelbrot(m) => iteration_entropy(&m.data, m.max_iterations),
+            FractalData::Julia(j) => iteration_entropy(&j.data, j.max_iterations),
+        }
+    }
+}
+
+fn iteration_entropy(data: &[u32], max_iterations: u32) -> f64 {
+    if data.is_empty() || max_iterations == 0 {
+        return 0.0;
+    }
+    let mut counts = std::collections::HashMap::new();
+    for &iteration in data {
+        *counts.entry(iteration).or_insert(0usize) += 1;
+    }
+    let total = data.len() as f64;
+    let entropy: f64 = counts
+        .values()
+        .map(|&count| {
+            let p = count as f64 / total;
+            -p * p.log2()
+        })
+        .sum();
+    (entropy / (max_iterations as f64 + 1.0).log2()).clamp(0.0, 1.0)
+}
+
+/// The generation cost, in triangles or pixels, that mining weights are
+/// normalized to: a depth-5 Sierpinski triangle, the fractal this node mines
+/// and hands out in templates. Fractals at least this costly are unweighted.
+pub const REFERENCE_GENERATION_COST: u64 = 243;
+
+/// An enum to represent the different types of fractals that can be generated.
+/// This will be used in the mining request. Coordinates are `Coord`s, so
+/// parameters that deserialize are always finite and hash one way; build
+/// them from floats with `mandelbrot` and `julia`.
+///
+/// In a mining request `seed` is the style seed the block commits to. The
+/// miner generates each nonce's fractal from a seed derived from it and the
+/// nonce, see `block::derive_fractal_seed`, so the same style seed gives a
+/// different fractal in every block.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub enum FractalType {
+    Sierpinski { depth: usize, seed: u64 },
+    Mandelbrot {
+        width: usize,
+        height: usize,
+        x_min: Coord,
+        x_max: Coord,
+        y_min: Coord,
+        y_max: Coord,
+        max_iterations: u32,
+        seed: u64,
+    },
+    Julia {
+        width: usize,
+        height: usize,
+        x_min: Coord,
+        x_max: Coord,
+        y_min: Coord,
+        y_max: Coord,
+        c_real: Coord,
+        c_imag: Coord,
+        max_iterations: u32,
+        seed: u64,
+    },
+}
+
+impl FractalType {
+    /// Mandelbrot parameters from floating-point coordinates, which are
+    /// rejected if not finite and otherwise rounded to `Coord`s.
+    #[allow(clippy::too_many_arguments)]
+    pub fn mandelbrot(
+        width: usize,
+        height: usize,
+        x_min: f64,
+        x_max: f64,
+        y_min: f64,
+        y_max: f64,
+        max_iterations: u32,
+        seed: u64,
+    ) -> Result<Self, String> {
+        Ok(FractalType::Mandelbrot {
+            width,
+            height,
+            x_min: parse_coord("x_min", x_min)?,
+            x_max: parse_coord("x_max", x_max)?,
+            y_min: parse_coord("y_min", y_min)?,
+            y_max: parse_coord("y_max", y_max)?,
+            max_iterations,
+            seed,
+        })
+    }
+
+    /// Julia set parameters from floating-point coordinates, checked like
+    /// those of `mandelbrot`.
+    #[allow(clippy::too_many_arguments)]
+    pub fn julia(
+        width: usize,
+        height: usize,
+        x_min: f64,
+        x_max: f64,
+        y_min: f64,
+        y_max: f64,
+        c_real: f64,
+        c_imag: f64,
+        max_iterations: u32,
+        seed: u64,
+    ) -> Result<Self, String> {
+        Ok(FractalType::Julia {
+            width,
+            height,
+            x_min: parse_coord("x_min", x_min)?,
+            x_max: parse_coord("x_max", x_max)?,
+            y_min: parse_coord("y_min", y_min)?,
+            y_max: parse_coord("y_max", y_max)?,
+            c_real: parse_coord("c_real", c_real)?,
+            c_imag: parse_coord("c_imag", c_imag)?,
+            max_iterations,
+            seed,
+        })
+    }
+
+    /// Returns the seed: the style seed of a mining request, or the seed a
+    /// fractal was generated from.
+    pub fn seed(&self) -> u64 {
+        match self {
+            FractalType::Sierpinski { seed, .. } | FractalType::Mandelbrot { seed, .. } | FractalType::Julia { seed, .. } => {
+                *seed
+            }
+        }
+    }
+
+    /// Returns the same fractal parameters with a different seed.
+    pub fn with_seed(mut self, new_seed: u64) -> Self {
+        match &mut self {
+            FractalType::Sierpinski { seed, .. } => *seed = new_seed,
+            FractalType::Mandelbrot { seed, .. } => *seed = new_seed,
+            FractalType::Julia { seed, .. } => *seed = new_seed,
+        }
+        self
+    }
+
+    /// Estimates how much work generating the fractal takes: the number of
+    /// triangles in a Sierpinski triangle, or of pixels in an escape-time
+    /// fractal. The seed doesn't affect it.
+    pub fn generation_cost(&self) -> u64 {
+        match self {
+            FractalType::Sierpinski { depth, .. } => 3u64.saturating_pow((*depth).try_into().unwrap_or(u32::MAX)),
+            FractalType::Mandelbrot { width, height, .. } | FractalType::Julia { width, height, .. } => {
+                (*width as u64).saturating_mul(*height as u64)
+            }
+        }
+    }
+
+    /// How many times more hashes a block of this fractal needs than one of
+    /// `REFERENCE_GENERATION_COST`, so that each nonce tried costs miners
+    /// about the same whichever fractal they pick. Never below 1: costlier
+    /// fractals don't get an easier target.
+    pub fn work_factor(&self) -> u64 {
+        (REFERENCE_GENERATION_COST / self.generation_cost().max(1)).max(1)
+    }
+
+    /// Generates the fractal with the generator new blocks use.
+    pub fn generate(&self) -> FractalData {
+        self.generate_with(RngVersion::CURRENT)
+    }
+
+    /// Generates the fractal with the generator `rng_version`, as a block
+    /// declaring it must have.
+    pub fn generate_with(&self, rng_version: RngVersion) -> FractalData {
+        match self {
+            FractalType::Sierpinski { depth, seed } => {
+                FractalData::Sierpinski(Sierpinski::generate(*depth, *seed, rng_version))
+            }
+            FractalType::Mandelbrot {
+                width,
+                height,
+                x_min,
+                x_max,
+                y_min,
+                y_max,
+                max_iterations,
+                seed,
+            } => FractalData::Mandelbrot(Mandelbrot::generate(
+                *width,
+                *height,
+                *x_min,
+                *x_max,
+                *y_min,
+                *y_max,
+                *max_iterations,
+                *seed,
+                rng_version,
+            )),
+            FractalType::Julia {
+                width,
+                height,
+                x_min,
+                x_max,
+                y_min,
+                y_max,
+                c_real,
+                c_imag,
+                max_iterations,
+                seed,
+            } => FractalData::Julia(Julia::generate(
+                *width,
+                *height,
+                *x_min,
+                *x_max,
+                *y_min,
+                *y_max,
+                *c_real,
+                *c_imag,
+                *max_iterations,
+                *seed,
+                rng_version,
+            )),
+        }
+    }
+}
@@ -0,0 +1,110 @@
+use serde::{Serialize, Deserialize};
+use std::sync::OnceLock;
+use super::utils::{FractalRng, RngVersion};
+
+/// Represents a Sierpinski triangle fractal.
+///
+/// The triangle is fully determined by its depth, seed and RNG version, so
+/// only those are serialized (and therefore hashed). The vertices are regenerated on demand
+/// and cached.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Sierpinski {
+    /// The depth of the fractal.
+    pub depth: usize,
+    /// The seed used to generate the fractal.
+    pub seed: u64,
+    /// The generator the seed was fed to.
+    #[serde(default, skip_serializing_if = "RngVersion::is_legacy")]
+    pub rng_version: RngVersion,
+    /// The vertices of the triangles that make up the fractal.
+    #[serde(skip)]
+    vertices: OnceLock<Vec<(f64, f64)>>,
+}
+
+impl PartialEq for Sierpinski {
+    fn eq(&self, other: &Self) -> bool {
+        self.depth == other.depth && self.seed == other.seed && self.rng_version == other.rng_version
+    }
+}
+
+impl Sierpinski {
+    /// Generates a new `Sierpinski` fractal of a given depth and seed.
+    pub fn generate(depth: usize, seed: u64, rng_version: RngVersion) -> Self {
+        let sierpinski = Sierpinski { depth, seed, rng_version, vertices: OnceLock::new() };
+        sierpinski.vertices();
+        sierpinski
+    }
+
+    /// Returns the vertices of the fractal, computing them on first use.
+    pub fn vertices(&self) -> &[(f64, f64)] {
+        self.vertices.get_or_init(|| {
+            let mut vertices = Vec::new();
+            let initial_triangle = [(0.0, 0.0), (1.0, 0.0), (0.5, 0.866)];
+            let mut rng = self.rng_version.rng(self.seed);
+            Self::subdivide(&mut vertices, self.depth, initial_triangle[0], initial_triangle[1], initial_triangle[2], rng.as_mut());
+            vertices
+        })
+    }
+
+    /// Recursively subdivides a triangle to generate the fractal.
+    fn subdivide(vertices: &mut Vec<(f64, f64)>, depth: usize, p1: (f64, f64), p2: (f64, f64), p3: (f64, f64), rng: &mut dyn FractalRng) {
+        if depth == 0 {
+            // Base case: add the triangle's vertices to the list.
+            vertices.push(p1);
+            vertices.push(p2);
+            vertices.push(p3);
+        } else {
+            // Recursive step: calculate midpoints and subdivide.
+            let perturbation_scale = 0.05 / (depth as f64);
+
+            let m12 = (
+                (p1.0 + p2.0) / 2.0 + rng.next_float() * perturbation_scale,
+                (p1.1 + p2.1) / 2.0 + rng.next_float() * perturbation_scale,
+            );
+            let m23 = (
+                (p2.0 + p3.0) / 2.0 + rng.next_float() * perturbation_scale,
+                (p2.1 + p3.1) / 2.0 + rng.next_float() * perturbation_scale,
+            );
+            let m13 = (
+                (p1.0 + p3.0) / 2.0 + rng.next_float() * perturbation_scale,
+                (p1.1 + p3.1) / 2.0 + rng.next_float() * perturbation_scale,
+            );
+
+            Self::subdivide(vertices, depth - 1, p1, m12, m13, rng);
+            Self::subdivide(vertices, depth - 1, m12, p2, m23, rng);
+            Self::subdivide(vertices, depth - 1, m13, m23, p3, rng);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_serialization_omits_vertices() {
+        let sierpinski = Sierpinski::generate(3, 42, RngVersion::Lcg);
+        let json = serde_json::to_value(&sierpinski).unwrap();
+        assert_eq!(json, serde_json::json!({ "depth": 3, "seed": 42 }));
+        let sierpinski = Sierpinski::generate(3, 42, RngVersion::SplitMix64);
+        let json = serde_json::to_value(&sierpinski).unwrap();
+        assert_eq!(json, serde_json::json!({ "depth": 3, "seed": 42, "rng_version": 2 }));
+    }
+
+    #[test]
+    fn test_regenerated_vertices_match_stored_vertices() {
+        for seed in [0, 1, 7, 12345, u64::MAX] {
+            let original = Sierpinski::generate(4, seed, RngVersion::Lcg);
+            // Blocks stored before compaction carry their vertices inline.
+            let stored = serde_json::json!({
+                "depth": original.depth,
+                "seed": original.seed,
+                "vertices": original.vertices(),
+            });
+
+            let restored: Sierpinski = serde_json::from_value(stored.clone()).unwrap();
+            assert_eq!(serde_json::to_value(restored.vertices()).unwrap(), stored["vertices"]);
+            assert_eq!(restored.vertices().len(), 3 * 3usize.pow(4));
+        }
+    }
+}
@@ -0,0 +1,167 @@
+//! The pseudo-random numbers fractals are perturbed with. They are part of
+//! consensus, since a block's fractal has to regenerate exactly, so each
+//! generator is numbered and every fractal records the one it used.
+
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// A source of perturbations for fractal generation.
+pub trait FractalRng {
+    fn next_u64(&mut self) -> u64;
+
+    /// Returns a float between -1.0 and 1.0
+    fn next_float(&mut self) -> f64;
+}
+
+/// Which `FractalRng` a fractal was generated with. Fractals from before
+/// there was a choice don't record one and used the LCG.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "u8", into = "u8")]
+pub enum RngVersion {
+    /// `Lcg`, the POSIX `rand()` generator.
+    #[default]
+    Lcg = 1,
+    /// `SplitMix64`.
+    SplitMix64 = 2,
+}
+
+impl RngVersion {
+    /// The version new blocks are mined with.
+    pub const CURRENT: RngVersion = RngVersion::SplitMix64;
+
+    /// Returns true for the LCG, which fractals leave unrecorded.
+    pub fn is_legacy(&self) -> bool {
+        *self == RngVersion::Lcg
+    }
+
+    /// Returns this version's generator, seeded with `seed`.
+    pub fn rng(self, seed: u64) -> Box<dyn FractalRng> {
+        match self {
+            RngVersion::Lcg => Box::new(Lcg::new(seed)),
+            RngVersion::SplitMix64 => Box::new(SplitMix64::new(seed)),
+        }
+    }
+}
+
+/// A version number no generator has.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UnknownRngVersion(pub u8);
+
+impl fmt::Display for UnknownRngVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Unknown fractal RNG version {}", self.0)
+    }
+}
+
+impl TryFrom<u8> for RngVersion {
+    type Error = UnknownRngVersion;
+
+    fn try_from(version: u8) -> Result<Self, Self::Error> {
+        match version {
+            1 => Ok(RngVersion::Lcg),
+            2 => Ok(RngVersion::SplitMix64),
+            _ => Err(UnknownRngVersion(version)),
+        }
+    }
+}
+
+impl From<RngVersion> for u8 {
+    fn from(version: RngVersion) -> Self {
+        version as u8
+    }
+}
+
+/// A simple Linear Congruential Generator for pseudo-random numbers.
+///
+/// Its low bits cycle quickly and `next_float` only takes 2001 values, which
+/// shows as regular artifacts in perturbed fractals. It is kept so that
+/// blocks generated with it still validate.
+pub struct Lcg {
+    state: u64,
+}
+
+impl Lcg {
+    pub fn new(seed: u64) -> Self {
+        Lcg { state: seed }
+    }
+}
+
+impl FractalRng for Lcg {
+    fn next_u64(&mut self) -> u64 {
+        // Parameters from POSIX standard for rand()
+        self.state = self.state.wrapping_mul(1103515245).wrapping_add(12345);
+        self.state
+    }
+
+    fn next_float(&mut self) -> f64 {
+        (self.next_u64() % 2001) as f64 / 1000.0 - 1.0
+    }
+}
+
+/// Keys `SplitMix64` streams, so that they differ from those of other
+/// programs using the generator with the same seeds: "SierpCha" in ASCII.
+const SPLITMIX64_KEY: u64 = 0x5369_6572_7043_6861;
+
+/// Vigna's SplitMix64, started at the seed XORed with `SPLITMIX64_KEY`.
+/// Every output bit depends on every state bit, and floats are uniform over
+/// 2^53 values.
+pub struct SplitMix64 {
+    state: u64,
+}
+
+impl SplitMix64 {
+    pub fn new(seed: u64) -> Self {
+        SplitMix64 { state: seed ^ SPLITMIX64_KEY }
+    }
+}
+
+impl FractalRng for SplitMix64 {
+    fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    fn next_float(&mut self) -> f64 {
+        // The top 53 bits, as a fraction of 2^53, scaled to [-1, 1).
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64 * 2.0 - 1.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn take(version: RngVersion, seed: u64, count: usize) -> Vec<u64> {
+        let mut rng = version.rng(seed);
+        (0..count).map(|_| rng.next_u64()).collect()
+    }
+
+    #[test]
+    fn test_lcg_outputs_are_pinned() {
+        assert_eq!(take(RngVersion::Lcg, 42, 3), [46347652635, 14251853105267829688, 7335485157495086481]);
+        assert_eq!(Lcg::new(42).next_float(), -0.61);
+    }
+
+    #[test]
+    fn test_splitmix64_is_deterministic_and_keyed() {
+        assert_eq!(take(RngVersion::SplitMix64, 42, 3), [776746479521281614, 6747430846780074087, 3702794235658574424]);
+        assert_eq!(take(RngVersion::SplitMix64, 7, 100), take(RngVersion::SplitMix64, 7, 100));
+        assert_ne!(take(RngVersion::SplitMix64, 7, 100), take(RngVersion::SplitMix64, 8, 100));
+
+        let mut rng = SplitMix64::new(0);
+        let floats: Vec<f64> = (0..10_000).map(|_| rng.next_float()).collect();
+        assert!(floats.iter().all(|float| (-1.0..1.0).contains(float)));
+        let mean = floats.iter().sum::<f64>() / floats.len() as f64;
+        assert!(mean.abs() < 0.05, "mean {}", mean);
+    }
+
+    #[test]
+    fn test_rng_version_serializes_as_a_number() {
+        assert_eq!(serde_json::to_string(&RngVersion::SplitMix64).unwrap(), "2");
+        assert_eq!(serde_json::from_str::<RngVersion>("1").unwrap(), RngVersion::Lcg);
+        assert!(serde_json::from_str::<RngVersion>("3").is_err());
+    }
+}
@@ -0,0 +1,13 @@
+//! The parts of SierpChain that the node and the frontend both run: the
+//! fractals, the canonical encoding blocks are hashed over, their targets,
+//! and the nonce search, so that a block mined in the browser is one the
+//! node accepts.
+
+pub mod block;
+pub mod canonical;
+pub mod fractal;
+pub mod mining;
+pub mod target;
+
+#[cfg(feature = "wasm")]
+pub mod wasm;
@@ -0,0 +1,137 @@
+//! The nonce search. The node and the in-browser miner both run this one, so
+//! that a block found in either is hashed the same way.
+
+use serde::{Deserialize, Serialize};
+
+use crate::block::{derive_fractal_seed, BlockHashableData};
+use crate::canonical::Encoded;
+use crate::fractal::{FractalData, FractalType};
+use crate::target::Target;
+
+fn is_unset<T: Default + PartialEq>(value: &T) -> bool {
+    *value == T::default()
+}
+
+/// A block template as `GET /mining/template` serves it, with only what
+/// searching its nonces takes.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct MiningTemplate {
+    pub block: TemplateBlock,
+    pub fractal_type: FractalType,
+    /// The block's transactions in the canonical encoding.
+    pub encoded_transactions: String,
+}
+
+/// The fields of a template's block that its hash commits to, apart from
+/// its transactions and the nonce being searched for.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct TemplateBlock {
+    pub index: u64,
+    pub timestamp: i64,
+    /// A placeholder whose `rng_version` the mined fractal must use.
+    pub fractal: FractalData,
+    pub previous_hash: String,
+    #[serde(default, skip_serializing_if = "is_unset")]
+    pub bits: u32,
+    #[serde(default, skip_serializing_if = "is_unset")]
+    pub style_seed: u64,
+}
+
+/// A nonce that meets a template's target, with the fractal generated from
+/// it and the block's hash.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct MinedNonce {
+    pub nonce: u64,
+    pub fractal: FractalData,
+    pub hash: String,
+}
+
+impl MiningTemplate {
+    /// Returns the target the mined block's hash must be below.
+    pub fn target(&self) -> Target {
+        Target::from_compact(self.block.bits).weighted(&self.fractal_type)
+    }
+
+    /// Tries up to `max_attempts` nonces from `start_nonce` on and returns
+    /// the first that meets the target. In-browser miners call this in
+    /// chunks, so they can report progress in between.
+    pub fn mine(&self, start_nonce: u64, max_attempts: u64) -> Option<MinedNonce> {
+        let target = self.target();
+        (start_nonce..start_nonce.saturating_add(max_attempts)).find_map(|nonce| self.try_nonce(&target, nonce))
+    }
+
+    /// Generates the fractal for `nonce` and returns it with the block's
+    /// hash if the hash meets `target`.
+    pub fn try_nonce(&self, target: &Target, nonce: u64) -> Option<MinedNonce> {
+        let block = &self.block;
+        let seed = derive_fractal_seed(&block.previous_hash, block.index, nonce, block.style_seed);
+        let fractal = self.fractal_type.clone().with_seed(seed).generate_with(block.fractal.rng_version());
+        let hash = BlockHashableData {
+            index: block.index,
+            timestamp: block.timestamp,
+            fractal: &fractal,
+            transactions: &Encoded(&self.encoded_transactions),
+            previous_hash: &block.previous_hash,
+            nonce,
+            bits: block.bits,
+            style_seed: block.style_seed,
+        }
+        .hash();
+        target.is_met_by(&hash).then_some(MinedNonce { nonce, fractal, hash })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn template(bits: u32) -> MiningTemplate {
+        let fractal_type = FractalType::Sierpinski { depth: 2, seed: 7 };
+        MiningTemplate {
+            block: TemplateBlock {
+                index: 1,
+                timestamp: 1_700_000_000,
+                fractal: fractal_type.generate(),
+                previous_hash: "ab".repeat(32),
+                bits,
+                style_seed: 7,
+            },
+            fractal_type,
+            encoded_transactions: "[]".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_mined_nonce_meets_the_target_and_matches_its_seed() {
+        let template = template(Target::from_difficulty(1).to_compact());
+        let mined = template.mine(0, 1_000).unwrap();
+        assert!(template.target().is_met_by(&mined.hash));
+        let seed = derive_fractal_seed(&template.block.previous_hash, 1, mined.nonce, 7);
+        assert_eq!(mined.fractal.seed(), seed);
+        // The lowest nonce wins, so resuming a search where it left off
+        // finds the same one.
+        assert_eq!(template.mine(0, mined.nonce + 1), Some(mined.clone()));
+        assert_eq!(template.mine(0, mined.nonce), None);
+    }
+
+    #[test]
+    fn test_template_json_ignores_the_fields_a_miner_fills_in() {
+        let json = serde_json::json!({
+            "block": {
+                "index": 1,
+                "timestamp": 1_700_000_000,
+                "fractal": template(0).block.fractal,
+                "transactions": [],
+                "previous_hash": "ab".repeat(32),
+                "hash": "",
+                "nonce": 0,
+                "style_seed": 7,
+            },
+            "fractal_type": { "Sierpinski": { "depth": 2, "seed": 7 } },
+            "work_factor": 1,
+            "effective_target": Target::MAX.to_string(),
+            "encoded_transactions": "[]",
+        });
+        assert_eq!(serde_json::from_value::<MiningTemplate>(json).unwrap(), template(0));
+    }
+}
@@ -0,0 +1,319 @@
+use std::fmt;
+use std::iter::Sum;
+use std::ops::Add;
+
+use crate::fractal::FractalType;
+
+/// A 256-bit proof-of-work target. A block's hash, read as a big-endian
+/// number, must be below its target.
+///
+/// Blocks store their target in the compact form Bitcoin uses for `nBits`:
+/// the top byte is the target's length in bytes and the other three are its
+/// leading bytes. Unlike a count of leading zero hex digits, this can express
+/// targets between the powers of 16.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Target([u8; 32]);
+
+impl Target {
+    /// The easiest target there is: every hash but all ones is below it.
+    pub const MAX: Target = Target([0xff; 32]);
+
+    /// The target equivalent to requiring `difficulty` leading zero hex
+    /// digits, i.e. 16^(64 - difficulty).
+    pub fn from_difficulty(difficulty: usize) -> Self {
+        if difficulty == 0 {
+            return Self::MAX;
+        }
+        let mut bytes = [0u8; 32];
+        if let Some(bit) = 256usize.checked_sub(4 * difficulty) {
+            bytes[31 - bit / 8] = 1 << (bit % 8);
+        }
+        Target(bytes)
+    }
+
+    /// The most leading zero hex digits `from_difficulty` can require while
+    /// still giving a target no harder than this one.
+    pub fn difficulty(&self) -> usize {
+        (0..=64).rev().find(|difficulty| Self::from_difficulty(*difficulty) >= *self).unwrap_or(0)
+    }
+
+    /// Decodes a compact target. Targets too large for 256 bits saturate to
+    /// `MAX`; the sign bit Bitcoin reserves is ignored.
+    pub fn from_compact(bits: u32) -> Self {
+        let size = (bits >> 24) as i64;
+        let mantissa = (bits & 0x007f_ffff).to_be_bytes();
+        let mut bytes = [0u8; 32];
+        for (i, byte) in mantissa[1..].iter().enumerate() {
+            let position = 32 - size + i as i64;
+            match usize::try_from(position) {
+                Ok(position) if position < 32 => bytes[position] = *byte,
+                // Shifted off the low end.
+                Ok(_) => {}
+                Err(_) if *byte != 0 => return Self::MAX,
+                Err(_) => {}
+            }
+        }
+        Target(bytes)
+    }
+
+    /// Encodes the target compactly, keeping its three leading bytes.
+    pub fn to_compact(self) -> u32 {
+        let Some(first) = self.0.iter().position(|byte| *byte != 0) else {
+            return 0;
+        };
+        let mut size = (32 - first) as u32;
+        let mut mantissa = (0..3).fold(0u32, |mantissa, i| (mantissa << 8) | *self.0.get(first + i).unwrap_or(&0) as u32);
+        if mantissa & 0x0080_0000 != 0 {
+            mantissa >>= 8;
+            size += 1;
+        }
+        (size << 24) | mantissa
+    }
+
+    /// Divides the target by `divisor`, rounding down, so a hash is
+    /// `divisor` times less likely to meet it.
+    pub fn divided_by(self, divisor: u64) -> Self {
+        let divisor = divisor.max(1) as u128;
+        let mut bytes = [0u8; 32];
+        let mut remainder = 0u128;
+        for (quotient, byte) in bytes.iter_mut().zip(self.0) {
+            let dividend = (remainder << 8) | byte as u128;
+            *quotient = (dividend / divisor) as u8;
+            remainder = dividend % divisor;
+        }
+        Target(bytes)
+    }
+
+    /// Multiplies the target by `numerator / denominator`, rounding down, so
+    /// retargets can move it by any ratio rather than whole hex digits.
+    /// Targets too large for 256 bits saturate to `MAX`.
+    pub fn scaled(self, numerator: u64, denominator: u64) -> Self {
+        let numerator = numerator as u128;
+        let mut product = [0u8; 32];
+        let mut carry = 0u128;
+        for (digit, byte) in product.iter_mut().zip(self.0).rev() {
+            let partial = byte as u128 * numerator + carry;
+            *digit = partial as u8;
+            carry = partial >> 8;
+        }
+        let denominator = denominator.max(1) as u128;
+        if carry >= denominator {
+            return Self::MAX;
+        }
+        let mut remainder = carry;
+        for byte in product.iter_mut() {
+            let dividend = (remainder << 8) | *byte as u128;
+            *byte = (dividend / denominator) as u8;
+            remainder = dividend % denominator;
+        }
+        Target(product)
+    }
+
+    /// The expected number of hashes it takes to meet the target: 2^256
+    /// divided by it, rounding down. No hash meets a zero target, so its
+    /// work is the most there is.
+    pub fn work(&self) -> Work {
+        let mut divisor = [0u64; 5];
+        for (limb, bytes) in divisor[1..].iter_mut().zip(self.0.chunks_exact(8)) {
+            *limb = u64::from_be_bytes(bytes.try_into().expect("8-byte chunks"));
+        }
+        if divisor == [0; 5] {
+            return Work::MAX;
+        }
+        // Long division of 2^256, a bit at a time.
+        let mut quotient = [0u64; 5];
+        let mut remainder = [0u64; 5];
+        for bit in (0..=256).rev() {
+            remainder = shifted_left(remainder);
+            remainder[4] |= (bit == 256) as u64;
+            if remainder >= divisor {
+                remainder = subtracted(remainder, divisor);
+                quotient[4 - bit / 64] |= 1 << (bit % 64);
+            }
+        }
+        Work(quotient)
+    }
+
+    /// The target a block of `fractal_type` must meet when its `bits` decode
+    /// to this one: divided by the fractal's work factor, so fractals that are
+    /// cheap to generate take proportionally more nonces to mine.
+    pub fn weighted(self, fractal_type: &FractalType) -> Self {
+        self.divided_by(fractal_type.work_factor())
+    }
+
+    /// Returns true if `hash`, a hex-encoded SHA-256 digest, is below the
+    /// target.
+    pub fn is_met_by(&self, hash: &str) -> bool {
+        match hex::decode(hash) {
+            Ok(bytes) => bytes.len() == 32 && bytes.as_slice() < self.0.as_slice(),
+            Err(_) => false,
+        }
+    }
+}
+
+impl fmt::Display for Target {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&hex::encode(self.0))
+    }
+}
+
+/// An amount of proof of work, in expected hashes, as a 320-bit number:
+/// enough to add up 2^64 blocks at the hardest target.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Work([u64; 5]);
+
+impl Work {
+    /// The most work there is. Sums saturate to it.
+    pub const MAX: Work = Work([u64::MAX; 5]);
+
+    /// Returns the work as a `u64`, or `u64::MAX` if it is larger.
+    pub fn saturating_u64(&self) -> u64 {
+        if self.0[..4] == [0; 4] { self.0[4] } else { u64::MAX }
+    }
+}
+
+impl Add for Work {
+    type Output = Work;
+
+    fn add(self, other: Work) -> Work {
+        let mut sum = [0u64; 5];
+        let mut carry = false;
+        for i in (0..5).rev() {
+            let (partial, overflowed) = self.0[i].overflowing_add(other.0[i]);
+            let (partial, carried) = partial.overflowing_add(carry as u64);
+            sum[i] = partial;
+            carry = overflowed || carried;
+        }
+        if carry { Work::MAX } else { Work(sum) }
+    }
+}
+
+impl Sum for Work {
+    fn sum<I: Iterator<Item = Work>>(iter: I) -> Work {
+        iter.fold(Work::default(), Add::add)
+    }
+}
+
+/// Shifts a big-endian 320-bit number left by one bit.
+fn shifted_left(n: [u64; 5]) -> [u64; 5] {
+    let mut shifted = [0u64; 5];
+    for i in 0..5 {
+        shifted[i] = n[i] << 1 | n.get(i + 1).map_or(0, |next| next >> 63);
+    }
+    shifted
+}
+
+/// Subtracts `b` from `a`, big-endian 320-bit numbers with `a >= b`.
+fn subtracted(a: [u64; 5], b: [u64; 5]) -> [u64; 5] {
+    let mut difference = [0u64; 5];
+    let mut borrow = false;
+    for i in (0..5).rev() {
+        let (partial, overflowed) = a[i].overflowing_sub(b[i]);
+        let (partial, borrowed) = partial.overflowing_sub(borrow as u64);
+        difference[i] = partial;
+        borrow = overflowed || borrowed;
+    }
+    difference
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_difficulty_targets_round_trip_through_compact_form() {
+        for difficulty in 1..=64 {
+            let target = Target::from_difficulty(difficulty);
+            assert_eq!(Target::from_compact(target.to_compact()), target, "difficulty {}", difficulty);
+            let prefix = "0".repeat(difficulty);
+            assert!(target.to_string().starts_with(&"0".repeat(difficulty - 1)));
+            assert!(!target.to_string().starts_with(&prefix));
+        }
+        assert_eq!(Target::from_difficulty(1).to_compact(), 0x2010_0000);
+        assert_eq!(Target::from_difficulty(2).to_compact(), 0x2001_0000);
+        assert_eq!(Target::from_compact(0x2201_0000), Target::MAX);
+    }
+
+    #[test]
+    fn test_difficulty_counts_the_leading_zeros_a_target_requires() {
+        for difficulty in 0..=64 {
+            assert_eq!(Target::from_difficulty(difficulty).difficulty(), difficulty);
+        }
+        // Targets between the powers of 16 round towards the easier one.
+        assert_eq!(Target::from_compact(0x200a_0000).difficulty(), 1);
+        assert_eq!(Target::from_difficulty(2).divided_by(2).difficulty(), 2);
+    }
+
+    #[test]
+    fn test_hash_just_below_target_passes_and_just_above_fails() {
+        let target = Target::from_difficulty(2);
+        assert!(target.is_met_by(&format!("00{}", "f".repeat(62))));
+        assert!(!target.is_met_by(&format!("01{}", "0".repeat(62))));
+        assert!(!target.is_met_by("not hex"));
+        assert!(!target.is_met_by("00"));
+    }
+
+    #[test]
+    fn test_targets_between_powers_of_sixteen() {
+        // 0x0a followed by 31 zero bytes, between the targets for one and
+        // two leading zeros.
+        let target = Target::from_compact(0x200a_0000);
+        assert!(Target::from_difficulty(2) < target && target < Target::from_difficulty(1));
+        assert!(target.is_met_by(&format!("09{}", "f".repeat(62))));
+        assert!(!target.is_met_by(&format!("0a{}", "0".repeat(62))));
+        // Difficulty 1 alone would accept it.
+        assert!(Target::from_difficulty(1).is_met_by(&format!("0a{}", "0".repeat(62))));
+
+        // A mantissa with its top bit set is stored a byte longer.
+        let target = Target::from_compact(0x1f00_ff80);
+        assert_eq!(target.to_compact(), 0x1f00_ff80);
+        assert!(target.is_met_by(&format!("0000ff7f{}", "f".repeat(56))));
+        assert!(!target.is_met_by(&format!("0000ff80{}", "0".repeat(56))));
+    }
+
+    #[test]
+    fn test_weighting_divides_the_target_by_the_work_factor() {
+        let target = Target::from_difficulty(1);
+        assert_eq!(target.divided_by(16), Target::from_difficulty(2));
+        assert_eq!(target.divided_by(1), target);
+        assert_eq!(target.divided_by(0), target);
+        assert_eq!(Target::from_compact(0x2003_0000).divided_by(3), Target::from_compact(0x2001_0000));
+
+        let cheap = FractalType::Sierpinski { depth: 1, seed: 0 };
+        assert_eq!(target.weighted(&cheap), target.divided_by(cheap.work_factor()));
+        let large = FractalType::mandelbrot(500, 500, -2.0, 1.0, -1.5, 1.5, 100, 0).unwrap();
+        assert_eq!(target.weighted(&large), target);
+    }
+
+    #[test]
+    fn test_scaling_moves_the_target_by_any_ratio() {
+        let target = Target::from_difficulty(2);
+        assert_eq!(target.scaled(1, 16), Target::from_difficulty(3));
+        assert_eq!(target.scaled(16, 1), Target::from_difficulty(1));
+        assert_eq!(target.scaled(3, 3), target);
+        assert_eq!(target.scaled(5, 4).to_compact(), 0x2001_4000);
+        assert_eq!(target.scaled(1, 0), target);
+        assert_eq!(Target::from_difficulty(1).scaled(16, 1), Target::MAX);
+        assert_eq!(Target::MAX.scaled(u64::MAX, 1), Target::MAX);
+        assert_eq!(Target::MAX.scaled(1, 1), Target::MAX);
+    }
+
+    #[test]
+    fn test_work_is_the_expected_number_of_hashes() {
+        for difficulty in [1, 2, 8, 15, 16] {
+            let expected = 16u128.pow(difficulty as u32);
+            let work = Target::from_difficulty(difficulty).work();
+            assert_eq!(work.saturating_u64(), u64::try_from(expected).unwrap_or(u64::MAX), "difficulty {}", difficulty);
+        }
+        assert_eq!(Target::MAX.work().saturating_u64(), 1);
+        assert_eq!(Target::from_difficulty(2).scaled(1, 3).work().saturating_u64(), 768);
+        assert_eq!(Target::from_difficulty(64).work(), [Target::from_difficulty(63).work(); 16].into_iter().sum());
+        assert_eq!(Target([0; 32]).work(), Work::MAX);
+
+        let harder = Target::from_difficulty(3).work();
+        let easier = Target::from_difficulty(2).work();
+        assert!(harder > easier + easier);
+        assert_eq!([easier; 16].into_iter().sum::<Work>(), harder);
+        assert_eq!(Work::MAX + easier, Work::MAX);
+    }
+}
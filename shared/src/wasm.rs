@@ -0,0 +1,17 @@
+//! The nonce search exported to JavaScript, for mining in the browser.
+
+use wasm_bindgen::prelude::*;
+
+use crate::mining::MiningTemplate;
+
+/// Tries up to `max_attempts` nonces of `template`, the JSON of
+/// `GET /mining/template`, from `start_nonce` on. Returns the JSON of the
+/// `MinedNonce` that meets the target, or nothing if none does.
+#[wasm_bindgen]
+pub fn mine_template(template: &str, start_nonce: u64, max_attempts: u64) -> Result<Option<String>, JsError> {
+    let template: MiningTemplate = serde_json::from_str(template)?;
+    match template.mine(start_nonce, max_attempts) {
+        Some(mined) => Ok(Some(serde_json::to_string(&mined)?)),
+        None => Ok(None),
+    }
+}
@@ -0,0 +1,318 @@
+use actix::Addr;
+use actix_web::{delete, get, post, web, HttpRequest, HttpResponse, Responder};
+use actix_web::http::header;
+use libp2p::{Multiaddr, PeerId};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use subtle::ConstantTimeEq;
+use std::path::Path;
+use crate::api::handlers::TransactionPool;
+use crate::api::health::SAFE_MODE_OFF;
+use crate::api::state::AppState;
+use crate::api::websocket::{BroadcastHub, BroadcastReorg};
+use crate::blockchain::chain::{Blockchain, Reorg, DB_FILE};
+use crate::network::p2p::P2pControl;
+
+/// The bearer token guarding the `/admin` endpoints. `None` disables them.
+#[derive(Clone, Debug)]
+pub struct AdminKey(pub Option<String>);
+
+/// Whether the admin endpoints that rewrite the active chain are enabled.
+#[derive(Clone, Copy, Debug)]
+pub struct AllowChainOps(pub bool);
+
+/// Checks the request's `Authorization: Bearer` header against the configured
+/// admin key, returning the error response to send if it does not match.
+pub fn authorize(req: &HttpRequest, admin_key: &AdminKey) -> Result<(), HttpResponse> {
+    let Some(expected) = &admin_key.0 else {
+        return Err(HttpResponse::Forbidden().json(serde_json::json!({ "error": "Admin API is disabled" })));
+    };
+    let provided = req
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "));
+    match provided {
+        Some(token) if keys_match(token, expected) => Ok(()),
+        _ => Err(HttpResponse::Unauthorized().json(serde_json::json!({ "error": "Invalid admin key" }))),
+    }
+}
+
+/// Compares a provided token with the admin key in constant time. Hashing
+/// both first hides the key's length as well as where they differ.
+fn keys_match(token: &str, expected: &str) -> bool {
+    Sha256::digest(token.as_bytes()).ct_eq(&Sha256::digest(expected.as_bytes())).into()
+}
+
+#[derive(Deserialize)]
+pub struct AddPeerRequest {
+    pub multiaddr: String,
+}
+
+#[derive(Deserialize)]
+pub struct DisconnectPeerRequest {
+    pub peer_id: String,
+}
+
+#[derive(Serialize)]
+struct PeerConnection {
+    peer_id: String,
+    multiaddr: String,
+    connected_secs: u64,
+}
+
+#[post("/admin/peers")]
+pub async fn add_peer(
+    req: HttpRequest,
+    body: web::Json<AddPeerRequest>,
+    state: web::Data<AppState>,
+) -> impl Responder {
+    if let Err(response) = authorize(&req, &state.admin_key) {
+        return response;
+    }
+    dial(&state, &body.multiaddr).await
+}
+
+/// Asks the P2P task to dial `multiaddr`, answering 202 once the request is
+/// queued or 400 if the address doesn't parse.
+pub async fn dial(state: &AppState, multiaddr: &str) -> HttpResponse {
+    let addr: Multiaddr = match multiaddr.parse() {
+        Ok(addr) => addr,
+        Err(_) => return HttpResponse::BadRequest().body("Invalid multiaddr"),
+    };
+    if state.p2p_control.send(P2pControl::Dial(addr.clone())).await.is_err() {
+        return HttpResponse::ServiceUnavailable().body("P2P network is not running");
+    }
+    HttpResponse::Accepted().json(serde_json::json!({ "dialing": addr.to_string() }))
+}
+
+#[get("/admin/peers")]
+pub async fn list_peers(req: HttpRequest, state: web::Data<AppState>) -> impl Responder {
+    if let Err(response) = authorize(&req, &state.admin_key) {
+        return response;
+    }
+    let connected_peers = state.connected_peers.lock().unwrap();
+    let peers: Vec<PeerConnection> = connected_peers
+        .iter()
+        .map(|(peer_id, peer)| PeerConnection {
+            peer_id: peer_id.to_string(),
+            multiaddr: peer.address.to_string(),
+            connected_secs: peer.connected_at.elapsed().as_secs(),
+        })
+        .collect();
+    HttpResponse::Ok().json(peers)
+}
+
+#[delete("/admin/peers/{peer_id}")]
+pub async fn remove_peer(
+    req: HttpRequest,
+    peer_id: web::Path<String>,
+    state: web::Data<AppState>,
+) -> impl Responder {
+    if let Err(response) = authorize(&req, &state.admin_key) {
+        return response;
+    }
+    disconnect(&state, &peer_id).await
+}
+
+/// Asks the P2P task to disconnect from `peer_id` and stop treating it as a
+/// gossip peer, answering 202 once the request is queued, 400 if the ID
+/// doesn't parse or 404 if the peer isn't connected.
+pub async fn disconnect(state: &AppState, peer_id: &str) -> HttpResponse {
+    let peer_id: PeerId = match peer_id.parse() {
+        Ok(peer_id) => peer_id,
+        Err(_) => return HttpResponse::BadRequest().body("Invalid peer ID"),
+    };
+    if !state.connected_peers.lock().unwrap().contains_key(&peer_id) {
+        return HttpResponse::NotFound().body("Peer is not connected");
+    }
+    if state.p2p_control.send(P2pControl::Disconnect(peer_id)).await.is_err() {
+        return HttpResponse::ServiceUnavailable().body("P2P network is not running");
+    }
+    HttpResponse::Accepted().finish()
+}
+
+#[derive(Deserialize)]
+pub struct BlockHashRequest {
+    hash: String,
+}
+
+/// Shared tail of the chain operations: updates the mempool, persists the
+/// chain, notifies WebSocket clients and records the change in the journal.
+fn finish_reorg(
+    action: &str,
+    hash: &str,
+    reorg: Reorg,
+    blockchain: &Blockchain,
+    tx_pool: &TransactionPool,
+    hub: &Addr<BroadcastHub>,
+) -> HttpResponse {
+    let returned_to_mempool = tx_pool.lock().unwrap().apply_reorg(&reorg, blockchain);
+    if let Err(e) = blockchain.save_to_file() {
+        tracing::error!("Failed to save blockchain: {}", e);
+    }
+
+    let height = blockchain.chain.last().map_or(0, |block| block.index);
+    let disconnected: Vec<String> = reorg.disconnected.iter().map(|block| block.hash.clone()).collect();
+    let connected: Vec<String> = reorg.connected.iter().map(|block| block.hash.clone()).collect();
+    tracing::warn!(
+        target: "journal",
+        action,
+        hash,
+        height,
+        disconnected = disconnected.len(),
+        connected = connected.len(),
+        "Active chain changed by an admin"
+    );
+    hub.do_send(BroadcastReorg { reorg, returned_to_mempool: returned_to_mempool.clone(), height });
+
+    HttpResponse::Ok().json(serde_json::json!({
+        "height": height,
+        "disconnected": disconnected,
+        "connected": connected,
+        "returned_to_mempool": returned_to_mempool,
+    }))
+}
+
+fn authorize_chain_ops(req: &HttpRequest, admin_key: &AdminKey, allow: &AllowChainOps) -> Result<(), HttpResponse> {
+    authorize(req, admin_key)?;
+    if !allow.0 {
+        return Err(HttpResponse::Forbidden().json(serde_json::json!({
+            "error": "Chain operations are disabled; start the node with --allow-admin-chain-ops"
+        })));
+    }
+    Ok(())
+}
+
+/// Marks a block and its descendants invalid and rolls the chain back to the
+/// block's parent, returning the disconnected transactions to the mempool.
+#[post("/admin/invalidate-block")]
+pub async fn invalidate_block(
+    req: HttpRequest,
+    body: web::Json<BlockHashRequest>,
+    state: web::Data<AppState>,
+) -> impl Responder {
+    if let Err(response) = authorize_chain_ops(&req, &state.admin_key, &state.allow_chain_ops) {
+        return response;
+    }
+    let mut blockchain = state.blockchain.lock().unwrap();
+    let Some(reorg) = blockchain.invalidate_block(&body.hash) else {
+        return HttpResponse::NotFound().body("Block is not in the active chain, or is the genesis block");
+    };
+    finish_reorg("invalidate-block", &body.hash, reorg, &blockchain, &state.tx_pool, &state.hub)
+}
+
+/// Undoes `invalidate-block`, reconnecting the branch it removed.
+#[post("/admin/reconsider-block")]
+pub async fn reconsider_block(
+    req: HttpRequest,
+    body: web::Json<BlockHashRequest>,
+    state: web::Data<AppState>,
+) -> impl Responder {
+    if let Err(response) = authorize_chain_ops(&req, &state.admin_key, &state.allow_chain_ops) {
+        return response;
+    }
+    let mut blockchain = state.blockchain.lock().unwrap();
+    if blockchain.is_archived(&body.hash) {
+        let max_reorg_depth = state.storage_gc.lock().unwrap().max_reorg_depth();
+        return HttpResponse::Gone().body(format!(
+            "Block was garbage-collected: its branch forked more than {} blocks below the tip, too deep to reconnect",
+            max_reorg_depth
+        ));
+    }
+    let Some(reorg) = blockchain.reconsider_block(&body.hash) else {
+        return HttpResponse::NotFound().body("Block has not been invalidated");
+    };
+    finish_reorg("reconsider-block", &body.hash, reorg, &blockchain, &state.tx_pool, &state.hub)
+}
+
+/// Leaves the safe mode a refused deep reorg put the node in, once an
+/// operator has looked into it. Answers with the reason it was entered,
+/// or `null` if the node wasn't in safe mode.
+#[delete("/admin/safe-mode")]
+pub async fn clear_safe_mode(req: HttpRequest, state: web::Data<AppState>) -> impl Responder {
+    if let Err(response) = authorize(&req, &state.admin_key) {
+        return response;
+    }
+    let reason = state.health.failing().into_iter().find(|(check, _)| *check == SAFE_MODE_OFF).map(|(_, reason)| reason);
+    state.health.report(SAFE_MODE_OFF, Ok(()));
+    if let Some(reason) = &reason {
+        tracing::warn!(target: "journal", reason = %reason, "Safe mode cleared by an admin");
+    }
+    HttpResponse::Ok().json(serde_json::json!({ "cleared": reason }))
+}
+
+/// How many blocks `/admin/storage` lists as the largest.
+pub const LARGEST_BLOCKS: usize = 10;
+
+#[derive(Serialize)]
+struct StoredFile {
+    name: &'static str,
+    path: String,
+    /// `None` if the file hasn't been written yet.
+    bytes: Option<u64>,
+}
+
+impl StoredFile {
+    fn stat(name: &'static str, path: &Path) -> Self {
+        let bytes = std::fs::metadata(path).ok().map(|metadata| metadata.len());
+        Self { name, path: path.display().to_string(), bytes }
+    }
+}
+
+/// Reports what the node keeps on disk: the size of each file it writes, and
+/// how big the chain's blocks are and how fast they are accumulating.
+#[get("/admin/storage")]
+pub async fn get_storage(req: HttpRequest, state: web::Data<AppState>) -> impl Responder {
+    if let Err(response) = authorize(&req, &state.admin_key) {
+        return response;
+    }
+    let storage_gc = state.storage_gc.lock().unwrap();
+    let mut files = vec![
+        StoredFile::stat("chain", Path::new(DB_FILE)),
+        StoredFile::stat("peers", state.peer_store.lock().unwrap().path()),
+        StoredFile::stat("faucet", state.faucet_ledger.lock().unwrap().path()),
+        StoredFile::stat("nft_registry", state.nft_registry.lock().unwrap().path()),
+    ];
+    if let Some(archive) = storage_gc.archive() {
+        files.push(StoredFile::stat("archive", archive));
+    }
+    let total_file_bytes: u64 = files.iter().filter_map(|file| file.bytes).sum();
+
+    let blockchain = state.blockchain.lock().unwrap();
+    let mut block_sizes = state.block_sizes.lock().unwrap();
+    block_sizes.update(&blockchain.chain);
+    let block_count = block_sizes.block_count();
+    let total_block_bytes = block_sizes.total_bytes();
+
+    HttpResponse::Ok().json(serde_json::json!({
+        "data_dir": std::env::current_dir().map(|dir| dir.display().to_string()).ok(),
+        "files": files,
+        "total_file_bytes": total_file_bytes,
+        "block_count": block_count,
+        "total_block_bytes": total_block_bytes,
+        "average_block_bytes": total_block_bytes.checked_div(block_count as u64),
+        "largest_blocks": block_sizes.largest(LARGEST_BLOCKS),
+        "growth_bytes_per_day": block_sizes.growth_bytes_per_day(&blockchain.chain),
+        "gc": {
+            "max_reorg_depth": storage_gc.max_reorg_depth(),
+            "archiving": storage_gc.archive().is_some(),
+            "side_chain_blocks": blockchain.side_chain_block_count(),
+            "last_run": storage_gc.last_report(),
+        },
+    }))
+}
+
+/// Collects stale side-chain blocks now rather than at the next scheduled
+/// run, answering with what was archived or deleted.
+#[post("/admin/gc")]
+pub async fn collect_garbage(req: HttpRequest, state: web::Data<AppState>) -> impl Responder {
+    if let Err(response) = authorize(&req, &state.admin_key) {
+        return response;
+    }
+    let mut blockchain = state.blockchain.lock().unwrap();
+    match state.storage_gc.lock().unwrap().run(&mut blockchain) {
+        Ok(report) => HttpResponse::Ok().json(report),
+        Err(e) => HttpResponse::InternalServerError().body(format!("Failed to archive blocks: {}", e)),
+    }
+}
@@ -0,0 +1,295 @@
+use actix::Addr;
+use actix_web::{get, put, web, HttpResponse, Responder};
+use rand::seq::SliceRandom;
+use rand::Rng;
+use serde::Deserialize;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::{mpsc, watch};
+
+use crate::api::handlers::TransactionPool;
+use crate::api::state::AppState;
+use crate::api::websocket::{BroadcastHub, TrackTransaction};
+use crate::blockchain::chain::Blockchain;
+use crate::core::mempool::MempoolPool;
+use crate::core::transaction::{BuildTransactRequest, Transaction, TxError};
+use crate::core::wallet::Wallet;
+use crate::network::p2p::P2pMessage;
+
+/// The memo every demo transaction carries.
+pub const DEMO_MEMO: &str = "demo";
+/// How many throwaway wallets the demo moves coins between.
+pub const DEMO_WALLETS: usize = 4;
+/// How many demo payments a drip from the miner wallet funds.
+pub const DRIP_PAYMENTS: u64 = 10;
+/// The most demo transactions left pending at once. Past it ticks are
+/// skipped until blocks take some.
+pub const MAX_PENDING_DEMO_TRANSACTIONS: usize = 20;
+/// How often the demo makes a payment by default, in milliseconds.
+pub const DEFAULT_DEMO_INTERVAL_MS: u64 = 5_000;
+/// The smallest and largest demo payments by default, in base units.
+pub const DEFAULT_DEMO_MIN_AMOUNT: u64 = 1_000;
+pub const DEFAULT_DEMO_MAX_AMOUNT: u64 = 100_000;
+
+/// How fast `--demo-traffic` moves coins, and how many.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DemoConfig {
+    pub interval: Duration,
+    /// Payments are drawn from `min_amount..=max_amount` base units.
+    pub min_amount: u64,
+    pub max_amount: u64,
+}
+
+impl Default for DemoConfig {
+    fn default() -> Self {
+        Self {
+            interval: Duration::from_millis(DEFAULT_DEMO_INTERVAL_MS),
+            min_amount: DEFAULT_DEMO_MIN_AMOUNT,
+            max_amount: DEFAULT_DEMO_MAX_AMOUNT,
+        }
+    }
+}
+
+/// What the demo task should be doing.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum DemoMode {
+    Enabled,
+    Disabled,
+    /// The node is shutting down, and the task should return.
+    Stopped,
+}
+
+/// Turns the demo traffic task on and off. Nodes started without
+/// `--demo-traffic` have no task to switch.
+#[derive(Clone, Debug, Default)]
+pub struct DemoSwitch(Option<Arc<watch::Sender<DemoMode>>>);
+
+impl DemoSwitch {
+    /// A switch for a new task, returning the receiver the task watches.
+    fn new(enabled: bool) -> (Self, watch::Receiver<DemoMode>) {
+        let mode = if enabled { DemoMode::Enabled } else { DemoMode::Disabled };
+        let (sender, receiver) = watch::channel(mode);
+        (Self(Some(Arc::new(sender))), receiver)
+    }
+
+    /// Returns false if the node runs no demo task.
+    pub fn is_available(&self) -> bool {
+        self.0.is_some()
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.0.as_ref().is_some_and(|sender| *sender.borrow() == DemoMode::Enabled)
+    }
+
+    /// Resumes or pauses the task. Returns false if there is none.
+    pub fn set_enabled(&self, enabled: bool) -> bool {
+        let Some(sender) = &self.0 else {
+            return false;
+        };
+        let mode = if enabled { DemoMode::Enabled } else { DemoMode::Disabled };
+        sender.send_if_modified(|current| {
+            let changed = *current != mode && *current != DemoMode::Stopped;
+            if changed {
+                *current = mode;
+            }
+            changed
+        });
+        true
+    }
+
+    /// Tells the task to return once it finishes the payment in hand.
+    pub fn stop(&self) {
+        if let Some(sender) = &self.0 {
+            sender.send_replace(DemoMode::Stopped);
+        }
+    }
+}
+
+/// Makes the chain look busy for demos by moving small random amounts
+/// between throwaway wallets through the mempool, like any other payment.
+/// The wallets are funded by drips from the miner wallet as they run dry,
+/// and every transaction is tagged with the memo `"demo"`.
+pub struct DemoTraffic {
+    pub blockchain: Arc<Mutex<Blockchain>>,
+    pub tx_pool: TransactionPool,
+    pub miner_wallet: Arc<Wallet>,
+    pub to_p2p: mpsc::UnboundedSender<P2pMessage>,
+    pub hub: Addr<BroadcastHub>,
+    pub config: DemoConfig,
+    /// Created for each run and forgotten after it; they only ever hold
+    /// demo coins.
+    pub wallets: Vec<Wallet>,
+}
+
+impl DemoTraffic {
+    pub fn new(
+        blockchain: Arc<Mutex<Blockchain>>,
+        tx_pool: TransactionPool,
+        miner_wallet: Arc<Wallet>,
+        to_p2p: mpsc::UnboundedSender<P2pMessage>,
+        hub: Addr<BroadcastHub>,
+        config: DemoConfig,
+    ) -> Self {
+        let wallets = (0..DEMO_WALLETS).map(|_| Wallet::new()).collect();
+        Self { blockchain, tx_pool, miner_wallet, to_p2p, hub, config, wallets }
+    }
+
+    /// Makes one demo payment from a wallet that can afford it, or drips
+    /// coins to one if none can and no earlier drip is still pending.
+    /// Returns the transaction sent, if any.
+    pub fn tick(&self) -> Option<Transaction> {
+        let blockchain = self.blockchain.lock().unwrap();
+        let mut pool = self.tx_pool.lock().unwrap();
+        let pending = pending_demo_transactions(&pool);
+        if pending >= MAX_PENDING_DEMO_TRANSACTIONS {
+            tracing::debug!("Skipping demo payment: {} demo transactions pending", pending);
+            return None;
+        }
+        let mut rng = rand::thread_rng();
+        // Amounts below the dust threshold wouldn't be relayed.
+        let min_amount = self.config.min_amount.max(pool.dust_threshold());
+        let amount = rng.gen_range(min_amount..=self.config.max_amount.max(min_amount));
+
+        let funded: Vec<&Wallet> = self.wallets.iter().filter(|wallet| spendable(&blockchain, &pool, wallet) > amount).collect();
+        let (from, to, amount) = match funded.choose(&mut rng) {
+            Some(from) => {
+                let others: Vec<&Wallet> = self.wallets.iter().filter(|wallet| wallet.get_address() != from.get_address()).collect();
+                (Wallet::clone(from), others.choose(&mut rng)?.get_address(), amount)
+            }
+            None if pending == 0 => {
+                let to = self.wallets.choose(&mut rng)?.get_address();
+                (Wallet::clone(&self.miner_wallet), to, self.config.max_amount.saturating_mul(DRIP_PAYMENTS))
+            }
+            None => return None,
+        };
+
+        let tx = match build_paying_relay_fee(&blockchain, &pool, from, to, amount) {
+            Ok(tx) => tx,
+            Err(e) => {
+                tracing::debug!("Skipping demo payment: {}", e);
+                return None;
+            }
+        };
+        if let Err(e) = pool.submit_checked(tx.clone(), &blockchain) {
+            tracing::warn!("Demo transaction refused by the mempool: {}", e);
+            return None;
+        }
+        self.to_p2p.send(P2pMessage::Transaction(tx.clone())).unwrap();
+        self.hub.do_send(TrackTransaction { txid: tx.id.clone(), expiry_height: tx.expiry_height });
+        Some(tx)
+    }
+
+    /// Ticks every `config.interval` while enabled, and returns once the
+    /// switch is stopped or dropped.
+    async fn run(self, mut mode: watch::Receiver<DemoMode>) {
+        let mut interval = tokio::time::interval(self.config.interval);
+        loop {
+            let current = *mode.borrow_and_update();
+            match current {
+                DemoMode::Stopped => break,
+                DemoMode::Disabled => {
+                    if mode.changed().await.is_err() {
+                        break;
+                    }
+                }
+                DemoMode::Enabled => {
+                    tokio::select! {
+                        _ = interval.tick() => {
+                            self.tick();
+                        }
+                        changed = mode.changed() => {
+                            if changed.is_err() {
+                                break;
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        tracing::info!("Demo traffic stopped");
+    }
+
+    /// Spawns the task, returning the switch for it and its handle.
+    pub fn spawn(self, enabled: bool) -> (DemoSwitch, tokio::task::JoinHandle<()>) {
+        let (switch, mode) = DemoSwitch::new(enabled);
+        (switch, tokio::spawn(self.run(mode)))
+    }
+}
+
+/// How many pooled transactions carry the demo memo.
+fn pending_demo_transactions(pool: &MempoolPool) -> usize {
+    pool.txids().filter_map(|txid| pool.get(txid)).filter(|tx| tx.memo.as_deref() == Some(DEMO_MEMO)).count()
+}
+
+/// What `wallet` can spend that no pending transaction already does.
+fn spendable(blockchain: &Blockchain, pool: &MempoolPool, wallet: &Wallet) -> u64 {
+    blockchain
+        .get_utxos(&wallet.get_address())
+        .into_iter()
+        .filter(|(txid, vout, _)| !pool.is_spent(&(txid.clone(), *vout)))
+        .fold(0u64, |total, (_, _, utxo)| total.saturating_add(utxo.value))
+}
+
+/// Builds a demo payment paying the pool's minimum relay fee, which
+/// depends on the size of the transaction, so it is built once to measure.
+fn build_paying_relay_fee(
+    blockchain: &Blockchain,
+    pool: &MempoolPool,
+    from: Wallet,
+    to: String,
+    amount: u64,
+) -> Result<Transaction, TxError> {
+    let mut request = BuildTransactRequest {
+        wallets: vec![from],
+        to,
+        amount,
+        fee: 0,
+        expiry_height: None,
+        memo: Some(DEMO_MEMO.to_string()),
+    };
+    let draft = blockchain.build_transaction(&request, |outpoint| pool.is_spent(outpoint))?;
+    request.fee = pool.required_fee(&draft);
+    blockchain.build_transaction(&request, |outpoint| pool.is_spent(outpoint))
+}
+
+#[derive(Deserialize)]
+pub struct DemoTrafficRequest {
+    pub enabled: bool,
+}
+
+/// Whether the demo traffic task is running.
+#[get("/demo-traffic")]
+pub async fn get_demo_traffic(state: web::Data<AppState>) -> impl Responder {
+    HttpResponse::Ok().json(serde_json::json!({
+        "available": state.demo_traffic.is_available(),
+        "enabled": state.demo_traffic.is_enabled(),
+    }))
+}
+
+/// Pauses or resumes the demo traffic of a node started with
+/// `--demo-traffic`.
+#[put("/demo-traffic")]
+pub async fn put_demo_traffic(body: web::Json<DemoTrafficRequest>, state: web::Data<AppState>) -> impl Responder {
+    if !state.demo_traffic.set_enabled(body.enabled) {
+        return HttpResponse::Forbidden()
+            .json(serde_json::json!({ "error": "Demo traffic is off; start the node with --demo-traffic" }));
+    }
+    HttpResponse::Ok().json(serde_json::json!({ "available": true, "enabled": state.demo_traffic.is_enabled() }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_switch_stays_stopped() {
+        let (switch, mode) = DemoSwitch::new(false);
+        assert!(switch.is_available() && !switch.is_enabled());
+        assert!(switch.set_enabled(true));
+        assert!(switch.is_enabled());
+        switch.stop();
+        assert!(switch.set_enabled(true));
+        assert_eq!(*mode.borrow(), DemoMode::Stopped);
+        assert!(!DemoSwitch::default().set_enabled(true));
+    }
+}
@@ -0,0 +1,312 @@
+//! `GET /embed/latest`: a page showing the tip's fractal, for other sites to
+//! put in an iframe. It is plain HTML with a little inline script, so it
+//! works without the frontend, and it reloads itself when a block arrives.
+
+use actix_web::{get, http::header, web, HttpResponse, Responder};
+use serde::{Deserialize, Serialize};
+
+use crate::api::state::AppState;
+use crate::blockchain::block::Block;
+use crate::fractal::FractalData;
+
+/// The width and height of the fractal when the request names none, in pixels.
+pub const DEFAULT_EMBED_SIZE: u32 = 256;
+/// The smallest width or height a request may ask for.
+pub const MIN_EMBED_SIZE: u32 = 32;
+/// The largest width or height a request may ask for.
+pub const MAX_EMBED_SIZE: u32 = 1024;
+/// How long the page waits before asking again after a failed poll.
+const RETRY_MS: u64 = 5_000;
+
+/// The sites that may frame embed pages, as `frame-ancestors` sources of a
+/// Content Security Policy. Set by `--embed-frame-ancestors`; any site may
+/// by default.
+#[derive(Clone, Debug, PartialEq)]
+pub struct FrameAncestors(pub Vec<String>);
+
+impl Default for FrameAncestors {
+    fn default() -> Self {
+        Self(vec!["*".to_string()])
+    }
+}
+
+impl FrameAncestors {
+    /// Returns the `Content-Security-Policy` header value to serve.
+    pub fn policy(&self) -> String {
+        format!("frame-ancestors {}", self.0.join(" "))
+    }
+}
+
+/// Parses one `--embed-frame-ancestors` source, rejecting anything that
+/// would end the policy directive early.
+pub fn parse_frame_ancestor(source: &str) -> Result<String, String> {
+    if source.is_empty() || source.contains(|c: char| c.is_whitespace() || c.is_control() || c == ';' || c == ',') {
+        return Err(format!("{:?} is not a frame-ancestors source", source));
+    }
+    Ok(source.to_string())
+}
+
+/// How escape-time fractals are coloured, named after the frontend's themes.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Palette {
+    #[default]
+    Classic,
+    Grayscale,
+    Fire,
+    Ice,
+    Electric,
+}
+
+impl Palette {
+    /// The colour Sierpinski triangles are filled with.
+    fn triangle_fill(self) -> &'static str {
+        match self {
+            Palette::Classic => "#1a1a2e",
+            Palette::Grayscale => "#404040",
+            Palette::Fire => "#b22222",
+            Palette::Ice => "#1e3a8a",
+            Palette::Electric => "#8b00ff",
+        }
+    }
+}
+
+#[derive(Deserialize, Default, Debug)]
+pub struct EmbedQuery {
+    /// The fractal's width in pixels, clamped to `MIN_EMBED_SIZE..=MAX_EMBED_SIZE`.
+    width: Option<u32>,
+    /// The fractal's height in pixels, clamped likewise.
+    height: Option<u32>,
+    #[serde(default)]
+    palette: Palette,
+    /// Whether to show the block's height, hash, fractal type and time under
+    /// the fractal. On by default.
+    show_meta: Option<bool>,
+}
+
+impl EmbedQuery {
+    fn width(&self) -> u32 {
+        self.width.unwrap_or(DEFAULT_EMBED_SIZE).clamp(MIN_EMBED_SIZE, MAX_EMBED_SIZE)
+    }
+
+    fn height(&self) -> u32 {
+        self.height.unwrap_or(DEFAULT_EMBED_SIZE).clamp(MIN_EMBED_SIZE, MAX_EMBED_SIZE)
+    }
+
+    fn show_meta(&self) -> bool {
+        self.show_meta.unwrap_or(true)
+    }
+}
+
+/// Serves the embed page for the tip, framable by `FrameAncestors`.
+#[get("/embed/latest")]
+pub async fn embed_latest(query: web::Query<EmbedQuery>, state: web::Data<AppState>) -> impl Responder {
+    let page = {
+        let blockchain = state.blockchain.lock().unwrap();
+        match blockchain.chain.last() {
+            Some(tip) => render_page(tip, &query),
+            None => return HttpResponse::ServiceUnavailable().body("The chain has no blocks yet"),
+        }
+    };
+    HttpResponse::Ok()
+        .content_type("text/html; charset=utf-8")
+        .insert_header((header::CONTENT_SECURITY_POLICY, state.frame_ancestors.policy()))
+        .insert_header((header::CACHE_CONTROL, "no-cache"))
+        .body(page)
+}
+
+/// Renders the embed page for `block`. Sierpinski triangles are drawn as an
+/// inline SVG; escape-time fractals on a canvas by the page's script, with
+/// the block's PNG as the fallback.
+pub fn render_page(block: &Block, query: &EmbedQuery) -> String {
+    let (width, height) = (query.width(), query.height());
+    let png_url = format!("../blocks/{}/fractal.png", block.index);
+    let fractal = match &block.fractal {
+        FractalData::Sierpinski(s) => {
+            let polygons: String = s
+                .vertices()
+                .chunks_exact(3)
+                .map(|t| format!("<polygon points=\"{},{} {},{} {},{}\"/>", t[0].0, t[0].1, t[1].0, t[1].1, t[2].0, t[2].1))
+                .collect();
+            format!(
+                "<svg viewBox=\"-0.1 -0.1 1.2 1.2\" width=\"{}\" height=\"{}\" fill=\"{}\">{}</svg>",
+                width,
+                height,
+                query.palette.triangle_fill(),
+                polygons
+            )
+        }
+        FractalData::Mandelbrot(_) | FractalData::Julia(_) => format!(
+            "<canvas id=\"fractal-canvas\" width=\"{w}\" height=\"{h}\"><img src=\"{png}\" width=\"{w}\" height=\"{h}\" alt=\"Fractal of block {index}\"></canvas>",
+            w = width,
+            h = height,
+            png = png_url,
+            index = block.index
+        ),
+    };
+    let meta = if query.show_meta() {
+        let time = chrono::DateTime::from_timestamp(block.timestamp, 0)
+            .map_or_else(|| block.timestamp.to_string(), |time| time.format("%Y-%m-%d %H:%M UTC").to_string());
+        format!(
+            "<p class=\"meta\">Block {} &middot; <span title=\"{}\">{}&hellip;</span> &middot; {} &middot; {}</p>",
+            block.index,
+            escape_html(&block.hash),
+            escape_html(&block.hash.chars().take(12).collect::<String>()),
+            block.fractal.type_name(),
+            time
+        )
+    } else {
+        String::new()
+    };
+    let config = serde_json::json!({
+        "index": block.index,
+        "hash": block.hash,
+        "palette": query.palette,
+        "block_url": format!("../blocks?before={}&limit=1", block.index + 1),
+        "png_url": png_url,
+        "tip_url": "../chain/tip/longpoll",
+        "retry_ms": RETRY_MS,
+    });
+    format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>SierpChain block {}</title>\n<style>{}</style>\n</head>\n<body>\n{}\n{}\n<script type=\"application/json\" id=\"embed-config\">{}</script>\n<script>{}</script>\n</body>\n</html>\n",
+        block.index,
+        STYLE,
+        fractal,
+        meta,
+        // Keep the JSON from closing the script element early.
+        config.to_string().replace("</", "<\\/"),
+        SCRIPT
+    )
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+const STYLE: &str = "body{margin:0;font:12px sans-serif;color:#444;background:#fff}\
+svg,canvas,img{display:block;image-rendering:pixelated}\
+.meta{margin:4px;white-space:nowrap;overflow:hidden;text-overflow:ellipsis}";
+
+/// Draws escape-time fractals with the palette the frontend would use, then
+/// long-polls the tip and reloads the page when it changes.
+const SCRIPT: &str = r#"
+(function () {
+  "use strict";
+  const config = JSON.parse(document.getElementById("embed-config").textContent);
+  const canvas = document.getElementById("fractal-canvas");
+
+  const fraction = (i, max) => (max === 0 ? 0 : Math.min(Math.max(i / max, 0), 1));
+  const channel = (v) => Math.floor(Math.min(Math.max(v, 0), 1) * 255);
+  function hue(h) {
+    const x = 1 - Math.abs(((h / 60) % 2) - 1);
+    const rgb = h < 60 ? [1, x, 0] : h < 120 ? [x, 1, 0] : h < 180 ? [0, 1, x] : h < 240 ? [0, x, 1] : h < 300 ? [x, 0, 1] : [1, 0, x];
+    return rgb.map(channel);
+  }
+  const palettes = {
+    classic: (i) => hue((i * 10) % 360),
+    grayscale: (i, max) => { const v = channel(0.1 + fraction(i, max) * 0.9); return [v, v, v]; },
+    fire: (i, max) => { const t = fraction(i, max); return [channel(0.5 + t * 1.5), channel(t * 2 - 0.2), channel(t * 3 - 2)]; },
+    ice: (i, max) => { const t = fraction(i, max); return [channel(t), channel(0.2 + t * 0.8), channel(0.5 + t * 0.5)]; },
+    electric: (i, max) => { const t = fraction(i, max); return [channel(t), channel(1 - t), 255]; },
+  };
+
+  function showPng() {
+    const img = canvas.querySelector("img");
+    if (img) canvas.replaceWith(img);
+  }
+
+  function draw(fractal) {
+    const colour = palettes[config.palette] || palettes.classic;
+    const source = document.createElement("canvas");
+    source.width = fractal.width;
+    source.height = fractal.height;
+    const context = source.getContext("2d");
+    const image = context.createImageData(fractal.width, fractal.height);
+    fractal.data.forEach((i, p) => {
+      const rgb = i >= fractal.max_iterations ? [0, 0, 0] : colour(i, fractal.max_iterations);
+      image.data.set([rgb[0], rgb[1], rgb[2], 255], p * 4);
+    });
+    context.putImageData(image, 0, 0);
+    const target = canvas.getContext("2d");
+    target.imageSmoothingEnabled = false;
+    target.drawImage(source, 0, 0, canvas.width, canvas.height);
+  }
+
+  if (canvas) {
+    if (!canvas.getContext) {
+      showPng();
+    } else {
+      fetch(config.block_url)
+        .then((response) => (response.ok ? response.json() : Promise.reject(response.status)))
+        .then((blocks) => draw(blocks[0].fractal.data))
+        .catch(showPng);
+    }
+  }
+
+  function waitForNextBlock() {
+    fetch(config.tip_url + "?current=" + encodeURIComponent(config.hash))
+      .then((response) => {
+        if (response.status === 200) location.reload();
+        else if (response.status === 304) waitForNextBlock();
+        else setTimeout(waitForNextBlock, config.retry_ms);
+      })
+      .catch(() => setTimeout(waitForNextBlock, config.retry_ms));
+  }
+  waitForNextBlock();
+})();
+"#;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fractal::FractalType;
+
+    fn block(fractal: FractalType) -> Block {
+        Block {
+            index: 7,
+            timestamp: 1_700_000_000,
+            fractal: fractal.generate(),
+            transactions: vec![],
+            previous_hash: "ab".repeat(32),
+            hash: "cd".repeat(32),
+            nonce: 42,
+            bits: 0,
+            style_seed: 0,
+        }
+    }
+
+    #[test]
+    fn test_page_references_the_block_and_its_hash() {
+        let block = block(FractalType::Sierpinski { depth: 2, seed: 1 });
+        let page = render_page(&block, &EmbedQuery::default());
+        assert!(page.contains(&format!("title=\"{}\"", block.hash)));
+        assert!(page.contains(&format!("\"hash\":\"{}\"", block.hash)));
+        assert!(page.contains("Block 7"));
+        assert_eq!(page.matches("<polygon").count(), 9);
+        assert!(!page.contains("<canvas"));
+
+        let page = render_page(&block, &EmbedQuery { show_meta: Some(false), ..EmbedQuery::default() });
+        assert!(!page.contains("class=\"meta\""));
+        assert!(page.contains(&block.hash), "the script still polls from the block's hash");
+    }
+
+    #[test]
+    fn test_page_respects_the_size_params() {
+        let block = block(FractalType::mandelbrot(20, 10, -2.0, 1.0, -1.0, 1.0, 50, 3).unwrap());
+        let page = render_page(&block, &EmbedQuery { width: Some(320), height: Some(200), ..EmbedQuery::default() });
+        assert!(page.contains("<canvas id=\"fractal-canvas\" width=\"320\" height=\"200\">"));
+        assert!(page.contains("<img src=\"../blocks/7/fractal.png\" width=\"320\" height=\"200\""));
+
+        let page = render_page(&block, &EmbedQuery { width: Some(1), height: Some(100_000), ..EmbedQuery::default() });
+        assert!(page.contains(&format!("width=\"{}\" height=\"{}\"", MIN_EMBED_SIZE, MAX_EMBED_SIZE)));
+    }
+
+    #[test]
+    fn test_frame_ancestors_reject_sources_that_end_the_directive() {
+        assert_eq!(FrameAncestors::default().policy(), "frame-ancestors *");
+        assert_eq!(parse_frame_ancestor("https://blog.example").unwrap(), "https://blog.example");
+        assert_eq!(parse_frame_ancestor("'self'").unwrap(), "'self'");
+        assert!(parse_frame_ancestor("https://a.example; script-src *").is_err());
+        assert!(parse_frame_ancestor("").is_err());
+    }
+}
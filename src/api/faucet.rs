@@ -0,0 +1,363 @@
+use actix_web::{get, post, web, HttpResponse, Responder};
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fmt;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use crate::api::handlers::{check_built_transaction, mine_and_announce};
+use crate::api::state::AppState;
+use crate::blockchain::chain::COIN;
+use crate::core::transaction::{BuildTransactRequest, TxError, TxOutput};
+use crate::core::wallet::Wallet;
+use crate::fractal::FractalType;
+use crate::mining::miner::{MineOutcome, Miner};
+use crate::network::network_id::NetworkId;
+use crate::network::p2p::P2pMessage;
+
+/// Where the faucet ledger is saved, next to `blockchain.json`.
+pub const FAUCET_FILE: &str = "faucet.json";
+/// How long an address must wait between faucet payouts, in seconds.
+pub const ADDRESS_COOLDOWN_SECS: i64 = 60 * 60;
+/// The window the global payout limit is counted over, in seconds.
+pub const GLOBAL_WINDOW_SECS: i64 = 60 * 60;
+/// The most payouts the faucet makes to all addresses within the window.
+pub const GLOBAL_MAX_PAYOUTS: usize = 100;
+/// What the faucet pays a request that names no amount, in base units.
+pub const DEFAULT_FAUCET_AMOUNT: u64 = COIN;
+
+/// Whether the node runs a faucet, set by `--faucet`, and what it pays.
+#[derive(Clone, Copy, Debug)]
+pub struct FaucetConfig {
+    pub enabled: bool,
+    /// Paid to requests that name no amount, capped at the miner's share.
+    pub amount: u64,
+}
+
+impl Default for FaucetConfig {
+    fn default() -> Self {
+        Self { enabled: false, amount: DEFAULT_FAUCET_AMOUNT }
+    }
+}
+
+/// How the faucet paid an address.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PayoutMethod {
+    /// A block was mined whose coinbase pays the address (regtest only).
+    Mined,
+    /// The node wallet sent an ordinary transaction.
+    Transaction,
+}
+
+/// One payout made by the faucet.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FaucetPayout {
+    pub address: String,
+    pub amount: u64,
+    /// When the payout was made, in Unix seconds.
+    pub timestamp: i64,
+    /// The transaction paying the address: the coinbase of the mined block,
+    /// or the transaction sent from the node wallet.
+    pub txid: String,
+    pub method: PayoutMethod,
+}
+
+/// Why the faucet turned a request down for now.
+#[derive(Debug, Clone, PartialEq)]
+pub enum FaucetError {
+    AddressCooldown { retry_after_secs: i64 },
+    GlobalLimit { retry_after_secs: i64 },
+}
+
+impl FaucetError {
+    pub fn retry_after_secs(&self) -> i64 {
+        match self {
+            FaucetError::AddressCooldown { retry_after_secs } | FaucetError::GlobalLimit { retry_after_secs } => {
+                *retry_after_secs
+            }
+        }
+    }
+}
+
+impl fmt::Display for FaucetError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FaucetError::AddressCooldown { retry_after_secs } => {
+                write!(f, "This address was paid recently; try again in {} seconds", retry_after_secs)
+            }
+            FaucetError::GlobalLimit { retry_after_secs } => {
+                write!(f, "The faucet has reached its payout limit; try again in {} seconds", retry_after_secs)
+            }
+        }
+    }
+}
+
+/// Totals over the faucet ledger, as reported by `/faucet/stats`.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct FaucetStats {
+    pub payouts: usize,
+    pub total_issued: u64,
+    pub unique_addresses: usize,
+    /// Payouts counted against the global limit right now.
+    pub payouts_in_window: usize,
+    pub max_payouts_per_window: usize,
+    pub window_secs: i64,
+    pub address_cooldown_secs: i64,
+}
+
+/// Every payout the faucet has made, persisted to a JSON file so that rate
+/// limits and issuance totals survive restarts.
+#[derive(Debug)]
+pub struct FaucetLedger {
+    path: PathBuf,
+    payouts: Vec<FaucetPayout>,
+}
+
+pub type SharedFaucetLedger = Arc<Mutex<FaucetLedger>>;
+
+impl FaucetLedger {
+    /// Loads the ledger saved at `path`. A missing or unreadable file gives
+    /// an empty ledger.
+    pub fn load(path: impl Into<PathBuf>) -> Self {
+        let path = path.into();
+        let payouts = match fs::read_to_string(&path) {
+            Ok(contents) => serde_json::from_str(&contents).unwrap_or_else(|e| {
+                tracing::warn!("Ignoring unreadable faucet ledger {}: {}", path.display(), e);
+                Vec::new()
+            }),
+            Err(_) => Vec::new(),
+        };
+        Self { path, payouts }
+    }
+
+    /// Writes the ledger back to the file it was loaded from.
+    pub fn save(&self) -> std::io::Result<()> {
+        let serialized = serde_json::to_string_pretty(&self.payouts).unwrap();
+        let mut file = fs::File::create(&self.path)?;
+        file.write_all(serialized.as_bytes())
+    }
+
+    /// The file the store is saved to.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Checks whether `address` may be paid at Unix time `now`.
+    pub fn check_limits(&self, address: &str, now: i64) -> Result<(), FaucetError> {
+        let last_paid = self.payouts.iter().filter(|payout| payout.address == address).map(|payout| payout.timestamp).max();
+        if let Some(last_paid) = last_paid.filter(|last_paid| now - last_paid < ADDRESS_COOLDOWN_SECS) {
+            return Err(FaucetError::AddressCooldown { retry_after_secs: last_paid + ADDRESS_COOLDOWN_SECS - now });
+        }
+        let in_window: Vec<i64> = self.payouts_in_window(now).map(|payout| payout.timestamp).collect();
+        if in_window.len() >= GLOBAL_MAX_PAYOUTS {
+            let oldest = in_window.into_iter().min().unwrap_or(now);
+            return Err(FaucetError::GlobalLimit { retry_after_secs: oldest + GLOBAL_WINDOW_SECS - now });
+        }
+        Ok(())
+    }
+
+    /// Adds a payout to the ledger and saves it.
+    pub fn record(&mut self, payout: FaucetPayout) {
+        self.payouts.push(payout);
+        if let Err(e) = self.save() {
+            tracing::error!("Failed to save faucet ledger: {}", e);
+        }
+    }
+
+    pub fn stats(&self, now: i64) -> FaucetStats {
+        let unique_addresses: HashSet<&str> = self.payouts.iter().map(|payout| payout.address.as_str()).collect();
+        FaucetStats {
+            payouts: self.payouts.len(),
+            total_issued: self.payouts.iter().map(|payout| payout.amount).sum(),
+            unique_addresses: unique_addresses.len(),
+            payouts_in_window: self.payouts_in_window(now).count(),
+            max_payouts_per_window: GLOBAL_MAX_PAYOUTS,
+            window_secs: GLOBAL_WINDOW_SECS,
+            address_cooldown_secs: ADDRESS_COOLDOWN_SECS,
+        }
+    }
+
+    fn payouts_in_window(&self, now: i64) -> impl Iterator<Item = &FaucetPayout> {
+        self.payouts.iter().filter(move |payout| now - payout.timestamp < GLOBAL_WINDOW_SECS)
+    }
+}
+
+#[derive(Deserialize)]
+pub struct FaucetRequest {
+    address: String,
+    /// The amount to pay, in base units. At most the miner's share of the
+    /// next block's subsidy. Defaults to the `--faucet-amount`.
+    amount: Option<u64>,
+}
+
+/// Refuses faucet requests unless `--faucet` is set and the node runs on a
+/// test network.
+fn require_faucet(config: FaucetConfig, network: NetworkId) -> Result<(), HttpResponse> {
+    if !config.enabled {
+        return Err(HttpResponse::Forbidden()
+            .json(serde_json::json!({ "error": "The faucet is disabled; start the node with --faucet" })));
+    }
+    if network.is_test_network() {
+        return Ok(());
+    }
+    Err(HttpResponse::Forbidden().json(serde_json::json!({
+        "error": format!("The faucet is only available on test networks, not {}", network),
+    })))
+}
+
+/// Pays test coins to an address, if the node was started with `--faucet`.
+/// On regtest it mines a block whose
+/// coinbase pays the address; on testnet the node wallet sends a fee-free
+/// transaction that arrives with the next block.
+#[post("/faucet")]
+pub async fn faucet(req: web::Json<FaucetRequest>, state: web::Data<AppState>) -> impl Responder {
+    if let Err(response) = require_faucet(state.faucet, state.network) {
+        return response;
+    }
+    if state.network == NetworkId::Regtest && state.light.0 {
+        return HttpResponse::Forbidden()
+            .json(serde_json::json!({ "error": "Regtest payouts are mined, and a light node doesn't mine" }));
+    }
+    let mut blockchain = state.blockchain.lock().unwrap();
+    let max_amount = blockchain.reward_schedule.miner_share(blockchain.chain.len() as u64);
+    if req.address.is_empty() {
+        return HttpResponse::BadRequest().json(serde_json::json!({ "error": "address is required" }));
+    }
+    let amount = req.amount.unwrap_or(state.faucet.amount.min(max_amount));
+    if amount == 0 || amount > max_amount {
+        return HttpResponse::BadRequest().json(serde_json::json!({
+            "error": format!("amount must be between 1 and {}", max_amount),
+        }));
+    }
+
+    let mut ledger = state.faucet_ledger.lock().unwrap();
+    let now = Utc::now().timestamp();
+    if let Err(e) = ledger.check_limits(&req.address, now) {
+        return HttpResponse::TooManyRequests()
+            .insert_header(("Retry-After", e.retry_after_secs().to_string()))
+            .json(serde_json::json!({ "error": e.to_string(), "retry_after_secs": e.retry_after_secs() }));
+    }
+
+    let mut pool = state.tx_pool.lock().unwrap();
+    let payout = TxOutput { value: amount, script_pub_key: req.address.clone().into() };
+    let (txid, method, block_index) = if state.network == NetworkId::Regtest {
+        let policy = state.mining_policy.lock().unwrap().clone();
+        let transactions = Miner::assemble_transactions_with_payouts(
+            &blockchain,
+            &mut pool,
+            &state.miner_wallet.get_address(),
+            vec![payout],
+            policy.coinbase_message.as_deref(),
+        );
+        let fractal_type = FractalType::Sierpinski { depth: 5, seed: 0 };
+        let budget = policy.budget(state.mining_caps);
+        let block = match mine_and_announce(
+            &mut blockchain,
+            &mut pool,
+            fractal_type,
+            transactions,
+            budget,
+            &state.hash_rate,
+            &state.to_p2p,
+            &state.hub,
+        ) {
+            Ok(MineOutcome::Found(block)) => block,
+            Ok(outcome) => return HttpResponse::ServiceUnavailable().json(serde_json::json!({ "error": outcome.to_string() })),
+            Err(e) => return HttpResponse::InternalServerError().json(serde_json::json!({ "error": e.to_string() })),
+        };
+        (block.transactions[0].id.clone(), PayoutMethod::Mined, Some(block.index))
+    } else {
+        let request = BuildTransactRequest {
+            wallets: vec![Wallet::clone(&state.miner_wallet)],
+            to: req.address.clone(),
+            amount,
+            fee: 0,
+            expiry_height: None,
+            memo: None,
+        };
+        let tx = match blockchain.build_transaction(&request, |outpoint| pool.is_spent(outpoint)) {
+            Ok(tx) => tx,
+            Err(e @ TxError::InsufficientFunds { .. }) => {
+                return HttpResponse::ServiceUnavailable()
+                    .json(serde_json::json!({ "error": format!("The faucet wallet is dry: {}", e) }));
+            }
+            Err(e) => return HttpResponse::InternalServerError().json(serde_json::json!({ "error": e.to_string() })),
+        };
+        if let Err(response) = check_built_transaction(&tx, &blockchain) {
+            return response;
+        }
+        if let Err(e) = pool.submit(tx.clone(), &blockchain) {
+            return HttpResponse::ServiceUnavailable().json(serde_json::json!({ "error": e.to_string() }));
+        }
+        state.to_p2p.send(P2pMessage::Transaction(tx.clone())).unwrap();
+        (tx.id, PayoutMethod::Transaction, None)
+    };
+
+    ledger.record(FaucetPayout { address: req.address.clone(), amount, timestamp: now, txid: txid.clone(), method });
+    HttpResponse::Ok().json(serde_json::json!({
+        "txid": txid,
+        "amount": amount,
+        "method": method,
+        "block_index": block_index,
+    }))
+}
+
+/// Reports how much the faucet has paid out and how close it is to its limits.
+#[get("/faucet/stats")]
+pub async fn faucet_stats(state: web::Data<AppState>) -> impl Responder {
+    if let Err(response) = require_faucet(state.faucet, state.network) {
+        return response;
+    }
+    HttpResponse::Ok().json(state.faucet_ledger.lock().unwrap().stats(Utc::now().timestamp()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn payout(address: &str, timestamp: i64) -> FaucetPayout {
+        FaucetPayout {
+            address: address.to_string(),
+            amount: 10,
+            timestamp,
+            txid: format!("{}-{}", address, timestamp),
+            method: PayoutMethod::Mined,
+        }
+    }
+
+    #[test]
+    fn test_ledger_rate_limits_and_persists() {
+        let path = std::env::temp_dir().join(format!("sierpchain-faucet-{}.json", rand::random::<u64>()));
+        let mut ledger = FaucetLedger::load(&path);
+        let now = 1_000_000;
+        assert_eq!(ledger.check_limits("alice", now), Ok(()));
+        ledger.record(payout("alice", now));
+
+        assert_eq!(
+            ledger.check_limits("alice", now + 60),
+            Err(FaucetError::AddressCooldown { retry_after_secs: ADDRESS_COOLDOWN_SECS - 60 })
+        );
+        assert_eq!(ledger.check_limits("alice", now + ADDRESS_COOLDOWN_SECS), Ok(()));
+
+        for i in 1..GLOBAL_MAX_PAYOUTS as i64 {
+            ledger.record(payout(&format!("tester-{}", i), now + i));
+        }
+        assert_eq!(
+            ledger.check_limits("bob", now + 100),
+            Err(FaucetError::GlobalLimit { retry_after_secs: GLOBAL_WINDOW_SECS - 100 })
+        );
+        assert_eq!(ledger.check_limits("bob", now + GLOBAL_WINDOW_SECS + 1), Ok(()));
+
+        let reloaded = FaucetLedger::load(&path);
+        let stats = reloaded.stats(now + 100);
+        assert_eq!(stats.payouts, GLOBAL_MAX_PAYOUTS);
+        assert_eq!(stats.total_issued, 10 * GLOBAL_MAX_PAYOUTS as u64);
+        assert_eq!(stats.unique_addresses, GLOBAL_MAX_PAYOUTS);
+        assert_eq!(stats.payouts_in_window, GLOBAL_MAX_PAYOUTS);
+        std::fs::remove_file(path).ok();
+    }
+}
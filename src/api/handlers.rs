@@ -1,22 +1,71 @@
-use actix_web::{get, post, web, Responder, HttpResponse};
+use actix::Addr;
+use actix_web::http::header;
+use actix_web::{delete, get, post, put, web, HttpRequest, HttpResponse, Responder};
+use actix_web::error::{InternalError, JsonPayloadError};
+use chrono::Utc;
+use clap::ValueEnum;
 use serde::{Deserialize, Serialize};
 use std::sync::{Arc, Mutex};
-use tokio::sync::mpsc;
-use crate::blockchain::chain::Blockchain;
-use crate::core::transaction::{Transaction, TxInput, TxOutput};
+use std::time::{Duration, Instant};
+use tokio::sync::{broadcast, mpsc};
+use crate::api::admin::{authorize, dial, disconnect, AddPeerRequest, DisconnectPeerRequest};
+use crate::api::spending::{queue_for_approval, spend_refused, wallet_policies};
+use crate::api::state::AppState;
+use crate::api::stream;
+use crate::api::websocket::{
+    BroadcastBlock, BroadcastHub, DropTransactions, GetTxStatus, SubscribeTip, SubscribeTxUpdates, TrackTransaction, TransactionReplaced,
+    WatchTransaction,
+};
+use crate::blockchain::block::Block;
+use crate::blockchain::chain::{AddressActivity, Blockchain, EmptyChain};
+use crate::blockchain::graph::ChainGraph;
+use crate::core::amount::parse_coins;
+use crate::core::mempool::{FeeBumpError, MempoolPool};
+use crate::core::script::UnlockingScript;
+use crate::core::spend_policy::{Payment, SpendError};
+use crate::core::transaction::{check_coinbase_message, BuildTransactRequest, Transaction, TxError, TxInput, TxOutput};
 use crate::core::wallet::Wallet;
-use crate::network::p2p::P2pMessage;
-use crate::fractal::FractalType;
+use crate::network::p2p::{self, P2pMessage, PeerStats};
+use crate::fractal::{dimension, render, FractalData, FractalType, FRACTAL_TYPE_NAMES};
+use crate::mining::miner::{BlockTemplate, HashRateMeter, MineOutcome, Miner, MiningBudget, MiningTarget};
+use crate::mining::policy::MiningPolicy;
 use ed25519_dalek::SigningKey;
 use hex;
 
-pub type TransactionPool = Arc<Mutex<Vec<Transaction>>>;
+pub type TransactionPool = Arc<Mutex<MempoolPool>>;
+
+/// The default maximum size of a JSON request body, in bytes. Transactions
+/// and admin requests are small, so this is too.
+pub const DEFAULT_JSON_LIMIT: usize = 64 * 1024;
+
+/// The default maximum size of a block POSTed to `/mining/submit`, in bytes.
+pub const DEFAULT_BLOCK_JSON_LIMIT: usize = 4 * 1024 * 1024;
+
+/// Builds a JSON extractor config.
+///
+/// Bodies larger than `limit` bytes are rejected with a 413 and a JSON error
+/// instead of being buffered in full; other payload errors become a 400.
+pub fn json_config(limit: usize) -> web::JsonConfig {
+    web::JsonConfig::default()
+        .limit(limit)
+        .error_handler(|err, _req| {
+            let response = match &err {
+                JsonPayloadError::Overflow { .. } | JsonPayloadError::OverflowKnownLength { .. } => {
+                    HttpResponse::PayloadTooLarge().json(serde_json::json!({ "error": err.to_string() }))
+                }
+                _ => HttpResponse::BadRequest().json(serde_json::json!({ "error": err.to_string() })),
+            };
+            InternalError::from_response(err, response).into()
+        })
+}
 
 #[derive(Deserialize, Debug)]
 #[serde(tag = "type", content = "params")]
 pub enum MineRequestParams {
     Sierpinski {
         depth: usize,
+        #[serde(default)]
+        style_seed: u64,
     },
     Mandelbrot {
         width: usize,
@@ -26,6 +75,8 @@ pub enum MineRequestParams {
         y_min: f64,
         y_max: f64,
         max_iterations: u32,
+        #[serde(default)]
+        style_seed: u64,
     },
     Julia {
         width: usize,
@@ -37,102 +88,566 @@ pub enum MineRequestParams {
         c_real: f64,
         c_imag: f64,
         max_iterations: u32,
+        #[serde(default)]
+        style_seed: u64,
     },
 }
 
 impl MineRequestParams {
-    // This function will be used to convert the request params to the internal FractalType
-    // The seed will be set to 0, as it will be determined by the miner.
-    pub fn to_fractal_type(&self) -> FractalType {
-        match self {
-            MineRequestParams::Sierpinski { depth } => FractalType::Sierpinski { depth: *depth, seed: 0 },
-            MineRequestParams::Mandelbrot { width, height, x_min, x_max, y_min, y_max, max_iterations } => {
-                FractalType::Mandelbrot {
-                    width: *width,
-                    height: *height,
-                    x_min: *x_min,
-                    x_max: *x_max,
-                    y_min: *y_min,
-                    y_max: *y_max,
-                    max_iterations: *max_iterations,
-                    seed: 0,
-                }
+    /// Converts the request to the internal `FractalType`, seeded with the
+    /// style seed the block will commit to. Fails if a coordinate isn't
+    /// finite or is out of `Coord` range.
+    pub fn to_fractal_type(&self) -> Result<FractalType, String> {
+        match *self {
+            MineRequestParams::Sierpinski { depth, style_seed } => Ok(FractalType::Sierpinski { depth, seed: style_seed }),
+            MineRequestParams::Mandelbrot { width, height, x_min, x_max, y_min, y_max, max_iterations, style_seed } => {
+                FractalType::mandelbrot(width, height, x_min, x_max, y_min, y_max, max_iterations, style_seed)
             }
-            MineRequestParams::Julia { width, height, x_min, x_max, y_min, y_max, c_real, c_imag, max_iterations } => {
-                FractalType::Julia {
-                    width: *width,
-                    height: *height,
-                    x_min: *x_min,
-                    x_max: *x_max,
-                    y_min: *y_min,
-                    y_max: *y_max,
-                    c_real: *c_real,
-                    c_imag: *c_imag,
-                    max_iterations: *max_iterations,
-                    seed: 0,
-                }
+            MineRequestParams::Julia { width, height, x_min, x_max, y_min, y_max, c_real, c_imag, max_iterations, style_seed } => {
+                FractalType::julia(width, height, x_min, x_max, y_min, y_max, c_real, c_imag, max_iterations, style_seed)
             }
         }
     }
 }
 
+/// Query options for endpoints that return blocks.
+#[derive(Deserialize, Default)]
+pub struct BlockQuery {
+    /// Expand Sierpinski fractals with their vertices, for clients that
+    /// cannot regenerate them from depth and seed.
+    #[serde(default)]
+    include_vertices: bool,
+    /// Only return blocks below this height, for paging back through the chain.
+    before: Option<u64>,
+    /// Return at most this many blocks, the highest ones below `before` (or
+    /// up to the tip). Capped at `MAX_PAGE_LIMIT`; without it every block in
+    /// range is returned.
+    limit: Option<usize>,
+    /// Stream the blocks as newline-delimited JSON, as an `Accept:
+    /// application/x-ndjson` header also asks for.
+    #[serde(default)]
+    stream: bool,
+}
+
+/// Whether the node runs as a light node, which follows and serves the
+/// chain but never produces blocks.
+#[derive(Clone, Copy, Debug)]
+pub struct LightNode(pub bool);
+
+/// Returns the 403 to answer block production endpoints with on a light node.
+fn forbid_on_light_node(light: LightNode) -> Result<(), HttpResponse> {
+    if light.0 {
+        return Err(HttpResponse::Forbidden().body("Mining is disabled on a light node"));
+    }
+    Ok(())
+}
+
+/// Serializes a block for an API response, optionally expanding its
+/// vertices, with the message from its coinbase, or `null`.
+fn render_block(block: &Block, include_vertices: bool) -> serde_json::Value {
+    let mut value = serde_json::to_value(block).unwrap();
+    if include_vertices && let FractalData::Sierpinski(sierpinski) = &block.fractal {
+        value["fractal"]["data"]["vertices"] = serde_json::to_value(sierpinski.vertices()).unwrap();
+    }
+    value["coinbase_message"] = block.transactions.first().and_then(Transaction::coinbase_message).into();
+    value
+}
+
+#[derive(Deserialize)]
+pub struct MineQuery {
+    /// Expand a Sierpinski fractal with its vertices, as for `/blocks`.
+    #[serde(default)]
+    include_vertices: bool,
+    /// Mine even if the mining policy would wait for more transactions.
+    #[serde(default)]
+    force: bool,
+    /// The most nonces to try, instead of the mining policy's, up to the
+    /// node's cap.
+    max_attempts: Option<u64>,
+    /// How long to mine for in milliseconds, instead of the mining policy's
+    /// timeout, up to the node's cap.
+    timeout_ms: Option<u64>,
+    /// The message to put in the coinbase, instead of the mining policy's.
+    coinbase_message: Option<String>,
+}
+
+/// The 400 to answer a coinbase message over `MAX_COINBASE_MESSAGE_BYTES` with.
+fn check_requested_coinbase_message(message: Option<&str>) -> Result<(), HttpResponse> {
+    match message.map(check_coinbase_message) {
+        Some(Err(e)) => Err(HttpResponse::BadRequest().json(serde_json::json!({ "error": e.to_string(), "details": e }))),
+        _ => Ok(()),
+    }
+}
 
+/// Mines the next block from the mempool. Unless `force` is set, it follows
+/// the mining policy and answers 409 while the mempool is short of it. A
+/// block not found within the job's budget answers 503 with the outcome,
+/// and its transactions go back to the mempool.
 #[post("/mine")]
 pub async fn mine(
-    blockchain: web::Data<Arc<Mutex<Blockchain>>>,
-    transaction_pool: web::Data<TransactionPool>,
-    to_p2p: web::Data<mpsc::UnboundedSender<P2pMessage>>,
-    miner_wallet: web::Data<Arc<Wallet>>,
+    state: web::Data<AppState>,
+    query: web::Query<MineQuery>,
     params: Option<web::Json<MineRequestParams>>,
 ) -> impl Responder {
-    let mut blockchain = blockchain.lock().unwrap();
-    let mut transactions = transaction_pool.lock().unwrap();
-
-    let coinbase_tx = Transaction::new(
-        vec![TxInput {
-            txid: "0".repeat(64),
-            vout: blockchain.chain.len() as usize,
-            script_sig: String::from("coinbase"),
-            pub_key: String::new(),
-            sequence: 0,
-        }],
-        vec![TxOutput {
-            value: 50, // Reward
-            script_pub_key: miner_wallet.get_address(),
-        }],
-    );
+    if let Err(response) = forbid_on_light_node(state.light) {
+        return response;
+    }
+    if let Err(response) = check_requested_coinbase_message(query.coinbase_message.as_deref()) {
+        return response;
+    }
+    let mut blockchain = state.blockchain.lock().unwrap();
+    let mut transactions = state.tx_pool.lock().unwrap();
+    let policy = state.mining_policy.lock().unwrap().clone();
+    if !query.force && !policy.allows(transactions.len()) {
+        return HttpResponse::Conflict().body(format!(
+            "The mining policy waits for {} pending transactions and the mempool has {}; pass force=true to mine anyway",
+            policy.required_transactions(),
+            transactions.len()
+        ));
+    }
 
-    let mut block_transactions = vec![coinbase_tx];
-    block_transactions.extend(transactions.drain(..));
+    let fractal_type = match params.map(|p| p.to_fractal_type()) {
+        None => FractalType::Sierpinski { depth: 5, seed: 0 }, // Default
+        Some(Ok(fractal_type)) => fractal_type,
+        Some(Err(e)) => return HttpResponse::BadRequest().body(e),
+    };
 
-    let fractal_type = params.map_or_else(
-        || FractalType::Sierpinski { depth: 5, seed: 0 }, // Default
-        |p| p.into_inner().to_fractal_type(),
-    );
+    let default_budget = policy.budget(state.mining_caps);
+    let budget = MiningBudget {
+        max_attempts: query.max_attempts.unwrap_or(default_budget.max_attempts),
+        timeout_ms: query.timeout_ms.unwrap_or(default_budget.timeout_ms),
+    }
+    .within(state.mining_caps);
 
-    let mined_block = blockchain.add_block(fractal_type, block_transactions);
+    let coinbase_message = query.coinbase_message.as_deref().or(policy.coinbase_message.as_deref());
+    let block_transactions =
+        Miner::assemble_transactions_with_message(&blockchain, &mut transactions, &state.miner_wallet.get_address(), coinbase_message);
+
+    match mine_and_announce(
+        &mut blockchain,
+        &mut transactions,
+        fractal_type,
+        block_transactions,
+        budget,
+        &state.hash_rate,
+        &state.to_p2p,
+        &state.hub,
+    ) {
+        Ok(MineOutcome::Found(mined_block)) => HttpResponse::Ok().json(render_block(&mined_block, query.include_vertices)),
+        Ok(outcome) => {
+            let mut body = serde_json::to_value(&outcome).unwrap();
+            body["error"] = outcome.to_string().into();
+            body["budget"] = serde_json::json!(budget);
+            HttpResponse::ServiceUnavailable().json(body)
+        }
+        Err(e) => HttpResponse::InternalServerError().body(e.to_string()),
+    }
+}
+
+/// Mines `transactions` into the next block, saves the chain and announces
+/// the block to peers and WebSocket clients. If `budget` runs out first,
+/// the transactions taken from `pool` are returned to it.
+#[allow(clippy::too_many_arguments)]
+pub fn mine_and_announce(
+    blockchain: &mut Blockchain,
+    pool: &mut MempoolPool,
+    fractal_type: FractalType,
+    transactions: Vec<Transaction>,
+    budget: MiningBudget,
+    hash_rate: &HashRateMeter,
+    to_p2p: &mpsc::UnboundedSender<P2pMessage>,
+    hub: &Addr<BroadcastHub>,
+) -> Result<MineOutcome, EmptyChain> {
+    let started = Instant::now();
+    let unmined = transactions.clone();
+    let outcome = blockchain.mine_next_block(fractal_type, transactions, budget)?;
+    let MineOutcome::Found(mined_block) = &outcome else {
+        pool.return_unmined(unmined, blockchain);
+        return Ok(outcome);
+    };
+    hash_rate.record(mined_block.fractal.type_name(), mined_block.nonce + 1, started.elapsed());
 
     if let Err(e) = blockchain.save_to_file() {
         tracing::error!("Failed to save blockchain: {}", e);
     }
 
     to_p2p.send(P2pMessage::Block(mined_block.clone())).unwrap();
+    hub.do_send(BroadcastBlock { block: mined_block.clone() });
+    Ok(outcome)
+}
+
+#[derive(Deserialize)]
+pub struct MiningTemplateQuery {
+    /// The address the coinbase pays. Defaults to the node's own wallet.
+    #[serde(default)]
+    address: Option<String>,
+    /// The message to put in the coinbase, instead of the mining policy's.
+    #[serde(default)]
+    coinbase_message: Option<String>,
+}
+
+/// Assembles the next block for an external miner, such as the in-browser
+/// one, without mining it. Its transactions stay in the mempool until a
+/// block including them is submitted.
+#[get("/mining/template")]
+pub async fn get_mining_template(
+    state: web::Data<AppState>,
+    query: web::Query<MiningTemplateQuery>,
+) -> impl Responder {
+    if let Err(response) = forbid_on_light_node(state.light) {
+        return response;
+    }
+    if let Err(response) = check_requested_coinbase_message(query.coinbase_message.as_deref()) {
+        return response;
+    }
+    let coinbase_message = query.coinbase_message.clone().or_else(|| state.mining_policy.lock().unwrap().coinbase_message.clone());
+    let blockchain = state.blockchain.lock().unwrap();
+    let mut pool = state.tx_pool.lock().unwrap().clone();
+    let address = query.address.clone().unwrap_or_else(|| state.miner_wallet.get_address());
+    let transactions = Miner::assemble_transactions_with_message(&blockchain, &mut pool, &address, coinbase_message.as_deref());
+    let fractal_type = FractalType::Sierpinski { depth: 5, seed: 0 };
+    match blockchain.next_block(&fractal_type, transactions) {
+        Ok(block) => HttpResponse::Ok().json(BlockTemplate::new(block, fractal_type)),
+        Err(e) => HttpResponse::InternalServerError().body(e.to_string()),
+    }
+}
+
+/// Serves `submit_mined_block` at `/mining/submit` with a JSON limit of its
+/// own, as it takes whole blocks.
+pub fn mining_submit(limit: usize) -> actix_web::Resource {
+    web::resource("/mining/submit")
+        .app_data(json_config(limit))
+        .route(web::post().to(submit_mined_block))
+}
+
+/// Accepts a block mined from a `/mining/template` and announces it like
+/// one mined here. A template the chain has moved past is refused with 409.
+pub async fn submit_mined_block(block: web::Json<Block>, state: web::Data<AppState>) -> impl Responder {
+    if let Err(response) = forbid_on_light_node(state.light) {
+        return response;
+    }
+    let block = block.into_inner();
+    let mut blockchain = state.blockchain.lock().unwrap();
+    if blockchain.chain.last().is_some_and(|tip| tip.hash != block.previous_hash) {
+        return HttpResponse::Conflict().body("Stale template: the chain has a new tip");
+    }
+    if let Err(e) = blockchain.add_block_from_network(block.clone()) {
+        return HttpResponse::BadRequest().json(serde_json::json!({ "error": e.to_string(), "details": e }));
+    }
+    state.tx_pool.lock().unwrap().block_connected(&block, &blockchain);
+    if let Err(e) = blockchain.save_to_file() {
+        tracing::error!("Failed to save blockchain: {}", e);
+    }
+
+    state.to_p2p.send(P2pMessage::Block(block.clone())).unwrap();
+    state.hub.do_send(BroadcastBlock { block: block.clone() });
+    HttpResponse::Ok().json(block)
+}
+
+/// Reports the mining policy, the budget mining jobs get under it, and
+/// whether the mempool satisfies it.
+#[get("/mining/status")]
+pub async fn get_mining_status(state: web::Data<AppState>) -> impl Responder {
+    let policy = state.mining_policy.lock().unwrap().clone();
+    let pending = state.tx_pool.lock().unwrap().len();
+    HttpResponse::Ok().json(serde_json::json!({
+        "policy": policy,
+        "budget": policy.budget(state.mining_caps),
+        "caps": state.mining_caps,
+        "pending_transactions": pending,
+        "ready": !state.light.0 && policy.allows(pending),
+        "light": state.light.0,
+    }))
+}
+
+/// Replaces the mining policy until the node restarts; keys left out take
+/// their defaults. It needs no admin key.
+#[put("/mining/config")]
+pub async fn put_mining_config(body: web::Json<MiningPolicy>, state: web::Data<AppState>) -> impl Responder {
+    let policy = body.into_inner();
+    if let Err(response) = check_requested_coinbase_message(policy.coinbase_message.as_deref()) {
+        return response;
+    }
+    *state.mining_policy.lock().unwrap() = policy.clone();
+    tracing::info!("Mining policy changed to {:?}", policy);
+    HttpResponse::Ok().json(policy)
+}
+
+/// Describes the proof-of-work target for the next block.
+#[get("/mining/target")]
+pub async fn get_mining_target(
+    state: web::Data<AppState>,
+) -> impl Responder {
+    let bits = state.blockchain.lock().unwrap().target_bits();
+    web::Json(MiningTarget::new(bits, state.hash_rate.hashes_per_second()))
+}
 
-    HttpResponse::Ok().json(mined_block)
+#[derive(Deserialize)]
+pub struct MineEstimateQuery {
+    /// Only count recent blocks of this fractal type, e.g. `sierpinski`.
+    #[serde(default)]
+    fractal: Option<String>,
+}
+
+/// Estimates how long the next block will take to mine at the current
+/// difficulty, from the hash rate of the blocks this node mined recently.
+#[get("/mine/estimate")]
+pub async fn get_mine_estimate(
+    state: web::Data<AppState>,
+    query: web::Query<MineEstimateQuery>,
+) -> impl Responder {
+    let fractal = match query.fractal.as_deref() {
+        None => None,
+        Some(name) => match FRACTAL_TYPE_NAMES.iter().find(|known| known.eq_ignore_ascii_case(name)) {
+            Some(known) => Some(*known),
+            None => return HttpResponse::BadRequest().body(format!("Unknown fractal type {}", name)),
+        },
+    };
+    let bits = state.blockchain.lock().unwrap().target_bits();
+    let recent_hashrate = state.hash_rate.recent_hashes_per_second(fractal);
+    let target = MiningTarget::new(bits, recent_hashrate);
+    HttpResponse::Ok().json(serde_json::json!({
+        "difficulty": target.difficulty,
+        "estimated_seconds": target.estimated_seconds_at_current_hashrate,
+        "recent_hashrate": recent_hashrate,
+    }))
 }
 
 #[get("/blocks")]
-pub async fn get_blocks(data: web::Data<Arc<Mutex<Blockchain>>>) -> impl Responder {
-    let blockchain = data.lock().unwrap();
-    web::Json(blockchain.chain.clone())
+pub async fn get_blocks(
+    req: HttpRequest,
+    state: web::Data<AppState>,
+    query: web::Query<BlockQuery>,
+) -> impl Responder {
+    let blockchain = state.blockchain.lock().unwrap();
+    let end = query
+        .before
+        .map_or(blockchain.chain.len(), |before| (before as usize).min(blockchain.chain.len()));
+    let start = query
+        .limit
+        .map_or(0, |limit| end.saturating_sub(limit.min(MAX_PAGE_LIMIT)));
+    if query.stream || accepts_ndjson(&req) {
+        drop(blockchain);
+        let include_vertices = query.include_vertices;
+        return stream::stored_blocks(state.clone(), start..end, move |block| render_block(block, include_vertices));
+    }
+    let blocks: Vec<serde_json::Value> = blockchain.chain[start..end]
+        .iter()
+        .map(|block| render_block(block, query.include_vertices))
+        .collect();
+    HttpResponse::Ok().json(blocks)
+}
+
+/// Returns true if the request's `Accept` header asks for newline-delimited JSON.
+fn accepts_ndjson(req: &HttpRequest) -> bool {
+    req.headers()
+        .get(header::ACCEPT)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|accept| accept.contains("application/x-ndjson"))
+}
+
+/// How `GET /chain/graph` renders the graph.
+#[derive(Deserialize, Default, Clone, Copy, PartialEq, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum GraphFormat {
+    #[default]
+    Json,
+    /// Graphviz's DOT language, for `dot -Tsvg`.
+    Dot,
+}
+
+#[derive(Deserialize)]
+pub struct ChainGraphQuery {
+    #[serde(default = "default_graph_depth")]
+    depth: u64,
+    #[serde(default)]
+    format: GraphFormat,
+}
+
+fn default_graph_depth() -> u64 {
+    20
+}
+
+/// Draws the blocks at the last `depth` heights, side chains included, as
+/// a graph linked by `previous_hash`.
+#[get("/chain/graph")]
+pub async fn get_chain_graph(query: web::Query<ChainGraphQuery>, state: web::Data<AppState>) -> impl Responder {
+    let graph = ChainGraph::build(&state.blockchain.lock().unwrap(), query.depth);
+    match query.format {
+        GraphFormat::Json => HttpResponse::Ok().json(graph),
+        GraphFormat::Dot => HttpResponse::Ok().content_type("text/vnd.graphviz").body(graph.to_dot()),
+    }
+}
+
+/// The longest a `/chain/tip/longpoll` request waits for the tip to change.
+pub const MAX_LONGPOLL_WAIT: Duration = Duration::from_secs(30);
+
+#[derive(Deserialize)]
+pub struct LongPollQuery {
+    /// The tip hash the client already has.
+    current: String,
+    /// How long to wait, in seconds, capped at `MAX_LONGPOLL_WAIT`.
+    timeout_secs: Option<u64>,
+}
+
+/// Returns the tip's height and hash if its hash isn't `current`.
+fn tip_if_changed(blockchain: &Mutex<Blockchain>, current: &str) -> Option<serde_json::Value> {
+    let blockchain = blockchain.lock().unwrap();
+    let tip = blockchain.chain.last()?;
+    (tip.hash != current).then(|| {
+        serde_json::json!({
+            "height": tip.index,
+            "hash": tip.hash,
+            "timestamp": tip.timestamp,
+        })
+    })
+}
+
+/// Waits until the chain tip is no longer `current`, then returns the new
+/// tip. Answers 304 if the tip is still the same when the wait times out.
+#[get("/chain/tip/longpoll")]
+pub async fn longpoll_tip(
+    query: web::Query<LongPollQuery>,
+    state: web::Data<AppState>,
+) -> impl Responder {
+    let wait = query.timeout_secs.map_or(MAX_LONGPOLL_WAIT, |secs| Duration::from_secs(secs).min(MAX_LONGPOLL_WAIT));
+    let deadline = tokio::time::Instant::now() + wait;
+    // Subscribe before looking at the tip, so a block arriving in between still wakes us.
+    let Ok(mut tip_changes) = state.hub.send(SubscribeTip).await else {
+        return HttpResponse::InternalServerError().body("Block notifications are unavailable");
+    };
+    loop {
+        if let Some(tip) = tip_if_changed(&state.blockchain, &query.current) {
+            return HttpResponse::Ok().json(tip);
+        }
+        match tokio::time::timeout_at(deadline, tip_changes.recv()).await {
+            // Lagging only means several changes happened; check the tip again.
+            Ok(Ok(())) | Ok(Err(broadcast::error::RecvError::Lagged(_))) => continue,
+            Ok(Err(broadcast::error::RecvError::Closed)) | Err(_) => return HttpResponse::NotModified().finish(),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+pub struct TrackQuery {
+    /// The `version` of the status the client already has.
+    after: Option<u64>,
+    /// How long to wait, in seconds, capped at `MAX_LONGPOLL_WAIT`.
+    timeout_secs: Option<u64>,
+}
+
+/// Returns the status of a transaction submitted to this node, waiting for
+/// it to change if the client already has version `after`: `pending`, then
+/// `included` and `confirmed` as blocks pile up on it, or `dropped`. The
+/// tracking ID is the txid. Answers 304 if nothing changed before the wait
+/// timed out.
+#[get("/track/{tracking_id}")]
+pub async fn track_transaction(
+    tracking_id: web::Path<String>,
+    query: web::Query<TrackQuery>,
+    state: web::Data<AppState>,
+) -> impl Responder {
+    let wait = query.timeout_secs.map_or(MAX_LONGPOLL_WAIT, |secs| Duration::from_secs(secs).min(MAX_LONGPOLL_WAIT));
+    let deadline = tokio::time::Instant::now() + wait;
+    // Subscribe before looking at the status, so a change in between still wakes us.
+    let Ok(mut updates) = state.hub.send(SubscribeTxUpdates).await else {
+        return HttpResponse::InternalServerError().body("Transaction tracking is unavailable");
+    };
+    loop {
+        let Ok(status) = state.hub.send(GetTxStatus { txid: tracking_id.to_string() }).await else {
+            return HttpResponse::InternalServerError().body("Transaction tracking is unavailable");
+        };
+        let Some(status) = status else {
+            return HttpResponse::NotFound().body("Unknown tracking ID");
+        };
+        if query.after.is_none_or(|after| status.version > after) {
+            return HttpResponse::Ok().json(status);
+        }
+        match tokio::time::timeout_at(deadline, updates.recv()).await {
+            Ok(Ok(_)) | Ok(Err(broadcast::error::RecvError::Lagged(_))) => continue,
+            Ok(Err(broadcast::error::RecvError::Closed)) | Err(_) => return HttpResponse::NotModified().finish(),
+        }
+    }
+}
+
+#[get("/supply")]
+pub async fn get_supply(state: web::Data<AppState>) -> impl Responder {
+    let blockchain = state.blockchain.lock().unwrap();
+    let supply = blockchain.utxo_index.supply();
+    web::Json(serde_json::json!({
+        "total_supply": supply.total_supply(),
+        "utxo_count": blockchain.utxo_index.len(),
+        "circulating": blockchain.circulating_supply(),
+        "unspent": supply.unspent,
+        "issued": supply.issued,
+        "fees": supply.fees,
+        "burned": supply.burned,
+        "invariant_ok": supply.is_consistent(),
+    }))
+}
+
+#[get("/status")]
+pub async fn get_status(
+    state: web::Data<AppState>,
+) -> impl Responder {
+    let blockchain = state.blockchain.lock().unwrap();
+    let listen_addrs = state.p2p_listen_addrs.lock().unwrap();
+    web::Json(serde_json::json!({
+        "height": blockchain.chain.last().map_or(0, |block| block.index),
+        "difficulty": blockchain.difficulty(),
+        "supply_invariant_ok": blockchain.utxo_index.supply().is_consistent(),
+        "display_decimals": blockchain.denomination.decimals(),
+        "network": &state.network,
+        "light": state.light.0,
+        "p2p": state.p2p_metrics.to_json(),
+        "http_port": state.http_addr.get().map(|address| address.port()),
+        "p2p_port": listen_addrs.iter().find_map(p2p::tcp_port),
+        "p2p_listen_addrs": listen_addrs.iter().map(|address| address.to_string()).collect::<Vec<_>>(),
+    }))
+}
+
+#[get("/mempool")]
+pub async fn get_mempool(state: web::Data<AppState>) -> impl Responder {
+    let pool = state.tx_pool.lock().unwrap();
+    web::Json(serde_json::json!({
+        "transactions": pool.len(),
+        "orphans": pool.orphan_count(),
+    }))
+}
+
+/// Lists the pending transactions a pending transaction spends outputs of
+/// and those spending its outputs, for deciding how to bump its fee.
+#[get("/mempool/{txid}/relatives")]
+pub async fn get_mempool_relatives(txid: web::Path<String>, state: web::Data<AppState>) -> impl Responder {
+    let blockchain = state.blockchain.lock().unwrap();
+    match state.tx_pool.lock().unwrap().relatives(&txid, &blockchain) {
+        Some(relatives) => HttpResponse::Ok().json(relatives),
+        None => HttpResponse::NotFound().body("Transaction not found in mempool"),
+    }
+}
+
+/// Evicts a pending transaction, and any pending transactions spending its
+/// outputs, from this node's pool so that its inputs can be spent again.
+#[delete("/mempool/{txid}")]
+pub async fn evict_transaction(txid: web::Path<String>, state: web::Data<AppState>) -> impl Responder {
+    let removed = state.tx_pool.lock().unwrap().remove(&txid);
+    if removed.is_empty() {
+        return HttpResponse::NotFound().body("Transaction not found in mempool");
+    }
+    let removed: Vec<String> = removed.into_iter().map(|tx| tx.id).collect();
+    state.hub.do_send(DropTransactions { txids: removed.clone(), reason: "Evicted from the mempool".to_string() });
+    HttpResponse::Ok().json(serde_json::json!({
+        "removed": removed,
+        "note": "Evicted from this node's mempool only; peers that already received the transaction may still mine it.",
+    }))
 }
 
 #[get("/address/{address}/balance")]
 pub async fn get_balance(
     address: web::Path<String>,
-    blockchain: web::Data<Arc<Mutex<Blockchain>>>,
+    state: web::Data<AppState>,
 ) -> impl Responder {
-    let blockchain = blockchain.lock().unwrap();
+    let blockchain = state.blockchain.lock().unwrap();
     let balance = blockchain.get_balance(&address.into_inner());
     web::Json(balance)
 }
@@ -140,21 +655,143 @@ pub async fn get_balance(
 #[get("/address/{address}/utxos")]
 pub async fn get_utxos(
     address: web::Path<String>,
-    blockchain: web::Data<Arc<Mutex<Blockchain>>>,
+    state: web::Data<AppState>,
 ) -> impl Responder {
-    let blockchain = blockchain.lock().unwrap();
+    let blockchain = state.blockchain.lock().unwrap();
     let utxos = blockchain.get_utxos(&address.into_inner());
     web::Json(utxos)
 }
 
+#[derive(Deserialize)]
+pub struct PageQuery {
+    #[serde(default)]
+    page: usize,
+    #[serde(default = "default_page_limit")]
+    limit: usize,
+}
+
+fn default_page_limit() -> usize {
+    10
+}
+
+/// The largest page size the paginated endpoints will return.
+const MAX_PAGE_LIMIT: usize = 100;
+
+#[derive(Serialize)]
+pub struct MinerFractalEntry {
+    block_index: u64,
+    timestamp: i64,
+    fractal_type: String,
+    quality_score: f64,
+    fractal_thumbnail_url: String,
+}
+
+#[get("/address/{address}/fractals")]
+pub async fn get_address_fractals(
+    address: web::Path<String>,
+    query: web::Query<PageQuery>,
+    state: web::Data<AppState>,
+) -> impl Responder {
+    let blockchain = state.blockchain.lock().unwrap();
+    let limit = query.limit.min(MAX_PAGE_LIMIT);
+    let entries: Vec<MinerFractalEntry> = blockchain
+        .get_blocks_mined_by(&address.into_inner())
+        .into_iter()
+        .skip(query.page.saturating_mul(limit))
+        .take(limit)
+        .map(|index| {
+            let block = &blockchain.chain[index as usize];
+            MinerFractalEntry {
+                block_index: block.index,
+                timestamp: block.timestamp,
+                fractal_type: block.fractal.type_name().to_string(),
+                quality_score: block.fractal.quality_score(),
+                fractal_thumbnail_url: format!("/blocks/{}/fractal.png", block.index),
+            }
+        })
+        .collect();
+    web::Json(entries)
+}
+
+#[derive(Deserialize)]
+pub struct BlockViewQuery {
+    /// Expand a Sierpinski fractal with its vertices, as for `/blocks`.
+    #[serde(default)]
+    include_vertices: bool,
+}
+
+/// Returns one block of the active chain, rendered as `/blocks` does.
+#[get("/blocks/{index}")]
+pub async fn get_block(
+    index: web::Path<u64>,
+    query: web::Query<BlockViewQuery>,
+    state: web::Data<AppState>,
+) -> impl Responder {
+    let blockchain = state.blockchain.lock().unwrap();
+    match blockchain.chain.get(index.into_inner() as usize) {
+        Some(block) => HttpResponse::Ok().json(render_block(block, query.include_vertices)),
+        None => HttpResponse::NotFound().body("Block not found"),
+    }
+}
+
+#[get("/blocks/{index}/fractal.png")]
+pub async fn get_block_fractal_png(
+    index: web::Path<u64>,
+    state: web::Data<AppState>,
+) -> impl Responder {
+    let fractal = {
+        let blockchain = state.blockchain.lock().unwrap();
+        match blockchain.chain.get(index.into_inner() as usize) {
+            Some(block) => block.fractal.clone(),
+            None => return HttpResponse::NotFound().body("Block not found"),
+        }
+    };
+    // PNGs are compressed already; deflating them again only costs time.
+    HttpResponse::Ok()
+        .content_type("image/png")
+        .insert_header(header::ContentEncoding::Identity)
+        .body(render::render_png(&fractal))
+}
+
+/// Estimates the box-counting dimension of a block's fractal. It is `null`
+/// for fractals too small or too plain to measure.
+#[get("/blocks/{index}/dimension")]
+pub async fn get_block_dimension(
+    index: web::Path<u64>,
+    state: web::Data<AppState>,
+) -> impl Responder {
+    let fractal = {
+        let blockchain = state.blockchain.lock().unwrap();
+        match blockchain.chain.get(index.into_inner() as usize) {
+            Some(block) => block.fractal.clone(),
+            None => return HttpResponse::NotFound().body("Block not found"),
+        }
+    };
+    HttpResponse::Ok().json(serde_json::json!({ "dimension": dimension::dimension(&fractal) }))
+}
+
+/// Recomputes a block's hash and re-checks its proof of work, its link to
+/// the block before it and its transactions' signatures, for explorers and
+/// debugging.
+#[get("/blocks/{index}/verify")]
+pub async fn verify_block(
+    index: web::Path<u64>,
+    state: web::Data<AppState>,
+) -> impl Responder {
+    let blockchain = state.blockchain.lock().unwrap();
+    match blockchain.verify_block(index.into_inner() as usize) {
+        Some(verification) => HttpResponse::Ok().json(verification),
+        None => HttpResponse::NotFound().body("Block not found"),
+    }
+}
+
 #[get("/wallet/info")]
 pub async fn get_wallet_info(
-    blockchain: web::Data<Arc<Mutex<Blockchain>>>,
-    miner_wallet: web::Data<Arc<Wallet>>,
+    state: web::Data<AppState>,
 ) -> impl Responder {
-    let address = miner_wallet.get_address();
+    let address = state.miner_wallet.get_address();
     let balance = {
-        let blockchain = blockchain.lock().unwrap();
+        let blockchain = state.blockchain.lock().unwrap();
         blockchain.get_balance(&address)
     };
     web::Json(serde_json::json!({
@@ -163,83 +800,751 @@ pub async fn get_wallet_info(
     }))
 }
 
+#[derive(Serialize)]
+struct PeerInfo {
+    peer_id: String,
+    #[serde(flatten)]
+    stats: PeerStats,
+}
+
+#[get("/peers")]
+pub async fn get_peers(state: web::Data<AppState>) -> impl Responder {
+    let peer_stats = state.peer_stats.lock().unwrap();
+    let peers: Vec<PeerInfo> = peer_stats
+        .iter()
+        .map(|(peer_id, stats)| PeerInfo {
+            peer_id: peer_id.to_string(),
+            stats: stats.clone(),
+        })
+        .collect();
+    web::Json(peers)
+}
+
+/// Dials a peer without restarting the node. Like `POST /admin/peers`, it
+/// needs the admin key.
+#[post("/peers/connect")]
+pub async fn connect_peer(req: HttpRequest, body: web::Json<AddPeerRequest>, state: web::Data<AppState>) -> impl Responder {
+    if let Err(response) = authorize(&req, &state.admin_key) {
+        return response;
+    }
+    dial(&state, &body.multiaddr).await
+}
+
+/// Disconnects from a peer and drops it from the gossip peers. Like
+/// `DELETE /admin/peers/{peer_id}`, it needs the admin key.
+#[post("/peers/disconnect")]
+pub async fn disconnect_peer(
+    req: HttpRequest,
+    body: web::Json<DisconnectPeerRequest>,
+    state: web::Data<AppState>,
+) -> impl Responder {
+    if let Err(response) = authorize(&req, &state.admin_key) {
+        return response;
+    }
+    disconnect(&state, &body.peer_id).await
+}
+
+/// Lists every peer the node remembers, including banned ones.
+#[get("/peers/known")]
+pub async fn get_known_peers(state: web::Data<AppState>) -> impl Responder {
+    let peer_store = state.peer_store.lock().unwrap();
+    let now = Utc::now().timestamp();
+    let peers: Vec<serde_json::Value> = peer_store
+        .records()
+        .iter()
+        .map(|(peer_id, record)| {
+            serde_json::json!({
+                "peer_id": peer_id,
+                "addresses": record.addresses,
+                "last_seen": record.last_seen,
+                "user_agent": record.user_agent,
+                "banned_until": record.banned_until.filter(|_| record.is_banned(now)),
+                "misbehavior_score": record.misbehavior_score,
+            })
+        })
+        .collect();
+    web::Json(peers)
+}
+
 #[derive(Deserialize)]
 pub struct TransactRequest {
     to: String,
-    amount: u64,
-    private_key: String,
+    /// The amount to send, in base units.
+    #[serde(default)]
+    amount: Option<u64>,
+    /// The amount to send as a decimal number of coins, e.g. `"0.005"`.
+    /// Used instead of `amount`.
+    #[serde(default)]
+    amount_coins: Option<String>,
+    #[serde(default)]
+    private_key: Option<String>,
+    /// Several keys to spend from together, used instead of `private_key`.
+    /// Change goes back to the first.
+    #[serde(default)]
+    private_keys: Vec<String>,
+    /// The fee to pay the miner, on top of `amount`.
+    #[serde(default)]
+    fee: u64,
+    /// WebSocket subscription to notify once the transaction is mined.
+    #[serde(default)]
+    subscription_id: Option<String>,
+    /// If set, the transaction may only be mined in one of the next
+    /// `expire_after_blocks` blocks and is evicted from the mempool after that.
+    #[serde(default)]
+    expire_after_blocks: Option<u64>,
+}
+
+impl TransactRequest {
+    /// Resolves the senders' keys, the amount and the expiry height.
+    fn to_build_request(&self, blockchain: &Blockchain) -> Result<BuildTransactRequest, String> {
+        let wallets = match (&self.private_key, self.private_keys.as_slice()) {
+            (Some(private_key), []) => vec![wallet_from_private_key(private_key)?],
+            (None, private_keys) if !private_keys.is_empty() => {
+                private_keys.iter().map(String::as_str).map(wallet_from_private_key).collect::<Result<_, _>>()?
+            }
+            _ => return Err("Specify exactly one of private_key and private_keys".to_string()),
+        };
+        let amount = match (self.amount, &self.amount_coins) {
+            (Some(amount), None) => amount,
+            (None, Some(coins)) => parse_coins(coins, blockchain.denomination.decimals()).map_err(|e| e.to_string())?,
+            _ => return Err("Specify exactly one of amount and amount_coins".to_string()),
+        };
+        let expiry_height = match self.expire_after_blocks {
+            None => None,
+            Some(0) => return Err("expire_after_blocks must be at least 1".to_string()),
+            Some(blocks) => Some((blockchain.chain.len() as u64).saturating_add(blocks)),
+        };
+        Ok(BuildTransactRequest { wallets, to: self.to.clone(), amount, fee: self.fee, expiry_height, memo: None })
+    }
+}
+
+/// Double-checks a transaction this node just built, which should never fail.
+pub fn check_built_transaction(tx: &Transaction, blockchain: &Blockchain) -> Result<(), HttpResponse> {
+    let spent = |input: &TxInput| blockchain.utxo_index.get(&(input.txid.clone(), input.vout)).map(|output| &output.script_pub_key);
+    if let Err(e) = tx.verify(spent) {
+        return Err(HttpResponse::InternalServerError().body(format!("Failed to verify new transaction: {}", e)));
+    }
+    if let Err(e) = tx.validate_amounts(blockchain) {
+        return Err(HttpResponse::InternalServerError().body(format!("Built an inconsistent transaction: {}", e)));
+    }
+    Ok(())
 }
 
 #[post("/transact")]
 pub async fn transact(
     req: web::Json<TransactRequest>,
-    blockchain: web::Data<Arc<Mutex<Blockchain>>>,
-    tx_pool: web::Data<TransactionPool>,
-    p2p_sender: web::Data<mpsc::UnboundedSender<P2pMessage>>,
+    state: web::Data<AppState>,
+) -> impl Responder {
+    let policies = wallet_policies(&state);
+    let now = Utc::now().timestamp();
+    let blockchain = state.blockchain.lock().unwrap();
+    let request = match req.to_build_request(&blockchain) {
+        Ok(request) => request,
+        Err(e) => return HttpResponse::BadRequest().body(e),
+    };
+    let payment = Payment::from(&request);
+    let refused = state.spend_ledger.lock().unwrap().check(&policies, std::slice::from_ref(&payment), now).pop();
+    match refused {
+        None => {}
+        Some((_, SpendError::NeedsApproval { .. })) => {
+            return queue_for_approval(&state, &policies, &request, req.expire_after_blocks, now);
+        }
+        Some((_, error)) => return spend_refused(error),
+    }
+    let mut pool = state.tx_pool.lock().unwrap();
+    // Outputs already spent by our pending transactions stay reserved until
+    // those are mined or evicted.
+    let new_tx = match blockchain.build_transaction(&request, |outpoint| pool.is_spent(outpoint)) {
+        Ok(tx) => tx,
+        Err(e) => return HttpResponse::BadRequest().body(e.to_string()),
+    };
+    if let Err(response) = check_built_transaction(&new_tx, &blockchain) {
+        return response;
+    }
+
+    if let Err(e) = pool.submit(new_tx.clone(), &blockchain) {
+        return HttpResponse::BadRequest().body(e.to_string());
+    }
+    state.spend_ledger.lock().unwrap().record(&policies, &payment, &new_tx.id, now);
+
+    state.to_p2p.send(P2pMessage::Transaction(new_tx.clone())).unwrap();
+
+    if let Some(subscription_id) = req.subscription_id.clone() {
+        state.hub.do_send(WatchTransaction { txid: new_tx.id.clone(), subscription_id });
+    }
+    state.hub.do_send(TrackTransaction { txid: new_tx.id.clone(), expiry_height: new_tx.expiry_height });
+
+    HttpResponse::Ok().json(Submitted { tracking_id: &new_tx.id, tx: &new_tx })
+}
+
+/// A transaction accepted into the mempool, with the ID `GET /track`
+/// follows it by.
+#[derive(Serialize)]
+struct Submitted<'a> {
+    #[serde(flatten)]
+    tx: &'a Transaction,
+    tracking_id: &'a str,
+}
+
+/// The most transactions `POST /transact/batch` accepts at once.
+pub const MAX_BATCH_SIZE: usize = 100;
+
+/// Why one transaction in a batch was rejected.
+#[derive(Serialize)]
+pub struct TransactError {
+    /// The position of the request in the batch.
+    index: usize,
+    error: String,
+}
+
+/// Submits up to `MAX_BATCH_SIZE` transactions at once. Either all of them
+/// enter the mempool, or none do and the response lists every request that
+/// failed.
+#[post("/transact/batch")]
+pub async fn transact_batch(
+    reqs: web::Json<Vec<TransactRequest>>,
+    state: web::Data<AppState>,
 ) -> impl Responder {
-    let private_key_bytes = match hex::decode(&req.private_key) {
+    if reqs.is_empty() || reqs.len() > MAX_BATCH_SIZE {
+        return HttpResponse::BadRequest().body(format!("A batch must contain 1 to {} transactions", MAX_BATCH_SIZE));
+    }
+    let policies = wallet_policies(&state);
+    let now = Utc::now().timestamp();
+    let blockchain = state.blockchain.lock().unwrap();
+    let mut errors = Vec::new();
+    let mut requests = Vec::new();
+    // The batch position of each entry in `requests`.
+    let mut positions = Vec::new();
+    for (index, req) in reqs.iter().enumerate() {
+        match req.to_build_request(&blockchain) {
+            Ok(request) => {
+                requests.push(request);
+                positions.push(index);
+            }
+            Err(error) => errors.push(TransactError { index, error }),
+        }
+    }
+
+    // Batches can't wait for approval, so payments that need it fail here too.
+    let payments: Vec<Payment> = requests.iter().map(Payment::from).collect();
+    let refused = state.spend_ledger.lock().unwrap().check(&policies, &payments, now);
+    errors.extend(refused.into_iter().map(|(i, e)| TransactError { index: positions[i], error: e.to_string() }));
+
+    let mut pool = state.tx_pool.lock().unwrap();
+    let transactions = match blockchain.check_batch_feasibility(&requests, |outpoint| pool.is_spent(outpoint)) {
+        Ok(transactions) if errors.is_empty() => transactions,
+        Ok(_) => Vec::new(),
+        Err(failures) => {
+            errors.extend(
+                failures.into_iter().map(|(i, e)| TransactError { index: positions[i], error: e.to_string() }),
+            );
+            Vec::new()
+        }
+    };
+    if !errors.is_empty() {
+        errors.sort_by_key(|error| error.index);
+        return HttpResponse::BadRequest().json(errors);
+    }
+    for tx in &transactions {
+        if let Err(response) = check_built_transaction(tx, &blockchain) {
+            return response;
+        }
+    }
+
+    for (index, tx) in transactions.iter().enumerate() {
+        if let Err(e) = pool.submit(tx.clone(), &blockchain) {
+            // Take back the ones already added, so the batch stays all-or-nothing.
+            for added in &transactions[..index] {
+                pool.remove(&added.id);
+            }
+            return HttpResponse::BadRequest().json(vec![TransactError { index: positions[index], error: e.to_string() }]);
+        }
+    }
+    drop(pool);
+    let mut ledger = state.spend_ledger.lock().unwrap();
+    for (payment, tx) in payments.iter().zip(&transactions) {
+        ledger.record(&policies, payment, &tx.id, now);
+    }
+    drop(ledger);
+
+    for (req, tx) in reqs.iter().zip(&transactions) {
+        state.to_p2p.send(P2pMessage::Transaction(tx.clone())).unwrap();
+        if let Some(subscription_id) = req.subscription_id.clone() {
+            state.hub.do_send(WatchTransaction { txid: tx.id.clone(), subscription_id });
+        }
+        state.hub.do_send(TrackTransaction { txid: tx.id.clone(), expiry_height: tx.expiry_height });
+    }
+    let submitted: Vec<Submitted> = transactions.iter().map(|tx| Submitted { tracking_id: &tx.id, tx }).collect();
+    HttpResponse::Ok().json(submitted)
+}
+
+/// Rebuilds a wallet from a hex-encoded private key supplied by a client.
+pub fn wallet_from_private_key(private_key: &str) -> Result<Wallet, String> {
+    let private_key_bytes = match hex::decode(private_key) {
         Ok(bytes) => bytes,
-        Err(_) => return HttpResponse::BadRequest().body("Invalid private key format"),
+        Err(_) => return Err("Invalid private key format".to_string()),
     };
 
     let private_key_array: [u8; 32] = match private_key_bytes.try_into() {
         Ok(arr) => arr,
-        Err(_) => return HttpResponse::BadRequest().body("Invalid private key length"),
+        Err(_) => return Err("Invalid private key length".to_string()),
     };
 
-    let signing_key = SigningKey::from_bytes(&private_key_array);
+    Ok(Wallet { signing_key: SigningKey::from_bytes(&private_key_array) })
+}
 
-    let sender_wallet = Wallet { signing_key };
-    let sender_address = sender_wallet.get_address();
+#[derive(Deserialize)]
+pub struct CpfpRequest {
+    parent_txid: String,
+    parent_vout: usize,
+    private_key: String,
+    fee_per_byte: u64,
+}
 
-    let blockchain = blockchain.lock().unwrap();
-    let utxos = blockchain.get_utxos(&sender_address);
-
-    let mut inputs = vec![];
-    let mut accumulated = 0;
-    for (txid, vout, utxo) in utxos {
-        inputs.push(TxInput {
-            txid,
-            vout,
-            script_sig: String::new(),
-            pub_key: String::new(),
-            sequence: 0,
-        });
-        accumulated += utxo.value;
-        if accumulated >= req.amount {
-            break;
+/// Bumps the fee of a stuck mempool transaction by spending one of its outputs
+/// in a child that pays a high fee (child-pays-for-parent).
+#[post("/transact/cpfp")]
+pub async fn transact_cpfp(
+    req: web::Json<CpfpRequest>,
+    state: web::Data<AppState>,
+) -> impl Responder {
+    let wallet = match wallet_from_private_key(&req.private_key) {
+        Ok(wallet) => wallet,
+        Err(e) => return HttpResponse::BadRequest().body(e),
+    };
+    let address = wallet.get_address();
+    let policies = wallet_policies(&state);
+    let now = Utc::now().timestamp();
+
+    let blockchain = state.blockchain.lock().unwrap();
+    let mut pool = state.tx_pool.lock().unwrap();
+
+    let Some(parent) = pool.get(&req.parent_txid) else {
+        return HttpResponse::NotFound().body("Parent transaction not found in mempool");
+    };
+    let Some(parent_output) = parent.outputs.get(req.parent_vout) else {
+        return HttpResponse::BadRequest().body("Parent output does not exist");
+    };
+    if !parent_output.script_pub_key.pays_to(&address) {
+        return HttpResponse::BadRequest().body("Parent output is not owned by this key");
+    }
+    if pool.is_spent(&(req.parent_txid.clone(), req.parent_vout)) {
+        return HttpResponse::Conflict().body("Parent output is already spent in the mempool");
+    }
+    let value = parent_output.value;
+
+    // The fee depends on the child's size, which in turn depends on the fee,
+    // so raise it until the signed child pays at least the requested rate.
+    let mut fee = 0;
+    let child = loop {
+        if fee >= value {
+            return HttpResponse::BadRequest().body("Parent output is too small to pay the requested fee");
         }
+        let mut child = Transaction::new(
+            vec![TxInput {
+                txid: req.parent_txid.clone(),
+                vout: req.parent_vout,
+                script_sig: UnlockingScript::default(),
+                sequence: 0,
+            }],
+            vec![TxOutput {
+                value: value - fee,
+                script_pub_key: address.clone().into(),
+            }],
+        );
+        child.sign(&wallet);
+        let required = child.size() as u64 * req.fee_per_byte;
+        if required <= fee {
+            break child;
+        }
+        fee = required;
+    };
+
+    // The child's fee leaves the wallet like any payment, and can't wait for
+    // approval either.
+    let payment = Payment::fee(vec![address], fee);
+    let mut ledger = state.spend_ledger.lock().unwrap();
+    if let Some((_, error)) = ledger.check(&policies, std::slice::from_ref(&payment), now).pop() {
+        return spend_refused(error);
+    }
+    if let Err(e) = pool.submit(child.clone(), &blockchain) {
+        return HttpResponse::BadRequest().body(e.to_string());
     }
+    ledger.record(&policies, &payment, &child.id, now);
+    drop(ledger);
+    state.to_p2p.send(P2pMessage::Transaction(child.clone())).unwrap();
+    let package_fee_rate = pool.compute_package_fee_rate(&child.id, &blockchain);
+
+    HttpResponse::Ok().json(serde_json::json!({
+        "transaction": child,
+        "package_fee_rate": package_fee_rate,
+    }))
+}
 
-    if accumulated < req.amount {
-        return HttpResponse::BadRequest().body("Not enough funds");
+#[derive(Deserialize)]
+pub struct BumpFeeRequest {
+    txid: String,
+    /// The total fee the replacement pays, in base units.
+    new_fee: u64,
+}
+
+/// Bumps the fee of a stuck mempool transaction spending the node's own
+/// wallets by replacing it with a rebuilt, re-signed one (replace-by-fee).
+/// The replaced transaction and its descendants leave the mempool.
+#[post("/transact/bump")]
+pub async fn transact_bump(
+    req: web::Json<BumpFeeRequest>,
+    state: web::Data<AppState>,
+) -> impl Responder {
+    let mut wallets = vec![Wallet::clone(&state.miner_wallet)];
+    wallets.extend(
+        state.key_store.lock().unwrap().wallets().iter().filter_map(|stored| wallet_from_private_key(&stored.private_key).ok()),
+    );
+
+    let policies = wallet_policies(&state);
+    let now = Utc::now().timestamp();
+
+    let blockchain = state.blockchain.lock().unwrap();
+    let mut pool = state.tx_pool.lock().unwrap();
+    let replacement = match pool.build_fee_bump(&req.txid, req.new_fee, &wallets, &blockchain) {
+        Ok(replacement) => replacement,
+        Err(e @ FeeBumpError::NotPending { .. }) => return HttpResponse::NotFound().body(e.to_string()),
+        Err(e) => return HttpResponse::BadRequest().body(e.to_string()),
+    };
+
+    // Only the fee on top of what the original paid is new spending, and it
+    // comes from every wallet the replacement spends from.
+    let old_fee = pool.get(&req.txid).and_then(|original| pool.fee(original, &blockchain)).unwrap_or(0);
+    let mut payers: Vec<String> = Vec::new();
+    for input in &replacement.inputs {
+        if let Some(output) = pool.spent_output(input, &blockchain)
+            && !payers.iter().any(|payer| payer == output.script_pub_key.address())
+        {
+            payers.push(output.script_pub_key.address().to_string());
+        }
+    }
+    let payment = Payment::fee(payers, req.new_fee.saturating_sub(old_fee));
+    let mut ledger = state.spend_ledger.lock().unwrap();
+    if let Some((_, error)) = ledger.check(&policies, std::slice::from_ref(&payment), now).pop() {
+        return spend_refused(error);
     }
+    let replaced = match pool.submit(replacement.clone(), &blockchain) {
+        Ok(replaced) => replaced,
+        Err(e) => return HttpResponse::BadRequest().body(e.to_string()),
+    };
+    ledger.record(&policies, &payment, &replacement.id, now);
+    drop(ledger);
+    drop(pool);
+
+    state.to_p2p.send(P2pMessage::Transaction(replacement.clone())).unwrap();
+    let replaced: Vec<String> = replaced.into_iter().map(|replaced| replaced.id).collect();
+    state.hub.do_send(TransactionReplaced { replaced: replaced.clone(), replacement: replacement.id.clone() });
+    state.hub.do_send(TrackTransaction { txid: replacement.id.clone(), expiry_height: replacement.expiry_height });
+
+    HttpResponse::Ok().json(serde_json::json!({
+        "transaction": Submitted { tracking_id: &replacement.id, tx: &replacement },
+        "replaced": replaced,
+    }))
+}
 
-    let mut outputs = vec![TxOutput {
-        value: req.amount,
-        script_pub_key: req.to.clone(),
-    }];
+/// A transaction posted to `/tx/decode` or `/tx/raw`: either its JSON form
+/// or its hex-encoded bincode.
+#[derive(Deserialize)]
+#[serde(untagged)]
+pub enum RawTransaction {
+    Hex { hex: String },
+    Transaction(Transaction),
+}
 
-    if accumulated > req.amount {
-        outputs.push(TxOutput {
-            value: accumulated - req.amount,
-            script_pub_key: sender_address,
-        });
+impl RawTransaction {
+    pub fn into_transaction(self) -> Result<Transaction, String> {
+        match self {
+            RawTransaction::Transaction(tx) => Ok(tx),
+            RawTransaction::Hex { hex } => {
+                let bytes = hex::decode(hex.trim()).map_err(|_| "hex is not valid hex".to_string())?;
+                Transaction::from_bincode(&bytes).map_err(|e| format!("Invalid transaction encoding: {}", e))
+            }
+        }
     }
+}
 
-    let mut new_tx = Transaction::new(inputs, outputs);
-    new_tx.sign(&sender_wallet);
+#[derive(Serialize)]
+pub struct DecodedInput {
+    txid: String,
+    vout: usize,
+    coinbase: bool,
+    /// The value and owner of the spent output, if it is unspent on chain or
+    /// created by a pooled transaction.
+    value: Option<u64>,
+    address: Option<String>,
+    /// Unknown when the spent output can't be found.
+    signature_valid: Option<bool>,
+    error: Option<String>,
+}
 
-    if !new_tx.verify() {
-        return HttpResponse::InternalServerError().body("Failed to verify new transaction");
+#[derive(Serialize)]
+pub struct DecodedOutput {
+    value: u64,
+    address: String,
+}
+
+/// Finds the block confirming `txid` and the transaction's position in it,
+/// through the transaction index.
+fn find_confirmed<'a>(state: &AppState, blockchain: &'a Blockchain, txid: &str) -> Result<Option<(&'a Block, usize)>, HttpResponse> {
+    let mut tx_index = state.tx_index.lock().unwrap();
+    match tx_index.sync(&blockchain.chain).and_then(|()| tx_index.lookup(txid)) {
+        Ok(location) => Ok(location.and_then(|location| {
+            blockchain.chain.get(location.height as usize).map(|block| (block, location.position))
+        })),
+        Err(e) => {
+            tracing::error!("Failed to read the transaction index: {}", e);
+            Err(HttpResponse::InternalServerError().body("Transaction index is unavailable"))
+        }
     }
+}
 
-    p2p_sender.send(P2pMessage::Transaction(new_tx.clone())).unwrap();
+/// Looks up a confirmed transaction through the transaction index. Unknown
+/// txids are usually answered from its bloom filter without reading the disk.
+#[get("/transaction/{txid}")]
+pub async fn get_transaction(txid: web::Path<String>, state: web::Data<AppState>) -> impl Responder {
+    let blockchain = state.blockchain.lock().unwrap();
+    let (block, position) = match find_confirmed(&state, &blockchain, &txid) {
+        Ok(Some(found)) => found,
+        Ok(None) => return HttpResponse::NotFound().body("Transaction not found"),
+        Err(response) => return response,
+    };
+    HttpResponse::Ok().json(serde_json::json!({
+        "txid": txid.as_str(),
+        "block_index": block.index,
+        "block_hash": block.hash,
+        "position": position,
+        "confirmations": blockchain.chain.len() as u64 - block.index,
+        "transaction": block.transactions.get(position),
+    }))
+}
 
-    let mut pool = tx_pool.lock().unwrap();
-    pool.push(new_tx.clone());
+#[derive(Serialize)]
+struct ResolvedInput {
+    txid: String,
+    vout: usize,
+    /// The value and owner of the spent output; `null` for the coinbase.
+    value: Option<u64>,
+    address: Option<String>,
+}
 
-    HttpResponse::Ok().json(new_tx)
+/// Finds the output `input` spends on the active chain, whether or not it
+/// has been spent since: in an earlier transaction of `block`, or through
+/// the transaction index in an earlier block.
+fn resolve_spent_output<'a>(
+    state: &AppState,
+    blockchain: &'a Blockchain,
+    block: &'a Block,
+    input: &TxInput,
+) -> Result<Option<&'a TxOutput>, HttpResponse> {
+    let funding = match block.transactions.iter().find(|tx| tx.id == input.txid) {
+        Some(tx) => Some(tx),
+        None => find_confirmed(state, blockchain, &input.txid)?.and_then(|(block, position)| block.transactions.get(position)),
+    };
+    Ok(funding.and_then(|tx| tx.outputs.get(input.vout)))
+}
+
+/// Lists a block's transactions with the value and address of every input
+/// resolved from the chain, their totals and fee, so explorers needn't look
+/// up each spent output themselves.
+#[get("/blocks/{index}/transactions")]
+pub async fn get_block_transactions(index: web::Path<u64>, state: web::Data<AppState>) -> impl Responder {
+    let blockchain = state.blockchain.lock().unwrap();
+    let Some(block) = blockchain.chain.get(index.into_inner() as usize) else {
+        return HttpResponse::NotFound().body("Block not found");
+    };
+    let mut transactions = Vec::with_capacity(block.transactions.len());
+    for (position, tx) in block.transactions.iter().enumerate() {
+        let coinbase = tx.is_coinbase();
+        let mut inputs = Vec::with_capacity(tx.inputs.len());
+        for input in &tx.inputs {
+            let spent = if coinbase {
+                None
+            } else {
+                match resolve_spent_output(&state, &blockchain, block, input) {
+                    Ok(spent) => spent,
+                    Err(response) => return response,
+                }
+            };
+            inputs.push(ResolvedInput {
+                txid: input.txid.clone(),
+                vout: input.vout,
+                value: spent.map(|output| output.value),
+                address: spent.map(|output| output.script_pub_key.address().to_string()),
+            });
+        }
+        let outputs: Vec<DecodedOutput> = tx
+            .outputs
+            .iter()
+            .map(|output| DecodedOutput { value: output.value, address: output.script_pub_key.address().to_string() })
+            .collect();
+        let total_in: Option<u64> = if coinbase { None } else { inputs.iter().map(|input| input.value).sum() };
+        let total_out: u64 = outputs.iter().map(|output| output.value).sum();
+        transactions.push(serde_json::json!({
+            "id": tx.id,
+            "position": position,
+            "coinbase": coinbase,
+            "timestamp": tx.timestamp,
+            "expiry_height": tx.expiry_height,
+            "memo": tx.memo,
+            "size": tx.size(),
+            "inputs": inputs,
+            "outputs": outputs,
+            "total_in": total_in,
+            "total_out": total_out,
+            "fee": total_in.map(|total_in| total_in.saturating_sub(total_out)),
+        }));
+    }
+    HttpResponse::Ok().json(serde_json::json!({
+        "block_index": block.index,
+        "block_hash": block.hash,
+        "transactions": transactions,
+    }))
+}
+
+/// Submits a transaction built and signed elsewhere, such as by `tx sign`.
+/// It goes through every check of `MempoolPool::submit_checked`; a rejection
+/// is a 400 whose `details` name the failed check.
+#[post("/tx/raw")]
+pub async fn submit_raw_transaction(req: web::Json<RawTransaction>, state: web::Data<AppState>) -> impl Responder {
+    let tx = match req.into_inner().into_transaction() {
+        Ok(tx) => tx,
+        Err(e) => return HttpResponse::BadRequest().json(serde_json::json!({ "error": e })),
+    };
+    let blockchain = state.blockchain.lock().unwrap();
+    let mut pool = state.tx_pool.lock().unwrap();
+    let (fee, replaced) = match pool.submit_checked(tx.clone(), &blockchain) {
+        Ok(submitted) => submitted,
+        Err(e) => return HttpResponse::BadRequest().json(serde_json::json!({ "error": e.to_string(), "details": e })),
+    };
+    state.to_p2p.send(P2pMessage::Transaction(tx.clone())).unwrap();
+    state.hub.do_send(TrackTransaction { txid: tx.id.clone(), expiry_height: tx.expiry_height });
+    let replaced: Vec<String> = replaced.into_iter().map(|replaced| replaced.id).collect();
+    if !replaced.is_empty() {
+        state.hub.do_send(TransactionReplaced { replaced: replaced.clone(), replacement: tx.id.clone() });
+    }
+
+    HttpResponse::Ok().json(serde_json::json!({
+        "txid": tx.id,
+        "tracking_id": tx.id,
+        "fee": fee,
+        "size": tx.size(),
+        "fee_per_byte": fee as f64 / tx.size() as f64,
+        "mempool_size": pool.len(),
+        "replaced": replaced,
+    }))
+}
+
+/// How `GET /tx/{txid}/raw` and `tx sign` encode a transaction.
+#[derive(Deserialize, Default, Clone, Copy, PartialEq, Debug, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum RawFormat {
+    #[default]
+    Hex,
+    Json,
+}
+
+#[derive(Deserialize)]
+pub struct RawQuery {
+    #[serde(default)]
+    format: RawFormat,
+}
+
+impl RawFormat {
+    /// Encodes `tx` in a form `POST /tx/raw` accepts: `{ "hex" }` with its
+    /// bincode, or its JSON.
+    pub fn encode(self, tx: &Transaction) -> serde_json::Value {
+        match self {
+            RawFormat::Hex => serde_json::json!({ "hex": hex::encode(tx.to_bincode()) }),
+            RawFormat::Json => serde_json::to_value(tx).unwrap(),
+        }
+    }
+}
+
+/// Returns a pending or confirmed transaction in the form `POST /tx/raw`
+/// takes, so it can be resubmitted to another node as is.
+#[get("/tx/{txid}/raw")]
+pub async fn get_raw_transaction(
+    txid: web::Path<String>,
+    query: web::Query<RawQuery>,
+    state: web::Data<AppState>,
+) -> impl Responder {
+    let blockchain = state.blockchain.lock().unwrap();
+    if let Some(tx) = state.tx_pool.lock().unwrap().get(&txid) {
+        return HttpResponse::Ok().json(query.format.encode(tx));
+    }
+    match find_confirmed(&state, &blockchain, &txid) {
+        Ok(Some((block, position))) => match block.transactions.get(position) {
+            Some(tx) => HttpResponse::Ok().json(query.format.encode(tx)),
+            None => HttpResponse::NotFound().body("Transaction not found"),
+        },
+        Ok(None) => HttpResponse::NotFound().body("Transaction not found"),
+        Err(response) => response,
+    }
+}
+
+/// Breaks a raw transaction down for inspection without submitting it.
+#[post("/tx/decode")]
+pub async fn decode_transaction(req: web::Json<RawTransaction>, state: web::Data<AppState>) -> impl Responder {
+    let tx = match req.into_inner().into_transaction() {
+        Ok(tx) => tx,
+        Err(e) => return HttpResponse::BadRequest().body(e),
+    };
+    let blockchain = state.blockchain.lock().unwrap();
+    let pool = state.tx_pool.lock().unwrap();
+
+    let coinbase = tx.is_coinbase();
+    let checks = tx.verify_detailed(|input| pool.spent_output(input, &blockchain).map(|output| &output.script_pub_key));
+    let inputs: Vec<DecodedInput> = tx
+        .inputs
+        .iter()
+        .zip(checks)
+        .map(|(input, check)| {
+            let spent = if coinbase { None } else { pool.spent_output(input, &blockchain) };
+            let (signature_valid, error) = match check {
+                Ok(()) => (Some(true), None),
+                Err(e @ TxError::UnknownInput { .. }) => (None, Some(e.to_string())),
+                Err(e) => (Some(false), Some(e.to_string())),
+            };
+            DecodedInput {
+                txid: input.txid.clone(),
+                vout: input.vout,
+                coinbase,
+                value: spent.map(|output| output.value),
+                address: spent.map(|output| output.script_pub_key.address().to_string()),
+                signature_valid,
+                error,
+            }
+        })
+        .collect();
+    let outputs: Vec<DecodedOutput> = tx
+        .outputs
+        .iter()
+        .map(|output| DecodedOutput { value: output.value, address: output.script_pub_key.address().to_string() })
+        .collect();
+
+    let total_in: Option<u64> = if coinbase { None } else { inputs.iter().map(|input| input.value).sum() };
+    let total_out: u64 = outputs.iter().map(|output| output.value).sum();
+    let id = tx.calculate_hash();
+    HttpResponse::Ok().json(serde_json::json!({
+        "id": id,
+        "id_matches": id == tx.id,
+        "coinbase": coinbase,
+        "timestamp": tx.timestamp,
+        "expiry_height": tx.expiry_height,
+        "size": tx.size(),
+        "hex": hex::encode(tx.to_bincode()),
+        "inputs": inputs,
+        "outputs": outputs,
+        "total_in": total_in,
+        "total_out": total_out,
+        "fee": total_in.map(|total_in| total_in.saturating_sub(total_out)),
+        "signatures_valid": inputs.iter().all(|input| input.signature_valid == Some(true)),
+    }))
 }
 
 #[derive(Serialize)]
@@ -249,6 +1554,44 @@ struct WalletInfoResponse {
     address: String,
 }
 
+#[derive(Deserialize)]
+pub struct FilterBlockRequest {
+    address: String,
+    block: Block,
+}
+
+#[derive(Serialize)]
+pub struct RelevantTransaction {
+    #[serde(flatten)]
+    activity: AddressActivity,
+    transaction: Transaction,
+}
+
+/// Picks out the transactions in a block that pay or spend from an address,
+/// with the net change each makes to its balance, so thin clients needn't
+/// scan every output themselves.
+#[post("/wallet/filter-block")]
+pub async fn filter_block(req: web::Json<FilterBlockRequest>, state: web::Data<AppState>) -> impl Responder {
+    let FilterBlockRequest { address, block } = req.into_inner();
+    let blockchain = state.blockchain.lock().unwrap();
+    let transactions: Vec<RelevantTransaction> = block
+        .transactions
+        .iter()
+        .filter_map(|tx| {
+            let activity = blockchain.address_activity(tx, &address, &block.transactions)?;
+            Some(RelevantTransaction { activity, transaction: tx.clone() })
+        })
+        .collect();
+    let net_change: i64 = transactions.iter().map(|relevant| relevant.activity.net_change).sum();
+    HttpResponse::Ok().json(serde_json::json!({
+        "address": address,
+        "block_index": block.index,
+        "block_hash": block.hash,
+        "net_change": net_change,
+        "transactions": transactions,
+    }))
+}
+
 #[post("/wallet")]
 pub async fn create_wallet() -> impl Responder {
     let wallet = Wallet::new();
@@ -0,0 +1,98 @@
+use actix_web::{get, web, HttpResponse, Responder};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+use crate::api::state::AppState;
+
+/// The saved chain was read, or there was none to read.
+pub const CHAIN_LOADED: &str = "chain_loaded";
+/// The P2P task is still running or, behind `--node-url`, the node is
+/// reachable.
+pub const P2P_RUNNING: &str = "p2p_running";
+/// The data directory accepted a test write at startup.
+pub const STORAGE_WRITABLE: &str = "storage_writable";
+/// The UTXO index isn't being rebuilt.
+pub const INDEX_READY: &str = "index_ready";
+
+/// The node hasn't refused a reorg deeper than `--max-reorg-depth` since
+/// it started or an operator last cleared safe mode.
+pub const SAFE_MODE_OFF: &str = "safe_mode_off";
+
+/// The checks `/health/ready` requires to pass.
+pub const READINESS_CHECKS: [&str; 5] = [CHAIN_LOADED, P2P_RUNNING, STORAGE_WRITABLE, INDEX_READY, SAFE_MODE_OFF];
+
+/// The latest status each subsystem reported for its readiness check. A
+/// check that hasn't been reported yet counts as failing.
+#[derive(Debug)]
+pub struct HealthRegistry {
+    checks: Mutex<BTreeMap<&'static str, Result<(), String>>>,
+}
+
+pub type SharedHealth = Arc<HealthRegistry>;
+
+impl Default for HealthRegistry {
+    fn default() -> Self {
+        let checks = READINESS_CHECKS.iter().map(|check| (*check, Err("Not reported yet".to_string()))).collect();
+        Self { checks: Mutex::new(checks) }
+    }
+}
+
+impl HealthRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records the current status of `check`.
+    pub fn report(&self, check: &'static str, status: Result<(), String>) {
+        if let Err(reason) = &status {
+            tracing::warn!("Health check {} failing: {}", check, reason);
+        }
+        self.checks.lock().unwrap().insert(check, status);
+    }
+
+    /// Returns true if `check` is failing.
+    pub fn is_failing(&self, check: &str) -> bool {
+        self.checks.lock().unwrap().get(check).is_some_and(Result::is_err)
+    }
+
+    /// Returns the failing checks with the reason each gave.
+    pub fn failing(&self) -> Vec<(&'static str, String)> {
+        self.checks
+            .lock()
+            .unwrap()
+            .iter()
+            .filter_map(|(check, status)| status.as_ref().err().map(|reason| (*check, reason.clone())))
+            .collect()
+    }
+}
+
+/// Checks that files can be created in `dir` by writing and removing a
+/// small probe file.
+pub fn probe_writable(dir: &Path) -> Result<(), String> {
+    let probe = dir.join(".sierpchain-write-probe");
+    fs::write(&probe, b"ok").and_then(|_| fs::remove_file(&probe)).map_err(|e| {
+        format!("Can't write to {}: {}", dir.display(), e)
+    })
+}
+
+/// Answers as long as the HTTP server does.
+#[get("/health/live")]
+pub async fn health_live() -> impl Responder {
+    HttpResponse::Ok().json(serde_json::json!({ "status": "live" }))
+}
+
+/// Answers 200 if every readiness check passes, or 503 listing those that fail.
+#[get("/health/ready")]
+pub async fn health_ready(state: web::Data<AppState>) -> impl Responder {
+    let failing = state.health.failing();
+    if failing.is_empty() {
+        return HttpResponse::Ok().json(serde_json::json!({ "status": "ready" }));
+    }
+    let failing: Vec<serde_json::Value> = failing
+        .into_iter()
+        .map(|(check, reason)| serde_json::json!({ "check": check, "reason": reason }))
+        .collect();
+    HttpResponse::ServiceUnavailable().json(serde_json::json!({ "status": "unavailable", "failing": failing }))
+}
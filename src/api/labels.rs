@@ -0,0 +1,35 @@
+use actix_web::{get, post, web, HttpResponse, Responder};
+use serde::Deserialize;
+
+use crate::api::state::AppState;
+use crate::core::labels::check_label;
+
+#[derive(Deserialize)]
+pub struct LabelRequest {
+    label: String,
+}
+
+/// Labels a transaction, for the node's user only: labels are kept in
+/// `labels.json` and never sent to peers.
+#[post("/labels/{txid}")]
+pub async fn set_label(txid: web::Path<String>, body: web::Json<LabelRequest>, state: web::Data<AppState>) -> impl Responder {
+    if let Err(e) = check_label(&body.label) {
+        return HttpResponse::BadRequest().json(serde_json::json!({ "error": e }));
+    }
+    let mut labels = state.labels.lock().unwrap();
+    labels.set(&txid, body.into_inner().label);
+    if let Err(e) = labels.save() {
+        tracing::error!("Failed to save labels to {}: {}", labels.path().display(), e);
+        return HttpResponse::InternalServerError().json(serde_json::json!({ "error": "Failed to save labels" }));
+    }
+    HttpResponse::Ok().json(serde_json::json!({ "txid": txid.as_str(), "label": labels.get(&txid) }))
+}
+
+/// Returns the label given to a transaction.
+#[get("/labels/{txid}")]
+pub async fn get_label(txid: web::Path<String>, state: web::Data<AppState>) -> impl Responder {
+    match state.labels.lock().unwrap().get(&txid) {
+        Some(label) => HttpResponse::Ok().json(serde_json::json!({ "txid": txid.as_str(), "label": label })),
+        None => HttpResponse::NotFound().json(serde_json::json!({ "error": "This transaction has no label" })),
+    }
+}
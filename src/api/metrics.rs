@@ -0,0 +1,161 @@
+use actix_web::body::{BodySize, BoxBody, MessageBody};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::middleware::Next;
+use actix_web::web::Bytes;
+use actix_web::{get, web, Error, HttpResponse, Responder};
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::task::{ready, Context, Poll};
+
+use crate::api::state::AppState;
+
+/// The route of requests no resource matched, such as 404s.
+pub const UNMATCHED_ROUTE: &str = "unmatched";
+
+/// The response body bytes of one route, counted as they are sent.
+#[derive(Debug, Default)]
+pub struct RouteBytes {
+    /// As the handlers wrote them.
+    pub uncompressed: AtomicU64,
+    /// As they went out, after `Compress`.
+    pub sent: AtomicU64,
+}
+
+impl RouteBytes {
+    fn add(&self, stage: Stage, bytes: usize) {
+        let counter = match stage {
+            Stage::Uncompressed => &self.uncompressed,
+            Stage::Sent => &self.sent,
+        };
+        counter.fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+}
+
+/// Response body sizes by route pattern, such as `/blocks/{index}`, so that
+/// `/metrics` shows what compression saves on each endpoint.
+#[derive(Debug, Default)]
+pub struct ResponseSizes {
+    routes: Mutex<BTreeMap<String, Arc<RouteBytes>>>,
+}
+
+pub type SharedResponseSizes = Arc<ResponseSizes>;
+
+impl ResponseSizes {
+    /// The counters of the route with the given pattern, created at zero.
+    pub fn route(&self, pattern: &str) -> Arc<RouteBytes> {
+        Arc::clone(self.routes.lock().unwrap().entry(pattern.to_string()).or_default())
+    }
+
+    /// The uncompressed and sent bytes of every route seen so far.
+    pub fn totals(&self) -> Vec<(String, u64, u64)> {
+        self.routes
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(route, bytes)| (route.clone(), bytes.uncompressed.load(Ordering::Relaxed), bytes.sent.load(Ordering::Relaxed)))
+            .collect()
+    }
+}
+
+/// Which side of `Compress` a body is counted on.
+#[derive(Debug, Clone, Copy)]
+enum Stage {
+    Uncompressed,
+    Sent,
+}
+
+/// A response body that adds each chunk to its route's counter as it is
+/// polled, so a stream is counted while it runs rather than when it ends.
+struct CountingBody {
+    body: BoxBody,
+    route: Option<Arc<RouteBytes>>,
+    stage: Stage,
+}
+
+impl MessageBody for CountingBody {
+    type Error = Box<dyn std::error::Error>;
+
+    fn size(&self) -> BodySize {
+        self.body.size()
+    }
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Result<Bytes, Self::Error>>> {
+        let chunk = ready!(Pin::new(&mut self.body).poll_next(cx));
+        if let (Some(Ok(bytes)), Some(route)) = (&chunk, &self.route) {
+            route.add(self.stage, bytes.len());
+        }
+        Poll::Ready(chunk)
+    }
+
+    fn try_into_bytes(self) -> Result<Bytes, Self> {
+        let CountingBody { body, route, stage } = self;
+        match body.try_into_bytes() {
+            Ok(bytes) => {
+                if let Some(route) = route {
+                    route.add(stage, bytes.len());
+                }
+                Ok(bytes)
+            }
+            Err(body) => Err(CountingBody { body, route, stage }),
+        }
+    }
+}
+
+async fn count_bytes(
+    req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+    stage: Stage,
+) -> Result<ServiceResponse<impl MessageBody>, Error> {
+    let route = req.app_data::<web::Data<AppState>>().map(|state| {
+        let pattern = req.match_pattern();
+        state.response_sizes.route(pattern.as_deref().unwrap_or(UNMATCHED_ROUTE))
+    });
+    let res = next.call(req).await?;
+    Ok(res.map_body(move |_, body| CountingBody { body: body.boxed(), route, stage }))
+}
+
+/// Counts response bodies as the handlers wrote them. Wrap it inside
+/// `Compress`.
+pub async fn count_uncompressed_bytes(
+    req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<impl MessageBody>, Error> {
+    count_bytes(req, next, Stage::Uncompressed).await
+}
+
+/// Counts response bodies as they are sent. Wrap it outside `Compress`.
+pub async fn count_sent_bytes(
+    req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<impl MessageBody>, Error> {
+    count_bytes(req, next, Stage::Sent).await
+}
+
+/// Serves the node's counters in the Prometheus text format.
+#[get("/metrics")]
+pub async fn get_metrics(state: web::Data<AppState>) -> impl Responder {
+    let mut body = String::new();
+    let counter = |body: &mut String, name: &str, help: &str| {
+        writeln!(body, "# HELP {} {}", name, help).unwrap();
+        writeln!(body, "# TYPE {} counter", name).unwrap();
+    };
+
+    counter(&mut body, "sierpchain_http_response_bytes_total", "Response body bytes by route, before and after compression.");
+    for (route, uncompressed, sent) in state.response_sizes.totals() {
+        writeln!(body, "sierpchain_http_response_bytes_total{{route=\"{}\",stage=\"uncompressed\"}} {}", route, uncompressed).unwrap();
+        writeln!(body, "sierpchain_http_response_bytes_total{{route=\"{}\",stage=\"sent\"}} {}", route, sent).unwrap();
+    }
+    counter(&mut body, "sierpchain_p2p_messages_decoded_total", "Gossip messages handed to the JSON decoder.");
+    writeln!(body, "sierpchain_p2p_messages_decoded_total {}", state.p2p_metrics.messages_decoded.load(Ordering::Relaxed)).unwrap();
+    counter(&mut body, "sierpchain_p2p_oversized_messages_rejected_total", "Gossip messages dropped for exceeding the size limit.");
+    writeln!(
+        body,
+        "sierpchain_p2p_oversized_messages_rejected_total {}",
+        state.p2p_metrics.oversized_messages_rejected.load(Ordering::Relaxed)
+    )
+    .unwrap();
+    HttpResponse::Ok().content_type("text/plain; version=0.0.4").body(body)
+}
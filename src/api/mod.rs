@@ -1,2 +1,14 @@
+pub mod admin;
+pub mod demo;
+pub mod faucet;
 pub mod handlers;
+pub mod embed;
+pub mod health;
+pub mod labels;
+pub mod metrics;
+pub mod nft;
+pub mod spending;
+pub mod state;
+pub mod stream;
+pub mod wallet;
 pub mod websocket;
@@ -0,0 +1,92 @@
+use actix_web::{get, post, web, HttpResponse, Responder};
+use serde::Deserialize;
+
+use crate::api::handlers::{check_built_transaction, wallet_from_private_key};
+use crate::api::state::AppState;
+use crate::blockchain::chain::BURN_ADDRESS;
+use crate::core::nft::{claim_fee, NftClaim, NftError};
+use crate::core::transaction::BuildTransactRequest;
+use crate::core::wallet::Wallet;
+use crate::fractal::FractalType;
+use crate::network::p2p::P2pMessage;
+
+#[derive(Deserialize)]
+pub struct NftClaimRequest {
+    fractal_type: FractalType,
+    /// The claiming wallet's hex-encoded private key. If omitted, the node's
+    /// own wallet claims the fractal.
+    #[serde(default)]
+    private_key: Option<String>,
+}
+
+/// Claims ownership of a fractal combination, burning the claim fee from
+/// the owner's balance and announcing the claim to peers.
+#[post("/nft/claim")]
+pub async fn claim_nft(req: web::Json<NftClaimRequest>, state: web::Data<AppState>) -> impl Responder {
+    let wallet = match &req.private_key {
+        Some(private_key) => match wallet_from_private_key(private_key) {
+            Ok(wallet) => wallet,
+            Err(e) => return HttpResponse::BadRequest().json(serde_json::json!({ "error": e })),
+        },
+        None => Wallet::clone(&state.miner_wallet),
+    };
+    let claim = NftClaim::new(req.fractal_type.clone(), &wallet);
+
+    let blockchain = state.blockchain.lock().unwrap();
+    let mut registry = state.nft_registry.lock().unwrap();
+    match registry.check(&claim, &blockchain) {
+        Ok(()) => {}
+        Err(e @ NftError::AlreadyClaimed { .. }) => {
+            return HttpResponse::Conflict().json(serde_json::json!({ "error": e.to_string() }));
+        }
+        Err(e) => return HttpResponse::BadRequest().json(serde_json::json!({ "error": e.to_string() })),
+    }
+
+    let mut pool = state.tx_pool.lock().unwrap();
+    let request = BuildTransactRequest {
+        wallets: vec![wallet],
+        to: BURN_ADDRESS.to_string(),
+        amount: claim_fee(blockchain.denomination),
+        fee: 0,
+        expiry_height: None,
+        memo: None,
+    };
+    // The balance check counts confirmed coins, some of which pending
+    // transactions may already spend.
+    let burn = match blockchain.build_transaction(&request, |outpoint| pool.is_spent(outpoint)) {
+        Ok(tx) => tx,
+        Err(e) => return HttpResponse::BadRequest().json(serde_json::json!({ "error": e.to_string() })),
+    };
+    if let Err(response) = check_built_transaction(&burn, &blockchain) {
+        return response;
+    }
+    if let Err(e) = pool.submit(burn.clone(), &blockchain) {
+        return HttpResponse::BadRequest().json(serde_json::json!({ "error": e.to_string() }));
+    }
+    registry.register(&claim);
+
+    state.to_p2p.send(P2pMessage::Transaction(burn.clone())).unwrap();
+    state.to_p2p.send(P2pMessage::NftClaim(claim.clone())).unwrap();
+    HttpResponse::Ok().json(serde_json::json!({
+        "fractal_hash": claim.fractal_hash(),
+        "owner_address": claim.owner_address,
+        "burn_txid": burn.id,
+    }))
+}
+
+#[derive(Deserialize)]
+pub struct NftOwnerQuery {
+    fractal_hash: String,
+}
+
+/// Looks up the owner of a claimed fractal by its hash.
+#[get("/nft/owner")]
+pub async fn get_nft_owner(query: web::Query<NftOwnerQuery>, state: web::Data<AppState>) -> impl Responder {
+    match state.nft_registry.lock().unwrap().owner(&query.fractal_hash) {
+        Some(owner_address) => HttpResponse::Ok().json(serde_json::json!({
+            "fractal_hash": query.fractal_hash,
+            "owner_address": owner_address,
+        })),
+        None => HttpResponse::NotFound().json(serde_json::json!({ "error": "This fractal has not been claimed" })),
+    }
+}
@@ -0,0 +1,187 @@
+use actix_web::{get, post, put, web, HttpRequest, HttpResponse, Responder};
+use chrono::Utc;
+use std::collections::HashMap;
+
+use crate::api::admin::authorize;
+use crate::api::handlers::{check_built_transaction, wallet_from_private_key};
+use crate::api::state::AppState;
+use crate::api::websocket::TrackTransaction;
+use crate::core::spend_policy::{Approval, ApprovalError, ApprovalStatus, Payment, SpendError, SpendPolicy};
+use crate::core::transaction::BuildTransactRequest;
+use crate::network::p2p::P2pMessage;
+
+/// The spend policies of the node's stored wallets. Read them before
+/// locking the chain, since `/wallet/import` takes the locks the other way
+/// round.
+pub fn wallet_policies(state: &AppState) -> HashMap<String, SpendPolicy> {
+    state.key_store.lock().unwrap().policies()
+}
+
+/// The 403 to answer a payment its wallet's policy refuses with.
+pub fn spend_refused(error: SpendError) -> HttpResponse {
+    HttpResponse::Forbidden().json(serde_json::json!({ "error": error.to_string(), "details": error }))
+}
+
+fn approval_error(error: ApprovalError) -> HttpResponse {
+    let body = serde_json::json!({ "error": error.to_string() });
+    match error {
+        ApprovalError::NotFound(_) => HttpResponse::NotFound().json(body),
+        ApprovalError::AlreadyDecided(_) => HttpResponse::Conflict().json(body),
+    }
+}
+
+/// Returns the spend policy of one of the node's stored wallets.
+#[get("/wallet/{address}/policy")]
+pub async fn get_spend_policy(req: HttpRequest, address: web::Path<String>, state: web::Data<AppState>) -> impl Responder {
+    if let Err(response) = authorize(&req, &state.admin_key) {
+        return response;
+    }
+    match wallet_policies(&state).remove(address.as_str()) {
+        Some(policy) => HttpResponse::Ok().json(policy),
+        None => HttpResponse::NotFound().json(serde_json::json!({ "error": "No stored wallet has that address" })),
+    }
+}
+
+/// Replaces the spend policy of one of the node's stored wallets.
+#[put("/wallet/{address}/policy")]
+pub async fn put_spend_policy(
+    req: HttpRequest,
+    address: web::Path<String>,
+    body: web::Json<SpendPolicy>,
+    state: web::Data<AppState>,
+) -> impl Responder {
+    if let Err(response) = authorize(&req, &state.admin_key) {
+        return response;
+    }
+    let mut key_store = state.key_store.lock().unwrap();
+    if !key_store.set_policy(&address, body.clone()) {
+        return HttpResponse::NotFound().json(serde_json::json!({ "error": "No stored wallet has that address" }));
+    }
+    if let Err(e) = key_store.save() {
+        tracing::error!("Failed to save wallets to {}: {}", key_store.path().display(), e);
+        return HttpResponse::InternalServerError().json(serde_json::json!({ "error": "Failed to save wallets" }));
+    }
+    HttpResponse::Ok().json(body.into_inner())
+}
+
+/// Lists every payment queued for approval, decided or not.
+#[get("/approvals")]
+pub async fn list_approvals(req: HttpRequest, state: web::Data<AppState>) -> impl Responder {
+    if let Err(response) = authorize(&req, &state.admin_key) {
+        return response;
+    }
+    HttpResponse::Ok().json(state.spend_ledger.lock().unwrap().approvals())
+}
+
+/// Builds, signs and broadcasts a pending payment with the keys of the
+/// wallets it spends from. It must still be within their limits.
+#[post("/approvals/{id}/approve")]
+pub async fn approve_payment(req: HttpRequest, id: web::Path<u64>, state: web::Data<AppState>) -> impl Responder {
+    if let Err(response) = authorize(&req, &state.admin_key) {
+        return response;
+    }
+    let id = id.into_inner();
+    let approval = match state.spend_ledger.lock().unwrap().approval(id) {
+        Some(approval) if approval.status == ApprovalStatus::Pending => approval.clone(),
+        Some(_) => return approval_error(ApprovalError::AlreadyDecided(id)),
+        None => return approval_error(ApprovalError::NotFound(id)),
+    };
+    let (policies, wallets) = {
+        let key_store = state.key_store.lock().unwrap();
+        let wallets: Result<Vec<_>, String> = approval
+            .from
+            .iter()
+            .map(|address| {
+                let stored = key_store.wallets().iter().find(|stored| stored.address == *address);
+                stored.ok_or_else(|| format!("{} is no longer a stored wallet", address)).and_then(|stored| wallet_from_private_key(&stored.private_key))
+            })
+            .collect();
+        (key_store.policies(), wallets)
+    };
+    let wallets = match wallets {
+        Ok(wallets) => wallets,
+        Err(e) => return HttpResponse::Conflict().json(serde_json::json!({ "error": e })),
+    };
+
+    let now = Utc::now().timestamp();
+    let blockchain = state.blockchain.lock().unwrap();
+    let request = BuildTransactRequest {
+        wallets,
+        to: approval.to.clone(),
+        amount: approval.amount,
+        fee: approval.fee,
+        expiry_height: approval.expire_after_blocks.map(|blocks| (blockchain.chain.len() as u64).saturating_add(blocks)),
+        memo: None,
+    };
+    let payment = Payment::from(&request);
+    let mut pool = state.tx_pool.lock().unwrap();
+    let mut ledger = state.spend_ledger.lock().unwrap();
+    // Approval waives the threshold, not the limits, which may have filled up since.
+    let mut breaches = ledger.check(&policies, std::slice::from_ref(&payment), now).into_iter().map(|(_, error)| error);
+    if let Some(error) = breaches.find(|error| !matches!(error, SpendError::NeedsApproval { .. })) {
+        return spend_refused(error);
+    }
+    let tx = match blockchain.build_transaction(&request, |outpoint| pool.is_spent(outpoint)) {
+        Ok(tx) => tx,
+        Err(e) => return HttpResponse::BadRequest().json(serde_json::json!({ "error": e.to_string() })),
+    };
+    if let Err(response) = check_built_transaction(&tx, &blockchain) {
+        return response;
+    }
+    if let Err(e) = pool.submit(tx.clone(), &blockchain) {
+        return HttpResponse::BadRequest().json(serde_json::json!({ "error": e.to_string() }));
+    }
+    let approval = match ledger.decide(id, ApprovalStatus::Approved { txid: tx.id.clone(), decided_at: now }) {
+        Ok(approval) => approval,
+        Err(e) => {
+            pool.remove(&tx.id);
+            return approval_error(e);
+        }
+    };
+    ledger.record(&policies, &payment, &tx.id, now);
+    state.to_p2p.send(P2pMessage::Transaction(tx.clone())).unwrap();
+    state.hub.do_send(TrackTransaction { txid: tx.id.clone(), expiry_height: tx.expiry_height });
+    HttpResponse::Ok().json(serde_json::json!({ "approval": approval, "transaction": tx, "tracking_id": tx.id }))
+}
+
+/// Drops a pending payment without sending it.
+#[post("/approvals/{id}/reject")]
+pub async fn reject_payment(req: HttpRequest, id: web::Path<u64>, state: web::Data<AppState>) -> impl Responder {
+    if let Err(response) = authorize(&req, &state.admin_key) {
+        return response;
+    }
+    let decided = ApprovalStatus::Rejected { decided_at: Utc::now().timestamp() };
+    match state.spend_ledger.lock().unwrap().decide(id.into_inner(), decided) {
+        Ok(approval) => HttpResponse::Ok().json(approval),
+        Err(e) => approval_error(e),
+    }
+}
+
+/// Queues a `/transact` payment over its wallets' approval threshold,
+/// answering 202 with the approval. Every wallet it spends from must be
+/// stored, so that their keys can sign it once approved.
+pub fn queue_for_approval(
+    state: &AppState,
+    policies: &HashMap<String, SpendPolicy>,
+    request: &BuildTransactRequest,
+    expire_after_blocks: Option<u64>,
+    now: i64,
+) -> HttpResponse {
+    let payment = Payment::from(request);
+    if let Some(address) = payment.from.iter().find(|address| !policies.contains_key(*address)) {
+        return HttpResponse::Forbidden().json(serde_json::json!({
+            "error": format!("This payment needs approval, but {} is not a stored wallet", address),
+        }));
+    }
+    let approval = state.spend_ledger.lock().unwrap().request_approval(Approval {
+        id: 0,
+        from: payment.from,
+        to: request.to.clone(),
+        amount: request.amount,
+        fee: request.fee,
+        expire_after_blocks,
+        requested_at: now,
+        status: ApprovalStatus::Pending,
+    });
+    HttpResponse::Accepted().json(approval)
+}
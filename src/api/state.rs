@@ -0,0 +1,86 @@
+use actix::Addr;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex, OnceLock};
+use tokio::sync::mpsc;
+
+use crate::api::admin::{AdminKey, AllowChainOps};
+use crate::api::demo::DemoSwitch;
+use crate::api::embed::FrameAncestors;
+use crate::api::faucet::{FaucetConfig, SharedFaucetLedger};
+use crate::api::handlers::{LightNode, TransactionPool};
+use crate::api::health::SharedHealth;
+use crate::api::metrics::SharedResponseSizes;
+use crate::api::websocket::{ApiKey, BroadcastHub};
+use crate::blockchain::chain::Blockchain;
+use crate::blockchain::gc::SharedStorageGc;
+use crate::blockchain::storage::SharedBlockSizes;
+use crate::blockchain::txindex::SharedTxIndex;
+use crate::core::keystore::SharedKeyStore;
+use crate::core::labels::SharedLabelStore;
+use crate::core::nft::SharedNftRegistry;
+use crate::core::spend_policy::SharedSpendLedger;
+use crate::core::wallet::Wallet;
+use crate::mining::miner::{HashRateMeter, MiningBudget};
+use crate::mining::policy::SharedMiningPolicy;
+use crate::network::network_id::NetworkId;
+use crate::network::p2p::{ConnectedPeers, ListenAddrs, P2pControl, P2pMessage, P2pMetrics, PeerStatsMap};
+use crate::network::peer_store::SharedPeerStore;
+
+/// Everything the HTTP handlers share, registered once as
+/// `web::Data<AppState>`. Cloning it clones the handles, not the state.
+#[derive(Clone)]
+pub struct AppState {
+    pub blockchain: Arc<Mutex<Blockchain>>,
+    pub tx_pool: TransactionPool,
+    /// Messages to gossip to peers.
+    pub to_p2p: mpsc::UnboundedSender<P2pMessage>,
+    /// Commands for the P2P task, such as dialing a peer.
+    pub p2p_control: mpsc::Sender<P2pControl>,
+    /// The wallet `/mine` pays and the faucet spends from.
+    pub miner_wallet: Arc<Wallet>,
+    /// Every wallet the node keeps, for `/wallet/backup` and `/wallet/restore`.
+    pub key_store: SharedKeyStore,
+    pub hub: Addr<BroadcastHub>,
+    pub peer_stats: PeerStatsMap,
+    pub connected_peers: ConnectedPeers,
+    pub p2p_metrics: Arc<P2pMetrics>,
+    /// Response body bytes by route, for `/metrics`.
+    pub response_sizes: SharedResponseSizes,
+    /// Where the P2P layer listens, for `/status`.
+    pub p2p_listen_addrs: ListenAddrs,
+    /// Where the HTTP server listens, set once it has bound.
+    pub http_addr: Arc<OnceLock<SocketAddr>>,
+    pub peer_store: SharedPeerStore,
+    pub hash_rate: Arc<HashRateMeter>,
+    /// When `/mine` and the auto-miner mine, set by `PUT /mining/config`.
+    pub mining_policy: SharedMiningPolicy,
+    /// The most a mining job may spend, set by `--mining-max-attempts` and
+    /// `--mining-timeout-ms`. Neither the policy nor a request can raise it.
+    pub mining_caps: MiningBudget,
+    pub health: SharedHealth,
+    pub network: NetworkId,
+    /// Set by `--faucet` and `--faucet-amount`.
+    pub faucet: FaucetConfig,
+    pub faucet_ledger: SharedFaucetLedger,
+    /// Recent spends from the stored wallets and the payments awaiting approval.
+    pub spend_ledger: SharedSpendLedger,
+    pub nft_registry: SharedNftRegistry,
+    /// The user's notes on transactions, kept only on this node.
+    pub labels: SharedLabelStore,
+    pub admin_key: AdminKey,
+    pub allow_chain_ops: AllowChainOps,
+    /// Set by `--light`: block production endpoints answer 403.
+    pub light: LightNode,
+    /// Required by `/ws` if set.
+    pub api_key: ApiKey,
+    /// Who may frame `/embed/latest`, set by `--embed-frame-ancestors`.
+    pub frame_ancestors: FrameAncestors,
+    /// Pauses and resumes `--demo-traffic`.
+    pub demo_traffic: DemoSwitch,
+    /// Block sizes measured so far for `/admin/storage`.
+    pub block_sizes: SharedBlockSizes,
+    /// The side-chain retention policy, run on a schedule and by `/admin/gc`.
+    pub storage_gc: SharedStorageGc,
+    /// Confirmed txids by location, for `/transaction/{txid}`.
+    pub tx_index: SharedTxIndex,
+}
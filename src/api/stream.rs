@@ -0,0 +1,112 @@
+use actix_web::http::header::ContentEncoding;
+use actix_web::web::Bytes;
+use actix_web::{get, web, HttpResponse, Responder};
+use futures_util::stream;
+use serde::{Deserialize, Serialize};
+use std::convert::Infallible;
+use std::ops::Range;
+use tokio::sync::broadcast;
+
+use crate::api::state::AppState;
+use crate::api::websocket::SubscribeBlocks;
+use crate::blockchain::block::Block;
+
+#[derive(Deserialize)]
+pub struct BlockStreamQuery {
+    /// The first height to send. Without it only blocks connected after the
+    /// stream opens are sent.
+    since_height: Option<u64>,
+}
+
+/// A `/blocks/stream` client's place in the chain.
+struct Follower {
+    state: web::Data<AppState>,
+    blocks: broadcast::Receiver<Block>,
+    /// The height of the next block the client hasn't been sent.
+    next_height: u64,
+}
+
+impl Follower {
+    /// Waits for the next block to send, or returns `None` once the node is
+    /// shutting down.
+    async fn next_block(&mut self) -> Option<Block> {
+        loop {
+            let stored = self.state.blockchain.lock().unwrap().chain.get(self.next_height as usize).cloned();
+            if let Some(block) = stored {
+                self.next_height += 1;
+                return Some(block);
+            }
+            match self.blocks.recv().await {
+                // A block below the next height replaced one already sent.
+                Ok(block) if block.index < self.next_height => {
+                    self.next_height = block.index + 1;
+                    return Some(block);
+                }
+                // Blocks are stored before they're broadcast, so the chain has
+                // it; lagging only means several arrived.
+                Ok(_) | Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                Err(broadcast::error::RecvError::Closed) => return None,
+            }
+        }
+    }
+}
+
+fn ndjson_line(value: &impl Serialize) -> Bytes {
+    let mut line = serde_json::to_vec(value).unwrap();
+    line.push(b'\n');
+    Bytes::from(line)
+}
+
+/// Answers with the stored blocks at `heights` as newline-delimited JSON,
+/// each as `render` makes it. Blocks are read one at a time as the client
+/// takes them, so the range is never held in memory whole. If a reorg
+/// shortens the chain meanwhile, the stream ends at the new tip.
+pub fn stored_blocks<F>(state: web::Data<AppState>, heights: Range<usize>, render: F) -> HttpResponse
+where
+    F: Fn(&Block) -> serde_json::Value + 'static,
+{
+    let lines = stream::unfold(heights, move |mut heights| {
+        let line = heights
+            .next()
+            .and_then(|height| Some(ndjson_line(&render(state.blockchain.lock().unwrap().chain.get(height)?))));
+        async move { Some((Ok::<_, Infallible>(line?), heights)) }
+    });
+    // Uncompressed, like `stream_blocks`, so each block reaches the client as
+    // it is read rather than once enough have piled up.
+    HttpResponse::Ok()
+        .content_type("application/x-ndjson")
+        .insert_header(ContentEncoding::Identity)
+        .streaming(lines)
+}
+
+/// Streams blocks as newline-delimited JSON, one block per line: first the
+/// stored blocks from `since_height`, then each block as it is connected.
+/// The response stays open until the client disconnects, which frees its
+/// place among the `MAX_BLOCK_STREAMS` allowed at once.
+#[get("/blocks/stream")]
+pub async fn stream_blocks(
+    query: web::Query<BlockStreamQuery>,
+    state: web::Data<AppState>,
+) -> impl Responder {
+    // Subscribe before reading the chain, so a block connected in between is still sent.
+    let blocks = match state.hub.send(SubscribeBlocks).await {
+        Ok(Some(blocks)) => blocks,
+        Ok(None) => return HttpResponse::ServiceUnavailable().body("Too many block streams are open"),
+        Err(_) => return HttpResponse::InternalServerError().body("Block notifications are unavailable"),
+    };
+    let next_height = match query.since_height {
+        Some(height) => height,
+        None => state.blockchain.lock().unwrap().chain.len() as u64,
+    };
+    let follower = Follower { state: state.clone(), blocks, next_height };
+    let lines = stream::unfold(follower, |mut follower| async move {
+        let block = follower.next_block().await?;
+        Some((Ok::<_, Infallible>(ndjson_line(&block)), follower))
+    });
+    // Compression would buffer lines until enough pile up, so blocks would
+    // reach the client late.
+    HttpResponse::Ok()
+        .content_type("application/x-ndjson")
+        .insert_header(ContentEncoding::Identity)
+        .streaming(lines)
+}
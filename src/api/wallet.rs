@@ -0,0 +1,187 @@
+use actix_web::{get, post, web, HttpRequest, HttpResponse, Responder};
+use serde::Deserialize;
+
+use crate::api::admin::authorize;
+use crate::api::handlers::{check_built_transaction, wallet_from_private_key};
+use crate::api::spending::{spend_refused, wallet_policies};
+use crate::api::state::AppState;
+use crate::api::websocket::TrackTransaction;
+use crate::core::keystore::StoredWallet;
+use crate::core::spend_policy::Payment;
+use crate::core::wallet_backup::{backup, restore, BackupError, RestoreMode};
+use crate::network::p2p::P2pMessage;
+
+/// The request header carrying the backup passphrase.
+pub const PASSPHRASE_HEADER: &str = "X-Wallet-Passphrase";
+
+/// Reads the backup passphrase from its header, answering 400 if it is
+/// missing or empty.
+fn passphrase(req: &HttpRequest) -> Result<&str, HttpResponse> {
+    req.headers()
+        .get(PASSPHRASE_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .filter(|passphrase| !passphrase.is_empty())
+        .ok_or_else(|| {
+            HttpResponse::BadRequest()
+                .json(serde_json::json!({ "error": format!("Missing {} header", PASSPHRASE_HEADER) }))
+        })
+}
+
+/// Returns every wallet in the node's key store, encrypted with the
+/// passphrase from the `X-Wallet-Passphrase` header.
+#[get("/wallet/backup")]
+pub async fn backup_wallets(req: HttpRequest, state: web::Data<AppState>) -> impl Responder {
+    if let Err(response) = authorize(&req, &state.admin_key) {
+        return response;
+    }
+    let passphrase = match passphrase(&req) {
+        Ok(passphrase) => passphrase,
+        Err(response) => return response,
+    };
+    let key_store = state.key_store.lock().unwrap();
+    HttpResponse::Ok().json(backup(&key_store, passphrase))
+}
+
+#[derive(Deserialize)]
+pub struct RestoreQuery {
+    #[serde(default)]
+    mode: RestoreMode,
+}
+
+/// Restores the wallets in a backup from `/wallet/backup`, merging them into
+/// the key store or replacing it per `mode`. The node keeps mining to the
+/// wallet it started with until it restarts.
+#[post("/wallet/restore")]
+pub async fn restore_wallets(
+    req: HttpRequest,
+    query: web::Query<RestoreQuery>,
+    body: web::Json<serde_json::Value>,
+    state: web::Data<AppState>,
+) -> impl Responder {
+    if let Err(response) = authorize(&req, &state.admin_key) {
+        return response;
+    }
+    let passphrase = match passphrase(&req) {
+        Ok(passphrase) => passphrase,
+        Err(response) => return response,
+    };
+    let mut key_store = state.key_store.lock().unwrap();
+    let restored = match restore(&mut key_store, body.into_inner(), passphrase, query.mode) {
+        Ok(restored) => restored,
+        Err(e @ BackupError::WrongPassphrase) => {
+            return HttpResponse::Forbidden().json(serde_json::json!({ "error": e.to_string() }));
+        }
+        Err(e) => return HttpResponse::BadRequest().json(serde_json::json!({ "error": e.to_string() })),
+    };
+    if let Err(e) = key_store.save() {
+        tracing::error!("Failed to save wallets to {}: {}", key_store.path().display(), e);
+        return HttpResponse::InternalServerError().json(serde_json::json!({ "error": "Failed to save wallets" }));
+    }
+    HttpResponse::Ok().json(serde_json::json!({
+        "restored": restored,
+        "wallets": key_store.wallets().len(),
+    }))
+}
+
+#[derive(Deserialize)]
+pub struct ImportRequest {
+    private_key: String,
+    #[serde(default)]
+    label: Option<String>,
+    /// Also lists the key's unspent outputs, as found in the address index.
+    #[serde(default)]
+    rescan: bool,
+}
+
+/// Adds a private key, e.g. one from `POST /wallet`, to the node's key
+/// store, and reports its address's balance.
+#[post("/wallet/import")]
+pub async fn import_wallet(req: HttpRequest, body: web::Json<ImportRequest>, state: web::Data<AppState>) -> impl Responder {
+    if let Err(response) = authorize(&req, &state.admin_key) {
+        return response;
+    }
+    let wallet = match wallet_from_private_key(&body.private_key) {
+        Ok(wallet) => wallet,
+        Err(e) => return HttpResponse::BadRequest().json(serde_json::json!({ "error": e })),
+    };
+    let stored = StoredWallet { label: body.label.clone(), ..StoredWallet::from(&wallet) };
+    let address = stored.address.clone();
+    let mut key_store = state.key_store.lock().unwrap();
+    let imported = key_store.merge(vec![stored]) > 0;
+    if imported && let Err(e) = key_store.save() {
+        tracing::error!("Failed to save wallets to {}: {}", key_store.path().display(), e);
+        return HttpResponse::InternalServerError().json(serde_json::json!({ "error": "Failed to save wallets" }));
+    }
+    let blockchain = state.blockchain.lock().unwrap();
+    let mut response = serde_json::json!({
+        "address": address,
+        "imported": imported,
+        "balance": blockchain.get_balance(&address),
+        "wallets": key_store.wallets().len(),
+    });
+    if body.rescan {
+        response["utxos"] = serde_json::json!(blockchain.get_utxos(&address));
+    }
+    HttpResponse::Ok().json(response)
+}
+
+#[derive(Deserialize)]
+pub struct SweepRequest {
+    from_private_key: String,
+    to_address: String,
+    /// The fee to pay the miner, taken out of the swept amount.
+    #[serde(default)]
+    fee: u64,
+}
+
+/// Sends everything a private key can spend to another address in one
+/// transaction. The key is used to sign and then forgotten.
+#[post("/wallet/sweep")]
+pub async fn sweep_wallet(body: web::Json<SweepRequest>, state: web::Data<AppState>) -> impl Responder {
+    let wallet = match wallet_from_private_key(&body.from_private_key) {
+        Ok(wallet) => wallet,
+        Err(e) => return HttpResponse::BadRequest().json(serde_json::json!({ "error": e })),
+    };
+    if body.to_address.is_empty() {
+        return HttpResponse::BadRequest().json(serde_json::json!({ "error": "to_address is required" }));
+    }
+    let from_address = wallet.get_address();
+    let policies = wallet_policies(&state);
+    let now = chrono::Utc::now().timestamp();
+    let blockchain = state.blockchain.lock().unwrap();
+    let mut pool = state.tx_pool.lock().unwrap();
+    let tx = match blockchain.build_sweep(wallet, body.to_address.clone(), body.fee, |outpoint| pool.is_spent(outpoint)) {
+        Ok(tx) => tx,
+        Err(e) => return HttpResponse::BadRequest().json(serde_json::json!({ "error": e.to_string() })),
+    };
+    if let Err(response) = check_built_transaction(&tx, &blockchain) {
+        return response;
+    }
+    // A sweep from a stored wallet can't wait for approval either.
+    let payment = Payment { from: vec![from_address.clone()], to: Some(body.to_address.clone()), value: tx.outputs[0].value + body.fee };
+    let mut ledger = state.spend_ledger.lock().unwrap();
+    if let Some((_, error)) = ledger.check(&policies, std::slice::from_ref(&payment), now).pop() {
+        return spend_refused(error);
+    }
+    if let Err(e) = pool.submit(tx.clone(), &blockchain) {
+        return HttpResponse::BadRequest().json(serde_json::json!({ "error": e.to_string() }));
+    }
+    ledger.record(&policies, &payment, &tx.id, now);
+    drop(ledger);
+    state.to_p2p.send(P2pMessage::Transaction(tx.clone())).unwrap();
+    state.hub.do_send(TrackTransaction { txid: tx.id.clone(), expiry_height: tx.expiry_height });
+
+    let swept = tx.outputs[0].value + body.fee;
+    HttpResponse::Ok().json(serde_json::json!({
+        "txid": tx.id,
+        "tracking_id": tx.id,
+        "from_address": from_address,
+        "to_address": body.to_address,
+        "amount": tx.outputs[0].value,
+        "fee": body.fee,
+        "inputs": tx.inputs.len(),
+        // The balances once the sweep is mined.
+        "from_balance": blockchain.get_balance(&from_address).saturating_sub(swept),
+        "to_balance": blockchain.get_balance(&body.to_address).saturating_add(tx.outputs[0].value),
+    }))
+}
@@ -1,7 +1,52 @@
-use actix::{Actor, Addr, ActorContext, ActorFutureExt, AsyncContext, Context, ContextFutureSpawner, fut, Handler, Message, Recipient, Running, StreamHandler, WrapFuture};
+use actix::{Actor, Addr, ActorContext, ActorFutureExt, AsyncContext, Context, ContextFutureSpawner, fut, Handler, Message, MessageResult, Recipient, Running, StreamHandler, WrapFuture};
 use actix_web_actors::ws;
-use std::collections::HashMap;
+use flate2::{write::GzEncoder, Compression};
+use serde::Deserialize;
+use std::collections::{HashMap, HashSet};
+use std::io::Write;
+use tokio::sync::broadcast;
 use crate::blockchain::block::Block;
+use crate::blockchain::chain::Reorg;
+use crate::core::tracker::{TxStatusUpdate, TxTracker};
+
+/// How many tip changes a subscriber may fall behind by. Long-pollers only
+/// care that the tip moved, so lagging is harmless.
+const TIP_CHANNEL_CAPACITY: usize = 16;
+
+/// How many blocks a `/blocks/stream` client may fall behind by before it
+/// has to catch up from the chain instead.
+const BLOCK_CHANNEL_CAPACITY: usize = 64;
+
+/// How many status changes of tracked transactions a `/track` long-poller
+/// may fall behind by. It looks the status up again either way.
+const TX_UPDATE_CHANNEL_CAPACITY: usize = 64;
+
+/// The most `/blocks/stream` clients served at once.
+pub const MAX_BLOCK_STREAMS: usize = 32;
+
+/// How a WebSocket session encodes the messages it is sent, negotiated with
+/// the `format` query parameter on the `/ws` handshake.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum WsFormat {
+    /// JSON in text frames.
+    #[default]
+    Text,
+    /// Gzip-compressed JSON in binary frames, which is much smaller for
+    /// blocks carrying escape-time fractal data.
+    Binary,
+}
+
+/// The key `/ws` clients must present, if the node is private.
+#[derive(Clone, Debug, Default)]
+pub struct ApiKey(pub Option<String>);
+
+/// Gzip-compresses a JSON message for a binary frame.
+fn gzip(message: &str) -> Vec<u8> {
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(message.as_bytes()).unwrap();
+    encoder.finish().unwrap()
+}
 
 /// Message sent from the `BroadcastHub` to a specific client.
 #[derive(Message)]
@@ -15,17 +60,147 @@ pub struct BroadcastBlock {
     pub block: Block,
 }
 
+/// Message to tell all clients that blocks were removed from the active chain.
+#[derive(Message, Clone)]
+#[rtype(result = "()")]
+pub struct BroadcastReorg {
+    pub reorg: Reorg,
+    /// Transactions from the disconnected blocks that went back to the mempool.
+    pub returned_to_mempool: Vec<String>,
+    pub height: u64,
+}
+
+/// Message asking the hub to notify a subscription once a transaction is mined.
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct WatchTransaction {
+    pub txid: String,
+    pub subscription_id: String,
+}
+
+/// Message asking the hub to track a transaction that just entered the
+/// mempool through this node's API.
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct TrackTransaction {
+    pub txid: String,
+    pub expiry_height: Option<u64>,
+}
+
+/// Message telling the hub that transactions left the mempool unmined.
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct DropTransactions {
+    pub txids: Vec<String>,
+    pub reason: String,
+}
+
+/// Message telling the hub that a transaction replaced pending ones, by
+/// paying a higher fee for the same inputs.
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct TransactionReplaced {
+    /// The transactions evicted, the conflicting ones and their descendants.
+    pub replaced: Vec<String>,
+    pub replacement: String,
+}
+
+/// Message asking the hub for a tracked transaction's status.
+#[derive(Message)]
+#[rtype(result = "Option<TxStatusUpdate>")]
+pub struct GetTxStatus {
+    pub txid: String,
+}
+
+/// Message asking the hub for a receiver of every tracked transaction's
+/// status changes.
+#[derive(Message)]
+#[rtype(result = "broadcast::Receiver<TxStatusUpdate>")]
+pub struct SubscribeTxUpdates;
+
+/// Message from a WebSocket session asking to be sent a transaction's
+/// status changes.
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct SubscribeTx {
+    pub id: usize,
+    pub txid: String,
+}
+
+/// Message asking the hub for a receiver that is signalled whenever the
+/// chain tip changes, through a new block or a reorg.
+#[derive(Message)]
+#[rtype(result = "broadcast::Receiver<()>")]
+pub struct SubscribeTip;
+
+/// Message asking the hub for a receiver of every block it broadcasts, or
+/// `None` if `MAX_BLOCK_STREAMS` receivers are already open. Dropping the
+/// receiver frees its place.
+#[derive(Message)]
+#[rtype(result = "Option<broadcast::Receiver<Block>>")]
+pub struct SubscribeBlocks;
+
 /// The central hub for broadcasting messages to all WebSocket clients.
-#[derive(Default)]
 pub struct BroadcastHub {
     sessions: HashMap<usize, Recipient<ClientMessage>>,
+    /// Session IDs registered under each client-supplied subscription ID.
+    subscriptions: HashMap<String, HashSet<usize>>,
+    /// Subscription IDs waiting for each unconfirmed transaction.
+    watched: HashMap<String, String>,
     next_id: usize,
+    /// Signalled on every tip change, for clients that aren't WebSocket sessions.
+    tip_changes: broadcast::Sender<()>,
+    /// Every broadcast block, for `/blocks/stream`.
+    blocks: broadcast::Sender<Block>,
+    /// Transactions submitted here, followed into the chain.
+    tracker: TxTracker,
+    /// Session IDs subscribed to each transaction's status changes.
+    tx_subscribers: HashMap<String, HashSet<usize>>,
+    /// Every status change of a tracked transaction, for `/track`.
+    tx_updates: broadcast::Sender<TxStatusUpdate>,
+}
+
+impl Default for BroadcastHub {
+    fn default() -> Self {
+        Self {
+            sessions: HashMap::new(),
+            subscriptions: HashMap::new(),
+            watched: HashMap::new(),
+            next_id: 0,
+            tip_changes: broadcast::channel(TIP_CHANNEL_CAPACITY).0,
+            blocks: broadcast::channel(BLOCK_CHANNEL_CAPACITY).0,
+            tracker: TxTracker::default(),
+            tx_subscribers: HashMap::new(),
+            tx_updates: broadcast::channel(TX_UPDATE_CHANNEL_CAPACITY).0,
+        }
+    }
 }
 
 impl BroadcastHub {
     pub fn new() -> Self {
         Self::default()
     }
+
+    /// Sends tracked transactions' status changes to the sessions
+    /// subscribed to them and to `/track` long-pollers.
+    fn publish(&self, updates: Vec<TxStatusUpdate>) {
+        for update in updates {
+            let event = serde_json::json!({
+                "type": "tx_status",
+                "txid": update.txid,
+                "version": update.version,
+                "status": update.status,
+            })
+            .to_string();
+            for id in self.tx_subscribers.get(&update.txid).into_iter().flatten() {
+                if let Some(addr) = self.sessions.get(id) {
+                    addr.do_send(ClientMessage(event.clone()));
+                }
+            }
+            // Fails only when nobody is long-polling.
+            self.tx_updates.send(update).ok();
+        }
+    }
 }
 
 impl Actor for BroadcastHub {
@@ -38,6 +213,9 @@ impl Handler<Connect> for BroadcastHub {
     fn handle(&mut self, msg: Connect, _: &mut Context<Self>) -> Self::Result {
         let id = self.next_id;
         self.sessions.insert(id, msg.addr);
+        if let Some(subscription_id) = msg.subscription_id {
+            self.subscriptions.entry(subscription_id).or_default().insert(id);
+        }
         self.next_id += 1;
         id
     }
@@ -48,6 +226,118 @@ impl Handler<Disconnect> for BroadcastHub {
 
     fn handle(&mut self, msg: Disconnect, _: &mut Context<Self>) {
         self.sessions.remove(&msg.id);
+        self.subscriptions.retain(|_, ids| {
+            ids.remove(&msg.id);
+            !ids.is_empty()
+        });
+        self.tx_subscribers.retain(|_, ids| {
+            ids.remove(&msg.id);
+            !ids.is_empty()
+        });
+    }
+}
+
+impl Handler<TrackTransaction> for BroadcastHub {
+    type Result = ();
+
+    fn handle(&mut self, msg: TrackTransaction, _: &mut Context<Self>) {
+        let update = self.tracker.track(&msg.txid, msg.expiry_height);
+        self.publish(update.into_iter().collect());
+    }
+}
+
+impl Handler<DropTransactions> for BroadcastHub {
+    type Result = ();
+
+    fn handle(&mut self, msg: DropTransactions, _: &mut Context<Self>) {
+        let updates = self.tracker.dropped(&msg.txids, &msg.reason);
+        self.publish(updates);
+    }
+}
+
+impl Handler<TransactionReplaced> for BroadcastHub {
+    type Result = ();
+
+    /// Tells every client, records the replacement in the journal and marks
+    /// the replaced transactions dropped.
+    fn handle(&mut self, msg: TransactionReplaced, _: &mut Context<Self>) {
+        tracing::info!(
+            target: "journal",
+            replacement = msg.replacement.as_str(),
+            replaced = ?msg.replaced,
+            "Transaction replaced by a higher fee"
+        );
+        let event = serde_json::json!({
+            "type": "transaction_replaced",
+            "replaced": msg.replaced,
+            "replacement": msg.replacement,
+        })
+        .to_string();
+        for addr in self.sessions.values() {
+            addr.do_send(ClientMessage(event.clone()));
+        }
+        let updates = self.tracker.dropped(&msg.replaced, &format!("Replaced by {}", msg.replacement));
+        self.publish(updates);
+    }
+}
+
+impl Handler<GetTxStatus> for BroadcastHub {
+    type Result = Option<TxStatusUpdate>;
+
+    fn handle(&mut self, msg: GetTxStatus, _: &mut Context<Self>) -> Self::Result {
+        self.tracker.status(&msg.txid)
+    }
+}
+
+impl Handler<SubscribeTxUpdates> for BroadcastHub {
+    type Result = MessageResult<SubscribeTxUpdates>;
+
+    fn handle(&mut self, _: SubscribeTxUpdates, _: &mut Context<Self>) -> Self::Result {
+        MessageResult(self.tx_updates.subscribe())
+    }
+}
+
+impl Handler<SubscribeTx> for BroadcastHub {
+    type Result = ();
+
+    /// Subscribes the session and sends it the transaction's current status,
+    /// if it is tracked. An untracked transaction is tracked once it is mined.
+    fn handle(&mut self, msg: SubscribeTx, _: &mut Context<Self>) {
+        self.tx_subscribers.entry(msg.txid.clone()).or_default().insert(msg.id);
+        let (Some(status), Some(addr)) = (self.tracker.status(&msg.txid), self.sessions.get(&msg.id)) else {
+            return;
+        };
+        let event = serde_json::json!({
+            "type": "tx_status",
+            "txid": status.txid,
+            "version": status.version,
+            "status": status.status,
+        });
+        addr.do_send(ClientMessage(event.to_string()));
+    }
+}
+
+impl Handler<WatchTransaction> for BroadcastHub {
+    type Result = ();
+
+    fn handle(&mut self, msg: WatchTransaction, _: &mut Context<Self>) {
+        self.watched.insert(msg.txid, msg.subscription_id);
+    }
+}
+
+impl Handler<SubscribeTip> for BroadcastHub {
+    type Result = MessageResult<SubscribeTip>;
+
+    fn handle(&mut self, _: SubscribeTip, _: &mut Context<Self>) -> Self::Result {
+        MessageResult(self.tip_changes.subscribe())
+    }
+}
+
+impl Handler<SubscribeBlocks> for BroadcastHub {
+    type Result = MessageResult<SubscribeBlocks>;
+
+    fn handle(&mut self, _: SubscribeBlocks, _: &mut Context<Self>) -> Self::Result {
+        MessageResult((self.blocks.receiver_count() < MAX_BLOCK_STREAMS).then(|| self.blocks.subscribe()))
     }
 }
 
@@ -55,10 +345,56 @@ impl Handler<BroadcastBlock> for BroadcastHub {
     type Result = ();
 
     fn handle(&mut self, msg: BroadcastBlock, _: &mut Context<Self>) {
+        // Fails only when nobody is subscribed.
+        self.tip_changes.send(()).ok();
+        self.blocks.send(msg.block.clone()).ok();
         let block_json = serde_json::to_string(&msg.block).unwrap();
         for addr in self.sessions.values() {
             addr.do_send(ClientMessage(block_json.clone()));
         }
+
+        for tx in &msg.block.transactions {
+            let Some(subscription_id) = self.watched.remove(&tx.id) else { continue };
+            let confirmation = serde_json::json!({
+                "type": "confirmed",
+                "txid": tx.id,
+                "block_index": msg.block.index,
+                "block_hash": msg.block.hash,
+            })
+            .to_string();
+            for id in self.subscriptions.get(&subscription_id).into_iter().flatten() {
+                if let Some(addr) = self.sessions.get(id) {
+                    addr.do_send(ClientMessage(confirmation.clone()));
+                }
+            }
+        }
+
+        let subscribers = &self.tx_subscribers;
+        let updates = self.tracker.block_connected(&msg.block, |txid| subscribers.contains_key(txid));
+        self.publish(updates);
+    }
+}
+
+impl Handler<BroadcastReorg> for BroadcastHub {
+    type Result = ();
+
+    fn handle(&mut self, msg: BroadcastReorg, _: &mut Context<Self>) {
+        self.tip_changes.send(()).ok();
+        let hashes = |blocks: &[Block]| blocks.iter().map(|block| block.hash.clone()).collect::<Vec<_>>();
+        let event = serde_json::json!({
+            "type": "reorg",
+            "disconnected": hashes(&msg.reorg.disconnected),
+            "connected": hashes(&msg.reorg.connected),
+            "height": msg.height,
+        })
+        .to_string();
+        for addr in self.sessions.values() {
+            addr.do_send(ClientMessage(event.clone()));
+        }
+
+        let subscribers = &self.tx_subscribers;
+        let updates = self.tracker.apply_reorg(&msg.reorg, &msg.returned_to_mempool, msg.height, |txid| subscribers.contains_key(txid));
+        self.publish(updates);
     }
 }
 
@@ -67,6 +403,7 @@ impl Handler<BroadcastBlock> for BroadcastHub {
 #[rtype(usize)]
 pub struct Connect {
     pub addr: Recipient<ClientMessage>,
+    pub subscription_id: Option<String>,
 }
 
 /// Message to disconnect a WebSocket session from the `BroadcastHub`.
@@ -80,11 +417,13 @@ pub struct Disconnect {
 pub struct WsConn {
     id: usize,
     hub_addr: Addr<BroadcastHub>,
+    subscription_id: Option<String>,
+    format: WsFormat,
 }
 
 impl WsConn {
-    pub fn new(hub_addr: Addr<BroadcastHub>) -> Self {
-        Self { id: 0, hub_addr }
+    pub fn new(hub_addr: Addr<BroadcastHub>, subscription_id: Option<String>, format: WsFormat) -> Self {
+        Self { id: 0, hub_addr, subscription_id, format }
     }
 }
 
@@ -93,8 +432,9 @@ impl Actor for WsConn {
 
     fn started(&mut self, ctx: &mut Self::Context) {
         let addr = ctx.address().recipient();
+        let subscription_id = self.subscription_id.clone();
         self.hub_addr
-            .send(Connect { addr })
+            .send(Connect { addr, subscription_id })
             .into_actor(self)
             .then(|res, act, ctx| {
                 match res {
@@ -116,10 +456,21 @@ impl Handler<ClientMessage> for WsConn {
     type Result = ();
 
     fn handle(&mut self, msg: ClientMessage, ctx: &mut Self::Context) {
-        ctx.text(msg.0);
+        match self.format {
+            WsFormat::Text => ctx.text(msg.0),
+            WsFormat::Binary => ctx.binary(gzip(&msg.0)),
+        }
     }
 }
 
+/// A request from a WebSocket client, e.g. `{"subscribe":"tx","value":"<txid>"}`
+/// to be sent a transaction's status changes.
+#[derive(Deserialize)]
+struct ClientRequest {
+    subscribe: String,
+    value: String,
+}
+
 impl StreamHandler<Result<ws::Message, ws::ProtocolError>> for WsConn {
     fn handle(&mut self, msg: Result<ws::Message, ws::ProtocolError>, ctx: &mut Self::Context) {
         match msg {
@@ -128,7 +479,109 @@ impl StreamHandler<Result<ws::Message, ws::ProtocolError>> for WsConn {
                 ctx.close(reason);
                 ctx.stop();
             }
-            _ => (), // We don't expect messages from the client in this app
+            Ok(ws::Message::Text(text)) => match serde_json::from_str::<ClientRequest>(&text) {
+                Ok(request) if request.subscribe == "tx" => {
+                    self.hub_addr.do_send(SubscribeTx { id: self.id, txid: request.value });
+                }
+                Ok(request) => ctx.text(serde_json::json!({ "type": "error", "error": format!("Unknown subscription {:?}", request.subscribe) }).to_string()),
+                Err(e) => ctx.text(serde_json::json!({ "type": "error", "error": e.to_string() }).to_string()),
+            },
+            _ => (),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::transaction::Transaction;
+    use crate::fractal::FractalType;
+    use std::sync::{Arc, Mutex};
+
+    /// A stand-in for a WebSocket session that records what it is sent.
+    struct Collector(Arc<Mutex<Vec<String>>>);
+
+    impl Actor for Collector {
+        type Context = Context<Self>;
+    }
+
+    impl Handler<ClientMessage> for Collector {
+        type Result = ();
+
+        fn handle(&mut self, msg: ClientMessage, _: &mut Context<Self>) {
+            self.0.lock().unwrap().push(msg.0);
+        }
+    }
+
+    #[actix_web::test]
+    async fn test_subscriber_receives_confirmation() {
+        let hub = BroadcastHub::new().start();
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let subscriber = Collector(Arc::clone(&received)).start();
+        let bystander_received = Arc::new(Mutex::new(Vec::new()));
+        let bystander = Collector(Arc::clone(&bystander_received)).start();
+
+        hub.send(Connect { addr: subscriber.recipient(), subscription_id: Some("sub-1".to_string()) }).await.unwrap();
+        hub.send(Connect { addr: bystander.recipient(), subscription_id: None }).await.unwrap();
+
+        let tx = Transaction::new(vec![], vec![]);
+        hub.send(WatchTransaction { txid: tx.id.clone(), subscription_id: "sub-1".to_string() }).await.unwrap();
+
+        let block = Block {
+            index: 7,
+            timestamp: 0,
+            fractal: FractalType::Sierpinski { depth: 0, seed: 0 }.generate(),
+            transactions: vec![tx.clone()],
+            previous_hash: String::new(),
+            hash: "abc".to_string(),
+            nonce: 0,
+            bits: 0,
+            style_seed: 0,
+        };
+        hub.send(BroadcastBlock { block }).await.unwrap();
+        // Let the collectors drain their mailboxes.
+        actix::clock::sleep(std::time::Duration::from_millis(10)).await;
+
+        let received = received.lock().unwrap();
+        let confirmation: serde_json::Value = serde_json::from_str(received.last().unwrap()).unwrap();
+        assert_eq!(confirmation["type"], "confirmed");
+        assert_eq!(confirmation["txid"], tx.id);
+        assert_eq!(confirmation["block_index"], 7);
+        assert_eq!(bystander_received.lock().unwrap().len(), 1);
+    }
+
+    #[actix_web::test]
+    async fn test_tx_subscriber_receives_status_changes() {
+        let hub = BroadcastHub::new().start();
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let subscriber = Collector(Arc::clone(&received)).start();
+        let id = hub.send(Connect { addr: subscriber.recipient(), subscription_id: None }).await.unwrap();
+
+        let tx = Transaction::new(vec![], vec![]);
+        hub.send(SubscribeTx { id, txid: tx.id.clone() }).await.unwrap();
+        hub.send(TrackTransaction { txid: tx.id.clone(), expiry_height: None }).await.unwrap();
+        let block = Block {
+            index: 1,
+            timestamp: 0,
+            fractal: FractalType::Sierpinski { depth: 0, seed: 0 }.generate(),
+            transactions: vec![tx.clone()],
+            previous_hash: String::new(),
+            hash: "abc".to_string(),
+            nonce: 0,
+            bits: 0,
+            style_seed: 0,
+        };
+        hub.send(BroadcastBlock { block }).await.unwrap();
+        actix::clock::sleep(std::time::Duration::from_millis(10)).await;
+
+        let states: Vec<serde_json::Value> = received
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|message| serde_json::from_str::<serde_json::Value>(message).unwrap())
+            .filter(|message| message["type"] == "tx_status")
+            .map(|message| message["status"]["state"].clone())
+            .collect();
+        assert_eq!(states, ["pending", "included"]);
+    }
+}
@@ -0,0 +1,24 @@
+//! Regenerates the consensus test vectors in `tests/fixtures`. Only run it
+//! when a change to hashing or serialization is deliberate: the version in
+//! the file's header is bumped whenever the vectors change.
+
+use sierpchain::vectors::{self, VectorsFile, VECTORS_FILE};
+use std::path::Path;
+
+fn main() -> std::io::Result<()> {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR")).join(VECTORS_FILE);
+    let previous = VectorsFile::load(&path).ok();
+    let mut file = VectorsFile::regenerate(previous.as_ref(), vectors::build());
+    if previous.is_none() {
+        // The old vectors are in a format that no longer parses, so they changed.
+        if let Some(version) = VectorsFile::load_version(&path) {
+            file.version = version + 1;
+        }
+    }
+    file.save(&path)?;
+    match previous {
+        Some(previous) if previous.version == file.version => println!("Consensus vectors unchanged at version {}", file.version),
+        _ => println!("Wrote version {} of the consensus vectors to {}", file.version, path.display()),
+    }
+    Ok(())
+}
@@ -1,5 +1,5 @@
-use sha2::{Sha256, Digest};
 use serde::{Serialize, Deserialize};
+use crate::blockchain::target::Target;
 use crate::fractal::FractalData;
 use crate::core::transaction::{Transaction};
 
@@ -13,17 +13,238 @@ pub struct Block {
     pub previous_hash: String,
     pub hash: String,
     pub nonce: u64,
+    /// The chain's proof-of-work target in compact form, before it is
+    /// weighted by the fractal's work factor; see `target`.
+    /// Blocks mined before targets were stored have 0, and leave it out of
+    /// both their JSON and their hash.
+    #[serde(default, skip_serializing_if = "is_unset")]
+    pub bits: u32,
+    /// The seed the miner chose to style the block's fractal. It is mixed
+    /// into the fractal's seed along with the nonce; see `fractal_seed`.
+    /// Blocks mined before it existed have 0, and leave it out of both
+    /// their JSON and their hash.
+    #[serde(default, skip_serializing_if = "is_unset")]
+    pub style_seed: u64,
+}
+
+fn is_unset<T: Default + PartialEq>(value: &T) -> bool {
+    *value == T::default()
+}
+
+/// The fields of a block that its hash commits to; see
+/// `sierpchain_shared::block::BlockHashableData`.
+pub type BlockHashableData<'a> = sierpchain_shared::block::BlockHashableData<'a, [Transaction]>;
+
+impl<'a> From<&'a Block> for BlockHashableData<'a> {
+    fn from(block: &'a Block) -> Self {
+        // Destructured exhaustively, so adding a field to `Block` fails to
+        // compile until it is either hashed here or excluded on purpose.
+        let Block { index, timestamp, fractal, transactions, previous_hash, hash: _, nonce, bits, style_seed } = block;
+        Self {
+            index: *index,
+            timestamp: *timestamp,
+            fractal,
+            transactions,
+            previous_hash,
+            nonce: *nonce,
+            bits: *bits,
+            style_seed: *style_seed,
+        }
+    }
+}
+
+/// A block without its fractal and transactions. Nodes relaying headers
+/// first announce new blocks with it, and peers missing the block fetch it
+/// in full with `GetBlocks`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct BlockHeader {
+    pub index: u64,
+    pub timestamp: i64,
+    pub previous_hash: String,
+    pub hash: String,
+    pub nonce: u64,
+    #[serde(default, skip_serializing_if = "is_unset")]
+    pub bits: u32,
+    pub transaction_count: usize,
+}
+
+impl From<&Block> for BlockHeader {
+    fn from(block: &Block) -> Self {
+        Self {
+            index: block.index,
+            timestamp: block.timestamp,
+            previous_hash: block.previous_hash.clone(),
+            hash: block.hash.clone(),
+            nonce: block.nonce,
+            bits: block.bits,
+            transaction_count: block.transactions.len(),
+        }
+    }
 }
 
 impl Block {
-    /// Calculates the SHA-256 hash of the block.
+    /// Derives the fractal seed for a block at `index` on top of
+    /// `previous_hash` trying `nonce` in the style of `style_seed`. Binding
+    /// the seed to the block's position means a fractal found for one block
+    /// can't be reused in another, and binding it to the nonce means the
+    /// style seed shapes the fractal without choosing it outright.
+    ///
+    /// A style seed of 0 is left out, so blocks mined before there were
+    /// style seeds keep their fractals.
+    pub fn derive_fractal_seed(previous_hash: &str, index: u64, nonce: u64, style_seed: u64) -> u64 {
+        sierpchain_shared::block::derive_fractal_seed(previous_hash, index, nonce, style_seed)
+    }
+
+    /// Returns the fractal seed this block should have been mined with.
+    pub fn fractal_seed(&self) -> u64 {
+        Self::derive_fractal_seed(&self.previous_hash, self.index, self.nonce, self.style_seed)
+    }
+
+    /// Returns true if the stored fractal was generated from this block's
+    /// derived seed, and so from its committed style seed and nonce, by the
+    /// generator it declares.
+    pub fn has_valid_fractal(&self) -> bool {
+        self.fractal.seed() == self.fractal_seed()
+            && self.fractal.fractal_type().generate_with(self.fractal.rng_version()) == self.fractal
+    }
+
+    /// Returns the target the block's hash must be below: the one in its
+    /// `bits`, weighted by its fractal's work factor.
+    pub fn target(&self) -> Target {
+        Target::from_compact(self.bits).weighted(&self.fractal.fractal_type())
+    }
+
+    /// Calculates the SHA-256 hash of the canonical encoding of the block's
+    /// `BlockHashableData`.
     pub fn calculate_hash(&self) -> String {
-        let mut headers = self.clone();
-        headers.hash = String::new(); // The hash is not part of the hash calculation.
-        let serialized = serde_json::to_string(&headers).unwrap();
-        let mut hasher = Sha256::new();
-        hasher.update(serialized.as_bytes());
-        let result = hasher.finalize();
-        format!("{:x}", result)
+        BlockHashableData::from(self).hash()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fractal::{self, FractalType};
+    use sha2::{Digest, Sha256};
+    use static_assertions::assert_fields;
+
+    // The canonical set of hashed fields.
+    assert_fields!(BlockHashableData<'static>: index, timestamp, fractal, transactions, previous_hash, nonce, bits, style_seed);
+
+    fn sample_block() -> Block {
+        Block {
+            index: 3,
+            timestamp: 1_700_000_000,
+            fractal: FractalType::Sierpinski { depth: 2, seed: 7 }.generate(),
+            transactions: vec![Transaction::new(vec![], vec![])],
+            previous_hash: "ab".repeat(32),
+            hash: "cd".repeat(32),
+            nonce: 42,
+            bits: 0,
+            style_seed: 0,
+        }
+    }
+
+    #[test]
+    fn test_hash_matches_legacy_encoding() {
+        let block = sample_block();
+        // Blocks used to be hashed as the whole `Block` with `hash` cleared.
+        let mut legacy = block.clone();
+        legacy.hash = String::new();
+        let legacy_hash = format!("{:x}", Sha256::digest(serde_json::to_string(&legacy).unwrap().as_bytes()));
+        assert_eq!(block.calculate_hash(), legacy_hash);
+    }
+
+    #[test]
+    fn test_fields_outside_hashable_data_do_not_change_the_hash() {
+        // Stands in for a future `Block` with an unhashed field such as `version`.
+        #[derive(Serialize)]
+        struct VersionedBlock {
+            #[serde(flatten)]
+            block: Block,
+            version: u32,
+        }
+        let block = sample_block();
+        let versioned: Block =
+            serde_json::from_value(serde_json::to_value(VersionedBlock { block: block.clone(), version: 2 }).unwrap())
+                .unwrap();
+        assert_eq!(versioned.calculate_hash(), block.calculate_hash());
+
+        let mut changed = block.clone();
+        changed.hash = "ef".repeat(32);
+        assert_eq!(changed.calculate_hash(), block.calculate_hash());
+        changed.nonce += 1;
+        assert_ne!(changed.calculate_hash(), block.calculate_hash());
+        changed.nonce -= 1;
+        changed.bits = 0x2010_0000;
+        assert_ne!(changed.calculate_hash(), block.calculate_hash());
+        changed.bits = 0;
+        changed.style_seed = 5;
+        assert_ne!(changed.calculate_hash(), block.calculate_hash());
+    }
+
+    #[test]
+    fn test_style_seed_shapes_the_fractal_without_fixing_it() {
+        let block = sample_block();
+        let legacy = Sha256::digest([block.previous_hash.as_bytes(), &3u64.to_le_bytes(), &42u64.to_le_bytes()].concat());
+        assert_eq!(block.fractal_seed(), u64::from_le_bytes(legacy[..8].try_into().unwrap()));
+
+        let styled = |nonce: u64| Block { nonce, style_seed: 99, ..sample_block() };
+        assert_ne!(styled(1).fractal_seed(), styled(2).fractal_seed());
+        assert_ne!(styled(42).fractal_seed(), block.fractal_seed());
+        let fractal_type = FractalType::Sierpinski { depth: 3, seed: 99 };
+        let generate = |block: &Block| fractal_type.clone().with_seed(block.fractal_seed()).generate();
+        assert_ne!(generate(&styled(1)), generate(&styled(2)));
+
+        let mut mined = styled(1);
+        mined.fractal = generate(&mined);
+        assert!(mined.has_valid_fractal());
+        mined.style_seed = 100;
+        assert!(!mined.has_valid_fractal());
+    }
+    #[test]
+    fn test_adversarial_fractal_floats_hash_stably() {
+        let edge_cases = [
+            0.0, -0.0, f64::NAN, -f64::NAN, f64::INFINITY, f64::NEG_INFINITY, f64::MIN_POSITIVE / 2.0,
+            -f64::MIN_POSITIVE, 1e-300, f64::EPSILON, 9.2e6, -9.3e6, f64::MAX, f64::MIN,
+        ];
+        let random = (0..500).map(|_| f64::from_bits(rand::random()));
+        let mut accepted = 0;
+        for (i, value) in edge_cases.into_iter().chain(random).enumerate() {
+            let fractal_type = if i % 2 == 0 {
+                FractalType::mandelbrot(6, 6, value, 1.0, -value, 1.5, 30, 0)
+            } else {
+                FractalType::julia(6, 6, -1.5, value, -1.5, 1.5, value, -value, 30, 0)
+            };
+            let Ok(fractal_type) = fractal_type else {
+                assert!(!value.is_finite() || value.abs() >= 9.2e6, "{} was rejected", value);
+                continue;
+            };
+            accepted += 1;
+            let mut block = sample_block();
+            // Generation is bounded by width * height * max_iterations, whatever the coordinates.
+            block.fractal = fractal_type.generate();
+            let (FractalData::Mandelbrot(fractal::mandelbrot::Mandelbrot { data, .. })
+            | FractalData::Julia(fractal::julia::Julia { data, .. })) = &block.fractal
+            else {
+                unreachable!()
+            };
+            assert!(data.len() == 36 && data.iter().all(|&iterations| iterations <= 30));
+
+            let hash = block.calculate_hash();
+            let mut round_tripped = block.clone();
+            for _ in 0..3 {
+                round_tripped = serde_json::from_str(&serde_json::to_string(&round_tripped).unwrap()).unwrap();
+            }
+            assert_eq!(round_tripped, block);
+            assert_eq!(round_tripped.calculate_hash(), hash);
+            assert_eq!(round_tripped.fractal.fractal_type(), fractal_type);
+        }
+        assert!(accepted > 10);
+
+        let positive = FractalType::mandelbrot(6, 6, 0.0, 1.0, -1.5, 1.5, 30, 0).unwrap();
+        assert_eq!(FractalType::mandelbrot(6, 6, -0.0, 1.0, -1.5, 1.5, 30, 0).unwrap(), positive);
+        let encoded = serde_json::to_string(&positive).unwrap();
+        assert!(serde_json::from_str::<FractalType>(&encoded.replace("1000000000000", "1.0")).is_err());
     }
 }
@@ -1,191 +1,1207 @@
-use chrono::Utc;
 use serde::{Serialize, Deserialize};
-use std::collections::HashSet;
+use std::cmp::Ordering;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
 use std::fs;
 use std::io::Write;
+use std::str::FromStr;
 
 use super::block::Block;
+use super::target::{Target, Work};
+use super::utxo::{OutPoint, UtxoIndex};
+use crate::fractal::utils::RngVersion;
 use crate::fractal::FractalType;
-use crate::core::transaction::{Transaction, TxInput, TxOutput};
-use crate::mining::miner::Miner;
+use crate::core::clock::{system_clock, SharedClock};
+use crate::core::script::UnlockingScript;
+use crate::core::transaction::{check_coinbase_message, BuildTransactRequest, Transaction, TxError, TxInput, TxOutput, MAX_MEMO_BYTES};
+use crate::core::wallet::Wallet;
+use crate::mining::miner::{MineOutcome, Miner, MiningBudget};
 
-const DB_FILE: &str = "blockchain.json";
+// The file the chain is saved to.
+pub const DB_FILE: &str = "blockchain.json";
 
 // The expected time to mine a block, in seconds.
 pub const BLOCK_GENERATION_INTERVAL: i64 = 10;
 // The number of blocks after which to adjust the difficulty.
 pub const DIFFICULTY_ADJUSTMENT_INTERVAL: u64 = 10;
+// How many block intervals the EMA difficulty algorithm's average spans:
+// each block moves the target a 1/EMA_WINDOW part of the way to where its
+// interval alone would put it.
+pub const EMA_WINDOW: i64 = 5;
+// The most a legacy retarget scales the target by, either way. The EMA
+// algorithm counts no block interval as longer than this many expected ones.
+pub const MAX_RETARGET_FACTOR: i64 = 4;
+// The lowest difficulty adjustments may reach.
+pub const MIN_DIFFICULTY: usize = 1;
+// The highest difficulty adjustments may reach. A hash has 64 hex digits,
+// so no target is harder than 64 leading zeros.
+pub const MAX_DIFFICULTY: usize = 64;
+// A block's timestamp must be later than the median of this many latest blocks'.
+pub const MEDIAN_TIME_SPAN: usize = 11;
+// How far ahead of local time a block may be timestamped, in seconds.
+pub const MAX_FUTURE_BLOCK_SECS: i64 = 30;
+// The coins created by each block's coinbase transaction, before fees.
+pub const BLOCK_REWARD_COINS: u64 = 50;
+// The address the genesis block's coinbase pays. Nobody holds its key.
+pub const GENESIS_ADDRESS: &str = "genesis_address";
+// The most coins a genesis block may pre-mine, across all its outputs.
+pub const PREMINE_CAP_COINS: u64 = 1_000_000;
+// Coinbase outputs from this many most recent blocks aren't counted as
+// circulating yet. Spending them isn't restricted.
+pub const COINBASE_MATURITY: usize = 100;
+// An address nobody holds a key for. Outputs sent here are destroyed.
+pub const BURN_ADDRESS: &str = "burn";
+// Amounts are integer base units, and one coin is 10^COIN_DECIMALS of them.
+pub const COIN_DECIMALS: u32 = 8;
+// The number of base units in one coin.
+pub const COIN: u64 = 10u64.pow(COIN_DECIMALS);
+// The most inputs a transaction may spend.
+pub const MAX_TX_INPUTS: usize = 1_000;
+// The most outputs a transaction may create.
+pub const MAX_TX_OUTPUTS: usize = 1_000;
+// The most serialized bytes of transactions a block may carry. The miner
+// packs fewer pooled ones, leaving room for the coinbase and payouts.
+pub const MAX_BLOCK_SIZE: usize = 2_000_000;
 
+/// Consensus limits on what blocks may contain, and how this node builds
+/// new ones. Every node on a chain must use the same limits; tests tighten
+/// them to exercise the checks.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChainParams {
+    /// The most inputs a transaction may spend.
+    pub max_tx_inputs: usize,
+    /// The most outputs a transaction may create.
+    pub max_tx_outputs: usize,
+    /// The most serialized bytes of transactions a block may carry.
+    pub max_block_size: usize,
+    /// The lowest difficulty adjustments may lower the chain to.
+    pub min_difficulty: usize,
+    /// The highest difficulty adjustments may raise the chain to, so that a
+    /// passing burst of hashrate can't leave it unminable.
+    pub max_difficulty: usize,
+    /// The generator new blocks' fractals are perturbed with. Received
+    /// blocks may use any known version, as long as they declare it.
+    pub fractal_rng: RngVersion,
+}
+
+impl Default for ChainParams {
+    fn default() -> Self {
+        Self {
+            max_tx_inputs: MAX_TX_INPUTS,
+            max_tx_outputs: MAX_TX_OUTPUTS,
+            max_block_size: MAX_BLOCK_SIZE,
+            min_difficulty: MIN_DIFFICULTY,
+            max_difficulty: MAX_DIFFICULTY,
+            fractal_rng: RngVersion::CURRENT,
+        }
+    }
+}
+
+impl ChainParams {
+    /// Checks that `tx` has no more inputs or outputs than allowed, no memo
+    /// longer than `MAX_MEMO_BYTES`, and no coinbase message longer than
+    /// `MAX_COINBASE_MESSAGE_BYTES`.
+    pub fn check_transaction(&self, tx: &Transaction) -> Result<(), TxError> {
+        if tx.inputs.len() > self.max_tx_inputs {
+            return Err(TxError::TooManyInputs { count: tx.inputs.len(), max: self.max_tx_inputs });
+        }
+        if tx.outputs.len() > self.max_tx_outputs {
+            return Err(TxError::TooManyOutputs { count: tx.outputs.len(), max: self.max_tx_outputs });
+        }
+        if let Some(memo) = tx.memo.as_ref().filter(|memo| memo.len() > MAX_MEMO_BYTES) {
+            return Err(TxError::MemoTooLong { bytes: memo.len(), max: MAX_MEMO_BYTES });
+        }
+        if let Some(message) = tx.coinbase_message() {
+            check_coinbase_message(message)?;
+        }
+        Ok(())
+    }
+
+    /// Returns `target` moved into the range adjustments may reach.
+    pub fn clamp_target(&self, target: Target) -> Target {
+        target.clamp(Target::from_difficulty(self.max_difficulty), Target::from_difficulty(self.min_difficulty))
+    }
+}
+
+/// How the amounts on a chain are denominated. This is a consensus
+/// parameter: it fixes the block reward, so it can't change once a chain
+/// has started.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum Denomination {
+    /// Amounts are whole coins. Chains saved before base units were
+    /// introduced have no denomination recorded and keep using this one.
+    #[default]
+    WholeCoins,
+    /// Amounts are base units of `1 / COIN` coins.
+    BaseUnits,
+}
+
+impl Denomination {
+    /// The number of decimal places a coin amount can have.
+    pub fn decimals(self) -> u32 {
+        match self {
+            Denomination::WholeCoins => 0,
+            Denomination::BaseUnits => COIN_DECIMALS,
+        }
+    }
+
+    /// The amount each block's coinbase creates, before fees.
+    pub fn block_reward(self) -> u64 {
+        match self {
+            Denomination::WholeCoins => BLOCK_REWARD_COINS,
+            Denomination::BaseUnits => BLOCK_REWARD_COINS * COIN,
+        }
+    }
+}
+
+/// How each block's new coins are split. The subsidy halves every
+/// `halving_interval` blocks; the dev fund and the burn address take fixed
+/// percentages of it, and the miner gets the rest, so the outputs always add
+/// up to the whole subsidy.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RewardSchedule {
+    /// The subsidy of the first block, in the chain's units.
+    pub initial_subsidy: u64,
+    /// The number of blocks between halvings, or 0 to never halve.
+    pub halving_interval: u64,
+    pub dev_fund_address: Option<String>,
+    pub dev_fund_percent: u8,
+    /// Must be `BURN_ADDRESS` if set: it's the only address whose outputs
+    /// are guaranteed to be unspendable.
+    pub burn_address: Option<String>,
+    pub burn_percent: u8,
+}
+
+impl Default for RewardSchedule {
+    fn default() -> Self {
+        Self::flat(Denomination::BaseUnits.block_reward())
+    }
+}
+
+impl RewardSchedule {
+    /// A schedule that pays `subsidy` to the miner of every block.
+    pub fn flat(subsidy: u64) -> Self {
+        Self {
+            initial_subsidy: subsidy,
+            halving_interval: 0,
+            dev_fund_address: None,
+            dev_fund_percent: 0,
+            burn_address: None,
+            burn_percent: 0,
+        }
+    }
+
+    /// Checks that the percentages fit in the subsidy and that every share
+    /// has somewhere to go.
+    pub fn validate(&self) -> Result<(), String> {
+        if u16::from(self.dev_fund_percent) + u16::from(self.burn_percent) > 100 {
+            return Err("dev_fund_percent and burn_percent add up to more than 100".to_string());
+        }
+        if self.dev_fund_percent > 0 && self.dev_fund_address.is_none() {
+            return Err("dev_fund_percent is set without a dev_fund_address".to_string());
+        }
+        if let Some(burn_address) = self.burn_address.as_deref().filter(|address| *address != BURN_ADDRESS) {
+            return Err(format!("burn_address must be {:?}, not {:?}: other addresses could be spent", BURN_ADDRESS, burn_address));
+        }
+        Ok(())
+    }
+
+    /// The new coins created by the block at `height`.
+    pub fn subsidy(&self, height: u64) -> u64 {
+        let halvings = height.checked_div(self.halving_interval).unwrap_or(0);
+        self.initial_subsidy.checked_shr(halvings.try_into().unwrap_or(u32::MAX)).unwrap_or(0)
+    }
+
+    /// The part of the subsidy at `height` that goes to the miner.
+    pub fn miner_share(&self, height: u64) -> u64 {
+        self.coinbase_outputs(height, "")[0].value
+    }
+
+    /// The coinbase outputs for the block at `height`, before fees: the
+    /// miner's share first, then the dev fund's and the burned share when
+    /// they aren't zero. Rounding remainders go to the miner.
+    pub fn coinbase_outputs(&self, height: u64, miner_address: &str) -> Vec<TxOutput> {
+        let subsidy = self.subsidy(height);
+        let share = |percent: u8| (u128::from(subsidy) * u128::from(percent) / 100) as u64;
+        let dev_fund = self.dev_fund_address.as_ref().map_or(0, |_| share(self.dev_fund_percent));
+        let burned = share(self.burn_percent);
+
+        let mut outputs = vec![TxOutput { value: subsidy - dev_fund - burned, script_pub_key: miner_address.to_string().into() }];
+        if let Some(address) = self.dev_fund_address.as_ref().filter(|_| dev_fund > 0) {
+            outputs.push(TxOutput { value: dev_fund, script_pub_key: address.clone().into() });
+        }
+        if burned > 0 {
+            let address = self.burn_address.clone().unwrap_or_else(|| BURN_ADDRESS.to_string());
+            outputs.push(TxOutput { value: burned, script_pub_key: address.into() });
+        }
+        outputs
+    }
+}
+
+/// How each block's target is derived from the blocks before it.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum DifficultyAlgo {
+    /// Every `DIFFICULTY_ADJUSTMENT_INTERVAL` blocks, the target is scaled
+    /// by how long they took over the expected time, by at most
+    /// `MAX_RETARGET_FACTOR` either way.
+    #[default]
+    Legacy,
+    /// Every block, the target is scaled towards an exponential moving
+    /// average of block intervals over about `EMA_WINDOW` blocks. The target
+    /// itself carries the average from block to block.
+    Ema,
+}
+
+impl FromStr for DifficultyAlgo {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "legacy" => Ok(DifficultyAlgo::Legacy),
+            "ema" => Ok(DifficultyAlgo::Ema),
+            _ => Err(format!("Unknown difficulty algorithm {:?}, expected \"legacy\" or \"ema\"", s)),
+        }
+    }
+}
+
+/// The blocks removed from and added to the active chain by a reorganisation.
+#[derive(Debug, Clone, Default)]
+pub struct Reorg {
+    pub disconnected: Vec<Block>,
+    pub connected: Vec<Block>,
+}
+
+/// The error for building on a chain that has no blocks, not even a
+/// genesis block. Loading always leaves a genesis block, so only a bug
+/// elsewhere leads to it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct EmptyChain;
+
+impl fmt::Display for EmptyChain {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "The chain has no blocks to build on")
+    }
+}
+
+impl std::error::Error for EmptyChain {}
+
+/// Why a block can't be connected to the tip of the chain.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "reason", rename_all = "snake_case")]
+pub enum ChainError {
+    EmptyChain,
+    /// The block, or one it descends from, was invalidated by an admin.
+    Invalidated { hash: String },
+    /// The block builds on another block than the tip.
+    PrevHashMismatch { expected: String, got: String },
+    WrongIndex { expected: u64, got: u64 },
+    /// The block declares another target than the chain requires of it.
+    WrongTarget { expected: u32, got: u32 },
+    /// The block's hash doesn't meet its target.
+    PowBelowTarget { hash: String },
+    /// The block doesn't hash to the hash it declares.
+    HashMismatch { declared: String, calculated: String },
+    /// The fractal isn't the one its parameters generate.
+    FractalMismatch,
+    BadTimestamp(TimestampError),
+    /// The block's transactions take more bytes than the chain allows.
+    OversizedBlock { size: usize, max: usize },
+    /// A transaction appears twice, or spends an output of one after it.
+    TransactionOutOfOrder { txid: String },
+    /// A transaction can no longer be mined at the block's height.
+    ExpiredTransaction { txid: String, expiry_height: u64 },
+    /// The transaction at `index` in the block breaks a rule.
+    InvalidTransaction { index: usize, source: TxError },
+}
+
+/// What is wrong with a block's timestamp.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum TimestampError {
+    /// More than `MAX_FUTURE_BLOCK_SECS` ahead of local time.
+    TooFarInFuture { timestamp: i64, now: i64 },
+    /// Earlier than the block before it.
+    BeforeParent { timestamp: i64, parent: i64 },
+    /// Not past the median time past.
+    NotPastMedian { timestamp: i64, median: i64 },
+}
+
+impl ChainError {
+    /// Returns true if the block breaks the rules, as opposed to building on
+    /// another tip, having been invalidated locally or being early. Only
+    /// these count against the peer that sent it.
+    pub fn is_invalid(&self) -> bool {
+        !matches!(
+            self,
+            ChainError::EmptyChain
+                | ChainError::Invalidated { .. }
+                | ChainError::PrevHashMismatch { .. }
+                | ChainError::BadTimestamp(TimestampError::TooFarInFuture { .. })
+        )
+    }
+}
+
+impl fmt::Display for ChainError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ChainError::EmptyChain => write!(f, "{}", EmptyChain),
+            ChainError::Invalidated { hash } => write!(f, "Block {} was invalidated", hash),
+            ChainError::PrevHashMismatch { expected, got } => {
+                write!(f, "Block builds on {} instead of the tip {}", got, expected)
+            }
+            ChainError::WrongIndex { expected, got } => write!(f, "Block has index {} instead of {}", got, expected),
+            ChainError::WrongTarget { expected, got } => {
+                write!(f, "Block declares target bits {:#010x} instead of {:#010x}", got, expected)
+            }
+            ChainError::PowBelowTarget { hash } => write!(f, "Block hash {} doesn't meet its target", hash),
+            ChainError::HashMismatch { declared, calculated } => {
+                write!(f, "Block declares hash {} but hashes to {}", declared, calculated)
+            }
+            ChainError::FractalMismatch => write!(f, "Block's fractal doesn't match its parameters"),
+            ChainError::BadTimestamp(TimestampError::TooFarInFuture { timestamp, now }) => {
+                write!(f, "Block timestamp {} is {} seconds ahead of local time", timestamp, timestamp - now)
+            }
+            ChainError::BadTimestamp(TimestampError::BeforeParent { timestamp, parent }) => {
+                write!(f, "Block timestamp {} is before its parent's {}", timestamp, parent)
+            }
+            ChainError::BadTimestamp(TimestampError::NotPastMedian { timestamp, median }) => {
+                write!(f, "Block timestamp {} is not past the median time past {}", timestamp, median)
+            }
+            ChainError::OversizedBlock { size, max } => {
+                write!(f, "Block's transactions take {} bytes, more than the {} allowed", size, max)
+            }
+            ChainError::TransactionOutOfOrder { txid } => write!(f, "Transaction {} is out of order", txid),
+            ChainError::ExpiredTransaction { txid, expiry_height } => {
+                write!(f, "Transaction {} expired at height {}", txid, expiry_height)
+            }
+            ChainError::InvalidTransaction { index, source } => write!(f, "Transaction {}: {}", index, source),
+        }
+    }
+}
+
+impl std::error::Error for ChainError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ChainError::InvalidTransaction { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+impl From<EmptyChain> for ChainError {
+    fn from(_: EmptyChain) -> Self {
+        ChainError::EmptyChain
+    }
+}
+
+/// How one transaction moves an address's coins.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct AddressActivity {
+    pub txid: String,
+    /// The total of the outputs paying the address.
+    pub received: u64,
+    /// The total of the address's outputs the transaction spends.
+    pub sent: u64,
+    pub net_change: i64,
+}
+
+/// The result of re-checking a stored block, as returned by
+/// `/blocks/{index}/verify`.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+pub struct BlockVerification {
+    /// The block hashes to its stored `hash`.
+    pub hash_ok: bool,
+    /// The stored `hash` meets the block's target.
+    pub pow_ok: bool,
+    /// The block follows the one before it, by index and `previous_hash`.
+    pub link_ok: bool,
+    /// Every input is signed by the owner of the output it spends.
+    pub tx_sigs_ok: bool,
+    pub overall: bool,
+}
+
+/// A block the operator trusts, written `<index>:<hash>`. A fast sync
+/// takes the blocks up to it on trust and validates the ones after it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Checkpoint {
+    pub index: u64,
+    pub hash: String,
+}
+
+impl FromStr for Checkpoint {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (index, hash) = s.split_once(':').ok_or("Expected <index>:<hash>")?;
+        let index = index.parse().map_err(|_| format!("Invalid checkpoint index {:?}", index))?;
+        if hash.is_empty() {
+            return Err("Checkpoint hash is empty".to_string());
+        }
+        Ok(Self { index, hash: hash.to_string() })
+    }
+}
+
+impl fmt::Display for Checkpoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.index, self.hash)
+    }
+}
 
 /// Represents the blockchain.
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Blockchain {
     pub chain: Vec<Block>,
-    pub difficulty: usize,
+    #[serde(default)]
+    pub denomination: Denomination,
+    /// How block subsidies are split, from the node's config. Defaults to
+    /// paying the denomination's block reward to the miner.
+    #[serde(skip)]
+    pub reward_schedule: RewardSchedule,
+    #[serde(skip)]
+    pub params: ChainParams,
+    /// Unspent outputs indexed by address, derived from `chain`.
+    #[serde(skip)]
+    pub utxo_index: UtxoIndex,
+    /// Branches removed by `invalidate_block`, keyed by the hash of the
+    /// invalidated block, kept so that the invalidation can be undone.
+    #[serde(skip)]
+    invalidated: HashMap<String, Vec<Block>>,
+    /// Hashes of invalidated blocks dropped by `archive_branches`. They stay
+    /// invalid, and `reconsider_block` can no longer bring them back.
+    #[serde(skip)]
+    archived: HashSet<String>,
+    /// Timestamps new blocks and judges whether received ones are from the future.
+    #[serde(skip, default = "system_clock")]
+    pub clock: SharedClock,
+    #[serde(skip)]
+    pub difficulty_algo: DifficultyAlgo,
 }
 
 impl Blockchain {
-    /// Creates a new blockchain, loading from a file if it exists.
+    /// Creates a new blockchain, loading from a file if it exists. Tests use
+    /// this; the node itself calls `load` to learn why a saved chain was skipped.
     pub fn new(difficulty: usize) -> Self {
-        if let Ok(file_content) = fs::read_to_string(DB_FILE) {
-            if let Ok(mut blockchain) = serde_json::from_str::<Blockchain>(&file_content) {
-                println!("Loaded blockchain from {}", DB_FILE);
-                if blockchain.chain.is_empty() {
-                    blockchain.create_genesis_block();
+        Self::load(difficulty).0
+    }
+
+    /// Loads the blockchain saved in the data file, or creates a new one
+    /// if there is none. A file that exists but can't be read also gives a
+    /// new chain, along with the reason it couldn't be loaded.
+    pub fn load(difficulty: usize) -> (Self, Option<String>) {
+        let error = match fs::read_to_string(DB_FILE) {
+            Ok(file_content) => match serde_json::from_str::<Blockchain>(&file_content) {
+                Ok(mut blockchain) => {
+                    println!("Loaded blockchain from {}", DB_FILE);
+                    if blockchain.chain.is_empty() {
+                        blockchain.create_genesis_block(Target::from_difficulty(difficulty).to_compact());
+                    }
+                    blockchain.reward_schedule = RewardSchedule::flat(blockchain.denomination.block_reward());
+                    blockchain.utxo_index = UtxoIndex::rebuild(&blockchain.chain);
+                    return (blockchain, None);
                 }
-                return blockchain;
-            }
-        }
+                Err(e) => Some(format!("Couldn't parse {}: {}", DB_FILE, e)),
+            },
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+            Err(e) => Some(format!("Couldn't read {}: {}", DB_FILE, e)),
+        };
+
+        (Self::with_clock(difficulty, system_clock()), error)
+    }
 
+    /// Creates a new blockchain timestamped by `clock`, without reading the
+    /// data file. Its genesis block requires `difficulty` leading zeros, and
+    /// later blocks' targets are retargeted from there. With a fixed clock
+    /// the genesis block is the same every time.
+    pub fn with_clock(difficulty: usize, clock: SharedClock) -> Self {
         let mut blockchain = Blockchain {
             chain: Vec::new(),
-            difficulty,
+            denomination: Denomination::BaseUnits,
+            reward_schedule: RewardSchedule::flat(Denomination::BaseUnits.block_reward()),
+            params: ChainParams::default(),
+            utxo_index: UtxoIndex::default(),
+            invalidated: HashMap::new(),
+            archived: HashSet::new(),
+            clock,
+            difficulty_algo: DifficultyAlgo::Legacy,
         };
-        blockchain.create_genesis_block();
+        blockchain.create_genesis_block(Target::from_difficulty(difficulty).to_compact());
         blockchain
     }
 
-    /// Adjusts the mining difficulty based on the time it took to mine the last
-    /// `DIFFICULTY_ADJUSTMENT_INTERVAL` blocks.
-    ///
-    /// The difficulty is adjusted to keep the block generation time close to
-    /// `BLOCK_GENERATION_INTERVAL`.
-    pub fn adjust_difficulty(&mut self) {
-        let latest_block = self.chain.last().unwrap();
-        if latest_block.index % DIFFICULTY_ADJUSTMENT_INTERVAL == 0 && latest_block.index != 0 {
-            let previous_adjustment_block = &self.chain[(latest_block.index - DIFFICULTY_ADJUSTMENT_INTERVAL) as usize];
-            let time_taken = latest_block.timestamp - previous_adjustment_block.timestamp;
-            let expected_time = (DIFFICULTY_ADJUSTMENT_INTERVAL as i64) * BLOCK_GENERATION_INTERVAL;
-
-            if time_taken < expected_time / 2 {
-                self.difficulty += 1;
-                println!("Difficulty increased to {}", self.difficulty);
-            } else if time_taken > expected_time * 2 {
-                if self.difficulty > 1 {
-                    self.difficulty -= 1;
-                    println!("Difficulty decreased to {}", self.difficulty);
-                }
-            }
-        }
+    /// Creates the genesis block for the blockchain, with target `bits`.
+    fn create_genesis_block(&mut self, bits: u32) {
+        self.create_genesis_block_with_premine(Vec::new(), bits);
     }
 
-    /// Creates the genesis block for the blockchain.
-    fn create_genesis_block(&mut self) {
-        let coinbase_tx = Transaction::new(
+    /// Creates the genesis block, with `premine` paid out by its coinbase
+    /// after the subsidy. The outputs are part of the block's hash.
+    fn create_genesis_block_with_premine(&mut self, premine: Vec<TxOutput>, bits: u32) {
+        let mut outputs = vec![TxOutput {
+            value: self.reward_schedule.subsidy(0),
+            script_pub_key: GENESIS_ADDRESS.into(), // Placeholder
+        }];
+        outputs.extend(premine);
+        let coinbase_tx = Transaction::new_at(
             vec![TxInput {
                 txid: "0".repeat(64),
                 vout: usize::MAX,
-                script_sig: String::from("genesis"),
-                pub_key: String::new(),
+                script_sig: UnlockingScript::Data("genesis".to_string()),
                 sequence: 0,
             }],
-            vec![TxOutput {
-                value: 50,
-                script_pub_key: String::from("genesis_address"), // Placeholder
-            }],
+            outputs,
+            self.clock.now(),
         );
 
-        let genesis_fractal = FractalType::Sierpinski { depth: 0, seed: 0 }.generate();
+        // Generated with the LCG, as it was before there was a choice, so the
+        // genesis hash stays the same.
+        let genesis_fractal = FractalType::Sierpinski { depth: 0, seed: 0 }.generate_with(RngVersion::Lcg);
         let genesis_block = Block {
             index: 0,
-            timestamp: Utc::now().timestamp(),
+            timestamp: self.clock.now(),
             fractal: genesis_fractal,
             transactions: vec![coinbase_tx],
             previous_hash: "0".to_string(),
             hash: String::new(),
             nonce: 0,
+            bits,
+            style_seed: 0,
         };
         let genesis_fractal_type = FractalType::Sierpinski { depth: 0, seed: 0 };
-        let mined_genesis = Miner::mine_block(self.difficulty, genesis_fractal_type, genesis_block);
+        let mined_genesis = Miner::mine_block(genesis_fractal_type, genesis_block, MiningBudget::UNLIMITED)
+            .found()
+            .expect("ran out of nonces");
+        self.utxo_index.apply_block(&mined_genesis);
         self.chain.push(mined_genesis);
     }
 
-    /// Adds a new block to the blockchain and returns it.
-    pub fn add_block(&mut self, fractal_type: FractalType, transactions: Vec<Transaction>) -> Block {
-        let previous_block = self.chain.last().unwrap().clone();
-        let new_block = Block {
+    /// Returns the outputs the genesis block pre-mines.
+    pub fn premine(&self) -> &[TxOutput] {
+        self.chain.first().map_or(&[], |genesis| &genesis.transactions[0].outputs[1..])
+    }
+
+    /// Rebuilds the genesis block to pre-mine `premine`, for bootstrapping
+    /// test networks with funded addresses. Every address must be valid and
+    /// the amounts must add up to at most `PREMINE_CAP_COINS`. A chain that
+    /// has grown past a genesis block with other outputs is refused, since
+    /// replacing its genesis would orphan every block.
+    pub fn set_premine(&mut self, premine: Vec<TxOutput>) -> Result<(), String> {
+        if let Some(output) = premine.iter().find(|output| !Wallet::is_valid_address(output.script_pub_key.address())) {
+            return Err(format!("Invalid premine address {}", output.script_pub_key.address()));
+        }
+        let cap = PREMINE_CAP_COINS * 10u64.pow(self.denomination.decimals());
+        let total = premine.iter().try_fold(0u64, |total, output| total.checked_add(output.value));
+        if total.is_none_or(|total| total > cap) {
+            return Err(format!("The premine exceeds the cap of {} coins", PREMINE_CAP_COINS));
+        }
+        if self.premine() == premine.as_slice() {
+            return Ok(());
+        }
+        if self.chain.len() > 1 {
+            return Err(format!("The chain was started with a different premine; remove {} to start over", DB_FILE));
+        }
+        let bits = self.chain.first().map_or_else(|| self.target_bits(), |genesis| genesis.bits);
+        self.chain.clear();
+        self.utxo_index = UtxoIndex::default();
+        self.create_genesis_block_with_premine(premine, bits);
+        Ok(())
+    }
+
+    /// Returns the compact target for the next block, before it is weighted
+    /// by the block's fractal.
+    pub fn target_bits(&self) -> u32 {
+        self.bits_after(&self.chain)
+    }
+
+    /// Returns how many leading zero hex digits the next block's target
+    /// requires, rounding towards the easier target.
+    pub fn difficulty(&self) -> usize {
+        Target::from_compact(self.target_bits()).difficulty()
+    }
+
+    /// Returns the compact target for the block after `chain`, derived from
+    /// the chain alone with `difficulty_algo` and kept within the bounds in
+    /// `params`. It only moves each block's work factor along with it, so
+    /// every fractal type gets harder or easier together.
+    pub fn bits_after(&self, chain: &[Block]) -> u32 {
+        let Some(tip) = chain.last() else {
+            return Target::from_difficulty(self.params.min_difficulty).to_compact();
+        };
+        let target = Target::from_compact(tip.bits);
+        let target = match self.difficulty_algo {
+            DifficultyAlgo::Legacy => Self::retarget_legacy(chain, target),
+            DifficultyAlgo::Ema => Self::retarget_ema(chain, target),
+        };
+        self.params.clamp_target(target).to_compact()
+    }
+
+    /// Scales the tip's `target` by how long the last
+    /// `DIFFICULTY_ADJUSTMENT_INTERVAL` blocks took over the expected time,
+    /// if the tip ends such an interval, by at most `MAX_RETARGET_FACTOR`
+    /// either way. Otherwise the target stays as it is.
+    fn retarget_legacy(chain: &[Block], target: Target) -> Target {
+        let tip = &chain[chain.len() - 1];
+        if tip.index == 0 || !tip.index.is_multiple_of(DIFFICULTY_ADJUSTMENT_INTERVAL) {
+            return target;
+        }
+        let Some(previous_adjustment_block) = chain.get((tip.index - DIFFICULTY_ADJUSTMENT_INTERVAL) as usize) else {
+            return target;
+        };
+        let expected_time = (DIFFICULTY_ADJUSTMENT_INTERVAL as i64) * BLOCK_GENERATION_INTERVAL;
+        let time_taken = (tip.timestamp - previous_adjustment_block.timestamp)
+            .clamp(expected_time / MAX_RETARGET_FACTOR, expected_time * MAX_RETARGET_FACTOR);
+        target.scaled(time_taken as u64, expected_time as u64)
+    }
+
+    /// Folds the tip's interval into the moving average the tip's `target`
+    /// carries: the target moves a `1 / EMA_WINDOW` part of the way to the
+    /// one that interval would have called for. Intervals count as at most
+    /// `MAX_RETARGET_FACTOR` expected ones, so one slow block can't undo a
+    /// run of fast ones.
+    fn retarget_ema(chain: &[Block], target: Target) -> Target {
+        let [previous_block, latest_block] = &chain[chain.len().saturating_sub(2)..] else {
+            return target;
+        };
+        let interval = (latest_block.timestamp - previous_block.timestamp).clamp(0, BLOCK_GENERATION_INTERVAL * MAX_RETARGET_FACTOR);
+        target.scaled(
+            ((EMA_WINDOW - 1) * BLOCK_GENERATION_INTERVAL + interval) as u64,
+            (EMA_WINDOW * BLOCK_GENERATION_INTERVAL) as u64,
+        )
+    }
+
+    /// Returns the median timestamp of the last `MEDIAN_TIME_SPAN` blocks.
+    /// The next block's timestamp must be later than it, so a miner can't
+    /// pull the chain's time back to lower the difficulty.
+    pub fn median_time_past(&self) -> i64 {
+        let mut timestamps: Vec<i64> = self.chain.iter().rev().take(MEDIAN_TIME_SPAN).map(|block| block.timestamp).collect();
+        timestamps.sort_unstable();
+        timestamps.get(timestamps.len() / 2).copied().unwrap_or(i64::MIN)
+    }
+
+    /// Returns the block to mine on top of the tip, with a placeholder
+    /// fractal, nonce 0 and no hash, committing to `fractal_type`'s seed as
+    /// its style seed. Its timestamp is the clock's, or just past the median
+    /// time past if the clock is behind it.
+    pub fn next_block(&self, fractal_type: &FractalType, transactions: Vec<Transaction>) -> Result<Block, EmptyChain> {
+        let previous_block = self.tip()?;
+        Ok(Block {
             index: previous_block.index + 1,
-            timestamp: Utc::now().timestamp(),
-            fractal: fractal_type.generate(), // Placeholder, miner will generate
+            timestamp: self.clock.now().max(self.median_time_past().saturating_add(1)),
+            // Placeholder, miner will generate with the same RNG version
+            fractal: fractal_type.generate_with(self.params.fractal_rng),
             transactions,
             previous_hash: previous_block.hash.clone(),
             hash: String::new(),
             nonce: 0,
+            bits: self.target_bits(),
+            style_seed: fractal_type.seed(),
+        })
+    }
+
+    /// Returns the block at the tip of the active chain.
+    pub fn tip(&self) -> Result<&Block, EmptyChain> {
+        self.chain.last().ok_or(EmptyChain)
+    }
+
+    /// Mines a new block on top of the tip, adds it to the blockchain and
+    /// returns it.
+    pub fn add_block(&mut self, fractal_type: FractalType, transactions: Vec<Transaction>) -> Result<Block, EmptyChain> {
+        let outcome = self.mine_next_block(fractal_type, transactions, MiningBudget::UNLIMITED)?;
+        Ok(outcome.found().expect("ran out of nonces"))
+    }
+
+    /// Like `add_block`, but gives up once `budget` runs out, leaving the
+    /// chain as it was.
+    pub fn mine_next_block(
+        &mut self,
+        fractal_type: FractalType,
+        transactions: Vec<Transaction>,
+        budget: MiningBudget,
+    ) -> Result<MineOutcome, EmptyChain> {
+        let new_block = self.next_block(&fractal_type, transactions)?;
+        let outcome = Miner::mine_block(fractal_type, new_block, budget);
+        if let MineOutcome::Found(mined_block) = &outcome {
+            self.utxo_index.apply_block(mined_block);
+            self.chain.push(mined_block.clone());
+        }
+        Ok(outcome)
+    }
+
+    /// Connects a block received from a peer or an external miner to the
+    /// tip, once `validate_block` accepts it.
+    pub fn add_block_from_network(&mut self, block: Block) -> Result<(), ChainError> {
+        self.validate_block(&block)?;
+        self.utxo_index.apply_block(&block);
+        self.chain.push(block);
+        Ok(())
+    }
+
+    /// Replaces the whole chain, e.g. with one received from a peer,
+    /// and rebuilds the UTXO index to match. An empty chain is refused, as
+    /// it would leave nothing to build on.
+    pub fn replace_chain(&mut self, chain: Vec<Block>) {
+        if chain.is_empty() {
+            tracing::error!("Refusing to replace the chain with an empty one");
+            return;
+        }
+        self.utxo_index = UtxoIndex::rebuild(&chain);
+        self.chain = chain;
+    }
+
+    /// Builds the blockchain `candidate` would make active: the blocks it
+    /// shares with the active chain, then its own after them, each validated
+    /// in full as it is connected, as if they had arrived one by one. The
+    /// first block that fails is returned by index with the reason. The
+    /// active chain is left as it is either way.
+    pub fn connect_candidate(&self, candidate: Vec<Block>) -> Result<Blockchain, (u64, ChainError)> {
+        let shared = self.chain.iter().zip(&candidate).take_while(|(ours, theirs)| ours.hash == theirs.hash).count();
+        let mut connected = self.clone();
+        connected.chain.truncate(shared);
+        connected.utxo_index = UtxoIndex::rebuild(&connected.chain);
+        for block in candidate.into_iter().skip(shared) {
+            let index = block.index;
+            connected.add_block_from_network(block).map_err(|e| (index, e))?;
+        }
+        Ok(connected)
+    }
+
+    /// How many blocks of the active chain replacing it with `candidate`
+    /// would disconnect: those after the last block both chains share.
+    pub fn reorg_depth(&self, candidate: &[Block]) -> u64 {
+        let shared = self.chain.iter().zip(candidate).take_while(|(ours, theirs)| ours.hash == theirs.hash).count();
+        (self.chain.len() - shared) as u64
+    }
+
+    /// The hashes expected to find `chain`: the sum of the work each of
+    /// its blocks' targets calls for. Fractal work factors are left out, so
+    /// a chain of cheap fractals counts for no more than its targets do.
+    pub fn chain_work(chain: &[Block]) -> Work {
+        chain.iter().map(|block| Target::from_compact(block.bits).work()).sum()
+    }
+
+    /// Fork choice: whether a competing chain with `work` in total (see
+    /// `chain_work`) ending in `tip_hash` should replace the active one.
+    /// The chain with the most work wins, however many blocks it has, and
+    /// between chains with as much the one whose tip hash sorts first, so
+    /// that nodes which received the tips in different orders still settle
+    /// on the same one.
+    pub fn prefers_tip(&self, work: Work, tip_hash: &str) -> bool {
+        match work.cmp(&Self::chain_work(&self.chain)) {
+            Ordering::Greater => true,
+            Ordering::Equal => self.chain.last().is_some_and(|tip| tip_hash < tip.hash.as_str()),
+            Ordering::Less => false,
+        }
+    }
+
+    /// Returns true if the active chain contains `checkpoint`.
+    pub fn contains_checkpoint(&self, checkpoint: &Checkpoint) -> bool {
+        self.chain.get(checkpoint.index as usize).is_some_and(|block| block.hash == checkpoint.hash)
+    }
+
+    /// Returns the blocks from `from_index` to the tip.
+    pub fn blocks_from(&self, from_index: u64) -> Vec<Block> {
+        self.chain.get(from_index as usize..).unwrap_or_default().to_vec()
+    }
+
+    /// Replaces the active chain with one that contains `checkpoint`, built
+    /// from the local blocks before the first of `blocks` followed by
+    /// `blocks`. Blocks up to the checkpoint are only checked to match their
+    /// hashes and link up to it, skipping the costly proof-of-work, fractal
+    /// and amount checks; the blocks after it are validated in full.
+    pub fn import_from_checkpoint(&mut self, checkpoint: &Checkpoint, blocks: Vec<Block>) -> Result<(), String> {
+        let (Some(first), Some(last)) = (blocks.first(), blocks.last()) else {
+            return Err("No blocks to import".to_string());
         };
-        let mined_block = Miner::mine_block(self.difficulty, fractal_type, new_block);
-        self.chain.push(mined_block.clone());
-        self.adjust_difficulty();
-        mined_block
+        let start = first.index as usize;
+        if start > self.chain.len() {
+            return Err(format!("Missing the blocks before {}", start));
+        }
+        let work = Self::chain_work(&self.chain[..start]) + Self::chain_work(&blocks);
+        if !self.prefers_tip(work, &last.hash) {
+            return Err("The imported chain doesn't beat the active one".to_string());
+        }
+
+        let mut candidate = self.clone();
+        candidate.chain.truncate(start);
+        let trusted = (checkpoint.index as usize + 1).saturating_sub(start).min(blocks.len());
+        let mut blocks = blocks.into_iter();
+        for block in blocks.by_ref().take(trusted) {
+            let links = match candidate.chain.last() {
+                Some(previous) => block.index == previous.index + 1 && block.previous_hash == previous.hash,
+                None => block.index == 0,
+            };
+            if !links || block.hash != block.calculate_hash() {
+                return Err(format!("Block {} doesn't link up to the checkpoint", block.index));
+            }
+            candidate.chain.push(block);
+        }
+        if !candidate.contains_checkpoint(checkpoint) {
+            return Err(format!("The chain doesn't contain checkpoint {}", checkpoint));
+        }
+
+        candidate.utxo_index = UtxoIndex::rebuild(&candidate.chain);
+        for block in blocks {
+            let index = block.index;
+            if let Err(e) = candidate.add_block_from_network(block) {
+                return Err(format!("Block {} after the checkpoint is invalid: {}", index, e));
+            }
+        }
+        *self = candidate;
+        Ok(())
     }
 
-    pub fn add_block_from_network(&mut self, block: Block) -> bool {
-        let previous_block = self.chain.last().unwrap();
-        if self.is_block_valid(&block, previous_block) {
-            self.chain.push(block);
-            self.adjust_difficulty();
-            true
-        } else {
-            false
+    /// Marks a block in the active chain and all of its descendants invalid,
+    /// rolling the chain back to the block's parent. The genesis block can't
+    /// be invalidated.
+    pub fn invalidate_block(&mut self, hash: &str) -> Option<Reorg> {
+        let position = self.chain.iter().position(|block| block.hash == hash)?;
+        if position == 0 {
+            return None;
+        }
+        let disconnected = self.chain.split_off(position);
+        self.utxo_index = UtxoIndex::rebuild(&self.chain);
+        self.invalidated.insert(hash.to_string(), disconnected.clone());
+        Some(Reorg { disconnected, connected: Vec::new() })
+    }
+
+    /// Undoes `invalidate_block`. The branch it removed is reconnected if it
+    /// still extends the active chain further than the blocks mined on top of
+    /// its parent since; otherwise it is simply forgotten.
+    pub fn reconsider_block(&mut self, hash: &str) -> Option<Reorg> {
+        let branch = self.invalidated.remove(hash)?;
+        let fork = branch[0].index as usize;
+        let still_attached = self.chain.get(fork - 1).is_some_and(|parent| parent.hash == branch[0].previous_hash);
+        if !still_attached || fork + branch.len() <= self.chain.len() {
+            return Some(Reorg::default());
+        }
+        let disconnected = self.chain.split_off(fork);
+        self.chain.extend(branch.iter().cloned());
+        self.utxo_index = UtxoIndex::rebuild(&self.chain);
+        Some(Reorg { disconnected, connected: branch })
+    }
+
+    /// Returns true if `hash` belongs to a block removed by `invalidate_block`,
+    /// whether or not its branch has been archived since.
+    fn is_invalidated(&self, hash: &str) -> bool {
+        self.archived.contains(hash) || self.invalidated.values().flatten().any(|block| block.hash == hash)
+    }
+
+    /// Returns the branches removed by `invalidate_block` whose fork point is
+    /// more than `max_reorg_depth` blocks below the tip, keyed by the hash of
+    /// the invalidated block. Reconnecting one would take a deeper reorg than
+    /// that, so they are only kept for the record.
+    pub fn stale_branches(&self, max_reorg_depth: u64) -> Vec<(String, Vec<Block>)> {
+        let tip = (self.chain.len() as u64).saturating_sub(1);
+        let mut stale: Vec<(String, Vec<Block>)> = self
+            .invalidated
+            .iter()
+            .filter(|(_, branch)| tip.saturating_sub(branch[0].index - 1) > max_reorg_depth)
+            .map(|(hash, branch)| (hash.clone(), branch.clone()))
+            .collect();
+        stale.sort_by_key(|(_, branch)| branch[0].index);
+        stale
+    }
+
+    /// Drops the invalidated branches keyed by `hashes`, remembering their
+    /// blocks as archived.
+    pub fn archive_branches(&mut self, hashes: &[String]) {
+        for hash in hashes {
+            if let Some(branch) = self.invalidated.remove(hash) {
+                self.archived.extend(branch.into_iter().map(|block| block.hash));
+            }
+        }
+    }
+
+    /// Returns true if `hash` belongs to a branch dropped by `archive_branches`.
+    pub fn is_archived(&self, hash: &str) -> bool {
+        self.archived.contains(hash)
+    }
+
+    /// How many blocks removed by `invalidate_block` are still kept.
+    pub fn side_chain_block_count(&self) -> usize {
+        self.invalidated.values().map(Vec::len).sum()
+    }
+
+    /// The blocks removed by `invalidate_block` that are still kept, branch
+    /// by branch.
+    pub fn side_chain_blocks(&self) -> impl Iterator<Item = &Block> {
+        self.invalidated.values().flatten()
+    }
+
+    /// Checks that `block` may be connected to the tip: that it builds on
+    /// it, meets its target and hashes as declared, that its fractal and
+    /// timestamp are right, and that its transactions are within the limits,
+    /// unexpired, in order and don't pay out more than they spend.
+    pub fn validate_block(&self, block: &Block) -> Result<(), ChainError> {
+        if self.is_invalidated(&block.hash) {
+            return Err(ChainError::Invalidated { hash: block.hash.clone() });
+        }
+        let previous_block = self.tip()?;
+        if block.previous_hash != previous_block.hash {
+            return Err(ChainError::PrevHashMismatch { expected: previous_block.hash.clone(), got: block.previous_hash.clone() });
+        }
+        if block.index != previous_block.index + 1 {
+            return Err(ChainError::WrongIndex { expected: previous_block.index + 1, got: block.index });
+        }
+        if block.bits != self.target_bits() {
+            return Err(ChainError::WrongTarget { expected: self.target_bits(), got: block.bits });
         }
+        if !block.target().is_met_by(&block.hash) {
+            return Err(ChainError::PowBelowTarget { hash: block.hash.clone() });
+        }
+        let calculated = block.calculate_hash();
+        if block.hash != calculated {
+            return Err(ChainError::HashMismatch { declared: block.hash.clone(), calculated });
+        }
+        if !block.has_valid_fractal() {
+            return Err(ChainError::FractalMismatch);
+        }
+        let size = block.transactions.iter().map(Transaction::size).sum::<usize>();
+        if size > self.params.max_block_size {
+            return Err(ChainError::OversizedBlock { size, max: self.params.max_block_size });
+        }
+        for (index, tx) in block.transactions.iter().enumerate() {
+            if let Some(expiry_height) = tx.expiry_height.filter(|_| tx.is_expired_at(block.index)) {
+                return Err(ChainError::ExpiredTransaction { txid: tx.id.clone(), expiry_height });
+            }
+            self.params.check_transaction(tx).map_err(|source| ChainError::InvalidTransaction { index, source })?;
+        }
+        Self::check_transaction_order(block)?;
+        self.check_timestamp(block, previous_block)?;
+        self.check_amounts(block)
     }
 
-    /// Validates a block.
-    fn is_block_valid(&self, new_block: &Block, previous_block: &Block) -> bool {
-        if new_block.index != previous_block.index + 1 {
-            return false;
+    fn check_timestamp(&self, block: &Block, previous_block: &Block) -> Result<(), ChainError> {
+        let now = self.clock.now();
+        if block.timestamp > now + MAX_FUTURE_BLOCK_SECS {
+            return Err(ChainError::BadTimestamp(TimestampError::TooFarInFuture { timestamp: block.timestamp, now }));
         }
-        if new_block.previous_hash != previous_block.hash {
-            return false;
+        if block.timestamp < previous_block.timestamp {
+            return Err(ChainError::BadTimestamp(TimestampError::BeforeParent {
+                timestamp: block.timestamp,
+                parent: previous_block.timestamp,
+            }));
         }
-        let prefix = "0".repeat(self.difficulty);
-        if !new_block.hash.starts_with(&prefix) || new_block.hash != new_block.calculate_hash() {
-            return false;
+        let median = self.median_time_past();
+        if block.timestamp <= median {
+            return Err(ChainError::BadTimestamp(TimestampError::NotPastMedian { timestamp: block.timestamp, median }));
         }
-        // Timestamp validation
-        let now = Utc::now().timestamp();
-        if new_block.timestamp > now + 30 { // 30 seconds tolerance for future blocks
-            return false;
+        Ok(())
+    }
+
+    /// Re-checks the block at `index` on the active chain, or returns `None`
+    /// if there is none. Inputs are checked against the outputs they spent
+    /// then, even if those have been spent again since.
+    pub fn verify_block(&self, index: usize) -> Option<BlockVerification> {
+        let block = self.chain.get(index)?;
+        let hash_ok = block.hash == block.calculate_hash();
+        let pow_ok = block.target().is_met_by(&block.hash);
+        let link_ok = match index.checked_sub(1).map(|previous| &self.chain[previous]) {
+            Some(previous) => block.index == previous.index + 1 && block.previous_hash == previous.hash,
+            None => block.index == 0,
+        };
+        let created: HashMap<&str, &Transaction> =
+            self.chain[..=index].iter().flat_map(|block| &block.transactions).map(|tx| (tx.id.as_str(), tx)).collect();
+        let spent = |input: &TxInput| {
+            created.get(input.txid.as_str()).and_then(|tx| tx.outputs.get(input.vout)).map(|output| &output.script_pub_key)
+        };
+        let tx_sigs_ok = block.transactions.iter().filter(|tx| !tx.is_coinbase()).all(|tx| tx.verify(spent).is_ok());
+        Some(BlockVerification { hash_ok, pow_ok, link_ok, tx_sigs_ok, overall: hash_ok && pow_ok && link_ok && tx_sigs_ok })
+    }
+
+    /// Checks that a transaction spending an output created in the same block
+    /// comes after the transaction creating it, and that no transaction
+    /// appears twice.
+    fn check_transaction_order(block: &Block) -> Result<(), ChainError> {
+        let in_block: HashSet<&str> = block.transactions.iter().map(|tx| tx.id.as_str()).collect();
+        let mut earlier = HashSet::new();
+        for tx in &block.transactions {
+            let spends_later = tx
+                .inputs
+                .iter()
+                .any(|input| in_block.contains(input.txid.as_str()) && !earlier.contains(input.txid.as_str()));
+            if spends_later || !earlier.insert(tx.id.as_str()) {
+                return Err(ChainError::TransactionOutOfOrder { txid: tx.id.clone() });
+            }
         }
-        if new_block.timestamp < previous_block.timestamp {
-            return false;
+        Ok(())
+    }
+
+    /// Checks that no transaction in `block` pays out more than it spends.
+    /// Transactions may spend outputs created earlier in the same block.
+    fn check_amounts(&self, block: &Block) -> Result<(), ChainError> {
+        let mut utxos = self.utxo_index.clone();
+        for (index, tx) in block.transactions.iter().enumerate() {
+            tx.validate_amounts_against(&utxos).map_err(|source| ChainError::InvalidTransaction { index, source })?;
+            utxos.apply_transaction(tx);
         }
-        true
+        Ok(())
     }
 
     /// Returns the UTXOs for a given address.
     pub fn get_utxos(&self, address: &str) -> Vec<(String, usize, TxOutput)> {
-        let mut utxos = Vec::new();
-        let mut spent_txos = HashSet::new();
+        self.utxo_index.utxos_for(address)
+    }
 
-        for block in &self.chain {
-            for tx in &block.transactions {
-                for input in &tx.inputs {
-                    spent_txos.insert((input.txid.clone(), input.vout));
+    /// Builds and signs the transaction `request` asks for, spending the
+    /// senders' unspent outputs in order until the amount and fee are
+    /// covered, with any excess returned as change to the first sender.
+    /// Each input is signed by the wallet owning the output it spends.
+    /// Outputs for which `is_reserved` is true, e.g. those already spent by
+    /// pending transactions, are left alone.
+    pub fn build_transaction(
+        &self,
+        request: &BuildTransactRequest,
+        is_reserved: impl Fn(&OutPoint) -> bool,
+    ) -> Result<Transaction, TxError> {
+        let required = request.amount.checked_add(request.fee).ok_or(TxError::AmountTooLarge)?;
+        let mut inputs = vec![];
+        let mut signers = vec![];
+        let mut accumulated = 0u64;
+        let mut senders = HashSet::new();
+        'senders: for wallet in &request.wallets {
+            let address = wallet.get_address();
+            // A key given twice must not spend its outputs twice.
+            if !senders.insert(address.clone()) {
+                continue;
+            }
+            for (txid, vout, utxo) in self.get_utxos(&address) {
+                if is_reserved(&(txid.clone(), vout)) {
+                    continue;
+                }
+                inputs.push(TxInput { txid, vout, script_sig: UnlockingScript::default(), sequence: 0 });
+                signers.push(wallet);
+                accumulated = accumulated.saturating_add(utxo.value);
+                if accumulated >= required {
+                    break 'senders;
                 }
             }
         }
+        if accumulated < required {
+            return Err(TxError::InsufficientFunds { available: accumulated, required });
+        }
 
-        for block in &self.chain {
-            for tx in &block.transactions {
-                for (vout, output) in tx.outputs.iter().enumerate() {
-                    if output.script_pub_key == address {
-                        if !spent_txos.contains(&(tx.id.clone(), vout)) {
-                            utxos.push((tx.id.clone(), vout, output.clone()));
-                        }
-                    }
+        let mut outputs = vec![TxOutput { value: request.amount, script_pub_key: request.to.clone().into() }];
+        if accumulated > required {
+            let change_address = request.wallets[0].get_address();
+            outputs.push(TxOutput { value: accumulated - required, script_pub_key: change_address.into() });
+        }
+        let mut tx = Transaction::new(inputs, outputs).with_expiry_height(request.expiry_height).with_memo(request.memo.clone());
+        tx.sign_inputs(&signers);
+        Ok(tx)
+    }
+
+    /// Builds and signs a transaction moving every unspent output of
+    /// `wallet` that `is_reserved` leaves alone to `to`, less `fee`, with no
+    /// change.
+    pub fn build_sweep(
+        &self,
+        wallet: Wallet,
+        to: String,
+        fee: u64,
+        is_reserved: impl Fn(&OutPoint) -> bool,
+    ) -> Result<Transaction, TxError> {
+        let available = self
+            .get_utxos(&wallet.get_address())
+            .into_iter()
+            .filter(|(txid, vout, _)| !is_reserved(&(txid.clone(), *vout)))
+            .fold(0u64, |total, (_, _, utxo)| total.saturating_add(utxo.value));
+        let amount = available
+            .checked_sub(fee)
+            .filter(|amount| *amount > 0)
+            .ok_or(TxError::InsufficientFunds { available, required: fee.saturating_add(1) })?;
+        self.build_transaction(&BuildTransactRequest { wallets: vec![wallet], to, amount, fee, expiry_height: None, memo: None }, is_reserved)
+    }
+
+    /// Builds the transactions for a batch of requests without submitting
+    /// them, making sure no two of them spend the same output. Fails with
+    /// the position and reason of every request that can't be built, so a
+    /// batch is only accepted if all of it can be.
+    pub fn check_batch_feasibility(
+        &self,
+        requests: &[BuildTransactRequest],
+        is_reserved: impl Fn(&OutPoint) -> bool,
+    ) -> Result<Vec<Transaction>, Vec<(usize, TxError)>> {
+        let mut claimed: HashSet<OutPoint> = HashSet::new();
+        let mut built = Vec::with_capacity(requests.len());
+        let mut errors = Vec::new();
+        for (index, request) in requests.iter().enumerate() {
+            match self.build_transaction(request, |outpoint| is_reserved(outpoint) || claimed.contains(outpoint)) {
+                Ok(tx) => {
+                    claimed.extend(tx.inputs.iter().map(|input| (input.txid.clone(), input.vout)));
+                    built.push(tx);
                 }
+                Err(e) => errors.push((index, e)),
             }
         }
-
-        utxos
+        if errors.is_empty() { Ok(built) } else { Err(errors) }
     }
 
     /// Returns the balance for a given address.
     pub fn get_balance(&self, address: &str) -> u64 {
-        self.get_utxos(address)
+        self.utxo_index.balance(address)
+    }
+
+    /// Returns the unspent coins free to change hands: all of them except
+    /// the genesis coinbase and the coinbase outputs of the last
+    /// `COINBASE_MATURITY` blocks.
+    pub fn circulating_supply(&self) -> u64 {
+        let immature: u64 = self
+            .chain
+            .iter()
+            .rev()
+            .take(COINBASE_MATURITY)
+            .filter_map(|block| block.transactions.first().filter(|tx| tx.is_coinbase()))
+            .flat_map(|coinbase| (0..coinbase.outputs.len()).filter_map(|vout| self.utxo_index.get(&(coinbase.id.clone(), vout))))
+            .filter(|output| !output.script_pub_key.pays_to(GENESIS_ADDRESS))
+            .map(|output| output.value)
+            .sum();
+        self.utxo_index.supply().unspent.saturating_sub(self.get_balance(GENESIS_ADDRESS) + immature)
+    }
+
+    /// Returns the indices of the blocks whose coinbase pays `address`.
+    pub fn get_blocks_mined_by(&self, address: &str) -> Vec<u64> {
+        self.chain
+            .iter()
+            .filter(|block| {
+                block.transactions.first().is_some_and(|coinbase| {
+                    coinbase.is_coinbase() && coinbase.outputs.iter().any(|output| output.script_pub_key.pays_to(address))
+                })
+            })
+            .map(|block| block.index)
+            .collect()
+    }
+
+    /// Returns output `vout` of transaction `txid`, whether or not it has
+    /// since been spent, looking through `pending` before the chain.
+    pub fn find_output<'a>(&'a self, txid: &str, vout: usize, pending: &'a [Transaction]) -> Option<&'a TxOutput> {
+        pending
             .iter()
-            .map(|(_, _, utxo)| utxo.value)
-            .sum()
+            .chain(self.chain.iter().flat_map(|block| &block.transactions))
+            .find(|tx| tx.id == txid)
+            .and_then(|tx| tx.outputs.get(vout))
+    }
+
+    /// Returns how `tx` moves `address`'s coins, or `None` if it neither pays
+    /// nor spends from the address. Inputs may spend outputs of `pending`
+    /// transactions, e.g. earlier ones in the same block, as well as the chain's.
+    pub fn address_activity(&self, tx: &Transaction, address: &str, pending: &[Transaction]) -> Option<AddressActivity> {
+        let received: u64 = tx.outputs.iter().filter(|output| output.script_pub_key.pays_to(address)).map(|output| output.value).sum();
+        let spent: Vec<&TxOutput> = if tx.is_coinbase() {
+            Vec::new()
+        } else {
+            tx.inputs
+                .iter()
+                .filter_map(|input| self.find_output(&input.txid, input.vout, pending))
+                .filter(|output| output.script_pub_key.pays_to(address))
+                .collect()
+        };
+        let pays_address = tx.outputs.iter().any(|output| output.script_pub_key.pays_to(address));
+        if !pays_address && spent.is_empty() {
+            return None;
+        }
+        let sent: u64 = spent.iter().map(|output| output.value).sum();
+        Some(AddressActivity { txid: tx.id.clone(), received, sent, net_change: received as i64 - sent as i64 })
     }
 
     /// Saves the blockchain to a file.
@@ -199,27 +1215,201 @@ impl Blockchain {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::core::clock::{Clock, MockClock};
+    use crate::core::mempool::MempoolPool;
+    use crate::core::transaction::MAX_COINBASE_MESSAGE_BYTES;
     use crate::core::wallet::Wallet;
+    use crate::fractal::FractalData;
+    use std::collections::HashSet;
+    use std::sync::Arc;
+
+    /// Computes the UTXOs for `address` by scanning the whole chain.
+    fn scan_utxos(chain: &[Block], address: &str) -> Vec<(String, usize, TxOutput)> {
+        let mut spent_txos = HashSet::new();
+        for block in chain {
+            for tx in &block.transactions {
+                for input in &tx.inputs {
+                    spent_txos.insert((input.txid.clone(), input.vout));
+                }
+            }
+        }
+
+        let mut utxos = Vec::new();
+        for block in chain {
+            for tx in &block.transactions {
+                for (vout, output) in tx.outputs.iter().enumerate() {
+                    if output.script_pub_key.pays_to(address) && !spent_txos.contains(&(tx.id.clone(), vout)) {
+                        utxos.push((tx.id.clone(), vout, output.clone()));
+                    }
+                }
+            }
+        }
+        utxos.sort_by(|a, b| (&a.0, a.1).cmp(&(&b.0, b.1)));
+        utxos
+    }
+
+    /// Pushes a copy of the tip timestamped `timestamp`, declaring the target
+    /// the chain requires of it, without mining it.
+    fn push_at(blockchain: &mut Blockchain, timestamp: i64) {
+        let mut block = blockchain.chain.last().unwrap().clone();
+        block.index += 1;
+        block.timestamp = timestamp;
+        block.bits = blockchain.target_bits();
+        blockchain.chain.push(block);
+    }
+
+    /// Adds `blocks` blocks from a miner trying 64 nonces a second, so that a
+    /// block takes a quarter of a second at difficulty 1 and the 10 s target
+    /// lies between difficulties 2 and 3, and returns how long each took.
+    fn simulate_block_times(algo: DifficultyAlgo, blocks: usize) -> Vec<f64> {
+        let mut blockchain = Blockchain::new(1);
+        blockchain.chain.truncate(1);
+        blockchain.difficulty_algo = algo;
+        let start = blockchain.chain[0].timestamp;
+        let mut elapsed = 0.0;
+        let mut block_times = Vec::new();
+        for _ in 0..blocks {
+            let block_time = Target::from_compact(blockchain.target_bits()).work().saturating_u64() as f64 / 64.0;
+            elapsed += block_time;
+            push_at(&mut blockchain, start + elapsed as i64);
+            block_times.push(block_time);
+        }
+        block_times
+    }
+
+    #[test]
+    fn test_ema_difficulty_ramps_smoothly_from_fast_blocks() {
+        let legacy = simulate_block_times(DifficultyAlgo::Legacy, 80);
+        let ema = simulate_block_times(DifficultyAlgo::Ema, 80);
+
+        // Targets move by any ratio, so both settle on the 10 s target
+        // rather than bouncing between the difficulties either side of it.
+        for block_times in [&legacy, &ema] {
+            let settled = block_times[50..].iter().sum::<f64>() / 30.0;
+            assert!((8.0..12.0).contains(&settled), "{:?}", block_times);
+        }
+        // Legacy gets there in jumps of up to MAX_RETARGET_FACTOR, while the
+        // EMA makes no block much more than a quarter harder than the last.
+        let steepest = |block_times: &[f64]| block_times.windows(2).map(|pair| pair[1] / pair[0]).fold(0.0, f64::max);
+        assert_eq!(steepest(&legacy), MAX_RETARGET_FACTOR as f64);
+        assert!(steepest(&ema) < 1.3, "{:?}", ema);
+    }
+
+    #[test]
+    fn test_difficulty_stays_within_the_configured_bounds() {
+        for algo in [DifficultyAlgo::Legacy, DifficultyAlgo::Ema] {
+            let mut blockchain = Blockchain::new(1);
+            blockchain.chain.truncate(1);
+            blockchain.difficulty_algo = algo;
+            blockchain.params.min_difficulty = 2;
+            blockchain.params.max_difficulty = 4;
+            let mut difficulties = Vec::new();
+            let mut mine_after = |blockchain: &mut Blockchain, interval: i64| {
+                let timestamp = blockchain.chain.last().unwrap().timestamp + interval;
+                push_at(blockchain, timestamp);
+                difficulties.push(blockchain.difficulty());
+            };
+            // A burst of hashrate mining a block a second climbs to the
+            // ceiling and stays there; blocks a minute apart fall to the floor.
+            for _ in 0..60 {
+                mine_after(&mut blockchain, 1);
+            }
+            for _ in 0..60 {
+                mine_after(&mut blockchain, 60);
+            }
+            assert!(difficulties.iter().all(|difficulty| (2..=4).contains(difficulty)), "{:?}: {:?}", algo, difficulties);
+            assert_eq!(difficulties[59], 4, "{:?}", algo);
+            assert_eq!(difficulties[119], 2, "{:?}", algo);
+        }
+    }
+
+    #[test]
+    fn test_ema_difficulty_is_derived_from_the_chain() {
+        let mut blockchain = Blockchain::new(1);
+        blockchain.chain.truncate(1);
+        blockchain.difficulty_algo = DifficultyAlgo::Ema;
+        for _ in 0..20 {
+            let timestamp = blockchain.chain.last().unwrap().timestamp + 1;
+            push_at(&mut blockchain, timestamp);
+        }
+        assert!(blockchain.difficulty() > 1);
+
+        // A node restarting from the saved chain arrives at the same target.
+        let mut restarted: Blockchain = serde_json::from_value(serde_json::to_value(&blockchain).unwrap()).unwrap();
+        restarted.difficulty_algo = DifficultyAlgo::Ema;
+        assert_eq!(restarted.target_bits(), blockchain.target_bits());
+    }
+
+    #[test]
+    fn test_fork_choice_follows_the_most_work() {
+        let mut blockchain = Blockchain::new(1);
+        for seed in 0..2 {
+            blockchain.add_block(FractalType::Sierpinski { depth: 1, seed }, vec![]).unwrap();
+        }
+        let work = Blockchain::chain_work(&blockchain.chain);
+        let tip = blockchain.chain.last().unwrap().hash.clone();
+        assert!(!blockchain.prefers_tip(work, &tip));
+        assert!(blockchain.prefers_tip(work, &"0".repeat(64)));
+
+        // Two more blocks at the easiest target don't outweigh ours...
+        let mut longer = blockchain.chain.clone();
+        longer.extend(longer.clone().into_iter().take(2));
+        for block in &mut longer {
+            block.bits = Target::MAX.to_compact();
+        }
+        assert!(!blockchain.prefers_tip(Blockchain::chain_work(&longer), &"0".repeat(64)));
+
+        // ...but one harder block after the genesis does.
+        let mut shorter = blockchain.chain[..2].to_vec();
+        shorter[1].bits = Target::from_difficulty(8).to_compact();
+        assert!(blockchain.prefers_tip(Blockchain::chain_work(&shorter), &"f".repeat(64)));
+    }
+
+    #[test]
+    fn test_reorg_adopts_the_new_chains_target() {
+        let clock = Arc::new(MockClock::new(1_700_000_000));
+        let mut ours = Blockchain::with_clock(1, clock.clone());
+        let mut theirs = ours.clone();
+        let fractal_type = FractalType::Sierpinski { depth: 1, seed: 0 };
+        for _ in 0..DIFFICULTY_ADJUSTMENT_INTERVAL {
+            theirs.add_block(fractal_type.clone(), vec![]).unwrap();
+        }
+        // Their blocks came quickly, so the retarget made the next one harder.
+        assert!(Target::from_compact(theirs.target_bits()) < Target::from_compact(ours.target_bits()));
+
+        ours.replace_chain(theirs.chain.clone());
+        assert_eq!(ours.target_bits(), theirs.target_bits());
+        let next = theirs.add_block(fractal_type, vec![]).unwrap();
+        assert_eq!(ours.add_block_from_network(next.clone()), Ok(()));
+
+        // Invalidating a block rolls the target back with it.
+        let genesis_bits = ours.chain[0].bits;
+        ours.invalidate_block(&ours.chain[1].hash.clone()).unwrap();
+        assert_eq!(ours.target_bits(), genesis_bits);
+        ours.reconsider_block(&theirs.chain[1].hash).unwrap();
+        assert_eq!(ours.chain.last(), Some(&next));
+        assert_eq!(ours.target_bits(), theirs.target_bits());
+    }
+
+    #[test]
+    fn test_get_balance_and_utxos() {
+        let mut blockchain = Blockchain::new(1);
+        let wallet1 = Wallet::new();
+        let wallet2 = Wallet::new();
+
+        // The genesis block creates a coinbase transaction. Let's assume it goes to a burn address for simplicity.
+        // In our implementation, it goes to "genesis_address".
 
-    #[test]
-    fn test_get_balance_and_utxos() {
-        let mut blockchain = Blockchain::new(1);
-        let wallet1 = Wallet::new();
-        let wallet2 = Wallet::new();
-
-        // The genesis block creates a coinbase transaction. Let's assume it goes to a burn address for simplicity.
-        // In our implementation, it goes to "genesis_address".
-
         let tx1 = Transaction::new(
             vec![], // No inputs, this is not a valid tx, but for testing balance it is ok
             vec![
                 TxOutput {
                     value: 20,
-                    script_pub_key: wallet1.get_address(),
+                    script_pub_key: wallet1.get_address().into(),
                 },
                 TxOutput {
                     value: 30,
-                    script_pub_key: wallet1.get_address(),
+                    script_pub_key: wallet1.get_address().into(),
                 },
             ],
         );
@@ -228,19 +1418,18 @@ mod tests {
             vec![TxInput {
                 txid: tx1.id.clone(),
                 vout: 0,
-                script_sig: String::new(),
-                pub_key: String::new(),
+                script_sig: UnlockingScript::default(),
                 sequence: 0,
             }],
             vec![TxOutput {
                 value: 20,
-                script_pub_key: wallet2.get_address(),
+                script_pub_key: wallet2.get_address().into(),
             }],
         );
 
         let fractal_type = FractalType::Sierpinski { depth: 1, seed: 0 };
-        let _ = blockchain.add_block(fractal_type.clone(), vec![tx1]);
-        let _ = blockchain.add_block(fractal_type, vec![tx2]);
+        let _ = blockchain.add_block(fractal_type.clone(), vec![tx1]).unwrap();
+        let _ = blockchain.add_block(fractal_type, vec![tx2]).unwrap();
 
         // Wallet 1 should have 30 (one output of 20 was spent)
         assert_eq!(blockchain.get_balance(&wallet1.get_address()), 30);
@@ -252,4 +1441,729 @@ mod tests {
         // Wallet 2 should have one UTXO
         assert_eq!(blockchain.get_utxos(&wallet2.get_address()).len(), 1);
     }
+
+    #[test]
+    fn test_utxo_index_matches_full_scan_after_reorg() {
+        let mut blockchain = Blockchain::new(1);
+        let wallet1 = Wallet::new();
+        let wallet2 = Wallet::new();
+        let fractal_type = FractalType::Sierpinski { depth: 1, seed: 0 };
+
+        let funding = Transaction::new(
+            vec![],
+            vec![
+                TxOutput { value: 40, script_pub_key: wallet1.get_address().into() },
+                TxOutput { value: 60, script_pub_key: wallet1.get_address().into() },
+            ],
+        );
+        let spend = Transaction::new(
+            vec![TxInput {
+                txid: funding.id.clone(),
+                vout: 1,
+                script_sig: UnlockingScript::default(),
+                sequence: 0,
+            }],
+            vec![
+                TxOutput { value: 25, script_pub_key: wallet2.get_address().into() },
+                TxOutput { value: 35, script_pub_key: wallet1.get_address().into() },
+            ],
+        );
+        let funding_id = funding.id.clone();
+        blockchain.add_block(fractal_type.clone(), vec![funding]).unwrap();
+        let fork_point = blockchain.chain.clone();
+        blockchain.add_block(fractal_type.clone(), vec![spend]).unwrap();
+        blockchain.add_block(fractal_type.clone(), vec![]).unwrap();
+
+        let addresses = [wallet1.get_address(), wallet2.get_address(), "genesis_address".to_string()];
+        for address in &addresses {
+            assert_eq!(blockchain.get_utxos(address), scan_utxos(&blockchain.chain, address));
+        }
+
+        // Reorg back onto a branch where the spend never happened.
+        let mut competing = Blockchain {
+            chain: fork_point,
+            denomination: blockchain.denomination,
+            reward_schedule: blockchain.reward_schedule.clone(),
+            params: blockchain.params.clone(),
+            utxo_index: UtxoIndex::default(),
+            invalidated: HashMap::new(),
+            archived: HashSet::new(),
+            clock: blockchain.clock.clone(),
+            difficulty_algo: blockchain.difficulty_algo,
+        };
+        let other_spend = Transaction::new(
+            vec![TxInput {
+                txid: funding_id,
+                vout: 0,
+                script_sig: UnlockingScript::default(),
+                sequence: 0,
+            }],
+            vec![TxOutput { value: 40, script_pub_key: wallet2.get_address().into() }],
+        );
+        competing.add_block(fractal_type, vec![other_spend]).unwrap();
+        blockchain.replace_chain(competing.chain);
+
+        for address in &addresses {
+            assert_eq!(blockchain.get_utxos(address), scan_utxos(&blockchain.chain, address));
+        }
+        assert_eq!(blockchain.get_balance(&wallet1.get_address()), 60);
+        assert_eq!(blockchain.get_balance(&wallet2.get_address()), 40);
+    }
+
+    #[test]
+    fn test_get_blocks_mined_by() {
+        let mut blockchain = Blockchain::new(1);
+        let wallet_a = Wallet::new();
+        let wallet_b = Wallet::new();
+        let mut pool = MempoolPool::default();
+        let fractal_type = FractalType::Sierpinski { depth: 1, seed: 0 };
+
+        let mut mined_by_a = Vec::new();
+        for miner in [&wallet_a, &wallet_b, &wallet_a, &wallet_b, &wallet_a] {
+            let transactions = Miner::assemble_transactions(&blockchain, &mut pool, &miner.get_address());
+            let block = blockchain.add_block(fractal_type.clone(), transactions).unwrap();
+            if std::ptr::eq(miner, &wallet_a) {
+                mined_by_a.push(block.index);
+            }
+        }
+
+        assert_eq!(blockchain.get_blocks_mined_by(&wallet_a.get_address()), mined_by_a);
+        assert_eq!(blockchain.get_blocks_mined_by(&wallet_b.get_address()).len(), 2);
+        assert!(blockchain.get_blocks_mined_by(&Wallet::new().get_address()).is_empty());
+    }
+
+    #[test]
+    fn test_network_block_creating_coins_is_rejected() {
+        let mut origin = Blockchain::new(1);
+        let mut blockchain = origin.clone();
+        let genesis_coinbase = origin.chain[0].transactions[0].clone();
+        let reward = genesis_coinbase.outputs[0].value;
+        let inflating = Transaction::new(
+            vec![TxInput {
+                txid: genesis_coinbase.id.clone(),
+                vout: 0,
+                script_sig: UnlockingScript::default(),
+                sequence: 0,
+            }],
+            vec![TxOutput { value: reward + 1, script_pub_key: "someone".into() }],
+        );
+        let height = blockchain.chain.len();
+        let block = origin.add_block(FractalType::Sierpinski { depth: 1, seed: 0 }, vec![inflating]).unwrap();
+        assert!(blockchain.add_block_from_network(block).is_err());
+        assert_eq!(blockchain.chain.len(), height);
+
+        // A block may spend outputs created earlier in the same block.
+        let mut origin = blockchain.clone();
+        let parent = Transaction::new(
+            vec![TxInput {
+                txid: genesis_coinbase.id,
+                vout: 0,
+                script_sig: UnlockingScript::default(),
+                sequence: 0,
+            }],
+            vec![TxOutput { value: reward, script_pub_key: "someone".into() }],
+        );
+        let child = Transaction::new(
+            vec![TxInput {
+                txid: parent.id.clone(),
+                vout: 0,
+                script_sig: UnlockingScript::default(),
+                sequence: 0,
+            }],
+            vec![TxOutput { value: reward - 1, script_pub_key: "someone".into() }],
+        );
+        let block = origin.add_block(FractalType::Sierpinski { depth: 1, seed: 1 }, vec![parent, child]).unwrap();
+        assert!(blockchain.add_block_from_network(block).is_ok());
+    }
+
+    #[test]
+    fn test_network_block_with_too_many_outputs_is_rejected() {
+        let mut origin = Blockchain::new(1);
+        let mut blockchain = origin.clone();
+        blockchain.params.max_tx_outputs = 2;
+        let genesis_coinbase = origin.chain[0].transactions[0].clone();
+        let split = Transaction::new(
+            vec![TxInput {
+                txid: genesis_coinbase.id.clone(),
+                vout: 0,
+                script_sig: UnlockingScript::default(),
+                sequence: 0,
+            }],
+            (0..3).map(|_| TxOutput { value: 1, script_pub_key: "someone".into() }).collect(),
+        );
+        assert_eq!(blockchain.params.check_transaction(&split), Err(TxError::TooManyOutputs { count: 3, max: 2 }));
+        let block = origin.add_block(FractalType::Sierpinski { depth: 1, seed: 0 }, vec![split]).unwrap();
+        assert!(blockchain.add_block_from_network(block.clone()).is_err());
+        assert_eq!(blockchain.chain.len(), 1);
+
+        blockchain.params = ChainParams::default();
+        assert!(blockchain.add_block_from_network(block).is_ok());
+    }
+
+    #[test]
+    fn test_coinbase_message_round_trips_and_is_bounded() {
+        use crate::network::p2p::P2pMessage;
+
+        let mut origin = Blockchain::new(1);
+        let mut peer = origin.clone();
+        let transactions = Miner::assemble_transactions_with_message(&origin, &mut MempoolPool::default(), "miner", Some("gm from the pool"));
+        let block = origin.add_block(FractalType::Sierpinski { depth: 1, seed: 0 }, transactions).unwrap();
+        assert_eq!(block.transactions[0].coinbase_message(), Some("gm from the pool"));
+
+        let saved: Blockchain = serde_json::from_str(&serde_json::to_string(&origin).unwrap()).unwrap();
+        assert_eq!(saved.chain, origin.chain);
+        assert_eq!(saved.chain[1].transactions[0].coinbase_message(), Some("gm from the pool"));
+
+        let P2pMessage::Block(relayed) = serde_json::from_slice(&serde_json::to_vec(&P2pMessage::Block(block.clone())).unwrap()).unwrap() else {
+            panic!("expected a block");
+        };
+        assert_eq!(relayed, block);
+        peer.add_block_from_network(relayed).unwrap();
+
+        // A peer's coinbase with a message over the limit is refused.
+        let outputs = origin.reward_schedule.coinbase_outputs(origin.chain.len() as u64, "miner");
+        let long = "x".repeat(MAX_COINBASE_MESSAGE_BYTES + 1);
+        let coinbase = Transaction::coinbase(origin.chain.len(), outputs, origin.clock.now(), Some(&long));
+        let oversized = origin.clone().add_block(FractalType::Sierpinski { depth: 1, seed: 0 }, vec![coinbase]).unwrap();
+        assert_eq!(
+            peer.add_block_from_network(oversized),
+            Err(ChainError::InvalidTransaction {
+                index: 0,
+                source: TxError::CoinbaseMessageTooLong { bytes: MAX_COINBASE_MESSAGE_BYTES + 1, max: MAX_COINBASE_MESSAGE_BYTES },
+            })
+        );
+        assert_eq!(peer.chain, origin.chain);
+    }
+
+    #[test]
+    fn test_chain_saved_without_denomination_keeps_whole_coins() {
+        let mut blockchain = Blockchain::new(1);
+        blockchain.denomination = Denomination::BaseUnits;
+        let mut saved = serde_json::to_value(&blockchain).unwrap();
+        assert_eq!(saved["denomination"], "base_units");
+        saved.as_object_mut().unwrap().remove("denomination");
+
+        let legacy: Blockchain = serde_json::from_value(saved).unwrap();
+        assert_eq!(legacy.denomination, Denomination::WholeCoins);
+        assert_eq!(legacy.denomination.block_reward(), BLOCK_REWARD_COINS);
+        assert_eq!(Denomination::BaseUnits.block_reward(), BLOCK_REWARD_COINS * COIN);
+    }
+
+    #[test]
+    fn test_reward_schedule_splits_subsidy() {
+        let schedule = RewardSchedule {
+            initial_subsidy: 50,
+            halving_interval: 100,
+            dev_fund_address: Some("dev".to_string()),
+            dev_fund_percent: 10,
+            burn_address: None,
+            burn_percent: 5,
+        };
+        assert_eq!(schedule.validate(), Ok(()));
+
+        let outputs = schedule.coinbase_outputs(0, "miner");
+        let split: Vec<(&str, u64)> = outputs.iter().map(|output| (output.script_pub_key.address(), output.value)).collect();
+        // 5% of 50 rounds down to 2, and the miner keeps the remainder.
+        assert_eq!(split, vec![("miner", 43), ("dev", 5), (BURN_ADDRESS, 2)]);
+        assert_eq!(outputs.iter().map(|output| output.value).sum::<u64>(), 50);
+
+        assert_eq!(schedule.subsidy(99), 50);
+        assert_eq!(schedule.subsidy(100), 25);
+        assert_eq!(schedule.subsidy(100 * 70), 0);
+        assert_eq!(RewardSchedule::flat(50).subsidy(u64::MAX), 50);
+
+        // A block mined under the schedule burns its share out of circulation.
+        let mut blockchain = Blockchain::new(1);
+        blockchain.reward_schedule = schedule.clone();
+        let burned_before = blockchain.utxo_index.supply().burned;
+        let transactions = Miner::assemble_transactions(&blockchain, &mut MempoolPool::default(), "miner");
+        let height = blockchain.chain.len() as u64;
+        assert_eq!(transactions[0].outputs, schedule.coinbase_outputs(height, "miner"));
+        blockchain.add_block(FractalType::Sierpinski { depth: 1, seed: 0 }, transactions).unwrap();
+        assert_eq!(blockchain.utxo_index.supply().burned, burned_before + schedule.coinbase_outputs(height, "miner")[2].value);
+        assert!(blockchain.utxo_index.supply().is_consistent());
+    }
+
+    #[test]
+    fn test_supply_after_mining_with_halvings() {
+        let mut blockchain = Blockchain::new(1);
+        blockchain.chain.truncate(1);
+        blockchain.utxo_index = UtxoIndex::rebuild(&blockchain.chain);
+        blockchain.reward_schedule = RewardSchedule { halving_interval: 2, ..RewardSchedule::flat(64) };
+        let genesis_reward = blockchain.chain[0].transactions[0].outputs[0].value;
+
+        let mut first_coinbase = None;
+        for height in 1..=5 {
+            let mut transactions = Miner::assemble_transactions(&blockchain, &mut MempoolPool::default(), "miner");
+            if height == 3 {
+                let funding: &Transaction = first_coinbase.as_ref().unwrap();
+                transactions.push(Transaction::new(
+                    vec![TxInput { txid: funding.id.clone(), vout: 0, script_sig: UnlockingScript::default(), sequence: 0 }],
+                    vec![
+                        TxOutput { value: 40, script_pub_key: "a".into() },
+                        TxOutput { value: 24, script_pub_key: "b".into() },
+                    ],
+                ));
+            }
+            let block = blockchain.add_block(FractalType::Sierpinski { depth: 1, seed: 0 }, transactions).unwrap();
+            first_coinbase.get_or_insert(block.transactions[0].clone());
+        }
+
+        let supply = blockchain.utxo_index.supply();
+        let rewards: u64 = (1..=5).map(|height| blockchain.reward_schedule.subsidy(height)).sum();
+        assert_eq!(rewards, 64 + 32 + 32 + 16 + 16);
+        assert_eq!(supply.total_supply(), genesis_reward + rewards);
+
+        let spent: HashSet<(String, usize)> =
+            blockchain.chain.iter().flat_map(|block| &block.transactions).flat_map(|tx| &tx.inputs).map(|input| (input.txid.clone(), input.vout)).collect();
+        let unspent = blockchain
+            .chain
+            .iter()
+            .flat_map(|block| &block.transactions)
+            .flat_map(|tx| (0..tx.outputs.len()).map(move |vout| (tx.id.clone(), vout)))
+            .filter(|outpoint| !spent.contains(outpoint))
+            .count();
+        assert_eq!(blockchain.utxo_index.len(), unspent);
+        assert_eq!(unspent, 1 + 5 - 1 + 2);
+        // Every coinbase is still immature, and the genesis coins don't count.
+        assert_eq!(blockchain.circulating_supply(), 64);
+    }
+
+    #[test]
+    fn test_fractal_is_bound_to_block_position() {
+        let mut blockchain = Blockchain::new(1);
+        let fractal_type = FractalType::Sierpinski { depth: 2, seed: 0 };
+        let previous_hash = blockchain.chain.last().unwrap().hash.clone();
+        let fractal_at = |previous_hash: &str, index: u64| {
+            let seed = Block::derive_fractal_seed(previous_hash, index, 7, 0);
+            let fractal = fractal_type.clone().with_seed(seed).generate();
+            match fractal {
+                FractalData::Sierpinski(s) => s.vertices().to_vec(),
+                _ => unreachable!(),
+            }
+        };
+        assert_ne!(fractal_at(&previous_hash, 1), fractal_at(&previous_hash, 2));
+        assert_ne!(fractal_at(&previous_hash, 1), fractal_at(&"f".repeat(64), 1));
+
+        let block = blockchain.add_block(fractal_type, vec![]).unwrap();
+        assert!(block.has_valid_fractal());
+
+        let mut tampered = block.clone();
+        tampered.previous_hash = "f".repeat(64);
+        assert!(!tampered.has_valid_fractal());
+    }
+
+    #[test]
+    fn test_fractal_must_come_from_the_committed_style_seed_and_nonce() {
+        let mut blockchain = Blockchain::new(1);
+        let styled = FractalType::Sierpinski { depth: 5, seed: 9 };
+        let template = blockchain.next_block(&styled, vec![]).unwrap();
+        assert_eq!(template.style_seed, 9);
+
+        // A block committing to style seed 9 whose fractals come from 10.
+        let mut forged = template.clone();
+        loop {
+            let seed = Block::derive_fractal_seed(&forged.previous_hash, forged.index, forged.nonce, 10);
+            forged.fractal = styled.clone().with_seed(seed).generate();
+            forged.hash = forged.calculate_hash();
+            if forged.target().is_met_by(&forged.hash) {
+                break;
+            }
+            forged.nonce += 1;
+        }
+        assert!(blockchain.add_block_from_network(forged).is_err());
+
+        let block = Miner::mine_block(styled.clone(), template, MiningBudget::UNLIMITED).found().unwrap();
+        assert_eq!(block.style_seed, 9);
+        assert!(blockchain.add_block_from_network(block.clone()).is_ok());
+        // The same style seed at the next height gives another fractal.
+        let next = blockchain.add_block(styled, vec![]).unwrap();
+        assert_eq!(next.style_seed, 9);
+        assert_ne!(next.fractal, block.fractal);
+    }
+
+    #[test]
+    fn test_blocks_validate_with_the_rng_version_they_declare() {
+        let mut blockchain = Blockchain::new(1);
+        let fractal_type = FractalType::mandelbrot(8, 8, -2.0, 1.0, -1.5, 1.5, 20, 0).unwrap();
+
+        // A block mined as before RNG versions, which doesn't record one.
+        let mut legacy_miner = blockchain.clone();
+        legacy_miner.params.fractal_rng = RngVersion::Lcg;
+        let legacy = legacy_miner.add_block(fractal_type.clone(), vec![]).unwrap();
+        assert_eq!(legacy.fractal.rng_version(), RngVersion::Lcg);
+        assert!(!serde_json::to_string(&legacy).unwrap().contains("rng_version"));
+        assert!(blockchain.add_block_from_network(legacy.clone()).is_ok());
+
+        let current = blockchain.add_block(fractal_type, vec![]).unwrap();
+        assert_eq!(current.fractal.rng_version(), RngVersion::CURRENT);
+        assert!(current.has_valid_fractal());
+
+        // The version is committed to by the hash.
+        let mut relabelled = legacy.clone();
+        if let FractalData::Mandelbrot(mandelbrot) = &mut relabelled.fractal {
+            mandelbrot.rng_version = RngVersion::SplitMix64;
+        }
+        assert_ne!(relabelled.calculate_hash(), legacy.hash);
+    }
+
+    #[test]
+    fn test_fast_sync_from_checkpoint_matches_full_sync() {
+        let mut origin = Blockchain::new(1);
+        origin.chain.truncate(1);
+        origin.utxo_index = UtxoIndex::rebuild(&origin.chain);
+        let fresh = origin.clone();
+        for value in 1..=6 {
+            let coinbase = TxInput {
+                txid: "0".repeat(64),
+                vout: value as usize,
+                script_sig: UnlockingScript::Data("coinbase".to_string()),
+                sequence: 0,
+            };
+            let payout = Transaction::new(vec![coinbase], vec![TxOutput { value, script_pub_key: "someone".into() }]);
+            origin.add_block(FractalType::Sierpinski { depth: 1, seed: 0 }, vec![payout]).unwrap();
+        }
+        let checkpoint = Checkpoint { index: 3, hash: origin.chain[3].hash.clone() };
+        assert_eq!(checkpoint.to_string().parse::<Checkpoint>(), Ok(checkpoint.clone()));
+
+        let mut full = fresh.clone();
+        full.replace_chain(origin.chain.clone());
+        let mut fast = fresh.clone();
+        fast.import_from_checkpoint(&checkpoint, origin.blocks_from(0)).unwrap();
+        assert_eq!(fast.chain, full.chain);
+        assert_eq!(fast.get_balance("someone"), full.get_balance("someone"));
+
+        // A node already holding the blocks up to the checkpoint fetches only the rest.
+        let mut resumed = origin.clone();
+        resumed.chain.truncate(4);
+        resumed.utxo_index = UtxoIndex::rebuild(&resumed.chain);
+        resumed.import_from_checkpoint(&checkpoint, origin.blocks_from(4)).unwrap();
+        assert_eq!(resumed.chain.last(), full.chain.last());
+        assert_eq!(resumed.get_balance("someone"), full.get_balance("someone"));
+
+        let wrong = Checkpoint { index: 3, hash: origin.chain[2].hash.clone() };
+        assert!(fresh.clone().import_from_checkpoint(&wrong, origin.blocks_from(0)).is_err());
+
+        // Blocks before the checkpoint must still match their hashes...
+        let mut tampered = origin.blocks_from(0);
+        tampered[2].transactions[0].outputs[0].value = 1_000_000;
+        assert!(fresh.clone().import_from_checkpoint(&checkpoint, tampered).is_err());
+        // ...and blocks after it must carry valid proof of work.
+        let mut tampered = origin.blocks_from(0);
+        tampered[5].nonce += 1;
+        tampered[5].hash = tampered[5].calculate_hash();
+        tampered[6].previous_hash = tampered[5].hash.clone();
+        tampered[6].hash = tampered[6].calculate_hash();
+        assert!(fresh.clone().import_from_checkpoint(&checkpoint, tampered).is_err());
+    }
+
+    #[test]
+    fn test_equal_length_import_takes_the_lower_tip() {
+        let genesis = Blockchain::new(1);
+        let checkpoint = Checkpoint { index: 0, hash: genesis.chain[0].hash.clone() };
+        let mut nodes = [genesis.clone(), genesis];
+        for (seed, node) in nodes.iter_mut().enumerate() {
+            node.add_block(FractalType::Sierpinski { depth: 1, seed: seed as u64 }, vec![]).unwrap();
+        }
+        nodes.sort_by(|a, b| a.chain[1].hash.cmp(&b.chain[1].hash));
+        let [lower, mut higher] = nodes;
+
+        assert!(lower.clone().import_from_checkpoint(&checkpoint, higher.blocks_from(1)).is_err());
+        higher.import_from_checkpoint(&checkpoint, lower.blocks_from(1)).unwrap();
+        assert_eq!(higher.chain, lower.chain);
+    }
+
+    #[test]
+    fn test_block_before_median_time_past_is_rejected() {
+        let mut blockchain = Blockchain::new(1);
+        for seed in 0..11 {
+            blockchain.add_block(FractalType::Sierpinski { depth: 1, seed }, vec![]).unwrap();
+        }
+        // The last 11 blocks are timestamped 1000, 1010, ..., 1090 and then
+        // 900, so the median is 1040 while the tip is well before it.
+        let tip = blockchain.chain.len() - 1;
+        for (offset, block) in blockchain.chain[tip - 10..tip].iter_mut().enumerate() {
+            block.timestamp = 1000 + 10 * offset as i64;
+        }
+        blockchain.chain[tip].timestamp = 900;
+        blockchain.clock = Arc::new(MockClock::new(1020));
+        assert_eq!(blockchain.median_time_past(), 1040);
+
+        let fractal_type = FractalType::Sierpinski { depth: 1, seed: 0 };
+        let mine_at = |blockchain: &Blockchain, timestamp: i64| {
+            let mut block = blockchain.next_block(&fractal_type, vec![]).unwrap();
+            block.timestamp = timestamp;
+            Miner::mine_block(fractal_type.clone(), block, MiningBudget::UNLIMITED).found().unwrap()
+        };
+        assert!(blockchain.add_block_from_network(mine_at(&blockchain, 950)).is_err());
+        assert!(blockchain.add_block_from_network(mine_at(&blockchain, 1040)).is_err());
+
+        // Blocks made here are moved past the median even though the clock is behind it.
+        assert_eq!(blockchain.next_block(&fractal_type, vec![]).unwrap().timestamp, 1041);
+        assert!(blockchain.add_block_from_network(mine_at(&blockchain, 1041)).is_ok());
+    }
+
+    #[test]
+    fn test_cheap_fractals_must_meet_the_weighted_target() {
+        let mut blockchain = Blockchain::new(1);
+        let fractal_type = FractalType::Sierpinski { depth: 1, seed: 0 };
+        assert_eq!(fractal_type.work_factor(), 81);
+        let template = blockchain.next_block(&fractal_type, vec![]).unwrap();
+        let unscaled = Target::from_compact(template.bits);
+
+        // The first nonce meeting the unscaled target but not the weighted one.
+        let mut block = template.clone();
+        loop {
+            block.fractal = fractal_type.clone().with_seed(block.fractal_seed()).generate();
+            block.hash = block.calculate_hash();
+            if unscaled.is_met_by(&block.hash) && !block.target().is_met_by(&block.hash) {
+                break;
+            }
+            block.nonce += 1;
+        }
+        assert!(blockchain.add_block_from_network(block).is_err());
+
+        let block = Miner::mine_block(fractal_type, template, MiningBudget::UNLIMITED).found().unwrap();
+        assert!(block.target().is_met_by(&block.hash));
+        assert!(blockchain.add_block_from_network(block).is_ok());
+    }
+
+    #[test]
+    fn test_premine_funds_addresses_in_genesis() {
+        let mut blockchain = Blockchain::new(1);
+        blockchain.chain.truncate(1);
+        blockchain.utxo_index = UtxoIndex::rebuild(&blockchain.chain);
+        let (alice, bob) = (Wallet::new().get_address(), Wallet::new().get_address());
+        let premine = vec![
+            TxOutput { value: 1_000 * COIN, script_pub_key: alice.as_str().into() },
+            TxOutput { value: 250 * COIN, script_pub_key: bob.as_str().into() },
+        ];
+        let plain_genesis = blockchain.chain[0].clone();
+        blockchain.set_premine(premine.clone()).unwrap();
+
+        let genesis = &blockchain.chain[0];
+        assert_eq!(blockchain.chain.len(), 1);
+        assert_ne!(genesis.hash, plain_genesis.hash);
+        assert_eq!(genesis.hash, genesis.calculate_hash());
+        assert_eq!(blockchain.premine(), premine.as_slice());
+        assert_eq!(blockchain.get_balance(&alice), 1_000 * COIN);
+        assert_eq!(blockchain.get_balance(&bob), 250 * COIN);
+
+        // The same premine again leaves the chain alone, even once it grows.
+        blockchain.add_block(FractalType::Sierpinski { depth: 1, seed: 0 }, vec![]).unwrap();
+        assert_eq!(blockchain.set_premine(premine.clone()), Ok(()));
+        assert!(blockchain.set_premine(premine[..1].to_vec()).is_err());
+
+        let mut fresh = Blockchain::new(1);
+        fresh.chain.truncate(1);
+        let invalid = vec![TxOutput { value: 1, script_pub_key: "alice".into() }];
+        assert!(fresh.set_premine(invalid).is_err());
+        let over_cap = vec![TxOutput { value: PREMINE_CAP_COINS * COIN + 1, script_pub_key: alice.as_str().into() }];
+        assert!(fresh.set_premine(over_cap).is_err());
+        let overflowing = vec![TxOutput { value: u64::MAX, script_pub_key: alice.as_str().into() }; 2];
+        assert!(fresh.set_premine(overflowing).is_err());
+    }
+
+    #[test]
+    fn test_invalidate_and_reconsider_block() {
+        let mut blockchain = Blockchain::new(1);
+        for seed in 0..4 {
+            blockchain.add_block(FractalType::Sierpinski { depth: 1, seed }, vec![]).unwrap();
+        }
+        let original = blockchain.chain.clone();
+        let parent_of_tip = original[original.len() - 2].hash.clone();
+
+        let reorg = blockchain.invalidate_block(&parent_of_tip).unwrap();
+        assert_eq!(reorg.disconnected, original[original.len() - 2..]);
+        assert_eq!(blockchain.chain, original[..original.len() - 2]);
+        assert!(blockchain.add_block_from_network(original[original.len() - 2].clone()).is_err());
+        assert!(blockchain.invalidate_block(&original[0].hash).is_none());
+
+        let reorg = blockchain.reconsider_block(&parent_of_tip).unwrap();
+        assert_eq!(reorg.connected, original[original.len() - 2..]);
+        assert_eq!(blockchain.chain, original);
+        assert!(blockchain.reconsider_block(&parent_of_tip).is_none());
+    }
+
+    #[test]
+    fn test_verify_block_flags_what_was_tampered_with() {
+        let mut blockchain = Blockchain::new(1);
+        let owner = Wallet::new();
+        let funding = Transaction::new(vec![], vec![TxOutput { value: 50, script_pub_key: owner.get_address().into() }]);
+        let mut spend = Transaction::new(
+            vec![TxInput { txid: funding.id.clone(), vout: 0, script_sig: UnlockingScript::default(), sequence: 0 }],
+            vec![TxOutput { value: 50, script_pub_key: Wallet::new().get_address().into() }],
+        );
+        spend.sign(&owner);
+        let fractal_type = FractalType::Sierpinski { depth: 1, seed: 0 };
+        blockchain.add_block(fractal_type.clone(), vec![funding]).unwrap();
+        blockchain.add_block(fractal_type, vec![spend]).unwrap();
+
+        // The spent output is gone from the UTXO set but still checked.
+        let valid = BlockVerification { hash_ok: true, pow_ok: true, link_ok: true, tx_sigs_ok: true, overall: true };
+        for index in 0..3 {
+            assert_eq!(blockchain.verify_block(index), Some(valid));
+        }
+        assert_eq!(blockchain.verify_block(3), None);
+
+        let mut tampered = blockchain.clone();
+        tampered.chain[2].nonce += 1;
+        assert_eq!(tampered.verify_block(2), Some(BlockVerification { hash_ok: false, overall: false, ..valid }));
+
+        let mut tampered = blockchain.clone();
+        tampered.chain[2].previous_hash = "0".repeat(64);
+        let verification = tampered.verify_block(2).unwrap();
+        assert!(!verification.link_ok && !verification.overall);
+
+        let mut tampered = blockchain.clone();
+        tampered.chain[2].transactions[0].sign(&Wallet::new());
+        let verification = tampered.verify_block(2).unwrap();
+        assert!(!verification.tx_sigs_ok && !verification.overall);
+        assert!(verification.link_ok && verification.pow_ok);
+    }
+
+    #[test]
+    fn test_validate_block_says_why_a_block_is_rejected() {
+        let clock = Arc::new(MockClock::new(1_000));
+        let mut blockchain = Blockchain::with_clock(1, clock.clone());
+        blockchain.add_block(FractalType::Sierpinski { depth: 1, seed: 0 }, vec![]).unwrap();
+        let fractal_type = FractalType::Sierpinski { depth: 1, seed: 1 };
+        let mine = |blockchain: &Blockchain, transactions: Vec<Transaction>, tweak: &dyn Fn(&mut Block)| {
+            let mut block = blockchain.next_block(&fractal_type, transactions).unwrap();
+            tweak(&mut block);
+            Miner::mine_block(fractal_type.clone(), block, MiningBudget::UNLIMITED).found().unwrap()
+        };
+        let valid = mine(&blockchain, vec![], &|_| {});
+        assert_eq!(blockchain.validate_block(&valid), Ok(()));
+
+        let mut empty = blockchain.clone();
+        empty.chain.clear();
+        assert_eq!(empty.validate_block(&valid), Err(ChainError::EmptyChain));
+
+        let mut invalidated = blockchain.clone();
+        invalidated.add_block_from_network(valid.clone()).unwrap();
+        invalidated.invalidate_block(&valid.hash).unwrap();
+        assert_eq!(invalidated.validate_block(&valid), Err(ChainError::Invalidated { hash: valid.hash.clone() }));
+
+        let tip = blockchain.chain.last().unwrap().clone();
+        let block = mine(&blockchain, vec![], &|block| block.previous_hash = "f".repeat(64));
+        assert_eq!(
+            blockchain.validate_block(&block),
+            Err(ChainError::PrevHashMismatch { expected: tip.hash.clone(), got: "f".repeat(64) })
+        );
+        let block = mine(&blockchain, vec![], &|block| block.index += 1);
+        assert_eq!(blockchain.validate_block(&block), Err(ChainError::WrongIndex { expected: 2, got: 3 }));
+
+        let mut block = valid.clone();
+        block.bits ^= 1;
+        let expected = blockchain.target_bits();
+        assert_eq!(blockchain.validate_block(&block), Err(ChainError::WrongTarget { expected, got: expected ^ 1 }));
+        let mut block = valid.clone();
+        block.hash = "f".repeat(64);
+        assert_eq!(blockchain.validate_block(&block), Err(ChainError::PowBelowTarget { hash: "f".repeat(64) }));
+        let mut block = valid.clone();
+        block.nonce += 1;
+        assert_eq!(
+            blockchain.validate_block(&block),
+            Err(ChainError::HashMismatch { declared: valid.hash.clone(), calculated: block.calculate_hash() })
+        );
+
+        // A fractal generated from another seed, under a hash meeting the target.
+        let mut block = blockchain.next_block(&fractal_type, vec![]).unwrap();
+        block.fractal = fractal_type.clone().with_seed(7).generate();
+        loop {
+            block.hash = block.calculate_hash();
+            if block.target().is_met_by(&block.hash) && !block.has_valid_fractal() {
+                break;
+            }
+            block.nonce += 1;
+        }
+        assert_eq!(blockchain.validate_block(&block), Err(ChainError::FractalMismatch));
+
+        let expired = Transaction::new(vec![], vec![TxOutput { value: 0, script_pub_key: "someone".into() }])
+            .with_expiry_height(Some(2));
+        let block = mine(&blockchain, vec![expired.clone()], &|_| {});
+        assert_eq!(
+            blockchain.validate_block(&block),
+            Err(ChainError::ExpiredTransaction { txid: expired.id.clone(), expiry_height: 2 })
+        );
+
+        let split = Transaction::new(vec![], (0..3).map(|_| TxOutput { value: 0, script_pub_key: "someone".into() }).collect());
+        let block = mine(&blockchain, vec![split.clone()], &|_| {});
+        let mut strict = blockchain.clone();
+        strict.params.max_tx_outputs = 2;
+        assert_eq!(
+            strict.validate_block(&block),
+            Err(ChainError::InvalidTransaction { index: 0, source: TxError::TooManyOutputs { count: 3, max: 2 } })
+        );
+        strict.params.max_block_size = split.size() - 1;
+        assert_eq!(strict.validate_block(&block), Err(ChainError::OversizedBlock { size: split.size(), max: split.size() - 1 }));
+
+        let coinbase = blockchain.chain[0].transactions[0].clone();
+        let spend = |txid: &str, value: u64| {
+            Transaction::new(
+                vec![TxInput { txid: txid.to_string(), vout: 0, script_sig: UnlockingScript::default(), sequence: 0 }],
+                vec![TxOutput { value, script_pub_key: "someone".into() }],
+            )
+        };
+        let reward = coinbase.outputs[0].value;
+        let parent = spend(&coinbase.id, reward);
+        let child = spend(&parent.id, reward);
+        let block = mine(&blockchain, vec![child.clone(), parent], &|_| {});
+        assert_eq!(blockchain.validate_block(&block), Err(ChainError::TransactionOutOfOrder { txid: child.id }));
+        let block = mine(&blockchain, vec![spend(&coinbase.id, reward + 1)], &|_| {});
+        assert_eq!(
+            blockchain.validate_block(&block),
+            Err(ChainError::InvalidTransaction {
+                index: 0,
+                source: TxError::OutputsExceedInputs { inputs_total: reward, outputs_total: reward + 1 },
+            })
+        );
+
+        let now = clock.now();
+        let block = mine(&blockchain, vec![], &|block| block.timestamp = now + MAX_FUTURE_BLOCK_SECS + 1);
+        assert_eq!(
+            blockchain.validate_block(&block),
+            Err(ChainError::BadTimestamp(TimestampError::TooFarInFuture { timestamp: now + MAX_FUTURE_BLOCK_SECS + 1, now }))
+        );
+        let parent = tip.timestamp;
+        let block = mine(&blockchain, vec![], &|block| block.timestamp = parent - 1);
+        assert_eq!(
+            blockchain.validate_block(&block),
+            Err(ChainError::BadTimestamp(TimestampError::BeforeParent { timestamp: parent - 1, parent }))
+        );
+        let median = blockchain.median_time_past();
+        let block = mine(&blockchain, vec![], &|block| block.timestamp = median);
+        assert_eq!(
+            blockchain.validate_block(&block),
+            Err(ChainError::BadTimestamp(TimestampError::NotPastMedian { timestamp: median, median }))
+        );
+
+        // Only blocks that are wrong in themselves count against their sender.
+        assert!(!ChainError::EmptyChain.is_invalid());
+        assert!(!ChainError::PrevHashMismatch { expected: String::new(), got: String::new() }.is_invalid());
+        assert!(!ChainError::BadTimestamp(TimestampError::TooFarInFuture { timestamp: 0, now: 0 }).is_invalid());
+        assert!(ChainError::FractalMismatch.is_invalid());
+    }
+
+    #[test]
+    fn test_empty_chain_is_an_error_not_a_panic() {
+        let mut blockchain = Blockchain::new(1);
+        let genesis = blockchain.chain[0].clone();
+        blockchain.chain.clear();
+
+        assert_eq!(blockchain.tip().err(), Some(EmptyChain));
+        let fractal_type = FractalType::Sierpinski { depth: 1, seed: 0 };
+        assert_eq!(blockchain.next_block(&fractal_type, vec![]).err(), Some(EmptyChain));
+        assert_eq!(blockchain.add_block(fractal_type, vec![]).err(), Some(EmptyChain));
+        assert_eq!(blockchain.add_block_from_network(genesis.clone()), Err(ChainError::EmptyChain));
+        for algo in [DifficultyAlgo::Legacy, DifficultyAlgo::Ema] {
+            blockchain.difficulty_algo = algo;
+            assert_eq!(blockchain.target_bits(), Target::from_difficulty(MIN_DIFFICULTY).to_compact());
+        }
+
+        blockchain.chain.push(genesis);
+        blockchain.replace_chain(vec![]);
+        assert_eq!(blockchain.chain.len(), 1);
+    }
 }
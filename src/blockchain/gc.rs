@@ -0,0 +1,130 @@
+use serde::Serialize;
+use std::fs::OpenOptions;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use super::chain::Blockchain;
+
+/// Where stale side-chain blocks are archived, one JSON block per line.
+pub const ARCHIVE_FILE: &str = "archive.jsonl";
+
+/// How many blocks below the tip a reorg may reach by default. Side-chain
+/// blocks forking deeper than this are garbage.
+pub const DEFAULT_MAX_REORG_DEPTH: u64 = 100;
+
+/// How often the node collects garbage on its own.
+pub const GC_INTERVAL: Duration = Duration::from_secs(600);
+
+/// What one garbage collection did.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize)]
+pub struct GcReport {
+    /// Blocks written to the archive file.
+    pub archived: usize,
+    /// Blocks dropped without being archived.
+    pub deleted: usize,
+    /// The serialized size of every block collected.
+    pub reclaimed_bytes: u64,
+}
+
+/// The retention policy for side-chain blocks, which are those removed from
+/// the active chain by `invalidate_block`. Branches whose fork point falls
+/// more than `max_reorg_depth` below the tip are moved to the archive file,
+/// or deleted if there is none.
+#[derive(Debug)]
+pub struct StorageGc {
+    max_reorg_depth: u64,
+    archive: Option<PathBuf>,
+    last_report: Option<GcReport>,
+}
+
+pub type SharedStorageGc = Arc<Mutex<StorageGc>>;
+
+impl StorageGc {
+    pub fn new(max_reorg_depth: u64, archive: Option<PathBuf>) -> Self {
+        Self { max_reorg_depth, archive, last_report: None }
+    }
+
+    pub fn max_reorg_depth(&self) -> u64 {
+        self.max_reorg_depth
+    }
+
+    /// The archive file, or `None` if collected blocks are deleted.
+    pub fn archive(&self) -> Option<&Path> {
+        self.archive.as_deref()
+    }
+
+    /// What the last collection did, if one has run.
+    pub fn last_report(&self) -> Option<GcReport> {
+        self.last_report
+    }
+
+    /// Collects the stale side-chain blocks of `blockchain`. Blocks are only
+    /// dropped once they have been written to the archive, so a failed write
+    /// leaves them in place for the next run.
+    pub fn run(&mut self, blockchain: &mut Blockchain) -> io::Result<GcReport> {
+        let stale = blockchain.stale_branches(self.max_reorg_depth);
+        let mut report = GcReport::default();
+        let mut lines = Vec::new();
+        for block in stale.iter().flat_map(|(_, branch)| branch) {
+            let line = serde_json::to_vec(block)?;
+            report.reclaimed_bytes += line.len() as u64;
+            lines.extend(line);
+            lines.push(b'\n');
+        }
+        let collected = stale.iter().map(|(_, branch)| branch.len()).sum();
+        match &self.archive {
+            Some(path) if collected > 0 => {
+                OpenOptions::new().create(true).append(true).open(path)?.write_all(&lines)?;
+                report.archived = collected;
+            }
+            Some(_) => {}
+            None => report.deleted = collected,
+        }
+
+        let hashes: Vec<String> = stale.into_iter().map(|(hash, _)| hash).collect();
+        blockchain.archive_branches(&hashes);
+        tracing::info!(
+            target: "journal",
+            archived = report.archived,
+            deleted = report.deleted,
+            reclaimed_bytes = report.reclaimed_bytes,
+            max_reorg_depth = self.max_reorg_depth,
+            "Collected stale side-chain blocks"
+        );
+        self.last_report = Some(report);
+        Ok(report)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::clock::MockClock;
+    use crate::fractal::FractalType;
+
+    #[test]
+    fn test_only_branches_deeper_than_the_bound_are_collected() {
+        let mut blockchain = Blockchain::with_clock(1, Arc::new(MockClock::new(1_700_000_000)));
+        for seed in 0..3 {
+            blockchain.add_block(FractalType::Sierpinski { depth: 1, seed }, vec![]).unwrap();
+        }
+        let invalidated = blockchain.chain[2].hash.clone();
+        blockchain.invalidate_block(&invalidated).unwrap();
+        assert_eq!(blockchain.side_chain_block_count(), 2);
+
+        // The fork point is the tip: within any bound.
+        let mut gc = StorageGc::new(1, None);
+        assert_eq!(gc.run(&mut blockchain).unwrap(), GcReport::default());
+        for seed in 3..5 {
+            blockchain.add_block(FractalType::Sierpinski { depth: 1, seed }, vec![]).unwrap();
+        }
+        let reclaimed_bytes = blockchain.stale_branches(1)[0].1.iter().map(|block| serde_json::to_vec(block).unwrap().len() as u64).sum();
+        assert_eq!(gc.run(&mut blockchain).unwrap(), GcReport { archived: 0, deleted: 2, reclaimed_bytes });
+        assert_eq!(blockchain.side_chain_block_count(), 0);
+        assert!(blockchain.is_archived(&invalidated));
+        assert!(blockchain.reconsider_block(&invalidated).is_none());
+        assert_eq!(blockchain.chain.len(), 4);
+    }
+}
@@ -0,0 +1,153 @@
+use serde::Serialize;
+use std::collections::HashSet;
+
+use super::block::Block;
+use super::chain::Blockchain;
+
+/// The most blocks back from the tip `ChainGraph::build` will go.
+pub const MAX_GRAPH_DEPTH: u64 = 500;
+/// How many leading characters of a block hash label its node.
+const SHORT_HASH_LEN: usize = 12;
+
+/// A block in the graph.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct GraphNode {
+    pub hash: String,
+    pub short_hash: String,
+    pub height: u64,
+    /// The address the coinbase pays, if the block has one.
+    pub miner: Option<String>,
+    pub fractal_type: &'static str,
+    /// Whether the block is on the active chain rather than a side chain.
+    pub active: bool,
+}
+
+/// A link from a block to its parent.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct GraphEdge {
+    pub from: String,
+    pub to: String,
+}
+
+/// The recent blocks the node knows of, active chain and side chains
+/// alike, linked by `previous_hash`.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct ChainGraph {
+    /// Nodes by height, the active block first among those of one height.
+    pub nodes: Vec<GraphNode>,
+    pub edges: Vec<GraphEdge>,
+}
+
+impl GraphNode {
+    fn new(block: &Block, active: bool) -> Self {
+        let miner = block
+            .transactions
+            .first()
+            .filter(|coinbase| coinbase.is_coinbase())
+            .and_then(|coinbase| coinbase.outputs.first())
+            .map(|output| output.script_pub_key.address().to_string());
+        GraphNode {
+            hash: block.hash.clone(),
+            short_hash: block.hash.chars().take(SHORT_HASH_LEN).collect(),
+            height: block.index,
+            miner,
+            fractal_type: block.fractal.type_name(),
+            active,
+        }
+    }
+}
+
+impl ChainGraph {
+    /// Builds the graph of the blocks at the last `depth` heights, counting
+    /// down from the highest block known and capped at `MAX_GRAPH_DEPTH`.
+    /// Edges to parents below that are left out.
+    pub fn build(blockchain: &Blockchain, depth: u64) -> Self {
+        let depth = depth.clamp(1, MAX_GRAPH_DEPTH);
+        let top = blockchain
+            .side_chain_blocks()
+            .map(|block| block.index + 1)
+            .fold(blockchain.chain.len() as u64, u64::max);
+        let floor = top.saturating_sub(depth);
+        let active_floor = (floor as usize).min(blockchain.chain.len());
+        let active = blockchain.chain[active_floor..].iter().map(|block| GraphNode::new(block, true));
+        let side = blockchain
+            .side_chain_blocks()
+            .filter(|block| block.index >= floor)
+            .map(|block| GraphNode::new(block, false));
+        let mut nodes: Vec<GraphNode> = active.chain(side).collect();
+        nodes.sort_by(|a, b| a.height.cmp(&b.height).then(b.active.cmp(&a.active)).then(a.hash.cmp(&b.hash)));
+
+        let drawn: HashSet<&str> = nodes.iter().map(|node| node.hash.as_str()).collect();
+        let edges = blockchain
+            .chain
+            .iter()
+            .chain(blockchain.side_chain_blocks())
+            .filter(|block| drawn.contains(block.hash.as_str()) && drawn.contains(block.previous_hash.as_str()))
+            .map(|block| GraphEdge { from: block.hash.clone(), to: block.previous_hash.clone() })
+            .collect::<Vec<_>>();
+        let mut graph = ChainGraph { nodes, edges };
+        graph.edges.sort_by(|a, b| a.from.cmp(&b.from));
+        graph
+    }
+
+    /// Renders the graph in graphviz's DOT language, newest blocks on the
+    /// left and the active chain filled in.
+    pub fn to_dot(&self) -> String {
+        let mut dot = String::from("digraph chain {\n    rankdir=RL;\n    node [shape=box, fontname=\"monospace\"];\n");
+        for node in &self.nodes {
+            let style = if node.active { "style=filled, fillcolor=\"#b7e4c7\"" } else { "style=dashed" };
+            let label = format!(
+                "#{} {}\\n{}\\n{}",
+                node.height,
+                node.short_hash,
+                node.fractal_type,
+                node.miner.as_deref().unwrap_or("-")
+            );
+            dot.push_str(&format!("    \"{}\" [label=\"{}\", {}];\n", node.hash, escape(&label), style));
+        }
+        for edge in &self.edges {
+            dot.push_str(&format!("    \"{}\" -> \"{}\";\n", edge.from, edge.to));
+        }
+        dot.push_str("}\n");
+        dot
+    }
+}
+
+/// Escapes double quotes for a DOT string, leaving the `\n` line breaks.
+fn escape(label: &str) -> String {
+    label.replace('"', "\\\"")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fractal::FractalType;
+
+    #[test]
+    fn test_graph_includes_invalidated_branch_and_renders_dot() {
+        let mut blockchain = Blockchain::new(1);
+        for seed in 0..3 {
+            blockchain.add_block(FractalType::Sierpinski { depth: 1, seed }, vec![]).unwrap();
+        }
+        let stale = blockchain.chain[2].hash.clone();
+        blockchain.invalidate_block(&stale).unwrap();
+        blockchain.add_block(FractalType::Sierpinski { depth: 1, seed: 10 }, vec![]).unwrap();
+
+        let graph = ChainGraph::build(&blockchain, 10);
+        let side: Vec<&GraphNode> = graph.nodes.iter().filter(|node| !node.active).collect();
+        assert_eq!(side.iter().map(|node| node.height).collect::<Vec<_>>(), [2, 3]);
+        assert_eq!(graph.nodes.len(), 5);
+        // Both branches hang off block 1.
+        let parent = &blockchain.chain[1].hash;
+        assert_eq!(graph.edges.iter().filter(|edge| &edge.to == parent).count(), 2);
+
+        let dot = graph.to_dot();
+        assert!(dot.starts_with("digraph chain {"));
+        assert!(dot.contains(&format!("\"{}\" [label=\"#2 ", stale)));
+        assert!(dot.contains("style=dashed"));
+
+        let shallow = ChainGraph::build(&blockchain, 1);
+        assert_eq!(shallow.nodes.len(), 1);
+        assert!(shallow.edges.is_empty());
+    }
+}
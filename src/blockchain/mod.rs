@@ -1,2 +1,8 @@
 pub mod block;
 pub mod chain;
+pub mod gc;
+pub mod graph;
+pub mod storage;
+pub use sierpchain_shared::target;
+pub mod txindex;
+pub mod utxo;
@@ -0,0 +1,113 @@
+use serde::Serialize;
+use std::sync::{Arc, Mutex};
+
+use super::block::Block;
+
+/// How many of the latest blocks the growth rate is estimated from.
+pub const GROWTH_WINDOW: usize = 100;
+
+/// A block's serialized size, as reported by `/admin/storage`.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct BlockSize {
+    pub index: u64,
+    pub hash: String,
+    pub fractal_type: &'static str,
+    pub bytes: u64,
+}
+
+/// The serialized size of each block on the active chain. Blocks are only
+/// measured once: `update` measures those added since the last call, and
+/// those that replaced others in a reorg.
+#[derive(Debug, Default)]
+pub struct BlockSizes {
+    sizes: Vec<BlockSize>,
+}
+
+pub type SharedBlockSizes = Arc<Mutex<BlockSizes>>;
+
+impl BlockSizes {
+    /// Brings the sizes in line with `chain`.
+    pub fn update(&mut self, chain: &[Block]) {
+        let unchanged = self
+            .sizes
+            .iter()
+            .zip(chain)
+            .take_while(|(size, block)| size.hash == block.hash)
+            .count();
+        self.sizes.truncate(unchanged);
+        self.sizes.extend(chain[unchanged..].iter().map(|block| BlockSize {
+            index: block.index,
+            hash: block.hash.clone(),
+            fractal_type: block.fractal.type_name(),
+            bytes: serde_json::to_vec(block).map_or(0, |bytes| bytes.len() as u64),
+        }));
+    }
+
+    pub fn block_count(&self) -> usize {
+        self.sizes.len()
+    }
+
+    pub fn total_bytes(&self) -> u64 {
+        self.sizes.iter().map(|size| size.bytes).sum()
+    }
+
+    /// Returns the `count` largest blocks, largest first. Blocks of the same
+    /// size are listed lowest first.
+    pub fn largest(&self, count: usize) -> Vec<BlockSize> {
+        let mut sizes: Vec<&BlockSize> = self.sizes.iter().collect();
+        sizes.sort_by(|a, b| b.bytes.cmp(&a.bytes).then(a.index.cmp(&b.index)));
+        sizes.into_iter().take(count).cloned().collect()
+    }
+
+    /// Estimates how many bytes a day the chain grows by, from the sizes and
+    /// timestamps of the last `GROWTH_WINDOW` blocks of `chain`. `None` if
+    /// they span no time.
+    pub fn growth_bytes_per_day(&self, chain: &[Block]) -> Option<f64> {
+        let window = GROWTH_WINDOW.min(chain.len()).min(self.sizes.len());
+        let (first, last) = (chain.len().checked_sub(window)?, chain.last()?);
+        let elapsed = last.timestamp - chain.get(first)?.timestamp;
+        if elapsed <= 0 {
+            return None;
+        }
+        // The first block in the window only marks when it started.
+        let bytes: u64 = self.sizes[self.sizes.len() - window + 1..].iter().map(|size| size.bytes).sum();
+        Some(bytes as f64 * 86_400.0 / elapsed as f64)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blockchain::chain::Blockchain;
+    use crate::fractal::FractalType;
+
+    #[test]
+    fn test_sizes_follow_reorgs_and_estimate_growth() {
+        let mut blockchain = Blockchain::new(1);
+        for seed in 0..3 {
+            blockchain.add_block(FractalType::Sierpinski { depth: 1, seed }, vec![]).unwrap();
+        }
+        let mut sizes = BlockSizes::default();
+        sizes.update(&blockchain.chain);
+        assert_eq!(sizes.block_count(), 4);
+
+        // Replace the tip and check only it is remeasured.
+        let replaced = blockchain.chain.pop().unwrap();
+        blockchain.add_block(FractalType::Sierpinski { depth: 3, seed: 0 }, vec![]).unwrap();
+        sizes.update(&blockchain.chain);
+        assert_eq!(sizes.block_count(), 4);
+        assert!(sizes.largest(4).iter().all(|size| size.hash != replaced.hash));
+        let expected: u64 = blockchain.chain.iter().map(|block| serde_json::to_vec(block).unwrap().len() as u64).sum();
+        assert_eq!(sizes.total_bytes(), expected);
+
+        // One block a minute, each of them counted once.
+        let start = blockchain.chain[0].timestamp;
+        for (i, block) in blockchain.chain.iter_mut().enumerate() {
+            block.timestamp = start + 60 * i as i64;
+        }
+        let later: u64 = sizes.sizes[1..].iter().map(|size| size.bytes).sum();
+        let growth = sizes.growth_bytes_per_day(&blockchain.chain).unwrap();
+        assert!((growth - later as f64 * 86_400.0 / 180.0).abs() < 1e-6);
+        assert_eq!(BlockSizes::default().growth_bytes_per_day(&blockchain.chain), None);
+    }
+}
@@ -0,0 +1,323 @@
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::fs;
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+use super::block::Block;
+
+/// The directory the transaction index is kept in.
+pub const TX_INDEX_DIR: &str = "txindex";
+/// The number of files the index's entries are spread over, by txid hash.
+const BUCKETS: usize = 256;
+/// The file recording which blocks the index covers.
+const BLOCKS_FILE: &str = "blocks.json";
+
+/// Sizing for the index's bloom filter, from the `[txindex]` config table.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TxIndexConfig {
+    /// How many transactions the filter is sized for. Beyond that, its false
+    /// positive rate climbs and more lookups fall through to disk.
+    pub expected_transactions: usize,
+    pub false_positive_rate: f64,
+}
+
+impl Default for TxIndexConfig {
+    fn default() -> Self {
+        Self { expected_transactions: 1_000_000, false_positive_rate: 0.01 }
+    }
+}
+
+/// A set of strings that can answer "definitely not present" without
+/// storing them. Entries can't be removed, so a transaction disconnected by
+/// a reorg is still "maybe present" until the filter is rebuilt.
+#[derive(Debug, Clone)]
+pub struct BloomFilter {
+    bits: Vec<u64>,
+    hashes: u32,
+}
+
+impl BloomFilter {
+    /// Sizes a filter to hold `expected_items` with the given false
+    /// positive rate.
+    pub fn with_capacity(expected_items: usize, false_positive_rate: f64) -> Self {
+        let items = expected_items.max(1) as f64;
+        let rate = false_positive_rate.clamp(1e-9, 0.5);
+        let bits = (-items * rate.ln() / std::f64::consts::LN_2.powi(2)).ceil().max(64.0) as usize;
+        let hashes = ((bits as f64 / items) * std::f64::consts::LN_2).round().clamp(1.0, 16.0) as u32;
+        Self { bits: vec![0; bits.div_ceil(64)], hashes }
+    }
+
+    /// The bit positions for `item`, derived from one SHA-256 digest by
+    /// double hashing.
+    fn positions(&self, item: &str) -> impl Iterator<Item = usize> + '_ {
+        let digest = Sha256::digest(item.as_bytes());
+        let h1 = u64::from_le_bytes(digest[..8].try_into().unwrap());
+        let h2 = u64::from_le_bytes(digest[8..16].try_into().unwrap()) | 1;
+        let len = (self.bits.len() * 64) as u64;
+        (0..self.hashes as u64).map(move |i| (h1.wrapping_add(i.wrapping_mul(h2)) % len) as usize)
+    }
+
+    pub fn insert(&mut self, item: &str) {
+        let positions: Vec<usize> = self.positions(item).collect();
+        for position in positions {
+            self.bits[position / 64] |= 1 << (position % 64);
+        }
+    }
+
+    /// False means `item` was never inserted; true means it may have been.
+    pub fn might_contain(&self, item: &str) -> bool {
+        self.positions(item).all(|position| self.bits[position / 64] & (1 << (position % 64)) != 0)
+    }
+}
+
+/// Where a confirmed transaction is: the height of its block and its
+/// position among the block's transactions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TxLocation {
+    pub height: u64,
+    pub position: usize,
+}
+
+/// Maps confirmed txids to their location on the active chain.
+///
+/// The map lives on disk, spread over bucket files by a hash of the txid so a
+/// lookup reads only one of them. A bloom filter in memory answers for
+/// txids that were never indexed without touching the disk. Only the hashes
+/// of the indexed blocks are kept in memory, so `sync` can tell which blocks
+/// were connected or disconnected since it last ran.
+#[derive(Debug)]
+pub struct TxIndex {
+    dir: PathBuf,
+    config: TxIndexConfig,
+    bloom: BloomFilter,
+    block_hashes: Vec<String>,
+}
+
+pub type SharedTxIndex = Arc<Mutex<TxIndex>>;
+
+impl TxIndex {
+    /// Opens the index kept in `dir`, creating it if there is none, and
+    /// fills the bloom filter from its entries.
+    pub fn open(dir: impl Into<PathBuf>, config: TxIndexConfig) -> std::io::Result<Self> {
+        let dir = dir.into();
+        fs::create_dir_all(&dir)?;
+        let block_hashes = match fs::read_to_string(dir.join(BLOCKS_FILE)) {
+            Ok(contents) => serde_json::from_str(&contents).unwrap_or_else(|e| {
+                tracing::warn!("Ignoring unreadable transaction index {}: {}", dir.display(), e);
+                Vec::new()
+            }),
+            Err(_) => Vec::new(),
+        };
+        let bloom = BloomFilter::with_capacity(config.expected_transactions, config.false_positive_rate);
+        let mut index = Self { dir, config, bloom, block_hashes };
+        if index.block_hashes.is_empty() {
+            // Nothing recorded as covered, so any entries are stale.
+            index.truncate(0)?;
+        }
+        for bucket in 0..BUCKETS {
+            for txid in index.read_bucket(bucket)?.keys() {
+                index.bloom.insert(txid);
+            }
+        }
+        Ok(index)
+    }
+
+    /// Empties the index and indexes `chain` from scratch, which also
+    /// clears disconnected transactions out of the bloom filter.
+    pub fn reindex(&mut self, chain: &[Block]) -> std::io::Result<()> {
+        self.truncate(0)?;
+        self.bloom = BloomFilter::with_capacity(self.config.expected_transactions, self.config.false_positive_rate);
+        self.sync(chain)
+    }
+
+    /// Brings the index in line with `chain`: entries from blocks no longer
+    /// on it are removed and the blocks added since are indexed.
+    pub fn sync(&mut self, chain: &[Block]) -> std::io::Result<()> {
+        let up_to_date = self.block_hashes.len() == chain.len()
+            && self.block_hashes.last().map(String::as_str) == chain.last().map(|block| block.hash.as_str());
+        if up_to_date {
+            return Ok(());
+        }
+        let fork = self
+            .block_hashes
+            .iter()
+            .zip(chain)
+            .take_while(|(hash, block)| **hash == block.hash)
+            .count();
+        if fork < self.block_hashes.len() {
+            self.truncate(fork as u64)?;
+        }
+        for block in &chain[fork..] {
+            self.connect_block(block)?;
+        }
+        self.save_block_hashes()
+    }
+
+    /// Returns the location of `txid`, or `None` if it isn't confirmed on
+    /// the chain last synced. Reads the disk only if the bloom filter says
+    /// the txid may be there.
+    pub fn lookup(&self, txid: &str) -> std::io::Result<Option<TxLocation>> {
+        if !self.bloom.might_contain(txid) {
+            return Ok(None);
+        }
+        Ok(self.read_bucket(Self::bucket_of(txid))?.get(txid).copied())
+    }
+
+    /// Like `lookup`, but only says whether the txid is confirmed. Unreadable
+    /// buckets count as not confirmed.
+    pub fn contains(&self, txid: &str) -> bool {
+        self.lookup(txid).ok().flatten().is_some()
+    }
+
+    /// Adds `block`'s transactions, at the height after the last indexed block.
+    fn connect_block(&mut self, block: &Block) -> std::io::Result<()> {
+        let height = self.block_hashes.len() as u64;
+        let mut by_bucket: HashMap<usize, Vec<(&str, TxLocation)>> = HashMap::new();
+        for (position, tx) in block.transactions.iter().enumerate() {
+            by_bucket.entry(Self::bucket_of(&tx.id)).or_default().push((&tx.id, TxLocation { height, position }));
+        }
+        for (bucket, entries) in by_bucket {
+            let mut contents = self.read_bucket(bucket)?;
+            for (txid, location) in entries {
+                self.bloom.insert(txid);
+                contents.insert(txid.to_string(), location);
+            }
+            self.write_bucket(bucket, &contents)?;
+        }
+        self.block_hashes.push(block.hash.clone());
+        Ok(())
+    }
+
+    /// Disconnects the indexed blocks from `height` up, removing their
+    /// entries. This reads every bucket, but only happens on a reorg.
+    fn truncate(&mut self, height: u64) -> std::io::Result<()> {
+        for bucket in 0..BUCKETS {
+            let mut contents = self.read_bucket(bucket)?;
+            let before = contents.len();
+            contents.retain(|_, location| location.height < height);
+            if contents.len() != before {
+                self.write_bucket(bucket, &contents)?;
+            }
+        }
+        self.block_hashes.truncate(height as usize);
+        self.save_block_hashes()
+    }
+
+    fn bucket_of(txid: &str) -> usize {
+        Sha256::digest(txid.as_bytes())[0] as usize % BUCKETS
+    }
+
+    fn bucket_path(&self, bucket: usize) -> PathBuf {
+        self.dir.join(format!("{:02x}.json", bucket))
+    }
+
+    fn read_bucket(&self, bucket: usize) -> std::io::Result<HashMap<String, TxLocation>> {
+        match fs::read_to_string(self.bucket_path(bucket)) {
+            Ok(contents) => serde_json::from_str(&contents).map_err(std::io::Error::other),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(HashMap::new()),
+            Err(e) => Err(e),
+        }
+    }
+
+    fn write_bucket(&self, bucket: usize, contents: &HashMap<String, TxLocation>) -> std::io::Result<()> {
+        let path = self.bucket_path(bucket);
+        if contents.is_empty() {
+            return fs::remove_file(&path).or_else(|e| if e.kind() == std::io::ErrorKind::NotFound { Ok(()) } else { Err(e) });
+        }
+        let mut file = fs::File::create(path)?;
+        file.write_all(serde_json::to_string(contents).unwrap().as_bytes())
+    }
+
+    fn save_block_hashes(&self) -> std::io::Result<()> {
+        let mut file = fs::File::create(self.dir.join(BLOCKS_FILE))?;
+        file.write_all(serde_json::to_string(&self.block_hashes).unwrap().as_bytes())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blockchain::chain::Blockchain;
+    use crate::core::transaction::{Transaction, TxOutput};
+    use crate::fractal::FractalType;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("sierpchain-{}-{}", name, rand::random::<u64>()));
+        fs::remove_dir_all(&dir).ok();
+        dir
+    }
+
+    fn payment(value: u64) -> Transaction {
+        Transaction::new(vec![], vec![TxOutput { value, script_pub_key: "someone".into() }])
+    }
+
+    #[test]
+    fn test_bloom_filter_has_no_false_negatives() {
+        let mut bloom = BloomFilter::with_capacity(1000, 0.01);
+        let items: Vec<String> = (0..1000).map(|i| format!("tx-{}", i)).collect();
+        for item in &items {
+            bloom.insert(item);
+        }
+        assert!(items.iter().all(|item| bloom.might_contain(item)));
+        let false_positives = (0..10_000).filter(|i| bloom.might_contain(&format!("other-{}", i))).count();
+        assert!(false_positives < 300, "{} false positives", false_positives);
+    }
+
+    #[test]
+    fn test_false_positive_falls_through_to_disk() {
+        let dir = temp_dir("txindex-false-positive");
+        // A filter this small says "maybe" to nearly everything.
+        let config = TxIndexConfig { expected_transactions: 1, false_positive_rate: 0.5 };
+        let mut index = TxIndex::open(&dir, config).unwrap();
+        let mut blockchain = Blockchain::new(1);
+        for seed in 0..10 {
+            blockchain.add_block(FractalType::Sierpinski { depth: 1, seed }, vec![payment(seed + 1)]).unwrap();
+        }
+        index.sync(&blockchain.chain).unwrap();
+
+        let unknown = (0..).map(|i| format!("unknown-{}", i)).find(|txid| index.bloom.might_contain(txid)).unwrap();
+        assert_eq!(index.lookup(&unknown).unwrap(), None);
+        assert!(!index.contains(&unknown));
+
+        // With a properly sized filter, the same miss never reaches the disk.
+        let sized = TxIndex::open(&dir, TxIndexConfig::default()).unwrap();
+        let tip = blockchain.chain.last().unwrap();
+        let location = sized.lookup(&tip.transactions[0].id).unwrap().unwrap();
+        assert_eq!(location, TxLocation { height: tip.index, position: 0 });
+        fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_reorg_moves_transaction_between_blocks() {
+        let dir = temp_dir("txindex-reorg");
+        let mut index = TxIndex::open(&dir, TxIndexConfig::default()).unwrap();
+        let base = Blockchain::new(1);
+        let tx = payment(7);
+
+        let mut first = base.clone();
+        first.add_block(FractalType::Sierpinski { depth: 1, seed: 1 }, vec![payment(1), tx.clone()]).unwrap();
+        index.sync(&first.chain).unwrap();
+        let height = base.chain.len() as u64;
+        assert_eq!(index.lookup(&tx.id).unwrap(), Some(TxLocation { height, position: 1 }));
+
+        // A longer branch confirms it one block later, in a different position.
+        let mut second = base.clone();
+        let replaced = payment(2);
+        second.add_block(FractalType::Sierpinski { depth: 1, seed: 2 }, vec![replaced.clone()]).unwrap();
+        second.add_block(FractalType::Sierpinski { depth: 1, seed: 3 }, vec![tx.clone()]).unwrap();
+        index.sync(&second.chain).unwrap();
+        assert_eq!(index.lookup(&tx.id).unwrap(), Some(TxLocation { height: height + 1, position: 0 }));
+        assert_eq!(index.lookup(&replaced.id).unwrap(), Some(TxLocation { height, position: 0 }));
+        assert_eq!(index.lookup(&first.chain.last().unwrap().transactions[0].id).unwrap(), None);
+
+        // The same state is found after reopening or reindexing.
+        let reopened = TxIndex::open(&dir, TxIndexConfig::default()).unwrap();
+        assert_eq!(reopened.lookup(&tx.id).unwrap(), Some(TxLocation { height: height + 1, position: 0 }));
+        index.reindex(&second.chain).unwrap();
+        assert_eq!(index.lookup(&tx.id).unwrap(), Some(TxLocation { height: height + 1, position: 0 }));
+        fs::remove_dir_all(&dir).ok();
+    }
+}
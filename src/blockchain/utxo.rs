@@ -0,0 +1,157 @@
+use std::collections::{HashMap, HashSet};
+
+use serde::Serialize;
+
+use super::block::Block;
+use super::chain::BURN_ADDRESS;
+use crate::core::transaction::{Transaction, TxOutput};
+
+/// A reference to a transaction output: `(txid, vout)`.
+pub type OutPoint = (String, usize);
+
+/// Running totals of the coins created, moved and destroyed by the chain.
+#[derive(Debug, Clone, Default, Serialize, PartialEq)]
+pub struct SupplyStats {
+    /// The sum of all unspent outputs.
+    pub unspent: u64,
+    /// Everything paid out by coinbase transactions, including recycled fees.
+    pub issued: u64,
+    /// Everything paid in fees by non-coinbase transactions.
+    pub fees: u64,
+    /// Everything sent to `BURN_ADDRESS`.
+    pub burned: u64,
+}
+
+impl SupplyStats {
+    /// Fees leave circulation when paid and come back through the coinbase,
+    /// so the unspent coins must be exactly the net issuance less whatever
+    /// was burned.
+    pub fn is_consistent(&self) -> bool {
+        self.total_supply().checked_sub(self.burned) == Some(self.unspent)
+    }
+
+    /// The net issuance: every coin created by a block reward, burned ones
+    /// included, but not fees paid back out by a coinbase.
+    pub fn total_supply(&self) -> u64 {
+        self.issued.saturating_sub(self.fees)
+    }
+}
+
+/// The set of unspent transaction outputs, indexed by address.
+///
+/// The index is derived entirely from the chain, so it is never serialized;
+/// it is updated as blocks are connected and rebuilt when the chain is loaded
+/// or replaced.
+#[derive(Debug, Clone, Default)]
+pub struct UtxoIndex {
+    utxos: HashMap<OutPoint, TxOutput>,
+    address_index: HashMap<String, HashSet<OutPoint>>,
+    supply: SupplyStats,
+}
+
+impl UtxoIndex {
+    /// Builds the index from scratch by replaying every block in `chain`.
+    pub fn rebuild(chain: &[Block]) -> Self {
+        let mut index = UtxoIndex::default();
+        for block in chain {
+            index.apply_block(block);
+        }
+        index
+    }
+
+    /// Spends the outputs consumed by `block` and adds the ones it creates.
+    pub fn apply_block(&mut self, block: &Block) {
+        for tx in &block.transactions {
+            self.apply_transaction(tx);
+        }
+
+        if !self.supply.is_consistent() {
+            tracing::error!("Supply invariant violated after block {}: {:?}", block.index, self.supply);
+        }
+    }
+
+    /// Spends the outputs consumed by `tx` and adds the ones it creates.
+    pub fn apply_transaction(&mut self, tx: &Transaction) {
+        let mut input_total = 0;
+        for input in &tx.inputs {
+            if let Some(output) = self.spend(&(input.txid.clone(), input.vout)) {
+                input_total += output.value;
+            }
+        }
+        let output_total: u64 = tx.outputs.iter().map(|output| output.value).sum();
+        if tx.is_coinbase() || tx.inputs.is_empty() {
+            self.supply.issued += output_total;
+        } else {
+            self.supply.fees += input_total.saturating_sub(output_total);
+        }
+
+        for (vout, output) in tx.outputs.iter().enumerate() {
+            if output.script_pub_key.pays_to(BURN_ADDRESS) {
+                // Burned outputs are unspendable, so they never enter the set.
+                self.supply.burned += output.value;
+                continue;
+            }
+            self.supply.unspent += output.value;
+            let outpoint = (tx.id.clone(), vout);
+            self.address_index
+                .entry(output.script_pub_key.address().to_string())
+                .or_default()
+                .insert(outpoint.clone());
+            self.utxos.insert(outpoint, output.clone());
+        }
+    }
+
+    fn spend(&mut self, outpoint: &OutPoint) -> Option<TxOutput> {
+        let output = self.utxos.remove(outpoint)?;
+        self.supply.unspent -= output.value;
+        if let Some(outpoints) = self.address_index.get_mut(output.script_pub_key.address()) {
+            outpoints.remove(outpoint);
+            if outpoints.is_empty() {
+                self.address_index.remove(output.script_pub_key.address());
+            }
+        }
+        Some(output)
+    }
+
+    /// Returns the supply totals for the chain indexed so far.
+    pub fn supply(&self) -> &SupplyStats {
+        &self.supply
+    }
+
+    /// Returns the number of unspent outputs.
+    pub fn len(&self) -> usize {
+        self.utxos.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.utxos.is_empty()
+    }
+
+    /// Returns the unspent output at `outpoint`, if there is one.
+    pub fn get(&self, outpoint: &OutPoint) -> Option<&TxOutput> {
+        self.utxos.get(outpoint)
+    }
+
+    /// Returns the unspent outputs locked to `address`, ordered by outpoint.
+    pub fn utxos_for(&self, address: &str) -> Vec<(String, usize, TxOutput)> {
+        let mut utxos: Vec<_> = self
+            .address_index
+            .get(address)
+            .into_iter()
+            .flatten()
+            .map(|(txid, vout)| (txid.clone(), *vout, self.utxos[&(txid.clone(), *vout)].clone()))
+            .collect();
+        utxos.sort_by(|a, b| (&a.0, a.1).cmp(&(&b.0, b.1)));
+        utxos
+    }
+
+    /// Returns the sum of the unspent outputs locked to `address`.
+    pub fn balance(&self, address: &str) -> u64 {
+        self.address_index
+            .get(address)
+            .into_iter()
+            .flatten()
+            .map(|outpoint| self.utxos[outpoint].value)
+            .sum()
+    }
+}
@@ -0,0 +1,344 @@
+use std::fs;
+use std::path::Path;
+use toml_edit::{Document, Item, TableLike};
+
+use crate::blockchain::chain::{DifficultyAlgo, RewardSchedule};
+use crate::blockchain::txindex::TxIndexConfig;
+use crate::core::transaction::{check_coinbase_message, TxOutput};
+use crate::mining::policy::MiningPolicy;
+
+/// The node's optional config file, read from the working directory.
+pub const CONFIG_FILE: &str = "config.toml";
+
+/// Reads the TOML config at `path`, or `None` if there is no such file.
+fn read(path: &Path) -> Result<Option<String>, String> {
+    match fs::read_to_string(path) {
+        Ok(contents) => Ok(Some(contents)),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(format!("Couldn't read {}: {}", path.display(), e)),
+    }
+}
+
+/// Reads the `[reward]` table of the TOML config at `path`, with missing
+/// keys taken from `defaults`. A missing file leaves `defaults` as they are.
+pub fn load_reward_schedule(path: &Path, defaults: RewardSchedule) -> Result<RewardSchedule, String> {
+    let Some(contents) = read(path)? else {
+        return Ok(defaults);
+    };
+    parse_reward_schedule(&contents, defaults).map_err(|e| format!("Invalid config {}: {}", path.display(), e))
+}
+
+/// Reads `difficulty.algorithm` from the TOML config at `path`. A missing
+/// file or key gives the legacy algorithm.
+pub fn load_difficulty_algo(path: &Path) -> Result<DifficultyAlgo, String> {
+    let Some(contents) = read(path)? else {
+        return Ok(DifficultyAlgo::default());
+    };
+    parse_difficulty_algo(&contents).map_err(|e| format!("Invalid config {}: {}", path.display(), e))
+}
+
+/// Parses the `[difficulty]` table of a TOML config, whose only key is
+/// `algorithm`, either `"legacy"` or `"ema"`.
+pub fn parse_difficulty_algo(toml: &str) -> Result<DifficultyAlgo, String> {
+    let document: Document = toml.parse().map_err(|e| format!("{}", e))?;
+    let Some(table) = document.get("difficulty") else {
+        return Ok(DifficultyAlgo::default());
+    };
+    let table = table.as_table_like().ok_or("[difficulty] must be a table")?;
+    let mut algo = DifficultyAlgo::default();
+    for (key, item) in table.iter() {
+        match key {
+            "algorithm" => algo = item.as_str().ok_or("difficulty.algorithm must be a string")?.parse()?,
+            _ => return Err(format!("Unknown key difficulty.{}", key)),
+        }
+    }
+    Ok(algo)
+}
+
+/// Reads the `[txindex]` table of the TOML config at `path`. A missing file
+/// or key keeps the default.
+pub fn load_tx_index_config(path: &Path) -> Result<TxIndexConfig, String> {
+    let Some(contents) = read(path)? else {
+        return Ok(TxIndexConfig::default());
+    };
+    parse_tx_index_config(&contents).map_err(|e| format!("Invalid config {}: {}", path.display(), e))
+}
+
+/// Parses the `[txindex]` table of a TOML config, which sizes the bloom
+/// filter with `expected_transactions` and `false_positive_rate`.
+pub fn parse_tx_index_config(toml: &str) -> Result<TxIndexConfig, String> {
+    let document: Document = toml.parse().map_err(|e| format!("{}", e))?;
+    let Some(table) = document.get("txindex") else {
+        return Ok(TxIndexConfig::default());
+    };
+    let table = table.as_table_like().ok_or("[txindex] must be a table")?;
+    let mut config = TxIndexConfig::default();
+    for (key, item) in table.iter() {
+        match key {
+            "expected_transactions" => {
+                config.expected_transactions = item
+                    .as_integer()
+                    .and_then(|value| usize::try_from(value).ok())
+                    .ok_or("txindex.expected_transactions must be a non-negative integer")?
+            }
+            "false_positive_rate" => {
+                config.false_positive_rate = item
+                    .as_float()
+                    .filter(|rate| *rate > 0.0 && *rate < 1.0)
+                    .ok_or("txindex.false_positive_rate must be a number between 0 and 1")?
+            }
+            _ => return Err(format!("Unknown key txindex.{}", key)),
+        }
+    }
+    Ok(config)
+}
+
+/// Reads the `[mining]` table of the TOML config at `path`. A missing file
+/// or key keeps the default, which mines every block.
+pub fn load_mining_policy(path: &Path) -> Result<MiningPolicy, String> {
+    let Some(contents) = read(path)? else {
+        return Ok(MiningPolicy::default());
+    };
+    parse_mining_policy(&contents).map_err(|e| format!("Invalid config {}: {}", path.display(), e))
+}
+
+/// Parses the `[mining]` table of a TOML config, which sets the mining
+/// policy's `mine_empty_blocks` and `min_transactions`, the default budget
+/// of mining jobs in `max_attempts` and `timeout_ms`, and the
+/// `coinbase_message` of the blocks the node mines.
+pub fn parse_mining_policy(toml: &str) -> Result<MiningPolicy, String> {
+    let document: Document = toml.parse().map_err(|e| format!("{}", e))?;
+    let Some(table) = document.get("mining") else {
+        return Ok(MiningPolicy::default());
+    };
+    let table = table.as_table_like().ok_or("[mining] must be a table")?;
+    let mut policy = MiningPolicy::default();
+    for (key, item) in table.iter() {
+        match key {
+            "mine_empty_blocks" => {
+                policy.mine_empty_blocks = item.as_bool().ok_or("mining.mine_empty_blocks must be a boolean")?
+            }
+            "min_transactions" => {
+                policy.min_transactions = item
+                    .as_integer()
+                    .and_then(|value| usize::try_from(value).ok())
+                    .ok_or("mining.min_transactions must be a non-negative integer")?
+            }
+            "max_attempts" => {
+                policy.max_attempts = Some(
+                    item.as_integer()
+                        .and_then(|value| u64::try_from(value).ok())
+                        .ok_or("mining.max_attempts must be a non-negative integer")?,
+                )
+            }
+            "timeout_ms" => {
+                policy.timeout_ms = Some(
+                    item.as_integer()
+                        .and_then(|value| u64::try_from(value).ok())
+                        .ok_or("mining.timeout_ms must be a non-negative integer")?,
+                )
+            }
+            "coinbase_message" => {
+                let message = item.as_str().ok_or("mining.coinbase_message must be a string")?;
+                check_coinbase_message(message).map_err(|e| format!("mining.coinbase_message: {}", e))?;
+                policy.coinbase_message = Some(message.to_string());
+            }
+            _ => return Err(format!("Unknown key mining.{}", key)),
+        }
+    }
+    Ok(policy)
+}
+
+/// Reads the genesis premine from the TOML config at `path`, or `None` if
+/// the file or its `[genesis]` table is missing.
+pub fn load_premine(path: &Path) -> Result<Option<Vec<TxOutput>>, String> {
+    let Some(contents) = read(path)? else {
+        return Ok(None);
+    };
+    parse_premine(&contents).map_err(|e| format!("Invalid config {}: {}", path.display(), e))
+}
+
+/// Parses the `[genesis]` table of a TOML config, whose `premine` lists the
+/// `address` and `amount`, in base units, of each output the genesis block
+/// pre-mines:
+///
+/// ```toml
+/// [[genesis.premine]]
+/// address = "1..."
+/// amount = 100000000000
+/// ```
+pub fn parse_premine(toml: &str) -> Result<Option<Vec<TxOutput>>, String> {
+    let document: Document = toml.parse().map_err(|e| format!("{}", e))?;
+    let Some(table) = document.get("genesis") else {
+        return Ok(None);
+    };
+    let table = table.as_table_like().ok_or("[genesis] must be a table")?;
+    let mut premine = Vec::new();
+    for (key, item) in table.iter() {
+        match key {
+            "premine" => {
+                let entries: Vec<&dyn TableLike> = match (item.as_array_of_tables(), item.as_array()) {
+                    (Some(tables), _) => tables.iter().map(|table| table as &dyn TableLike).collect(),
+                    (_, Some(array)) => array
+                        .iter()
+                        .map(|value| value.as_inline_table().map(|table| table as &dyn TableLike))
+                        .collect::<Option<_>>()
+                        .ok_or("genesis.premine must be a list of tables")?,
+                    _ => return Err("genesis.premine must be a list of tables".to_string()),
+                };
+                for entry in entries {
+                    premine.push(premine_output(entry)?);
+                }
+            }
+            _ => return Err(format!("Unknown key genesis.{}", key)),
+        }
+    }
+    Ok(Some(premine))
+}
+
+fn premine_output(entry: &dyn TableLike) -> Result<TxOutput, String> {
+    let (mut address, mut amount) = (None, None);
+    for (key, item) in entry.iter() {
+        match key {
+            "address" => address = Some(item.as_str().ok_or("genesis.premine.address must be a string")?),
+            "amount" => {
+                amount = Some(
+                    item.as_integer()
+                        .and_then(|value| u64::try_from(value).ok())
+                        .ok_or("genesis.premine.amount must be a non-negative integer")?,
+                )
+            }
+            _ => return Err(format!("Unknown key genesis.premine.{}", key)),
+        }
+    }
+    match (address, amount) {
+        (Some(address), Some(value)) => Ok(TxOutput { value, script_pub_key: address.into() }),
+        _ => Err("Each genesis.premine entry needs an address and an amount".to_string()),
+    }
+}
+
+/// Parses the `[reward]` table of a TOML config over `defaults`.
+pub fn parse_reward_schedule(toml: &str, defaults: RewardSchedule) -> Result<RewardSchedule, String> {
+    let document: Document = toml.parse().map_err(|e| format!("{}", e))?;
+    let Some(table) = document.get("reward") else {
+        return Ok(defaults);
+    };
+    let table = table.as_table_like().ok_or("[reward] must be a table")?;
+    let mut schedule = defaults;
+    for (key, item) in table.iter() {
+        match key {
+            "initial_subsidy" => schedule.initial_subsidy = integer(key, item)?,
+            "halving_interval" => schedule.halving_interval = integer(key, item)?,
+            "dev_fund_address" => schedule.dev_fund_address = Some(string(key, item)?),
+            "dev_fund_percent" => schedule.dev_fund_percent = integer(key, item)?,
+            "burn_address" => schedule.burn_address = Some(string(key, item)?),
+            "burn_percent" => schedule.burn_percent = integer(key, item)?,
+            _ => return Err(format!("Unknown key reward.{}", key)),
+        }
+    }
+    schedule.validate()?;
+    Ok(schedule)
+}
+
+fn integer<T: TryFrom<i64>>(key: &str, item: &Item) -> Result<T, String> {
+    item.as_integer()
+        .and_then(|value| T::try_from(value).ok())
+        .ok_or_else(|| format!("reward.{} must be a non-negative integer in range", key))
+}
+
+fn string(key: &str, item: &Item) -> Result<String, String> {
+    item.as_str().map(str::to_string).ok_or_else(|| format!("reward.{} must be a string", key))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_reward_schedule() {
+        let toml = r#"
+[blockchain]
+target_block_time = 10000
+
+[reward]
+initial_subsidy = 50
+halving_interval = 210000
+dev_fund_address = "dev"
+dev_fund_percent = 10
+burn_percent = 5
+"#;
+        let schedule = parse_reward_schedule(toml, RewardSchedule::flat(1)).unwrap();
+        assert_eq!(schedule.initial_subsidy, 50);
+        assert_eq!(schedule.halving_interval, 210_000);
+        assert_eq!(schedule.dev_fund_address.as_deref(), Some("dev"));
+        assert_eq!((schedule.dev_fund_percent, schedule.burn_percent), (10, 5));
+        assert_eq!(schedule.burn_address, None);
+
+        assert_eq!(parse_reward_schedule("[fractal]\ndefault_depth = 3\n", RewardSchedule::flat(7)), Ok(RewardSchedule::flat(7)));
+        assert!(parse_reward_schedule("[reward]\nburn_percent = -1\n", RewardSchedule::flat(1)).is_err());
+        assert!(parse_reward_schedule("[reward]\nburn_percent = 101\n", RewardSchedule::flat(1)).is_err());
+        assert!(parse_reward_schedule("[reward]\ndev_fund_percent = 10\n", RewardSchedule::flat(1)).is_err());
+        assert!(parse_reward_schedule("[reward]\nburn_address = \"mine\"\nburn_percent = 1\n", RewardSchedule::flat(1)).is_err());
+        assert!(parse_reward_schedule("[reward]\nsubsidy = 50\n", RewardSchedule::flat(1)).is_err());
+    }
+
+    #[test]
+    fn test_parse_difficulty_algo() {
+        assert_eq!(parse_difficulty_algo("[difficulty]\nalgorithm = \"ema\"\n"), Ok(DifficultyAlgo::Ema));
+        assert_eq!(parse_difficulty_algo("[difficulty]\nalgorithm = \"legacy\"\n"), Ok(DifficultyAlgo::Legacy));
+        assert_eq!(parse_difficulty_algo("[reward]\ninitial_subsidy = 50\n"), Ok(DifficultyAlgo::Legacy));
+        assert!(parse_difficulty_algo("[difficulty]\nalgorithm = \"asert\"\n").is_err());
+        assert!(parse_difficulty_algo("[difficulty]\nwindow = 10\n").is_err());
+    }
+
+    #[test]
+    fn test_parse_tx_index_config() {
+        let config = parse_tx_index_config("[txindex]\nexpected_transactions = 5000\nfalse_positive_rate = 0.001\n").unwrap();
+        assert_eq!(config, TxIndexConfig { expected_transactions: 5000, false_positive_rate: 0.001 });
+        assert_eq!(parse_tx_index_config("[reward]\ninitial_subsidy = 50\n"), Ok(TxIndexConfig::default()));
+        assert!(parse_tx_index_config("[txindex]\nfalse_positive_rate = 1.5\n").is_err());
+        assert!(parse_tx_index_config("[txindex]\nexpected_transactions = -1\n").is_err());
+        assert!(parse_tx_index_config("[txindex]\nsize = 10\n").is_err());
+    }
+
+    #[test]
+    fn test_parse_mining_policy() {
+        let policy = parse_mining_policy("[mining]\nmine_empty_blocks = false\nmin_transactions = 3\n").unwrap();
+        assert_eq!(policy, MiningPolicy { mine_empty_blocks: false, min_transactions: 3, ..MiningPolicy::default() });
+        let policy = parse_mining_policy("[mining]\nmax_attempts = 1000\ntimeout_ms = 5000\n").unwrap();
+        assert_eq!((policy.max_attempts, policy.timeout_ms), (Some(1_000), Some(5_000)));
+        assert!(parse_mining_policy("[mining]\ntimeout_ms = -1\n").is_err());
+        let policy = parse_mining_policy("[mining]\ncoinbase_message = \"hello from the pool\"\n").unwrap();
+        assert_eq!(policy.coinbase_message.as_deref(), Some("hello from the pool"));
+        assert!(parse_mining_policy(&format!("[mining]\ncoinbase_message = \"{}\"\n", "x".repeat(101))).is_err());
+        assert_eq!(parse_mining_policy("[reward]\ninitial_subsidy = 50\n"), Ok(MiningPolicy::default()));
+        assert!(parse_mining_policy("[mining]\nmine_empty_blocks = \"no\"\n").is_err());
+        assert!(parse_mining_policy("[mining]\nmin_transactions = -1\n").is_err());
+        assert!(parse_mining_policy("[mining]\ninterval_ms = 100\n").is_err());
+    }
+
+    #[test]
+    fn test_parse_premine() {
+        let toml = r#"
+[[genesis.premine]]
+address = "alice"
+amount = 100
+
+[[genesis.premine]]
+address = "bob"
+amount = 250
+"#;
+        let premine = parse_premine(toml).unwrap().unwrap();
+        assert_eq!(premine, vec![
+            TxOutput { value: 100, script_pub_key: "alice".into() },
+            TxOutput { value: 250, script_pub_key: "bob".into() },
+        ]);
+        let inline = parse_premine("[genesis]\npremine = [{ address = \"alice\", amount = 100 }]\n").unwrap().unwrap();
+        assert_eq!(inline, premine[..1]);
+        assert_eq!(parse_premine("[genesis]\n"), Ok(Some(vec![])));
+        assert_eq!(parse_premine("[reward]\ninitial_subsidy = 50\n"), Ok(None));
+        assert!(parse_premine("[[genesis.premine]]\naddress = \"alice\"\n").is_err());
+        assert!(parse_premine("[[genesis.premine]]\naddress = \"alice\"\namount = -5\n").is_err());
+        assert!(parse_premine("[genesis]\npremine = 5\n").is_err());
+    }
+}
@@ -0,0 +1,90 @@
+use std::fmt;
+
+/// The reasons a decimal coin amount can't be converted to base units.
+#[derive(Debug, Clone, PartialEq)]
+pub enum AmountError {
+    /// The string isn't a plain non-negative decimal number.
+    Invalid,
+    /// The amount has more decimals than the chain's base unit can express.
+    TooPrecise { max_decimals: u32 },
+    /// The amount doesn't fit in a `u64` of base units.
+    TooLarge,
+}
+
+impl fmt::Display for AmountError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AmountError::Invalid => write!(f, "Amount must be a decimal number such as 12.5"),
+            AmountError::TooPrecise { max_decimals } => {
+                write!(f, "Amount has more than {} decimal places", max_decimals)
+            }
+            AmountError::TooLarge => write!(f, "Amount exceeds the maximum supply"),
+        }
+    }
+}
+
+/// Parses a decimal number of coins, e.g. `"0.005"`, into base units, where
+/// one coin is `10^decimals` base units. The conversion is exact: amounts
+/// that would need rounding are rejected rather than truncated.
+pub fn parse_coins(amount: &str, decimals: u32) -> Result<u64, AmountError> {
+    let (whole, fraction) = amount.split_once('.').unwrap_or((amount, ""));
+    let is_digits = |part: &str| part.bytes().all(|byte| byte.is_ascii_digit());
+    if (whole.is_empty() && fraction.is_empty()) || !is_digits(whole) || !is_digits(fraction) {
+        return Err(AmountError::Invalid);
+    }
+    if amount.ends_with('.') {
+        return Err(AmountError::Invalid);
+    }
+    // Trailing zeros don't add precision.
+    let fraction = fraction.trim_end_matches('0');
+    if fraction.len() > decimals as usize {
+        return Err(AmountError::TooPrecise { max_decimals: decimals });
+    }
+
+    let scale = 10u64.checked_pow(decimals).ok_or(AmountError::TooLarge)?;
+    let whole_units = if whole.is_empty() {
+        0
+    } else {
+        whole
+            .parse::<u64>()
+            .map_err(|_| AmountError::TooLarge)?
+            .checked_mul(scale)
+            .ok_or(AmountError::TooLarge)?
+    };
+    let fraction_units = if fraction.is_empty() {
+        0
+    } else {
+        // At most `decimals` digits, so this is below `scale` and can't overflow.
+        fraction.parse::<u64>().unwrap() * 10u64.pow(decimals - fraction.len() as u32)
+    };
+    whole_units.checked_add(fraction_units).ok_or(AmountError::TooLarge)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_coins() {
+        assert_eq!(parse_coins("1", 8), Ok(100_000_000));
+        assert_eq!(parse_coins("0.005", 8), Ok(500_000));
+        assert_eq!(parse_coins(".5", 8), Ok(50_000_000));
+        assert_eq!(parse_coins("0.00000001", 8), Ok(1));
+        assert_eq!(parse_coins("1.500000000", 8), Ok(150_000_000));
+        assert_eq!(parse_coins("50", 0), Ok(50));
+
+        assert_eq!(parse_coins("0.000000001", 8), Err(AmountError::TooPrecise { max_decimals: 8 }));
+        assert_eq!(parse_coins("0.5", 0), Err(AmountError::TooPrecise { max_decimals: 0 }));
+        for invalid in ["", ".", "1.", "-1", "+1", "1e3", "1.2.3", " 1", "1,000", "one"] {
+            assert_eq!(parse_coins(invalid, 8), Err(AmountError::Invalid), "{:?}", invalid);
+        }
+    }
+
+    #[test]
+    fn test_parse_coins_at_max_supply() {
+        assert_eq!(parse_coins("184467440737.09551615", 8), Ok(u64::MAX));
+        assert_eq!(parse_coins("184467440737.09551616", 8), Err(AmountError::TooLarge));
+        assert_eq!(parse_coins("184467440738", 8), Err(AmountError::TooLarge));
+        assert_eq!(parse_coins("99999999999999999999999", 8), Err(AmountError::TooLarge));
+    }
+}
@@ -0,0 +1,142 @@
+//! The canonical encoding of transactions. The encoding itself, and that of
+//! blocks and fractals, is in `sierpchain-shared`, which miners outside the
+//! node hash blocks with too.
+
+use crate::core::script::UnlockingScript;
+use crate::core::transaction::{Transaction, TxInput, TxOutput};
+
+pub use sierpchain_shared::canonical::{to_bytes, to_string, Canonical, Encoder};
+
+impl Canonical for Transaction {
+    fn encode(&self, out: &mut Encoder) {
+        let Transaction { id, timestamp, inputs, outputs, expiry_height, memo } = self;
+        out.object(|o| {
+            o.field("id", id).field("timestamp", timestamp).field("inputs", inputs).field("outputs", outputs);
+            if let Some(expiry_height) = expiry_height {
+                o.field("expiry_height", expiry_height);
+            }
+            if let Some(memo) = memo {
+                o.field("memo", memo);
+            }
+        });
+    }
+}
+
+impl Canonical for TxInput {
+    fn encode(&self, out: &mut Encoder) {
+        let TxInput { txid, vout, script_sig, sequence } = self;
+        // The legacy two-string layout of the unlocking script.
+        let (script_sig, pub_key) = match script_sig {
+            UnlockingScript::Data(data) => (data.as_str(), ""),
+            UnlockingScript::SignaturePubKey { sig, pub_key } => (sig.as_str(), pub_key.as_str()),
+        };
+        out.object(|o| {
+            o.field("txid", txid)
+                .field("vout", vout)
+                .field("script_sig", script_sig)
+                .field("pub_key", pub_key)
+                .field("sequence", sequence);
+        });
+    }
+}
+
+impl Canonical for TxOutput {
+    fn encode(&self, out: &mut Encoder) {
+        let TxOutput { value, script_pub_key } = self;
+        out.object(|o| {
+            o.field("value", value).field("script_pub_key", script_pub_key.address());
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blockchain::block::{Block, BlockHashableData};
+    use crate::fractal::coord::Coord;
+    use crate::fractal::julia::Julia;
+    use crate::fractal::utils::RngVersion;
+    use crate::fractal::{FractalData, FractalType};
+    use sha2::{Digest, Sha256};
+
+    /// A block whose Julia fractal has fractional coordinates, built field
+    /// by field so that nothing in it depends on generation.
+    fn fractional_block() -> Block {
+        let spend = TxInput {
+            txid: "ab".repeat(32),
+            vout: 1,
+            script_sig: UnlockingScript::SignaturePubKey { sig: "5161".to_string(), pub_key: "beef".to_string() },
+            sequence: 0,
+        };
+        let payment = TxOutput { value: 250, script_pub_key: "recipient".into() };
+        let mut tx = Transaction::new_at(vec![spend], vec![payment], 1_700_000_000);
+        tx.id = "tx\"id\\\n\u{1}é".to_string();
+        tx.expiry_height = Some(12);
+        Block {
+            index: 7,
+            timestamp: -3,
+            fractal: FractalData::Julia(Julia {
+                width: 2,
+                height: 1,
+                x_min: Coord::from_f64(-1.5).unwrap(),
+                x_max: Coord::from_f64(0.1).unwrap(),
+                y_min: Coord::from_f64(-0.000_000_000_001).unwrap(),
+                y_max: Coord::from_f64(1.25).unwrap(),
+                c_real: Coord::from_f64(-0.8).unwrap(),
+                c_imag: Coord::from_f64(0.156).unwrap(),
+                max_iterations: 30,
+                seed: u64::MAX,
+                data: vec![3, 30],
+                rng_version: RngVersion::Lcg,
+            }),
+            transactions: vec![tx],
+            previous_hash: "00".repeat(4),
+            hash: "ignored".to_string(),
+            nonce: 9,
+            bits: 0x1f00_ffff,
+            style_seed: 7,
+        }
+    }
+
+    #[test]
+    fn test_canonical_bytes_are_pinned() {
+        let block = fractional_block();
+        let encoded = String::from_utf8(to_bytes(&BlockHashableData::from(&block))).unwrap();
+        let expected = concat!(
+            r#"{"index":7,"timestamp":-3,"#,
+            r#""fractal":{"type":"Julia","data":{"width":2,"height":1,"x_min":-1500000000000,"x_max":100000000000,"#,
+            r#""y_min":-1,"y_max":1250000000000,"c_real":-800000000000,"c_imag":156000000000,"max_iterations":30,"#,
+            r#""seed":18446744073709551615,"data":[3,30]}},"#,
+            r#""transactions":[{"id":"tx\"id\\\n\u0001é","timestamp":1700000000,"#,
+            r#""inputs":[{"txid":"abababababababababababababababababababababababababababababababab","vout":1,"#,
+            r#""script_sig":"5161","pub_key":"beef","sequence":0}],"#,
+            r#""outputs":[{"value":250,"script_pub_key":"recipient"}],"expiry_height":12}],"#,
+            r#""previous_hash":"00000000","hash":"","nonce":9,"bits":520159231,"style_seed":7}"#,
+        );
+        assert_eq!(encoded, expected);
+        assert_eq!(block.calculate_hash(), format!("{:x}", Sha256::digest(expected.as_bytes())));
+    }
+
+    #[test]
+    fn test_canonical_bytes_match_the_legacy_json() {
+        let mut block = fractional_block();
+        for (bits, style_seed) in [(0, 0), (0, 7), (0x1f00_ffff, 0)] {
+            block.bits = bits;
+            block.style_seed = style_seed;
+            block.transactions[0].expiry_height = (bits != 0).then_some(12);
+            let hashable = BlockHashableData::from(&block);
+            let mut legacy = block.clone();
+            legacy.hash = String::new();
+            assert_eq!(to_bytes(&hashable), serde_json::to_vec(&legacy).unwrap());
+        }
+        for fractal_type in [FractalType::Sierpinski { depth: 2, seed: 3 }, block.fractal.fractal_type()] {
+            block.fractal = fractal_type.generate_with(RngVersion::SplitMix64);
+            let mut json = block.clone();
+            json.hash = String::new();
+            assert_eq!(to_bytes(&BlockHashableData::from(&block)), serde_json::to_vec(&json).unwrap());
+        }
+        for c in (0..0x80u8).map(char::from).chain(['é', '\u{2028}', '🔺']) {
+            assert_eq!(to_bytes(&c.to_string()), serde_json::to_vec(&c.to_string()).unwrap(), "{:?}", c);
+        }
+    }
+}
@@ -0,0 +1,127 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use crate::api::handlers::wallet_from_private_key;
+use crate::core::spend_policy::SpendPolicy;
+use crate::core::wallet::Wallet;
+
+/// Where the node's wallets are saved, next to `blockchain.json`.
+pub const WALLETS_FILE: &str = "wallets.json";
+
+/// A wallet as saved to disk and in backups.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct StoredWallet {
+    pub address: String,
+    /// The hex-encoded signing key.
+    pub private_key: String,
+    /// A name given to an imported key.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub label: Option<String>,
+    /// What the node may spend from the wallet.
+    #[serde(default, skip_serializing_if = "SpendPolicy::is_unrestricted")]
+    pub policy: SpendPolicy,
+}
+
+impl From<&Wallet> for StoredWallet {
+    fn from(wallet: &Wallet) -> Self {
+        Self {
+            address: wallet.get_address(),
+            private_key: hex::encode(wallet.signing_key.to_bytes()),
+            label: None,
+            policy: SpendPolicy::default(),
+        }
+    }
+}
+
+/// The node's wallets, persisted to a JSON file. The first one is the wallet
+/// the node mines to.
+#[derive(Debug)]
+pub struct KeyStore {
+    path: PathBuf,
+    wallets: Vec<StoredWallet>,
+}
+
+pub type SharedKeyStore = Arc<Mutex<KeyStore>>;
+
+impl KeyStore {
+    /// Loads the wallets saved at `path`. A missing file gives an empty store;
+    /// an unreadable one is an error rather than being overwritten.
+    pub fn load(path: impl Into<PathBuf>) -> std::io::Result<Self> {
+        let path = path.into();
+        let wallets = match fs::read_to_string(&path) {
+            Ok(contents) => serde_json::from_str(&contents)
+                .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+            Err(e) => return Err(e),
+        };
+        Ok(Self { path, wallets })
+    }
+
+    /// Writes the wallets back to the file they were loaded from.
+    pub fn save(&self) -> std::io::Result<()> {
+        let serialized = serde_json::to_string_pretty(&self.wallets).unwrap();
+        let mut file = fs::File::create(&self.path)?;
+        file.write_all(serialized.as_bytes())
+    }
+
+    /// The file the store is saved to.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn wallets(&self) -> &[StoredWallet] {
+        &self.wallets
+    }
+
+    /// Returns the wallet the node mines to, creating and saving one if the
+    /// store is empty.
+    pub fn default_wallet(&mut self) -> std::io::Result<Wallet> {
+        if self.wallets.is_empty() {
+            self.wallets.push(StoredWallet::from(&Wallet::new()));
+            self.save()?;
+        }
+        wallet_from_private_key(&self.wallets[0].private_key)
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))
+    }
+
+    /// Adds the wallets not already in the store, after the existing ones,
+    /// and returns how many were added.
+    pub fn merge(&mut self, wallets: Vec<StoredWallet>) -> usize {
+        let before = self.wallets.len();
+        for wallet in wallets {
+            if !self.wallets.iter().any(|existing| existing.address == wallet.address) {
+                self.wallets.push(wallet);
+            }
+        }
+        self.wallets.len() - before
+    }
+
+    /// The spend policy of every stored wallet, by address.
+    pub fn policies(&self) -> HashMap<String, SpendPolicy> {
+        self.wallets
+            .iter()
+            .map(|wallet| (wallet.address.clone(), wallet.policy.clone()))
+            .collect()
+    }
+
+    /// Sets the spend policy of the wallet at `address`, returning false if
+    /// there is none.
+    pub fn set_policy(&mut self, address: &str, policy: SpendPolicy) -> bool {
+        match self.wallets.iter_mut().find(|wallet| wallet.address == address) {
+            Some(wallet) => {
+                wallet.policy = policy;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Replaces every wallet in the store.
+    pub fn replace(&mut self, wallets: Vec<StoredWallet>) {
+        self.wallets = wallets;
+    }
+}
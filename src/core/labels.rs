@@ -0,0 +1,103 @@
+use std::collections::BTreeMap;
+use std::ffi::OsString;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+/// Where transaction labels are saved, next to `blockchain.json`.
+pub const LABELS_FILE: &str = "labels.json";
+/// The longest label the node keeps, in bytes.
+pub const MAX_LABEL_BYTES: usize = 256;
+
+/// Checks that `label` is worth keeping: not blank and within
+/// `MAX_LABEL_BYTES`.
+pub fn check_label(label: &str) -> Result<(), String> {
+    if label.trim().is_empty() {
+        return Err("A label can't be blank".to_string());
+    }
+    if label.len() > MAX_LABEL_BYTES {
+        return Err(format!("A label is at most {} bytes, not {}", MAX_LABEL_BYTES, label.len()));
+    }
+    Ok(())
+}
+
+/// Notes the node's user has written about transactions, by txid, persisted
+/// to a JSON file. They are the node's own and never leave it.
+#[derive(Debug)]
+pub struct LabelStore {
+    path: PathBuf,
+    labels: BTreeMap<String, String>,
+}
+
+pub type SharedLabelStore = Arc<Mutex<LabelStore>>;
+
+impl LabelStore {
+    /// Loads the labels saved at `path`. A missing file gives an empty store;
+    /// an unreadable one is an error rather than being overwritten.
+    pub fn load(path: impl Into<PathBuf>) -> std::io::Result<Self> {
+        let path = path.into();
+        let labels = match fs::read_to_string(&path) {
+            Ok(contents) => serde_json::from_str(&contents)
+                .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => BTreeMap::new(),
+            Err(e) => return Err(e),
+        };
+        Ok(Self { path, labels })
+    }
+
+    /// Writes the labels back to the file they were loaded from. They are
+    /// written to a temporary file beside it first and renamed over it, so a
+    /// crash mid-write leaves the old labels rather than half of the new.
+    pub fn save(&self) -> std::io::Result<()> {
+        let serialized = serde_json::to_string_pretty(&self.labels).unwrap();
+        let mut temp_path = OsString::from(self.path.as_os_str());
+        temp_path.push(".tmp");
+        let temp_path = PathBuf::from(temp_path);
+        let mut file = fs::File::create(&temp_path)?;
+        file.write_all(serialized.as_bytes())?;
+        file.sync_all()?;
+        fs::rename(&temp_path, &self.path)
+    }
+
+    /// The file the store is saved to.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn get(&self, txid: &str) -> Option<&str> {
+        self.labels.get(txid).map(String::as_str)
+    }
+
+    /// Labels `txid`, replacing any label it had.
+    pub fn set(&mut self, txid: &str, label: String) {
+        self.labels.insert(txid.to_string(), label);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_labels_are_saved_and_reloaded() {
+        let path = std::env::temp_dir().join(format!("sierpchain-labels-{}.json", rand::random::<u64>()));
+        let mut store = LabelStore::load(&path).unwrap();
+        assert_eq!(store.get("ab12"), None);
+        store.set("ab12", "rent payment".to_string());
+        store.set("ab12", "refund".to_string());
+        store.save().unwrap();
+
+        let reloaded = LabelStore::load(&path).unwrap();
+        assert_eq!(reloaded.get("ab12"), Some("refund"));
+        assert!(!path.with_extension("json.tmp").exists());
+
+        fs::write(&path, "not json").unwrap();
+        assert!(LabelStore::load(&path).is_err());
+        fs::remove_file(&path).ok();
+
+        assert!(check_label("rent").is_ok());
+        assert!(check_label("  ").is_err());
+        assert!(check_label(&"x".repeat(MAX_LABEL_BYTES + 1)).is_err());
+    }
+}
@@ -0,0 +1,1232 @@
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+use crate::blockchain::block::Block;
+use crate::blockchain::chain::{Blockchain, Reorg};
+use crate::blockchain::utxo::OutPoint;
+use crate::core::clock::{system_clock, SharedClock};
+use crate::core::orphan::OrphanPool;
+use crate::core::script::UnlockingScript;
+use crate::core::transaction::{Transaction, TxError, TxInput, TxOutput};
+use crate::core::wallet::Wallet;
+
+/// How far ahead of local time a transaction may be timestamped, in seconds.
+pub const DEFAULT_MAX_FUTURE_DRIFT_SECS: i64 = 120;
+/// The most serialized bytes of pooled transactions the miner packs into one block.
+pub const MAX_BLOCK_TRANSACTIONS_SIZE: usize = 1_000_000;
+/// The default minimum fee per serialized byte for relaying a transaction.
+/// Zero accepts free transactions.
+pub const DEFAULT_MIN_RELAY_FEE_PER_BYTE: u64 = 0;
+/// Roughly how many bytes a signed input adds to a transaction. An output
+/// worth less than the relay fee for that many bytes costs more to spend
+/// than it is worth.
+pub const SIGNED_INPUT_SIZE: u64 = 330;
+/// The default for how much more a replacement must pay in fees than the
+/// transactions it replaces. Any increase will do.
+pub const DEFAULT_MIN_REPLACEMENT_FEE_INCREMENT: u64 = 1;
+
+/// The reasons a transaction can be refused entry to the pool.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "reason", rename_all = "snake_case")]
+pub enum MempoolError {
+    TimestampTooFarInFuture { timestamp: i64, now: i64 },
+    FeeTooLow { fee: u64, required: u64 },
+    Expired { expiry_height: u64, height: u64 },
+    /// Coinbase transactions only enter the chain in the blocks that mint them.
+    Coinbase,
+    /// The declared ID isn't the transaction's hash.
+    IdMismatch { id: String, expected: String },
+    /// The transaction is already pooled, or waiting for its parents.
+    AlreadyKnown { txid: String },
+    /// The transaction spends the same output twice.
+    DuplicateInput { txid: String, vout: usize },
+    /// A spend is unknown or unsigned, the outputs exceed the inputs, or
+    /// there are more inputs or outputs than the chain allows.
+    Invalid(TxError),
+    /// A pooled transaction already spends one of the inputs, and can't be
+    /// replaced because it spends an input the new one doesn't.
+    Conflict { txid: String, vout: usize, spent_by: String },
+    /// A replacement doesn't pay enough more than the transactions it
+    /// replaces and their descendants.
+    ReplacementFeeTooLow { fee: u64, required: u64 },
+    /// A replacement pays the recipient of an output of the transaction it
+    /// replaces less than that did, change aside.
+    ReplacementReducesOutput { txid: String, vout: usize },
+    /// An output is worth less than it would cost to spend.
+    Dust { vout: usize, value: u64, threshold: u64 },
+}
+
+impl fmt::Display for MempoolError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MempoolError::TimestampTooFarInFuture { timestamp, now } => write!(
+                f,
+                "Transaction timestamp {} is {} seconds ahead of local time",
+                timestamp,
+                timestamp - now
+            ),
+            MempoolError::FeeTooLow { fee, required } => write!(
+                f,
+                "Transaction pays a fee of {} but the minimum relay fee for its size is {}",
+                fee, required
+            ),
+            MempoolError::Expired { expiry_height, height } => write!(
+                f,
+                "Transaction expired at height {} and can't be mined at height {}",
+                expiry_height, height
+            ),
+            MempoolError::Coinbase => write!(f, "Coinbase transactions can't be relayed"),
+            MempoolError::IdMismatch { id, expected } => {
+                write!(f, "Transaction ID {} doesn't match its hash {}", id, expected)
+            }
+            MempoolError::AlreadyKnown { txid } => write!(f, "Transaction {} is already in the mempool", txid),
+            MempoolError::DuplicateInput { txid, vout } => write!(f, "Input {}:{} is spent twice", txid, vout),
+            MempoolError::Invalid(e) => write!(f, "{}", e),
+            MempoolError::Conflict { txid, vout, spent_by } => {
+                write!(f, "Input {}:{} is already spent by pending transaction {}", txid, vout, spent_by)
+            }
+            MempoolError::ReplacementFeeTooLow { fee, required } => write!(
+                f,
+                "Replacement pays a fee of {} but must pay at least {} to replace the pending transactions it conflicts with",
+                fee, required
+            ),
+            MempoolError::ReplacementReducesOutput { txid, vout } => {
+                write!(f, "Replacement pays less than output {}:{} of the transaction it replaces", txid, vout)
+            }
+            MempoolError::Dust { vout, value, threshold } => {
+                write!(f, "Output {} is worth {}, below the dust threshold of {}", vout, value, threshold)
+            }
+        }
+    }
+}
+
+/// Whether a transaction offered to the pool can be mined yet.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Admission {
+    /// All of its inputs are known, so it joined the pool.
+    Accepted,
+    /// It joined the pool in place of the pooled transactions with these
+    /// IDs, which it conflicted with, and their descendants.
+    Replaced(Vec<String>),
+    /// Some of its parents haven't been seen, so it is waiting in the orphan pool.
+    Orphaned,
+}
+
+/// Why `MempoolPool::build_fee_bump` couldn't rebuild a transaction.
+#[derive(Debug, Clone, PartialEq)]
+pub enum FeeBumpError {
+    /// There is no pooled transaction with this ID.
+    NotPending { txid: String },
+    /// An input spends an output none of the wallets owns.
+    NotOwned { txid: String, vout: usize },
+    /// The new fee is no more than the transaction already pays.
+    FeeNotHigher { fee: u64, new_fee: u64 },
+    InsufficientFunds { available: u64, required: u64 },
+}
+
+impl fmt::Display for FeeBumpError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FeeBumpError::NotPending { txid } => write!(f, "Transaction {} is not in the mempool", txid),
+            FeeBumpError::NotOwned { txid, vout } => {
+                write!(f, "Input {}:{} is not owned by any of this node's wallets", txid, vout)
+            }
+            FeeBumpError::FeeNotHigher { fee, new_fee } => {
+                write!(f, "The transaction already pays a fee of {}, so a new fee of {} is no bump", fee, new_fee)
+            }
+            FeeBumpError::InsufficientFunds { available, required } => {
+                write!(f, "Insufficient funds to pay the new fee: {} available, {} required", available, required)
+            }
+        }
+    }
+}
+
+/// A pooled transaction related to another through the outputs they spend.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Relative {
+    pub txid: String,
+    /// `None` if any of its inputs are unknown.
+    pub fee: Option<u64>,
+    pub size: usize,
+}
+
+/// The pooled transactions a transaction depends on and that depend on it,
+/// as returned by `/mempool/{txid}/relatives`.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Relatives {
+    /// Pooled transactions it spends outputs of, directly or transitively,
+    /// parents before children.
+    pub ancestors: Vec<Relative>,
+    /// Pooled transactions spending its outputs, directly or transitively,
+    /// in the order they were received.
+    pub descendants: Vec<Relative>,
+}
+
+/// Transactions waiting to be mined, in the order they were received.
+#[derive(Debug, Clone)]
+pub struct MempoolPool {
+    transactions: Vec<Transaction>,
+    orphans: OrphanPool,
+    max_future_drift_secs: i64,
+    min_relay_fee_per_byte: u64,
+    min_replacement_fee_increment: u64,
+    clock: SharedClock,
+    /// Whether `select_for_block` breaks fee rate ties by txid rather than
+    /// by arrival order, so a block's contents don't depend on timing.
+    deterministic: bool,
+}
+
+impl Default for MempoolPool {
+    fn default() -> Self {
+        Self::with_max_future_drift(DEFAULT_MAX_FUTURE_DRIFT_SECS)
+    }
+}
+
+impl MempoolPool {
+    /// Creates a pool that rejects transactions timestamped more than
+    /// `max_future_drift_secs` ahead of local time.
+    pub fn with_max_future_drift(max_future_drift_secs: i64) -> Self {
+        Self {
+            transactions: Vec::new(),
+            orphans: OrphanPool::default(),
+            max_future_drift_secs,
+            min_relay_fee_per_byte: DEFAULT_MIN_RELAY_FEE_PER_BYTE,
+            min_replacement_fee_increment: DEFAULT_MIN_REPLACEMENT_FEE_INCREMENT,
+            clock: system_clock(),
+            deterministic: false,
+        }
+    }
+
+    /// Sets the clock timestamps are checked against and orphans age by.
+    pub fn with_clock(mut self, clock: SharedClock) -> Self {
+        self.orphans = self.orphans.with_clock(clock.clone());
+        self.clock = clock;
+        self
+    }
+
+    /// Orders transactions for a block by fee rate, highest first, then by
+    /// txid, instead of by fee rate and then arrival.
+    pub fn with_deterministic_order(mut self, deterministic: bool) -> Self {
+        self.deterministic = deterministic;
+        self
+    }
+
+    /// Sets the fee per serialized byte a transaction must pay to be
+    /// accepted by `submit` and `accept`.
+    pub fn with_min_relay_fee_per_byte(mut self, min_relay_fee_per_byte: u64) -> Self {
+        self.min_relay_fee_per_byte = min_relay_fee_per_byte;
+        self
+    }
+
+    /// Sets how much more in fees than the transactions it replaces a
+    /// replacement must pay.
+    pub fn with_min_replacement_fee_increment(mut self, min_replacement_fee_increment: u64) -> Self {
+        self.min_replacement_fee_increment = min_replacement_fee_increment;
+        self
+    }
+
+    /// Returns the smallest fee `tx` must pay to be relayed.
+    pub fn required_fee(&self, tx: &Transaction) -> u64 {
+        (tx.size() as u64).saturating_mul(self.min_relay_fee_per_byte)
+    }
+
+    /// Checks that `tx` pays at least the minimum relay fee. The floor is
+    /// waived while the pool is empty, so a quiet network still confirms
+    /// free transactions; it only starts to bite once there's competition.
+    fn check_relay_fee(&self, tx: &Transaction, blockchain: &Blockchain) -> Result<(), MempoolError> {
+        if self.transactions.is_empty() || tx.is_coinbase() {
+            return Ok(());
+        }
+        let required = self.required_fee(tx);
+        let fee = self.fee(tx, blockchain).unwrap_or(0);
+        if fee < required {
+            return Err(MempoolError::FeeTooLow { fee, required });
+        }
+        Ok(())
+    }
+
+    /// Adds a transaction to the pool, unless its timestamp is too far in
+    /// the future. Clocks are never perfectly in sync, so some drift is
+    /// tolerated, but a far-future timestamp would otherwise end up in a block.
+    pub fn add(&mut self, tx: Transaction) -> Result<(), MempoolError> {
+        self.check_timestamp(&tx)?;
+        self.transactions.push(tx);
+        Ok(())
+    }
+
+    fn check_timestamp(&self, tx: &Transaction) -> Result<(), MempoolError> {
+        let now = self.clock.now();
+        if tx.timestamp > now.saturating_add(self.max_future_drift_secs) {
+            return Err(MempoolError::TimestampTooFarInFuture { timestamp: tx.timestamp, now });
+        }
+        Ok(())
+    }
+
+    /// Returns the IDs of the pooled transactions `tx` conflicts with, after
+    /// checking that it may replace them: it must spend every input they
+    /// spend, pay each of their recipients at least as much, change aside,
+    /// and pay `min_replacement_fee_increment` more in fees than they and
+    /// their descendants together.
+    fn replaceable(&self, tx: &Transaction, blockchain: &Blockchain) -> Result<Vec<String>, MempoolError> {
+        let mut conflicts: Vec<&Transaction> = Vec::new();
+        for input in &tx.inputs {
+            if let Some(spender) = self.spender(input).filter(|spender| !conflicts.iter().any(|c| c.id == spender.id)) {
+                conflicts.push(spender);
+            }
+        }
+        if conflicts.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let spends = |input: &TxInput| tx.inputs.iter().any(|own| own.txid == input.txid && own.vout == input.vout);
+        let paid_to = |outputs: &[TxOutput], address: &str| {
+            outputs
+                .iter()
+                .filter(|output| output.script_pub_key.address() == address)
+                .fold(0u64, |total, output| total.saturating_add(output.value))
+        };
+        let mut evicted = HashSet::new();
+        for conflict in &conflicts {
+            if conflict.inputs.iter().any(|input| !spends(input)) {
+                let shared = tx.inputs.iter().find(|input| conflict.inputs.iter().any(|spent| spent.txid == input.txid && spent.vout == input.vout));
+                let shared = shared.expect("a conflict shares an input");
+                return Err(MempoolError::Conflict { txid: shared.txid.clone(), vout: shared.vout, spent_by: conflict.id.clone() });
+            }
+            // Outputs paying whoever owns an input are change, which is what
+            // a replacement takes its extra fee from.
+            let owners: HashSet<&str> = conflict
+                .inputs
+                .iter()
+                .filter_map(|input| self.spent_output(input, blockchain))
+                .map(|output| output.script_pub_key.address())
+                .collect();
+            for (vout, output) in conflict.outputs.iter().enumerate() {
+                let address = output.script_pub_key.address();
+                if !owners.contains(address) && paid_to(&tx.outputs, address) < paid_to(&conflict.outputs, address) {
+                    return Err(MempoolError::ReplacementReducesOutput { txid: conflict.id.clone(), vout });
+                }
+            }
+            evicted.insert(conflict.id.clone());
+            evicted.extend(self.descendants(&conflict.id));
+        }
+
+        let replaced_fees = evicted
+            .iter()
+            .filter_map(|txid| self.get(txid))
+            .fold(0u64, |total, replaced| total.saturating_add(self.fee(replaced, blockchain).unwrap_or(0)));
+        let required = replaced_fees.saturating_add(self.min_replacement_fee_increment);
+        let fee = self.fee(tx, blockchain).unwrap_or(0);
+        if fee < required {
+            return Err(MempoolError::ReplacementFeeTooLow { fee, required });
+        }
+        Ok(conflicts.into_iter().map(|conflict| conflict.id.clone()).collect())
+    }
+
+    /// The smallest output value the pool relays: whatever it costs to spend
+    /// an output at the minimum relay fee, and never less than 1.
+    pub fn dust_threshold(&self) -> u64 {
+        SIGNED_INPUT_SIZE.saturating_mul(self.min_relay_fee_per_byte).max(1)
+    }
+
+    /// Runs every check on a transaction from outside the node before adding
+    /// it to the pool: its ID, signatures, the existence of the outputs it
+    /// spends, its amounts and dust, then everything `submit` checks. Returns
+    /// the fee it pays and the transactions it replaced.
+    ///
+    /// Unlike `accept`, a transaction with unknown inputs is rejected rather
+    /// than held as an orphan, so the caller learns what is wrong with it.
+    pub fn submit_checked(&mut self, tx: Transaction, blockchain: &Blockchain) -> Result<(u64, Vec<Transaction>), MempoolError> {
+        if tx.is_coinbase() {
+            return Err(MempoolError::Coinbase);
+        }
+        let expected = tx.calculate_hash();
+        if tx.id != expected {
+            return Err(MempoolError::IdMismatch { id: tx.id, expected });
+        }
+        if self.contains(&tx.id) || self.contains_orphan(&tx.id) {
+            return Err(MempoolError::AlreadyKnown { txid: tx.id });
+        }
+        let mut seen = HashSet::new();
+        if let Some(input) = tx.inputs.iter().find(|input| !seen.insert((&input.txid, input.vout))) {
+            return Err(MempoolError::DuplicateInput { txid: input.txid.clone(), vout: input.vout });
+        }
+        self.verify(&tx, blockchain).map_err(MempoolError::Invalid)?;
+        // Every input is known now that the signatures checked out.
+        let inputs_total = tx.inputs.iter().fold(0u64, |total, input| total.saturating_add(self.input_value(input, blockchain).unwrap_or(0)));
+        let outputs_total = tx.outputs.iter().fold(0u64, |total, output| total.saturating_add(output.value));
+        if outputs_total > inputs_total {
+            return Err(MempoolError::Invalid(TxError::OutputsExceedInputs { inputs_total, outputs_total }));
+        }
+        let threshold = self.dust_threshold();
+        if let Some((vout, output)) = tx.outputs.iter().enumerate().find(|(_, output)| output.value < threshold) {
+            return Err(MempoolError::Dust { vout, value: output.value, threshold });
+        }
+        let replaced = self.submit(tx, blockchain)?;
+        Ok((inputs_total - outputs_total, replaced))
+    }
+
+    /// Adds a transaction whose inputs are all known to the pool, provided it
+    /// is within the chain's input and output limits and pays the minimum
+    /// relay fee.
+    ///
+    /// A transaction spending outputs pooled transactions already spend
+    /// replaces them, and their descendants, if it follows the rules of
+    /// `replaceable`. Returns the transactions it replaced, parents first.
+    pub fn submit(&mut self, tx: Transaction, blockchain: &Blockchain) -> Result<Vec<Transaction>, MempoolError> {
+        blockchain.params.check_transaction(&tx).map_err(MempoolError::Invalid)?;
+        let height = blockchain.chain.len() as u64;
+        if let Some(expiry_height) = tx.expiry_height.filter(|_| tx.is_expired_at(height)) {
+            return Err(MempoolError::Expired { expiry_height, height });
+        }
+        let conflicts = self.replaceable(&tx, blockchain)?;
+        self.check_relay_fee(&tx, blockchain)?;
+        self.check_timestamp(&tx)?;
+        let replaced = conflicts.iter().flat_map(|txid| self.remove(txid)).collect();
+        self.transactions.push(tx);
+        Ok(replaced)
+    }
+
+    /// Offers a transaction received from a peer to the pool.
+    ///
+    /// Transactions spending outputs that are neither on chain nor in the
+    /// pool are held as orphans rather than rejected, since their parents are
+    /// often just a hop behind. Accepting a transaction promotes any orphans
+    /// that were waiting on it. Like `submit`, it enforces the minimum relay fee.
+    pub fn accept(&mut self, tx: Transaction, blockchain: &Blockchain) -> Result<Admission, MempoolError> {
+        let missing = self.missing_inputs(&tx, blockchain);
+        if !missing.is_empty() {
+            self.orphans.insert(tx, missing);
+            return Ok(Admission::Orphaned);
+        }
+        let replaced = self.submit(tx.clone(), blockchain)?;
+        self.promote_orphans(vec![tx], blockchain);
+        if replaced.is_empty() {
+            Ok(Admission::Accepted)
+        } else {
+            Ok(Admission::Replaced(replaced.into_iter().map(|tx| tx.id).collect()))
+        }
+    }
+
+    /// Drops the transactions confirmed in `block` and those that can no
+    /// longer be mined after it, and promotes the orphans whose parents it
+    /// contains.
+    pub fn block_connected(&mut self, block: &Block, blockchain: &Blockchain) {
+        self.remove_confirmed(&block.transactions);
+        self.promote_orphans(block.transactions.clone(), blockchain);
+        self.evict_expired(block.index + 1);
+    }
+
+    /// Drops `transactions` from the pool, e.g. once they are in a block.
+    pub fn remove_confirmed(&mut self, transactions: &[Transaction]) {
+        let confirmed: HashSet<&str> = transactions.iter().map(|tx| tx.id.as_str()).collect();
+        self.transactions.retain(|tx| !confirmed.contains(tx.id.as_str()));
+    }
+
+    /// Evicts the transactions that can't be mined in a block at `height`,
+    /// along with their descendants. Returns the evicted transactions.
+    pub fn evict_expired(&mut self, height: u64) -> Vec<Transaction> {
+        let expired: Vec<String> = self
+            .transactions
+            .iter()
+            .filter(|tx| tx.is_expired_at(height))
+            .map(|tx| tx.id.clone())
+            .collect();
+        let mut evicted = Vec::new();
+        for txid in expired {
+            evicted.extend(self.remove(&txid));
+        }
+        if !evicted.is_empty() {
+            tracing::info!("Evicted {} expired transaction(s) at height {}", evicted.len(), height);
+        }
+        evicted
+    }
+
+    /// Updates the pool after the active chain was reorganised: transactions
+    /// from disconnected blocks are returned to it, unless the connected
+    /// blocks confirm them again. Returns the IDs of the returned transactions.
+    pub fn apply_reorg(&mut self, reorg: &Reorg, blockchain: &Blockchain) -> Vec<String> {
+        for block in &reorg.connected {
+            self.block_connected(block, blockchain);
+        }
+        let reconfirmed: HashSet<&str> = reorg
+            .connected
+            .iter()
+            .flat_map(|block| &block.transactions)
+            .map(|tx| tx.id.as_str())
+            .collect();
+
+        let mut returned = Vec::new();
+        for tx in reorg.disconnected.iter().flat_map(|block| &block.transactions) {
+            if tx.is_coinbase() || reconfirmed.contains(tx.id.as_str()) || self.contains(&tx.id) {
+                continue;
+            }
+            match self.accept(tx.clone(), blockchain) {
+                Ok(Admission::Accepted | Admission::Replaced(_)) => returned.push(tx.id.clone()),
+                // Its parent was a disconnected coinbase, or otherwise lost.
+                Ok(Admission::Orphaned) => {}
+                Err(e) => tracing::warn!("Dropping disconnected transaction {}: {}", tx.id, e),
+            }
+        }
+        returned
+    }
+
+    /// Returns the transactions taken for a block that wasn't mined, because
+    /// mining ran out of its budget, to the pool. The coinbase is skipped.
+    pub fn return_unmined(&mut self, transactions: Vec<Transaction>, blockchain: &Blockchain) {
+        for tx in transactions.into_iter().filter(|tx| !tx.is_coinbase()) {
+            let txid = tx.id.clone();
+            if let Err(e) = self.accept(tx, blockchain) {
+                tracing::warn!("Dropping unmined transaction {}: {}", txid, e);
+            }
+        }
+    }
+
+    /// Moves orphans waiting on `parents` into the pool, then does the same
+    /// for the orphans waiting on those, and so on.
+    fn promote_orphans(&mut self, mut parents: Vec<Transaction>, blockchain: &Blockchain) {
+        while let Some(parent) = parents.pop() {
+            for child in self.orphans.take_children(&parent) {
+                let missing = self.missing_inputs(&child, blockchain);
+                if !missing.is_empty() {
+                    // Still waiting on another parent.
+                    self.orphans.insert(child, missing);
+                } else if let Err(e) = self.verify(&child, blockchain) {
+                    // Orphans are admitted before the outputs they spend are known.
+                    tracing::warn!("Dropping orphan {}: {}", child.id, e);
+                } else if let Err(e) = self.submit(child.clone(), blockchain) {
+                    tracing::warn!("Dropping orphan {}: {}", child.id, e);
+                } else {
+                    parents.push(child);
+                }
+            }
+        }
+    }
+
+    /// Returns the outpoints spent by `tx` that are neither unspent on chain
+    /// nor created by a pooled transaction.
+    fn missing_inputs(&self, tx: &Transaction, blockchain: &Blockchain) -> Vec<OutPoint> {
+        if tx.is_coinbase() {
+            return Vec::new();
+        }
+        tx.inputs
+            .iter()
+            .filter(|input| self.input_value(input, blockchain).is_none())
+            .map(|input| (input.txid.clone(), input.vout))
+            .collect()
+    }
+
+    /// Returns true if a transaction with the given ID is waiting for its parents.
+    pub fn contains_orphan(&self, txid: &str) -> bool {
+        self.orphans.contains(txid)
+    }
+
+    /// Returns the number of transactions in the pool, excluding orphans.
+    pub fn len(&self) -> usize {
+        self.transactions.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.transactions.is_empty()
+    }
+
+    /// Returns the number of orphans waiting for their parents.
+    pub fn orphan_count(&self) -> usize {
+        self.orphans.len()
+    }
+
+    /// Returns true if a transaction with the given ID is in the pool.
+    pub fn contains(&self, txid: &str) -> bool {
+        self.transactions.iter().any(|tx| tx.id == txid)
+    }
+
+    /// Returns the IDs of the pooled transactions, in the order they were received.
+    pub fn txids(&self) -> impl Iterator<Item = &str> {
+        self.transactions.iter().map(|tx| tx.id.as_str())
+    }
+
+    /// Returns the pooled transaction with the given ID.
+    pub fn get(&self, txid: &str) -> Option<&Transaction> {
+        self.transactions.iter().find(|tx| tx.id == txid)
+    }
+
+    /// Returns true if a pooled transaction already spends `outpoint`.
+    pub fn is_spent(&self, outpoint: &OutPoint) -> bool {
+        self.transactions
+            .iter()
+            .flat_map(|tx| &tx.inputs)
+            .any(|input| input.txid == outpoint.0 && input.vout == outpoint.1)
+    }
+
+    /// Returns the pooled transaction spending the output `input` spends.
+    fn spender(&self, input: &TxInput) -> Option<&Transaction> {
+        self.transactions
+            .iter()
+            .find(|tx| tx.inputs.iter().any(|spent| spent.txid == input.txid && spent.vout == input.vout))
+    }
+
+    /// Evicts a transaction from the pool along with the pooled transactions
+    /// spending its outputs, which could never be mined without it. Returns
+    /// the evicted transactions, parents first, or nothing if `txid` isn't
+    /// in the pool.
+    pub fn remove(&mut self, txid: &str) -> Vec<Transaction> {
+        let mut evicted = HashSet::new();
+        let mut pending = vec![txid.to_string()];
+        while let Some(id) = pending.pop() {
+            if !self.contains(&id) || !evicted.insert(id.clone()) {
+                continue;
+            }
+            pending.extend(
+                self.transactions
+                    .iter()
+                    .filter(|tx| tx.inputs.iter().any(|input| input.txid == id))
+                    .map(|tx| tx.id.clone()),
+            );
+        }
+        let (removed, kept) = self.transactions.drain(..).partition(|tx| evicted.contains(&tx.id));
+        self.transactions = kept;
+        removed
+    }
+
+    /// Returns the value of the output an input spends, looking first at the
+    /// chain's UTXO set and then at unconfirmed outputs in the pool.
+    fn input_value(&self, input: &TxInput, blockchain: &Blockchain) -> Option<u64> {
+        self.spent_output(input, blockchain).map(|output| output.value)
+    }
+
+    /// Returns the output `input` spends, unspent on chain or created by a
+    /// pooled transaction.
+    pub fn spent_output<'a>(&'a self, input: &TxInput, blockchain: &'a Blockchain) -> Option<&'a TxOutput> {
+        if let Some(output) = blockchain.utxo_index.get(&(input.txid.clone(), input.vout)) {
+            return Some(output);
+        }
+        self.get(&input.txid).and_then(|parent| parent.outputs.get(input.vout))
+    }
+
+    /// Checks that every input of `tx` is signed by the owner of the output
+    /// it spends, on chain or in the pool.
+    pub fn verify(&self, tx: &Transaction, blockchain: &Blockchain) -> Result<(), TxError> {
+        tx.verify(|input| self.spent_output(input, blockchain).map(|output| &output.script_pub_key))
+    }
+
+    /// Returns the fee paid by `tx`, or `None` if any of its inputs are unknown.
+    pub fn fee(&self, tx: &Transaction, blockchain: &Blockchain) -> Option<u64> {
+        if tx.is_coinbase() {
+            return Some(0);
+        }
+        let mut input_total = 0u64;
+        for input in &tx.inputs {
+            input_total += self.input_value(input, blockchain)?;
+        }
+        let output_total: u64 = tx.outputs.iter().map(|output| output.value).sum();
+        Some(input_total.saturating_sub(output_total))
+    }
+
+    /// Rebuilds the pooled transaction `txid` to pay `new_fee`, for `submit`
+    /// to replace it with. Its recipients are paid as before and the extra
+    /// fee comes out of its change, or out of more of the owners' unspent
+    /// outputs if the change falls short. Whatever is left goes back to the
+    /// owner of its first input, unless it would be dust, in which case it
+    /// goes to the fee too. Every input must be owned by one of `wallets`,
+    /// which sign the replacement.
+    pub fn build_fee_bump(
+        &self,
+        txid: &str,
+        new_fee: u64,
+        wallets: &[Wallet],
+        blockchain: &Blockchain,
+    ) -> Result<Transaction, FeeBumpError> {
+        let original = self.get(txid).ok_or_else(|| FeeBumpError::NotPending { txid: txid.to_string() })?;
+        let mut signers: Vec<&Wallet> = Vec::new();
+        let mut accumulated = 0u64;
+        for input in &original.inputs {
+            let not_owned = || FeeBumpError::NotOwned { txid: input.txid.clone(), vout: input.vout };
+            let spent = self.spent_output(input, blockchain).ok_or_else(not_owned)?;
+            let owner = wallets.iter().find(|wallet| spent.script_pub_key.pays_to(&wallet.get_address())).ok_or_else(not_owned)?;
+            signers.push(owner);
+            accumulated = accumulated.saturating_add(spent.value);
+        }
+        let fee = self.fee(original, blockchain).unwrap_or(0);
+        if new_fee <= fee {
+            return Err(FeeBumpError::FeeNotHigher { fee, new_fee });
+        }
+
+        let owners: Vec<String> = signers.iter().map(|wallet| wallet.get_address()).collect();
+        let payments: Vec<TxOutput> = original
+            .outputs
+            .iter()
+            .filter(|output| !owners.iter().any(|owner| output.script_pub_key.pays_to(owner)))
+            .cloned()
+            .collect();
+        let required = payments
+            .iter()
+            .fold(new_fee, |total, output| total.saturating_add(output.value));
+        let mut inputs: Vec<TxInput> = original
+            .inputs
+            .iter()
+            .map(|input| TxInput { script_sig: UnlockingScript::default(), ..input.clone() })
+            .collect();
+        // Outputs the original spends show as spent by it, so they aren't
+        // picked twice.
+        let mut searched = HashSet::new();
+        for (owner, address) in signers.clone().into_iter().zip(&owners) {
+            if accumulated >= required {
+                break;
+            }
+            if !searched.insert(address) {
+                continue;
+            }
+            for (utxo_txid, vout, utxo) in blockchain.get_utxos(address) {
+                if accumulated >= required {
+                    break;
+                }
+                if self.is_spent(&(utxo_txid.clone(), vout)) {
+                    continue;
+                }
+                inputs.push(TxInput { txid: utxo_txid, vout, script_sig: UnlockingScript::default(), sequence: 0 });
+                signers.push(owner);
+                accumulated = accumulated.saturating_add(utxo.value);
+            }
+        }
+        if accumulated < required {
+            return Err(FeeBumpError::InsufficientFunds { available: accumulated, required });
+        }
+
+        let mut outputs = payments;
+        let change = accumulated - required;
+        if change >= self.dust_threshold() {
+            outputs.push(TxOutput { value: change, script_pub_key: owners[0].clone().into() });
+        }
+        let mut replacement = Transaction::new(inputs, outputs).with_expiry_height(original.expiry_height);
+        replacement.sign_inputs(&signers);
+        Ok(replacement)
+    }
+
+    /// Returns the IDs of the pooled transactions `txid` depends on, directly
+    /// or transitively, parents before children.
+    fn ancestors(&self, txid: &str) -> Vec<String> {
+        let mut ancestors = Vec::new();
+        let mut seen = HashSet::new();
+        self.collect_ancestors(txid, &mut seen, &mut ancestors);
+        ancestors
+    }
+
+    fn collect_ancestors(&self, txid: &str, seen: &mut HashSet<String>, ancestors: &mut Vec<String>) {
+        let Some(tx) = self.get(txid) else { return };
+        for input in &tx.inputs {
+            if self.contains(&input.txid) && seen.insert(input.txid.clone()) {
+                self.collect_ancestors(&input.txid, seen, ancestors);
+                ancestors.push(input.txid.clone());
+            }
+        }
+    }
+
+    /// Returns the IDs of the pooled transactions depending on `txid`,
+    /// directly or transitively, in the order they were received.
+    fn descendants(&self, txid: &str) -> Vec<String> {
+        let mut children: HashMap<&str, Vec<&str>> = HashMap::new();
+        for tx in &self.transactions {
+            for input in &tx.inputs {
+                children.entry(input.txid.as_str()).or_default().push(tx.id.as_str());
+            }
+        }
+        let mut seen = HashSet::new();
+        let mut pending = vec![txid];
+        while let Some(id) = pending.pop() {
+            for child in children.get(id).into_iter().flatten() {
+                if seen.insert(*child) {
+                    pending.push(*child);
+                }
+            }
+        }
+        self.txids().filter(|id| *id != txid && seen.contains(id)).map(str::to_string).collect()
+    }
+
+    /// Returns the pooled transactions `txid` depends on and those depending
+    /// on it, or `None` if it isn't in the pool.
+    pub fn relatives(&self, txid: &str, blockchain: &Blockchain) -> Option<Relatives> {
+        self.get(txid)?;
+        let relative = |id: String| {
+            let tx = self.get(&id)?;
+            Some(Relative { fee: self.fee(tx, blockchain), size: tx.size(), txid: id })
+        };
+        Some(Relatives {
+            ancestors: self.ancestors(txid).into_iter().filter_map(relative).collect(),
+            descendants: self.descendants(txid).into_iter().filter_map(relative).collect(),
+        })
+    }
+
+    /// Computes the fee rate, in fee per byte, of `txid` together with all of
+    /// its unconfirmed ancestors. This is the rate a miner earns by including
+    /// the whole package, which is what lets a child pay for its parent.
+    pub fn compute_package_fee_rate(&self, txid: &str, blockchain: &Blockchain) -> f64 {
+        let mut fees = 0u64;
+        let mut size = 0usize;
+        for id in self.ancestors(txid).iter().map(String::as_str).chain([txid]) {
+            if let Some(tx) = self.get(id) {
+                fees += self.fee(tx, blockchain).unwrap_or(0);
+                size += tx.size();
+            }
+        }
+        if size == 0 {
+            return 0.0;
+        }
+        fees as f64 / size as f64
+    }
+
+    /// Chooses the pooled transactions for the next block, at most
+    /// `max_size` serialized bytes of them, in the order they must appear.
+    ///
+    /// Transactions are considered by package fee rate, highest first, ties
+    /// going to the earliest received, or in deterministic mode to the
+    /// lowest txid. A
+    /// transaction is only taken together with the unconfirmed ancestors it
+    /// spends from, which are placed before it, so a block never spends an
+    /// output before the transaction creating it. A package that doesn't fit
+    /// is skipped whole; the packages of its descendants contain it, so
+    /// they are skipped too.
+    pub fn select_for_block(&self, blockchain: &Blockchain, max_size: usize) -> Vec<Transaction> {
+        let mut by_rate: Vec<(f64, usize)> = self
+            .transactions
+            .iter()
+            .enumerate()
+            .map(|(position, tx)| (self.compute_package_fee_rate(&tx.id, blockchain), position))
+            .collect();
+        by_rate.sort_by(|a, b| {
+            let tie = if self.deterministic {
+                self.transactions[a.1].id.cmp(&self.transactions[b.1].id)
+            } else {
+                a.1.cmp(&b.1)
+            };
+            b.0.total_cmp(&a.0).then(tie)
+        });
+
+        let mut included = HashSet::new();
+        let mut ordered = Vec::new();
+        let mut size = 0;
+        for (_, position) in by_rate {
+            let txid = &self.transactions[position].id;
+            if included.contains(txid) {
+                continue;
+            }
+            let package: Vec<&Transaction> = self
+                .ancestors(txid)
+                .iter()
+                .chain([txid])
+                .filter(|id| !included.contains(*id))
+                .map(|id| self.get(id).unwrap())
+                .collect();
+            let package_size: usize = package.iter().map(|tx| tx.size()).sum();
+            if size + package_size > max_size {
+                continue;
+            }
+            size += package_size;
+            for tx in package {
+                included.insert(tx.id.clone());
+                ordered.push(tx.clone());
+            }
+        }
+        ordered
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::script::UnlockingScript;
+    use crate::core::wallet::Wallet;
+    use crate::fractal::FractalType;
+
+    fn spend(txid: &str, vout: usize, value: u64) -> Transaction {
+        Transaction::new(
+            vec![TxInput {
+                txid: txid.to_string(),
+                vout,
+                script_sig: UnlockingScript::default(),
+                sequence: 0,
+            }],
+            vec![TxOutput { value, script_pub_key: "someone".into() }],
+        )
+    }
+
+    /// Like `spend`, but paying `owner` back and signed by it.
+    fn signed_spend(owner: &Wallet, txid: &str, vout: usize, value: u64) -> Transaction {
+        let mut tx = spend(txid, vout, value);
+        tx.outputs[0].script_pub_key = owner.get_address().into();
+        tx.id = tx.calculate_hash();
+        tx.sign(owner);
+        tx
+    }
+
+    #[test]
+    fn test_child_pays_for_parent() {
+        let mut blockchain = Blockchain::new(1);
+        let funding = Transaction::new(
+            vec![],
+            vec![
+                TxOutput { value: 10_000, script_pub_key: "someone".into() },
+                TxOutput { value: 10_000, script_pub_key: "someone".into() },
+            ],
+        );
+        blockchain.add_block(FractalType::Sierpinski { depth: 1, seed: 0 }, vec![funding.clone()]).unwrap();
+
+        let parent = spend(&funding.id, 0, 10_000);
+        let unrelated = spend(&funding.id, 1, 9_900);
+        let child = spend(&parent.id, 0, 5_000);
+
+        let mut pool = MempoolPool::default();
+        pool.add(parent.clone()).unwrap();
+        pool.add(unrelated.clone()).unwrap();
+        pool.add(child.clone()).unwrap();
+
+        assert_eq!(pool.fee(&parent, &blockchain), Some(0));
+        assert_eq!(pool.fee(&child, &blockchain), Some(5_000));
+        assert!(
+            pool.compute_package_fee_rate(&child.id, &blockchain)
+                > pool.compute_package_fee_rate(&unrelated.id, &blockchain)
+        );
+
+        let ordered: Vec<String> = pool.select_for_block(&blockchain, MAX_BLOCK_TRANSACTIONS_SIZE).into_iter().map(|tx| tx.id).collect();
+        assert_eq!(ordered, vec![parent.id, child.id, unrelated.id]);
+    }
+
+    #[test]
+    fn test_package_that_does_not_fit_is_skipped_whole() {
+        let blockchain = Blockchain::new(1);
+        let funding = Transaction::new(
+            vec![],
+            vec![
+                TxOutput { value: 10_000, script_pub_key: "someone".into() },
+                TxOutput { value: 10_000, script_pub_key: "someone".into() },
+            ],
+        );
+        let parent = spend(&funding.id, 0, 10_000);
+        let child = spend(&parent.id, 0, 9_000);
+        let unrelated = spend(&funding.id, 1, 10_000);
+        let mut pool = MempoolPool::default();
+        for tx in [&parent, &child, &unrelated] {
+            pool.add(tx.clone()).unwrap();
+        }
+
+        // The child's package pays best but needs room for its parent too.
+        // Without it, the parent is taken alone and the child waits.
+        let room = parent.size().max(unrelated.size());
+        let selected: Vec<String> = pool.select_for_block(&blockchain, room).into_iter().map(|tx| tx.id).collect();
+        assert_eq!(selected, vec![parent.id.clone()]);
+
+        let room = parent.size() + child.size();
+        let selected: Vec<String> = pool.select_for_block(&blockchain, room).into_iter().map(|tx| tx.id).collect();
+        assert_eq!(selected, vec![parent.id, child.id]);
+    }
+
+    #[test]
+    fn test_relatives_span_the_whole_chain_of_pending_transactions() {
+        let funding = Transaction::new(
+            vec![],
+            vec![
+                TxOutput { value: 10_000, script_pub_key: "someone".into() },
+                TxOutput { value: 10_000, script_pub_key: "someone".into() },
+            ],
+        );
+        let parent = spend(&funding.id, 0, 10_000);
+        let unrelated = spend(&funding.id, 1, 10_000);
+        let child = spend(&parent.id, 0, 9_000);
+        let grandchild = spend(&child.id, 0, 8_000);
+        let mut pool = MempoolPool::default();
+        for tx in [&parent, &unrelated, &child, &grandchild] {
+            pool.add(tx.clone()).unwrap();
+        }
+        let blockchain = Blockchain::new(1);
+        let ids = |relatives: &[Relative]| relatives.iter().map(|relative| relative.txid.clone()).collect::<Vec<_>>();
+
+        let relatives = pool.relatives(&child.id, &blockchain).unwrap();
+        assert_eq!(ids(&relatives.ancestors), [parent.id.as_str()]);
+        assert_eq!(ids(&relatives.descendants), [grandchild.id.as_str()]);
+        assert_eq!(relatives.descendants[0].fee, Some(1_000));
+
+        let relatives = pool.relatives(&parent.id, &blockchain).unwrap();
+        assert!(relatives.ancestors.is_empty());
+        assert_eq!(ids(&relatives.descendants), [child.id.clone(), grandchild.id.clone()]);
+        assert_eq!(pool.relatives(&grandchild.id, &blockchain).unwrap().ancestors.len(), 2);
+        assert!(pool.relatives(&funding.id, &blockchain).is_none());
+    }
+
+    #[test]
+    fn test_remove_evicts_descendants() {
+        let funding = Transaction::new(
+            vec![],
+            vec![
+                TxOutput { value: 10_000, script_pub_key: "someone".into() },
+                TxOutput { value: 10_000, script_pub_key: "someone".into() },
+            ],
+        );
+        let parent = spend(&funding.id, 0, 10_000);
+        let unrelated = spend(&funding.id, 1, 10_000);
+        let child = spend(&parent.id, 0, 9_000);
+        let grandchild = spend(&child.id, 0, 8_000);
+
+        let mut pool = MempoolPool::default();
+        for tx in [&parent, &unrelated, &child, &grandchild] {
+            pool.add(tx.clone()).unwrap();
+        }
+        assert!(pool.is_spent(&(funding.id.clone(), 0)));
+
+        let removed: Vec<String> = pool.remove(&parent.id).into_iter().map(|tx| tx.id).collect();
+        assert_eq!(removed, vec![parent.id.clone(), child.id, grandchild.id]);
+        assert_eq!(pool.len(), 1);
+        assert!(pool.contains(&unrelated.id));
+        assert!(!pool.is_spent(&(funding.id, 0)));
+        assert!(pool.remove(&parent.id).is_empty());
+    }
+
+    #[test]
+    fn test_expired_transactions_are_evicted() {
+        let mut blockchain = Blockchain::new(1);
+        let funding = Transaction::new(
+            vec![],
+            vec![
+                TxOutput { value: 10_000, script_pub_key: "someone".into() },
+                TxOutput { value: 10_000, script_pub_key: "someone".into() },
+            ],
+        );
+        blockchain.add_block(FractalType::Sierpinski { depth: 1, seed: 0 }, vec![funding.clone()]).unwrap();
+        let height = blockchain.chain.len() as u64;
+
+        let expiring = spend(&funding.id, 0, 10_000).with_expiry_height(Some(height + 1));
+        let child = spend(&expiring.id, 0, 10_000);
+        let lasting = spend(&funding.id, 1, 10_000);
+        let mut pool = MempoolPool::default();
+        for tx in [&expiring, &child, &lasting] {
+            pool.submit(tx.clone(), &blockchain).unwrap();
+        }
+
+        // The next block may still include it.
+        assert!(pool.evict_expired(height).is_empty());
+
+        let block = blockchain.add_block(FractalType::Sierpinski { depth: 1, seed: 0 }, vec![]).unwrap();
+        pool.block_connected(&block, &blockchain);
+        assert_eq!(pool.len(), 1);
+        assert!(pool.contains(&lasting.id));
+        assert_eq!(
+            pool.submit(expiring, &blockchain),
+            Err(MempoolError::Expired { expiry_height: height + 1, height: height + 1 })
+        );
+    }
+
+    #[test]
+    fn test_future_timestamped_transaction_is_rejected() {
+        let mut pool = MempoolPool::default();
+
+        let current = spend(&"0".repeat(64), 0, 1);
+        assert_eq!(pool.add(current.clone()), Ok(()));
+
+        let mut future = spend(&"1".repeat(64), 0, 1);
+        future.timestamp = chrono::Utc::now().timestamp() + 3_600;
+        assert!(matches!(pool.add(future.clone()), Err(MempoolError::TimestampTooFarInFuture { .. })));
+
+        assert!(pool.contains(&current.id));
+        assert!(!pool.contains(&future.id));
+    }
+
+    #[test]
+    fn test_relay_fee_floor() {
+        let mut blockchain = Blockchain::new(1);
+        let funding = Transaction::new(
+            vec![],
+            vec![
+                TxOutput { value: 100_000, script_pub_key: "someone".into() },
+                TxOutput { value: 100_000, script_pub_key: "someone".into() },
+                TxOutput { value: 100_000, script_pub_key: "someone".into() },
+            ],
+        );
+        blockchain.add_block(FractalType::Sierpinski { depth: 1, seed: 0 }, vec![funding.clone()]).unwrap();
+
+        let mut pool = MempoolPool::default().with_min_relay_fee_per_byte(10);
+        // The floor is waived while the pool is empty.
+        let first = spend(&funding.id, 0, 100_000);
+        assert_eq!(pool.submit(first, &blockchain), Ok(vec![]));
+
+        let free = spend(&funding.id, 1, 100_000);
+        let required = pool.required_fee(&free);
+        assert_eq!(pool.submit(free, &blockchain), Err(MempoolError::FeeTooLow { fee: 0, required }));
+
+        let paying = spend(&funding.id, 2, 100_000 - required);
+        assert_eq!(pool.submit(paying, &blockchain), Ok(vec![]));
+        assert_eq!(pool.len(), 2);
+
+        let mut unfloored = MempoolPool::default();
+        unfloored.submit(spend(&funding.id, 0, 100_000), &blockchain).unwrap();
+        assert_eq!(unfloored.submit(spend(&funding.id, 1, 100_000), &blockchain), Ok(vec![]));
+    }
+
+    #[test]
+    fn test_child_gossiped_before_parent_is_mined_with_it() {
+        use crate::network::p2p::{forward_gossip_message, InboundMessage, P2pMessage, P2pMetrics, DEFAULT_MAX_MESSAGE_SIZE};
+        use libp2p::PeerId;
+        use tokio::sync::mpsc;
+
+        let mut blockchain = Blockchain::new(1);
+        let owner = Wallet::new();
+        let funding = Transaction::new(vec![], vec![TxOutput { value: 10_000, script_pub_key: owner.get_address().into() }]);
+        blockchain.add_block(FractalType::Sierpinski { depth: 1, seed: 0 }, vec![funding.clone()]).unwrap();
+        let parent = signed_spend(&owner, &funding.id, 0, 9_000);
+        let child = signed_spend(&owner, &parent.id, 0, 8_000);
+        let grandchild = signed_spend(&owner, &child.id, 0, 7_000);
+
+        let (sender, mut receiver) = mpsc::unbounded_channel::<InboundMessage>();
+        let metrics = P2pMetrics::default();
+        for tx in [&grandchild, &child, &parent] {
+            let data = serde_json::to_vec(&P2pMessage::Transaction(tx.clone())).unwrap();
+            forward_gossip_message(&sender, PeerId::random(), None, &data, DEFAULT_MAX_MESSAGE_SIZE, &metrics);
+        }
+
+        let mut pool = MempoolPool::default();
+        let mut admissions = Vec::new();
+        while let Ok(InboundMessage { message: P2pMessage::Transaction(tx), .. }) = receiver.try_recv() {
+            admissions.push(pool.accept(tx, &blockchain).unwrap());
+        }
+        assert_eq!(admissions, vec![Admission::Orphaned, Admission::Orphaned, Admission::Accepted]);
+        assert_eq!(pool.orphan_count(), 0);
+
+        let transactions = pool.select_for_block(&blockchain, MAX_BLOCK_TRANSACTIONS_SIZE);
+        let ids: Vec<&str> = transactions.iter().map(|tx| tx.id.as_str()).collect();
+        assert_eq!(ids, vec![parent.id.as_str(), child.id.as_str(), grandchild.id.as_str()]);
+        blockchain.add_block(FractalType::Sierpinski { depth: 1, seed: 0 }, transactions).unwrap();
+        assert!(blockchain.utxo_index.get(&(grandchild.id.clone(), 0)).is_some());
+    }
+
+    #[test]
+    fn test_orphan_is_promoted_when_parent_is_mined() {
+        let mut blockchain = Blockchain::new(1);
+        let owner = Wallet::new();
+        let funding = Transaction::new(
+            vec![],
+            vec![
+                TxOutput { value: 10_000, script_pub_key: owner.get_address().into() },
+                TxOutput { value: 10_000, script_pub_key: owner.get_address().into() },
+            ],
+        );
+        let child = signed_spend(&owner, &funding.id, 0, 9_000);
+        let stolen = signed_spend(&Wallet::new(), &funding.id, 1, 9_000);
+
+        let mut pool = MempoolPool::default();
+        assert_eq!(pool.accept(child.clone(), &blockchain), Ok(Admission::Orphaned));
+        assert_eq!(pool.accept(stolen.clone(), &blockchain), Ok(Admission::Orphaned));
+
+        let block = blockchain.add_block(FractalType::Sierpinski { depth: 1, seed: 0 }, vec![funding]).unwrap();
+        pool.block_connected(&block, &blockchain);
+        assert_eq!(pool.orphan_count(), 0);
+        assert!(pool.contains(&child.id));
+        // Its signature is checked once the output it spends is known.
+        assert!(!pool.contains(&stolen.id));
+    }
+    #[test]
+    fn test_submit_checked_runs_every_check() {
+        let mut blockchain = Blockchain::new(1);
+        let owner = Wallet::new();
+        let funding = Transaction::new(
+            vec![],
+            vec![
+                TxOutput { value: 10_000, script_pub_key: owner.get_address().into() },
+                TxOutput { value: 10_000, script_pub_key: owner.get_address().into() },
+            ],
+        );
+        blockchain.add_block(FractalType::Sierpinski { depth: 1, seed: 0 }, vec![funding.clone()]).unwrap();
+        let mut pool = MempoolPool::default().with_min_relay_fee_per_byte(1);
+
+        let mut mislabelled = signed_spend(&owner, &funding.id, 0, 9_000);
+        mislabelled.id = "0".repeat(64);
+        assert!(matches!(pool.submit_checked(mislabelled, &blockchain), Err(MempoolError::IdMismatch { .. })));
+        let stolen = signed_spend(&Wallet::new(), &funding.id, 0, 9_000);
+        assert!(matches!(pool.submit_checked(stolen, &blockchain), Err(MempoolError::Invalid(TxError::InvalidSpend { .. }))));
+        let unknown = signed_spend(&owner, &funding.id, 2, 9_000);
+        assert!(matches!(pool.submit_checked(unknown, &blockchain), Err(MempoolError::Invalid(TxError::UnknownInput { .. }))));
+        let inflated = signed_spend(&owner, &funding.id, 0, 10_001);
+        assert!(matches!(pool.submit_checked(inflated, &blockchain), Err(MempoolError::Invalid(TxError::OutputsExceedInputs { .. }))));
+        let dust = signed_spend(&owner, &funding.id, 0, 100);
+        assert_eq!(
+            pool.submit_checked(dust, &blockchain),
+            Err(MempoolError::Dust { vout: 0, value: 100, threshold: SIGNED_INPUT_SIZE })
+        );
+        let mut twice = spend(&funding.id, 0, 9_000);
+        twice.inputs.push(twice.inputs[0].clone());
+        twice.id = twice.calculate_hash();
+        assert!(matches!(pool.submit_checked(twice, &blockchain), Err(MempoolError::DuplicateInput { .. })));
+
+        let tx = signed_spend(&owner, &funding.id, 0, 9_000);
+        assert_eq!(pool.submit_checked(tx.clone(), &blockchain), Ok((1_000, vec![])));
+        assert_eq!(pool.submit_checked(tx.clone(), &blockchain), Err(MempoolError::AlreadyKnown { txid: tx.id.clone() }));
+        let double_spend = signed_spend(&owner, &funding.id, 0, 9_500);
+        assert_eq!(
+            pool.submit_checked(double_spend, &blockchain),
+            Err(MempoolError::ReplacementFeeTooLow { fee: 500, required: 1_001 })
+        );
+        // Now that the pool isn't empty, the relay fee applies too.
+        let free = signed_spend(&owner, &funding.id, 1, 10_000);
+        assert!(matches!(pool.submit_checked(free, &blockchain), Err(MempoolError::FeeTooLow { .. })));
+    }
+
+    #[test]
+    fn test_replacement_must_cover_inputs_recipients_and_fees() {
+        let mut blockchain = Blockchain::new(1);
+        let owner = Wallet::new();
+        let pay = |address: &str, value: u64| TxOutput { value, script_pub_key: address.to_string().into() };
+        let funding = Transaction::new(vec![], vec![pay(&owner.get_address(), 10_000), pay(&owner.get_address(), 10_000)]);
+        blockchain.add_block(FractalType::Sierpinski { depth: 1, seed: 0 }, vec![funding.clone()]).unwrap();
+        let signed = |spent: &[(&str, usize)], outputs: Vec<TxOutput>| {
+            let inputs = spent
+                .iter()
+                .map(|(txid, vout)| TxInput { txid: txid.to_string(), vout: *vout, script_sig: UnlockingScript::default(), sequence: 0 })
+                .collect();
+            let mut tx = Transaction::new(inputs, outputs);
+            tx.sign(&owner);
+            tx
+        };
+        let both = [(funding.id.as_str(), 0), (funding.id.as_str(), 1)];
+
+        let mut pool = MempoolPool::default();
+        let original = signed(&both, vec![pay("bob", 6_000), pay(&owner.get_address(), 13_000)]);
+        pool.submit(original.clone(), &blockchain).unwrap();
+        let child = signed(&[(original.id.as_str(), 1)], vec![pay(&owner.get_address(), 12_900)]);
+        pool.submit(child.clone(), &blockchain).unwrap();
+
+        let partial = signed(&both[..1], vec![pay("bob", 6_000), pay(&owner.get_address(), 1_000)]);
+        assert_eq!(
+            pool.submit(partial, &blockchain),
+            Err(MempoolError::Conflict { txid: funding.id.clone(), vout: 0, spent_by: original.id.clone() })
+        );
+        let short_changed = signed(&both, vec![pay("bob", 5_000), pay(&owner.get_address(), 13_000)]);
+        assert_eq!(
+            pool.submit(short_changed, &blockchain),
+            Err(MempoolError::ReplacementReducesOutput { txid: original.id.clone(), vout: 0 })
+        );
+        // The child's fee is lost with it, so it has to be covered too.
+        let cheap = signed(&both, vec![pay("bob", 6_000), pay(&owner.get_address(), 12_950)]);
+        assert_eq!(pool.submit(cheap, &blockchain), Err(MempoolError::ReplacementFeeTooLow { fee: 1_050, required: 1_101 }));
+
+        let replacement = signed(&both, vec![pay("bob", 6_000), pay(&owner.get_address(), 12_000)]);
+        assert_eq!(pool.submit(replacement.clone(), &blockchain), Ok(vec![original.clone(), child]));
+        assert_eq!(pool.txids().collect::<Vec<_>>(), [replacement.id.as_str()]);
+
+        // A bump rebuilds the transaction, taking the extra fee from the change.
+        let wallets = std::slice::from_ref(&owner);
+        let mut pool = MempoolPool::default();
+        pool.submit(original.clone(), &blockchain).unwrap();
+        assert_eq!(
+            pool.build_fee_bump(&original.id, 1_000, wallets, &blockchain),
+            Err(FeeBumpError::FeeNotHigher { fee: 1_000, new_fee: 1_000 })
+        );
+        assert!(matches!(pool.build_fee_bump(&original.id, 1_500, &[Wallet::new()], &blockchain), Err(FeeBumpError::NotOwned { .. })));
+        assert!(matches!(
+            pool.build_fee_bump(&original.id, 20_000, wallets, &blockchain),
+            Err(FeeBumpError::InsufficientFunds { available: 20_000, required: 26_000 })
+        ));
+        let bumped = pool.build_fee_bump(&original.id, 1_500, wallets, &blockchain).unwrap();
+        assert_eq!(bumped.outputs, vec![pay("bob", 6_000), pay(&owner.get_address(), 12_500)]);
+        assert_eq!(pool.submit(bumped, &blockchain), Ok(vec![original.clone()]));
+        assert_eq!(
+            pool.build_fee_bump(&original.id, 2_000, wallets, &blockchain),
+            Err(FeeBumpError::NotPending { txid: original.id.clone() })
+        );
+    }
+}
@@ -1,2 +1,14 @@
+pub mod amount;
+pub mod canonical;
+pub mod clock;
+pub mod keystore;
+pub mod labels;
+pub mod mempool;
+pub mod nft;
+pub mod orphan;
+pub mod script;
+pub mod spend_policy;
+pub mod tracker;
 pub mod transaction;
 pub mod wallet;
+pub mod wallet_backup;
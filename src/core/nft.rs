@@ -0,0 +1,206 @@
+use ed25519_dalek::{Signature, Verifier, VerifyingKey};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use crate::blockchain::chain::{Blockchain, Denomination};
+use crate::core::wallet::Wallet;
+use crate::fractal::FractalType;
+
+/// Where the NFT registry is saved, next to `blockchain.json`.
+pub const NFT_REGISTRY_FILE: &str = "nft_registry.json";
+/// The coins a claim burns, which its owner must hold.
+pub const NFT_CLAIM_FEE_COINS: u64 = 10;
+
+/// The burn fee for a claim, in the chain's base units.
+pub fn claim_fee(denomination: Denomination) -> u64 {
+    NFT_CLAIM_FEE_COINS * 10u64.pow(denomination.decimals())
+}
+
+/// Identifies a fractal combination: the hex SHA-256 of its JSON encoding.
+pub fn fractal_hash(fractal_type: &FractalType) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(serde_json::to_string(fractal_type).unwrap().as_bytes());
+    hex::encode(hasher.finalize())
+}
+
+/// A claim of ownership over a fractal combination, signed by its owner.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct NftClaim {
+    pub fractal_type: FractalType,
+    pub owner_address: String,
+    /// The owner's hex-encoded public key. The address is a hash of it, so
+    /// the signature can't be checked without it.
+    pub public_key: String,
+    /// The hex-encoded signature of `signed_message()`.
+    pub signature: String,
+}
+
+impl NftClaim {
+    /// Creates a claim of `fractal_type` for `wallet`, signed with its key.
+    pub fn new(fractal_type: FractalType, wallet: &Wallet) -> Self {
+        let mut claim = NftClaim {
+            fractal_type,
+            owner_address: wallet.get_address(),
+            public_key: hex::encode(wallet.get_public_key().as_bytes()),
+            signature: String::new(),
+        };
+        claim.signature = hex::encode(wallet.sign(claim.signed_message().as_bytes()).to_bytes());
+        claim
+    }
+
+    pub fn fractal_hash(&self) -> String {
+        fractal_hash(&self.fractal_type)
+    }
+
+    /// The message the owner signs, binding the fractal to their address.
+    fn signed_message(&self) -> String {
+        format!("{}:{}", self.fractal_hash(), self.owner_address)
+    }
+
+    /// Returns true if `public_key` belongs to `owner_address` and signed the claim.
+    pub fn verify(&self) -> bool {
+        let Some(public_key) = hex::decode(&self.public_key)
+            .ok()
+            .and_then(|bytes| <[u8; 32]>::try_from(bytes).ok())
+            .and_then(|bytes| VerifyingKey::from_bytes(&bytes).ok())
+        else {
+            return false;
+        };
+        let Some(signature) = hex::decode(&self.signature).ok().and_then(|bytes| <[u8; 64]>::try_from(bytes).ok()) else {
+            return false;
+        };
+        Wallet::address_of(&public_key) == self.owner_address
+            && public_key.verify(self.signed_message().as_bytes(), &Signature::from_bytes(&signature)).is_ok()
+    }
+}
+
+/// Why a claim was refused.
+#[derive(Debug, Clone, PartialEq)]
+pub enum NftError {
+    AlreadyClaimed { owner_address: String },
+    InsufficientBalance { balance: u64, required: u64 },
+    InvalidSignature,
+}
+
+impl fmt::Display for NftError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NftError::AlreadyClaimed { owner_address } => write!(f, "This fractal is already owned by {}", owner_address),
+            NftError::InsufficientBalance { balance, required } => {
+                write!(f, "Claiming a fractal burns {} but the owner's balance is {}", required, balance)
+            }
+            NftError::InvalidSignature => write!(f, "The claim's signature does not match its owner"),
+        }
+    }
+}
+
+/// The owner of every claimed fractal, by fractal hash, persisted to a
+/// JSON file.
+#[derive(Debug)]
+pub struct NftRegistry {
+    path: PathBuf,
+    owners: HashMap<String, String>,
+}
+
+pub type SharedNftRegistry = Arc<Mutex<NftRegistry>>;
+
+impl NftRegistry {
+    /// Loads the registry saved at `path`. A missing or unreadable file gives
+    /// an empty registry.
+    pub fn load(path: impl Into<PathBuf>) -> Self {
+        let path = path.into();
+        let owners = match fs::read_to_string(&path) {
+            Ok(contents) => serde_json::from_str(&contents).unwrap_or_else(|e| {
+                tracing::warn!("Ignoring unreadable NFT registry {}: {}", path.display(), e);
+                HashMap::new()
+            }),
+            Err(_) => HashMap::new(),
+        };
+        Self { path, owners }
+    }
+
+    /// Writes the registry back to the file it was loaded from.
+    pub fn save(&self) -> std::io::Result<()> {
+        let serialized = serde_json::to_string_pretty(&self.owners).unwrap();
+        let mut file = fs::File::create(&self.path)?;
+        file.write_all(serialized.as_bytes())
+    }
+
+    /// The file the store is saved to.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Returns the address owning the fractal with the given hash.
+    pub fn owner(&self, fractal_hash: &str) -> Option<&str> {
+        self.owners.get(fractal_hash).map(String::as_str)
+    }
+
+    /// Checks that `claim` is for an unclaimed fractal, that its owner can
+    /// pay the burn fee and that it is signed by the owner.
+    pub fn check(&self, claim: &NftClaim, blockchain: &Blockchain) -> Result<(), NftError> {
+        if let Some(owner_address) = self.owner(&claim.fractal_hash()) {
+            return Err(NftError::AlreadyClaimed { owner_address: owner_address.to_string() });
+        }
+        let required = claim_fee(blockchain.denomination);
+        let balance = blockchain.get_balance(&claim.owner_address);
+        if balance < required {
+            return Err(NftError::InsufficientBalance { balance, required });
+        }
+        if !claim.verify() {
+            return Err(NftError::InvalidSignature);
+        }
+        Ok(())
+    }
+
+    /// Records a checked claim and saves the registry.
+    pub fn register(&mut self, claim: &NftClaim) {
+        self.owners.insert(claim.fractal_hash(), claim.owner_address.clone());
+        if let Err(e) = self.save() {
+            tracing::error!("Failed to save NFT registry: {}", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::transaction::{Transaction, TxOutput};
+
+    #[test]
+    fn test_claim_is_checked_and_persisted() {
+        let path = std::env::temp_dir().join(format!("sierpchain-nft-{}.json", rand::random::<u64>()));
+        let mut registry = NftRegistry::load(&path);
+        let mut blockchain = Blockchain::new(1);
+        let owner = Wallet::new();
+        let fractal_type = FractalType::Sierpinski { depth: 3, seed: 7 };
+        let claim = NftClaim::new(fractal_type.clone(), &owner);
+        let required = claim_fee(blockchain.denomination);
+        assert_eq!(registry.check(&claim, &blockchain), Err(NftError::InsufficientBalance { balance: 0, required }));
+
+        let funding = Transaction::new(vec![], vec![TxOutput { value: required, script_pub_key: owner.get_address().into() }]);
+        blockchain.add_block(FractalType::Sierpinski { depth: 1, seed: 0 }, vec![funding]).unwrap();
+        let mut forged = claim.clone();
+        forged.owner_address = Wallet::new().get_address();
+        assert_eq!(registry.check(&forged, &blockchain), Err(NftError::InsufficientBalance { balance: 0, required }));
+        forged.owner_address = owner.get_address();
+        forged.fractal_type = FractalType::Sierpinski { depth: 3, seed: 8 };
+        assert_eq!(registry.check(&forged, &blockchain), Err(NftError::InvalidSignature));
+
+        assert_eq!(registry.check(&claim, &blockchain), Ok(()));
+        registry.register(&claim);
+        let reloaded = NftRegistry::load(&path);
+        assert_eq!(reloaded.owner(&fractal_hash(&fractal_type)), Some(owner.get_address().as_str()));
+        assert_eq!(
+            reloaded.check(&claim, &blockchain),
+            Err(NftError::AlreadyClaimed { owner_address: owner.get_address() })
+        );
+        std::fs::remove_file(path).ok();
+    }
+}
@@ -0,0 +1,173 @@
+use std::collections::{HashMap, HashSet};
+use std::time::Duration;
+
+use crate::blockchain::utxo::OutPoint;
+use crate::core::clock::{system_clock, SharedClock};
+use crate::core::transaction::Transaction;
+
+/// The most orphans kept at once.
+pub const MAX_ORPHANS: usize = 100;
+/// How long an orphan waits for its parents before it is dropped.
+pub const ORPHAN_TTL: Duration = Duration::from_secs(20 * 60);
+
+#[derive(Debug, Clone)]
+struct Orphan {
+    tx: Transaction,
+    missing: Vec<OutPoint>,
+    /// The clock's time when the orphan arrived, and how many orphans
+    /// arrived before it, which orders orphans received in the same second.
+    received_at: (i64, u64),
+}
+
+/// Transactions that spend outputs we haven't seen yet, held until the
+/// transactions creating those outputs arrive.
+///
+/// Orphans are indexed by the outpoints they're missing, so that when a
+/// parent shows up its children can be found without scanning the pool.
+#[derive(Debug, Clone)]
+pub struct OrphanPool {
+    orphans: HashMap<String, Orphan>,
+    by_missing: HashMap<OutPoint, HashSet<String>>,
+    max_orphans: usize,
+    ttl: Duration,
+    clock: SharedClock,
+    received: u64,
+}
+
+impl Default for OrphanPool {
+    fn default() -> Self {
+        Self::new(MAX_ORPHANS, ORPHAN_TTL)
+    }
+}
+
+impl OrphanPool {
+    pub fn new(max_orphans: usize, ttl: Duration) -> Self {
+        Self { orphans: HashMap::new(), by_missing: HashMap::new(), max_orphans, ttl, clock: system_clock(), received: 0 }
+    }
+
+    /// Sets the clock orphans age by.
+    pub fn with_clock(mut self, clock: SharedClock) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    pub fn len(&self) -> usize {
+        self.orphans.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.orphans.is_empty()
+    }
+
+    pub fn contains(&self, txid: &str) -> bool {
+        self.orphans.contains_key(txid)
+    }
+
+    /// Holds `tx` until the outputs in `missing` become available. Expired
+    /// orphans are dropped first, then the oldest ones if the pool is full.
+    pub fn insert(&mut self, tx: Transaction, missing: Vec<OutPoint>) {
+        if self.max_orphans == 0 || self.contains(&tx.id) {
+            return;
+        }
+        let now = self.clock.now();
+        let ttl = i64::try_from(self.ttl.as_secs()).unwrap_or(i64::MAX);
+        let expired: Vec<String> = self
+            .orphans
+            .iter()
+            .filter(|(_, orphan)| now.saturating_sub(orphan.received_at.0) >= ttl)
+            .map(|(txid, _)| txid.clone())
+            .collect();
+        for txid in expired {
+            self.remove(&txid);
+        }
+        while self.orphans.len() >= self.max_orphans {
+            let oldest = self
+                .orphans
+                .iter()
+                .min_by_key(|(_, orphan)| orphan.received_at)
+                .map(|(txid, _)| txid.clone())
+                .unwrap();
+            tracing::debug!("Orphan pool full, evicting {}", oldest);
+            self.remove(&oldest);
+        }
+
+        for outpoint in &missing {
+            self.by_missing.entry(outpoint.clone()).or_default().insert(tx.id.clone());
+        }
+        self.received += 1;
+        self.orphans.insert(tx.id.clone(), Orphan { tx, missing, received_at: (now, self.received) });
+    }
+
+    /// Removes and returns the orphans waiting on any output of `parent`.
+    pub fn take_children(&mut self, parent: &Transaction) -> Vec<Transaction> {
+        let mut children = HashSet::new();
+        for vout in 0..parent.outputs.len() {
+            if let Some(txids) = self.by_missing.get(&(parent.id.clone(), vout)) {
+                children.extend(txids.iter().cloned());
+            }
+        }
+        let mut children: Vec<Orphan> = children.iter().filter_map(|txid| self.remove(txid)).collect();
+        children.sort_by_key(|orphan| orphan.received_at);
+        children.into_iter().map(|orphan| orphan.tx).collect()
+    }
+
+    fn remove(&mut self, txid: &str) -> Option<Orphan> {
+        let orphan = self.orphans.remove(txid)?;
+        for outpoint in &orphan.missing {
+            if let Some(txids) = self.by_missing.get_mut(outpoint) {
+                txids.remove(txid);
+                if txids.is_empty() {
+                    self.by_missing.remove(outpoint);
+                }
+            }
+        }
+        Some(orphan)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::script::UnlockingScript;
+    use crate::core::transaction::{TxInput, TxOutput};
+
+    fn spend(txid: &str, vout: usize) -> Transaction {
+        Transaction::new(
+            vec![TxInput {
+                txid: txid.to_string(),
+                vout,
+                script_sig: UnlockingScript::default(),
+                sequence: 0,
+            }],
+            vec![TxOutput { value: 1, script_pub_key: "someone".into() }],
+        )
+    }
+
+    #[test]
+    fn test_oldest_orphan_is_evicted_on_overflow() {
+        let mut orphans = OrphanPool::new(2, ORPHAN_TTL);
+        let parents: Vec<Transaction> = (0..3).map(|i| spend(&i.to_string().repeat(64), 0)).collect();
+        for parent in &parents {
+            orphans.insert(spend(&parent.id, 0), vec![(parent.id.clone(), 0)]);
+            std::thread::sleep(Duration::from_millis(1));
+        }
+
+        assert_eq!(orphans.len(), 2);
+        assert!(orphans.take_children(&parents[0]).is_empty());
+        assert_eq!(orphans.take_children(&parents[1]).len(), 1);
+        assert_eq!(orphans.take_children(&parents[2]).len(), 1);
+        assert_eq!(orphans.len(), 0);
+    }
+
+    #[test]
+    fn test_expired_orphans_are_dropped() {
+        let mut orphans = OrphanPool::new(MAX_ORPHANS, Duration::ZERO);
+        let first = spend(&"0".repeat(64), 0);
+        let second = spend(&"1".repeat(64), 0);
+        orphans.insert(spend(&first.id, 0), vec![(first.id.clone(), 0)]);
+        orphans.insert(spend(&second.id, 0), vec![(second.id.clone(), 0)]);
+
+        assert_eq!(orphans.len(), 1);
+        assert!(orphans.take_children(&first).is_empty());
+    }
+}
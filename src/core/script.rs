@@ -0,0 +1,205 @@
+use ed25519_dalek::{Signature, Verifier, VerifyingKey};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+use crate::core::wallet::Wallet;
+
+/// The condition an output sets on who may spend it.
+///
+/// A pay-to-public-key-hash lock is serialized as the bare address, which is
+/// also how outputs were stored before scripts were typed, so existing
+/// transaction IDs and block hashes are unchanged.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Hash)]
+#[serde(from = "String", into = "String")]
+pub enum LockingScript {
+    /// Spendable by the holder of the key whose address this is.
+    PayToPubKeyHash(String),
+}
+
+impl LockingScript {
+    /// Returns the address the output pays.
+    pub fn address(&self) -> &str {
+        match self {
+            LockingScript::PayToPubKeyHash(address) => address,
+        }
+    }
+
+    /// Returns true if the output pays `address`.
+    pub fn pays_to(&self, address: &str) -> bool {
+        self.address() == address
+    }
+}
+
+impl From<String> for LockingScript {
+    fn from(address: String) -> Self {
+        LockingScript::PayToPubKeyHash(address)
+    }
+}
+
+impl From<&str> for LockingScript {
+    fn from(address: &str) -> Self {
+        LockingScript::PayToPubKeyHash(address.to_string())
+    }
+}
+
+impl From<LockingScript> for String {
+    fn from(script: LockingScript) -> Self {
+        match script {
+            LockingScript::PayToPubKeyHash(address) => address,
+        }
+    }
+}
+
+/// What an input presents to spend an output.
+///
+/// Serialized as the `script_sig` and `pub_key` strings inputs have always
+/// had, so it is flattened into `TxInput`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(from = "RawUnlockingScript", into = "RawUnlockingScript")]
+pub enum UnlockingScript {
+    /// No proof of ownership: the tag of a coinbase input, or nothing at all
+    /// in an input that hasn't been signed yet.
+    Data(String),
+    /// A hex-encoded signature and the hex-encoded public key that made it.
+    SignaturePubKey { sig: String, pub_key: String },
+}
+
+impl Default for UnlockingScript {
+    fn default() -> Self {
+        UnlockingScript::Data(String::new())
+    }
+}
+
+/// The legacy layout of an unlocking script, as two strings.
+#[derive(Serialize, Deserialize)]
+pub struct RawUnlockingScript {
+    script_sig: String,
+    pub_key: String,
+}
+
+impl From<RawUnlockingScript> for UnlockingScript {
+    fn from(raw: RawUnlockingScript) -> Self {
+        if raw.pub_key.is_empty() {
+            UnlockingScript::Data(raw.script_sig)
+        } else {
+            UnlockingScript::SignaturePubKey { sig: raw.script_sig, pub_key: raw.pub_key }
+        }
+    }
+}
+
+impl From<UnlockingScript> for RawUnlockingScript {
+    fn from(script: UnlockingScript) -> Self {
+        match script {
+            UnlockingScript::Data(data) => RawUnlockingScript { script_sig: data, pub_key: String::new() },
+            UnlockingScript::SignaturePubKey { sig, pub_key } => RawUnlockingScript { script_sig: sig, pub_key },
+        }
+    }
+}
+
+/// Why an unlocking script fails to spend an output.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ScriptError {
+    MissingSignature,
+    MalformedPubKey,
+    MalformedSignature,
+    /// The public key doesn't hash to the address the output pays.
+    WrongKey,
+    BadSignature,
+}
+
+impl fmt::Display for ScriptError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ScriptError::MissingSignature => write!(f, "Input is not signed"),
+            ScriptError::MalformedPubKey => write!(f, "Public key is not a valid hex-encoded key"),
+            ScriptError::MalformedSignature => write!(f, "Signature is not a valid hex-encoded signature"),
+            ScriptError::WrongKey => write!(f, "Public key does not belong to the address the output pays"),
+            ScriptError::BadSignature => write!(f, "Signature does not verify"),
+        }
+    }
+}
+
+/// Checks that `unlocking` satisfies `locking` for a transaction whose
+/// signed bytes are `signing_bytes`: the key must own the locked address
+/// and must have signed those bytes.
+pub fn verify_spend(locking: &LockingScript, unlocking: &UnlockingScript, signing_bytes: &[u8]) -> Result<(), ScriptError> {
+    let LockingScript::PayToPubKeyHash(address) = locking;
+    let UnlockingScript::SignaturePubKey { sig, pub_key } = unlocking else {
+        return Err(ScriptError::MissingSignature);
+    };
+    let public_key = hex::decode(pub_key)
+        .ok()
+        .and_then(|bytes| <[u8; 32]>::try_from(bytes).ok())
+        .and_then(|bytes| VerifyingKey::from_bytes(&bytes).ok())
+        .ok_or(ScriptError::MalformedPubKey)?;
+    let signature = hex::decode(sig)
+        .ok()
+        .and_then(|bytes| <[u8; 64]>::try_from(bytes).ok())
+        .map(|bytes| Signature::from_bytes(&bytes))
+        .ok_or(ScriptError::MalformedSignature)?;
+    if Wallet::address_of(&public_key) != *address {
+        return Err(ScriptError::WrongKey);
+    }
+    public_key.verify(signing_bytes, &signature).map_err(|_| ScriptError::BadSignature)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn unlock(wallet: &Wallet, message: &[u8]) -> UnlockingScript {
+        UnlockingScript::SignaturePubKey {
+            sig: hex::encode(wallet.sign(message).to_bytes()),
+            pub_key: hex::encode(wallet.get_public_key().as_bytes()),
+        }
+    }
+
+    #[test]
+    fn test_owner_can_spend() {
+        let owner = Wallet::new();
+        let locking = LockingScript::from(owner.get_address());
+        assert_eq!(verify_spend(&locking, &unlock(&owner, b"tx"), b"tx"), Ok(()));
+    }
+
+    #[test]
+    fn test_other_key_cannot_spend() {
+        let owner = Wallet::new();
+        let thief = Wallet::new();
+        let locking = LockingScript::from(owner.get_address());
+        // A perfectly valid signature, just not by the owner.
+        assert_eq!(verify_spend(&locking, &unlock(&thief, b"tx"), b"tx"), Err(ScriptError::WrongKey));
+
+        let UnlockingScript::SignaturePubKey { pub_key, .. } = unlock(&owner, b"tx") else { unreachable!() };
+        let UnlockingScript::SignaturePubKey { sig, .. } = unlock(&thief, b"tx") else { unreachable!() };
+        let forged = UnlockingScript::SignaturePubKey { sig, pub_key };
+        assert_eq!(verify_spend(&locking, &forged, b"tx"), Err(ScriptError::BadSignature));
+    }
+
+    #[test]
+    fn test_malformed_or_missing_unlocking_scripts_fail() {
+        let owner = Wallet::new();
+        let locking = LockingScript::from(owner.get_address());
+        assert_eq!(verify_spend(&locking, &unlock(&owner, b"tx"), b"other tx"), Err(ScriptError::BadSignature));
+        assert_eq!(verify_spend(&locking, &UnlockingScript::default(), b"tx"), Err(ScriptError::MissingSignature));
+
+        let UnlockingScript::SignaturePubKey { sig, pub_key } = unlock(&owner, b"tx") else { unreachable!() };
+        let bad_key = UnlockingScript::SignaturePubKey { sig: sig.clone(), pub_key: "zz".to_string() };
+        assert_eq!(verify_spend(&locking, &bad_key, b"tx"), Err(ScriptError::MalformedPubKey));
+        let short_sig = UnlockingScript::SignaturePubKey { sig: sig[..64].to_string(), pub_key };
+        assert_eq!(verify_spend(&locking, &short_sig, b"tx"), Err(ScriptError::MalformedSignature));
+    }
+
+    #[test]
+    fn test_legacy_strings_deserialize_to_scripts() {
+        let locking: LockingScript = serde_json::from_str("\"1address\"").unwrap();
+        assert_eq!(locking, LockingScript::PayToPubKeyHash("1address".to_string()));
+        assert_eq!(serde_json::to_string(&locking).unwrap(), "\"1address\"");
+
+        let signed: UnlockingScript = serde_json::from_str(r#"{"script_sig":"ab","pub_key":"cd"}"#).unwrap();
+        assert_eq!(signed, UnlockingScript::SignaturePubKey { sig: "ab".to_string(), pub_key: "cd".to_string() });
+        let coinbase: UnlockingScript = serde_json::from_str(r#"{"script_sig":"coinbase","pub_key":""}"#).unwrap();
+        assert_eq!(coinbase, UnlockingScript::Data("coinbase".to_string()));
+        assert_eq!(serde_json::to_string(&coinbase).unwrap(), r#"{"script_sig":"coinbase","pub_key":""}"#);
+    }
+}
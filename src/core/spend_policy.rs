@@ -0,0 +1,413 @@
+//! Limits on what the node's stored wallets may spend, and the queue of
+//! payments waiting for an operator to approve them. They protect the hot
+//! wallets from a caller who can reach the API but shouldn't drain them.
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use crate::core::transaction::BuildTransactRequest;
+
+/// Where spends and approvals are saved, next to `wallets.json`.
+pub const SPENDING_FILE: &str = "spending.json";
+/// The rolling window daily limits are counted over, in seconds.
+pub const SPEND_WINDOW_SECS: i64 = 24 * 60 * 60;
+
+/// What a stored wallet may spend. Every limit is off unless set.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct SpendPolicy {
+    /// The most one transaction may send, fee included, in base units.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_per_transaction: Option<u64>,
+    /// The most the wallet may send over any `SPEND_WINDOW_SECS`, fees included.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_per_day: Option<u64>,
+    /// The only addresses the wallet may pay, if set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub allowed_destinations: Option<Vec<String>>,
+    /// Payments sending more than this wait for approval.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub approval_threshold: Option<u64>,
+}
+
+impl SpendPolicy {
+    /// Returns true if the policy sets no limits.
+    pub fn is_unrestricted(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+/// A payment about to be made from stored wallets.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Payment {
+    /// The addresses of the wallets paying.
+    pub from: Vec<String>,
+    /// Who is paid, or `None` for a fee added to an earlier payment.
+    pub to: Option<String>,
+    /// What leaves the wallets, fee included.
+    pub value: u64,
+}
+
+impl Payment {
+    /// The extra fee `from` pays to speed up an earlier payment, by
+    /// replacing it or by spending its output in a child. It pays nobody
+    /// new, so destination limits don't apply.
+    pub fn fee(from: Vec<String>, value: u64) -> Self {
+        Self { from, to: None, value }
+    }
+}
+
+impl From<&BuildTransactRequest> for Payment {
+    fn from(request: &BuildTransactRequest) -> Self {
+        Self {
+            from: request.wallets.iter().map(|wallet| wallet.get_address()).collect(),
+            to: Some(request.to.clone()),
+            value: request.amount.saturating_add(request.fee),
+        }
+    }
+}
+
+/// Why a stored wallet may not make a payment, or not yet.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "reason", rename_all = "snake_case")]
+pub enum SpendError {
+    OverTransactionLimit { address: String, value: u64, limit: u64 },
+    OverDailyLimit { address: String, value: u64, spent: u64, limit: u64 },
+    DestinationNotAllowed { address: String, to: String },
+    /// Within the limits, but over the approval threshold.
+    NeedsApproval { address: String, value: u64, threshold: u64 },
+}
+
+impl fmt::Display for SpendError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SpendError::OverTransactionLimit { address, value, limit } => {
+                write!(f, "Sending {} from {} exceeds its limit of {} per transaction", value, address, limit)
+            }
+            SpendError::OverDailyLimit { address, value, spent, limit } => write!(
+                f,
+                "Sending {} from {} exceeds its limit of {} per 24 hours, of which {} is spent",
+                value, address, limit, spent
+            ),
+            SpendError::DestinationNotAllowed { address, to } => write!(f, "{} may not pay {}", address, to),
+            SpendError::NeedsApproval { address, value, threshold } => write!(
+                f,
+                "Sending {} from {} needs approval above {}; send it alone with /transact to queue it",
+                value, address, threshold
+            ),
+        }
+    }
+}
+
+/// Where an approval stands.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "state", rename_all = "snake_case")]
+pub enum ApprovalStatus {
+    Pending,
+    Approved { txid: String, decided_at: i64 },
+    Rejected { decided_at: i64 },
+}
+
+/// A `/transact` payment waiting for an operator.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Approval {
+    pub id: u64,
+    /// The stored wallets paying, whose keys sign it once approved.
+    pub from: Vec<String>,
+    pub to: String,
+    pub amount: u64,
+    pub fee: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expire_after_blocks: Option<u64>,
+    /// When it was requested, in Unix seconds.
+    pub requested_at: i64,
+    pub status: ApprovalStatus,
+}
+
+/// Why an approval can't be decided.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ApprovalError {
+    NotFound(u64),
+    AlreadyDecided(u64),
+}
+
+impl fmt::Display for ApprovalError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ApprovalError::NotFound(id) => write!(f, "No approval {}", id),
+            ApprovalError::AlreadyDecided(id) => write!(f, "Approval {} was already decided", id),
+        }
+    }
+}
+
+/// One payment from a stored wallet.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Spend {
+    pub address: String,
+    pub value: u64,
+    /// When it was sent, in Unix seconds.
+    pub timestamp: i64,
+    pub txid: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+struct SpendingRecords {
+    #[serde(default)]
+    spends: Vec<Spend>,
+    #[serde(default)]
+    approvals: Vec<Approval>,
+}
+
+/// The recent spends of the stored wallets, and every approval,
+/// persisted to a JSON file so that both survive restarts.
+#[derive(Debug)]
+pub struct SpendLedger {
+    path: PathBuf,
+    records: SpendingRecords,
+}
+
+pub type SharedSpendLedger = Arc<Mutex<SpendLedger>>;
+
+impl SpendLedger {
+    /// Loads the ledger saved at `path`. A missing file gives an empty
+    /// ledger; an unreadable one is an error rather than being overwritten,
+    /// since that would forget both limits and approvals.
+    pub fn load(path: impl Into<PathBuf>) -> std::io::Result<Self> {
+        let path = path.into();
+        let records = match fs::read_to_string(&path) {
+            Ok(contents) => serde_json::from_str(&contents)
+                .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => SpendingRecords::default(),
+            Err(e) => return Err(e),
+        };
+        Ok(Self { path, records })
+    }
+
+    /// Writes the ledger back to the file it was loaded from.
+    pub fn save(&self) -> std::io::Result<()> {
+        let serialized = serde_json::to_string_pretty(&self.records).unwrap();
+        let mut file = fs::File::create(&self.path)?;
+        file.write_all(serialized.as_bytes())
+    }
+
+    /// The file the ledger is saved to.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    fn save_or_log(&self) {
+        if let Err(e) = self.save() {
+            tracing::error!("Failed to save spending records to {}: {}", self.path.display(), e);
+        }
+    }
+
+    /// What `address` has sent within the window ending at `now`.
+    pub fn spent_in_window(&self, address: &str, now: i64) -> u64 {
+        self.records
+            .spends
+            .iter()
+            .filter(|spend| spend.address == address && now - spend.timestamp < SPEND_WINDOW_SECS)
+            .map(|spend| spend.value)
+            .sum()
+    }
+
+    /// Checks `payments`, in order, against the policies of the stored
+    /// wallets they spend from, each counting towards the daily limits of the ones after
+    /// it. A payment from several wallets counts in full against each.
+    /// Returns the position and error of every payment that breaks a limit
+    /// or, failing that, needs approval.
+    pub fn check(&self, policies: &HashMap<String, SpendPolicy>, payments: &[Payment], now: i64) -> Vec<(usize, SpendError)> {
+        let mut earlier: HashMap<&str, u64> = HashMap::new();
+        let mut errors = Vec::new();
+        for (index, payment) in payments.iter().enumerate() {
+            let guarded: Vec<(&str, &SpendPolicy)> = payment
+                .from
+                .iter()
+                .filter_map(|address| policies.get(address).map(|policy| (address.as_str(), policy)))
+                .collect();
+            let breach = guarded.iter().find_map(|(address, policy)| {
+                let spent = self.spent_in_window(address, now) + earlier.get(address).copied().unwrap_or(0);
+                limit_breach(address, policy, payment, spent)
+            });
+            if let Some(error) = breach {
+                errors.push((index, error));
+                continue;
+            }
+            for (address, _) in &guarded {
+                *earlier.entry(address).or_default() += payment.value;
+            }
+            let approval = guarded.iter().find_map(|(address, policy)| {
+                let threshold = policy.approval_threshold.filter(|threshold| payment.value > *threshold)?;
+                Some(SpendError::NeedsApproval { address: address.to_string(), value: payment.value, threshold })
+            });
+            errors.extend(approval.map(|error| (index, error)));
+        }
+        errors
+    }
+
+    /// Records `payment`, sent as `txid`, against the stored wallets in
+    /// `policies` it spends from, forgets spends that have left the window, and saves.
+    pub fn record(&mut self, policies: &HashMap<String, SpendPolicy>, payment: &Payment, txid: &str, now: i64) {
+        self.records.spends.retain(|spend| now - spend.timestamp < SPEND_WINDOW_SECS);
+        for address in payment.from.iter().filter(|address| policies.contains_key(*address)) {
+            self.records.spends.push(Spend { address: address.clone(), value: payment.value, timestamp: now, txid: txid.to_string() });
+        }
+        self.save_or_log();
+    }
+
+    pub fn approvals(&self) -> &[Approval] {
+        &self.records.approvals
+    }
+
+    pub fn approval(&self, id: u64) -> Option<&Approval> {
+        self.records.approvals.iter().find(|approval| approval.id == id)
+    }
+
+    /// Queues a payment for approval, numbering it after the last one, and
+    /// saves.
+    pub fn request_approval(&mut self, mut approval: Approval) -> Approval {
+        approval.id = self.records.approvals.iter().map(|approval| approval.id).max().map_or(1, |id| id + 1);
+        approval.status = ApprovalStatus::Pending;
+        self.records.approvals.push(approval.clone());
+        self.save_or_log();
+        approval
+    }
+
+    /// Settles a pending approval with `status`, and saves.
+    pub fn decide(&mut self, id: u64, status: ApprovalStatus) -> Result<Approval, ApprovalError> {
+        let approval = self.records.approvals.iter_mut().find(|approval| approval.id == id).ok_or(ApprovalError::NotFound(id))?;
+        if approval.status != ApprovalStatus::Pending {
+            return Err(ApprovalError::AlreadyDecided(id));
+        }
+        approval.status = status;
+        let approval = approval.clone();
+        self.save_or_log();
+        Ok(approval)
+    }
+}
+
+/// The limit in `policy` that sending `payment` from `address` breaks, given
+/// that `spent` was sent within the window.
+fn limit_breach(address: &str, policy: &SpendPolicy, payment: &Payment, spent: u64) -> Option<SpendError> {
+    if let (Some(allowed), Some(to)) = (&policy.allowed_destinations, &payment.to)
+        && !allowed.contains(to)
+    {
+        return Some(SpendError::DestinationNotAllowed { address: address.to_string(), to: to.clone() });
+    }
+    if let Some(limit) = policy.max_per_transaction.filter(|limit| payment.value > *limit) {
+        return Some(SpendError::OverTransactionLimit { address: address.to_string(), value: payment.value, limit });
+    }
+    if let Some(limit) = policy.max_per_day.filter(|limit| spent.saturating_add(payment.value) > *limit) {
+        return Some(SpendError::OverDailyLimit { address: address.to_string(), value: payment.value, spent, limit });
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_ledger() -> (PathBuf, SpendLedger) {
+        let path = std::env::temp_dir().join(format!("sierpchain-spending-{}.json", rand::random::<u64>()));
+        (path.clone(), SpendLedger::load(path).unwrap())
+    }
+
+    fn payment(to: &str, value: u64) -> Payment {
+        Payment { from: vec!["hot".to_string()], to: Some(to.to_string()), value }
+    }
+
+    fn policies(policy: SpendPolicy) -> HashMap<String, SpendPolicy> {
+        HashMap::from([("hot".to_string(), policy)])
+    }
+
+    #[test]
+    fn test_payments_breaking_a_limit_are_refused() {
+        let (path, ledger) = temp_ledger();
+        let policies = policies(SpendPolicy {
+            max_per_transaction: Some(100),
+            allowed_destinations: Some(vec!["cold".to_string()]),
+            ..SpendPolicy::default()
+        });
+        assert!(ledger.check(&policies, &[payment("cold", 100)], 0).is_empty());
+        assert_eq!(
+            ledger.check(&policies, &[payment("cold", 101)], 0),
+            vec![(0, SpendError::OverTransactionLimit { address: "hot".to_string(), value: 101, limit: 100 })]
+        );
+        assert_eq!(
+            ledger.check(&policies, &[payment("thief", 1)], 0),
+            vec![(0, SpendError::DestinationNotAllowed { address: "hot".to_string(), to: "thief".to_string() })]
+        );
+        // Wallets without a policy are not limited.
+        let unguarded = Payment { from: vec!["other".to_string()], ..payment("thief", u64::MAX) };
+        assert!(ledger.check(&policies, &[unguarded], 0).is_empty());
+        // A fee bump pays no new destination, but still counts.
+        assert!(ledger.check(&policies, &[Payment::fee(vec!["hot".to_string()], 100)], 0).is_empty());
+        assert_eq!(
+            ledger.check(&policies, &[Payment::fee(vec!["hot".to_string()], 101)], 0),
+            vec![(0, SpendError::OverTransactionLimit { address: "hot".to_string(), value: 101, limit: 100 })]
+        );
+        std::fs::remove_file(path).ok();
+    }
+
+    #[test]
+    fn test_daily_limit_counts_a_rolling_window() {
+        let (path, mut ledger) = temp_ledger();
+        let policies = policies(SpendPolicy { max_per_day: Some(1_000), ..SpendPolicy::default() });
+        let now = 1_000_000;
+        ledger.record(&policies, &payment("cold", 600), "a", now);
+        ledger.record(&policies, &payment("cold", 300), "b", now + 3_600);
+        assert_eq!(ledger.spent_in_window("hot", now + 3_600), 900);
+
+        let over = SpendError::OverDailyLimit { address: "hot".to_string(), value: 200, spent: 900, limit: 1_000 };
+        assert_eq!(ledger.check(&policies, &[payment("cold", 200)], now + 3_600), vec![(0, over)]);
+        // Earlier payments in the same check count too.
+        let errors = ledger.check(&policies, &[payment("cold", 100), payment("cold", 1)], now + 3_600);
+        assert_eq!(errors.iter().map(|(index, _)| *index).collect::<Vec<_>>(), vec![1]);
+
+        // Once the first spend leaves the window, its share is free again.
+        assert_eq!(ledger.spent_in_window("hot", now + SPEND_WINDOW_SECS), 300);
+        assert!(ledger.check(&policies, &[payment("cold", 700)], now + SPEND_WINDOW_SECS).is_empty());
+
+        let reloaded = SpendLedger::load(&path).unwrap();
+        assert_eq!(reloaded.spent_in_window("hot", now + 3_600), 900);
+        std::fs::remove_file(path).ok();
+    }
+
+    #[test]
+    fn test_approval_lifecycle_persists() {
+        let (path, mut ledger) = temp_ledger();
+        let policies = policies(SpendPolicy { approval_threshold: Some(50), ..SpendPolicy::default() });
+        assert_eq!(
+            ledger.check(&policies, &[payment("cold", 51)], 0),
+            vec![(0, SpendError::NeedsApproval { address: "hot".to_string(), value: 51, threshold: 50 })]
+        );
+
+        let request = Approval {
+            id: 0,
+            from: vec!["hot".to_string()],
+            to: "cold".to_string(),
+            amount: 50,
+            fee: 1,
+            expire_after_blocks: None,
+            requested_at: 10,
+            status: ApprovalStatus::Pending,
+        };
+        let first = ledger.request_approval(request.clone());
+        let second = ledger.request_approval(request);
+        assert_eq!((first.id, second.id), (1, 2));
+
+        let approved = ApprovalStatus::Approved { txid: "tx".to_string(), decided_at: 20 };
+        assert_eq!(ledger.decide(1, approved.clone()).unwrap().status, approved);
+        assert_eq!(ledger.decide(1, ApprovalStatus::Rejected { decided_at: 21 }), Err(ApprovalError::AlreadyDecided(1)));
+        assert_eq!(ledger.decide(3, ApprovalStatus::Rejected { decided_at: 21 }), Err(ApprovalError::NotFound(3)));
+
+        let reloaded = SpendLedger::load(&path).unwrap();
+        assert_eq!(reloaded.approval(1).unwrap().status, approved);
+        assert_eq!(reloaded.approval(2).unwrap().status, ApprovalStatus::Pending);
+        std::fs::remove_file(path).ok();
+    }
+}
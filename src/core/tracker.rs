@@ -0,0 +1,281 @@
+use serde::Serialize;
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use crate::blockchain::block::Block;
+use crate::blockchain::chain::Reorg;
+
+/// The most transactions tracked at once. The oldest is forgotten first.
+pub const MAX_TRACKED: usize = 10_000;
+/// The confirmations after which a transaction's status stops changing,
+/// short of a reorg disconnecting its block.
+pub const FINAL_CONFIRMATIONS: u64 = 6;
+
+/// Where a tracked transaction is on its way into the chain.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "state", rename_all = "snake_case")]
+pub enum TxStatus {
+    /// Waiting in the mempool.
+    Pending,
+    /// In the tip block.
+    Included { height: u64, block_hash: String },
+    /// In a block with `confirmations - 1` blocks on top of it.
+    Confirmed { height: u64, block_hash: String, confirmations: u64 },
+    /// Neither pending nor in the active chain any more.
+    Dropped { reason: String },
+}
+
+/// A tracked transaction's status, and how many times it has changed, so
+/// that a client can tell whether it has already seen it.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct TxStatusUpdate {
+    pub txid: String,
+    pub version: u64,
+    pub status: TxStatus,
+}
+
+#[derive(Debug, Clone)]
+struct Tracked {
+    status: TxStatus,
+    version: u64,
+    expiry_height: Option<u64>,
+}
+
+impl Tracked {
+    /// Moves to `status`, returning the update if it is a change.
+    fn change(&mut self, txid: &str, status: TxStatus) -> Option<TxStatusUpdate> {
+        if self.status == status {
+            return None;
+        }
+        self.status = status;
+        self.version += 1;
+        Some(TxStatusUpdate { txid: txid.to_string(), version: self.version, status: self.status.clone() })
+    }
+}
+
+/// Follows submitted transactions from the mempool into the chain, for
+/// clients waiting on them. It is told about blocks joining and leaving the
+/// active chain and about evictions, and returns the status changes each
+/// one causes.
+#[derive(Debug, Clone)]
+pub struct TxTracker {
+    entries: HashMap<String, Tracked>,
+    /// Tracked txids, oldest first.
+    order: VecDeque<String>,
+    capacity: usize,
+    /// The height of the active chain's tip, as of the last block seen.
+    tip_height: u64,
+}
+
+impl Default for TxTracker {
+    fn default() -> Self {
+        Self::new(MAX_TRACKED)
+    }
+}
+
+impl TxTracker {
+    pub fn new(capacity: usize) -> Self {
+        Self { entries: HashMap::new(), order: VecDeque::new(), capacity: capacity.max(1), tip_height: 0 }
+    }
+
+    /// Starts tracking a transaction that has just entered the mempool.
+    /// Returns `None` if it is already tracked.
+    pub fn track(&mut self, txid: &str, expiry_height: Option<u64>) -> Option<TxStatusUpdate> {
+        if self.entries.contains_key(txid) {
+            return None;
+        }
+        Some(self.insert(txid, TxStatus::Pending, expiry_height))
+    }
+
+    fn insert(&mut self, txid: &str, status: TxStatus, expiry_height: Option<u64>) -> TxStatusUpdate {
+        if self.order.len() >= self.capacity && let Some(oldest) = self.order.pop_front() {
+            self.entries.remove(&oldest);
+        }
+        self.order.push_back(txid.to_string());
+        self.entries.insert(txid.to_string(), Tracked { status: status.clone(), version: 0, expiry_height });
+        TxStatusUpdate { txid: txid.to_string(), version: 0, status }
+    }
+
+    /// The current status of `txid`, if it is tracked.
+    pub fn status(&self, txid: &str) -> Option<TxStatusUpdate> {
+        self.entries
+            .get(txid)
+            .map(|entry| TxStatusUpdate { txid: txid.to_string(), version: entry.version, status: entry.status.clone() })
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Records `block` becoming the tip. Its transactions are included, the
+    /// ones in earlier blocks gain a confirmation, and pending ones that can
+    /// no longer be mined are dropped. Untracked transactions in the block
+    /// are tracked from here on if `also_track` says so.
+    pub fn block_connected(&mut self, block: &Block, also_track: impl Fn(&str) -> bool) -> Vec<TxStatusUpdate> {
+        self.tip_height = block.index;
+        let mut updates = Vec::new();
+        for tx in block.transactions.iter().filter(|tx| !tx.is_coinbase()) {
+            let included = TxStatus::Included { height: block.index, block_hash: block.hash.clone() };
+            match self.entries.get_mut(&tx.id) {
+                Some(entry) => updates.extend(entry.change(&tx.id, included)),
+                None if also_track(&tx.id) => updates.push(self.insert(&tx.id, included, tx.expiry_height)),
+                None => {}
+            }
+        }
+        updates.extend(self.settle());
+        updates
+    }
+
+    /// Records a reorganisation leaving the tip at `height`. Transactions
+    /// from disconnected blocks are pending again if they are among
+    /// `returned_to_mempool`, and dropped otherwise, unless a connected
+    /// block includes them again.
+    pub fn apply_reorg(
+        &mut self,
+        reorg: &Reorg,
+        returned_to_mempool: &[String],
+        height: u64,
+        also_track: impl Fn(&str) -> bool,
+    ) -> Vec<TxStatusUpdate> {
+        let reconfirmed: HashSet<&str> =
+            reorg.connected.iter().flat_map(|block| &block.transactions).map(|tx| tx.id.as_str()).collect();
+        let mut updates = Vec::new();
+        for block in &reorg.disconnected {
+            for tx in block.transactions.iter().filter(|tx| !reconfirmed.contains(tx.id.as_str())) {
+                let Some(entry) = self.entries.get_mut(&tx.id) else { continue };
+                let status = if returned_to_mempool.contains(&tx.id) {
+                    TxStatus::Pending
+                } else {
+                    TxStatus::Dropped { reason: format!("Block {} was orphaned by a reorg", block.hash) }
+                };
+                updates.extend(entry.change(&tx.id, status));
+            }
+        }
+        for block in &reorg.connected {
+            updates.extend(self.block_connected(block, &also_track));
+        }
+        self.tip_height = height;
+        updates.extend(self.settle());
+        updates
+    }
+
+    /// Records that `txids` left the mempool without being mined.
+    pub fn dropped(&mut self, txids: &[String], reason: &str) -> Vec<TxStatusUpdate> {
+        txids
+            .iter()
+            .filter_map(|txid| {
+                let entry = self.entries.get_mut(txid).filter(|entry| entry.status == TxStatus::Pending)?;
+                entry.change(txid, TxStatus::Dropped { reason: reason.to_string() })
+            })
+            .collect()
+    }
+
+    /// Brings every status up to date with the tip height: confirmations
+    /// up to `FINAL_CONFIRMATIONS`, and expiry of pending transactions.
+    fn settle(&mut self) -> Vec<TxStatusUpdate> {
+        let tip_height = self.tip_height;
+        let mut updates = Vec::new();
+        for txid in &self.order {
+            let Some(entry) = self.entries.get_mut(txid) else { continue };
+            let status = match &entry.status {
+                TxStatus::Included { height, block_hash } | TxStatus::Confirmed { height, block_hash, .. }
+                    if *height <= tip_height =>
+                {
+                    let confirmations = (tip_height - height + 1).min(FINAL_CONFIRMATIONS);
+                    if confirmations == 1 {
+                        TxStatus::Included { height: *height, block_hash: block_hash.clone() }
+                    } else {
+                        TxStatus::Confirmed { height: *height, block_hash: block_hash.clone(), confirmations }
+                    }
+                }
+                TxStatus::Pending => match entry.expiry_height {
+                    Some(expiry_height) if tip_height + 1 >= expiry_height => {
+                        TxStatus::Dropped { reason: format!("Expired at height {}", expiry_height) }
+                    }
+                    _ => continue,
+                },
+                _ => continue,
+            };
+            updates.extend(entry.change(txid, status));
+        }
+        updates
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::transaction::{Transaction, TxOutput};
+    use crate::fractal::FractalType;
+
+    fn block(index: u64, transactions: Vec<Transaction>) -> Block {
+        Block {
+            index,
+            timestamp: 0,
+            fractal: FractalType::Sierpinski { depth: 0, seed: 0 }.generate(),
+            transactions,
+            previous_hash: String::new(),
+            hash: format!("block-{}", index),
+            nonce: 0,
+            bits: 0,
+            style_seed: 0,
+        }
+    }
+
+    fn states(updates: &[TxStatusUpdate]) -> Vec<&TxStatus> {
+        updates.iter().map(|update| &update.status).collect()
+    }
+
+    #[test]
+    fn test_transaction_goes_from_pending_to_confirmed_and_back() {
+        let tx = Transaction::new(vec![], vec![TxOutput { value: 1, script_pub_key: "someone".into() }]);
+        let mut tracker = TxTracker::default();
+        let never = |_: &str| false;
+        assert_eq!(tracker.track(&tx.id, None).unwrap().status, TxStatus::Pending);
+        assert_eq!(tracker.track(&tx.id, None), None);
+
+        let included = tracker.block_connected(&block(1, vec![tx.clone()]), never);
+        assert_eq!(states(&included), [&TxStatus::Included { height: 1, block_hash: "block-1".to_string() }]);
+        let confirmed = tracker.block_connected(&block(2, vec![]), never);
+        assert_eq!(
+            states(&confirmed),
+            [&TxStatus::Confirmed { height: 1, block_hash: "block-1".to_string(), confirmations: 2 }]
+        );
+        for index in 3..10 {
+            tracker.block_connected(&block(index, vec![]), never);
+        }
+        let status = tracker.status(&tx.id).unwrap();
+        assert_eq!(status.status, TxStatus::Confirmed { height: 1, block_hash: "block-1".to_string(), confirmations: FINAL_CONFIRMATIONS });
+        assert_eq!(status.version, FINAL_CONFIRMATIONS);
+
+        // Orphaning its block returns it to the mempool.
+        let reorg = Reorg { disconnected: vec![block(1, vec![tx.clone()])], connected: vec![] };
+        let updates = tracker.apply_reorg(&reorg, std::slice::from_ref(&tx.id), 0, never);
+        assert_eq!(states(&updates), [&TxStatus::Pending]);
+        let updates = tracker.dropped(std::slice::from_ref(&tx.id), "Evicted");
+        assert_eq!(states(&updates), [&TxStatus::Dropped { reason: "Evicted".to_string() }]);
+    }
+
+    #[test]
+    fn test_expired_and_forgotten_transactions() {
+        let mut tracker = TxTracker::new(2);
+        tracker.track("a", Some(2));
+        tracker.track("b", None);
+        let updates = tracker.block_connected(&block(1, vec![]), |_| false);
+        assert_eq!(states(&updates), [&TxStatus::Dropped { reason: "Expired at height 2".to_string() }]);
+
+        tracker.track("c", None);
+        assert_eq!(tracker.len(), 2);
+        assert!(tracker.status("a").is_none());
+
+        // Only watched transactions start being tracked when they're mined.
+        let tx = Transaction::new(vec![], vec![TxOutput { value: 1, script_pub_key: "someone".into() }]);
+        let watched = tx.id.clone();
+        let updates = tracker.block_connected(&block(2, vec![tx]), |txid| txid == watched);
+        assert_eq!(updates[0].txid, watched);
+        assert!(tracker.status(&watched).is_some());
+    }
+}
@@ -1,8 +1,19 @@
 use serde::{Serialize, Deserialize};
 use sha2::{Sha256, Digest};
 use chrono::Utc;
+use std::fmt;
+use crate::blockchain::chain::Blockchain;
+use crate::blockchain::utxo::UtxoIndex;
+use crate::core::canonical;
+use crate::core::script::{verify_spend, LockingScript, RawUnlockingScript, ScriptError, UnlockingScript};
 use crate::core::wallet::Wallet;
-use ed25519_dalek::{Signature, VerifyingKey, Verifier};
+
+/// The longest memo a transaction may carry, in bytes.
+pub const MAX_MEMO_BYTES: usize = 80;
+/// The longest message a miner may put in a coinbase, in bytes.
+pub const MAX_COINBASE_MESSAGE_BYTES: usize = 100;
+/// What the script of a coinbase input holds, ahead of the miner's message.
+pub const COINBASE_TAG: &str = "coinbase";
 
 /// An input to a transaction.
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
@@ -11,11 +22,10 @@ pub struct TxInput {
     pub txid: String,
     /// The index of the output in the transaction that this input is spending from.
     pub vout: usize,
-    /// The script that proves ownership of the output being spent.
-    /// For now, this will just be the signature.
-    pub script_sig: String,
-    /// The public key of the sender.
-    pub pub_key: String,
+    /// The script that proves ownership of the output being spent,
+    /// serialized as the `script_sig` and `pub_key` fields.
+    #[serde(flatten)]
+    pub script_sig: UnlockingScript,
     /// The sequence number. Not used in this implementation.
     pub sequence: u32,
 }
@@ -25,9 +35,83 @@ pub struct TxInput {
 pub struct TxOutput {
     /// The value of the output in the smallest unit of the currency.
     pub value: u64,
-    /// The script that locks the output.
-    /// For now, this will just be the recipient's public key hash.
-    pub script_pub_key: String,
+    /// The script that locks the output to its recipient.
+    pub script_pub_key: LockingScript,
+}
+
+/// The reasons a transaction can't be built, has inconsistent amounts, or
+/// is too big for the chain.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum TxError {
+    /// An input spends an output that isn't in the UTXO set.
+    UnknownInput { txid: String, vout: usize },
+    /// The transaction pays out more than it spends.
+    OutputsExceedInputs { inputs_total: u64, outputs_total: u64 },
+    /// The sender's spendable outputs don't cover the amount plus fee.
+    InsufficientFunds { available: u64, required: u64 },
+    /// The amount plus fee doesn't fit in a `u64`.
+    AmountTooLarge,
+    /// An input's unlocking script doesn't satisfy the output it spends.
+    InvalidSpend { txid: String, vout: usize, error: ScriptError },
+    /// The transaction has more inputs than `ChainParams` allows.
+    TooManyInputs { count: usize, max: usize },
+    /// The transaction has more outputs than `ChainParams` allows.
+    TooManyOutputs { count: usize, max: usize },
+    /// The memo is longer than `MAX_MEMO_BYTES`.
+    MemoTooLong { bytes: usize, max: usize },
+    /// The coinbase message is longer than `MAX_COINBASE_MESSAGE_BYTES`.
+    CoinbaseMessageTooLong { bytes: usize, max: usize },
+}
+
+impl fmt::Display for TxError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TxError::UnknownInput { txid, vout } => write!(f, "Input {}:{} is not an unspent output", txid, vout),
+            TxError::OutputsExceedInputs { inputs_total, outputs_total } => write!(
+                f,
+                "Outputs total {} exceeds inputs total {}",
+                outputs_total, inputs_total
+            ),
+            TxError::InsufficientFunds { available, required } => {
+                write!(f, "Not enough funds: {} available, {} required", available, required)
+            }
+            TxError::AmountTooLarge => write!(f, "Amount plus fee exceeds the maximum supply"),
+            TxError::InvalidSpend { txid, vout, error } => write!(f, "Input {}:{} can't be spent: {}", txid, vout, error),
+            TxError::TooManyInputs { count, max } => write!(f, "Transaction has {} inputs, more than the limit of {}", count, max),
+            TxError::TooManyOutputs { count, max } => write!(f, "Transaction has {} outputs, more than the limit of {}", count, max),
+            TxError::MemoTooLong { bytes, max } => write!(f, "Memo is {} bytes, more than the limit of {}", bytes, max),
+            TxError::CoinbaseMessageTooLong { bytes, max } => {
+                write!(f, "Coinbase message is {} bytes, more than the limit of {}", bytes, max)
+            }
+        }
+    }
+}
+
+impl std::error::Error for TxError {}
+
+/// Checks that a coinbase message fits in `MAX_COINBASE_MESSAGE_BYTES`.
+pub fn check_coinbase_message(message: &str) -> Result<(), TxError> {
+    if message.len() > MAX_COINBASE_MESSAGE_BYTES {
+        return Err(TxError::CoinbaseMessageTooLong { bytes: message.len(), max: MAX_COINBASE_MESSAGE_BYTES });
+    }
+    Ok(())
+}
+
+/// A payment one or more wallets want to make, before inputs are chosen for it.
+#[derive(Debug)]
+pub struct BuildTransactRequest {
+    /// The wallets paying, whose outputs are spent in this order. Change
+    /// goes back to the first.
+    pub wallets: Vec<Wallet>,
+    pub to: String,
+    /// The amount to send, in base units.
+    pub amount: u64,
+    /// The fee to pay the miner, on top of `amount`.
+    pub fee: u64,
+    pub expiry_height: Option<u64>,
+    /// A note to attach to the transaction.
+    pub memo: Option<String>,
 }
 
 /// A transaction.
@@ -41,89 +125,257 @@ pub struct Transaction {
     pub inputs: Vec<TxInput>,
     /// The outputs from the transaction.
     pub outputs: Vec<TxOutput>,
+    /// The height from which the transaction may no longer be mined.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expiry_height: Option<u64>,
+    /// A short note from the sender, such as `"demo"` on demo traffic.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub memo: Option<String>,
 }
 
 impl Transaction {
     /// Creates a new transaction.
     pub fn new(inputs: Vec<TxInput>, outputs: Vec<TxOutput>) -> Self {
+        Self::new_at(inputs, outputs, Utc::now().timestamp())
+    }
+
+    /// Creates a new transaction timestamped `timestamp`.
+    pub fn new_at(inputs: Vec<TxInput>, outputs: Vec<TxOutput>, timestamp: i64) -> Self {
         let mut tx = Transaction {
             id: String::new(),
-            timestamp: Utc::now().timestamp(),
+            timestamp,
             inputs,
             outputs,
+            expiry_height: None,
+            memo: None,
         };
         tx.id = tx.calculate_hash();
         tx
     }
 
-    /// Calculates the SHA-256 hash of the transaction.
+    /// Creates the coinbase of the block at `height`, paying `outputs`. Its
+    /// one input spends nothing; its script holds `COINBASE_TAG`, followed
+    /// by a colon and the miner's `message` if there is one.
+    pub fn coinbase(height: usize, outputs: Vec<TxOutput>, timestamp: i64, message: Option<&str>) -> Self {
+        let script_sig = match message.filter(|message| !message.is_empty()) {
+            Some(message) => format!("{}:{}", COINBASE_TAG, message),
+            None => COINBASE_TAG.to_string(),
+        };
+        let input = TxInput { txid: "0".repeat(64), vout: height, script_sig: UnlockingScript::Data(script_sig), sequence: 0 };
+        Self::new_at(vec![input], outputs, timestamp)
+    }
+
+    /// The message the miner put in this coinbase, if it is one and has one.
+    pub fn coinbase_message(&self) -> Option<&str> {
+        if !self.is_coinbase() {
+            return None;
+        }
+        match &self.inputs[0].script_sig {
+            UnlockingScript::Data(data) => data.strip_prefix(COINBASE_TAG)?.strip_prefix(':'),
+            UnlockingScript::SignaturePubKey { .. } => None,
+        }
+    }
+
+    /// Sets the height from which the transaction may no longer be mined.
+    /// Must be called before signing, as the expiry is part of the hash.
+    pub fn with_expiry_height(mut self, expiry_height: Option<u64>) -> Self {
+        self.expiry_height = expiry_height;
+        self.id = self.calculate_hash();
+        self
+    }
+
+    /// Attaches a memo. Like the expiry, it is part of the hash, so this must
+    /// be called before signing.
+    pub fn with_memo(mut self, memo: Option<String>) -> Self {
+        self.memo = memo;
+        self.id = self.calculate_hash();
+        self
+    }
+
+    /// Returns true if the transaction can't be mined in a block at `height`.
+    pub fn is_expired_at(&self, height: u64) -> bool {
+        self.expiry_height.is_some_and(|expiry_height| height >= expiry_height)
+    }
+
+    /// Calculates the SHA-256 hash of the transaction's canonical encoding.
     pub fn calculate_hash(&self) -> String {
         let mut tx_clone = self.clone();
         tx_clone.id = String::new(); // The id is not part of the hash calculation.
         // For signing and verification, we don't want to include the signature
-        // in the hash.
-        for input in &mut tx_clone.inputs {
-            input.script_sig = String::new();
-            input.pub_key = String::new();
+        // in the hash. A coinbase isn't signed, and its message is committed;
+        // a bare tag never was, which keeps older coinbases' IDs.
+        if self.coinbase_message().is_none() {
+            for input in &mut tx_clone.inputs {
+                input.script_sig = UnlockingScript::default();
+            }
         }
 
-        let serialized = serde_json::to_string(&tx_clone).unwrap();
         let mut hasher = Sha256::new();
-        hasher.update(serialized.as_bytes());
+        hasher.update(canonical::to_bytes(&tx_clone));
         let result = hasher.finalize();
         format!("{:x}", result)
     }
 
+    /// Returns the size of the serialized transaction in bytes, used for fee rates.
+    pub fn size(&self) -> usize {
+        serde_json::to_vec(self).unwrap().len()
+    }
+
+    /// Returns true if this is a coinbase transaction.
+    pub fn is_coinbase(&self) -> bool {
+        self.inputs.len() == 1 && self.inputs[0].txid == "0".repeat(64)
+    }
+
+    /// Checks that the transaction doesn't pay out more than the outputs it
+    /// spends from the chain's UTXO set. Coinbase transactions are exempt.
+    pub fn validate_amounts(&self, blockchain: &Blockchain) -> Result<(), TxError> {
+        self.validate_amounts_against(&blockchain.utxo_index)
+    }
+
+    /// Like `validate_amounts`, but against an arbitrary UTXO set, such as one
+    /// that already includes the earlier transactions of a block.
+    pub fn validate_amounts_against(&self, utxos: &UtxoIndex) -> Result<(), TxError> {
+        if self.is_coinbase() {
+            return Ok(());
+        }
+        let mut inputs_total = 0u64;
+        for input in &self.inputs {
+            let Some(output) = utxos.get(&(input.txid.clone(), input.vout)) else {
+                return Err(TxError::UnknownInput { txid: input.txid.clone(), vout: input.vout });
+            };
+            inputs_total = inputs_total.saturating_add(output.value);
+        }
+        let outputs_total = self.outputs.iter().fold(0u64, |total, output| total.saturating_add(output.value));
+        if outputs_total > inputs_total {
+            return Err(TxError::OutputsExceedInputs { inputs_total, outputs_total });
+        }
+        Ok(())
+    }
+
     /// Signs the transaction with the provided wallet.
     /// This is a simplified signing method that assumes the wallet owns all inputs.
     pub fn sign(&mut self, wallet: &Wallet) {
-        let tx_hash = self.calculate_hash();
-        let signature = wallet.sign(tx_hash.as_bytes());
+        let signers = vec![wallet; self.inputs.len()];
+        self.sign_inputs(&signers);
+    }
 
-        for input in &mut self.inputs {
-            input.script_sig = hex::encode(signature.to_bytes());
-            input.pub_key = hex::encode(wallet.get_public_key().as_bytes());
+    /// Signs each input with the wallet at the same position in `signers`,
+    /// which should own the output the input spends.
+    pub fn sign_inputs(&mut self, signers: &[&Wallet]) {
+        assert_eq!(signers.len(), self.inputs.len(), "every input needs a signer");
+        let tx_hash = self.calculate_hash();
+        for (input, wallet) in self.inputs.iter_mut().zip(signers) {
+            input.script_sig = UnlockingScript::SignaturePubKey {
+                sig: hex::encode(wallet.sign(tx_hash.as_bytes()).to_bytes()),
+                pub_key: hex::encode(wallet.get_public_key().as_bytes()),
+            };
         }
     }
 
-    /// Verifies the transaction's signatures.
-    /// This is a simplified verification method.
-    pub fn verify(&self) -> bool {
+    /// Verifies that every input is signed by the owner of the output it
+    /// spends, whose locking script `locking_script` looks up. Coinbase
+    /// transactions spend nothing and always pass.
+    pub fn verify<'a>(&self, locking_script: impl Fn(&TxInput) -> Option<&'a LockingScript>) -> Result<(), TxError> {
+        self.verify_detailed(locking_script).into_iter().find(Result::is_err).unwrap_or(Ok(()))
+    }
+
+    /// Like `verify`, but checks every input and returns each one's result.
+    pub fn verify_detailed<'a>(
+        &self,
+        locking_script: impl Fn(&TxInput) -> Option<&'a LockingScript>,
+    ) -> Vec<Result<(), TxError>> {
+        if self.is_coinbase() {
+            return vec![Ok(()); self.inputs.len()];
+        }
         let tx_hash = self.calculate_hash();
-        for input in &self.inputs {
-            // Coinbase transactions have no real signature to verify
-            if input.txid == "0".repeat(64) {
-                continue;
-            }
+        self.inputs
+            .iter()
+            .map(|input| {
+                let Some(locking) = locking_script(input) else {
+                    return Err(TxError::UnknownInput { txid: input.txid.clone(), vout: input.vout });
+                };
+                verify_spend(locking, &input.script_sig, tx_hash.as_bytes()).map_err(|error| TxError::InvalidSpend {
+                    txid: input.txid.clone(),
+                    vout: input.vout,
+                    error,
+                })
+            })
+            .collect()
+    }
 
-            let signature_bytes = match hex::decode(&input.script_sig) {
-                Ok(bytes) => bytes,
-                Err(_) => return false,
-            };
-            let signature_array: [u8; 64] = match signature_bytes.try_into() {
-                Ok(arr) => arr,
-                Err(_) => return false,
-            };
-            let signature = Signature::from_bytes(&signature_array);
+    /// Encodes the transaction with bincode, for clients that prefer a
+    /// compact binary form to JSON.
+    pub fn to_bincode(&self) -> Vec<u8> {
+        bincode::serialize(&WireTransaction::from(self.clone())).unwrap()
+    }
 
-            let pub_key_bytes = match hex::decode(&input.pub_key) {
-                Ok(bytes) => bytes,
-                Err(_) => return false,
-            };
-            let pub_key_array: [u8; 32] = match pub_key_bytes.try_into() {
-                Ok(arr) => arr,
-                Err(_) => return false,
-            };
-            let verifying_key = match VerifyingKey::from_bytes(&pub_key_array) {
-                Ok(key) => key,
-                Err(_) => return false,
-            };
+    /// Decodes a transaction encoded by `to_bincode`.
+    pub fn from_bincode(bytes: &[u8]) -> Result<Self, String> {
+        bincode::deserialize::<WireTransaction>(bytes).map(Transaction::from).map_err(|e| e.to_string())
+    }
+}
 
-            if verifying_key.verify(tx_hash.as_bytes(), &signature).is_err() {
-                return false;
-            }
+/// The bincode layout of a transaction. Bincode can't encode the flattened
+/// and optional fields of the JSON form, so every field is spelled out.
+#[derive(Serialize, Deserialize)]
+struct WireTransaction {
+    id: String,
+    timestamp: i64,
+    inputs: Vec<WireInput>,
+    outputs: Vec<(u64, String)>,
+    expiry_height: Option<u64>,
+    memo: Option<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct WireInput {
+    txid: String,
+    vout: usize,
+    script_sig: RawUnlockingScript,
+    sequence: u32,
+}
+
+impl From<Transaction> for WireTransaction {
+    fn from(tx: Transaction) -> Self {
+        WireTransaction {
+            id: tx.id,
+            timestamp: tx.timestamp,
+            inputs: tx
+                .inputs
+                .into_iter()
+                .map(|input| WireInput {
+                    txid: input.txid,
+                    vout: input.vout,
+                    script_sig: input.script_sig.into(),
+                    sequence: input.sequence,
+                })
+                .collect(),
+            outputs: tx.outputs.into_iter().map(|output| (output.value, output.script_pub_key.into())).collect(),
+            expiry_height: tx.expiry_height,
+            memo: tx.memo,
+        }
+    }
+}
+
+impl From<WireTransaction> for Transaction {
+    fn from(wire: WireTransaction) -> Self {
+        Transaction {
+            id: wire.id,
+            timestamp: wire.timestamp,
+            inputs: wire
+                .inputs
+                .into_iter()
+                .map(|input| TxInput {
+                    txid: input.txid,
+                    vout: input.vout,
+                    script_sig: input.script_sig.into(),
+                    sequence: input.sequence,
+                })
+                .collect(),
+            outputs: wire.outputs.into_iter().map(|(value, address)| TxOutput { value, script_pub_key: address.into() }).collect(),
+            expiry_height: wire.expiry_height,
+            memo: wire.memo,
         }
-        true
     }
 }
 
@@ -134,22 +386,115 @@ mod tests {
 
     #[test]
     fn test_sign_and_verify_transaction() {
-        let wallet = Wallet::new();
+        let owner = Wallet::new();
+        let funding = TxOutput { value: 10, script_pub_key: owner.get_address().into() };
+        let spend = || {
+            Transaction::new(
+                vec![TxInput { txid: "funding".to_string(), vout: 0, script_sig: UnlockingScript::default(), sequence: 0 }],
+                vec![TxOutput { value: 10, script_pub_key: "someone".into() }],
+            )
+        };
+        let locking_script = |input: &TxInput| (input.txid == "funding" && input.vout == 0).then_some(&funding.script_pub_key);
+
+        let mut tx = spend();
+        assert!(matches!(tx.verify(locking_script), Err(TxError::InvalidSpend { error: ScriptError::MissingSignature, .. })));
+        tx.sign(&owner);
+        assert_eq!(tx.verify(locking_script), Ok(()));
+
+        // Signing with someone else's key no longer passes for the owner's output.
+        let mut stolen = spend();
+        stolen.sign(&Wallet::new());
+        assert!(matches!(stolen.verify(locking_script), Err(TxError::InvalidSpend { error: ScriptError::WrongKey, .. })));
+
+        let mut unknown = spend();
+        unknown.inputs[0].txid = "elsewhere".to_string();
+        unknown.sign(&owner);
+        assert!(matches!(unknown.verify(locking_script), Err(TxError::UnknownInput { .. })));
+    }
+
+    #[test]
+    fn test_legacy_transaction_keeps_its_id() {
+        let owner = Wallet::new();
         let mut tx = Transaction::new(
-            vec![TxInput {
-                txid: "0".repeat(64),
-                vout: 0,
-                script_sig: String::new(),
-                pub_key: String::new(),
-                sequence: 0,
-            }],
-            vec![TxOutput {
-                value: 10,
-                script_pub_key: wallet.get_address(),
-            }],
+            vec![TxInput { txid: "funding".to_string(), vout: 1, script_sig: UnlockingScript::default(), sequence: 0 }],
+            vec![TxOutput { value: 10, script_pub_key: "someone".into() }],
         );
+        tx.sign(&owner);
+        let UnlockingScript::SignaturePubKey { sig, pub_key } = tx.inputs[0].script_sig.clone() else { unreachable!() };
+
+        // A transaction as saved before scripts were typed.
+        let legacy = serde_json::json!({
+            "id": tx.id,
+            "timestamp": tx.timestamp,
+            "inputs": [{ "txid": "funding", "vout": 1, "script_sig": sig, "pub_key": pub_key, "sequence": 0 }],
+            "outputs": [{ "value": 10, "script_pub_key": "someone" }],
+        });
+        let parsed: Transaction = serde_json::from_value(legacy.clone()).unwrap();
+        assert_eq!(parsed, tx);
+        assert_eq!(parsed.calculate_hash(), tx.id);
+        assert_eq!(serde_json::to_value(&parsed).unwrap(), legacy);
+    }
 
-        tx.sign(&wallet);
-        assert!(tx.verify());
+    #[test]
+    fn test_bincode_roundtrip() {
+        let mut tx = Transaction::new(
+            vec![TxInput { txid: "funding".to_string(), vout: 1, script_sig: UnlockingScript::default(), sequence: 3 }],
+            vec![TxOutput { value: 10, script_pub_key: "someone".into() }],
+        )
+        .with_expiry_height(Some(42))
+        .with_memo(Some("demo".to_string()));
+        tx.sign(&Wallet::new());
+        assert_eq!(Transaction::from_bincode(&tx.to_bincode()).unwrap(), tx);
+        assert!(Transaction::from_bincode(b"garbage").is_err());
+    }
+
+    #[test]
+    fn test_coinbase_message_is_committed() {
+        let outputs = vec![TxOutput { value: 10, script_pub_key: "miner".into() }];
+        let bare = Transaction::coinbase(3, outputs.clone(), 0, None);
+        assert_eq!(bare.inputs[0].script_sig, UnlockingScript::Data(COINBASE_TAG.to_string()));
+        assert_eq!(bare.coinbase_message(), None);
+        assert_eq!(Transaction::coinbase(3, outputs.clone(), 0, Some("")), bare);
+
+        let signed = Transaction::coinbase(3, outputs.clone(), 0, Some("gm: from the pool"));
+        assert_eq!(signed.coinbase_message(), Some("gm: from the pool"));
+        assert_ne!(signed.id, bare.id);
+        let mut altered = signed.clone();
+        altered.inputs[0].script_sig = UnlockingScript::Data("coinbase:gn".to_string());
+        assert_ne!(altered.calculate_hash(), signed.id);
+        assert_eq!(Transaction::from_bincode(&signed.to_bincode()).unwrap(), signed);
+
+        assert!(check_coinbase_message(&"x".repeat(MAX_COINBASE_MESSAGE_BYTES)).is_ok());
+        assert_eq!(
+            check_coinbase_message(&"x".repeat(MAX_COINBASE_MESSAGE_BYTES + 1)),
+            Err(TxError::CoinbaseMessageTooLong { bytes: MAX_COINBASE_MESSAGE_BYTES + 1, max: MAX_COINBASE_MESSAGE_BYTES })
+        );
+    }
+
+    #[test]
+    fn test_outputs_exceeding_inputs_are_rejected() {
+        let blockchain = Blockchain::new(1);
+        let genesis_coinbase = &blockchain.chain[0].transactions[0];
+        let spend = |value| {
+            Transaction::new(
+                vec![TxInput {
+                    txid: genesis_coinbase.id.clone(),
+                    vout: 0,
+                    script_sig: UnlockingScript::default(),
+                    sequence: 0,
+                }],
+                vec![TxOutput { value, script_pub_key: "someone".into() }],
+            )
+        };
+        let available = genesis_coinbase.outputs[0].value;
+
+        assert_eq!(spend(available).validate_amounts(&blockchain), Ok(()));
+        assert_eq!(
+            spend(available + 1).validate_amounts(&blockchain),
+            Err(TxError::OutputsExceedInputs { inputs_total: available, outputs_total: available + 1 })
+        );
+        let from_nothing = Transaction::new(vec![], vec![TxOutput { value: 1, script_pub_key: "someone".into() }]);
+        assert!(from_nothing.validate_amounts(&blockchain).is_err());
+        assert!(genesis_coinbase.validate_amounts(&blockchain).is_ok());
     }
 }
@@ -6,7 +6,7 @@ const VERSION: u8 = 0x00;
 const CHECKSUM_LEN: usize = 4;
 
 /// A wallet that holds a signing key.
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Wallet {
     pub signing_key: SigningKey,
 }
@@ -19,6 +19,12 @@ impl Wallet {
         Wallet { signing_key }
     }
 
+    /// Creates a `Wallet` whose signing key is `seed`, for keys that must be
+    /// the same on every run, such as those signing test vectors.
+    pub fn from_seed(seed: [u8; 32]) -> Self {
+        Wallet { signing_key: SigningKey::from_bytes(&seed) }
+    }
+
     /// Returns the wallet's public key (verifying key).
     pub fn get_public_key(&self) -> VerifyingKey {
         self.signing_key.verifying_key()
@@ -38,7 +44,12 @@ impl Wallet {
     /// 4. Append checksum to the version-prefixed hash
     /// 5. Base58 encode the result
     pub fn get_address(&self) -> String {
-        let pub_key_hash = self.hash_pub_key();
+        Self::address_of(&self.get_public_key())
+    }
+
+    /// Returns the address belonging to a public key, as `get_address` does.
+    pub fn address_of(public_key: &VerifyingKey) -> String {
+        let pub_key_hash = Self::hash_pub_key(public_key);
         let mut versioned_payload = vec![VERSION];
         versioned_payload.extend_from_slice(&pub_key_hash);
 
@@ -49,10 +60,23 @@ impl Wallet {
         bs58::encode(full_payload).into_string()
     }
 
+    /// Returns true if `address` has the form `address_of` gives: a version
+    /// byte, a public key hash and a matching checksum, Base58 encoded.
+    pub fn is_valid_address(address: &str) -> bool {
+        let Ok(payload) = bs58::decode(address).into_vec() else {
+            return false;
+        };
+        let Some(split) = payload.len().checked_sub(CHECKSUM_LEN) else {
+            return false;
+        };
+        let (versioned_payload, checksum) = payload.split_at(split);
+        versioned_payload.len() == 1 + 32 && versioned_payload[0] == VERSION && Self::checksum(versioned_payload) == checksum
+    }
+
     /// Hashes the public key using SHA-256.
-    fn hash_pub_key(&self) -> Vec<u8> {
+    fn hash_pub_key(public_key: &VerifyingKey) -> Vec<u8> {
         let mut hasher = Sha256::new();
-        hasher.update(self.get_public_key().as_bytes());
+        hasher.update(public_key.as_bytes());
         hasher.finalize().to_vec()
     }
 
@@ -63,7 +87,7 @@ impl Wallet {
         let first_hash = first_hasher.finalize();
 
         let mut second_hasher = Sha256::new();
-        second_hasher.update(&first_hash);
+        second_hasher.update(first_hash);
         let second_hash = second_hasher.finalize();
 
         second_hash[0..CHECKSUM_LEN].to_vec()
@@ -87,6 +111,18 @@ mod tests {
         assert!(wallet.get_address().starts_with("1"));
     }
 
+    #[test]
+    fn test_address_validation() {
+        let address = Wallet::new().get_address();
+        assert!(Wallet::is_valid_address(&address));
+        let mut tampered = address.clone().into_bytes();
+        let last = tampered.len() - 1;
+        tampered[last] = if tampered[last] == b'2' { b'3' } else { b'2' };
+        assert!(!Wallet::is_valid_address(std::str::from_utf8(&tampered).unwrap()));
+        assert!(!Wallet::is_valid_address("genesis_address"));
+        assert!(!Wallet::is_valid_address(""));
+    }
+
     #[test]
     fn test_wallet_signing() {
         let wallet = Wallet::new();
@@ -0,0 +1,257 @@
+use chacha20poly1305::aead::{Aead, KeyInit};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use clap::ValueEnum;
+use hmac::{Hmac, Mac};
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fmt;
+
+use crate::api::handlers::wallet_from_private_key;
+use crate::core::keystore::{KeyStore, StoredWallet};
+
+/// The backup format this node writes. Backups of any later version are
+/// refused, since they may hold data this node would silently drop.
+pub const BACKUP_VERSION: u32 = 1;
+
+/// PBKDF2-HMAC-SHA256 rounds used to derive the encryption key from the
+/// passphrase.
+pub const KDF_ITERATIONS: u32 = 100_000;
+
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 12;
+
+/// What a backup holds once decrypted.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct BackupContents {
+    pub wallets: Vec<StoredWallet>,
+}
+
+/// An encrypted backup of the node's wallets, as returned by
+/// `GET /wallet/backup`. The contents are encrypted with ChaCha20-Poly1305
+/// under a key derived from the passphrase, and the checksum covers every
+/// other field, so a corrupted backup can be told apart from a wrong
+/// passphrase.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct WalletBackup {
+    pub version: u32,
+    pub kdf_iterations: u32,
+    /// Hex-encoded.
+    pub salt: String,
+    /// Hex-encoded.
+    pub nonce: String,
+    /// The encrypted JSON `BackupContents`, hex-encoded.
+    pub ciphertext: String,
+    /// The hex SHA-256 of the other fields.
+    pub checksum: String,
+}
+
+/// How a restore treats the wallets already in the store.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum RestoreMode {
+    /// Adds the backed up wallets that aren't in the store yet.
+    #[default]
+    Merge,
+    /// Replaces the store's wallets with the backed up ones.
+    Replace,
+}
+
+/// Why a backup couldn't be opened.
+#[derive(Debug, Clone, PartialEq)]
+pub enum BackupError {
+    Malformed(String),
+    UnsupportedVersion(u32),
+    ChecksumMismatch,
+    WrongPassphrase,
+}
+
+impl fmt::Display for BackupError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BackupError::Malformed(reason) => write!(f, "Malformed backup: {}", reason),
+            BackupError::UnsupportedVersion(version) => {
+                write!(f, "Backup version {} is not supported; this node reads version {}", version, BACKUP_VERSION)
+            }
+            BackupError::ChecksumMismatch => write!(f, "The backup's checksum does not match; it may be corrupted"),
+            BackupError::WrongPassphrase => write!(f, "Wrong passphrase"),
+        }
+    }
+}
+
+impl WalletBackup {
+    /// Encrypts `contents` under `passphrase`.
+    pub fn seal(contents: &BackupContents, passphrase: &str) -> Self {
+        let mut salt = [0u8; SALT_LEN];
+        let mut nonce = [0u8; NONCE_LEN];
+        rand::thread_rng().fill_bytes(&mut salt);
+        rand::thread_rng().fill_bytes(&mut nonce);
+        let cipher = ChaCha20Poly1305::new(&derive_key(passphrase, &salt, KDF_ITERATIONS));
+        let plaintext = serde_json::to_vec(contents).unwrap();
+        let ciphertext = cipher.encrypt(Nonce::from_slice(&nonce), plaintext.as_slice()).expect("encryption failed");
+        let mut backup = Self {
+            version: BACKUP_VERSION,
+            kdf_iterations: KDF_ITERATIONS,
+            salt: hex::encode(salt),
+            nonce: hex::encode(nonce),
+            ciphertext: hex::encode(ciphertext),
+            checksum: String::new(),
+        };
+        backup.checksum = backup.expected_checksum();
+        backup
+    }
+
+    /// Reads a backup document, checking its version before anything else
+    /// so that a later format is refused cleanly rather than misread.
+    pub fn parse(document: serde_json::Value) -> Result<Self, BackupError> {
+        let version = document
+            .get("version")
+            .and_then(serde_json::Value::as_u64)
+            .ok_or_else(|| BackupError::Malformed("missing version".to_string()))?;
+        if version != BACKUP_VERSION as u64 {
+            return Err(BackupError::UnsupportedVersion(version.try_into().unwrap_or(u32::MAX)));
+        }
+        let backup: Self = serde_json::from_value(document).map_err(|e| BackupError::Malformed(e.to_string()))?;
+        if backup.checksum != backup.expected_checksum() {
+            return Err(BackupError::ChecksumMismatch);
+        }
+        Ok(backup)
+    }
+
+    /// Decrypts the backup, checking every wallet in it is usable.
+    pub fn open(&self, passphrase: &str) -> Result<BackupContents, BackupError> {
+        let decode = |field: &str, value: &str| {
+            hex::decode(value).map_err(|_| BackupError::Malformed(format!("{} is not hex", field)))
+        };
+        let salt = decode("salt", &self.salt)?;
+        let nonce = decode("nonce", &self.nonce)?;
+        let ciphertext = decode("ciphertext", &self.ciphertext)?;
+        if nonce.len() != NONCE_LEN {
+            return Err(BackupError::Malformed("nonce has the wrong length".to_string()));
+        }
+        // Don't let a doctored backup tie the node up deriving keys.
+        if self.kdf_iterations == 0 || self.kdf_iterations > KDF_ITERATIONS * 10 {
+            return Err(BackupError::Malformed(format!("{} key derivation rounds", self.kdf_iterations)));
+        }
+        let cipher = ChaCha20Poly1305::new(&derive_key(passphrase, &salt, self.kdf_iterations));
+        let plaintext = cipher
+            .decrypt(Nonce::from_slice(&nonce), ciphertext.as_slice())
+            .map_err(|_| BackupError::WrongPassphrase)?;
+        let contents: BackupContents =
+            serde_json::from_slice(&plaintext).map_err(|e| BackupError::Malformed(e.to_string()))?;
+        for wallet in &contents.wallets {
+            let key = wallet_from_private_key(&wallet.private_key).map_err(BackupError::Malformed)?;
+            if key.get_address() != wallet.address {
+                return Err(BackupError::Malformed(format!("key does not match address {}", wallet.address)));
+            }
+        }
+        Ok(contents)
+    }
+
+    fn expected_checksum(&self) -> String {
+        let mut hasher = Sha256::new();
+        hasher.update(
+            format!("{}:{}:{}:{}:{}", self.version, self.kdf_iterations, self.salt, self.nonce, self.ciphertext).as_bytes(),
+        );
+        hex::encode(hasher.finalize())
+    }
+}
+
+/// Backs up every wallet in `store` under `passphrase`.
+pub fn backup(store: &KeyStore, passphrase: &str) -> WalletBackup {
+    WalletBackup::seal(&BackupContents { wallets: store.wallets().to_vec() }, passphrase)
+}
+
+/// Opens `document` with `passphrase` and restores its wallets into `store`
+/// without saving it. Returns how many wallets were added.
+pub fn restore(
+    store: &mut KeyStore,
+    document: serde_json::Value,
+    passphrase: &str,
+    mode: RestoreMode,
+) -> Result<usize, BackupError> {
+    let contents = WalletBackup::parse(document)?.open(passphrase)?;
+    Ok(match mode {
+        RestoreMode::Merge => store.merge(contents.wallets),
+        RestoreMode::Replace => {
+            let restored = contents.wallets.len();
+            store.replace(contents.wallets);
+            restored
+        }
+    })
+}
+
+/// PBKDF2-HMAC-SHA256 with a single 32-byte output block.
+fn derive_key(passphrase: &str, salt: &[u8], iterations: u32) -> Key {
+    let prf = <Hmac<Sha256> as Mac>::new_from_slice(passphrase.as_bytes()).expect("HMAC takes keys of any length");
+    let mut block = prf.clone().chain_update(salt).chain_update(1u32.to_be_bytes()).finalize().into_bytes();
+    let mut key = block;
+    for _ in 1..iterations {
+        block = prf.clone().chain_update(block).finalize().into_bytes();
+        key.iter_mut().zip(block.iter()).for_each(|(k, b)| *k ^= b);
+    }
+    Key::clone_from_slice(&key)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::wallet::Wallet;
+
+    fn store_with(count: usize) -> KeyStore {
+        let path = std::env::temp_dir().join(format!("sierpchain-wallets-{}.json", rand::random::<u64>()));
+        let mut store = KeyStore::load(path).unwrap();
+        store.merge((0..count).map(|_| StoredWallet::from(&Wallet::new())).collect());
+        store
+    }
+
+    fn document(backup: &WalletBackup) -> serde_json::Value {
+        serde_json::to_value(backup).unwrap()
+    }
+
+    #[test]
+    fn test_backup_round_trips_and_merges_or_replaces() {
+        let original = store_with(2);
+        let sealed = backup(&original, "correct horse");
+        assert!(!sealed.ciphertext.contains(&original.wallets()[0].private_key));
+
+        let mut other = store_with(1);
+        assert_eq!(restore(&mut other, document(&sealed), "correct horse", RestoreMode::Merge), Ok(2));
+        assert_eq!(other.wallets().len(), 3);
+        assert_eq!(other.wallets()[1..], original.wallets()[..]);
+        // Merging again adds nothing.
+        assert_eq!(restore(&mut other, document(&sealed), "correct horse", RestoreMode::Merge), Ok(0));
+
+        assert_eq!(restore(&mut other, document(&sealed), "correct horse", RestoreMode::Replace), Ok(2));
+        assert_eq!(other.wallets(), original.wallets());
+    }
+
+    #[test]
+    fn test_wrong_passphrase_and_corruption_are_told_apart() {
+        let original = store_with(1);
+        let sealed = backup(&original, "correct horse");
+        let mut store = store_with(0);
+        assert_eq!(restore(&mut store, document(&sealed), "battery staple", RestoreMode::Merge), Err(BackupError::WrongPassphrase));
+
+        let mut corrupted = sealed.clone();
+        corrupted.ciphertext.replace_range(0..2, if &sealed.ciphertext[0..2] == "00" { "01" } else { "00" });
+        assert_eq!(restore(&mut store, document(&corrupted), "correct horse", RestoreMode::Merge), Err(BackupError::ChecksumMismatch));
+        assert!(store.wallets().is_empty());
+    }
+
+    #[test]
+    fn test_future_versions_are_refused() {
+        let sealed = backup(&store_with(1), "correct horse");
+        let mut future = document(&sealed);
+        future["version"] = serde_json::json!(BACKUP_VERSION + 1);
+        // A later format may rename or drop fields entirely.
+        future.as_object_mut().unwrap().remove("ciphertext");
+        future["wallets_v2"] = serde_json::json!({ "sealed": "..." });
+        let mut store = store_with(0);
+        assert_eq!(
+            restore(&mut store, future, "correct horse", RestoreMode::Merge),
+            Err(BackupError::UnsupportedVersion(BACKUP_VERSION + 1))
+        );
+        assert!(matches!(WalletBackup::parse(serde_json::json!({})), Err(BackupError::Malformed(_))));
+    }
+}
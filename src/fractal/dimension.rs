@@ -0,0 +1,115 @@
+use std::collections::HashSet;
+
+use super::FractalData;
+
+/// The most times the box grid is halved. Each level is one point of the
+/// log-log fit.
+pub const MAX_LEVELS: u32 = 6;
+
+/// Estimates a fractal's box-counting dimension.
+///
+/// Sierpinski triangles are measured on their vertices, escape-time fractals
+/// on the boundary of the set: the pixels that reached `max_iterations` next
+/// to one that didn't. Grids are refined no further than the fractal's own
+/// resolution, the triangles of its last subdivision or single pixels, since
+/// finer boxes would only count the same points again. `None` if there are
+/// fewer than two levels to fit, or nothing to measure.
+pub fn dimension(fractal: &FractalData) -> Option<f64> {
+    match fractal {
+        FractalData::Sierpinski(s) => {
+            box_counting_dimension(s.vertices(), (s.depth as u32).min(MAX_LEVELS))
+        }
+        FractalData::Mandelbrot(m) => {
+            let boundary = boundary_pixels(&m.data, m.width, m.height, m.max_iterations);
+            box_counting_dimension(&boundary, pixel_levels(m.width, m.height))
+        }
+        FractalData::Julia(j) => {
+            let boundary = boundary_pixels(&j.data, j.width, j.height, j.max_iterations);
+            box_counting_dimension(&boundary, pixel_levels(j.width, j.height))
+        }
+    }
+}
+
+/// How many times a grid over a `width` by `height` image can be halved
+/// before its boxes are smaller than a pixel.
+fn pixel_levels(width: usize, height: usize) -> u32 {
+    width.max(height).checked_ilog2().unwrap_or(0).min(MAX_LEVELS)
+}
+
+/// The centres of the pixels in the set with a neighbour outside it.
+fn boundary_pixels(data: &[u32], width: usize, height: usize, max_iterations: u32) -> Vec<(f64, f64)> {
+    let inside = |x: usize, y: usize| data.get(y * width + x) == Some(&max_iterations);
+    let mut boundary = Vec::new();
+    for y in 0..height {
+        for x in 0..width {
+            if !inside(x, y) {
+                continue;
+            }
+            let neighbours = [
+                x.checked_sub(1).map(|x| (x, y)),
+                (x + 1 < width).then_some((x + 1, y)),
+                y.checked_sub(1).map(|y| (x, y)),
+                (y + 1 < height).then_some((x, y + 1)),
+            ];
+            if neighbours.into_iter().flatten().any(|(x, y)| !inside(x, y)) {
+                boundary.push((x as f64 + 0.5, y as f64 + 0.5));
+            }
+        }
+    }
+    boundary
+}
+
+/// Covers `points` with grids of 2, 4, ... 2^`levels` boxes a side over
+/// their bounding square, and returns the slope of log(occupied boxes)
+/// against log(boxes a side).
+pub fn box_counting_dimension(points: &[(f64, f64)], levels: u32) -> Option<f64> {
+    let (min_x, max_x) = points.iter().map(|p| p.0).fold((f64::INFINITY, f64::NEG_INFINITY), |(lo, hi), x| (lo.min(x), hi.max(x)));
+    let (min_y, max_y) = points.iter().map(|p| p.1).fold((f64::INFINITY, f64::NEG_INFINITY), |(lo, hi), y| (lo.min(y), hi.max(y)));
+    let side = (max_x - min_x).max(max_y - min_y);
+    if levels < 2 || side <= 0.0 {
+        return None;
+    }
+
+    let samples: Vec<(f64, f64)> = (1..=levels)
+        .map(|level| {
+            let boxes = 1usize << level;
+            let cell = |value: f64, min: f64| (((value - min) / side * boxes as f64) as usize).min(boxes - 1);
+            let occupied: HashSet<(usize, usize)> = points.iter().map(|&(x, y)| (cell(x, min_x), cell(y, min_y))).collect();
+            ((boxes as f64).ln(), (occupied.len() as f64).ln())
+        })
+        .collect();
+
+    // Least-squares slope of the samples.
+    let n = samples.len() as f64;
+    let mean_x = samples.iter().map(|s| s.0).sum::<f64>() / n;
+    let mean_y = samples.iter().map(|s| s.1).sum::<f64>() / n;
+    let covariance: f64 = samples.iter().map(|s| (s.0 - mean_x) * (s.1 - mean_y)).sum();
+    let variance: f64 = samples.iter().map(|s| (s.0 - mean_x).powi(2)).sum();
+    Some(covariance / variance)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fractal::FractalType;
+
+    #[test]
+    fn test_sierpinski_dimension_is_near_log3_over_log2() {
+        let expected = 3f64.ln() / 2f64.ln();
+        for seed in [0, 42] {
+            let fractal = FractalType::Sierpinski { depth: 6, seed }.generate();
+            let measured = dimension(&fractal).unwrap();
+            assert!((measured - expected).abs() < 0.1, "seed {}: {} is not near {}", seed, measured, expected);
+        }
+    }
+
+    #[test]
+    fn test_lines_and_squares_have_whole_dimensions() {
+        let line: Vec<(f64, f64)> = (0..=1000).map(|i| (i as f64 / 1000.0, 0.5)).collect();
+        assert!((box_counting_dimension(&line, 5).unwrap() - 1.0).abs() < 0.01);
+        let square: Vec<(f64, f64)> = (0..=100).flat_map(|i| (0..=100).map(move |j| (i as f64 / 100.0, j as f64 / 100.0))).collect();
+        assert!((box_counting_dimension(&square, 5).unwrap() - 2.0).abs() < 0.01);
+        assert_eq!(box_counting_dimension(&line, 1), None);
+        assert_eq!(box_counting_dimension(&[(0.5, 0.5)], 5), None);
+    }
+}
@@ -1,100 +1,8 @@
-use serde::{Serialize, Deserialize};
-pub mod sierpinski;
-pub mod mandelbrot;
-pub mod julia;
-pub mod utils;
+//! The fractals themselves live in `sierpchain-shared`, so that the
+//! frontend generates exactly the ones the node does; rendering and
+//! measuring them is the node's own.
 
-use self::sierpinski::Sierpinski;
-use self::mandelbrot::Mandelbrot;
-use self::julia::Julia;
+pub use sierpchain_shared::fractal::*;
 
-/// An enum to hold the data for different fractal types.
-/// This will be stored in the block.
-#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
-#[serde(tag = "type", content = "data")]
-pub enum FractalData {
-    Sierpinski(Sierpinski),
-    Mandelbrot(Mandelbrot),
-    Julia(Julia),
-}
-
-/// An enum to represent the different types of fractals that can be generated.
-/// This will be used in the mining request.
-#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
-pub enum FractalType {
-    Sierpinski { depth: usize, seed: u64 },
-    Mandelbrot {
-        width: usize,
-        height: usize,
-        x_min: f64,
-        x_max: f64,
-        y_min: f64,
-        y_max: f64,
-        max_iterations: u32,
-        seed: u64,
-    },
-    Julia {
-        width: usize,
-        height: usize,
-        x_min: f64,
-        x_max: f64,
-        y_min: f64,
-        y_max: f64,
-        c_real: f64,
-        c_imag: f64,
-        max_iterations: u32,
-        seed: u64,
-    },
-}
-
-impl FractalType {
-    pub fn generate(&self) -> FractalData {
-        match self {
-            FractalType::Sierpinski { depth, seed } => {
-                FractalData::Sierpinski(Sierpinski::generate(*depth, *seed))
-            }
-            FractalType::Mandelbrot {
-                width,
-                height,
-                x_min,
-                x_max,
-                y_min,
-                y_max,
-                max_iterations,
-                seed,
-            } => FractalData::Mandelbrot(Mandelbrot::generate(
-                *width,
-                *height,
-                *x_min,
-                *x_max,
-                *y_min,
-                *y_max,
-                *max_iterations,
-                *seed,
-            )),
-            FractalType::Julia {
-                width,
-                height,
-                x_min,
-                x_max,
-                y_min,
-                y_max,
-                c_real,
-                c_imag,
-                max_iterations,
-                seed,
-            } => FractalData::Julia(Julia::generate(
-                *width,
-                *height,
-                *x_min,
-                *x_max,
-                *y_min,
-                *y_max,
-                *c_real,
-                *c_imag,
-                *max_iterations,
-                *seed,
-            )),
-        }
-    }
-}
+pub mod dimension;
+pub mod render;
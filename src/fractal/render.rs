@@ -0,0 +1,161 @@
+use super::FractalData;
+
+/// The width and height of rendered Sierpinski thumbnails, in pixels.
+const SIERPINSKI_SIZE: usize = 128;
+/// The colour of Sierpinski triangles, matching the frontend's SVG fill.
+const SIERPINSKI_FILL: [u8; 3] = [0x1a, 0x1a, 0x2e];
+
+/// Renders a fractal as a PNG image.
+///
+/// Escape-time fractals are drawn one pixel per data point with the same
+/// palette the frontend uses; Sierpinski triangles are rasterized onto a
+/// square canvas covering the same area as the frontend's SVG view box.
+pub fn render_png(fractal: &FractalData) -> Vec<u8> {
+    let (width, height, pixels) = match fractal {
+        FractalData::Sierpinski(s) => (SIERPINSKI_SIZE, SIERPINSKI_SIZE, rasterize_triangles(s.vertices())),
+        FractalData::Mandelbrot(m) => (m.width, m.height, color_iterations(&m.data, m.max_iterations)),
+        FractalData::Julia(j) => (j.width, j.height, color_iterations(&j.data, j.max_iterations)),
+    };
+    encode_png(width as u32, height as u32, &pixels)
+}
+
+fn color_iterations(data: &[u32], max_iterations: u32) -> Vec<u8> {
+    data.iter()
+        .flat_map(|&iteration| {
+            if iteration == max_iterations {
+                [0, 0, 0]
+            } else {
+                hsl_to_rgb((iteration as f64 * 10.0) % 360.0, 1.0, 0.5)
+            }
+        })
+        .collect()
+}
+
+fn hsl_to_rgb(h: f64, s: f64, l: f64) -> [u8; 3] {
+    let c = (1.0 - (2.0 * l - 1.0).abs()) * s;
+    let x = c * (1.0 - ((h / 60.0) % 2.0 - 1.0).abs());
+    let m = l - c / 2.0;
+    let (r, g, b) = match h {
+        h if h < 60.0 => (c, x, 0.0),
+        h if h < 120.0 => (x, c, 0.0),
+        h if h < 180.0 => (0.0, c, x),
+        h if h < 240.0 => (0.0, x, c),
+        h if h < 300.0 => (x, 0.0, c),
+        _ => (c, 0.0, x),
+    };
+    [((r + m) * 255.0) as u8, ((g + m) * 255.0) as u8, ((b + m) * 255.0) as u8]
+}
+
+fn rasterize_triangles(vertices: &[(f64, f64)]) -> Vec<u8> {
+    let size = SIERPINSKI_SIZE;
+    let mut pixels = vec![0xff; size * size * 3];
+    // Maps pixel centres onto the view box (-0.1, -0.1) to (1.1, 1.1).
+    let to_world = |p: usize| -0.1 + (p as f64 + 0.5) / size as f64 * 1.2;
+    let to_pixel = |w: f64| (((w + 0.1) / 1.2 * size as f64).max(0.0) as usize).min(size - 1);
+    let edge = |a: (f64, f64), b: (f64, f64), p: (f64, f64)| (b.0 - a.0) * (p.1 - a.1) - (b.1 - a.1) * (p.0 - a.0);
+
+    for triangle in vertices.chunks_exact(3) {
+        let (a, b, c) = (triangle[0], triangle[1], triangle[2]);
+        let min_x = to_pixel(a.0.min(b.0).min(c.0));
+        let max_x = to_pixel(a.0.max(b.0).max(c.0));
+        let min_y = to_pixel(a.1.min(b.1).min(c.1));
+        let max_y = to_pixel(a.1.max(b.1).max(c.1));
+        for py in min_y..=max_y {
+            for px in min_x..=max_x {
+                let p = (to_world(px), to_world(py));
+                let (e1, e2, e3) = (edge(a, b, p), edge(b, c, p), edge(c, a, p));
+                let inside = (e1 >= 0.0 && e2 >= 0.0 && e3 >= 0.0) || (e1 <= 0.0 && e2 <= 0.0 && e3 <= 0.0);
+                if inside {
+                    let offset = (py * size + px) * 3;
+                    pixels[offset..offset + 3].copy_from_slice(&SIERPINSKI_FILL);
+                }
+            }
+        }
+    }
+    pixels
+}
+
+/// Encodes 8-bit RGB pixels as a PNG. The image data is stored rather than
+/// compressed, which keeps the encoder small; thumbnails are tiny anyway.
+fn encode_png(width: u32, height: u32, rgb: &[u8]) -> Vec<u8> {
+    let mut raw = Vec::with_capacity(rgb.len() + height as usize);
+    for row in rgb.chunks(width as usize * 3) {
+        raw.push(0); // Filter type: none.
+        raw.extend_from_slice(row);
+    }
+
+    // A zlib stream made of uncompressed deflate blocks.
+    let mut zlib = vec![0x78, 0x01];
+    let mut blocks = raw.chunks(u16::MAX as usize).peekable();
+    if blocks.peek().is_none() {
+        zlib.extend_from_slice(&[1, 0, 0, 0xff, 0xff]);
+    }
+    while let Some(block) = blocks.next() {
+        let len = block.len() as u16;
+        zlib.push(u8::from(blocks.peek().is_none()));
+        zlib.extend_from_slice(&len.to_le_bytes());
+        zlib.extend_from_slice(&(!len).to_le_bytes());
+        zlib.extend_from_slice(block);
+    }
+    zlib.extend_from_slice(&adler32(&raw).to_be_bytes());
+
+    let mut header = Vec::with_capacity(13);
+    header.extend_from_slice(&width.to_be_bytes());
+    header.extend_from_slice(&height.to_be_bytes());
+    header.extend_from_slice(&[8, 2, 0, 0, 0]); // 8-bit RGB, no interlacing.
+
+    let mut png = vec![0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1a, b'\n'];
+    write_chunk(&mut png, b"IHDR", &header);
+    write_chunk(&mut png, b"IDAT", &zlib);
+    write_chunk(&mut png, b"IEND", &[]);
+    png
+}
+
+fn write_chunk(png: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    png.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    let start = png.len();
+    png.extend_from_slice(kind);
+    png.extend_from_slice(data);
+    let crc = crc32(&png[start..]);
+    png.extend_from_slice(&crc.to_be_bytes());
+}
+
+fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = 0xffff_ffffu32;
+    for &byte in bytes {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 == 1 { (crc >> 1) ^ 0xedb8_8320 } else { crc >> 1 };
+        }
+    }
+    !crc
+}
+
+fn adler32(bytes: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+    for &byte in bytes {
+        a = (a + byte as u32) % 65521;
+        b = (b + a) % 65521;
+    }
+    (b << 16) | a
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fractal::FractalType;
+
+    #[test]
+    fn test_crc32_matches_reference() {
+        assert_eq!(crc32(b"IEND"), 0xae42_6082);
+    }
+
+    #[test]
+    fn test_render_png_header() {
+        let png = render_png(&FractalType::Sierpinski { depth: 3, seed: 0 }.generate());
+        assert_eq!(&png[..8], b"\x89PNG\r\n\x1a\n");
+        assert_eq!(&png[12..16], b"IHDR");
+        assert_eq!(u32::from_be_bytes(png[16..20].try_into().unwrap()), SIERPINSKI_SIZE as u32);
+        assert_eq!(&png[png.len() - 8..png.len() - 4], b"IEND");
+    }
+}
@@ -0,0 +1,9 @@
+// Declare the top-level modules
+pub mod api;
+pub mod blockchain;
+pub mod config;
+pub mod core;
+pub mod fractal;
+pub mod network;
+pub mod mining;
+pub mod vectors;
@@ -1,28 +1,74 @@
-// Declare the top-level modules
-mod api;
-mod blockchain;
-mod core;
-mod fractal;
-mod network;
-mod mining;
+use sierpchain::{api, blockchain, config, core, mining, network};
 
+use crate::api::faucet::{faucet_stats, FaucetConfig, FaucetLedger, SharedFaucetLedger, DEFAULT_FAUCET_AMOUNT, FAUCET_FILE};
+use crate::api::nft::{claim_nft, get_nft_owner};
+use crate::api::labels::{get_label, set_label};
+use crate::api::metrics::{count_sent_bytes, count_uncompressed_bytes, get_metrics};
+use crate::api::demo::{
+    get_demo_traffic, put_demo_traffic, DemoConfig, DemoSwitch, DemoTraffic, DEFAULT_DEMO_INTERVAL_MS, DEFAULT_DEMO_MAX_AMOUNT,
+    DEFAULT_DEMO_MIN_AMOUNT,
+};
+use crate::api::embed::{embed_latest, FrameAncestors};
+use crate::api::stream::stream_blocks;
+use crate::api::spending::{approve_payment, get_spend_policy, list_approvals, put_spend_policy, reject_payment};
+use crate::api::wallet::{backup_wallets, import_wallet, restore_wallets, sweep_wallet};
+use crate::api::admin::{
+    add_peer, clear_safe_mode, collect_garbage, invalidate_block, list_peers, reconsider_block, remove_peer, get_storage, AdminKey,
+    AllowChainOps,
+};
 use crate::api::handlers::{
-    get_blocks, get_balance, get_utxos, transact, get_wallet_info, mine, create_wallet, TransactionPool,
+    get_blocks, get_chain_graph, get_balance, get_utxos, get_address_fractals, get_block_fractal_png, get_block_dimension, verify_block,
+    get_block, get_block_transactions, get_peers, get_known_peers, connect_peer, disconnect_peer,
+    get_supply, get_status, get_mempool, get_mempool_relatives, evict_transaction, transact, transact_bump, transact_cpfp, get_wallet_info,
+    mine, get_mine_estimate, get_mining_target, get_mining_status, put_mining_config, create_wallet, longpoll_tip, track_transaction, transact_batch, decode_transaction,
+    filter_block, get_mining_template, get_transaction, submit_raw_transaction, get_raw_transaction,
+    RawFormat, RawTransaction,
+    json_config, mining_submit, LightNode, TransactionPool, DEFAULT_BLOCK_JSON_LIMIT, DEFAULT_JSON_LIMIT,
+};
+use crate::api::state::AppState;
+use crate::api::health::{
+    health_live, health_ready, probe_writable, HealthRegistry, SharedHealth, CHAIN_LOADED, INDEX_READY, P2P_RUNNING,
+    SAFE_MODE_OFF, STORAGE_WRITABLE,
+};
+use crate::api::websocket::{ApiKey, BroadcastBlock, BroadcastHub, TransactionReplaced, WsConn, WsFormat};
+use crate::blockchain::chain::{Blockchain, ChainError, Checkpoint};
+use crate::blockchain::gc::{SharedStorageGc, StorageGc, ARCHIVE_FILE, DEFAULT_MAX_REORG_DEPTH, GC_INTERVAL};
+use crate::blockchain::txindex::{SharedTxIndex, TxIndex, TX_INDEX_DIR};
+use crate::core::clock::system_clock;
+use crate::core::keystore::{KeyStore, SharedKeyStore, WALLETS_FILE};
+use crate::core::spend_policy::{SharedSpendLedger, SpendLedger, SPENDING_FILE};
+use crate::core::mempool::{
+    Admission, MempoolPool, DEFAULT_MAX_FUTURE_DRIFT_SECS, DEFAULT_MIN_RELAY_FEE_PER_BYTE, DEFAULT_MIN_REPLACEMENT_FEE_INCREMENT,
 };
-use crate::api::websocket::{BroadcastBlock, BroadcastHub, WsConn};
-use crate::blockchain::chain::Blockchain;
-use crate::core::wallet::Wallet;
-use crate::network::p2p::{P2p, P2pMessage};
+use crate::core::nft::{NftRegistry, SharedNftRegistry, NFT_REGISTRY_FILE};
+use crate::core::labels::{LabelStore, SharedLabelStore, LABELS_FILE};
+use crate::core::transaction::TxError;
+use crate::core::wallet_backup::RestoreMode;
+use crate::mining::miner::{HashRateMeter, MiningBudget, DEFAULT_MINING_TIMEOUT_MS};
+use crate::mining::policy::{AutoMiner, SharedMiningPolicy};
+use network::network_id::NetworkId;
+use network::dedup::{SeenCache, SeenMessages};
+use network::header_sync;
+use network::mempool_sync;
+use network::p2p::{InboundMessage, P2p, P2pConfig, P2pControl, P2pMessage, PeerStatsMap, DEFAULT_MAX_MESSAGE_SIZE};
+use network::peer_store::{PeerStore, SharedPeerStore, INVALID_BLOCK_PENALTY, PEERS_FILE};
+use network::reorg_guard::{self, ChainOffer};
+use network::rpc::{Replica, RpcClient, RpcNode};
 
-use actix::{Actor, Addr};
+use actix::Actor;
 use actix_cors::Cors;
+use actix_web::middleware::{from_fn, Compress};
 use actix_web::{web, App, HttpRequest, HttpResponse, HttpServer, Error};
 use actix_web_actors::ws;
-use clap::Parser;
+use clap::{Parser, Subcommand};
 use dotenv::dotenv;
+use libp2p::gossipsub::MessageAcceptance;
 use libp2p::Multiaddr;
 use once_cell::sync::Lazy;
+use serde::Deserialize;
+use std::collections::HashMap;
 use std::env;
+use std::path::Path;
 use std::sync::{Arc, Mutex};
 use tokio::sync::mpsc;
 use tokio::time::{self, Duration};
@@ -42,19 +88,265 @@ struct Cli {
     p2p_port: u16,
     #[arg(long)]
     peer: Vec<Multiaddr>,
+    /// Maximum size of a JSON request body, in bytes, except for blocks
+    /// POSTed to `/mining/submit`.
+    #[arg(long, default_value_t = DEFAULT_JSON_LIMIT)]
+    max_json_body: usize,
+    /// Maximum size of a block POSTed to `/mining/submit`, in bytes.
+    #[arg(long, default_value_t = DEFAULT_BLOCK_JSON_LIMIT)]
+    max_block_json_body: usize,
+    /// Maximum size of a gossip message, in bytes. Larger messages are dropped unread.
+    #[arg(long, default_value_t = DEFAULT_MAX_MESSAGE_SIZE)]
+    max_p2p_message_size: usize,
+    /// Announces new blocks by their headers only; peers fetch the blocks
+    /// they lack with `GetBlocks`.
+    #[arg(long)]
+    header_first_relay: bool,
+    /// How far ahead of local time a transaction may be timestamped, in seconds.
+    #[arg(long, default_value_t = DEFAULT_MAX_FUTURE_DRIFT_SECS)]
+    max_future_drift_secs: i64,
+    /// Minimum fee per serialized byte for the mempool to accept a transaction.
+    /// Waived while the mempool is empty.
+    #[arg(long, default_value_t = DEFAULT_MIN_RELAY_FEE_PER_BYTE)]
+    min_relay_fee_per_byte: u64,
+    /// How much more in fees than the pending transactions it conflicts with
+    /// a replacement must pay.
+    #[arg(long, default_value_t = DEFAULT_MIN_REPLACEMENT_FEE_INCREMENT)]
+    min_replacement_fee_increment: u64,
+    /// Bearer token required by the `/admin` endpoints. They are disabled if unset.
+    #[arg(long)]
+    admin_key: Option<String>,
+    /// Key WebSocket clients must present to connect to `/ws`. Anyone may
+    /// connect if unset.
+    #[arg(long)]
+    api_key: Option<String>,
+    /// The sites allowed to show `/embed/latest` in a frame, as
+    /// comma-separated `frame-ancestors` sources such as `https://blog.example`.
+    #[arg(long, value_delimiter = ',', value_parser = api::embed::parse_frame_ancestor, default_value = "*")]
+    embed_frame_ancestors: Vec<String>,
+    /// Enables the `/admin` endpoints that invalidate and reconsider blocks.
+    #[arg(long)]
+    allow_admin_chain_ops: bool,
+    /// How many blocks below the tip a reorg may reach. A peer's chain
+    /// forking deeper is refused and puts the node in safe mode, and
+    /// side-chain blocks forking deeper are archived by the storage GC.
+    #[arg(long, default_value_t = DEFAULT_MAX_REORG_DEPTH)]
+    max_reorg_depth: u64,
+    /// Deletes stale side-chain blocks instead of archiving them.
+    #[arg(long)]
+    no_archive: bool,
+    /// The most nonces any mining job may try. Unlimited if unset.
+    #[arg(long)]
+    mining_max_attempts: Option<u64>,
+    /// The longest any mining job may run, in milliseconds. The mining
+    /// policy and `/mine` requests may ask for less, never more.
+    #[arg(long, default_value_t = DEFAULT_MINING_TIMEOUT_MS)]
+    mining_timeout_ms: u64,
+    /// Runs a light node: it follows and relays the chain and serves the
+    /// read-only endpoints, but never mines, and `/mine` answers 403.
+    #[arg(long)]
+    light: bool,
+    /// Syncs from a trusted `<index>:<hash>` checkpoint, without re-verifying
+    /// the proof of work of the blocks up to it.
+    #[arg(long, value_name = "INDEX:HASH")]
+    fast_sync_from: Option<Checkpoint>,
+    /// The network this node serves. The faucet is only enabled on testnet and regtest.
+    #[arg(long, value_enum, default_value_t = NetworkId::Mainnet)]
+    network: NetworkId,
+    /// Serves `POST /faucet`, paying test coins from the miner wallet.
+    #[arg(long)]
+    faucet: bool,
+    /// What the faucet pays a request that names no amount, in base units.
+    #[arg(long, default_value_t = DEFAULT_FAUCET_AMOUNT)]
+    faucet_amount: u64,
+    /// Moves small random amounts between throwaway wallets in the
+    /// background, so that a demo chain looks busy. Testnet and regtest only.
+    #[arg(long)]
+    demo_traffic: bool,
+    /// How often demo traffic makes a payment, in milliseconds.
+    #[arg(long, default_value_t = DEFAULT_DEMO_INTERVAL_MS)]
+    demo_traffic_interval_ms: u64,
+    /// The smallest demo payment, in base units.
+    #[arg(long, default_value_t = DEFAULT_DEMO_MIN_AMOUNT)]
+    demo_traffic_min_amount: u64,
+    /// The largest demo payment, in base units.
+    #[arg(long, default_value_t = DEFAULT_DEMO_MAX_AMOUNT)]
+    demo_traffic_max_amount: u64,
+    /// Assembles blocks reproducibly: transactions with equal fee rates are
+    /// ordered by txid rather than arrival. Always on in regtest.
+    #[arg(long)]
+    deterministic: bool,
+    /// Rebuilds the transaction index from the chain before starting.
+    #[arg(long)]
+    reindex: bool,
+    /// TOML config file. Only its `[reward]`, `[difficulty]`, `[txindex]`, `[genesis]` and `[mining]` sections are read so far.
+    #[arg(long, default_value = config::CONFIG_FILE)]
+    config: std::path::PathBuf,
+    /// Serves the HTTP API for the headless node listening at `unix:<path>`,
+    /// instead of running P2P and consensus here.
+    #[arg(long, value_parser = network::rpc::parse_node_url)]
+    node_url: Option<std::path::PathBuf>,
+    /// Where `node --headless` listens for API processes.
+    #[arg(long, default_value = network::rpc::RPC_SOCKET)]
+    rpc_socket: std::path::PathBuf,
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Runs the node. With `--headless` only P2P, consensus and the RPC
+    /// socket run, and the HTTP API is left to processes started with
+    /// `--node-url`.
+    Node {
+        #[arg(long)]
+        headless: bool,
+    },
+    /// Backs up or restores the node's wallets without starting the node.
+    Wallet {
+        #[command(subcommand)]
+        action: WalletCommand,
+    },
+    /// Works with transactions without starting the node.
+    Tx {
+        #[command(subcommand)]
+        action: TxCommand,
+    },
+}
+
+/// The passphrase for the `wallet` commands is read from this environment
+/// variable, or from `.env`, so it stays out of the shell history.
+const WALLET_PASSPHRASE_VAR: &str = "SIERPCHAIN_WALLET_PASSPHRASE";
+
+#[derive(Subcommand, Debug)]
+enum WalletCommand {
+    /// Writes an encrypted backup of every wallet, as `GET /wallet/backup` does.
+    Backup {
+        /// Where to write the backup. Printed to stdout if omitted.
+        #[arg(long)]
+        out: Option<std::path::PathBuf>,
+    },
+    /// Restores the wallets in a backup, as `POST /wallet/restore` does.
+    Restore {
+        file: std::path::PathBuf,
+        #[arg(long, value_enum, default_value_t = RestoreMode::Merge)]
+        mode: RestoreMode,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum TxCommand {
+    /// Signs a transaction with one of the node's wallets and prints it in
+    /// a form `POST /tx/raw` accepts.
+    Sign {
+        /// The unsigned transaction, as JSON or `{ "hex": "..." }`.
+        file: std::path::PathBuf,
+        /// The wallet to sign with. Defaults to the node's mining wallet.
+        #[arg(long)]
+        address: Option<String>,
+        #[arg(long, value_enum, default_value_t = RawFormat::Hex)]
+        format: RawFormat,
+    },
+}
+
+/// Signs every input of `unsigned` with the wallet in `key_store` owning
+/// `address`, or its first wallet, after setting the transaction's ID to its
+/// hash so that offline tools needn't compute it.
+fn sign_transaction(unsigned: &str, key_store: &KeyStore, address: Option<&str>, format: RawFormat) -> Result<serde_json::Value, String> {
+    let raw: RawTransaction = serde_json::from_str(unsigned).map_err(|e| format!("Invalid transaction: {}", e))?;
+    let mut tx = raw.into_transaction()?;
+    let stored = match address {
+        Some(address) => key_store.wallets().iter().find(|wallet| wallet.address == address),
+        None => key_store.wallets().first(),
+    };
+    let stored = stored.ok_or_else(|| format!("No wallet for {} in {}", address.unwrap_or("signing"), key_store.path().display()))?;
+    let wallet = api::handlers::wallet_from_private_key(&stored.private_key)?;
+    tx.id = tx.calculate_hash();
+    tx.sign(&wallet);
+    Ok(format.encode(&tx))
+}
+
+/// Runs a `tx` command against the key store in the working directory.
+fn run_tx_command(action: TxCommand) -> std::io::Result<()> {
+    match action {
+        TxCommand::Sign { file, address, format } => {
+            let key_store = KeyStore::load(WALLETS_FILE)?;
+            let signed = sign_transaction(&std::fs::read_to_string(file)?, &key_store, address.as_deref(), format)
+                .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e))?;
+            println!("{}", serde_json::to_string_pretty(&signed).unwrap());
+        }
+    }
+    Ok(())
+}
+
+/// Runs a `wallet` command against the key store in the working directory.
+fn run_wallet_command(action: WalletCommand) -> std::io::Result<()> {
+    let invalid = |e: String| std::io::Error::new(std::io::ErrorKind::InvalidInput, e);
+    let passphrase = env::var(WALLET_PASSPHRASE_VAR)
+        .ok()
+        .filter(|passphrase| !passphrase.is_empty())
+        .ok_or_else(|| invalid(format!("Set {} to the backup passphrase", WALLET_PASSPHRASE_VAR)))?;
+    let mut key_store = KeyStore::load(WALLETS_FILE)?;
+    match action {
+        WalletCommand::Backup { out } => {
+            let backup = serde_json::to_string_pretty(&core::wallet_backup::backup(&key_store, &passphrase)).unwrap();
+            match out {
+                Some(path) => std::fs::write(&path, backup)?,
+                None => println!("{}", backup),
+            }
+            eprintln!("Backed up {} wallets", key_store.wallets().len());
+        }
+        WalletCommand::Restore { file, mode } => {
+            let document = serde_json::from_str(&std::fs::read_to_string(file)?).map_err(|e| invalid(e.to_string()))?;
+            let restored = core::wallet_backup::restore(&mut key_store, document, &passphrase, mode)
+                .map_err(|e| invalid(e.to_string()))?;
+            key_store.save()?;
+            println!("Restored {} wallets; the store now has {}", restored, key_store.wallets().len());
+        }
+    }
+    Ok(())
+}
+
+#[derive(Deserialize)]
+struct WsQuery {
+    /// Client-chosen ID used to route targeted notifications to this session.
+    subscription_id: Option<String>,
+    /// `binary` to receive gzip-compressed JSON in binary frames instead of text.
+    #[serde(default)]
+    format: WsFormat,
+    /// The node's API key, for clients that can't offer it as a subprotocol.
+    api_key: Option<String>,
 }
 
-/// WebSocket handshake and actor starting
+/// WebSocket handshake and actor starting. If the node has an API key, the
+/// client must present it as the `api_key` query parameter or offer it as a
+/// subprotocol, since browsers can't set headers on the handshake.
 async fn ws_route(
     req: HttpRequest,
     stream: web::Payload,
-    hub_addr: web::Data<Addr<BroadcastHub>>,
+    query: web::Query<WsQuery>,
+    state: web::Data<AppState>,
 ) -> Result<HttpResponse, Error> {
-    ws::start(
-        WsConn::new(hub_addr.get_ref().clone()),
-        &req,
-        stream,
-    )
+    let query = query.into_inner();
+    let conn = WsConn::new(state.hub.clone(), query.subscription_id, query.format);
+    let Some(api_key) = state.api_key.0.as_deref() else {
+        return ws::start(conn, &req, stream);
+    };
+    if query.api_key.as_deref() == Some(api_key) {
+        return ws::start(conn, &req, stream);
+    }
+    let offered = req
+        .headers()
+        .get(actix_web::http::header::SEC_WEBSOCKET_PROTOCOL)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|protocols| protocols.split(',').any(|protocol| protocol.trim() == api_key));
+    if offered {
+        // A subprotocol the client offers must be echoed back for the
+        // handshake to complete.
+        let protocols = [api_key];
+        return ws::WsResponseBuilder::new(conn, &req, stream).protocols(&protocols).start();
+    }
+    Ok(HttpResponse::Unauthorized().json(serde_json::json!({ "error": "Missing or invalid API key" })))
 }
 
 /// The main entry point for the SierpChain backend.
@@ -63,155 +355,608 @@ async fn main() -> std::io::Result<()> {
     Lazy::force(&TRACING_SUBSCRIBER);
     dotenv().ok();
     let cli = Cli::parse();
+    if let Some(Command::Wallet { action }) = cli.command {
+        return run_wallet_command(action);
+    }
+    if let Some(Command::Tx { action }) = cli.command {
+        return run_tx_command(action);
+    }
+    let headless = matches!(cli.command, Some(Command::Node { headless: true }));
+    if headless && cli.node_url.is_some() {
+        return Err(std::io::Error::new(std::io::ErrorKind::InvalidInput, "A headless node can't use --node-url"));
+    }
+    if cli.demo_traffic && !cli.network.is_test_network() {
+        let error = format!("--demo-traffic is only available on test networks, not {}", cli.network);
+        return Err(std::io::Error::new(std::io::ErrorKind::InvalidInput, error));
+    }
+    if cli.demo_traffic_min_amount == 0 || cli.demo_traffic_min_amount > cli.demo_traffic_max_amount {
+        let error = "--demo-traffic-min-amount must be at least 1 and at most --demo-traffic-max-amount";
+        return Err(std::io::Error::new(std::io::ErrorKind::InvalidInput, error));
+    }
 
     // Start the broadcast hub
     let hub = BroadcastHub::new().start();
 
     // Create channels for P2P communication.
-    let (p2p_message_sender, mut p2p_message_receiver) = mpsc::unbounded_channel::<P2pMessage>();
+    let (p2p_message_sender, mut p2p_message_receiver) = mpsc::unbounded_channel::<InboundMessage>();
     let (to_p2p_sender, to_p2p_receiver) = mpsc::unbounded_channel::<P2pMessage>();
+    // Roomy enough for a validation report for every gossip message in a burst.
+    let (p2p_control_sender, p2p_control_receiver) = mpsc::channel::<P2pControl>(1024);
 
     // Initialize shared state.
-    let blockchain = Arc::new(Mutex::new(Blockchain::new(2)));
-    let transaction_pool: TransactionPool = Arc::new(Mutex::new(vec![]));
-    let miner_wallet = Arc::new(Wallet::new());
+    let health: SharedHealth = Arc::new(HealthRegistry::new());
+    let clock = system_clock();
+    let (mut blockchain, load_error) = Blockchain::load(2);
+    blockchain.clock = clock.clone();
+    blockchain.reward_schedule = config::load_reward_schedule(&cli.config, blockchain.reward_schedule.clone())
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e))?;
+    blockchain.difficulty_algo = config::load_difficulty_algo(&cli.config)
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e))?;
+    if let Some(premine) = config::load_premine(&cli.config).map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e))? {
+        blockchain.set_premine(premine).map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e))?;
+    }
+    health.report(CHAIN_LOADED, load_error.map_or(Ok(()), Err));
+    health.report(INDEX_READY, Ok(()));
+    health.report(STORAGE_WRITABLE, probe_writable(Path::new(".")));
+    health.report(SAFE_MODE_OFF, Ok(()));
+    let tx_index_config = config::load_tx_index_config(&cli.config)
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e))?;
+    let mut tx_index = TxIndex::open(TX_INDEX_DIR, tx_index_config)?;
+    if cli.reindex {
+        println!("Rebuilding the transaction index");
+        tx_index.reindex(&blockchain.chain)?;
+    } else {
+        tx_index.sync(&blockchain.chain)?;
+    }
+    let tx_index: SharedTxIndex = Arc::new(Mutex::new(tx_index));
+    let blockchain = Arc::new(Mutex::new(blockchain));
+    let transaction_pool: TransactionPool =
+        Arc::new(Mutex::new(
+            MempoolPool::with_max_future_drift(cli.max_future_drift_secs)
+                .with_min_relay_fee_per_byte(cli.min_relay_fee_per_byte)
+                .with_min_replacement_fee_increment(cli.min_replacement_fee_increment)
+                .with_clock(clock)
+                .with_deterministic_order(cli.deterministic || cli.network == NetworkId::Regtest),
+        ));
+    let mut key_store = KeyStore::load(WALLETS_FILE)?;
+    let miner_wallet = Arc::new(key_store.default_wallet()?);
+    let key_store: SharedKeyStore = Arc::new(Mutex::new(key_store));
+    let spend_ledger: SharedSpendLedger = Arc::new(Mutex::new(SpendLedger::load(SPENDING_FILE)?));
+    let peer_stats: PeerStatsMap = Arc::new(Mutex::new(HashMap::new()));
+    let hash_rate = Arc::new(HashRateMeter::default());
+    let peer_store: SharedPeerStore = Arc::new(Mutex::new(PeerStore::load(PEERS_FILE)));
+    let faucet_ledger: SharedFaucetLedger = Arc::new(Mutex::new(FaucetLedger::load(FAUCET_FILE)));
+    let nft_registry: SharedNftRegistry = Arc::new(Mutex::new(NftRegistry::load(NFT_REGISTRY_FILE)));
+    let labels: SharedLabelStore = Arc::new(Mutex::new(LabelStore::load(LABELS_FILE)?));
+    let network_id = cli.network;
+    let mining_policy = config::load_mining_policy(&cli.config)
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e))?;
+    let mining_policy: SharedMiningPolicy = Arc::new(Mutex::new(mining_policy));
+    let mining_caps = MiningBudget { max_attempts: cli.mining_max_attempts.unwrap_or(u64::MAX), timeout_ms: cli.mining_timeout_ms };
+    let archive = (!cli.no_archive).then(|| ARCHIVE_FILE.into());
+    let storage_gc: SharedStorageGc = Arc::new(Mutex::new(StorageGc::new(cli.max_reorg_depth, archive)));
 
     println!(
         "Genesis block mined: {:#?}",
         blockchain.lock().unwrap().chain.first().unwrap()
     );
     println!("Miner address: {}", miner_wallet.get_address());
+    println!("Network: {}", network_id);
+    if cli.light {
+        println!("Running as a light node: mining is disabled");
+    }
 
-    // Start the P2P network layer.
-    let p2p = P2p::new(p2p_message_sender, to_p2p_receiver, cli.p2p_port, cli.peer).await;
-    tokio::spawn(p2p.run());
+    let (connected_peers, p2p_metrics, p2p_listen_addrs) = if let Some(node_socket) = &cli.node_url {
+        // The node does the networking: blocks and transactions are handed
+        // to it instead of to peers, and the chain follows its own.
+        println!("Following the node at {}", node_socket.display());
+        let client = RpcClient::new(node_socket.clone());
+        tokio::spawn(network::rpc::forward(client.clone(), to_p2p_receiver));
+        let replica = Replica {
+            blockchain: Arc::clone(&blockchain),
+            tx_pool: Arc::clone(&transaction_pool),
+            hub: hub.clone(),
+            health: Arc::clone(&health),
+        };
+        tokio::spawn(network::rpc::follow(client, replica));
+        drop((p2p_message_sender, p2p_control_receiver));
+        (Arc::default(), Arc::default(), Arc::default())
+    } else {
+        // Start the P2P network layer.
+        let p2p = P2p::new(
+            p2p_message_sender,
+            to_p2p_receiver,
+            p2p_control_receiver,
+            P2pConfig {
+                port: cli.p2p_port,
+                initial_peers: cli.peer,
+                max_message_size: cli.max_p2p_message_size,
+                header_first_relay: cli.header_first_relay,
+                ..P2pConfig::default()
+            },
+            Arc::clone(&peer_store),
+        )
+        .await;
+        let connected_peers = Arc::clone(&p2p.connected_peers);
+        let p2p_metrics = Arc::clone(&p2p.metrics);
+        let p2p_listen_addrs = Arc::clone(&p2p.listen_addrs);
+        let p2p_task = tokio::spawn(p2p.run());
+        health.report(P2P_RUNNING, Ok(()));
+        let health_for_p2p = Arc::clone(&health);
+        tokio::spawn(async move {
+            let reason = match p2p_task.await {
+                Ok(()) => "P2P task exited".to_string(),
+                Err(e) => format!("P2P task failed: {}", e),
+            };
+            health_for_p2p.report(P2P_RUNNING, Err(reason));
+        });
+        (connected_peers, p2p_metrics, p2p_listen_addrs)
+    };
 
     // Spawn a thread to handle incoming P2P messages.
     let blockchain_for_networking = Arc::clone(&blockchain);
     let transaction_pool_for_networking = Arc::clone(&transaction_pool);
     let to_p2p_sender_for_networking = to_p2p_sender.clone();
     let hub_for_networking = hub.clone();
-    let miner_wallet_for_networking = Arc::clone(&miner_wallet);
+    let peer_stats_for_networking = Arc::clone(&peer_stats);
+    let p2p_control_for_networking = p2p_control_sender.clone();
+    let health_for_networking = Arc::clone(&health);
+    let max_reorg_depth = cli.max_reorg_depth;
+    let nft_registry_for_networking = Arc::clone(&nft_registry);
+    let tx_index_for_networking = Arc::clone(&tx_index);
+    let seen_messages: SeenMessages = Arc::new(Mutex::new(SeenCache::default()));
+    // Cleared once the chain has been synced from the checkpoint.
+    let mut fast_sync = cli.fast_sync_from.clone();
     tokio::spawn(async move {
-        let auto_mine = env::var("AUTO_MINE").unwrap_or_else(|_| "false".to_string()) == "true";
-        let interval_ms = env::var("MINING_INTERVAL_MS")
-            .unwrap_or_else(|_| "10000".to_string())
-            .parse::<u64>()
-            .unwrap_or(10000);
-        let mut mine_interval = if auto_mine {
-            let mut interval = time::interval(Duration::from_millis(interval_ms));
-            interval.tick().await; // First tick is immediate
-            Some(interval)
-        } else {
-            None
-        };
-
         loop {
             tokio::select! {
-                Some(message) = p2p_message_receiver.recv() => {
-                    match message {
-                        P2pMessage::Block(block) => {
-                            let mut blockchain_lock = blockchain_for_networking.lock().unwrap();
-                            let added = blockchain_lock.add_block_from_network(block.clone());
-                            if added {
-                                hub_for_networking.do_send(BroadcastBlock { block });
+                Some(InboundMessage { source, message_id, message }) = p2p_message_receiver.recv() => {
+                    let acceptance = if seen_messages.lock().unwrap().is_duplicate(&message) {
+                        tracing::debug!("Dropping recently seen message from {:?}", source);
+                        MessageAcceptance::Ignore
+                    } else {
+                        match message {
+                            P2pMessage::Block(block) => {
+                                let mut blockchain_lock = blockchain_for_networking.lock().unwrap();
+                                let added = blockchain_lock.add_block_from_network(block.clone());
+                                if let Err(e) = &added {
+                                    tracing::warn!("Rejected block {} from {:?}: {}", block.index, source, e);
+                                }
+                                // A block that doesn't build on our tip may just be from a
+                                // peer ahead of us; only one that is invalid in itself is
+                                // held against the peer.
+                                let invalid = added.as_ref().is_err_and(ChainError::is_invalid);
+                                if let Some(peer_id) = source {
+                                    let mut peer_stats = peer_stats_for_networking.lock().unwrap();
+                                    let stats = peer_stats.entry(peer_id).or_default();
+                                    stats.blocks_received += 1;
+                                    if added.is_err() {
+                                        stats.invalid_blocks += 1;
+                                    }
+                                    if invalid {
+                                        let penalty = P2pControl::Penalize { peer_id, points: INVALID_BLOCK_PENALTY };
+                                        if let Err(e) = p2p_control_for_networking.try_send(penalty) {
+                                            tracing::warn!("Failed to penalize {}: {}", peer_id, e);
+                                        }
+                                    }
+                                }
+                                if added.is_ok() {
+                                    transaction_pool_for_networking
+                                        .lock()
+                                        .unwrap()
+                                        .block_connected(&block, &blockchain_lock);
+                                    hub_for_networking.do_send(BroadcastBlock { block });
+                                }
+                                if let Err(e) = blockchain_lock.save_to_file() {
+                                    tracing::error!("Failed to save blockchain: {}", e);
+                                }
+                                match added {
+                                    Ok(()) => MessageAcceptance::Accept,
+                                    Err(_) if invalid => MessageAcceptance::Reject,
+                                    Err(_) => MessageAcceptance::Ignore,
+                                }
+                            }
+                            P2pMessage::BlockHeader(header) => {
+                                let blockchain_lock = blockchain_for_networking.lock().unwrap();
+                                match header_sync::request_for(&header, &blockchain_lock) {
+                                    Some(request) => {
+                                        to_p2p_sender_for_networking.send(request).unwrap();
+                                        MessageAcceptance::Accept
+                                    }
+                                    None => MessageAcceptance::Ignore,
+                                }
                             }
-                            if let Err(e) = blockchain_lock.save_to_file() {
-                                tracing::error!("Failed to save blockchain: {}", e);
+                            P2pMessage::ChainRequest => {
+                                let blockchain_lock = blockchain_for_networking.lock().unwrap();
+                                if let Some(checkpoint) = &fast_sync {
+                                    // Blocks we already hold up to the checkpoint needn't be fetched again.
+                                    let from_index = if blockchain_lock.contains_checkpoint(checkpoint) {
+                                        blockchain_lock.chain.len() as u64
+                                    } else {
+                                        0
+                                    };
+                                    to_p2p_sender_for_networking.send(P2pMessage::GetBlocks { from_index }).unwrap();
+                                }
+                                let chain = blockchain_lock.clone();
+                                to_p2p_sender_for_networking
+                                    .send(P2pMessage::ChainResponse(chain))
+                                    .unwrap();
+                                // Raised locally when a peer connects, so send it our mempool too,
+                                // announcing what doesn't fit for it to ask for.
+                                let (pushed, inventory) = mempool_sync::for_new_peer(&transaction_pool_for_networking.lock().unwrap());
+                                if source.is_some() {
+                                    for transaction in pushed {
+                                        to_p2p_sender_for_networking.send(P2pMessage::Transaction(transaction)).unwrap();
+                                    }
+                                    if !inventory.is_empty() {
+                                        to_p2p_sender_for_networking.send(P2pMessage::MempoolInv(inventory)).unwrap();
+                                    }
+                                }
+                                MessageAcceptance::Accept
                             }
-                        }
-                        P2pMessage::ChainRequest => {
-                            let blockchain_lock = blockchain_for_networking.lock().unwrap();
-                            let chain = blockchain_lock.clone();
-                            to_p2p_sender_for_networking
-                                .send(P2pMessage::ChainResponse(chain))
-                                .unwrap();
-                        }
-                        P2pMessage::ChainResponse(chain) => {
-                            let mut blockchain_lock = blockchain_for_networking.lock().unwrap();
-                            if chain.chain.len() > blockchain_lock.chain.len() {
-                                blockchain_lock.chain = chain.chain;
-                                if let Err(e) = blockchain_lock.save_to_file() {
+                            P2pMessage::GetBlocks { from_index } => {
+                                let blocks = blockchain_for_networking.lock().unwrap().blocks_from(from_index);
+                                if source.is_some() && !blocks.is_empty() {
+                                    to_p2p_sender_for_networking.send(P2pMessage::Blocks(blocks)).unwrap();
+                                }
+                                MessageAcceptance::Accept
+                            }
+                            P2pMessage::Blocks(blocks) => {
+                                if let Some(checkpoint) = &fast_sync {
+                                    let mut blockchain_lock = blockchain_for_networking.lock().unwrap();
+                                    health_for_networking.report(INDEX_READY, Err("Rebuilding the UTXO index".to_string()));
+                                    let imported = blockchain_lock.import_from_checkpoint(checkpoint, blocks);
+                                    health_for_networking.report(INDEX_READY, Ok(()));
+                                    match imported {
+                                        Ok(()) => {
+                                            tracing::info!("Fast-synced from checkpoint {}", checkpoint);
+                                            fast_sync = None;
+                                            if let Err(e) = blockchain_lock.save_to_file() {
+                                                tracing::error!("Failed to save blockchain: {}", e);
+                                            }
+                                        }
+                                        Err(e) => tracing::warn!("Fast sync from {:?} failed: {}", source, e),
+                                    }
+                                } else {
+                                    // Fetched after a header was announced.
+                                    let mut blockchain_lock = blockchain_for_networking.lock().unwrap();
+                                    let connected = header_sync::connect(&mut blockchain_lock, blocks);
+                                    if !connected.is_empty() {
+                                        let mut pool = transaction_pool_for_networking.lock().unwrap();
+                                        for block in connected {
+                                            pool.block_connected(&block, &blockchain_lock);
+                                            hub_for_networking.do_send(BroadcastBlock { block });
+                                        }
+                                        if let Err(e) = blockchain_lock.save_to_file() {
+                                            tracing::error!("Failed to save blockchain: {}", e);
+                                        }
+                                    }
+                                }
+                                MessageAcceptance::Accept
+                            }
+                            P2pMessage::ChainResponse(chain) => {
+                                let mut blockchain_lock = blockchain_for_networking.lock().unwrap();
+                                // While fast-syncing, only chains that contain the checkpoint are taken.
+                                let offer = fast_sync.is_none().then(|| {
+                                    reorg_guard::offer_chain(&mut blockchain_lock, chain.chain, max_reorg_depth, &health_for_networking, source)
+                                });
+                                if offer == Some(ChainOffer::Replaced)
+                                    && let Err(e) = blockchain_lock.save_to_file()
+                                {
                                     tracing::error!("Failed to save blockchain: {}", e);
                                 }
+                                match offer {
+                                    Some(ChainOffer::Invalid { error, .. }) if error.is_invalid() => {
+                                        if let Some(peer_id) = source {
+                                            let penalty = P2pControl::Penalize { peer_id, points: INVALID_BLOCK_PENALTY };
+                                            if let Err(e) = p2p_control_for_networking.try_send(penalty) {
+                                                tracing::warn!("Failed to penalize {}: {}", peer_id, e);
+                                            }
+                                        }
+                                        MessageAcceptance::Reject
+                                    }
+                                    _ => MessageAcceptance::Accept,
+                                }
                             }
-                        }
-                        P2pMessage::Transaction(transaction) => {
-                            if transaction.verify() {
+                            P2pMessage::MempoolInv(txids) => {
+                                let missing = mempool_sync::missing(&transaction_pool_for_networking.lock().unwrap(), &txids);
+                                if source.is_some() && !missing.is_empty() {
+                                    to_p2p_sender_for_networking.send(P2pMessage::GetTransactions(missing)).unwrap();
+                                }
+                                MessageAcceptance::Accept
+                            }
+                            P2pMessage::GetTransactions(txids) => {
+                                let transactions = mempool_sync::requested(&transaction_pool_for_networking.lock().unwrap(), &txids);
+                                if source.is_some() {
+                                    for transaction in transactions {
+                                        to_p2p_sender_for_networking.send(P2pMessage::Transaction(transaction)).unwrap();
+                                    }
+                                }
+                                MessageAcceptance::Accept
+                            }
+                            P2pMessage::NftClaim(claim) => {
+                                let blockchain_lock = blockchain_for_networking.lock().unwrap();
+                                let mut registry = nft_registry_for_networking.lock().unwrap();
+                                match registry.check(&claim, &blockchain_lock) {
+                                    Ok(()) => {
+                                        registry.register(&claim);
+                                        MessageAcceptance::Accept
+                                    }
+                                    Err(e) => {
+                                        tracing::warn!("Rejected NFT claim from {:?}: {}", source, e);
+                                        MessageAcceptance::Reject
+                                    }
+                                }
+                            }
+                            P2pMessage::Transaction(transaction) => {
+                                if let Some(peer_id) = source {
+                                    peer_stats_for_networking.lock().unwrap().entry(peer_id).or_default().transactions += 1;
+                                }
+                                let blockchain_lock = blockchain_for_networking.lock().unwrap();
                                 let mut pool = transaction_pool_for_networking.lock().unwrap();
-                                if !pool.iter().any(|tx| tx.id == transaction.id) {
-                                    pool.push(transaction);
+                                let confirmed = || {
+                                    let mut tx_index = tx_index_for_networking.lock().unwrap();
+                                    tx_index.sync(&blockchain_lock.chain).is_ok() && tx_index.contains(&transaction.id)
+                                };
+                                if pool.contains(&transaction.id) || pool.contains_orphan(&transaction.id) {
+                                    MessageAcceptance::Ignore
+                                } else if confirmed() {
+                                    tracing::debug!("Ignoring already confirmed transaction {} from {:?}", transaction.id, source);
+                                    MessageAcceptance::Ignore
+                                } else {
+                                    // Orphans are verified once the outputs they spend turn up,
+                                    // and aren't relayed until then.
+                                    match pool.verify(&transaction, &blockchain_lock) {
+                                        Ok(()) | Err(TxError::UnknownInput { .. }) => match pool.accept(transaction.clone(), &blockchain_lock) {
+                                            Ok(Admission::Accepted) => MessageAcceptance::Accept,
+                                            Ok(Admission::Replaced(replaced)) => {
+                                                hub_for_networking.do_send(TransactionReplaced { replaced, replacement: transaction.id });
+                                                MessageAcceptance::Accept
+                                            }
+                                            Ok(Admission::Orphaned) => MessageAcceptance::Ignore,
+                                            Err(e) => {
+                                                // Failing this node's mempool policy doesn't make a peer malicious.
+                                                tracing::warn!("Rejected transaction from {:?}: {}", source, e);
+                                                MessageAcceptance::Ignore
+                                            }
+                                        },
+                                        Err(e) => {
+                                            tracing::warn!("Rejected transaction from {:?}: {}", source, e);
+                                            MessageAcceptance::Reject
+                                        }
+                                    }
                                 }
                             }
                         }
-                    }
-                }
-                _ = async {
-                    if let Some(ref mut interval) = mine_interval {
-                        interval.tick().await;
-                    } else {
-                        // This branch will never be selected if auto-mining is disabled
-                        std::future::pending::<()>().await;
-                    }
-                } => {
-                    let mined_block = {
-                        let mut blockchain = blockchain_for_networking.lock().unwrap();
-                        let mut transactions = transaction_pool_for_networking.lock().unwrap();
-                        let coinbase_tx = crate::core::transaction::Transaction::new(
-                            vec![crate::core::transaction::TxInput {
-                                txid: "0".repeat(64),
-                                vout: blockchain.chain.len() as usize,
-                                script_sig: String::from("coinbase"),
-                                pub_key: String::new(),
-                                sequence: 0,
-                            }],
-                            vec![crate::core::transaction::TxOutput {
-                                value: 50,
-                                script_pub_key: miner_wallet_for_networking.get_address(),
-                            }],
-                        );
-                        let mut block_transactions = vec![coinbase_tx];
-                        block_transactions.extend(transactions.drain(..));
-                        let fractal_type = crate::fractal::FractalType::Sierpinski { depth: 5, seed: 0 };
-                        let mined_block = blockchain.add_block(fractal_type, block_transactions);
-                        if let Err(e) = blockchain.save_to_file() {
-                            tracing::error!("Failed to save blockchain: {}", e);
-                        }
-                        mined_block
                     };
-                    hub_for_networking.do_send(BroadcastBlock { block: mined_block.clone() });
-                    to_p2p_sender_for_networking.send(P2pMessage::Block(mined_block)).unwrap();
+                    if let (Some(source), Some(message_id)) = (source, message_id) {
+                        let report = P2pControl::ReportValidation { message_id, source, acceptance };
+                        if let Err(e) = p2p_control_for_networking.try_send(report) {
+                            tracing::warn!("Failed to report a message's validation to gossipsub: {}", e);
+                        }
+                    }
                 }
+                // Only when the P2P layer is gone, as with `--node-url`.
+                else => break,
+            }
+        }
+    });
+
+    let auto_mine = !cli.light && env::var("AUTO_MINE").unwrap_or_else(|_| "false".to_string()) == "true";
+    if auto_mine {
+        let interval_ms = env::var("MINING_INTERVAL_MS")
+            .unwrap_or_else(|_| "10000".to_string())
+            .parse::<u64>()
+            .unwrap_or(10000);
+        let auto_miner = AutoMiner {
+            blockchain: Arc::clone(&blockchain),
+            tx_pool: Arc::clone(&transaction_pool),
+            policy: Arc::clone(&mining_policy),
+            caps: mining_caps,
+            miner_address: miner_wallet.get_address(),
+            hash_rate: Arc::clone(&hash_rate),
+            to_p2p: to_p2p_sender.clone(),
+            hub: hub.clone(),
+            health: Arc::clone(&health),
+        };
+        tokio::spawn(auto_miner.run(Duration::from_millis(interval_ms)));
+    }
+
+    let demo_traffic = cli.demo_traffic.then(|| {
+        let config = DemoConfig {
+            interval: Duration::from_millis(cli.demo_traffic_interval_ms),
+            min_amount: cli.demo_traffic_min_amount,
+            max_amount: cli.demo_traffic_max_amount,
+        };
+        let demo = DemoTraffic::new(
+            Arc::clone(&blockchain),
+            Arc::clone(&transaction_pool),
+            Arc::clone(&miner_wallet),
+            to_p2p_sender.clone(),
+            hub.clone(),
+            config,
+        );
+        demo.spawn(true)
+    });
+    let (demo_switch, demo_task) = demo_traffic.map_or((DemoSwitch::default(), None), |(switch, task)| (switch, Some(task)));
+
+    // Collect stale side-chain blocks in the background.
+    let blockchain_for_gc = Arc::clone(&blockchain);
+    let storage_gc_for_gc = Arc::clone(&storage_gc);
+    tokio::spawn(async move {
+        let mut interval = time::interval(GC_INTERVAL);
+        loop {
+            interval.tick().await;
+            let mut blockchain = blockchain_for_gc.lock().unwrap();
+            if let Err(e) = storage_gc_for_gc.lock().unwrap().run(&mut blockchain) {
+                tracing::error!("Storage GC failed: {}", e);
             }
         }
     });
 
+    if headless {
+        let listener = network::rpc::bind(&cli.rpc_socket)?;
+        println!("Serving node RPC at {}", cli.rpc_socket.display());
+        let node = RpcNode { blockchain, tx_pool: transaction_pool, to_p2p: to_p2p_sender, hub };
+        return network::rpc::serve(listener, node).await;
+    }
+
     let http_addr = format!("127.0.0.1:{}", cli.http_port);
-    println!("Starting web server at http://{}", http_addr);
-    HttpServer::new(move || {
+    let (max_json_body, max_block_json_body) = (cli.max_json_body, cli.max_block_json_body);
+    let state = web::Data::new(AppState {
+        blockchain,
+        tx_pool: transaction_pool,
+        to_p2p: to_p2p_sender,
+        p2p_control: p2p_control_sender,
+        miner_wallet,
+        key_store,
+        hub,
+        peer_stats,
+        connected_peers,
+        p2p_metrics,
+        response_sizes: Arc::default(),
+        p2p_listen_addrs,
+        http_addr: Arc::default(),
+        peer_store,
+        hash_rate,
+        mining_policy,
+        mining_caps,
+        health,
+        network: network_id,
+        faucet: FaucetConfig { enabled: cli.faucet, amount: cli.faucet_amount },
+        faucet_ledger,
+        spend_ledger,
+        nft_registry,
+        labels,
+        admin_key: AdminKey(cli.admin_key),
+        allow_chain_ops: AllowChainOps(cli.allow_admin_chain_ops),
+        light: LightNode(cli.light),
+        api_key: ApiKey(cli.api_key),
+        frame_ancestors: FrameAncestors(cli.embed_frame_ancestors),
+        demo_traffic: demo_switch.clone(),
+        block_sizes: Arc::default(),
+        storage_gc,
+        tx_index,
+    });
+    let (server, bound) = http_server(state, max_json_body, max_block_json_body, &http_addr)?;
+    println!("Starting web server at http://{}", bound);
+    let result = server.await;
+    // Let a demo payment in progress finish before the runtime goes away.
+    demo_switch.stop();
+    if let Some(task) = demo_task {
+        task.await.ok();
+    }
+    result
+}
+
+/// Binds the web server serving every endpoint from `state` to `address`,
+/// returning it with the address it bound, which tells the port when
+/// `address` asks for port 0. The address is also recorded for `/status`.
+/// Blocks POSTed to `/mining/submit` are capped at `max_block_json_body`
+/// bytes and every other JSON body at `max_json_body`.
+fn http_server(
+    state: web::Data<AppState>,
+    max_json_body: usize,
+    max_block_json_body: usize,
+    address: &str,
+) -> std::io::Result<(actix_web::dev::Server, std::net::SocketAddr)> {
+    let state_for_status = state.clone();
+    let server = HttpServer::new(move || {
         let cors = Cors::default()
             .allow_any_origin()
             .allow_any_method()
             .allow_any_header();
         App::new()
+            // Compresses responses for clients sending `Accept-Encoding`.
+            // Streams and PNGs opt out, and WebSocket upgrades are never
+            // compressed. Bodies are measured on both sides for `/metrics`.
+            .wrap(from_fn(count_uncompressed_bytes))
+            .wrap(Compress::default())
+            .wrap(from_fn(count_sent_bytes))
             .wrap(cors)
-            .app_data(web::Data::new(Arc::clone(&blockchain)))
-            .app_data(web::Data::new(Arc::clone(&transaction_pool)))
-            .app_data(web::Data::new(to_p2p_sender.clone()))
-            .app_data(web::Data::new(Arc::clone(&miner_wallet)))
-            .app_data(web::Data::new(hub.clone()))
+            .app_data(json_config(max_json_body))
+            .app_data(state.clone())
+            .service(health_live)
+            .service(health_ready)
             .service(get_blocks)
+            .service(stream_blocks)
+            .service(longpoll_tip)
+            .service(track_transaction)
             .service(get_balance)
             .service(get_utxos)
+            .service(get_address_fractals)
+            .service(get_block_fractal_png)
+            .service(embed_latest)
+            .service(get_demo_traffic)
+            .service(put_demo_traffic)
+            .service(get_block_dimension)
+            .service(verify_block)
+            .service(get_block_transactions)
+            .service(get_block)
             .service(transact)
+            .service(transact_batch)
+            .service(transact_cpfp)
+            .service(transact_bump)
+            .service(decode_transaction)
+            .service(get_transaction)
+            .service(submit_raw_transaction)
+            .service(get_raw_transaction)
+            .service(filter_block)
+            .service(get_mining_template)
+            .service(mining_submit(max_block_json_body))
             .service(get_wallet_info)
             .service(mine)
             .service(create_wallet)
+            .service(backup_wallets)
+            .service(restore_wallets)
+            .service(import_wallet)
+            .service(sweep_wallet)
+            .service(get_spend_policy)
+            .service(put_spend_policy)
+            .service(list_approvals)
+            .service(approve_payment)
+            .service(reject_payment)
+            .service(get_peers)
+            .service(get_known_peers)
+            .service(connect_peer)
+            .service(disconnect_peer)
+            .service(get_supply)
+            .service(get_status)
+            .service(get_metrics)
+            .service(get_mempool)
+            .service(get_mempool_relatives)
+            .service(get_chain_graph)
+            .service(get_mining_target)
+            .service(get_mining_status)
+            .service(put_mining_config)
+            .service(get_mine_estimate)
+            .service(evict_transaction)
+            .service(api::faucet::faucet)
+            .service(faucet_stats)
+            .service(claim_nft)
+            .service(get_nft_owner)
+            .service(set_label)
+            .service(get_label)
+            .service(add_peer)
+            .service(list_peers)
+            .service(remove_peer)
+            .service(invalidate_block)
+            .service(reconsider_block)
+            .service(clear_safe_mode)
+            .service(get_storage)
+            .service(collect_garbage)
             .route("/ws", web::get().to(ws_route))
     })
-    .bind(http_addr)?
-    .run()
-    .await
+    .bind(address)
+    .map_err(|e| {
+        std::io::Error::new(
+            e.kind(),
+            format!("Failed to bind the web server to {}: {} (pass --http-port 0 to use any free port)", address, e),
+        )
+    })?;
+    let bound = server.addrs()[0];
+    state_for_status.http_addr.set(bound).ok();
+    Ok((server.run(), bound))
 }
 
 #[cfg(test)]
@@ -219,38 +964,198 @@ mod tests {
     use super::*;
     use actix_web::{test, App, dev::{Service, ServiceResponse}};
     use actix_http::Request;
-    use serde_json;
-    use hex;
+    use crate::core::wallet::Wallet;
+    use crate::mining::miner::Miner;
+    use sierpchain::fractal;
+
+    const TEST_ADMIN_KEY: &str = "test-admin-key";
 
     async fn setup_test_app() -> (impl Service<Request, Response = ServiceResponse, Error = actix_web::Error>, String) {
+        setup_test_app_with_pool(MempoolPool::default()).await
+    }
+
+    async fn setup_test_app_with_pool(
+        pool: MempoolPool,
+    ) -> (impl Service<Request, Response = ServiceResponse, Error = actix_web::Error>, String) {
+        setup_test_app_on(pool, NetworkId::Regtest).await
+    }
+
+    async fn setup_test_app_on(
+        pool: MempoolPool,
+        network: NetworkId,
+    ) -> (impl Service<Request, Response = ServiceResponse, Error = actix_web::Error>, String) {
+        let (app, private_key, mut control_receiver) = setup_test_app_with_control(pool, network).await;
+        tokio::spawn(async move {
+            while control_receiver.recv().await.is_some() {}
+        });
+        (app, private_key)
+    }
+
+    /// Builds the shared state for a test app on a fresh chain, with every
+    /// health check passing. Returns it with the miner's private key and the
+    /// receiving end of the P2P control channel.
+    fn test_state(pool: MempoolPool, network: NetworkId) -> (AppState, String, mpsc::Receiver<P2pControl>) {
         std::fs::remove_file("blockchain.json").ok();
         let blockchain = Arc::new(Mutex::new(Blockchain::new(1)));
-        let transaction_pool: TransactionPool = Arc::new(Mutex::new(vec![]));
+        let transaction_pool: TransactionPool = Arc::new(Mutex::new(pool));
         let miner_wallet = Arc::new(Wallet::new());
         let private_key = hex::encode(miner_wallet.signing_key.to_bytes());
         let (p2p_sender, mut p2p_receiver) = mpsc::unbounded_channel::<P2pMessage>();
         tokio::spawn(async move {
-            while let Some(_) = p2p_receiver.recv().await {}
+            while p2p_receiver.recv().await.is_some() {}
         });
         let hub = BroadcastHub::new().start();
+        let peer_stats: PeerStatsMap = Arc::new(Mutex::new(HashMap::new()));
+        let (control_sender, control_receiver) = mpsc::channel::<P2pControl>(32);
+        let connected_peers: network::p2p::ConnectedPeers = Arc::new(Mutex::new(HashMap::new()));
+        // Each test app gets its own, initially empty, peer store.
+        let peers_file = std::env::temp_dir().join(format!("sierpchain-peers-{}.json", rand::random::<u64>()));
+        let peer_store: SharedPeerStore = Arc::new(Mutex::new(PeerStore::load(peers_file)));
+        let faucet_file = std::env::temp_dir().join(format!("sierpchain-faucet-{}.json", rand::random::<u64>()));
+        let faucet_ledger: SharedFaucetLedger = Arc::new(Mutex::new(FaucetLedger::load(faucet_file)));
+        let spending_file = std::env::temp_dir().join(format!("sierpchain-spending-{}.json", rand::random::<u64>()));
+        let nft_file = std::env::temp_dir().join(format!("sierpchain-nft-{}.json", rand::random::<u64>()));
+        let nft_registry: SharedNftRegistry = Arc::new(Mutex::new(NftRegistry::load(nft_file)));
+        let labels_file = std::env::temp_dir().join(format!("sierpchain-labels-{}.json", rand::random::<u64>()));
+        let tx_index_dir = std::env::temp_dir().join(format!("sierpchain-txindex-{}", rand::random::<u64>()));
+        let wallets_file = std::env::temp_dir().join(format!("sierpchain-wallets-{}.json", rand::random::<u64>()));
+        let archive_file = std::env::temp_dir().join(format!("sierpchain-archive-{}.jsonl", rand::random::<u64>()));
+        let mut key_store = KeyStore::load(wallets_file).unwrap();
+        key_store.merge(vec![core::keystore::StoredWallet::from(miner_wallet.as_ref())]);
+        let health: SharedHealth = Arc::new(HealthRegistry::new());
+        for check in api::health::READINESS_CHECKS {
+            health.report(check, Ok(()));
+        }
 
-        let app = test::init_service(
+        let state = AppState {
+            blockchain,
+            tx_pool: transaction_pool,
+            to_p2p: p2p_sender,
+            p2p_control: control_sender,
+            miner_wallet,
+            key_store: Arc::new(Mutex::new(key_store)),
+            hub,
+            peer_stats,
+            connected_peers,
+            p2p_metrics: Arc::new(network::p2p::P2pMetrics::default()),
+            response_sizes: Arc::default(),
+            p2p_listen_addrs: Arc::default(),
+            http_addr: Arc::default(),
+            peer_store,
+            hash_rate: Arc::new(mining::miner::HashRateMeter::default()),
+            mining_policy: Arc::default(),
+            mining_caps: MiningBudget::default(),
+            health,
+            network,
+            faucet: FaucetConfig { enabled: true, ..FaucetConfig::default() },
+            faucet_ledger,
+            spend_ledger: Arc::new(Mutex::new(SpendLedger::load(spending_file).unwrap())),
+            nft_registry,
+            labels: Arc::new(Mutex::new(LabelStore::load(labels_file).unwrap())),
+            admin_key: AdminKey(Some(TEST_ADMIN_KEY.to_string())),
+            allow_chain_ops: AllowChainOps(true),
+            light: LightNode(false),
+            api_key: ApiKey::default(),
+            frame_ancestors: FrameAncestors::default(),
+            demo_traffic: DemoSwitch::default(),
+            block_sizes: Arc::default(),
+            storage_gc: Arc::new(Mutex::new(StorageGc::new(DEFAULT_MAX_REORG_DEPTH, Some(archive_file)))),
+            tx_index: Arc::new(Mutex::new(TxIndex::open(tx_index_dir, blockchain::txindex::TxIndexConfig::default()).unwrap())),
+        };
+        (state, private_key, control_receiver)
+    }
+
+    async fn setup_test_app_with_control(
+        pool: MempoolPool,
+        network: NetworkId,
+    ) -> (
+        impl Service<Request, Response = ServiceResponse, Error = actix_web::Error>,
+        String,
+        mpsc::Receiver<P2pControl>,
+    ) {
+        let (state, private_key, control_receiver) = test_state(pool, network);
+        (init_test_app(state).await, private_key, control_receiver)
+    }
+
+    /// Serves every endpoint from `state`.
+    async fn init_test_app(
+        state: AppState,
+    ) -> impl Service<Request, Response = ServiceResponse, Error = actix_web::Error> {
+        test::init_service(
             App::new()
-                .app_data(web::Data::new(Arc::clone(&blockchain)))
-                .app_data(web::Data::new(Arc::clone(&transaction_pool)))
-                .app_data(web::Data::new(p2p_sender.clone()))
-                .app_data(web::Data::new(Arc::clone(&miner_wallet)))
-                .app_data(web::Data::new(hub.clone()))
+                .app_data(json_config(api::handlers::DEFAULT_JSON_LIMIT))
+                .app_data(web::Data::new(state))
+                .service(api::health::health_live)
+                .service(api::health::health_ready)
                 .service(api::handlers::create_wallet)
+                .service(api::wallet::backup_wallets)
+                .service(api::wallet::restore_wallets)
+                .service(api::wallet::import_wallet)
+                .service(api::wallet::sweep_wallet)
+                .service(api::spending::get_spend_policy)
+                .service(api::spending::put_spend_policy)
+                .service(api::spending::list_approvals)
+                .service(api::spending::approve_payment)
+                .service(api::spending::reject_payment)
                 .service(api::handlers::get_blocks)
+                .service(api::stream::stream_blocks)
+                .service(api::handlers::longpoll_tip)
+                .service(api::handlers::track_transaction)
                 .service(api::handlers::mine)
                 .service(api::handlers::transact)
+                .service(api::handlers::transact_batch)
+                .service(api::handlers::transact_cpfp)
+                .service(api::handlers::transact_bump)
+                .service(api::handlers::decode_transaction)
+                .service(api::handlers::get_transaction)
+                .service(api::handlers::submit_raw_transaction)
+                .service(api::handlers::get_raw_transaction)
+                .service(api::handlers::filter_block)
+                .service(api::handlers::get_mining_template)
+                .service(mining_submit(DEFAULT_BLOCK_JSON_LIMIT))
                 .service(api::handlers::get_wallet_info)
                 .service(api::handlers::get_balance)
                 .service(api::handlers::get_utxos)
+                .service(api::handlers::get_address_fractals)
+                .service(api::handlers::get_block_fractal_png)
+                .service(api::embed::embed_latest)
+                .service(api::demo::get_demo_traffic)
+                .service(api::demo::put_demo_traffic)
+                .service(api::handlers::get_block_dimension)
+                .service(api::handlers::verify_block)
+                .service(api::handlers::get_block_transactions)
+                .service(api::handlers::get_block)
+                .service(api::handlers::get_peers)
+                .service(api::handlers::get_known_peers)
+                .service(api::handlers::connect_peer)
+                .service(api::handlers::disconnect_peer)
+                .service(api::handlers::get_supply)
+                .service(api::handlers::get_status)
+                .service(api::metrics::get_metrics)
+                .service(api::handlers::get_mempool)
+                .service(api::handlers::get_mempool_relatives)
+                .service(api::handlers::get_chain_graph)
+                .service(api::handlers::get_mining_target)
+                .service(api::handlers::get_mining_status)
+                .service(api::handlers::put_mining_config)
+                .service(api::handlers::get_mine_estimate)
+                .service(api::handlers::evict_transaction)
+                .service(api::faucet::faucet)
+                .service(api::faucet::faucet_stats)
+                .service(api::nft::claim_nft)
+                .service(api::nft::get_nft_owner)
+                .service(api::labels::set_label)
+                .service(api::labels::get_label)
+                .service(api::admin::add_peer)
+                .service(api::admin::list_peers)
+                .service(api::admin::remove_peer)
+                .service(api::admin::invalidate_block)
+                .service(api::admin::reconsider_block)
+                .service(api::admin::clear_safe_mode)
+                .service(api::admin::get_storage)
+                .service(api::admin::collect_garbage)
                 .route("/ws", web::get().to(ws_route))
-        ).await;
-        (app, private_key)
+        ).await
     }
 
     #[actix_web::test]
@@ -275,7 +1180,7 @@ mod tests {
 
         let body: serde_json::Value = test::read_body_json(resp).await;
         assert_eq!(body["index"], 1);
-        assert!(body["transactions"].as_array().unwrap().len() >= 1); // Coinbase tx
+        assert!(!body["transactions"].as_array().unwrap().is_empty()); // Coinbase tx
         assert_eq!(body["fractal"]["type"], "Sierpinski");
     }
 
@@ -302,6 +1207,15 @@ mod tests {
         assert_eq!(body["index"], 1);
         assert_eq!(body["fractal"]["type"], "Mandelbrot");
         assert_eq!(body["fractal"]["data"]["width"], 10);
+        // Coordinates are stored in fixed point.
+        assert_eq!(body["fractal"]["data"]["x_min"], -2_000_000_000_000i64);
+
+        let mut out_of_range = mine_req.clone();
+        out_of_range["params"]["y_max"] = serde_json::json!(1e300);
+        let req = test::TestRequest::post().uri("/mine").set_json(&out_of_range).to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), actix_web::http::StatusCode::BAD_REQUEST);
+        assert_eq!(test::read_body(resp).await, "y_max must be between -9.2e6 and 9.2e6");
     }
 
     #[actix_web::test]
@@ -341,4 +1255,2469 @@ mod tests {
         let balance: u64 = test::read_body_json(resp).await;
         assert_eq!(balance, 10);
     }
-}
\ No newline at end of file
+
+    #[actix_web::test]
+    async fn test_transact_spends_from_several_wallets_at_once() {
+        let (app, miner_private_key) = setup_test_app().await;
+        let req = test::TestRequest::post().uri("/mine").to_request();
+        test::call_service(&app, req).await;
+
+        // Fund two wallets, mining each payment.
+        let mut senders = Vec::new();
+        for amount in [30, 20] {
+            let req = test::TestRequest::post().uri("/wallet").to_request();
+            let wallet: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+            let transact_req = serde_json::json!({ "to": wallet["address"], "amount": amount, "private_key": miner_private_key });
+            let req = test::TestRequest::post().uri("/transact").set_json(&transact_req).to_request();
+            assert!(test::call_service(&app, req).await.status().is_success());
+            let req = test::TestRequest::post().uri("/mine").to_request();
+            test::call_service(&app, req).await;
+            senders.push(wallet);
+        }
+
+        // Neither can pay 45 alone, but together they can.
+        let receiver = Wallet::new().get_address();
+        let transact_req = serde_json::json!({
+            "to": receiver,
+            "amount": 45,
+            "private_keys": [senders[0]["private_key"], senders[1]["private_key"]],
+        });
+        let req = test::TestRequest::post().uri("/transact").set_json(&transact_req).to_request();
+        let resp = test::call_service(&app, req).await;
+        assert!(resp.status().is_success());
+        let tx: serde_json::Value = test::read_body_json(resp).await;
+        let signers: std::collections::HashSet<&str> =
+            tx["inputs"].as_array().unwrap().iter().map(|input| input["pub_key"].as_str().unwrap()).collect();
+        assert_eq!(signers.len(), 2);
+        let req = test::TestRequest::post().uri("/mine").to_request();
+        test::call_service(&app, req).await;
+
+        // The change goes back to the first sender.
+        let balances = [
+            (receiver.as_str(), 45),
+            (senders[0]["address"].as_str().unwrap(), 5),
+            (senders[1]["address"].as_str().unwrap(), 0),
+        ];
+        for (address, expected) in balances {
+            let req = test::TestRequest::get().uri(&format!("/address/{}/balance", address)).to_request();
+            let balance: u64 = test::call_and_read_body_json(&app, req).await;
+            assert_eq!(balance, expected);
+        }
+
+        // Keys are given one way or the other, not both.
+        let transact_req = serde_json::json!({
+            "to": receiver,
+            "amount": 1,
+            "private_key": miner_private_key,
+            "private_keys": [miner_private_key],
+        });
+        let req = test::TestRequest::post().uri("/transact").set_json(&transact_req).to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), actix_web::http::StatusCode::BAD_REQUEST);
+    }
+
+    #[actix_web::test]
+    async fn test_bumped_transaction_replaces_the_stuck_one() {
+        let (state, miner_private_key, _control_receiver) = test_state(MempoolPool::default(), NetworkId::Regtest);
+        let app = init_test_app(state).await;
+        let req = test::TestRequest::post().uri("/mine").to_request();
+        test::call_service(&app, req).await;
+
+        let receiver = Wallet::new().get_address();
+        let transact_req = serde_json::json!({ "to": receiver, "amount": 10, "fee": 1, "private_key": miner_private_key });
+        let req = test::TestRequest::post().uri("/transact").set_json(&transact_req).to_request();
+        let stuck: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+
+        // Not a bump at all.
+        let bump_req = serde_json::json!({ "txid": stuck["id"], "new_fee": 1 });
+        let req = test::TestRequest::post().uri("/transact/bump").set_json(&bump_req).to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), actix_web::http::StatusCode::BAD_REQUEST);
+
+        let bump_req = serde_json::json!({ "txid": stuck["id"], "new_fee": 5 });
+        let req = test::TestRequest::post().uri("/transact/bump").set_json(&bump_req).to_request();
+        let resp = test::call_service(&app, req).await;
+        assert!(resp.status().is_success());
+        let bumped: serde_json::Value = test::read_body_json(resp).await;
+        assert_eq!(bumped["replaced"], serde_json::json!([stuck["id"]]));
+        let replacement = &bumped["transaction"]["id"];
+
+        let req = test::TestRequest::get().uri(&format!("/mempool/{}/relatives", stuck["id"].as_str().unwrap())).to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), actix_web::http::StatusCode::NOT_FOUND);
+        let req = test::TestRequest::get().uri("/mempool").to_request();
+        let mempool: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(mempool["transactions"], 1);
+
+        let req = test::TestRequest::post().uri("/mine").to_request();
+        test::call_service(&app, req).await;
+        let req = test::TestRequest::get().uri(&format!("/transaction/{}", replacement.as_str().unwrap())).to_request();
+        let confirmed: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(confirmed["confirmations"], 1);
+        let req = test::TestRequest::get().uri(&format!("/address/{}/balance", receiver)).to_request();
+        let balance: u64 = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(balance, 10);
+
+        // The replaced transaction is gone for good.
+        let req = test::TestRequest::post().uri("/transact/bump").set_json(&bump_req).to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), actix_web::http::StatusCode::NOT_FOUND);
+    }
+
+    #[actix_web::test]
+    async fn test_bump_over_the_spend_limit_is_refused() {
+        let (app, miner_private_key) = setup_test_app().await;
+        let req = test::TestRequest::post().uri("/mine").to_request();
+        test::call_service(&app, req).await;
+        let req = test::TestRequest::get().uri("/wallet/info").to_request();
+        let info: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        let req = test::TestRequest::put()
+            .uri(&format!("/wallet/{}/policy", info["address"].as_str().unwrap()))
+            .insert_header(("Authorization", format!("Bearer {}", TEST_ADMIN_KEY)))
+            .set_json(serde_json::json!({ "max_per_transaction": 50 }))
+            .to_request();
+        assert!(test::call_service(&app, req).await.status().is_success());
+
+        let transact_req = serde_json::json!({ "to": Wallet::new().get_address(), "amount": 10, "fee": 1, "private_key": miner_private_key });
+        let req = test::TestRequest::post().uri("/transact").set_json(&transact_req).to_request();
+        let stuck: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+
+        // Only the 60 added to the fee counts, but that is still over the limit.
+        let bump = |new_fee: u64| {
+            let body = serde_json::json!({ "txid": stuck["id"], "new_fee": new_fee });
+            test::TestRequest::post().uri("/transact/bump").set_json(body).to_request()
+        };
+        let resp = test::call_service(&app, bump(61)).await;
+        assert_eq!(resp.status(), actix_web::http::StatusCode::FORBIDDEN);
+        let body: serde_json::Value = test::read_body_json(resp).await;
+        assert_eq!(body["details"]["reason"], "over_transaction_limit");
+        assert_eq!(body["details"]["value"], 60);
+        let req = test::TestRequest::get().uri(&format!("/mempool/{}/relatives", stuck["id"].as_str().unwrap())).to_request();
+        assert!(test::call_service(&app, req).await.status().is_success());
+
+        assert!(test::call_service(&app, bump(51)).await.status().is_success());
+    }
+
+    #[actix_web::test]
+    async fn test_transact_over_output_limit_is_rejected() {
+        let (state, miner_private_key, _control_receiver) = test_state(MempoolPool::default(), NetworkId::Regtest);
+        // A payment with change has two outputs.
+        state.blockchain.lock().unwrap().params.max_tx_outputs = 1;
+        let app = init_test_app(state).await;
+        let req = test::TestRequest::post().uri("/mine").to_request();
+        assert!(test::call_service(&app, req).await.status().is_success());
+
+        let req = test::TestRequest::post()
+            .uri("/transact")
+            .set_json(serde_json::json!({ "to": Wallet::new().get_address(), "amount": 10, "private_key": miner_private_key }))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), actix_web::http::StatusCode::BAD_REQUEST);
+        assert_eq!(test::read_body(resp).await, "Transaction has 2 outputs, more than the limit of 1");
+    }
+
+    #[actix_web::test]
+    async fn test_transact_fractional_coins() {
+        let (app, miner_private_key) = setup_test_app().await;
+        let req = test::TestRequest::post().uri("/wallet").to_request();
+        let receiver_wallet: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        let req = test::TestRequest::post().uri("/mine").to_request();
+        test::call_service(&app, req).await;
+
+        let transact_req = serde_json::json!({
+            "to": receiver_wallet["address"],
+            "amount_coins": "0.000000001",
+            "private_key": miner_private_key
+        });
+        let req = test::TestRequest::post().uri("/transact").set_json(&transact_req).to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), actix_web::http::StatusCode::BAD_REQUEST);
+
+        let transact_req = serde_json::json!({
+            "to": receiver_wallet["address"],
+            "amount_coins": "0.005",
+            "private_key": miner_private_key
+        });
+        let req = test::TestRequest::post().uri("/transact").set_json(&transact_req).to_request();
+        let resp = test::call_service(&app, req).await;
+        assert!(resp.status().is_success());
+        let req = test::TestRequest::post().uri("/mine").to_request();
+        test::call_service(&app, req).await;
+
+        let req = test::TestRequest::get()
+            .uri(&format!("/address/{}/balance", receiver_wallet["address"].as_str().unwrap()))
+            .to_request();
+        let balance: u64 = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(balance, blockchain::chain::COIN / 200);
+    }
+
+    #[actix_web::test]
+    async fn test_relay_fee_floor() {
+        for (min_relay_fee_per_byte, free_accepted) in [(1, false), (0, true)] {
+            let pool = MempoolPool::default().with_min_relay_fee_per_byte(min_relay_fee_per_byte);
+            let (app, miner_private_key) = setup_test_app_with_pool(pool).await;
+            for _ in 0..3 {
+                let req = test::TestRequest::post().uri("/mine").to_request();
+                test::call_service(&app, req).await;
+            }
+            let transact_request = |fee: u64| {
+                test::TestRequest::post()
+                    .uri("/transact")
+                    .set_json(serde_json::json!({
+                        "to": "someone",
+                        "amount": 1,
+                        "fee": fee,
+                        "private_key": miner_private_key
+                    }))
+                    .to_request()
+            };
+
+            // The first transaction enters an empty mempool, where the floor is waived.
+            let resp = test::call_service(&app, transact_request(0)).await;
+            assert!(resp.status().is_success());
+
+            let resp = test::call_service(&app, transact_request(0)).await;
+            if free_accepted {
+                assert!(resp.status().is_success());
+            } else {
+                assert_eq!(resp.status(), actix_web::http::StatusCode::BAD_REQUEST);
+                let body = String::from_utf8(test::read_body(resp).await.to_vec()).unwrap();
+                assert!(body.contains("minimum relay fee"), "{}", body);
+                let resp = test::call_service(&app, transact_request(10_000)).await;
+                assert!(resp.status().is_success());
+            }
+        }
+    }
+
+    #[actix_web::test]
+    async fn test_transact_batch_is_all_or_nothing() {
+        let (app, miner_private_key) = setup_test_app().await;
+        for _ in 0..4 {
+            let req = test::TestRequest::post().uri("/mine").to_request();
+            test::call_service(&app, req).await;
+        }
+        let req = test::TestRequest::get().uri("/blocks").to_request();
+        let blocks: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        let reward = blocks[1]["transactions"][0]["outputs"][0]["value"].as_u64().unwrap();
+        let payment = |amount: u64| {
+            serde_json::json!({ "to": "recipient", "amount": amount, "private_key": miner_private_key })
+        };
+
+        // Four block rewards pay for four whole-reward payments, but not a fifth.
+        let batch: Vec<serde_json::Value> = (0..5).map(|_| payment(reward)).collect();
+        let req = test::TestRequest::post().uri("/transact/batch").set_json(&batch).to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), actix_web::http::StatusCode::BAD_REQUEST);
+        let errors: serde_json::Value = test::read_body_json(resp).await;
+        let errors = errors.as_array().unwrap();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0]["index"], 4);
+        assert!(errors[0]["error"].as_str().unwrap().starts_with("Not enough funds"));
+        let req = test::TestRequest::get().uri("/mempool").to_request();
+        let mempool: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(mempool["transactions"], 0);
+
+        // Transaction 3 asks for more than any output holds; the rest are affordable.
+        let mut batch: Vec<serde_json::Value> = (0..5).map(|_| payment(1)).collect();
+        batch[2] = payment(reward * 10);
+        let req = test::TestRequest::post().uri("/transact/batch").set_json(&batch).to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), actix_web::http::StatusCode::BAD_REQUEST);
+        let errors: serde_json::Value = test::read_body_json(resp).await;
+        assert_eq!(errors.as_array().unwrap().len(), 1);
+        assert_eq!(errors[0]["index"], 2);
+        assert!(errors[0]["error"].as_str().unwrap().starts_with("Not enough funds"));
+        let req = test::TestRequest::get().uri("/mempool").to_request();
+        let mempool: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(mempool["transactions"], 0);
+
+        let batch: Vec<serde_json::Value> = (0..4).map(|_| payment(1)).collect();
+        let req = test::TestRequest::post().uri("/transact/batch").set_json(&batch).to_request();
+        let transactions: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(transactions.as_array().unwrap().len(), 4);
+        let req = test::TestRequest::get().uri("/mempool").to_request();
+        let mempool: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(mempool["transactions"], 4);
+    }
+
+    async fn get_balance_of(
+        app: &impl Service<Request, Response = ServiceResponse, Error = actix_web::Error>,
+        address: &str,
+    ) -> u64 {
+        let req = test::TestRequest::get().uri(&format!("/address/{}/balance", address)).to_request();
+        let balance: serde_json::Value = test::call_and_read_body_json(app, req).await;
+        balance.as_u64().unwrap()
+    }
+
+    fn faucet_request(address: &str, amount: u64) -> Request {
+        test::TestRequest::post()
+            .uri("/faucet")
+            .set_json(serde_json::json!({ "address": address, "amount": amount }))
+            .to_request()
+    }
+
+    #[actix_web::test]
+    async fn test_regtest_faucet_mines_payout_and_rate_limits() {
+        let (app, _) = setup_test_app_on(MempoolPool::default(), NetworkId::Regtest).await;
+        let tester = format!("tester-{}", rand::random::<u64>());
+
+        let resp = test::call_service(&app, faucet_request(&tester, 0)).await;
+        assert_eq!(resp.status(), actix_web::http::StatusCode::BAD_REQUEST);
+
+        let payout: serde_json::Value = test::call_and_read_body_json(&app, faucet_request(&tester, 100)).await;
+        assert_eq!(payout["method"], "mined");
+        assert!(payout["block_index"].is_u64());
+        assert_eq!(get_balance_of(&app, &tester).await, 100);
+
+        let resp = test::call_service(&app, faucet_request(&tester, 100)).await;
+        assert_eq!(resp.status(), actix_web::http::StatusCode::TOO_MANY_REQUESTS);
+        let retry_after: i64 = resp.headers().get("Retry-After").unwrap().to_str().unwrap().parse().unwrap();
+        assert!(retry_after > 0 && retry_after <= api::faucet::ADDRESS_COOLDOWN_SECS);
+        assert_eq!(get_balance_of(&app, &tester).await, 100);
+
+        let other = format!("tester-{}", rand::random::<u64>());
+        let resp = test::call_service(&app, faucet_request(&other, 50)).await;
+        assert!(resp.status().is_success());
+
+        let req = test::TestRequest::get().uri("/faucet/stats").to_request();
+        let stats: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(stats["payouts"], 2);
+        assert_eq!(stats["total_issued"], 150);
+        assert_eq!(stats["unique_addresses"], 2);
+    }
+
+    #[actix_web::test]
+    async fn test_faucet_pays_fixed_amount_only_when_enabled() {
+        let (mut state, _, _) = test_state(MempoolPool::default(), NetworkId::Regtest);
+        state.faucet = FaucetConfig::default();
+        let app = init_test_app(state.clone()).await;
+        let tester = format!("tester-{}", rand::random::<u64>());
+        let request = || test::TestRequest::post().uri("/faucet").set_json(serde_json::json!({ "address": tester })).to_request();
+        let resp = test::call_service(&app, request()).await;
+        assert_eq!(resp.status(), actix_web::http::StatusCode::FORBIDDEN);
+
+        state.faucet.enabled = true;
+        let app = init_test_app(state).await;
+        let payout: serde_json::Value = test::call_and_read_body_json(&app, request()).await;
+        assert_eq!(payout["amount"], DEFAULT_FAUCET_AMOUNT);
+        assert_eq!(get_balance_of(&app, &tester).await, DEFAULT_FAUCET_AMOUNT);
+
+        let resp = test::call_service(&app, request()).await;
+        assert_eq!(resp.status(), actix_web::http::StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(get_balance_of(&app, &tester).await, DEFAULT_FAUCET_AMOUNT);
+    }
+
+    #[actix_web::test]
+    async fn test_testnet_faucet_pays_from_node_wallet() {
+        let (app, _) = setup_test_app_on(MempoolPool::default(), NetworkId::Testnet).await;
+        let tester = format!("tester-{}", rand::random::<u64>());
+
+        // The node wallet hasn't mined anything yet, so it has nothing to give.
+        let resp = test::call_service(&app, faucet_request(&tester, 100)).await;
+        assert_eq!(resp.status(), actix_web::http::StatusCode::SERVICE_UNAVAILABLE);
+
+        let req = test::TestRequest::post().uri("/mine").to_request();
+        test::call_service(&app, req).await;
+        let payout: serde_json::Value = test::call_and_read_body_json(&app, faucet_request(&tester, 100)).await;
+        assert_eq!(payout["method"], "transaction");
+        assert_eq!(get_balance_of(&app, &tester).await, 0);
+
+        let req = test::TestRequest::post().uri("/mine").to_request();
+        test::call_service(&app, req).await;
+        assert_eq!(get_balance_of(&app, &tester).await, 100);
+    }
+
+    #[actix_web::test]
+    async fn test_nft_claim_and_double_claim() {
+        let (app, miner_private_key) = setup_test_app().await;
+        let fractal_type = fractal::FractalType::Sierpinski { depth: 4, seed: 42 };
+        let fractal_hash = crate::core::nft::fractal_hash(&fractal_type);
+        let claim = serde_json::json!({ "fractal_type": fractal_type, "private_key": miner_private_key });
+
+        let req = test::TestRequest::post().uri("/nft/claim").set_json(&claim).to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), actix_web::http::StatusCode::BAD_REQUEST);
+
+        let req = test::TestRequest::post().uri("/mine").to_request();
+        test::call_service(&app, req).await;
+        let req = test::TestRequest::post().uri("/nft/claim").set_json(&claim).to_request();
+        let claimed: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(claimed["fractal_hash"], fractal_hash);
+
+        let req = test::TestRequest::get().uri(&format!("/nft/owner?fractal_hash={}", fractal_hash)).to_request();
+        let owner: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(owner["owner_address"], claimed["owner_address"]);
+
+        let req = test::TestRequest::post().uri("/wallet").to_request();
+        let rival: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        let rival_claim = serde_json::json!({ "fractal_type": fractal_type, "private_key": rival["private_key"] });
+        for claim in [&claim, &rival_claim] {
+            let req = test::TestRequest::post().uri("/nft/claim").set_json(claim).to_request();
+            let resp = test::call_service(&app, req).await;
+            assert_eq!(resp.status(), actix_web::http::StatusCode::CONFLICT);
+        }
+
+        // The fee is burned once the claim's transaction is mined.
+        let req = test::TestRequest::post().uri("/mine").to_request();
+        test::call_service(&app, req).await;
+        let req = test::TestRequest::get().uri("/supply").to_request();
+        let supply: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(supply["burned"], crate::core::nft::NFT_CLAIM_FEE_COINS * blockchain::chain::COIN);
+
+        let req = test::TestRequest::get().uri("/nft/owner?fractal_hash=unclaimed").to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), actix_web::http::StatusCode::NOT_FOUND);
+    }
+
+    #[actix_web::test]
+    async fn test_label_round_trips() {
+        let (app, _) = setup_test_app().await;
+        let req = test::TestRequest::post().uri("/mine").to_request();
+        let block: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        let txid = block["transactions"][0]["id"].as_str().unwrap();
+
+        let req = test::TestRequest::get().uri(&format!("/labels/{}", txid)).to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), actix_web::http::StatusCode::NOT_FOUND);
+
+        let req = test::TestRequest::post()
+            .uri(&format!("/labels/{}", txid))
+            .set_json(serde_json::json!({ "label": "rent payment" }))
+            .to_request();
+        let saved: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(saved, serde_json::json!({ "txid": txid, "label": "rent payment" }));
+        let req = test::TestRequest::get().uri(&format!("/labels/{}", txid)).to_request();
+        let label: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(label, saved);
+
+        let req = test::TestRequest::post()
+            .uri(&format!("/labels/{}", txid))
+            .set_json(serde_json::json!({ "label": " " }))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), actix_web::http::StatusCode::BAD_REQUEST);
+    }
+
+    #[actix_web::test]
+    async fn test_faucet_is_forbidden_on_mainnet() {
+        let (app, _) = setup_test_app_on(MempoolPool::default(), NetworkId::Mainnet).await;
+        let resp = test::call_service(&app, faucet_request("tester", 100)).await;
+        assert_eq!(resp.status(), actix_web::http::StatusCode::FORBIDDEN);
+        let req = test::TestRequest::get().uri("/faucet/stats").to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), actix_web::http::StatusCode::FORBIDDEN);
+
+        let req = test::TestRequest::get().uri("/status").to_request();
+        let status: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(status["network"], "mainnet");
+    }
+
+    #[actix_web::test]
+    async fn test_transact_with_expiry() {
+        let (app, miner_private_key) = setup_test_app().await;
+        let req = test::TestRequest::post().uri("/mine").to_request();
+        test::call_service(&app, req).await;
+        let transact_request = |expire_after_blocks: u64| {
+            test::TestRequest::post()
+                .uri("/transact")
+                .set_json(serde_json::json!({
+                    "to": "someone",
+                    "amount": 1,
+                    "private_key": miner_private_key,
+                    "expire_after_blocks": expire_after_blocks
+                }))
+                .to_request()
+        };
+
+        let resp = test::call_service(&app, transact_request(0)).await;
+        assert_eq!(resp.status(), actix_web::http::StatusCode::BAD_REQUEST);
+
+        let req = test::TestRequest::get().uri("/blocks").to_request();
+        let blocks: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        let next_height = blocks.as_array().unwrap().len() as u64;
+        let tx: serde_json::Value = test::call_and_read_body_json(&app, transact_request(2)).await;
+        assert_eq!(tx["expiry_height"], next_height + 2);
+
+        let req = test::TestRequest::post().uri("/mine").to_request();
+        test::call_service(&app, req).await;
+        let req = test::TestRequest::get().uri("/blocks").to_request();
+        let blocks: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        let mined = blocks.as_array().unwrap().last().unwrap();
+        assert_eq!(mined["index"], next_height);
+        assert!(mined["transactions"].as_array().unwrap().iter().any(|mined_tx| mined_tx["id"] == tx["id"]));
+    }
+
+    #[actix_web::test]
+    async fn test_mining_target_endpoint() {
+        let (app, _) = setup_test_app().await;
+        let req = test::TestRequest::get().uri("/mining/target").to_request();
+        let target: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(target["difficulty"], 1);
+        assert_eq!(target["target_prefix"], "0");
+        assert_eq!(target["estimated_hashes"], 16);
+        assert!(target["estimated_seconds_at_current_hashrate"].is_null());
+
+        let req = test::TestRequest::post().uri("/mine").to_request();
+        test::call_service(&app, req).await;
+        let req = test::TestRequest::get().uri("/mining/target").to_request();
+        let target: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        assert!(target["estimated_seconds_at_current_hashrate"].as_f64().unwrap() > 0.0);
+    }
+
+    #[actix_web::test]
+    async fn test_mine_estimate_endpoint() {
+        let (app, _) = setup_test_app().await;
+        let req = test::TestRequest::get().uri("/mine/estimate").to_request();
+        let estimate: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        assert!(estimate["estimated_seconds"].is_null());
+
+        for _ in 0..2 {
+            let req = test::TestRequest::post().uri("/mine").to_request();
+            test::call_service(&app, req).await;
+        }
+        let req = test::TestRequest::get().uri("/mine/estimate?fractal=sierpinski").to_request();
+        let estimate: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        let seconds = estimate["estimated_seconds"].as_f64().unwrap();
+        let hashrate = estimate["recent_hashrate"].as_f64().unwrap();
+        assert!(seconds.is_finite() && seconds > 0.0);
+        // Each extra leading zero makes a block 16 times as much work.
+        let difficulty = estimate["difficulty"].as_u64().unwrap() as usize;
+        let harder = mining::miner::MiningTarget::new(blockchain::target::Target::from_difficulty(difficulty + 1).to_compact(), Some(hashrate));
+        let ratio = harder.estimated_seconds_at_current_hashrate.unwrap() / seconds;
+        assert!((ratio - 16.0).abs() < 1e-6);
+
+        let req = test::TestRequest::get().uri("/mine/estimate?fractal=julia").to_request();
+        let estimate: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        assert!(estimate["estimated_seconds"].is_null());
+        let req = test::TestRequest::get().uri("/mine/estimate?fractal=koch").to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), actix_web::http::StatusCode::BAD_REQUEST);
+    }
+
+    #[actix_web::test]
+    async fn test_decode_signed_transaction() {
+        let (app, miner_private_key) = setup_test_app().await;
+        let req = test::TestRequest::post().uri("/mine").to_request();
+        test::call_service(&app, req).await;
+        let wallet = api::handlers::wallet_from_private_key(&miner_private_key).unwrap();
+        let req = test::TestRequest::get().uri(&format!("/address/{}/utxos", wallet.get_address())).to_request();
+        let utxos: Vec<(String, usize, core::transaction::TxOutput)> = test::call_and_read_body_json(&app, req).await;
+        let (txid, vout, spent) = utxos.last().unwrap().clone();
+
+        let mut tx = core::transaction::Transaction::new(
+            vec![core::transaction::TxInput {
+                txid,
+                vout,
+                script_sig: core::script::UnlockingScript::default(),
+                sequence: 0,
+            }],
+            vec![core::transaction::TxOutput { value: spent.value - 1, script_pub_key: "someone".into() }],
+        );
+        tx.sign(&wallet);
+
+        let bodies = [serde_json::to_value(&tx).unwrap(), serde_json::json!({ "hex": hex::encode(tx.to_bincode()) })];
+        for body in bodies {
+            let req = test::TestRequest::post().uri("/tx/decode").set_json(&body).to_request();
+            let decoded: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+            assert_eq!(decoded["id"], tx.id);
+            assert_eq!(decoded["id_matches"], true);
+            assert_eq!(decoded["hex"], hex::encode(tx.to_bincode()));
+            assert_eq!(decoded["inputs"][0]["coinbase"], false);
+            assert_eq!(decoded["inputs"][0]["signature_valid"], true);
+            assert_eq!(decoded["outputs"][0]["address"], "someone");
+            assert_eq!(decoded["total_in"], spent.value);
+            assert_eq!(decoded["fee"], 1);
+            assert_eq!(decoded["signatures_valid"], true);
+        }
+
+        // Signed by someone else, the same spend is reported as invalid.
+        tx.sign(&core::wallet::Wallet::new());
+        let req = test::TestRequest::post().uri("/tx/decode").set_json(&tx).to_request();
+        let decoded: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(decoded["inputs"][0]["signature_valid"], false);
+        assert_eq!(decoded["signatures_valid"], false);
+
+        let req = test::TestRequest::post().uri("/tx/decode").set_json(serde_json::json!({ "hex": "zz" })).to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), actix_web::http::StatusCode::BAD_REQUEST);
+    }
+
+    #[actix_web::test]
+    async fn test_cli_signed_transaction_is_submitted_raw_and_mined() {
+        let (app, miner_private_key) = setup_test_app().await;
+        let req = test::TestRequest::post().uri("/mine").to_request();
+        test::call_service(&app, req).await;
+        let wallet = api::handlers::wallet_from_private_key(&miner_private_key).unwrap();
+        let req = test::TestRequest::get().uri(&format!("/address/{}/utxos", wallet.get_address())).to_request();
+        let utxos: Vec<(String, usize, core::transaction::TxOutput)> = test::call_and_read_body_json(&app, req).await;
+        let (txid, vout, spent) = utxos.last().unwrap().clone();
+
+        // Built offline, without an ID or signatures.
+        let mut unsigned = serde_json::to_value(core::transaction::Transaction::new(
+            vec![core::transaction::TxInput {
+                txid,
+                vout,
+                script_sig: core::script::UnlockingScript::default(),
+                sequence: 0,
+            }],
+            vec![core::transaction::TxOutput { value: spent.value - 10, script_pub_key: "someone".into() }],
+        ))
+        .unwrap();
+        unsigned["id"] = "".into();
+        let wallets_file = std::env::temp_dir().join(format!("sierpchain-wallets-{}.json", rand::random::<u64>()));
+        let mut key_store = KeyStore::load(&wallets_file).unwrap();
+        key_store.merge(vec![core::keystore::StoredWallet::from(&wallet)]);
+        assert!(sign_transaction(&unsigned.to_string(), &key_store, Some("nobody"), RawFormat::Hex).is_err());
+        let signed = sign_transaction(&unsigned.to_string(), &key_store, None, RawFormat::Hex).unwrap();
+
+        let req = test::TestRequest::post().uri("/tx/raw").set_json(&signed).to_request();
+        let accepted: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(accepted["fee"], 10);
+        let txid = accepted["txid"].as_str().unwrap().to_string();
+        let req = test::TestRequest::post().uri("/tx/raw").set_json(&signed).to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), actix_web::http::StatusCode::BAD_REQUEST);
+        let rejected: serde_json::Value = test::read_body_json(resp).await;
+        assert_eq!(rejected["details"]["reason"], "already_known");
+
+        // Pending, it can be fetched as JSON, which is also submittable.
+        let req = test::TestRequest::get().uri(&format!("/tx/{}/raw?format=json", txid)).to_request();
+        let pending: core::transaction::Transaction = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(pending.id, txid);
+        assert_eq!(sign_transaction(&unsigned.to_string(), &key_store, None, RawFormat::Json).unwrap(), serde_json::to_value(&pending).unwrap());
+
+        let req = test::TestRequest::post().uri("/mine").to_request();
+        let block: blockchain::block::Block = test::call_and_read_body_json(&app, req).await;
+        assert!(block.transactions.iter().any(|tx| tx.id == txid));
+        let req = test::TestRequest::get().uri(&format!("/tx/{}/raw", txid)).to_request();
+        let confirmed: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(confirmed, signed);
+
+        let req = test::TestRequest::get().uri(&format!("/tx/{}/raw", "f".repeat(64))).to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), actix_web::http::StatusCode::NOT_FOUND);
+        std::fs::remove_file(wallets_file).ok();
+    }
+
+    #[actix_web::test]
+    async fn test_filter_block_keeps_only_relevant_transactions() {
+        let (app, miner_private_key) = setup_test_app().await;
+        let miner_address = api::handlers::wallet_from_private_key(&miner_private_key).unwrap().get_address();
+        let req = test::TestRequest::post().uri("/mine").to_request();
+        test::call_service(&app, req).await;
+        let transact_req = serde_json::json!({
+            "to": "someone_else",
+            "amount": 1000,
+            "private_key": miner_private_key,
+        });
+        let req = test::TestRequest::post().uri("/transact").set_json(&transact_req).to_request();
+        let payment: core::transaction::Transaction = test::call_and_read_body_json(&app, req).await;
+        let req = test::TestRequest::post().uri("/mine").to_request();
+        let block: blockchain::block::Block = test::call_and_read_body_json(&app, req).await;
+        assert!(block.transactions.iter().any(|tx| tx.id == payment.id));
+
+        let filter = |address: &str| {
+            test::TestRequest::post()
+                .uri("/wallet/filter-block")
+                .set_json(serde_json::json!({ "address": address, "block": block }))
+                .to_request()
+        };
+        // Neither the coinbase nor the payment touches an unrelated address.
+        let filtered: serde_json::Value = test::call_and_read_body_json(&app, filter("unrelated_address")).await;
+        assert!(filtered["transactions"].as_array().unwrap().is_empty());
+        assert_eq!(filtered["net_change"], 0);
+
+        let filtered: serde_json::Value = test::call_and_read_body_json(&app, filter("someone_else")).await;
+        let relevant = filtered["transactions"].as_array().unwrap();
+        assert_eq!(relevant.len(), 1);
+        assert_eq!(relevant[0]["txid"], payment.id);
+        assert_eq!(relevant[0]["net_change"], 1000);
+
+        let filtered: serde_json::Value = test::call_and_read_body_json(&app, filter(&miner_address)).await;
+        let txids: Vec<&str> = filtered["transactions"].as_array().unwrap().iter().map(|tx| tx["txid"].as_str().unwrap()).collect();
+        assert!(txids.contains(&payment.id.as_str()));
+        let payment_activity = filtered["transactions"].as_array().unwrap().iter().find(|tx| tx["txid"] == payment.id).unwrap();
+        assert!(payment_activity["net_change"].as_i64().unwrap() <= -1000);
+    }
+
+    #[actix_web::test]
+    async fn test_block_mined_from_template_is_accepted() {
+        let (app, _) = setup_test_app().await;
+        let req = test::TestRequest::get().uri("/mining/template?address=browser_miner").to_request();
+        let template: mining::miner::BlockTemplate = test::call_and_read_body_json(&app, req).await;
+        let coinbase = &template.block.transactions[0];
+        assert!(coinbase.outputs[0].script_pub_key.pays_to("browser_miner"));
+
+        let balance = get_balance_of(&app, "browser_miner").await;
+        let block = mining::miner::Miner::mine_template(&template, 0, u64::MAX).unwrap();
+        assert_eq!(template.work_factor, 1);
+        assert_eq!(template.effective_target, blockchain::target::Target::from_compact(template.block.bits).to_string());
+        assert!(template.target().is_met_by(&block.hash));
+        let req = test::TestRequest::post().uri("/mining/submit").set_json(&block).to_request();
+        let resp = test::call_service(&app, req).await;
+        assert!(resp.status().is_success());
+        assert_eq!(get_balance_of(&app, "browser_miner").await, balance + coinbase.outputs[0].value);
+
+        // The chain has moved on, so the same template is now stale.
+        let req = test::TestRequest::post().uri("/mining/submit").set_json(&block).to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), actix_web::http::StatusCode::CONFLICT);
+    }
+
+    #[actix_web::test]
+    async fn test_block_mined_in_the_browser_is_accepted() {
+        let (app, _) = setup_test_app().await;
+        let req = test::TestRequest::get().uri("/mining/template?address=browser_miner").to_request();
+        let mut template: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+
+        // What the frontend's worker does: search the template's JSON with
+        // the shared miner, then fill the nonce in to its block.
+        let mining: sierpchain_shared::mining::MiningTemplate = serde_json::from_value(template.clone()).unwrap();
+        let mined = mining.mine(0, u64::MAX).unwrap();
+        let block = &mut template["block"];
+        block["nonce"] = mined.nonce.into();
+        block["fractal"] = serde_json::to_value(&mined.fractal).unwrap();
+        block["hash"] = mined.hash.clone().into();
+        let req = test::TestRequest::post().uri("/mining/submit").set_json(&*block).to_request();
+        let accepted: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(accepted["hash"], mined.hash);
+    }
+
+    #[actix_web::test]
+    async fn test_transaction_lookup_through_index() {
+        let (app, _) = setup_test_app().await;
+        let req = test::TestRequest::post().uri("/mine").to_request();
+        let block: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        let coinbase = &block["transactions"][0];
+
+        let uri = format!("/transaction/{}", coinbase["id"].as_str().unwrap());
+        let req = test::TestRequest::get().uri(&uri).to_request();
+        let found: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(found["block_hash"], block["hash"]);
+        assert_eq!(found["block_index"], block["index"]);
+        assert_eq!(found["position"], 0);
+        assert_eq!(found["confirmations"], 1);
+        assert_eq!(&found["transaction"], coinbase);
+
+        let req = test::TestRequest::get().uri(&format!("/transaction/{}", "0".repeat(64))).to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), actix_web::http::StatusCode::NOT_FOUND);
+    }
+
+    #[actix_web::test]
+    async fn test_known_peers_endpoint() {
+        let (app, _) = setup_test_app().await;
+        let req = test::TestRequest::get().uri("/peers/known").to_request();
+        let peers: Vec<serde_json::Value> = test::call_and_read_body_json(&app, req).await;
+        assert!(peers.is_empty());
+    }
+
+    #[actix_web::test]
+    async fn test_oversized_json_body_is_rejected() {
+        let (app, miner_private_key) = setup_test_app().await;
+        let transact_req = serde_json::json!({
+            "to": "x".repeat(api::handlers::DEFAULT_JSON_LIMIT + 1),
+            "amount": 10,
+            "private_key": miner_private_key
+        });
+        let req = test::TestRequest::post().uri("/transact").set_json(&transact_req).to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), actix_web::http::StatusCode::PAYLOAD_TOO_LARGE);
+
+        let body: serde_json::Value = test::read_body_json(resp).await;
+        assert!(body["error"].is_string());
+    }
+
+    #[actix_web::test]
+    async fn test_block_submission_has_a_larger_json_limit() {
+        let (app, _) = setup_test_app().await;
+        // Too big for any other endpoint, so a 400 shows the body was read.
+        let body = serde_json::json!({ "padding": "x".repeat(api::handlers::DEFAULT_JSON_LIMIT + 1) });
+        let req = test::TestRequest::post().uri("/mining/submit").set_json(&body).to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), actix_web::http::StatusCode::BAD_REQUEST);
+
+        let body = serde_json::json!({ "padding": "x".repeat(api::handlers::DEFAULT_BLOCK_JSON_LIMIT + 1) });
+        let req = test::TestRequest::post().uri("/mining/submit").set_json(&body).to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), actix_web::http::StatusCode::PAYLOAD_TOO_LARGE);
+    }
+
+    #[actix_web::test]
+    async fn test_peers_connect_enqueues_dial() {
+        let (app, _, mut control_receiver) = setup_test_app_with_control(MempoolPool::default(), NetworkId::Regtest).await;
+        let admin = ("Authorization", format!("Bearer {}", TEST_ADMIN_KEY));
+        let multiaddr = "/ip4/127.0.0.1/tcp/4002";
+        let req = test::TestRequest::post()
+            .uri("/peers/connect")
+            .set_json(serde_json::json!({ "multiaddr": multiaddr }))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), actix_web::http::StatusCode::UNAUTHORIZED);
+
+        let req = test::TestRequest::post()
+            .uri("/peers/connect")
+            .insert_header(admin.clone())
+            .set_json(serde_json::json!({ "multiaddr": "not a multiaddr" }))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), actix_web::http::StatusCode::BAD_REQUEST);
+        assert!(control_receiver.try_recv().is_err());
+
+        let req = test::TestRequest::post()
+            .uri("/peers/connect")
+            .insert_header(admin)
+            .set_json(serde_json::json!({ "multiaddr": multiaddr }))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), actix_web::http::StatusCode::ACCEPTED);
+        match control_receiver.try_recv() {
+            Ok(P2pControl::Dial(addr)) => assert_eq!(addr.to_string(), multiaddr),
+            other => panic!("expected a dial request, got {:?}", other),
+        }
+    }
+
+    #[actix_web::test]
+    async fn test_wallet_backup_restores_through_the_api() {
+        let (app, miner_private_key) = setup_test_app().await;
+        let passphrase = (api::wallet::PASSPHRASE_HEADER, "correct horse");
+        let admin = ("Authorization", format!("Bearer {}", TEST_ADMIN_KEY));
+
+        let req = test::TestRequest::get().uri("/wallet/backup").insert_header(passphrase).to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), actix_web::http::StatusCode::UNAUTHORIZED);
+        let req = test::TestRequest::get().uri("/wallet/backup").insert_header(admin.clone()).to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), actix_web::http::StatusCode::BAD_REQUEST);
+
+        let req = test::TestRequest::get().uri("/wallet/backup").insert_header(admin.clone()).insert_header(passphrase).to_request();
+        let backup: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(backup["version"], core::wallet_backup::BACKUP_VERSION);
+        assert!(!backup.to_string().contains(&miner_private_key));
+
+        let req = test::TestRequest::post()
+            .uri("/wallet/restore?mode=replace")
+            .insert_header(admin.clone())
+            .insert_header((api::wallet::PASSPHRASE_HEADER, "battery staple"))
+            .set_json(&backup)
+            .to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), actix_web::http::StatusCode::FORBIDDEN);
+
+        let req = test::TestRequest::post()
+            .uri("/wallet/restore?mode=replace")
+            .insert_header(admin.clone())
+            .insert_header(passphrase)
+            .set_json(&backup)
+            .to_request();
+        let restored: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(restored, serde_json::json!({ "restored": 1, "wallets": 1 }));
+
+        let mut future = backup.clone();
+        future["version"] = serde_json::json!(core::wallet_backup::BACKUP_VERSION + 1);
+        let req = test::TestRequest::post()
+            .uri("/wallet/restore")
+            .insert_header(admin)
+            .insert_header(passphrase)
+            .set_json(&future)
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), actix_web::http::StatusCode::BAD_REQUEST);
+        let body: serde_json::Value = test::read_body_json(resp).await;
+        assert!(body["error"].as_str().unwrap().contains("not supported"));
+    }
+
+    #[actix_web::test]
+    async fn test_stored_wallet_spends_are_limited_and_approved() {
+        let (app, miner_private_key) = setup_test_app().await;
+        let admin = ("Authorization", format!("Bearer {}", TEST_ADMIN_KEY));
+        let req = test::TestRequest::post().uri("/mine").to_request();
+        test::call_service(&app, req).await;
+        let req = test::TestRequest::get().uri("/wallet/info").to_request();
+        let info: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        let policy_uri = format!("/wallet/{}/policy", info["address"].as_str().unwrap());
+
+        let policy = serde_json::json!({ "max_per_transaction": 500, "max_per_day": 700, "approval_threshold": 100 });
+        let req = test::TestRequest::put().uri(&policy_uri).set_json(&policy).to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), actix_web::http::StatusCode::UNAUTHORIZED);
+        let req = test::TestRequest::put().uri(&policy_uri).insert_header(admin.clone()).set_json(&policy).to_request();
+        assert_eq!(test::call_and_read_body_json::<_, _, serde_json::Value>(&app, req).await, policy);
+
+        let receiver = Wallet::new().get_address();
+        let send = |amount: u64| {
+            let body = serde_json::json!({ "to": receiver, "amount": amount, "private_key": miner_private_key });
+            test::TestRequest::post().uri("/transact").set_json(body).to_request()
+        };
+        let resp = test::call_service(&app, send(501)).await;
+        assert_eq!(resp.status(), actix_web::http::StatusCode::FORBIDDEN);
+        let body: serde_json::Value = test::read_body_json(resp).await;
+        assert_eq!(body["details"]["reason"], "over_transaction_limit");
+
+        // Small payments go straight out; larger ones wait for approval.
+        assert!(test::call_service(&app, send(100)).await.status().is_success());
+        let resp = test::call_service(&app, send(400)).await;
+        assert_eq!(resp.status(), actix_web::http::StatusCode::ACCEPTED);
+        let queued: serde_json::Value = test::read_body_json(resp).await;
+        assert_eq!(queued["status"]["state"], "pending");
+        let resp = test::call_service(&app, send(300)).await;
+        let rejected: serde_json::Value = test::read_body_json(resp).await;
+
+        let req = test::TestRequest::get().uri("/approvals").insert_header(admin.clone()).to_request();
+        let approvals: Vec<serde_json::Value> = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(approvals.len(), 2);
+
+        // Mine the first payment so its change can fund the approved ones.
+        let req = test::TestRequest::post().uri("/mine").to_request();
+        test::call_service(&app, req).await;
+        let approve = format!("/approvals/{}/approve", queued["id"]);
+        let req = test::TestRequest::post().uri(&approve).insert_header(admin.clone()).to_request();
+        let approved: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(approved["approval"]["status"]["state"], "approved");
+        let req = test::TestRequest::get().uri(&format!("/mempool/{}/relatives", approved["tracking_id"].as_str().unwrap())).to_request();
+        assert!(test::call_service(&app, req).await.status().is_success());
+        let req = test::TestRequest::post().uri(&approve).insert_header(admin.clone()).to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), actix_web::http::StatusCode::CONFLICT);
+
+        // 500 of the 700 a day is spent, so the other approval would break the daily limit.
+        let req = test::TestRequest::post().uri(&format!("/approvals/{}/approve", rejected["id"])).insert_header(admin.clone()).to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), actix_web::http::StatusCode::FORBIDDEN);
+        let body: serde_json::Value = test::read_body_json(resp).await;
+        assert_eq!(body["details"]["reason"], "over_daily_limit");
+        let req = test::TestRequest::post().uri(&format!("/approvals/{}/reject", rejected["id"])).insert_header(admin).to_request();
+        let decided: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(decided["status"]["state"], "rejected");
+    }
+
+    #[actix_web::test]
+    async fn test_import_and_sweep_stranded_wallet() {
+        let (app, miner_private_key) = setup_test_app().await;
+        let admin = ("Authorization", format!("Bearer {}", TEST_ADMIN_KEY));
+        let req = test::TestRequest::post().uri("/mine").to_request();
+        test::call_service(&app, req).await;
+
+        // Fund a wallet the node then forgets about.
+        let req = test::TestRequest::post().uri("/wallet").to_request();
+        let stranded: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        let req = test::TestRequest::post()
+            .uri("/transact")
+            .set_json(serde_json::json!({ "to": stranded["address"], "amount": 1_000, "private_key": miner_private_key }))
+            .to_request();
+        assert!(test::call_service(&app, req).await.status().is_success());
+        let req = test::TestRequest::post().uri("/mine").to_request();
+        test::call_service(&app, req).await;
+
+        let import = |private_key: &serde_json::Value| {
+            test::TestRequest::post()
+                .uri("/wallet/import")
+                .insert_header(admin.clone())
+                .set_json(serde_json::json!({ "private_key": private_key, "label": "stranded", "rescan": true }))
+                .to_request()
+        };
+        let resp = test::call_service(&app, import(&serde_json::json!("not-a-key"))).await;
+        assert_eq!(resp.status(), actix_web::http::StatusCode::BAD_REQUEST);
+        let imported: serde_json::Value = test::call_and_read_body_json(&app, import(&stranded["private_key"])).await;
+        assert_eq!(imported["address"], stranded["address"]);
+        assert_eq!(imported["imported"], true);
+        assert_eq!(imported["balance"], 1_000);
+        assert_eq!(imported["utxos"].as_array().unwrap().len(), 1);
+        let again: serde_json::Value = test::call_and_read_body_json(&app, import(&stranded["private_key"])).await;
+        assert_eq!(again["imported"], false);
+
+        let destination = format!("destination-{}", rand::random::<u64>());
+        let sweep = || {
+            test::TestRequest::post()
+                .uri("/wallet/sweep")
+                .set_json(serde_json::json!({ "from_private_key": stranded["private_key"], "to_address": destination, "fee": 10 }))
+                .to_request()
+        };
+        let swept: serde_json::Value = test::call_and_read_body_json(&app, sweep()).await;
+        assert_eq!(swept["amount"], 990);
+        assert_eq!(swept["from_balance"], 0);
+        assert_eq!(swept["to_balance"], 990);
+        // Its outputs are spent by the pending sweep, so there's nothing left.
+        let resp = test::call_service(&app, sweep()).await;
+        assert_eq!(resp.status(), actix_web::http::StatusCode::BAD_REQUEST);
+
+        let req = test::TestRequest::post().uri("/mine").to_request();
+        test::call_service(&app, req).await;
+        assert_eq!(get_balance_of(&app, &destination).await, 990);
+        assert_eq!(get_balance_of(&app, stranded["address"].as_str().unwrap()).await, 0);
+    }
+
+    #[actix_web::test]
+    async fn test_peers_disconnect_unknown_peer_is_not_found() {
+        let (app, _, mut control_receiver) = setup_test_app_with_control(MempoolPool::default(), NetworkId::Regtest).await;
+        let admin = ("Authorization", format!("Bearer {}", TEST_ADMIN_KEY));
+        let peer_id = libp2p::PeerId::random().to_string();
+        let req = test::TestRequest::post()
+            .uri("/peers/disconnect")
+            .set_json(serde_json::json!({ "peer_id": peer_id }))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), actix_web::http::StatusCode::UNAUTHORIZED);
+
+        let req = test::TestRequest::post()
+            .uri("/peers/disconnect")
+            .insert_header(admin.clone())
+            .set_json(serde_json::json!({ "peer_id": peer_id }))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), actix_web::http::StatusCode::NOT_FOUND);
+
+        let req = test::TestRequest::post()
+            .uri("/peers/disconnect")
+            .insert_header(admin)
+            .set_json(serde_json::json!({ "peer_id": "not a peer id" }))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), actix_web::http::StatusCode::BAD_REQUEST);
+        assert!(control_receiver.try_recv().is_err());
+    }
+
+    #[actix_web::test]
+    async fn test_admin_add_peer_dials() {
+        let (app, _, mut control_receiver) = setup_test_app_with_control(MempoolPool::default(), NetworkId::Regtest).await;
+        let multiaddr = "/ip4/127.0.0.1/tcp/4001";
+        let req = test::TestRequest::post()
+            .uri("/admin/peers")
+            .insert_header(("Authorization", format!("Bearer {}", TEST_ADMIN_KEY)))
+            .set_json(serde_json::json!({ "multiaddr": multiaddr }))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), actix_web::http::StatusCode::ACCEPTED);
+
+        match control_receiver.try_recv() {
+            Ok(P2pControl::Dial(addr)) => assert_eq!(addr.to_string(), multiaddr),
+            other => panic!("expected a dial request, got {:?}", other),
+        }
+    }
+
+    #[actix_web::test]
+    async fn test_block_dimension_endpoint() {
+        let (app, _) = setup_test_app().await;
+        let req = test::TestRequest::post().uri("/mine").to_request();
+        let sierpinski: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        let mandelbrot = serde_json::json!({ "type": "Mandelbrot", "params": {
+            "width": 64, "height": 64,
+            "x_min": -2.0, "x_max": 1.0, "y_min": -1.5, "y_max": 1.5,
+            "max_iterations": 50
+        }});
+        let req = test::TestRequest::post().uri("/mine").set_json(mandelbrot).to_request();
+        let mandelbrot: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+
+        let req = test::TestRequest::get().uri(&format!("/blocks/{}/dimension", sierpinski["index"])).to_request();
+        let body: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        let dimension = body["dimension"].as_f64().unwrap();
+        assert!((dimension - 3f64.ln() / 2f64.ln()).abs() < 0.15, "Sierpinski dimension {}", dimension);
+
+        // The Mandelbrot set's boundary is a curve; at this size it measures between a line and a plane.
+        let req = test::TestRequest::get().uri(&format!("/blocks/{}/dimension", mandelbrot["index"])).to_request();
+        let body: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        let dimension = body["dimension"].as_f64().unwrap();
+        assert!((1.0..2.0).contains(&dimension), "Mandelbrot dimension {}", dimension);
+
+        let req = test::TestRequest::get().uri("/blocks/99/dimension").to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), actix_web::http::StatusCode::NOT_FOUND);
+    }
+
+    #[actix_web::test]
+    async fn test_embed_page_shows_the_tip_and_may_be_framed() {
+        let (mut state, _, _) = test_state(MempoolPool::default(), NetworkId::Regtest);
+        state.frame_ancestors = FrameAncestors(vec!["'self'".to_string(), "https://blog.example".to_string()]);
+        let app = init_test_app(state).await;
+        let req = test::TestRequest::post().uri("/mine").to_request();
+        let tip: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+
+        let req = test::TestRequest::get().uri("/embed/latest?width=300&height=150&palette=fire").to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), actix_web::http::StatusCode::OK);
+        let header = |name| resp.headers().get(name).unwrap().to_str().unwrap().to_string();
+        assert_eq!(header(actix_web::http::header::CONTENT_SECURITY_POLICY), "frame-ancestors 'self' https://blog.example");
+        assert!(header(actix_web::http::header::CONTENT_TYPE).starts_with("text/html"));
+        assert!(resp.headers().get(actix_web::http::header::X_FRAME_OPTIONS).is_none());
+        let page = String::from_utf8(test::read_body(resp).await.to_vec()).unwrap();
+        assert!(page.contains(tip["hash"].as_str().unwrap()));
+        assert!(page.contains("width=\"300\" height=\"150\""));
+
+        let req = test::TestRequest::get().uri("/embed/latest?palette=sepia").to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), actix_web::http::StatusCode::BAD_REQUEST);
+    }
+
+    #[actix_web::test]
+    async fn test_verify_block_endpoint() {
+        let (state, miner_private_key, _control_receiver) = test_state(MempoolPool::default(), NetworkId::Regtest);
+        let app = init_test_app(state.clone()).await;
+        let req = test::TestRequest::post().uri("/mine").to_request();
+        test::call_service(&app, req).await;
+        let transact_req = serde_json::json!({ "to": Wallet::new().get_address(), "amount": 10, "private_key": miner_private_key });
+        let req = test::TestRequest::post().uri("/transact").set_json(&transact_req).to_request();
+        assert!(test::call_service(&app, req).await.status().is_success());
+        let req = test::TestRequest::post().uri("/mine").to_request();
+        test::call_service(&app, req).await;
+
+        let all_ok = serde_json::json!({ "hash_ok": true, "pow_ok": true, "link_ok": true, "tx_sigs_ok": true, "overall": true });
+        let req = test::TestRequest::get().uri("/blocks/2/verify").to_request();
+        let verification: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(verification, all_ok);
+
+        state.blockchain.lock().unwrap().chain[2].timestamp += 1;
+        let req = test::TestRequest::get().uri("/blocks/2/verify").to_request();
+        let verification: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(verification["hash_ok"], false);
+        assert_eq!(verification["tx_sigs_ok"], true);
+        assert_eq!(verification["overall"], false);
+
+        let req = test::TestRequest::get().uri("/blocks/3/verify").to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), actix_web::http::StatusCode::NOT_FOUND);
+    }
+
+    #[actix_web::test]
+    async fn test_block_transactions_resolve_inputs_and_fees() {
+        let (app, miner_private_key) = setup_test_app().await;
+        let req = test::TestRequest::get().uri("/wallet/info").to_request();
+        let info: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        let req = test::TestRequest::post().uri("/mine").to_request();
+        test::call_service(&app, req).await;
+        let transact_req = serde_json::json!({ "to": Wallet::new().get_address(), "amount": 10, "fee": 7, "private_key": miner_private_key });
+        let req = test::TestRequest::post().uri("/transact").set_json(&transact_req).to_request();
+        let sent: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        let req = test::TestRequest::post().uri("/mine").to_request();
+        test::call_service(&app, req).await;
+
+        let req = test::TestRequest::get().uri("/blocks/2/transactions").to_request();
+        let body: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(body["block_index"], 2);
+        let transactions = body["transactions"].as_array().unwrap();
+        assert_eq!(transactions.len(), 2);
+        assert_eq!(transactions[0]["coinbase"], true);
+        assert_eq!(transactions[0]["fee"], serde_json::Value::Null);
+        assert_eq!(transactions[0]["inputs"][0]["address"], serde_json::Value::Null);
+
+        let payment = &transactions[1];
+        assert_eq!(payment["id"], sent["id"]);
+        assert_eq!(payment["coinbase"], false);
+        assert_eq!(payment["fee"], 7);
+        // The coinbase output it spends was spent by this very payment, so only the chain still has it.
+        assert_eq!(payment["inputs"][0]["address"], info["address"]);
+        assert_eq!(payment["total_in"], payment["inputs"][0]["value"]);
+        assert_eq!(payment["total_in"].as_u64().unwrap(), payment["total_out"].as_u64().unwrap() + 7);
+
+        let req = test::TestRequest::get().uri("/blocks/3/transactions").to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), actix_web::http::StatusCode::NOT_FOUND);
+    }
+
+    #[actix_web::test]
+    async fn test_coinbase_message_from_request_or_config() {
+        let (app, _) = setup_test_app().await;
+        let req = test::TestRequest::post().uri("/mine?coinbase_message=hello%20chain").to_request();
+        assert!(test::call_service(&app, req).await.status().is_success());
+        let req = test::TestRequest::get().uri("/blocks/1").to_request();
+        let block: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(block["index"], 1);
+        assert_eq!(block["coinbase_message"], "hello chain");
+        assert_eq!(block["transactions"][0]["inputs"][0]["script_sig"], "coinbase:hello chain");
+
+        let config = serde_json::json!({ "coinbase_message": "pool #7" });
+        let req = test::TestRequest::put().uri("/mining/config").set_json(&config).to_request();
+        assert!(test::call_service(&app, req).await.status().is_success());
+        let req = test::TestRequest::get().uri("/mining/template").to_request();
+        let template: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(template["block"]["transactions"][0]["inputs"][0]["script_sig"], "coinbase:pool #7");
+        let req = test::TestRequest::post().uri("/mine").to_request();
+        let mined: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(mined["coinbase_message"], "pool #7");
+
+        let long = "x".repeat(crate::core::transaction::MAX_COINBASE_MESSAGE_BYTES + 1);
+        let req = test::TestRequest::post().uri(&format!("/mine?coinbase_message={}", long)).to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), actix_web::http::StatusCode::BAD_REQUEST);
+        let req = test::TestRequest::put().uri("/mining/config").set_json(serde_json::json!({ "coinbase_message": long })).to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), actix_web::http::StatusCode::BAD_REQUEST);
+        let req = test::TestRequest::get().uri("/blocks/3").to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), actix_web::http::StatusCode::NOT_FOUND);
+    }
+
+    #[actix_web::test]
+    async fn test_admin_storage_ranks_largest_blocks() {
+        let (app, _) = setup_test_app().await;
+        let escape_time = |kind: &str, size: usize| {
+            let mut params = serde_json::json!({
+                "width": size, "height": size,
+                "x_min": -2.0, "x_max": 1.0, "y_min": -1.5, "y_max": 1.5,
+                "max_iterations": 20
+            });
+            if kind == "Julia" {
+                params["c_real"] = serde_json::json!(-0.8);
+                params["c_imag"] = serde_json::json!(0.156);
+            }
+            serde_json::json!({ "type": kind, "params": params })
+        };
+        let mut mined = Vec::new();
+        for params in [None, Some(escape_time("Mandelbrot", 32)), Some(escape_time("Mandelbrot", 8)), Some(escape_time("Julia", 16))] {
+            let mut req = test::TestRequest::post().uri("/mine");
+            if let Some(params) = params {
+                req = req.set_json(params);
+            }
+            let block: serde_json::Value = test::call_and_read_body_json(&app, req.to_request()).await;
+            mined.push(block["index"].as_u64().unwrap());
+        }
+
+        let req = test::TestRequest::get().uri("/admin/storage").to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), actix_web::http::StatusCode::UNAUTHORIZED);
+
+        let req = test::TestRequest::get()
+            .uri("/admin/storage")
+            .insert_header(("Authorization", format!("Bearer {}", TEST_ADMIN_KEY)))
+            .to_request();
+        let storage: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(storage["block_count"], 5);
+        let names: Vec<&str> = storage["files"].as_array().unwrap().iter().map(|file| file["name"].as_str().unwrap()).collect();
+        assert_eq!(names, vec!["chain", "peers", "faucet", "nft_registry", "archive"]);
+
+        let largest = storage["largest_blocks"].as_array().unwrap();
+        assert_eq!(largest.len(), 5);
+        let ranking: Vec<(u64, &str)> = largest[..3]
+            .iter()
+            .map(|block| (block["index"].as_u64().unwrap(), block["fractal_type"].as_str().unwrap()))
+            .collect();
+        assert_eq!(ranking, vec![(mined[1], "Mandelbrot"), (mined[3], "Julia"), (mined[2], "Mandelbrot")]);
+        let sizes: Vec<u64> = largest.iter().map(|block| block["bytes"].as_u64().unwrap()).collect();
+        assert!(sizes.windows(2).all(|pair| pair[0] >= pair[1]));
+        assert_eq!(storage["total_block_bytes"].as_u64().unwrap(), sizes.iter().sum::<u64>());
+        assert_eq!(storage["average_block_bytes"].as_u64().unwrap(), sizes.iter().sum::<u64>() / 5);
+    }
+
+    #[actix_web::test]
+    async fn test_admin_endpoints_require_key() {
+        let (app, _) = setup_test_app().await;
+        let req = test::TestRequest::get().uri("/admin/peers").to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), actix_web::http::StatusCode::UNAUTHORIZED);
+
+        let req = test::TestRequest::get()
+            .uri("/admin/peers")
+            .insert_header(("Authorization", format!("Bearer {}", TEST_ADMIN_KEY)))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert!(resp.status().is_success());
+        let peers: serde_json::Value = test::read_body_json(resp).await;
+        assert!(peers.as_array().unwrap().is_empty());
+    }
+
+    #[actix_web::test]
+    async fn test_app_state_serves_blocks_and_mine() {
+        let (app, _) = setup_test_app().await;
+        let req = test::TestRequest::get().uri("/blocks").to_request();
+        let before: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        let before = before.as_array().unwrap().clone();
+
+        let req = test::TestRequest::post().uri("/mine").to_request();
+        let mined: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(mined["index"], before.len() as u64);
+        assert_eq!(mined["previous_hash"], before.last().unwrap()["hash"]);
+
+        let req = test::TestRequest::get().uri("/blocks").to_request();
+        let after: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(after.as_array().unwrap().len(), before.len() + 1);
+        assert_eq!(after.as_array().unwrap().last().unwrap(), &mined);
+
+        let req = test::TestRequest::get().uri("/wallet/info").to_request();
+        let wallet: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(mined["transactions"][0]["outputs"][0]["script_pub_key"], wallet["address"]);
+    }
+
+    #[actix_web::test]
+    async fn test_health_endpoints() {
+        let (mut state, _, _) = test_state(MempoolPool::default(), NetworkId::Regtest);
+        let health: SharedHealth = Arc::new(HealthRegistry::new());
+        state.health = Arc::clone(&health);
+        let app =
+            test::init_service(App::new().app_data(web::Data::new(state)).service(health_live).service(health_ready))
+                .await;
+        let ready = || test::TestRequest::get().uri("/health/ready").to_request();
+
+        // Nothing has reported yet, so every check is failing, but the node is live.
+        let resp = test::call_service(&app, ready()).await;
+        assert_eq!(resp.status(), actix_web::http::StatusCode::SERVICE_UNAVAILABLE);
+        let body: serde_json::Value = test::read_body_json(resp).await;
+        assert_eq!(body["failing"].as_array().unwrap().len(), api::health::READINESS_CHECKS.len());
+        let req = test::TestRequest::get().uri("/health/live").to_request();
+        assert!(test::call_service(&app, req).await.status().is_success());
+
+        for check in api::health::READINESS_CHECKS {
+            health.report(check, Ok(()));
+        }
+        let resp = test::call_service(&app, ready()).await;
+        assert!(resp.status().is_success());
+
+        for check in api::health::READINESS_CHECKS {
+            health.report(check, Err("broken".to_string()));
+            let resp = test::call_service(&app, ready()).await;
+            assert_eq!(resp.status(), actix_web::http::StatusCode::SERVICE_UNAVAILABLE);
+            let body: serde_json::Value = test::read_body_json(resp).await;
+            assert_eq!(body["failing"], serde_json::json!([{ "check": check, "reason": "broken" }]));
+            health.report(check, Ok(()));
+        }
+        let resp = test::call_service(&app, ready()).await;
+        assert!(resp.status().is_success());
+    }
+
+    #[actix_web::test]
+    async fn test_longpoll_tip() {
+        let (app, _) = setup_test_app().await;
+        let req = test::TestRequest::get().uri("/blocks").to_request();
+        let blocks: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        let tip = blocks.as_array().unwrap().last().unwrap()["hash"].as_str().unwrap().to_string();
+
+        let req = test::TestRequest::get().uri(&format!("/chain/tip/longpoll?current={}&timeout_secs=1", tip)).to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), actix_web::http::StatusCode::NOT_MODIFIED);
+
+        let poll = test::TestRequest::get().uri(&format!("/chain/tip/longpoll?current={}&timeout_secs=10", tip)).to_request();
+        let started = std::time::Instant::now();
+        let (resp, _) = tokio::join!(test::call_service(&app, poll), async {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            let req = test::TestRequest::post().uri("/mine").to_request();
+            test::call_service(&app, req).await
+        });
+        assert!(resp.status().is_success());
+        assert!(started.elapsed() < Duration::from_secs(5));
+        let new_tip: serde_json::Value = test::read_body_json(resp).await;
+        assert_ne!(new_tip["hash"], tip.as_str());
+    }
+
+    #[actix_web::test]
+    async fn test_track_transaction_until_confirmed_and_reorged_out() {
+        let (app, miner_private_key) = setup_test_app().await;
+        let req = test::TestRequest::post().uri("/mine").to_request();
+        test::call_service(&app, req).await;
+        let req = test::TestRequest::post()
+            .uri("/transact")
+            .set_json(serde_json::json!({ "to": Wallet::new().get_address(), "amount": 10, "private_key": miner_private_key }))
+            .to_request();
+        let tx: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        let tracking_id = tx["tracking_id"].as_str().unwrap().to_string();
+        assert_eq!(tx["tracking_id"], tx["id"]);
+        let track = |query: &str| test::TestRequest::get().uri(&format!("/track/{}?{}", tracking_id, query)).to_request();
+
+        let pending: serde_json::Value = test::call_and_read_body_json(&app, track("")).await;
+        assert_eq!(pending["status"], serde_json::json!({ "state": "pending" }));
+        let resp = test::call_service(&app, track("after=0&timeout_secs=1")).await;
+        assert_eq!(resp.status(), actix_web::http::StatusCode::NOT_MODIFIED);
+
+        let (resp, _) = tokio::join!(test::call_service(&app, track("after=0&timeout_secs=10")), async {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            let req = test::TestRequest::post().uri("/mine").to_request();
+            test::call_service(&app, req).await
+        });
+        let included: serde_json::Value = test::read_body_json(resp).await;
+        assert_eq!(included["version"], 1);
+        assert_eq!(included["status"]["state"], "included");
+        assert_eq!(included["status"]["height"], 2);
+        let block_hash = included["status"]["block_hash"].clone();
+
+        let req = test::TestRequest::post().uri("/mine").to_request();
+        test::call_service(&app, req).await;
+        let confirmed: serde_json::Value = test::call_and_read_body_json(&app, track("after=1")).await;
+        assert_eq!(
+            confirmed["status"],
+            serde_json::json!({ "state": "confirmed", "height": 2, "block_hash": block_hash, "confirmations": 2 })
+        );
+
+        let req = test::TestRequest::post()
+            .uri("/admin/invalidate-block")
+            .insert_header(("Authorization", format!("Bearer {}", TEST_ADMIN_KEY)))
+            .set_json(serde_json::json!({ "hash": block_hash }))
+            .to_request();
+        assert!(test::call_service(&app, req).await.status().is_success());
+        let reorged: serde_json::Value = test::call_and_read_body_json(&app, track("after=2")).await;
+        assert_eq!(reorged["status"], serde_json::json!({ "state": "pending" }));
+
+        let req = test::TestRequest::get().uri("/track/unknown").to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), actix_web::http::StatusCode::NOT_FOUND);
+    }
+
+    /// Polls `body` for its next chunk, giving up after `wait`.
+    async fn next_chunk<B: actix_web::body::MessageBody + Unpin>(body: &mut B, wait: Duration) -> Option<actix_web::web::Bytes> {
+        actix::clock::timeout(wait, std::future::poll_fn(|cx| std::pin::Pin::new(&mut *body).poll_next(cx)))
+            .await
+            .ok()
+            .flatten()
+            .map(|chunk| chunk.ok().unwrap())
+    }
+
+    #[actix_web::test]
+    async fn test_auto_miner_waits_for_transactions() {
+        let (state, miner_private_key, _control_receiver) = test_state(MempoolPool::default(), NetworkId::Regtest);
+        let auto_miner = AutoMiner {
+            blockchain: Arc::clone(&state.blockchain),
+            tx_pool: Arc::clone(&state.tx_pool),
+            policy: Arc::clone(&state.mining_policy),
+            caps: state.mining_caps,
+            miner_address: state.miner_wallet.get_address(),
+            hash_rate: Arc::clone(&state.hash_rate),
+            to_p2p: state.to_p2p.clone(),
+            hub: state.hub.clone(),
+            health: Arc::clone(&state.health),
+        };
+        let blockchain = Arc::clone(&state.blockchain);
+        let app = init_test_app(state).await;
+
+        let req = test::TestRequest::put()
+            .uri("/mining/config")
+            .set_json(serde_json::json!({ "mine_empty_blocks": false }))
+            .to_request();
+        let policy: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(policy, serde_json::json!({ "mine_empty_blocks": false, "min_transactions": 0 }));
+        let req = test::TestRequest::get().uri("/mining/status").to_request();
+        let status: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(status["policy"], policy);
+        assert_eq!(status["ready"], false);
+
+        // An empty block for coins to spend, which the policy only allows when forced.
+        let req = test::TestRequest::post().uri("/mine").to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), actix_web::http::StatusCode::CONFLICT);
+        let req = test::TestRequest::post().uri("/mine?force=true").to_request();
+        assert!(test::call_service(&app, req).await.status().is_success());
+
+        tokio::spawn(auto_miner.run(Duration::from_millis(50)));
+        tokio::time::sleep(Duration::from_millis(300)).await;
+        assert_eq!(blockchain.lock().unwrap().chain.len(), 2);
+
+        let req = test::TestRequest::post()
+            .uri("/transact")
+            .set_json(serde_json::json!({ "to": Wallet::new().get_address(), "amount": 10, "private_key": miner_private_key }))
+            .to_request();
+        let tx: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        eventually(|| blockchain.lock().unwrap().chain.len() > 2).await;
+        tokio::time::sleep(Duration::from_millis(300)).await;
+        let chain = blockchain.lock().unwrap().chain.clone();
+        assert_eq!(chain.len(), 3);
+        assert!(chain[2].transactions.iter().any(|included| included.id == tx["id"]));
+    }
+
+    #[actix_web::test]
+    async fn test_mining_gives_up_within_its_budget() {
+        let (mut state, miner_private_key, _control_receiver) = test_state(MempoolPool::default(), NetworkId::Regtest);
+        state.mining_caps.timeout_ms = 10_000;
+        let auto_miner = AutoMiner {
+            blockchain: Arc::clone(&state.blockchain),
+            tx_pool: Arc::clone(&state.tx_pool),
+            policy: Arc::clone(&state.mining_policy),
+            caps: state.mining_caps,
+            miner_address: state.miner_wallet.get_address(),
+            hash_rate: Arc::clone(&state.hash_rate),
+            to_p2p: state.to_p2p.clone(),
+            hub: state.hub.clone(),
+            health: Arc::clone(&state.health),
+        };
+        let (blockchain, pool) = (Arc::clone(&state.blockchain), Arc::clone(&state.tx_pool));
+        let app = init_test_app(state).await;
+
+        let req = test::TestRequest::post().uri("/mine").to_request();
+        assert!(test::call_service(&app, req).await.status().is_success());
+        let req = test::TestRequest::post()
+            .uri("/transact")
+            .set_json(serde_json::json!({ "to": Wallet::new().get_address(), "amount": 10, "private_key": miner_private_key }))
+            .to_request();
+        let tx: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        let txid = tx["id"].as_str().unwrap().to_string();
+
+        // At this difficulty a handful of nonces won't find a block.
+        blockchain.lock().unwrap().params.min_difficulty = 16;
+        let req = test::TestRequest::post().uri("/mine?max_attempts=5&timeout_ms=60000").to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), actix_web::http::StatusCode::SERVICE_UNAVAILABLE);
+        let body: serde_json::Value = test::read_body_json(resp).await;
+        assert_eq!(body["outcome"], "exhausted");
+        assert_eq!(body["attempts"], 5);
+        assert_eq!(body["budget"], serde_json::json!({ "max_attempts": 5, "timeout_ms": 10_000 }));
+        let req = test::TestRequest::post().uri("/mine?timeout_ms=0").to_request();
+        let body: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(body["outcome"], "timed_out");
+        assert_eq!(blockchain.lock().unwrap().chain.len(), 2);
+        assert!(pool.lock().unwrap().contains(&txid));
+
+        // The policy's budget holds for the auto-miner too, which skips the tick.
+        let req = test::TestRequest::put().uri("/mining/config").set_json(serde_json::json!({ "max_attempts": 5 })).to_request();
+        assert!(test::call_service(&app, req).await.status().is_success());
+        let req = test::TestRequest::get().uri("/mining/status").to_request();
+        let status: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(status["budget"], serde_json::json!({ "max_attempts": 5, "timeout_ms": 10_000 }));
+        assert_eq!(auto_miner.tick(), None);
+        assert_eq!(blockchain.lock().unwrap().chain.len(), 2);
+        assert!(pool.lock().unwrap().contains(&txid));
+    }
+
+    #[actix_web::test]
+    async fn test_demo_traffic_is_mined_until_disabled() {
+        let (mut state, _, _control_receiver) = test_state(MempoolPool::default(), NetworkId::Regtest);
+        let config = DemoConfig { interval: Duration::from_millis(20), ..DemoConfig::default() };
+        let demo = DemoTraffic::new(
+            Arc::clone(&state.blockchain),
+            Arc::clone(&state.tx_pool),
+            Arc::clone(&state.miner_wallet),
+            state.to_p2p.clone(),
+            state.hub.clone(),
+            config,
+        );
+        let demo_wallets: Vec<String> = demo.wallets.iter().map(Wallet::get_address).collect();
+        let (switch, task) = demo.spawn(true);
+        state.demo_traffic = switch.clone();
+        let (blockchain, pool) = (Arc::clone(&state.blockchain), Arc::clone(&state.tx_pool));
+        let app = init_test_app(state).await;
+        let demo_transactions = |transactions: Vec<core::transaction::Transaction>| {
+            transactions.into_iter().filter(|tx| tx.memo.as_deref() == Some(api::demo::DEMO_MEMO)).collect::<Vec<_>>()
+        };
+
+        // The first drip waits for the miner wallet to have coins; the
+        // payments between demo wallets for the drip to be mined.
+        let mut mined = Vec::new();
+        for _ in 0..50 {
+            let req = test::TestRequest::post().uri("/mine").to_request();
+            assert!(test::call_service(&app, req).await.status().is_success());
+            let chain = blockchain.lock().unwrap().chain.clone();
+            mined = demo_transactions(chain.into_iter().flat_map(|block| block.transactions).collect());
+            if mined.len() >= 3 {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+        assert!(mined.len() >= 3, "only {} demo transactions were mined", mined.len());
+        let to_demo_wallet = |output: &core::transaction::TxOutput| demo_wallets.iter().any(|address| address == output.script_pub_key.address());
+        assert!(mined.iter().all(|tx| to_demo_wallet(&tx.outputs[0])));
+        // Besides the drips from the miner wallet, the demo wallets paid each other.
+        let spends_demo_coins = |input: &core::transaction::TxInput| {
+            mined.iter().any(|funding| funding.id == input.txid && to_demo_wallet(&funding.outputs[input.vout]))
+        };
+        assert!(mined.iter().any(|tx| tx.inputs.iter().all(spends_demo_coins)));
+
+        let req = test::TestRequest::put().uri("/demo-traffic").set_json(serde_json::json!({ "enabled": false })).to_request();
+        let body: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(body, serde_json::json!({ "available": true, "enabled": false }));
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        let req = test::TestRequest::post().uri("/mine").to_request();
+        assert!(test::call_service(&app, req).await.status().is_success());
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        let pooled: Vec<_> = {
+            let pool = pool.lock().unwrap();
+            pool.txids().filter_map(|txid| pool.get(txid).cloned()).collect()
+        };
+        assert!(demo_transactions(pooled).is_empty());
+
+        switch.stop();
+        tokio::time::timeout(Duration::from_secs(5), task).await.unwrap().unwrap();
+    }
+
+    #[actix_web::test]
+    async fn test_safe_mode_stops_auto_mining_until_cleared() {
+        let (state, _, _control_receiver) = test_state(MempoolPool::default(), NetworkId::Regtest);
+        let auto_miner = AutoMiner {
+            blockchain: Arc::clone(&state.blockchain),
+            tx_pool: Arc::clone(&state.tx_pool),
+            policy: Arc::clone(&state.mining_policy),
+            caps: state.mining_caps,
+            miner_address: state.miner_wallet.get_address(),
+            hash_rate: Arc::clone(&state.hash_rate),
+            to_p2p: state.to_p2p.clone(),
+            hub: state.hub.clone(),
+            health: Arc::clone(&state.health),
+        };
+        state.health.report(SAFE_MODE_OFF, Err("Refused a reorg of 150 blocks".to_string()));
+        let app = init_test_app(state).await;
+
+        assert_eq!(auto_miner.tick(), None);
+        let req = test::TestRequest::get().uri("/health/ready").to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), actix_web::http::StatusCode::SERVICE_UNAVAILABLE);
+        let body: serde_json::Value = test::read_body_json(resp).await;
+        assert_eq!(body["failing"][0]["check"], SAFE_MODE_OFF);
+
+        let req = test::TestRequest::delete().uri("/admin/safe-mode").to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), actix_web::http::StatusCode::UNAUTHORIZED);
+        let clear = || {
+            test::TestRequest::delete()
+                .uri("/admin/safe-mode")
+                .insert_header(("Authorization", format!("Bearer {}", TEST_ADMIN_KEY)))
+                .to_request()
+        };
+        let body: serde_json::Value = test::call_and_read_body_json(&app, clear()).await;
+        assert_eq!(body["cleared"], "Refused a reorg of 150 blocks");
+        let body: serde_json::Value = test::call_and_read_body_json(&app, clear()).await;
+        assert_eq!(body["cleared"], serde_json::Value::Null);
+        let req = test::TestRequest::get().uri("/health/ready").to_request();
+        assert!(test::call_service(&app, req).await.status().is_success());
+        assert!(auto_miner.tick().is_some());
+    }
+
+    #[actix_web::test]
+    async fn test_block_stream_sends_backlog_then_new_blocks() {
+        let (app, _) = setup_test_app().await;
+        let req = test::TestRequest::post().uri("/mine").to_request();
+        let first: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+
+        let req = test::TestRequest::get().uri("/blocks/stream?since_height=0").to_request();
+        let resp = test::call_service(&app, req).await;
+        assert!(resp.status().is_success());
+        assert_eq!(resp.headers().get("content-type").unwrap(), "application/x-ndjson");
+        let mut body = resp.into_body();
+
+        let mut lines = Vec::new();
+        while let Some(chunk) = next_chunk(&mut body, Duration::from_millis(200)).await {
+            lines.push(serde_json::from_slice::<serde_json::Value>(&chunk).unwrap());
+            assert_eq!(chunk.last(), Some(&b'\n'));
+        }
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0]["index"], 0);
+        assert_eq!(lines[1]["hash"], first["hash"]);
+
+        let req = test::TestRequest::post().uri("/mine").to_request();
+        let mined: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        let chunk = next_chunk(&mut body, Duration::from_secs(5)).await.expect("the mined block was not streamed");
+        let line: serde_json::Value = serde_json::from_slice(&chunk).unwrap();
+        assert_eq!(line["hash"], mined["hash"]);
+        assert_eq!(line["index"], 2);
+    }
+
+    #[actix_web::test]
+    async fn test_responses_are_gzipped_except_streams() {
+        use std::io::Read;
+
+        let (state, _, _control_receiver) = test_state(MempoolPool::default(), NetworkId::Regtest);
+        let app = test::init_service(
+            App::new()
+                .wrap(from_fn(count_uncompressed_bytes))
+                .wrap(Compress::default())
+                .wrap(from_fn(count_sent_bytes))
+                .app_data(web::Data::new(state))
+                .service(api::handlers::mine)
+                .service(api::handlers::get_blocks)
+                .service(api::stream::stream_blocks)
+                .service(api::handlers::get_block_fractal_png)
+                .service(api::metrics::get_metrics),
+        )
+        .await;
+        let req = test::TestRequest::post().uri("/mine").to_request();
+        test::call_service(&app, req).await;
+
+        let req = test::TestRequest::get().uri("/blocks").to_request();
+        let resp = test::call_service(&app, req).await;
+        assert!(resp.headers().get("content-encoding").is_none());
+        let plain = test::read_body(resp).await;
+        let expected: serde_json::Value = serde_json::from_slice(&plain).unwrap();
+        let req = test::TestRequest::get().uri("/blocks").insert_header(("accept-encoding", "gzip")).to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.headers().get("content-encoding").unwrap(), "gzip");
+        let compressed = test::read_body(resp).await;
+        let mut json = String::new();
+        flate2::read::GzDecoder::new(&compressed[..]).read_to_string(&mut json).unwrap();
+        assert_eq!(serde_json::from_str::<serde_json::Value>(&json).unwrap(), expected);
+
+        let req = test::TestRequest::get().uri("/blocks?stream=true").insert_header(("accept-encoding", "gzip")).to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.headers().get("content-type").unwrap(), "application/x-ndjson");
+        assert_eq!(resp.headers().get("content-encoding").unwrap(), "identity");
+        let lines = test::read_body(resp).await;
+        assert_eq!(lines.split(|byte| *byte == b'\n').filter(|line| !line.is_empty()).count(), 2);
+
+        let req = test::TestRequest::get().uri("/blocks/1/fractal.png").insert_header(("accept-encoding", "gzip")).to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.headers().get("content-encoding").unwrap(), "identity");
+        let png = test::read_body(resp).await;
+
+        // Each streamed block still arrives as a plain line of its own, as it
+        // is mined.
+        let req = test::TestRequest::get()
+            .uri("/blocks/stream?since_height=0")
+            .insert_header(("accept-encoding", "gzip"))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_ne!(resp.headers().get("content-encoding").unwrap(), "gzip");
+        let mut body = resp.into_body();
+        let chunk = next_chunk(&mut body, Duration::from_secs(5)).await.expect("the genesis block was not streamed");
+        assert_eq!(serde_json::from_slice::<serde_json::Value>(&chunk).unwrap()["index"], 0);
+        next_chunk(&mut body, Duration::from_secs(5)).await.expect("the first mined block was not streamed");
+        let req = test::TestRequest::post().uri("/mine").to_request();
+        test::call_service(&app, req).await;
+        let chunk = next_chunk(&mut body, Duration::from_secs(5)).await.expect("the new block was not streamed");
+        assert_eq!(serde_json::from_slice::<serde_json::Value>(&chunk).unwrap()["index"], 2);
+
+        let req = test::TestRequest::get().uri("/metrics").to_request();
+        let metrics = String::from_utf8(test::read_body(test::call_service(&app, req).await).await.to_vec()).unwrap();
+        let bytes = |route: &str, stage: &str| -> u64 {
+            let prefix = format!("sierpchain_http_response_bytes_total{{route=\"{}\",stage=\"{}\"}} ", route, stage);
+            metrics.lines().find_map(|line| line.strip_prefix(prefix.as_str())).unwrap().parse().unwrap()
+        };
+        assert_eq!(bytes("/blocks", "uncompressed"), (2 * plain.len() + lines.len()) as u64);
+        assert_eq!(bytes("/blocks", "sent"), (plain.len() + compressed.len() + lines.len()) as u64);
+        assert_eq!(bytes("/blocks/{index}/fractal.png", "sent"), png.len() as u64);
+        assert_eq!(bytes("/blocks/{index}/fractal.png", "uncompressed"), png.len() as u64);
+        assert!(bytes("/blocks/stream", "sent") > 0);
+    }
+
+    #[actix_web::test]
+    async fn test_block_streams_are_capped() {
+        let (app, _) = setup_test_app().await;
+        let mut open = Vec::new();
+        for _ in 0..api::websocket::MAX_BLOCK_STREAMS {
+            let req = test::TestRequest::get().uri("/blocks/stream").to_request();
+            let resp = test::call_service(&app, req).await;
+            assert!(resp.status().is_success());
+            open.push(resp);
+        }
+        let req = test::TestRequest::get().uri("/blocks/stream").to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), actix_web::http::StatusCode::SERVICE_UNAVAILABLE);
+
+        // A client disconnecting frees its place.
+        open.pop();
+        let req = test::TestRequest::get().uri("/blocks/stream").to_request();
+        let resp = test::call_service(&app, req).await;
+        assert!(resp.status().is_success());
+    }
+
+    #[actix_web::test]
+    async fn test_light_node_serves_and_follows_the_chain_but_does_not_mine() {
+        let (mut state, _, _) = test_state(MempoolPool::default(), NetworkId::Regtest);
+        state.light = LightNode(true);
+        let app = init_test_app(state.clone()).await;
+
+        for req in [
+            test::TestRequest::post().uri("/mine").to_request(),
+            test::TestRequest::get().uri("/mining/template").to_request(),
+            test::TestRequest::post().uri("/faucet").set_json(serde_json::json!({ "address": "someone", "amount": 1 })).to_request(),
+        ] {
+            let resp = test::call_service(&app, req).await;
+            assert_eq!(resp.status(), actix_web::http::StatusCode::FORBIDDEN);
+        }
+        let req = test::TestRequest::get().uri("/status").to_request();
+        let status: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(status["light"], true);
+
+        // A block relayed by a peer is still connected and served.
+        let block = {
+            let mut peer = state.blockchain.lock().unwrap().clone();
+            let transactions = Miner::assemble_transactions(&peer, &mut MempoolPool::default(), "peer");
+            peer.add_block(fractal::FractalType::Sierpinski { depth: 5, seed: 0 }, transactions).unwrap()
+        };
+        assert!(state.blockchain.lock().unwrap().add_block_from_network(block.clone()).is_ok());
+        let req = test::TestRequest::get().uri("/blocks").to_request();
+        let blocks: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(blocks.as_array().unwrap().last().unwrap()["hash"], block.hash.as_str());
+    }
+
+    /// Waits up to five seconds for `condition` to hold.
+    async fn eventually(condition: impl Fn() -> bool) {
+        let deadline = std::time::Instant::now() + Duration::from_secs(5);
+        while !condition() {
+            assert!(std::time::Instant::now() < deadline, "timed out waiting for the condition");
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+    }
+
+    /// Fetches `path` from the server at `address` over a real connection.
+    async fn get_json(address: std::net::SocketAddr, path: &str) -> serde_json::Value {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+        let mut stream = tokio::net::TcpStream::connect(address).await.unwrap();
+        let request = format!("GET {} HTTP/1.1\r\nHost: {}\r\nConnection: close\r\n\r\n", path, address);
+        stream.write_all(request.as_bytes()).await.unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        let (_, body) = response.split_once("\r\n\r\n").unwrap();
+        serde_json::from_str(body).unwrap()
+    }
+
+    #[actix_web::test]
+    async fn test_node_on_port_zero_reports_the_ports_it_bound() {
+        let (state, _, control_receiver) = test_state(MempoolPool::default(), NetworkId::Regtest);
+        let (inbound_sender, _inbound_receiver) = mpsc::unbounded_channel();
+        let (_to_p2p, to_p2p_receiver) = mpsc::unbounded_channel();
+        let config = P2pConfig { port: 0, mdns: false, ..P2pConfig::default() };
+        let p2p = P2p::new(inbound_sender, to_p2p_receiver, control_receiver, config, Arc::clone(&state.peer_store)).await;
+        let state = AppState { p2p_listen_addrs: Arc::clone(&p2p.listen_addrs), ..state };
+        tokio::spawn(p2p.run());
+        eventually(|| !state.p2p_listen_addrs.lock().unwrap().is_empty()).await;
+
+        let (server, bound) = http_server(web::Data::new(state), DEFAULT_JSON_LIMIT, DEFAULT_BLOCK_JSON_LIMIT, "127.0.0.1:0").unwrap();
+        let server_handle = server.handle();
+        tokio::spawn(server);
+
+        let status = get_json(bound, "/status").await;
+        let http_port = status["http_port"].as_u64().unwrap() as u16;
+        let p2p_port = status["p2p_port"].as_u64().unwrap() as u16;
+        assert_eq!(http_port, bound.port());
+        assert_ne!(http_port, 0);
+        assert_ne!(p2p_port, 0);
+        assert!(status["p2p_listen_addrs"].as_array().unwrap().iter().any(|address| {
+            address.as_str().unwrap().ends_with(&format!("/tcp/{}", p2p_port))
+        }));
+        tokio::net::TcpStream::connect(("127.0.0.1", p2p_port)).await.expect("the P2P port accepts connections");
+
+        // A port already taken is reported as such.
+        let (other, _, _) = test_state(MempoolPool::default(), NetworkId::Regtest);
+        let error = http_server(web::Data::new(other), DEFAULT_JSON_LIMIT, DEFAULT_BLOCK_JSON_LIMIT, &bound.to_string()).err().expect("the port is in use");
+        assert!(error.to_string().contains(&bound.to_string()), "{}", error);
+        server_handle.stop(false).await;
+    }
+
+    #[actix_web::test]
+    async fn test_api_process_follows_a_headless_node_over_rpc() {
+        // The headless node, serving RPC on a socket of its own.
+        let (node, _, _) = test_state(MempoolPool::default(), NetworkId::Regtest);
+        let socket = std::env::temp_dir().join(format!("sierpchain-rpc-{}.sock", rand::random::<u64>()));
+        let listener = network::rpc::bind(&socket).unwrap();
+        let rpc_node = RpcNode {
+            blockchain: Arc::clone(&node.blockchain),
+            tx_pool: Arc::clone(&node.tx_pool),
+            to_p2p: node.to_p2p.clone(),
+            hub: node.hub.clone(),
+        };
+        tokio::spawn(network::rpc::serve(listener, rpc_node));
+
+        // The API process, wired to the node as `--node-url` does.
+        let (mut api, miner_private_key, _) = test_state(MempoolPool::default(), NetworkId::Regtest);
+        let client = RpcClient::new(socket);
+        let (to_node, to_node_receiver) = mpsc::unbounded_channel();
+        api.to_p2p = to_node;
+        tokio::spawn(network::rpc::forward(client.clone(), to_node_receiver));
+        api.health.report(P2P_RUNNING, Err("Not following the node yet".to_string()));
+        let replica = Replica {
+            blockchain: Arc::clone(&api.blockchain),
+            tx_pool: Arc::clone(&api.tx_pool),
+            hub: api.hub.clone(),
+            health: Arc::clone(&api.health),
+        };
+        tokio::spawn(network::rpc::follow(client, replica));
+        eventually(|| api.health.failing().is_empty()).await;
+        let app = init_test_app(api.clone()).await;
+
+        let (resp, _sender) = ws_handshake(api.clone(), "", None).await;
+        let mut ws_body = resp.into_body();
+        assert!(next_chunk(&mut ws_body, Duration::from_millis(50)).await.is_none());
+
+        // A block mined through the API is connected by the node.
+        let req = test::TestRequest::post().uri("/mine").to_request();
+        let mined: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        eventually(|| node.blockchain.lock().unwrap().chain.last().unwrap().hash == mined["hash"]).await;
+
+        // So is a transaction submitted through it.
+        let receiver = Wallet::new().get_address();
+        let transact_req = serde_json::json!({ "to": receiver, "amount": 10, "private_key": miner_private_key });
+        let req = test::TestRequest::post().uri("/transact").set_json(&transact_req).to_request();
+        let tx: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        let txid = tx["id"].as_str().unwrap().to_string();
+        eventually(|| node.tx_pool.lock().unwrap().contains(&txid)).await;
+
+        // A block the node connects reaches the API's chain and its WebSocket clients.
+        let from_peer = {
+            let mut blockchain = node.blockchain.lock().unwrap();
+            let mut pool = node.tx_pool.lock().unwrap();
+            let transactions = Miner::assemble_transactions(&blockchain, &mut pool, &receiver);
+            api::handlers::mine_and_announce(
+                &mut blockchain,
+                &mut pool,
+                fractal::FractalType::Sierpinski { depth: 5, seed: 0 },
+                transactions,
+                MiningBudget::UNLIMITED,
+                &node.hash_rate,
+                &node.to_p2p,
+                &node.hub,
+            )
+            .unwrap()
+            .found()
+            .unwrap()
+        };
+        let mut frames = Vec::new();
+        while let Some(frame) = next_chunk(&mut ws_body, Duration::from_secs(5)).await {
+            frames.push(frame);
+            if String::from_utf8_lossy(frames.last().unwrap()).contains(&from_peer.hash) {
+                break;
+            }
+        }
+        assert!(String::from_utf8_lossy(frames.last().unwrap()).contains(&from_peer.hash));
+
+        let req = test::TestRequest::get().uri("/blocks").to_request();
+        let blocks: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(blocks.as_array().unwrap().last().unwrap()["hash"], from_peer.hash.as_str());
+        eventually(|| !api.tx_pool.lock().unwrap().contains(&txid)).await;
+    }
+
+    #[actix_web::test]
+    async fn test_blocks_include_vertices_on_request() {
+        let (app, _) = setup_test_app().await;
+        let req = test::TestRequest::get().uri("/blocks").to_request();
+        let blocks: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        assert!(blocks[0]["fractal"]["data"].get("vertices").is_none());
+
+        let req = test::TestRequest::get().uri("/blocks?include_vertices=true").to_request();
+        let blocks: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        assert!(blocks[0]["fractal"]["data"]["vertices"].is_array());
+    }
+
+    #[actix_web::test]
+    async fn test_blocks_pages_back_from_before() {
+        let (app, _) = setup_test_app().await;
+        for _ in 0..3 {
+            let req = test::TestRequest::post().uri("/mine").to_request();
+            assert!(test::call_service(&app, req).await.status().is_success());
+        }
+        let req = test::TestRequest::get().uri("/blocks").to_request();
+        let all: Vec<serde_json::Value> = test::call_and_read_body_json(&app, req).await;
+        let tip = all.len() as u64 - 1;
+
+        let req = test::TestRequest::get().uri("/blocks?limit=2").to_request();
+        let page: Vec<serde_json::Value> = test::call_and_read_body_json(&app, req).await;
+        let indices: Vec<u64> = page.iter().map(|block| block["index"].as_u64().unwrap()).collect();
+        assert_eq!(indices, vec![tip - 1, tip]);
+
+        let uri = format!("/blocks?before={}&limit=2", tip - 1);
+        let req = test::TestRequest::get().uri(&uri).to_request();
+        let page: Vec<serde_json::Value> = test::call_and_read_body_json(&app, req).await;
+        let indices: Vec<u64> = page.iter().map(|block| block["index"].as_u64().unwrap()).collect();
+        assert_eq!(indices, vec![tip - 3, tip - 2]);
+
+        let req = test::TestRequest::get().uri("/blocks?before=0&limit=2").to_request();
+        let page: Vec<serde_json::Value> = test::call_and_read_body_json(&app, req).await;
+        assert!(page.is_empty());
+    }
+
+    #[actix_web::test]
+    async fn test_blocks_streams_one_block_per_line() {
+        let (app, _) = setup_test_app().await;
+        for _ in 0..3 {
+            let req = test::TestRequest::post().uri("/mine").to_request();
+            assert!(test::call_service(&app, req).await.status().is_success());
+        }
+        let req = test::TestRequest::get().uri("/blocks?include_vertices=true").to_request();
+        let all: Vec<serde_json::Value> = test::call_and_read_body_json(&app, req).await;
+
+        let read_lines = |body: &[u8]| -> Vec<serde_json::Value> {
+            let body = std::str::from_utf8(body).unwrap();
+            assert!(body.ends_with('\n'));
+            body.lines().map(|line| serde_json::from_str(line).unwrap()).collect()
+        };
+        let req = test::TestRequest::get().uri("/blocks?stream=true&include_vertices=true").to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.headers().get("content-type").unwrap(), "application/x-ndjson");
+        assert_eq!(read_lines(&test::read_body(resp).await), all);
+
+        // The same pages as the JSON array, asked for by the Accept header.
+        let req = test::TestRequest::get()
+            .uri("/blocks?before=3&limit=2")
+            .insert_header(("Accept", "application/x-ndjson"))
+            .to_request();
+        let streamed = read_lines(&test::call_and_read_body(&app, req).await);
+        let indices: Vec<u64> = streamed.iter().map(|block| block["index"].as_u64().unwrap()).collect();
+        assert_eq!(indices, vec![1, 2]);
+        assert!(streamed[0]["fractal"]["data"].get("vertices").is_none());
+    }
+
+    #[actix_web::test]
+    async fn test_cpfp_child_is_mined_with_parent() {
+        let (app, miner_private_key) = setup_test_app().await;
+
+        let req = test::TestRequest::post().uri("/wallet").to_request();
+        let receiver_wallet: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        let receiver_address = receiver_wallet["address"].as_str().unwrap();
+
+        let req = test::TestRequest::post().uri("/mine").to_request();
+        let resp = test::call_service(&app, req).await;
+        assert!(resp.status().is_success());
+
+        // A zero-fee parent paying the receiver.
+        let transact_req = serde_json::json!({
+            "to": receiver_address,
+            "amount": 10,
+            "private_key": miner_private_key
+        });
+        let req = test::TestRequest::post().uri("/transact").set_json(&transact_req).to_request();
+        let parent: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+
+        // The receiver bumps it by spending its output with a fee.
+        let cpfp_req = serde_json::json!({
+            "parent_txid": parent["id"],
+            "parent_vout": 0,
+            "private_key": receiver_wallet["private_key"],
+            "fee_per_byte": 0
+        });
+        let req = test::TestRequest::post().uri("/transact/cpfp").set_json(&cpfp_req).to_request();
+        let resp = test::call_service(&app, req).await;
+        assert!(resp.status().is_success());
+        let cpfp: serde_json::Value = test::read_body_json(resp).await;
+        let child_id = cpfp["transaction"]["id"].clone();
+
+        let req = test::TestRequest::post().uri("/mine").to_request();
+        let block: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        let ids: Vec<&serde_json::Value> = block["transactions"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|tx| &tx["id"])
+            .collect();
+        let parent_position = ids.iter().position(|id| **id == parent["id"]).unwrap();
+        let child_position = ids.iter().position(|id| **id == child_id).unwrap();
+        assert!(parent_position < child_position);
+    }
+
+    #[actix_web::test]
+    async fn test_mempool_relatives_of_chained_transactions() {
+        let (app, miner_private_key) = setup_test_app().await;
+        let req = test::TestRequest::post().uri("/wallet").to_request();
+        let receiver_wallet: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        let req = test::TestRequest::post().uri("/mine").to_request();
+        test::call_service(&app, req).await;
+
+        let transact_req = serde_json::json!({ "to": receiver_wallet["address"], "amount": 10, "private_key": miner_private_key });
+        let req = test::TestRequest::post().uri("/transact").set_json(&transact_req).to_request();
+        let parent: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        let cpfp_req = serde_json::json!({
+            "parent_txid": parent["id"],
+            "parent_vout": 0,
+            "private_key": receiver_wallet["private_key"],
+            "fee_per_byte": 0
+        });
+        let req = test::TestRequest::post().uri("/transact/cpfp").set_json(&cpfp_req).to_request();
+        let cpfp: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        let child_id = &cpfp["transaction"]["id"];
+
+        let relatives_of = |txid: &serde_json::Value| {
+            test::TestRequest::get().uri(&format!("/mempool/{}/relatives", txid.as_str().unwrap())).to_request()
+        };
+        let relatives: serde_json::Value = test::call_and_read_body_json(&app, relatives_of(&parent["id"])).await;
+        assert_eq!(relatives["ancestors"], serde_json::json!([]));
+        assert_eq!(relatives["descendants"].as_array().unwrap().len(), 1);
+        assert_eq!(&relatives["descendants"][0]["txid"], child_id);
+
+        let relatives: serde_json::Value = test::call_and_read_body_json(&app, relatives_of(child_id)).await;
+        assert_eq!(relatives["ancestors"].as_array().unwrap().len(), 1);
+        assert_eq!(relatives["ancestors"][0]["txid"], parent["id"]);
+        assert_eq!(relatives["descendants"], serde_json::json!([]));
+
+        let resp = test::call_service(&app, relatives_of(&serde_json::json!("unknown"))).await;
+        assert_eq!(resp.status(), actix_web::http::StatusCode::NOT_FOUND);
+    }
+
+    #[actix_web::test]
+    async fn test_evict_transaction_releases_its_inputs() {
+        let (app, miner_private_key) = setup_test_app().await;
+        let req = test::TestRequest::post().uri("/mine").to_request();
+        test::call_service(&app, req).await;
+
+        let transact_req = serde_json::json!({
+            "to": "someone",
+            "amount": 10,
+            "private_key": miner_private_key
+        });
+        let req = test::TestRequest::get().uri("/mempool").to_request();
+        let mempool_before: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        let req = test::TestRequest::post().uri("/transact").set_json(&transact_req).to_request();
+        let tx: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+
+        let req = test::TestRequest::delete().uri(&format!("/mempool/{}", tx["id"].as_str().unwrap())).to_request();
+        let resp = test::call_service(&app, req).await;
+        assert!(resp.status().is_success());
+        let body: serde_json::Value = test::read_body_json(resp).await;
+        assert_eq!(body["removed"], serde_json::json!([tx["id"]]));
+        let req = test::TestRequest::get().uri("/mempool").to_request();
+        let mempool: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(mempool["transactions"], mempool_before["transactions"]);
+
+        let req = test::TestRequest::delete().uri(&format!("/mempool/{}", tx["id"].as_str().unwrap())).to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), actix_web::http::StatusCode::NOT_FOUND);
+
+        // The evicted transaction's inputs are no longer reserved.
+        let req = test::TestRequest::post().uri("/transact").set_json(&transact_req).to_request();
+        let replacement: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(replacement["inputs"][0]["txid"], tx["inputs"][0]["txid"]);
+        assert_eq!(replacement["inputs"][0]["vout"], tx["inputs"][0]["vout"]);
+    }
+
+    #[actix_web::test]
+    async fn test_invalidate_and_reconsider_block_endpoints() {
+        let (app, miner_private_key) = setup_test_app().await;
+
+        let req = test::TestRequest::post().uri("/wallet").to_request();
+        let receiver_wallet: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        let transact_req = serde_json::json!({
+            "to": receiver_wallet["address"],
+            "amount": 10,
+            "private_key": miner_private_key
+        });
+
+        let req = test::TestRequest::post().uri("/mine").to_request();
+        test::call_service(&app, req).await;
+        let mut txids = Vec::new();
+        for _ in 0..2 {
+            let req = test::TestRequest::post().uri("/transact").set_json(&transact_req).to_request();
+            let tx: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+            txids.push(tx["id"].as_str().unwrap().to_string());
+            let req = test::TestRequest::post().uri("/mine").to_request();
+            test::call_service(&app, req).await;
+        }
+        let req = test::TestRequest::post().uri("/mine").to_request();
+        test::call_service(&app, req).await;
+
+        let req = test::TestRequest::get().uri("/blocks").to_request();
+        let blocks: Vec<serde_json::Value> = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(blocks.len(), 5);
+        let tip_parent = blocks[3]["hash"].clone();
+        let req = test::TestRequest::get().uri("/mempool").to_request();
+        let mempool_before: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+
+        let req = test::TestRequest::post()
+            .uri("/admin/invalidate-block")
+            .insert_header(("Authorization", format!("Bearer {}", TEST_ADMIN_KEY)))
+            .set_json(serde_json::json!({ "hash": tip_parent }))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert!(resp.status().is_success());
+        let reorg: serde_json::Value = test::read_body_json(resp).await;
+        assert_eq!(reorg["height"], 2);
+        assert_eq!(reorg["disconnected"].as_array().unwrap().len(), 2);
+        assert_eq!(reorg["returned_to_mempool"], serde_json::json!([txids[1]]));
+        let req = test::TestRequest::get().uri("/mempool").to_request();
+        let mempool: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(mempool["transactions"], mempool_before["transactions"].as_u64().unwrap() + 1);
+
+        let req = test::TestRequest::post()
+            .uri("/admin/reconsider-block")
+            .insert_header(("Authorization", format!("Bearer {}", TEST_ADMIN_KEY)))
+            .set_json(serde_json::json!({ "hash": tip_parent }))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert!(resp.status().is_success());
+        let reorg: serde_json::Value = test::read_body_json(resp).await;
+        assert_eq!(reorg["height"], 4);
+        assert_eq!(reorg["connected"].as_array().unwrap().len(), 2);
+        let req = test::TestRequest::get().uri("/mempool").to_request();
+        let mempool: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(mempool["transactions"], mempool_before["transactions"]);
+    }
+
+    #[actix_web::test]
+    async fn test_chain_graph_shows_both_branches_of_a_fork() {
+        let (state, _, _control_receiver) = test_state(MempoolPool::default(), NetworkId::Regtest);
+        let app = init_test_app(state).await;
+
+        for _ in 0..3 {
+            let req = test::TestRequest::post().uri("/mine").to_request();
+            test::call_service(&app, req).await;
+        }
+        let req = test::TestRequest::get().uri("/blocks").to_request();
+        let blocks: Vec<serde_json::Value> = test::call_and_read_body_json(&app, req).await;
+        let stale: Vec<serde_json::Value> = blocks[2..].iter().map(|block| block["hash"].clone()).collect();
+        let req = test::TestRequest::post()
+            .uri("/admin/invalidate-block")
+            .insert_header(("Authorization", format!("Bearer {}", TEST_ADMIN_KEY)))
+            .set_json(serde_json::json!({ "hash": stale[0] }))
+            .to_request();
+        assert!(test::call_service(&app, req).await.status().is_success());
+        // A different style seed, so the new branch can't repeat the stale one
+        // within the same second.
+        let other_seed = serde_json::json!({ "type": "Sierpinski", "params": { "depth": 5, "style_seed": 1 } });
+        for _ in 0..3 {
+            let req = test::TestRequest::post().uri("/mine").set_json(&other_seed).to_request();
+            test::call_service(&app, req).await;
+        }
+
+        let req = test::TestRequest::get().uri("/chain/graph?depth=10").to_request();
+        let graph: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        let nodes = graph["nodes"].as_array().unwrap();
+        assert_eq!(nodes.len(), 7);
+        for node in nodes {
+            assert_eq!(node["active"], !stale.contains(&node["hash"]), "{}", node);
+        }
+        let active_tip = nodes.iter().rfind(|node| node["active"] == true).unwrap();
+        assert_eq!(active_tip["height"], 4);
+        assert_eq!(active_tip["fractal_type"], "Sierpinski");
+        assert!(active_tip["miner"].is_string());
+        // Both branches fork from block 1.
+        let forks = graph["edges"].as_array().unwrap().iter().filter(|edge| edge["to"] == blocks[1]["hash"]).count();
+        assert_eq!(forks, 2);
+
+        let req = test::TestRequest::get().uri("/chain/graph?depth=10&format=dot").to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.headers().get("content-type").unwrap(), "text/vnd.graphviz");
+        let dot = String::from_utf8(test::read_body(resp).await.to_vec()).unwrap();
+        assert!(dot.starts_with("digraph chain {"));
+        assert!(dot.contains(&format!("\"{}\" -> \"{}\"", stale[1].as_str().unwrap(), stale[0].as_str().unwrap())));
+    }
+
+    #[actix_web::test]
+    async fn test_gc_archives_stale_forks() {
+        let (mut state, _, _control_receiver) = test_state(MempoolPool::default(), NetworkId::Regtest);
+        let archive_file = std::env::temp_dir().join(format!("sierpchain-archive-{}.jsonl", rand::random::<u64>()));
+        state.storage_gc = Arc::new(Mutex::new(StorageGc::new(1, Some(archive_file.clone()))));
+        let app = init_test_app(state).await;
+        let admin = ("Authorization", format!("Bearer {}", TEST_ADMIN_KEY));
+
+        for _ in 0..3 {
+            let req = test::TestRequest::post().uri("/mine").to_request();
+            test::call_service(&app, req).await;
+        }
+        let req = test::TestRequest::get().uri("/blocks").to_request();
+        let blocks: Vec<serde_json::Value> = test::call_and_read_body_json(&app, req).await;
+        let fork: Vec<serde_json::Value> = blocks[2..].iter().map(|block| block["hash"].clone()).collect();
+        let req = test::TestRequest::post()
+            .uri("/admin/invalidate-block")
+            .insert_header(admin.clone())
+            .set_json(serde_json::json!({ "hash": fork[0] }))
+            .to_request();
+        assert!(test::call_service(&app, req).await.status().is_success());
+
+        // The fork is still within reach of a reorg.
+        let req = test::TestRequest::post().uri("/admin/gc").insert_header(admin.clone()).to_request();
+        let report: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(report["archived"], 0);
+
+        for _ in 0..2 {
+            let req = test::TestRequest::post().uri("/mine").to_request();
+            test::call_service(&app, req).await;
+        }
+        let req = test::TestRequest::get().uri("/blocks").to_request();
+        let active_before: Vec<serde_json::Value> = test::call_and_read_body_json(&app, req).await;
+
+        let req = test::TestRequest::post().uri("/admin/gc").insert_header(admin.clone()).to_request();
+        let report: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(report["archived"], 2);
+        assert_eq!(report["deleted"], 0);
+        let archived: Vec<serde_json::Value> = std::fs::read_to_string(&archive_file)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap()["hash"].clone())
+            .collect();
+        assert_eq!(archived, fork);
+        assert_eq!(report["reclaimed_bytes"], std::fs::metadata(&archive_file).unwrap().len() - 2);
+
+        let req = test::TestRequest::get().uri("/blocks").to_request();
+        let active_after: Vec<serde_json::Value> = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(active_after, active_before);
+
+        let req = test::TestRequest::post()
+            .uri("/admin/reconsider-block")
+            .insert_header(admin.clone())
+            .set_json(serde_json::json!({ "hash": fork[0] }))
+            .to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), actix_web::http::StatusCode::GONE);
+
+        let req = test::TestRequest::get().uri("/admin/storage").insert_header(admin).to_request();
+        let storage: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(storage["gc"]["side_chain_blocks"], 0);
+        assert_eq!(storage["gc"]["last_run"], report);
+        std::fs::remove_file(archive_file).ok();
+    }
+
+    #[actix_web::test]
+    async fn test_supply_tracks_fees_and_burns() {
+        let (app, miner_private_key) = setup_test_app().await;
+
+        let req = test::TestRequest::get().uri("/supply").to_request();
+        let before: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(before["invariant_ok"], true);
+
+        let req = test::TestRequest::post().uri("/mine").to_request();
+        let resp = test::call_service(&app, req).await;
+        assert!(resp.status().is_success());
+
+        let transact_req = serde_json::json!({
+            "to": blockchain::chain::BURN_ADDRESS,
+            "amount": 10,
+            "fee": 5,
+            "private_key": miner_private_key
+        });
+        let req = test::TestRequest::post().uri("/transact").set_json(&transact_req).to_request();
+        let resp = test::call_service(&app, req).await;
+        assert!(resp.status().is_success());
+
+        let req = test::TestRequest::post().uri("/mine").to_request();
+        let block: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        let reward = blockchain::chain::BLOCK_REWARD_COINS * blockchain::chain::COIN;
+        assert_eq!(block["transactions"][0]["outputs"][0]["value"], reward + 5);
+
+        let req = test::TestRequest::get().uri("/supply").to_request();
+        let after: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        let delta = |field: &str| after[field].as_u64().unwrap() - before[field].as_u64().unwrap();
+        assert_eq!(delta("issued"), 2 * reward + 5);
+        assert_eq!(delta("fees"), 5);
+        assert_eq!(delta("burned"), 10);
+        assert_eq!(delta("unspent"), 2 * reward - 10);
+        assert_eq!(after["invariant_ok"], true);
+    }
+
+    #[actix_web::test]
+    async fn test_address_fractals_endpoint() {
+        let (app, _) = setup_test_app().await;
+        let req = test::TestRequest::get().uri("/wallet/info").to_request();
+        let info: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        let miner_address = info["address"].as_str().unwrap().to_string();
+
+        let mandelbrot = serde_json::json!({
+            "type": "Mandelbrot",
+            "params": {
+                "width": 8, "height": 8,
+                "x_min": -2.0, "x_max": 1.0, "y_min": -1.5, "y_max": 1.5,
+                "max_iterations": 20
+            }
+        });
+        for params in [None, Some(&mandelbrot), None] {
+            let mut req = test::TestRequest::post().uri("/mine");
+            if let Some(params) = params {
+                req = req.set_json(params);
+            }
+            let resp = test::call_service(&app, req.to_request()).await;
+            assert!(resp.status().is_success());
+        }
+
+        let req = test::TestRequest::get().uri(&format!("/address/{}/fractals", miner_address)).to_request();
+        let entries: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        let entries = entries.as_array().unwrap();
+        assert_eq!(entries.len(), 3);
+        let types: Vec<&str> = entries.iter().map(|e| e["fractal_type"].as_str().unwrap()).collect();
+        assert_eq!(types, vec!["Sierpinski", "Mandelbrot", "Sierpinski"]);
+
+        let req = test::TestRequest::get()
+            .uri(&format!("/address/{}/fractals?page=1&limit=2", miner_address))
+            .to_request();
+        let page: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(page.as_array().unwrap().len(), 1);
+
+        let thumbnail_url = entries[1]["fractal_thumbnail_url"].as_str().unwrap();
+        let req = test::TestRequest::get().uri(thumbnail_url).to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.headers().get("content-type").unwrap(), "image/png");
+    }
+
+    #[actix_web::test]
+    async fn test_status_reports_display_decimals() {
+        let (app, _) = setup_test_app().await;
+        let req = test::TestRequest::get().uri("/status").to_request();
+        let status: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(status["display_decimals"], blockchain::chain::COIN_DECIMALS);
+        assert_eq!(status["p2p"]["oversized_messages_rejected"], 0);
+    }
+
+    #[actix_web::test]
+    async fn test_mempool_stats() {
+        let (app, private_key) = setup_test_app().await;
+        let req = test::TestRequest::post().uri("/mine").to_request();
+        test::call_service(&app, req).await;
+
+        let req = test::TestRequest::get().uri("/mempool").to_request();
+        let before: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(before["orphans"], 0);
+
+        let req = test::TestRequest::post()
+            .uri("/transact")
+            .set_json(serde_json::json!({ "to": "someone", "amount": 1, "private_key": private_key }))
+            .to_request();
+        assert!(test::call_service(&app, req).await.status().is_success());
+
+        let req = test::TestRequest::get().uri("/mempool").to_request();
+        let after: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(after["transactions"], before["transactions"].as_u64().unwrap() + 1);
+    }
+
+    /// Sends a `/ws` handshake with the given query string and subprotocol
+    /// header. The returned sender keeps the client side of the connection open.
+    async fn ws_handshake(
+        state: AppState,
+        query: &str,
+        subprotocol: Option<&str>,
+    ) -> (HttpResponse, impl Sized) {
+        use actix_web::FromRequest;
+
+        let mut req = test::TestRequest::get()
+            .uri(&format!("/ws?{}", query))
+            .insert_header(("upgrade", "websocket"))
+            .insert_header(("connection", "upgrade"))
+            .insert_header(("sec-websocket-version", "13"))
+            .insert_header(("sec-websocket-key", "dGhlIHNhbXBsZSBub25jZQ=="));
+        if let Some(subprotocol) = subprotocol {
+            req = req.insert_header(("sec-websocket-protocol", subprotocol));
+        }
+        let (req, _) = req.to_http_parts();
+        let (sender, payload) = actix_http::h1::Payload::create(false);
+        let stream = web::Payload::from_request(&req, &mut payload.into()).await.unwrap();
+        let query = web::Query::<WsQuery>::from_query(req.query_string()).unwrap();
+        (ws_route(req, stream, query, web::Data::new(state)).await.unwrap(), sender)
+    }
+
+    /// Opens a `/ws` session with the given query string and returns the
+    /// first frame it sends after a block is broadcast.
+    async fn first_ws_frame(query: &str, block: &blockchain::block::Block) -> actix_web::web::Bytes {
+        use actix_web::body::MessageBody;
+
+        let (state, _, _) = test_state(MempoolPool::default(), NetworkId::Regtest);
+        let hub = state.hub.clone();
+        let (resp, _sender) = ws_handshake(state, query, None).await;
+        assert_eq!(resp.status(), actix_web::http::StatusCode::SWITCHING_PROTOCOLS);
+
+        // The session only runs while its response body is polled, so poll
+        // it briefly to let the session register with the hub.
+        let mut body = resp.into_body();
+        let idle = actix::clock::timeout(
+            std::time::Duration::from_millis(50),
+            std::future::poll_fn(|cx| std::pin::Pin::new(&mut body).poll_next(cx)),
+        )
+        .await;
+        assert!(idle.is_err(), "no frames are sent before a block is broadcast");
+
+        hub.send(BroadcastBlock { block: block.clone() }).await.unwrap();
+        actix::clock::timeout(
+            std::time::Duration::from_secs(5),
+            std::future::poll_fn(|cx| std::pin::Pin::new(&mut body).poll_next(cx)),
+        )
+            .await
+            .expect("no frame was sent")
+            .unwrap()
+            .unwrap()
+    }
+
+    /// Returns the opcode and payload of a single unmasked WebSocket frame.
+    fn decode_frame(frame: &[u8]) -> (u8, &[u8]) {
+        let opcode = frame[0] & 0x0f;
+        let payload = match frame[1] & 0x7f {
+            126 => &frame[4..],
+            127 => &frame[10..],
+            _ => &frame[2..],
+        };
+        (opcode, payload)
+    }
+
+    #[actix_web::test]
+    async fn test_ws_binary_format_sends_gzipped_binary_frames() {
+        use std::io::Read;
+
+        // Other tests rewrite blockchain.json, so build the block once.
+        let block = Blockchain::new(1).chain[0].clone();
+        let frame = first_ws_frame("", &block).await;
+        let (opcode, payload) = decode_frame(&frame);
+        assert_eq!(opcode, 0x1, "text frames are the default");
+        let text_block: serde_json::Value = serde_json::from_slice(payload).unwrap();
+
+        let frame = first_ws_frame("format=binary", &block).await;
+        let (opcode, payload) = decode_frame(&frame);
+        assert_eq!(opcode, 0x2);
+        let mut json = String::new();
+        flate2::read::GzDecoder::new(payload).read_to_string(&mut json).unwrap();
+        let binary_block: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(binary_block, text_block);
+    }
+
+    #[actix_web::test]
+    async fn test_ws_requires_api_key_when_set() {
+        use actix_web::http::StatusCode;
+
+        let (mut state, _, _) = test_state(MempoolPool::default(), NetworkId::Regtest);
+        state.api_key = ApiKey(Some("secret".to_string()));
+        for (query, subprotocol) in [("", None), ("api_key=wrong", None), ("", Some("wrong, other"))] {
+            let (resp, _sender) = ws_handshake(state.clone(), query, subprotocol).await;
+            assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+        }
+
+        let (resp, _sender) = ws_handshake(state.clone(), "api_key=secret", None).await;
+        assert_eq!(resp.status(), StatusCode::SWITCHING_PROTOCOLS);
+        let (resp, _sender) = ws_handshake(state.clone(), "", Some("other, secret")).await;
+        assert_eq!(resp.status(), StatusCode::SWITCHING_PROTOCOLS);
+        assert_eq!(resp.headers().get("sec-websocket-protocol").unwrap(), "secret");
+
+        // Without a key, anyone may connect.
+        state.api_key = ApiKey::default();
+        let (resp, _sender) = ws_handshake(state, "", None).await;
+        assert_eq!(resp.status(), StatusCode::SWITCHING_PROTOCOLS);
+    }
+
+}
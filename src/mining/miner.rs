@@ -1,32 +1,536 @@
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
 use crate::blockchain::block::Block;
+use crate::blockchain::chain::Blockchain;
+use crate::blockchain::target::Target;
+use crate::core::canonical;
+use crate::core::mempool::{MempoolPool, MAX_BLOCK_TRANSACTIONS_SIZE};
+use crate::core::transaction::{Transaction, TxOutput};
 use crate::fractal::FractalType;
+use sierpchain_shared::mining::{MinedNonce, MiningTemplate, TemplateBlock};
 
 pub struct Miner;
 
+/// How long a mining job may run, unless the node is started with another cap.
+pub const DEFAULT_MINING_TIMEOUT_MS: u64 = 60_000;
+
+/// How much work a mining job may do before giving up, so that one at an
+/// unexpectedly high difficulty can't hold the chain forever.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MiningBudget {
+    /// The most nonces to try.
+    pub max_attempts: u64,
+    /// How long to try for, in milliseconds.
+    pub timeout_ms: u64,
+}
+
+impl MiningBudget {
+    /// No limit at all, for the genesis block and tests.
+    pub const UNLIMITED: MiningBudget = MiningBudget { max_attempts: u64::MAX, timeout_ms: u64::MAX };
+
+    /// Returns this budget with each limit lowered to that of `caps` if it
+    /// is higher.
+    pub fn within(self, caps: MiningBudget) -> MiningBudget {
+        MiningBudget { max_attempts: self.max_attempts.min(caps.max_attempts), timeout_ms: self.timeout_ms.min(caps.timeout_ms) }
+    }
+
+    /// When a job started at `started` has to stop, if it ever does.
+    fn deadline(&self, started: Instant) -> Option<Instant> {
+        started.checked_add(Duration::from_millis(self.timeout_ms))
+    }
+}
+
+impl Default for MiningBudget {
+    fn default() -> Self {
+        Self { max_attempts: u64::MAX, timeout_ms: DEFAULT_MINING_TIMEOUT_MS }
+    }
+}
+
+/// How a mining job ended.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+#[serde(tag = "outcome", rename_all = "snake_case")]
+#[allow(clippy::large_enum_variant)]
+pub enum MineOutcome {
+    Found(Block),
+    /// Every nonce in the budget was tried without meeting the target.
+    Exhausted { attempts: u64 },
+    /// The budget's time ran out first.
+    TimedOut,
+}
+
+impl MineOutcome {
+    /// Returns the mined block, if one was found.
+    pub fn found(self) -> Option<Block> {
+        match self {
+            MineOutcome::Found(block) => Some(block),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for MineOutcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MineOutcome::Found(block) => write!(f, "Mined block {} with nonce {}", block.index, block.nonce),
+            MineOutcome::Exhausted { attempts } => write!(f, "Tried {} nonces without meeting the target", attempts),
+            MineOutcome::TimedOut => write!(f, "Ran out of time before meeting the target"),
+        }
+    }
+}
+
+/// How many recently mined blocks the rolling hash rate is measured over.
+pub const RECENT_HASH_RATE_SAMPLES: usize = 10;
+
+/// The work that went into one mined block.
+#[derive(Debug, Clone, Copy)]
+struct HashRateSample {
+    /// The fractal's type name, as given by `FractalData::type_name`.
+    fractal: &'static str,
+    hashes: u64,
+    elapsed: Duration,
+}
+
+/// Measures this node's hash rate from the blocks it mines. Each nonce tried
+/// means generating a fractal and hashing the block, which counts as one hash.
+#[derive(Debug, Default)]
+pub struct HashRateMeter {
+    hashes: AtomicU64,
+    nanos: AtomicU64,
+    recent: Mutex<VecDeque<HashRateSample>>,
+}
+
+impl HashRateMeter {
+    /// Records that `hashes` nonces of a `fractal` block were tried in `elapsed`.
+    pub fn record(&self, fractal: &'static str, hashes: u64, elapsed: Duration) {
+        self.hashes.fetch_add(hashes, Ordering::Relaxed);
+        self.nanos.fetch_add(elapsed.as_nanos().try_into().unwrap_or(u64::MAX), Ordering::Relaxed);
+        let mut recent = self.recent.lock().unwrap();
+        if recent.len() == RECENT_HASH_RATE_SAMPLES {
+            recent.pop_front();
+        }
+        recent.push_back(HashRateSample { fractal, hashes, elapsed });
+    }
+
+    /// Returns the hashes per second over the last `RECENT_HASH_RATE_SAMPLES`
+    /// blocks, counting only those of the given fractal type if there is one.
+    /// Fractals differ a lot in how long they take to generate, so one
+    /// type's rate says little about another's.
+    pub fn recent_hashes_per_second(&self, fractal: Option<&str>) -> Option<f64> {
+        let recent = self.recent.lock().unwrap();
+        let (hashes, elapsed) = recent
+            .iter()
+            .filter(|sample| fractal.is_none_or(|fractal| sample.fractal == fractal))
+            .fold((0, Duration::ZERO), |(hashes, elapsed), sample| (hashes + sample.hashes, elapsed + sample.elapsed));
+        if elapsed.is_zero() {
+            return None;
+        }
+        Some(hashes as f64 / elapsed.as_secs_f64())
+    }
+
+    /// Returns the average hashes per second so far, or `None` before
+    /// anything has been mined.
+    pub fn hashes_per_second(&self) -> Option<f64> {
+        let nanos = self.nanos.load(Ordering::Relaxed);
+        if nanos == 0 {
+            return None;
+        }
+        Some(self.hashes.load(Ordering::Relaxed) as f64 / Duration::from_nanos(nanos).as_secs_f64())
+    }
+}
+
+/// The next block for an external miner to search nonces for.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct BlockTemplate {
+    /// The block to mine, with a placeholder fractal, nonce 0 and no hash.
+    /// The mined fractal must use the placeholder's `rng_version`.
+    pub block: Block,
+    /// The fractal generated from each nonce's seed. Its own seed is the
+    /// block's style seed.
+    pub fractal_type: FractalType,
+    /// How many times harder than its `bits` alone the block's target is,
+    /// from `FractalType::work_factor`.
+    pub work_factor: u64,
+    /// The target the block's hash must come out below, in hex: the one in
+    /// its `bits`, weighted by `work_factor`.
+    pub effective_target: String,
+    /// The block's transactions in the canonical encoding, so that a miner
+    /// can hash the block without encoding them itself.
+    pub encoded_transactions: String,
+}
+
+impl BlockTemplate {
+    pub fn new(block: Block, fractal_type: FractalType) -> Self {
+        let work_factor = fractal_type.work_factor();
+        let effective_target = Target::from_compact(block.bits).weighted(&fractal_type).to_string();
+        let encoded_transactions = canonical::to_string(&block.transactions);
+        Self { block, fractal_type, work_factor, effective_target, encoded_transactions }
+    }
+
+    /// Returns the target the mined block's hash must be below.
+    pub fn target(&self) -> Target {
+        Target::from_compact(self.block.bits).weighted(&self.fractal_type)
+    }
+
+    /// Returns the template as the shared nonce search takes it.
+    pub fn mining_template(&self) -> MiningTemplate {
+        let Block { index, timestamp, fractal, previous_hash, bits, style_seed, .. } = &self.block;
+        MiningTemplate {
+            block: TemplateBlock {
+                index: *index,
+                timestamp: *timestamp,
+                fractal: fractal.clone(),
+                previous_hash: previous_hash.clone(),
+                bits: *bits,
+                style_seed: *style_seed,
+            },
+            fractal_type: self.fractal_type.clone(),
+            encoded_transactions: self.encoded_transactions.clone(),
+        }
+    }
+
+    /// Returns the template's block mined with `mined`.
+    fn block_with(&self, mined: MinedNonce) -> Block {
+        Block { nonce: mined.nonce, fractal: mined.fractal, hash: mined.hash, ..self.block.clone() }
+    }
+}
+
+/// The proof-of-work target for the next block and how much work it takes.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct MiningTarget {
+    /// The leading zero hex digits the target requires, rounding towards
+    /// the easier target.
+    pub difficulty: usize,
+    /// The hex digits a block hash must start with.
+    pub target_prefix: String,
+    /// The target a block hash must be below, in compact form and in full.
+    pub bits: u32,
+    pub target: String,
+    /// The expected number of nonces to try: the target's work, at most
+    /// `u64::MAX`.
+    pub estimated_hashes: u64,
+    /// `estimated_hashes` divided by the node's hash rate, or `None` if the
+    /// node hasn't mined anything to measure it yet.
+    pub estimated_seconds_at_current_hashrate: Option<f64>,
+}
+
+impl MiningTarget {
+    pub fn new(bits: u32, hashes_per_second: Option<f64>) -> Self {
+        let target = Target::from_compact(bits);
+        let difficulty = target.difficulty();
+        let estimated_hashes = target.work().saturating_u64();
+        Self {
+            difficulty,
+            target_prefix: "0".repeat(difficulty),
+            bits,
+            target: target.to_string(),
+            estimated_hashes,
+            estimated_seconds_at_current_hashrate: hashes_per_second
+                .filter(|rate| *rate > 0.0)
+                .map(|rate| estimated_hashes as f64 / rate),
+        }
+    }
+}
+
 impl Miner {
+    /// Moves the transactions chosen for the next block out of the mempool,
+    /// parents before children, headed by a coinbase paying the miner's
+    /// share of the subsidy plus their fees to `miner_address`, and the rest
+    /// of the subsidy as the reward schedule says. Transactions that expire
+    /// at the new block's height are evicted rather than included.
+    pub fn assemble_transactions(blockchain: &Blockchain, pool: &mut MempoolPool, miner_address: &str) -> Vec<Transaction> {
+        Self::assemble_transactions_with_payouts(blockchain, pool, miner_address, Vec::new(), None)
+    }
+
+    /// Like `assemble_transactions`, with the miner's `coinbase_message`, if
+    /// any, in the coinbase.
+    pub fn assemble_transactions_with_message(
+        blockchain: &Blockchain,
+        pool: &mut MempoolPool,
+        miner_address: &str,
+        coinbase_message: Option<&str>,
+    ) -> Vec<Transaction> {
+        Self::assemble_transactions_with_payouts(blockchain, pool, miner_address, Vec::new(), coinbase_message)
+    }
+
+    /// Like `assemble_transactions_with_message`, but the coinbase pays
+    /// `payouts` first and only the rest of the miner's share and fees to
+    /// `miner_address`. The payouts must not add up to more than the miner's
+    /// share.
+    pub fn assemble_transactions_with_payouts(
+        blockchain: &Blockchain,
+        pool: &mut MempoolPool,
+        miner_address: &str,
+        payouts: Vec<TxOutput>,
+        coinbase_message: Option<&str>,
+    ) -> Vec<Transaction> {
+        pool.evict_expired(blockchain.chain.len() as u64);
+        let transactions = pool.select_for_block(blockchain, MAX_BLOCK_TRANSACTIONS_SIZE);
+        let fees: u64 = transactions.iter().filter_map(|tx| pool.fee(tx, blockchain)).sum();
+        pool.remove_confirmed(&transactions);
+        let paid_out: u64 = payouts.iter().map(|output| output.value).sum();
+        let mut reward = blockchain.reward_schedule.coinbase_outputs(blockchain.chain.len() as u64, miner_address);
+        reward[0].value = (reward[0].value + fees).saturating_sub(paid_out);
+        let mut outputs = payouts;
+        outputs.extend(reward);
+        let coinbase_tx = Transaction::coinbase(blockchain.chain.len(), outputs, blockchain.clock.now(), coinbase_message);
+
+        let mut block_transactions = vec![coinbase_tx];
+        block_transactions.extend(transactions);
+        block_transactions
+    }
+
     /// Mines a block using a proof-of-work algorithm that involves generating fractals.
     ///
-    /// The algorithm requires finding a nonce that, when used as a seed for the fractal,
-    /// produces a block hash that starts with a certain number of zeros.
-    pub fn mine_block(difficulty: usize, fractal_type: FractalType, mut block: Block) -> Block {
-        let prefix = "0".repeat(difficulty);
-
-        loop {
-            let mut current_fractal_type = fractal_type.clone();
-            match &mut current_fractal_type {
-                FractalType::Sierpinski { seed, .. } => *seed = block.nonce,
-                FractalType::Mandelbrot { seed, .. } => *seed = block.nonce,
-                FractalType::Julia { seed, .. } => *seed = block.nonce,
+    /// The algorithm requires finding a nonce that, when hashed together with the
+    /// block's position and style seed into a seed for the fractal, produces a
+    /// block hash that is below the block's target. Nonces from the block's own, which
+    /// `add_block` sets to 0, are shared out between `mining_threads`
+    /// workers, and the lowest one meeting the target wins, so mining the
+    /// same block twice finds the same nonce. Mining stops once `budget`
+    /// runs out, and the outcome says which of its limits was reached.
+    pub fn mine_block(fractal_type: FractalType, block: Block, budget: MiningBudget) -> MineOutcome {
+        let start_nonce = block.nonce;
+        let template = BlockTemplate::new(block, fractal_type);
+        let deadline = budget.deadline(Instant::now());
+        match Self::search(&template, start_nonce, budget.max_attempts, Self::mining_threads(), deadline) {
+            Some(block) => MineOutcome::Found(block),
+            None if deadline.is_some_and(|deadline| Instant::now() >= deadline) => MineOutcome::TimedOut,
+            None => MineOutcome::Exhausted { attempts: start_nonce.saturating_add(budget.max_attempts) - start_nonce },
+        }
+    }
+
+    /// How many nonces `mine_block` tries at once: one per available core.
+    /// Generating the fractal dominates each attempt, Mandelbrot and Julia
+    /// sets especially, so this is what parallelism buys.
+    pub fn mining_threads() -> usize {
+        std::thread::available_parallelism().map_or(1, usize::from)
+    }
+
+    /// Tries up to `max_attempts` nonces of a template from `start_nonce` on
+    /// and returns the mined block if one meets the target. External miners
+    /// call this in chunks, so they can report progress in between.
+    pub fn mine_template(template: &BlockTemplate, start_nonce: u64, max_attempts: u64) -> Option<Block> {
+        template.mining_template().mine(start_nonce, max_attempts).map(|mined| template.block_with(mined))
+    }
+
+    /// Like `mine_template`, but `threads` workers each take the next untried
+    /// nonce and generate its fractal, so that several are in progress at
+    /// once. Workers stop once every nonce below the best found so far has
+    /// been tried, which makes the result the lowest valid nonce, the same
+    /// block `mine_template` returns.
+    pub fn mine_template_parallel(template: &BlockTemplate, start_nonce: u64, max_attempts: u64, threads: usize) -> Option<Block> {
+        Self::search(template, start_nonce, max_attempts, threads, None)
+    }
+
+    /// `mine_template_parallel`, giving up at `deadline` if there is one.
+    fn search(
+        template: &BlockTemplate,
+        start_nonce: u64,
+        max_attempts: u64,
+        threads: usize,
+        deadline: Option<Instant>,
+    ) -> Option<Block> {
+        let mining = template.mining_template();
+        let target = mining.target();
+        let end = start_nonce.saturating_add(max_attempts);
+        let timed_out = || deadline.is_some_and(|deadline| Instant::now() >= deadline);
+        if threads <= 1 {
+            return (start_nonce..end)
+                .take_while(|_| !timed_out())
+                .find_map(|nonce| mining.try_nonce(&target, nonce))
+                .map(|mined| template.block_with(mined));
+        }
+        let next_nonce = AtomicU64::new(start_nonce);
+        let best_nonce = AtomicU64::new(u64::MAX);
+        let best: Mutex<Option<MinedNonce>> = Mutex::new(None);
+        std::thread::scope(|scope| {
+            for _ in 0..threads {
+                scope.spawn(|| {
+                    loop {
+                        let nonce = next_nonce.fetch_add(1, Ordering::Relaxed);
+                        if nonce >= end || nonce > best_nonce.load(Ordering::Relaxed) || timed_out() {
+                            break;
+                        }
+                        if let Some(mined) = mining.try_nonce(&target, nonce) {
+                            let mut best = best.lock().unwrap();
+                            if best.as_ref().is_none_or(|best| nonce < best.nonce) {
+                                best_nonce.store(nonce, Ordering::Relaxed);
+                                *best = Some(mined);
+                            }
+                        }
+                    }
+                });
             }
+        });
+        best.into_inner().unwrap().map(|mined| template.block_with(mined))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::clock::{Clock, MockClock};
+    use crate::core::script::UnlockingScript;
+    use crate::core::transaction::TxInput;
+    use std::sync::Arc;
 
-            block.fractal = current_fractal_type.generate();
+    fn spend(txid: &str, value: u64) -> Transaction {
+        Transaction::new(
+            vec![TxInput {
+                txid: txid.to_string(),
+                vout: 0,
+                script_sig: UnlockingScript::default(),
+                sequence: 0,
+            }],
+            vec![TxOutput { value, script_pub_key: "someone".into() }],
+        )
+    }
+
+    #[test]
+    fn test_chained_transactions_are_mined_parent_first() {
+        let mut blockchain = Blockchain::new(1);
+        let funding = Transaction::new(vec![], vec![TxOutput { value: 10_000, script_pub_key: "someone".into() }]);
+        blockchain.add_block(FractalType::Sierpinski { depth: 1, seed: 0 }, vec![funding.clone()]).unwrap();
+        let peer = blockchain.clone();
+
+        let parent = spend(&funding.id, 9_000);
+        let child = spend(&parent.id, 8_000);
+        let grandchild = spend(&child.id, 1_000);
+        let mut pool = MempoolPool::default();
+        // The grandchild pays by far the highest fee, so it is considered first.
+        for tx in [&grandchild, &child, &parent] {
+            pool.add(tx.clone()).unwrap();
+        }
 
-            let hash = block.calculate_hash();
-            if hash.starts_with(&prefix) {
-                block.hash = hash;
-                return block;
+        let transactions = Miner::assemble_transactions(&blockchain, &mut pool, "miner");
+        assert_eq!(transactions[0].outputs[0].value, blockchain.denomination.block_reward() + 9_000);
+        let ids: Vec<&str> = transactions[1..].iter().map(|tx| tx.id.as_str()).collect();
+        assert_eq!(ids, vec![parent.id.as_str(), child.id.as_str(), grandchild.id.as_str()]);
+        assert_eq!(pool.len(), 0);
+
+        let mut reordered = transactions.clone();
+        reordered.swap(1, 3);
+        let block = blockchain.clone().add_block(FractalType::Sierpinski { depth: 1, seed: 0 }, reordered).unwrap();
+        assert!(peer.clone().add_block_from_network(block).is_err());
+
+        let block = blockchain.add_block(FractalType::Sierpinski { depth: 1, seed: 0 }, transactions).unwrap();
+        assert!(peer.clone().add_block_from_network(block).is_ok());
+    }
+
+    #[test]
+    fn test_deterministic_assembly_mines_identical_blocks() {
+        let clock = Arc::new(MockClock::new(1_700_000_000));
+        let mut blockchain = Blockchain::new(1);
+        blockchain.clock = clock.clone();
+        let outputs = vec![TxOutput { value: 10_000, script_pub_key: "someone".into() }; 2];
+        let funding = Transaction::new_at(vec![], outputs, clock.now());
+        blockchain.add_block(FractalType::Sierpinski { depth: 1, seed: 0 }, vec![funding.clone()]).unwrap();
+        clock.advance(10);
+
+        // Two spends paying the same fee rate, received in either order.
+        let spends: Vec<Transaction> = (0..2)
+            .map(|vout| {
+                let input = TxInput { txid: funding.id.clone(), vout, script_sig: UnlockingScript::default(), sequence: 0 };
+                let output = TxOutput { value: 9_000, script_pub_key: "someone".into() };
+                Transaction::new_at(vec![input], vec![output], clock.now())
+            })
+            .collect();
+        let mine = |arrival: [&Transaction; 2]| {
+            let mut blockchain = blockchain.clone();
+            let mut pool = MempoolPool::default().with_clock(clock.clone()).with_deterministic_order(true);
+            for tx in arrival {
+                pool.add(tx.clone()).unwrap();
             }
-            block.nonce += 1;
+            let transactions = Miner::assemble_transactions(&blockchain, &mut pool, "miner");
+            blockchain.add_block(FractalType::Sierpinski { depth: 3, seed: 0 }, transactions).unwrap()
+        };
+
+        let first = mine([&spends[0], &spends[1]]);
+        let second = mine([&spends[1], &spends[0]]);
+        assert_eq!(first.hash, second.hash);
+        assert_eq!(first, second);
+        let mut txids = vec![spends[0].id.as_str(), spends[1].id.as_str()];
+        txids.sort();
+        assert_eq!(first.transactions[1..].iter().map(|tx| tx.id.as_str()).collect::<Vec<_>>(), txids);
+    }
+
+    #[test]
+    fn test_parallel_mining_finds_the_sequential_block() {
+        let blockchain = Blockchain::with_clock(2, Arc::new(MockClock::new(1_700_000_000)));
+        let fractal_type = FractalType::mandelbrot(16, 16, -2.0, 1.0, -1.5, 1.5, 50, 0).unwrap();
+        let template = BlockTemplate::new(blockchain.next_block(&fractal_type, vec![]).unwrap(), fractal_type.clone());
+        let sequential = Miner::mine_template(&template, 0, u64::MAX).unwrap();
+        assert!(sequential.nonce > 0);
+        for threads in [2, 4, 7] {
+            let parallel = Miner::mine_template_parallel(&template, 0, u64::MAX, threads).unwrap();
+            assert_eq!(parallel, sequential);
+            // The stored fractal is the one its nonce's seed regenerates.
+            assert_eq!(fractal_type.clone().with_seed(parallel.fractal_seed()).generate(), parallel.fractal);
+            assert!(blockchain.clone().add_block_from_network(parallel).is_ok());
         }
+        assert_eq!(Miner::mine_template_parallel(&template, 0, sequential.nonce, 4), None);
+    }
+
+    #[test]
+    fn test_mining_gives_up_when_its_budget_runs_out() {
+        let mut blockchain = Blockchain::with_clock(1, Arc::new(MockClock::new(1_700_000_000)));
+        blockchain.params.min_difficulty = 16;
+        let fractal_type = FractalType::Sierpinski { depth: 1, seed: 0 };
+        let block = blockchain.next_block(&fractal_type, vec![]).unwrap();
+
+        let attempts = MiningBudget { max_attempts: 50, ..MiningBudget::UNLIMITED };
+        assert_eq!(Miner::mine_block(fractal_type.clone(), block.clone(), attempts), MineOutcome::Exhausted { attempts: 50 });
+        let time = MiningBudget { timeout_ms: 0, ..MiningBudget::UNLIMITED };
+        assert_eq!(Miner::mine_block(fractal_type.clone(), block, time), MineOutcome::TimedOut);
+
+        assert_eq!(blockchain.mine_next_block(fractal_type, vec![], attempts), Ok(MineOutcome::Exhausted { attempts: 50 }));
+        assert_eq!(blockchain.chain.len(), 1);
+        assert_eq!(MiningBudget::default().within(attempts), MiningBudget { max_attempts: 50, timeout_ms: DEFAULT_MINING_TIMEOUT_MS });
+    }
+
+    #[test]
+    fn test_mining_target_estimates() {
+        for (difficulty, expected) in [(1, 16), (2, 256), (3, 4_096), (4, 65_536), (5, 1_048_576)] {
+            let target = MiningTarget::new(Target::from_difficulty(difficulty).to_compact(), Some(256.0));
+            assert_eq!(target.target_prefix, "0".repeat(difficulty));
+            assert_eq!(target.estimated_hashes, expected);
+            assert_eq!(target.estimated_seconds_at_current_hashrate, Some(expected as f64 / 256.0));
+        }
+        // Targets between the powers of 16 take proportionally more work.
+        let target = MiningTarget::new(Target::from_difficulty(2).scaled(1, 2).to_compact(), None);
+        assert_eq!((target.difficulty, target.estimated_hashes), (2, 512));
+        assert_eq!(MiningTarget::new(Target::from_difficulty(16).to_compact(), None).estimated_hashes, u64::MAX);
+        assert_eq!(MiningTarget::new(Target::from_difficulty(1).to_compact(), None).estimated_seconds_at_current_hashrate, None);
+    }
+
+    #[test]
+    fn test_hash_rate_meter_averages_recorded_work() {
+        let meter = HashRateMeter::default();
+        assert_eq!(meter.hashes_per_second(), None);
+        meter.record("Sierpinski", 100, Duration::from_secs(1));
+        meter.record("Sierpinski", 300, Duration::from_secs(1));
+        assert_eq!(meter.hashes_per_second(), Some(200.0));
+    }
+
+    #[test]
+    fn test_recent_hash_rate_rolls_over_by_fractal() {
+        let meter = HashRateMeter::default();
+        meter.record("Mandelbrot", 10, Duration::from_secs(10));
+        for _ in 0..RECENT_HASH_RATE_SAMPLES - 1 {
+            meter.record("Sierpinski", 400, Duration::from_secs(1));
+        }
+        assert_eq!(meter.recent_hashes_per_second(Some("Mandelbrot")), Some(1.0));
+        assert_eq!(meter.recent_hashes_per_second(Some("Julia")), None);
+
+        // The oldest sample drops out once the window is full.
+        meter.record("Sierpinski", 400, Duration::from_secs(1));
+        assert_eq!(meter.recent_hashes_per_second(Some("Mandelbrot")), None);
+        assert_eq!(meter.recent_hashes_per_second(None), Some(400.0));
+        assert!(meter.hashes_per_second().unwrap() < 400.0);
     }
 }
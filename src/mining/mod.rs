@@ -1 +1,2 @@
 pub mod miner;
+pub mod policy;
@@ -0,0 +1,161 @@
+use actix::Addr;
+use serde::{Deserialize, Serialize};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::mpsc;
+
+use crate::api::handlers::{mine_and_announce, TransactionPool};
+use crate::api::health::{SharedHealth, SAFE_MODE_OFF};
+use crate::api::websocket::BroadcastHub;
+use crate::blockchain::block::Block;
+use crate::blockchain::chain::Blockchain;
+use crate::fractal::FractalType;
+use crate::mining::miner::{HashRateMeter, MineOutcome, Miner, MiningBudget};
+use crate::network::p2p::P2pMessage;
+
+/// When blocks are worth mining: a node can hold off until the mempool has
+/// enough transactions, rather than filling the chain with coinbase-only
+/// blocks. Explicit `/mine?force=true` calls ignore it.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(default, deny_unknown_fields)]
+pub struct MiningPolicy {
+    /// Whether a block with nothing but its coinbase may be mined.
+    pub mine_empty_blocks: bool,
+    /// How many mempool transactions a block waits for.
+    pub min_transactions: usize,
+    /// The most nonces a mining job tries, up to the node's cap. Unset
+    /// means the cap.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_attempts: Option<u64>,
+    /// How long a mining job runs for, in milliseconds, up to the node's
+    /// cap. Unset means the cap.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub timeout_ms: Option<u64>,
+    /// What the miner puts in the coinbase of the blocks it mines, up to
+    /// `MAX_COINBASE_MESSAGE_BYTES`. `/mine` and `/mining/template` may ask
+    /// for another.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub coinbase_message: Option<String>,
+}
+
+impl Default for MiningPolicy {
+    fn default() -> Self {
+        Self { mine_empty_blocks: true, min_transactions: 0, max_attempts: None, timeout_ms: None, coinbase_message: None }
+    }
+}
+
+pub type SharedMiningPolicy = Arc<Mutex<MiningPolicy>>;
+
+impl MiningPolicy {
+    /// How many mempool transactions the next block needs.
+    pub fn required_transactions(&self) -> usize {
+        if self.mine_empty_blocks {
+            self.min_transactions
+        } else {
+            self.min_transactions.max(1)
+        }
+    }
+
+    /// Whether a block may be mined with `pending` transactions in the mempool.
+    pub fn allows(&self, pending: usize) -> bool {
+        pending >= self.required_transactions()
+    }
+
+    /// The budget a mining job gets by default, within `caps`.
+    pub fn budget(&self, caps: MiningBudget) -> MiningBudget {
+        MiningBudget {
+            max_attempts: self.max_attempts.unwrap_or(caps.max_attempts),
+            timeout_ms: self.timeout_ms.unwrap_or(caps.timeout_ms),
+        }
+        .within(caps)
+    }
+}
+
+/// Mines a block on a timer when `AUTO_MINE` is set, following the node's
+/// mining policy. It stops while the node is in safe mode.
+pub struct AutoMiner {
+    pub blockchain: Arc<Mutex<Blockchain>>,
+    pub tx_pool: TransactionPool,
+    pub policy: SharedMiningPolicy,
+    pub miner_address: String,
+    pub hash_rate: Arc<HashRateMeter>,
+    pub to_p2p: mpsc::UnboundedSender<P2pMessage>,
+    pub hub: Addr<BroadcastHub>,
+    /// The most any mining job may spend.
+    pub caps: MiningBudget,
+    /// Ticks are skipped while the node is in safe mode.
+    pub health: SharedHealth,
+}
+
+impl AutoMiner {
+    /// Mines the next block from the mempool, unless the policy says to
+    /// wait for more transactions. A block not found within the policy's
+    /// budget skips the tick, so that the next one starts on time.
+    pub fn tick(&self) -> Option<Block> {
+        if self.health.is_failing(SAFE_MODE_OFF) {
+            tracing::debug!("Skipping auto-mine: the node is in safe mode");
+            return None;
+        }
+        let mut blockchain = self.blockchain.lock().unwrap();
+        let mut pool = self.tx_pool.lock().unwrap();
+        let policy = self.policy.lock().unwrap().clone();
+        if !policy.allows(pool.len()) {
+            tracing::debug!(
+                "Skipping auto-mine: {} of {} transactions pending",
+                pool.len(),
+                policy.required_transactions()
+            );
+            return None;
+        }
+        let transactions =
+            Miner::assemble_transactions_with_message(&blockchain, &mut pool, &self.miner_address, policy.coinbase_message.as_deref());
+        let fractal_type = FractalType::Sierpinski { depth: 5, seed: 0 };
+        let budget = policy.budget(self.caps);
+        match mine_and_announce(&mut blockchain, &mut pool, fractal_type, transactions, budget, &self.hash_rate, &self.to_p2p, &self.hub) {
+            Ok(MineOutcome::Found(block)) => Some(block),
+            Ok(outcome) => {
+                tracing::warn!("Skipping auto-mine tick: {}", outcome);
+                None
+            }
+            Err(e) => {
+                tracing::error!("Auto-mine failed: {}", e);
+                None
+            }
+        }
+    }
+
+    /// Ticks every `period`, the first time one period from now.
+    pub async fn run(self, period: Duration) {
+        let mut interval = tokio::time::interval(period);
+        interval.tick().await; // First tick is immediate
+        loop {
+            interval.tick().await;
+            self.tick();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_policy_counts_pending_transactions() {
+        assert!(MiningPolicy::default().allows(0));
+        let no_empty = MiningPolicy { mine_empty_blocks: false, ..MiningPolicy::default() };
+        assert!(!no_empty.allows(0));
+        assert!(no_empty.allows(1));
+        let batches = MiningPolicy { min_transactions: 3, ..MiningPolicy::default() };
+        assert_eq!(batches.required_transactions(), 3);
+        assert!(!batches.allows(2));
+        assert!(batches.allows(3));
+    }
+
+    #[test]
+    fn test_policy_budget_stays_within_the_caps() {
+        let caps = MiningBudget { max_attempts: 1_000, timeout_ms: 5_000 };
+        assert_eq!(MiningPolicy::default().budget(caps), caps);
+        let policy = MiningPolicy { max_attempts: Some(10), timeout_ms: Some(60_000), ..MiningPolicy::default() };
+        assert_eq!(policy.budget(caps), MiningBudget { max_attempts: 10, timeout_ms: 5_000 });
+    }
+}
@@ -0,0 +1,123 @@
+use std::num::NonZeroUsize;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use lru::LruCache;
+
+use super::p2p::P2pMessage;
+
+/// The number of recently seen blocks and transactions to remember.
+pub const SEEN_CACHE_CAPACITY: usize = 500;
+/// How long a block or transaction is remembered for.
+pub const SEEN_CACHE_TTL: Duration = Duration::from_secs(60);
+
+/// Remembers the blocks and transactions the node has recently processed, so
+/// that copies relayed by other peers can be dropped without revalidating
+/// them.
+///
+/// Gossipsub already deduplicates by message ID, but two peers can publish
+/// the same block independently under different IDs. This cache is keyed by
+/// block hash and transaction ID instead.
+#[derive(Debug)]
+pub struct SeenCache {
+    entries: LruCache<String, Instant>,
+    ttl: Duration,
+}
+
+pub type SeenMessages = Arc<Mutex<SeenCache>>;
+
+impl Default for SeenCache {
+    fn default() -> Self {
+        Self::new(SEEN_CACHE_CAPACITY, SEEN_CACHE_TTL)
+    }
+}
+
+impl SeenCache {
+    pub fn new(capacity: usize, ttl: Duration) -> Self {
+        let capacity = NonZeroUsize::new(capacity).expect("seen cache capacity must be non-zero");
+        Self { entries: LruCache::new(capacity), ttl }
+    }
+
+    /// Records `key` as seen, returning true if it was already seen within
+    /// the TTL.
+    pub fn check_and_insert(&mut self, key: String) -> bool {
+        let now = Instant::now();
+        match self.entries.put(key, now) {
+            Some(seen_at) => now.duration_since(seen_at) < self.ttl,
+            None => false,
+        }
+    }
+
+    /// Returns true if `message` is a block, header, transaction or NFT claim that has already
+    /// been processed recently. Other messages are never duplicates.
+    pub fn is_duplicate(&mut self, message: &P2pMessage) -> bool {
+        match message {
+            P2pMessage::Block(block) => self.check_and_insert(format!("block:{}", block.hash)),
+            P2pMessage::BlockHeader(header) => self.check_and_insert(format!("header:{}", header.hash)),
+            P2pMessage::Transaction(tx) => self.check_and_insert(format!("tx:{}", tx.id)),
+            P2pMessage::NftClaim(claim) => self.check_and_insert(format!("nft:{}", claim.fractal_hash())),
+            P2pMessage::ChainRequest
+            | P2pMessage::ChainResponse(_)
+            | P2pMessage::GetBlocks { .. }
+            | P2pMessage::Blocks(_)
+            | P2pMessage::MempoolInv(_)
+            | P2pMessage::GetTransactions(_) => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blockchain::chain::Blockchain;
+    use crate::fractal::FractalType;
+    use crate::network::p2p::{forward_gossip_message, InboundMessage, P2pMetrics, DEFAULT_MAX_MESSAGE_SIZE};
+    use libp2p::PeerId;
+    use tokio::sync::mpsc;
+
+    #[test]
+    fn test_relayed_block_is_processed_once() {
+        let mut origin = Blockchain::new(1);
+        let mut blockchain = origin.clone();
+        let block = origin.add_block(FractalType::Sierpinski { depth: 1, seed: 0 }, vec![]).unwrap();
+
+        // The same block, relayed by two peers in separate gossip messages.
+        let (sender, mut receiver) = mpsc::unbounded_channel::<InboundMessage>();
+        let data = serde_json::to_vec(&P2pMessage::Block(block)).unwrap();
+        let metrics = P2pMetrics::default();
+        forward_gossip_message(&sender, PeerId::random(), None, &data, DEFAULT_MAX_MESSAGE_SIZE, &metrics);
+        forward_gossip_message(&sender, PeerId::random(), None, &data, DEFAULT_MAX_MESSAGE_SIZE, &metrics);
+
+        let mut seen = SeenCache::default();
+        let mut processed = 0;
+        while let Ok(InboundMessage { message, .. }) = receiver.try_recv() {
+            if seen.is_duplicate(&message) {
+                continue;
+            }
+            if let P2pMessage::Block(block) = message {
+                processed += 1;
+                assert!(blockchain.add_block_from_network(block).is_ok());
+            }
+        }
+        assert_eq!(processed, 1);
+    }
+
+    #[test]
+    fn test_seen_cache_evicts_beyond_capacity() {
+        let mut seen = SeenCache::new(2, SEEN_CACHE_TTL);
+        assert!(!seen.check_and_insert("a".to_string()));
+        assert!(!seen.check_and_insert("b".to_string()));
+        assert!(!seen.check_and_insert("c".to_string()));
+
+        // "a" was the least recently used entry, so it was evicted.
+        assert!(!seen.check_and_insert("a".to_string()));
+        assert!(seen.check_and_insert("c".to_string()));
+    }
+
+    #[test]
+    fn test_seen_cache_expires_entries() {
+        let mut seen = SeenCache::new(SEEN_CACHE_CAPACITY, Duration::ZERO);
+        assert!(!seen.check_and_insert("a".to_string()));
+        assert!(!seen.check_and_insert("a".to_string()));
+    }
+}
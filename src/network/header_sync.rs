@@ -0,0 +1,67 @@
+use crate::blockchain::block::{Block, BlockHeader};
+use crate::blockchain::chain::Blockchain;
+use crate::network::p2p::P2pMessage;
+
+/// The message to gossip in place of `message`. With header-first relay a
+/// block goes out as its header alone.
+pub fn outbound(message: P2pMessage, header_first: bool) -> P2pMessage {
+    match message {
+        P2pMessage::Block(block) if header_first => P2pMessage::BlockHeader(BlockHeader::from(&block)),
+        message => message,
+    }
+}
+
+/// The request for the blocks from our tip up to an announced header, or
+/// `None` if the chain already has a block at its height.
+pub fn request_for(header: &BlockHeader, blockchain: &Blockchain) -> Option<P2pMessage> {
+    let height = blockchain.chain.len() as u64;
+    (header.index >= height).then_some(P2pMessage::GetBlocks { from_index: height })
+}
+
+/// Connects the blocks from a `Blocks` answer that build on the tip, in
+/// order, skipping the heights the chain already has and stopping at the
+/// first block that doesn't connect. Returns the blocks connected.
+pub fn connect(blockchain: &mut Blockchain, blocks: Vec<Block>) -> Vec<Block> {
+    let mut connected = Vec::new();
+    for block in blocks {
+        if block.index < blockchain.chain.len() as u64 {
+            continue;
+        }
+        if blockchain.add_block_from_network(block.clone()).is_err() {
+            break;
+        }
+        connected.push(block);
+    }
+    connected
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fractal::FractalType;
+
+    #[test]
+    fn test_peer_fetches_the_blocks_behind_a_header() {
+        let mut node_a = Blockchain::new(1);
+        let mut node_b = node_a.clone();
+        node_a.add_block(FractalType::Sierpinski { depth: 1, seed: 0 }, vec![]).unwrap();
+        let block = node_a.add_block(FractalType::Sierpinski { depth: 1, seed: 0 }, vec![]).unwrap();
+
+        assert!(matches!(outbound(P2pMessage::Block(block.clone()), false), P2pMessage::Block(_)));
+        let P2pMessage::BlockHeader(header) = outbound(P2pMessage::Block(block.clone()), true) else {
+            panic!("expected a header");
+        };
+        assert_eq!(header.hash, block.hash);
+
+        // B is two blocks behind, so it asks for both.
+        let Some(P2pMessage::GetBlocks { from_index }) = request_for(&header, &node_b) else {
+            panic!("expected a GetBlocks request");
+        };
+        assert_eq!(from_index, 1);
+        let connected = connect(&mut node_b, node_a.blocks_from(from_index));
+        assert_eq!(connected.len(), 2);
+        assert_eq!(node_b.chain.last().unwrap().hash, block.hash);
+        assert!(request_for(&header, &node_b).is_none());
+        assert!(connect(&mut node_b, node_a.blocks_from(0)).is_empty());
+    }
+}
@@ -0,0 +1,167 @@
+use std::collections::HashSet;
+
+use crate::core::mempool::MempoolPool;
+use crate::core::transaction::Transaction;
+
+/// The most transaction IDs announced in, or read from, one `MempoolInv`.
+pub const MAX_INV_TXIDS: usize = 5_000;
+/// The most transactions asked for, or sent, in answer to one `GetTransactions`.
+pub const MAX_GET_TRANSACTIONS: usize = 500;
+/// The most pooled transactions sent outright to a newly connected peer.
+pub const MAX_PUSHED_TRANSACTIONS: usize = 100;
+
+/// The pool's transaction IDs to announce to a newly connected peer.
+pub fn inventory(pool: &MempoolPool) -> Vec<String> {
+    pool.txids().take(MAX_INV_TXIDS).map(str::to_string).collect()
+}
+
+/// What to send a newly connected peer: the first `MAX_PUSHED_TRANSACTIONS`
+/// pooled transactions, in pool order so parents go out ahead of the
+/// children spending them, and the IDs of the rest for it to ask for.
+pub fn for_new_peer(pool: &MempoolPool) -> (Vec<Transaction>, Vec<String>) {
+    let mut txids = pool.txids();
+    let pushed = txids.by_ref().take(MAX_PUSHED_TRANSACTIONS).filter_map(|txid| pool.get(txid).cloned()).collect();
+    let announced = txids.take(MAX_INV_TXIDS).map(str::to_string).collect();
+    (pushed, announced)
+}
+
+/// The IDs from a peer's inventory that are neither pooled nor waiting as
+/// orphans, in the order the peer announced them.
+pub fn missing(pool: &MempoolPool, txids: &[String]) -> Vec<String> {
+    let mut seen = HashSet::new();
+    txids
+        .iter()
+        .take(MAX_INV_TXIDS)
+        .filter(|txid| !pool.contains(txid) && !pool.contains_orphan(txid) && seen.insert(txid.as_str()))
+        .take(MAX_GET_TRANSACTIONS)
+        .cloned()
+        .collect()
+}
+
+/// The pooled transactions a peer asked for. They're returned in pool order,
+/// so parents go out ahead of the children spending them.
+pub fn requested(pool: &MempoolPool, txids: &[String]) -> Vec<Transaction> {
+    let wanted: HashSet<&str> = txids.iter().take(MAX_GET_TRANSACTIONS).map(String::as_str).collect();
+    pool.txids()
+        .filter(|txid| wanted.contains(txid))
+        .filter_map(|txid| pool.get(txid).cloned())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::script::UnlockingScript;
+    use crate::blockchain::chain::Blockchain;
+    use crate::core::transaction::{TxInput, TxOutput};
+    use crate::fractal::FractalType;
+
+    fn spend(txid: &str, vout: usize, value: u64) -> Transaction {
+        Transaction::new(
+            vec![TxInput {
+                txid: txid.to_string(),
+                vout,
+                script_sig: UnlockingScript::default(),
+                sequence: 0,
+            }],
+            vec![TxOutput { value, script_pub_key: "someone".into() }],
+        )
+    }
+
+    #[test]
+    fn test_fresh_peer_catches_up_with_mempool() {
+        let mut blockchain = Blockchain::new(1);
+        let funding = Transaction::new(
+            vec![],
+            vec![
+                TxOutput { value: 10_000, script_pub_key: "someone".into() },
+                TxOutput { value: 10_000, script_pub_key: "someone".into() },
+            ],
+        );
+        blockchain.add_block(FractalType::Sierpinski { depth: 1, seed: 0 }, vec![funding.clone()]).unwrap();
+
+        let parent = spend(&funding.id, 0, 10_000);
+        let child = spend(&parent.id, 0, 9_000);
+        let unrelated = spend(&funding.id, 1, 9_000);
+        let mut node_a = MempoolPool::default();
+        for tx in [parent, child, unrelated] {
+            node_a.accept(tx, &blockchain).unwrap();
+        }
+
+        // Node B connects with an empty pool; A announces its inventory.
+        let mut node_b = MempoolPool::default();
+        let wanted = missing(&node_b, &inventory(&node_a));
+        assert_eq!(wanted.len(), 3);
+        for tx in requested(&node_a, &wanted) {
+            node_b.accept(tx, &blockchain).unwrap();
+        }
+
+        assert_eq!(node_b.txids().collect::<Vec<_>>(), node_a.txids().collect::<Vec<_>>());
+        assert_eq!(node_b.orphan_count(), 0);
+        // Announcing the same inventory again asks for nothing.
+        assert!(missing(&node_b, &inventory(&node_a)).is_empty());
+    }
+
+    #[test]
+    fn test_new_peer_is_sent_the_pooled_transactions() {
+        let mut blockchain = Blockchain::new(1);
+        let funding = Transaction::new(
+            vec![],
+            vec![
+                TxOutput { value: 10_000, script_pub_key: "someone".into() },
+                TxOutput { value: 10_000, script_pub_key: "someone".into() },
+            ],
+        );
+        blockchain.add_block(FractalType::Sierpinski { depth: 1, seed: 0 }, vec![funding.clone()]).unwrap();
+
+        let parent = spend(&funding.id, 0, 10_000);
+        let child = spend(&parent.id, 0, 9_000);
+        let unrelated = spend(&funding.id, 1, 9_000);
+        let mut node_a = MempoolPool::default();
+        for tx in [parent, child, unrelated] {
+            node_a.accept(tx, &blockchain).unwrap();
+        }
+
+        // Node B connects with an empty pool and is sent all three.
+        let (pushed, announced) = for_new_peer(&node_a);
+        assert_eq!(pushed.len(), 3);
+        assert!(announced.is_empty());
+        let mut node_b = MempoolPool::default();
+        for tx in &pushed {
+            node_b.accept(tx.clone(), &blockchain).unwrap();
+        }
+        assert_eq!(node_b.txids().collect::<Vec<_>>(), node_a.txids().collect::<Vec<_>>());
+        assert_eq!(node_b.orphan_count(), 0);
+
+        // Sent again, say by another peer, they are already pooled and dropped.
+        assert!(pushed.iter().all(|tx| node_b.contains(&tx.id)));
+        assert!(missing(&node_b, &inventory(&node_a)).is_empty());
+    }
+
+    #[test]
+    fn test_pushes_to_new_peers_are_capped() {
+        let mut pool = MempoolPool::default();
+        for vout in 0..MAX_PUSHED_TRANSACTIONS + 5 {
+            pool.add(spend("unknown", vout, 1)).unwrap();
+        }
+        let (pushed, announced) = for_new_peer(&pool);
+        assert_eq!(pushed.len(), MAX_PUSHED_TRANSACTIONS);
+        assert_eq!(announced.len(), 5);
+        let txids: Vec<&str> = pushed.iter().map(|tx| tx.id.as_str()).chain(announced.iter().map(String::as_str)).collect();
+        assert_eq!(txids, pool.txids().collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_requests_are_capped() {
+        let mut pool = MempoolPool::default();
+        let txids: Vec<String> = (0..MAX_GET_TRANSACTIONS + 10).map(|i| format!("tx{}", i)).collect();
+        assert_eq!(missing(&pool, &txids).len(), MAX_GET_TRANSACTIONS);
+
+        let tx = spend("unknown", 0, 1);
+        pool.add(tx.clone()).unwrap();
+        let mut asked = vec![String::new(); MAX_GET_TRANSACTIONS];
+        asked.push(tx.id.clone());
+        assert!(requested(&pool, &asked).is_empty());
+        assert_eq!(requested(&pool, &[tx.id.clone(), tx.id]).len(), 1);
+    }
+}
@@ -1 +1,8 @@
+pub mod dedup;
+pub mod header_sync;
+pub mod mempool_sync;
+pub mod network_id;
 pub mod p2p;
+pub mod peer_store;
+pub mod reorg_guard;
+pub mod rpc;
@@ -0,0 +1,34 @@
+use clap::ValueEnum;
+use serde::Serialize;
+use std::fmt;
+
+/// Which network a node is configured for. Test networks enable
+/// conveniences, such as the faucet, that must never run on mainnet.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum NetworkId {
+    #[default]
+    Mainnet,
+    /// A shared test network, where coins have no value.
+    Testnet,
+    /// A local network for development, where blocks are mined on demand.
+    Regtest,
+}
+
+impl NetworkId {
+    /// Returns true for the networks whose coins are worthless.
+    pub fn is_test_network(self) -> bool {
+        !matches!(self, NetworkId::Mainnet)
+    }
+}
+
+impl fmt::Display for NetworkId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            NetworkId::Mainnet => "mainnet",
+            NetworkId::Testnet => "testnet",
+            NetworkId::Regtest => "regtest",
+        };
+        f.write_str(name)
+    }
+}
@@ -3,18 +3,24 @@ use libp2p::{
     identity,
     mdns,
     noise,
-    swarm::{NetworkBehaviour, SwarmBuilder},
+    swarm::{self, behaviour::toggle::Toggle, NetworkBehaviour},
     tcp,
     PeerId, Swarm,
     futures::StreamExt, Multiaddr,
-    kad::{store::MemoryStore, Event as KadEvent, Kademlia},
-    identify, Transport,
+    kad::{self, store::MemoryStore, Event as KadEvent},
+    identify, multiaddr::Protocol, Transport,
 };
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 use tokio::sync::mpsc;
 use tracing::{error, info, warn};
-use crate::blockchain::{block::Block, chain::Blockchain};
+use crate::blockchain::{block::{Block, BlockHeader}, chain::Blockchain};
+use crate::core::nft::NftClaim;
 use crate::core::transaction::Transaction;
+use crate::network::header_sync;
+use crate::network::peer_store::SharedPeerStore;
 use serde::{Serialize, Deserialize};
 use std::fmt;
 
@@ -22,23 +28,192 @@ use std::fmt;
 pub enum P2pMessage {
     ChainRequest,
     ChainResponse(Blockchain),
+    /// Asks peers for their blocks from `from_index` to the tip, e.g. to
+    /// fast-sync from a checkpoint.
+    GetBlocks { from_index: u64 },
+    /// The answer to `GetBlocks`.
+    Blocks(Vec<Block>),
     Block(Block),
+    /// Announces a block without its body, sent instead of `Block` by nodes
+    /// relaying headers first.
+    BlockHeader(BlockHeader),
     Transaction(Transaction),
+    /// The IDs of the transactions in the sender's mempool, announced when a
+    /// peer connects.
+    MempoolInv(Vec<String>),
+    /// Asks peers for the pooled transactions with these IDs, which they
+    /// answer with `Transaction` messages.
+    GetTransactions(Vec<String>),
+    /// Registers the ownership of a fractal combination.
+    NftClaim(NftClaim),
+}
+
+/// A message delivered from the network layer.
+#[derive(Debug)]
+pub struct InboundMessage {
+    /// The peer it came from, or `None` for messages that originate locally.
+    pub source: Option<PeerId>,
+    /// The gossipsub ID to report the message's validation result under,
+    /// for messages that arrived by gossip. Gossipsub only relays them once
+    /// `P2pControl::ReportValidation` accepts them.
+    pub message_id: Option<gossipsub::MessageId>,
+    pub message: P2pMessage,
+}
+
+impl InboundMessage {
+    /// A message raised by this node rather than received from a peer.
+    pub fn local(message: P2pMessage) -> Self {
+        Self { source: None, message_id: None, message }
+    }
+}
+
+/// Per-peer message counters, surfaced by `GET /peers`.
+#[derive(Debug, Default, Clone, Serialize)]
+pub struct PeerStats {
+    pub blocks_received: u64,
+    pub invalid_blocks: u64,
+    pub transactions: u64,
+}
+
+pub type PeerStatsMap = Arc<Mutex<HashMap<PeerId, PeerStats>>>;
+
+/// Commands sent to the running swarm from outside the P2P task.
+#[derive(Debug)]
+pub enum P2pControl {
+    Dial(Multiaddr),
+    Disconnect(PeerId),
+    /// Adds to a peer's misbehavior score, banning it if the score gets too high.
+    Penalize { peer_id: PeerId, points: u32 },
+    /// Tells gossipsub whether a message it delivered was valid. Accepted
+    /// messages are relayed on; rejected ones are dropped and count against
+    /// the peer's gossipsub score; ignored ones are just dropped.
+    ReportValidation {
+        message_id: gossipsub::MessageId,
+        source: PeerId,
+        acceptance: gossipsub::MessageAcceptance,
+    },
+}
+
+/// A currently connected peer, as seen by the swarm.
+#[derive(Debug, Clone)]
+pub struct ConnectedPeer {
+    pub address: Multiaddr,
+    pub connected_at: Instant,
+}
+
+pub type ConnectedPeers = Arc<Mutex<HashMap<PeerId, ConnectedPeer>>>;
+
+/// The addresses the swarm is listening on. With port 0 the port is only
+/// known once the listener reports it.
+pub type ListenAddrs = Arc<Mutex<Vec<Multiaddr>>>;
+
+/// The TCP port of `address`, if it has one.
+pub fn tcp_port(address: &Multiaddr) -> Option<u16> {
+    address.iter().find_map(|protocol| match protocol {
+        Protocol::Tcp(port) => Some(port),
+        _ => None,
+    })
+}
+
+/// The default limit on the size of a gossip message, in bytes.
+pub const DEFAULT_MAX_MESSAGE_SIZE: usize = 2 * 1024 * 1024;
+/// How often gossipsub maintains its mesh by default.
+pub const DEFAULT_HEARTBEAT_INTERVAL: Duration = Duration::from_secs(10);
+
+/// How the P2P layer listens, dials and gossips.
+#[derive(Debug, Clone)]
+pub struct P2pConfig {
+    /// The TCP port to listen on, or 0 for any free one.
+    pub port: u16,
+    /// Peers to dial on startup, before the remembered ones.
+    pub initial_peers: Vec<Multiaddr>,
+    pub max_message_size: usize,
+    /// Discovers peers on the local network with mDNS.
+    pub mdns: bool,
+    pub heartbeat_interval: Duration,
+    /// Announces blocks by their headers rather than in full.
+    pub header_first_relay: bool,
+}
+
+impl Default for P2pConfig {
+    fn default() -> Self {
+        Self {
+            port: 0,
+            initial_peers: Vec::new(),
+            max_message_size: DEFAULT_MAX_MESSAGE_SIZE,
+            mdns: true,
+            heartbeat_interval: DEFAULT_HEARTBEAT_INTERVAL,
+            header_first_relay: false,
+        }
+    }
+}
+
+/// Counters describing the gossip traffic the node has handled.
+#[derive(Debug, Default)]
+pub struct P2pMetrics {
+    /// Messages dropped for exceeding the size limit.
+    pub oversized_messages_rejected: AtomicU64,
+    /// Messages handed to the JSON decoder.
+    pub messages_decoded: AtomicU64,
+}
+
+impl P2pMetrics {
+    pub fn to_json(&self) -> serde_json::Value {
+        serde_json::json!({
+            "oversized_messages_rejected": self.oversized_messages_rejected.load(Ordering::Relaxed),
+            "messages_decoded": self.messages_decoded.load(Ordering::Relaxed),
+        })
+    }
+}
+
+/// Decodes a gossipsub payload and forwards it, together with its
+/// propagation source and message ID, to the main message loop, which
+/// reports back whether it is valid. Returns false if the message was
+/// dropped here instead, in which case it should be reported as rejected.
+///
+/// Payloads larger than `max_message_size` are dropped before any attempt
+/// is made to decode them, so a peer can't make the node parse arbitrarily
+/// large garbage.
+pub fn forward_gossip_message(
+    sender: &mpsc::UnboundedSender<InboundMessage>,
+    source: PeerId,
+    message_id: Option<gossipsub::MessageId>,
+    data: &[u8],
+    max_message_size: usize,
+    metrics: &P2pMetrics,
+) -> bool {
+    if data.len() > max_message_size {
+        metrics.oversized_messages_rejected.fetch_add(1, Ordering::Relaxed);
+        warn!("Dropping {} byte message from {}: limit is {} bytes", data.len(), source, max_message_size);
+        return false;
+    }
+    metrics.messages_decoded.fetch_add(1, Ordering::Relaxed);
+    match serde_json::from_slice::<P2pMessage>(data) {
+        Ok(message) => {
+            tracing::debug!("Received message from peer {:?}: {:#?}", source, message);
+            sender.send(InboundMessage { source: Some(source), message_id, message }).unwrap();
+            true
+        }
+        Err(e) => {
+            warn!("Dropping malformed message from {}: {}", source, e);
+            false
+        }
+    }
 }
 
 #[derive(NetworkBehaviour)]
 #[behaviour(to_swarm = "P2pEvent")]
 pub struct P2pBehaviour {
     pub gossipsub: gossipsub::Behaviour,
-    pub mdns: mdns::tokio::Behaviour,
-    pub kademlia: Kademlia<MemoryStore>,
+    pub mdns: Toggle<mdns::tokio::Behaviour>,
+    pub kademlia: kad::Behaviour<MemoryStore>,
     pub identify: identify::Behaviour,
 }
 
 pub enum P2pEvent {
     Gossipsub(gossipsub::Event),
     Mdns(mdns::Event),
-    Kademlia(KadEvent),
+    Kademlia(#[allow(dead_code)] KadEvent),
     Identify(identify::Event),
 }
 
@@ -81,17 +256,26 @@ pub struct P2p {
     pub swarm: Swarm<P2pBehaviour>,
     pub topic: gossipsub::IdentTopic,
     pub message_receiver: mpsc::UnboundedReceiver<P2pMessage>,
-    pub message_sender: mpsc::UnboundedSender<P2pMessage>,
+    pub message_sender: mpsc::UnboundedSender<InboundMessage>,
+    pub control_receiver: mpsc::Receiver<P2pControl>,
     pub peers: HashSet<PeerId>,
+    pub connected_peers: ConnectedPeers,
+    pub listen_addrs: ListenAddrs,
+    pub max_message_size: usize,
+    pub metrics: Arc<P2pMetrics>,
+    pub peer_store: SharedPeerStore,
+    pub header_first_relay: bool,
 }
 
 impl P2p {
     pub async fn new(
-        message_sender: mpsc::UnboundedSender<P2pMessage>,
+        message_sender: mpsc::UnboundedSender<InboundMessage>,
         message_receiver: mpsc::UnboundedReceiver<P2pMessage>,
-        p2p_port: u16,
-        initial_peers: Vec<Multiaddr>,
+        control_receiver: mpsc::Receiver<P2pControl>,
+        config: P2pConfig,
+        peer_store: SharedPeerStore,
     ) -> Self {
+        let P2pConfig { port, initial_peers, max_message_size, mdns, heartbeat_interval, header_first_relay } = config;
         let id_keys = identity::Keypair::generate_ed25519();
         let peer_id = PeerId::from(id_keys.public());
         info!("Peer ID: {}", peer_id);
@@ -102,14 +286,18 @@ impl P2p {
             let gossipsub = gossipsub::Behaviour::new(
                 gossipsub::MessageAuthenticity::Signed(id_keys.clone()),
                 gossipsub::ConfigBuilder::default()
-                    .heartbeat_interval(std::time::Duration::from_secs(10))
-                    .max_transmit_size(2 * 1024 * 1024) // 2MB
+                    .heartbeat_initial_delay(heartbeat_interval / 2)
+                    .heartbeat_interval(heartbeat_interval)
+                    .max_transmit_size(max_message_size)
+                    // Messages are only relayed once the main loop has validated them.
+                    .validation_mode(gossipsub::ValidationMode::Strict)
+                    .validate_messages()
                     .build()
                     .unwrap(),
             )
             .unwrap();
-            let mdns = mdns::tokio::Behaviour::new(mdns::Config::default(), peer_id).unwrap();
-            let kademlia = Kademlia::new(peer_id, MemoryStore::new(peer_id));
+            let mdns = Toggle::from(mdns.then(|| mdns::tokio::Behaviour::new(mdns::Config::default(), peer_id).unwrap()));
+            let kademlia = kad::Behaviour::new(peer_id, MemoryStore::new(peer_id));
             let identify = identify::Behaviour::new(identify::Config::new(
                 "/sierpchain/1.0.0".to_string(),
                 id_keys.public(),
@@ -117,7 +305,7 @@ impl P2p {
             P2pBehaviour { gossipsub, mdns, kademlia, identify }
         };
 
-        let mut swarm = SwarmBuilder::with_tokio_executor(
+        let mut swarm = Swarm::new(
             libp2p::tcp::tokio::Transport::new(tcp::Config::default().nodelay(true))
                 .upgrade(libp2p::core::upgrade::Version::V1)
                 .authenticate(noise::Config::new(&id_keys).unwrap())
@@ -125,17 +313,28 @@ impl P2p {
                 .boxed(),
             behaviour,
             peer_id,
-        )
-        .build();
+            swarm::Config::with_tokio_executor(),
+        );
 
         swarm.behaviour_mut().gossipsub.subscribe(&topic).unwrap();
 
-        let listen_addr = format!("/ip4/0.0.0.0/tcp/{}", p2p_port);
+        let listen_addr = format!("/ip4/0.0.0.0/tcp/{}", port);
         let addr: Multiaddr = listen_addr.parse().expect("Failed to parse listen address");
         swarm.listen_on(addr.clone()).unwrap();
-        info!("Listening on {}", addr);
+        info!("Opening a listener on {}", addr);
 
-        for peer in initial_peers {
+        // Dial the peers we were given, then the ones we remember.
+        let now = chrono::Utc::now().timestamp();
+        let known_peers = peer_store.lock().unwrap().dial_candidates(now);
+        let mut dialed = HashSet::new();
+        for peer in initial_peers.into_iter().chain(known_peers) {
+            if !dialed.insert(peer.clone()) {
+                continue;
+            }
+            if !peer_store.lock().unwrap().may_dial(&peer, now) {
+                warn!("Not dialing banned peer at {}", peer);
+                continue;
+            }
             info!("Dialing peer at {}", peer);
             if let Err(e) = swarm.dial(peer) {
                 warn!("Failed to dial peer: {}", e);
@@ -147,7 +346,20 @@ impl P2p {
             topic,
             message_receiver,
             message_sender,
+            control_receiver,
             peers: HashSet::new(),
+            connected_peers: Arc::new(Mutex::new(HashMap::new())),
+            listen_addrs: ListenAddrs::default(),
+            max_message_size,
+            metrics: Arc::new(P2pMetrics::default()),
+            peer_store,
+            header_first_relay,
+        }
+    }
+
+    fn save_peer_store(&self) {
+        if let Err(e) = self.peer_store.lock().unwrap().save() {
+            error!("Failed to save peer store: {}", e);
         }
     }
 
@@ -155,6 +367,7 @@ impl P2p {
         loop {
             tokio::select! {
                 Some(message) = self.message_receiver.recv() => {
+                    let message = header_sync::outbound(message, self.header_first_relay);
                     if let Ok(json) = serde_json::to_vec(&message) {
                         if self.swarm.behaviour().gossipsub.all_peers().next().is_none() {
                             error!("Failed to publish message: InsufficientPeers");
@@ -163,10 +376,50 @@ impl P2p {
                         }
                     }
                 }
+                Some(control) = self.control_receiver.recv() => {
+                    match control {
+                        P2pControl::Dial(addr) => {
+                            if !self.peer_store.lock().unwrap().may_dial(&addr, chrono::Utc::now().timestamp()) {
+                                warn!("Refusing to dial banned peer at {}", addr);
+                                continue;
+                            }
+                            info!("Dialing peer at {}", addr);
+                            if let Err(e) = self.swarm.dial(addr) {
+                                warn!("Failed to dial peer: {}", e);
+                            }
+                        }
+                        P2pControl::Disconnect(peer_id) => {
+                            info!("Disconnecting from {peer_id}");
+                            if self.swarm.disconnect_peer_id(peer_id).is_err() {
+                                warn!("Peer {peer_id} was not connected");
+                            }
+                            self.swarm.behaviour_mut().gossipsub.remove_explicit_peer(&peer_id);
+                            self.peers.remove(&peer_id);
+                        }
+                        P2pControl::Penalize { peer_id, points } => {
+                            let banned = self.peer_store.lock().unwrap().penalize(&peer_id, points);
+                            if banned {
+                                warn!("Banning {peer_id} for misbehaving");
+                                let _ = self.swarm.disconnect_peer_id(peer_id);
+                            }
+                            self.save_peer_store();
+                        }
+                        P2pControl::ReportValidation { message_id, source, acceptance } => {
+                            if let Err(e) = self.swarm.behaviour_mut().gossipsub.report_message_validation_result(&message_id, &source, acceptance) {
+                                warn!("Failed to report validation of message {} from {}: {:?}", message_id, source, e);
+                            }
+                        }
+                    }
+                }
                 event = self.swarm.select_next_some() => {
                     match event {
                         libp2p::swarm::SwarmEvent::NewListenAddr { address, .. } => {
-                            info!("Listening on {:?}", address);
+                            info!("Listening on {}", address);
+                            self.listen_addrs.lock().unwrap().push(address);
+                        }
+                        libp2p::swarm::SwarmEvent::ExpiredListenAddr { address, .. } => {
+                            info!("No longer listening on {}", address);
+                            self.listen_addrs.lock().unwrap().retain(|listening| *listening != address);
                         }
                         libp2p::swarm::SwarmEvent::Behaviour(P2pEvent::Mdns(mdns::Event::Discovered(list))) => {
                             for (peer_id, multiaddr) in list {
@@ -176,12 +429,12 @@ impl P2p {
                                 self.peers.insert(peer_id);
                             }
                             if !self.peers.is_empty() {
-                                self.message_sender.send(P2pMessage::ChainRequest).unwrap();
+                                self.message_sender.send(InboundMessage::local(P2pMessage::ChainRequest)).unwrap();
                             }
                         }
                         libp2p::swarm::SwarmEvent::Behaviour(P2pEvent::Mdns(mdns::Event::Expired(list))) => {
                             for (peer_id, _multiaddr) in list {
-                                if !self.swarm.behaviour().mdns.has_node(&peer_id) {
+                                if !self.swarm.behaviour().mdns.as_ref().is_some_and(|mdns| mdns.has_node(&peer_id)) {
                                     self.swarm.behaviour_mut().gossipsub.remove_explicit_peer(&peer_id);
                                     self.peers.remove(&peer_id);
                                 }
@@ -189,22 +442,62 @@ impl P2p {
                         }
                         libp2p::swarm::SwarmEvent::Behaviour(P2pEvent::Gossipsub(gossipsub::Event::Message {
                             propagation_source: peer_id,
-                            message_id: _id,
+                            message_id,
                             message,
                         })) => {
-                            if let Ok(msg) = serde_json::from_slice::<P2pMessage>(&message.data) {
-                                tracing::debug!("Received message from peer {:?}: {:#?}", peer_id, msg);
-                                self.message_sender.send(msg).unwrap();
+                            let forwarded = forward_gossip_message(
+                                &self.message_sender,
+                                peer_id,
+                                Some(message_id.clone()),
+                                &message.data,
+                                self.max_message_size,
+                                &self.metrics,
+                            );
+                            if !forwarded {
+                                let _ = self.swarm.behaviour_mut().gossipsub.report_message_validation_result(
+                                    &message_id,
+                                    &peer_id,
+                                    gossipsub::MessageAcceptance::Reject,
+                                );
                             }
                         }
-                        libp2p::swarm::SwarmEvent::ConnectionEstablished { peer_id, .. } => {
+                        libp2p::swarm::SwarmEvent::ConnectionEstablished { peer_id, endpoint, .. } => {
+                            if self.peer_store.lock().unwrap().is_banned(&peer_id, chrono::Utc::now().timestamp()) {
+                                warn!("Dropping connection from banned peer {peer_id}");
+                                let _ = self.swarm.disconnect_peer_id(peer_id);
+                                continue;
+                            }
                             info!("Connected to {peer_id}");
+                            {
+                                let mut peer_store = self.peer_store.lock().unwrap();
+                                peer_store.touch(&peer_id);
+                                // Inbound connections come from ephemeral ports we can't dial back.
+                                if endpoint.is_dialer() {
+                                    peer_store.add_address(&peer_id, endpoint.get_remote_address());
+                                }
+                            }
+                            self.save_peer_store();
+                            self.connected_peers.lock().unwrap().entry(peer_id).or_insert_with(|| ConnectedPeer {
+                                address: endpoint.get_remote_address().clone(),
+                                connected_at: Instant::now(),
+                            });
                             self.peers.insert(peer_id);
-                            self.message_sender.send(P2pMessage::ChainRequest).unwrap();
+                            self.message_sender
+                                .send(InboundMessage { source: Some(peer_id), message_id: None, message: P2pMessage::ChainRequest })
+                                .unwrap();
                         }
-                        libp2p::swarm::SwarmEvent::ConnectionClosed { peer_id, cause, .. } => {
+                        libp2p::swarm::SwarmEvent::ConnectionClosed { peer_id, cause, num_established, .. } => {
                             warn!("Disconnected from {peer_id}: {:?}", cause);
+                            if num_established == 0 {
+                                self.connected_peers.lock().unwrap().remove(&peer_id);
+                            }
                             self.peers.remove(&peer_id);
+                            self.peer_store.lock().unwrap().touch(&peer_id);
+                            self.save_peer_store();
+                        }
+                        libp2p::swarm::SwarmEvent::Behaviour(P2pEvent::Identify(identify::Event::Received { peer_id, info })) => {
+                            self.peer_store.lock().unwrap().record_identify(&peer_id, info.agent_version, &info.listen_addrs);
+                            self.save_peer_store();
                         }
                         _ => {}
                     }
@@ -214,3 +507,186 @@ impl P2p {
     }
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fractal::FractalType;
+    use crate::network::peer_store::PeerStore;
+
+    /// A config without mDNS, so that the node only talks to the peers it
+    /// dials or is dialed by, listening on any free port.
+    fn local_config(initial_peers: Vec<Multiaddr>) -> P2pConfig {
+        P2pConfig {
+            port: 0,
+            initial_peers,
+            mdns: false,
+            heartbeat_interval: Duration::from_millis(200),
+            ..P2pConfig::default()
+        }
+    }
+
+    /// Waits for the node to report a loopback address to dial it on.
+    async fn loopback_address(listen_addrs: &ListenAddrs) -> Multiaddr {
+        tokio::time::timeout(Duration::from_secs(5), async {
+            loop {
+                let loopback = listen_addrs.lock().unwrap().iter().find(|address| {
+                    address.iter().any(|protocol| protocol == Protocol::Ip4(std::net::Ipv4Addr::LOCALHOST))
+                }).cloned();
+                if let Some(address) = loopback {
+                    return address;
+                }
+                tokio::time::sleep(Duration::from_millis(20)).await;
+            }
+        })
+        .await
+        .expect("the node never started listening")
+    }
+
+    /// Starts a node, returning its channels and the address it listens on.
+    async fn spawn_node(
+        config: P2pConfig,
+    ) -> (mpsc::UnboundedSender<P2pMessage>, mpsc::UnboundedReceiver<InboundMessage>, mpsc::Sender<P2pControl>, Multiaddr) {
+        let (inbound_sender, inbound_receiver) = mpsc::unbounded_channel();
+        let (outbound_sender, outbound_receiver) = mpsc::unbounded_channel();
+        let (control_sender, control_receiver) = mpsc::channel(32);
+        let peers_file = std::env::temp_dir().join(format!("sierpchain-peers-{}.json", rand::random::<u64>()));
+        let peer_store = Arc::new(Mutex::new(PeerStore::load(peers_file)));
+        let p2p = P2p::new(inbound_sender, outbound_receiver, control_receiver, config, peer_store).await;
+        let listen_addrs = Arc::clone(&p2p.listen_addrs);
+        tokio::spawn(p2p.run());
+        let address = loopback_address(&listen_addrs).await;
+        assert_ne!(tcp_port(&address), Some(0));
+        (outbound_sender, inbound_receiver, control_sender, address)
+    }
+
+    #[tokio::test]
+    async fn test_rejected_block_is_not_relayed() {
+        // publisher -> relay -> receiver, with no link between the ends.
+        let (_, mut relay_inbound, relay_control, relay_address) = spawn_node(local_config(vec![])).await;
+        let (publisher, _publisher_inbound, _, _) = spawn_node(local_config(vec![relay_address.clone()])).await;
+        let (_, mut receiver_inbound, _, _) = spawn_node(local_config(vec![relay_address])).await;
+
+        let mut origin = Blockchain::new(1);
+        let mut relay_chain = origin.clone();
+        let valid = origin.add_block(FractalType::Sierpinski { depth: 1, seed: 0 }, vec![]).unwrap();
+        let mut invalid = valid.clone();
+        invalid.nonce += 1;
+
+        // The relay validates blocks against its chain, as the main loop does.
+        tokio::spawn(async move {
+            while let Some(InboundMessage { source, message_id, message }) = relay_inbound.recv().await {
+                let (Some(source), Some(message_id)) = (source, message_id) else { continue };
+                let acceptance = match message {
+                    P2pMessage::Block(block) if relay_chain.add_block_from_network(block.clone()).is_ok() => gossipsub::MessageAcceptance::Accept,
+                    _ => gossipsub::MessageAcceptance::Reject,
+                };
+                relay_control.send(P2pControl::ReportValidation { message_id, source, acceptance }).await.unwrap();
+            }
+        });
+
+        // Give the mesh a few heartbeats to form.
+        tokio::time::sleep(Duration::from_secs(2)).await;
+        publisher.send(P2pMessage::Block(invalid)).unwrap();
+        publisher.send(P2pMessage::Block(valid.clone())).unwrap();
+
+        // The valid block, sent second, is the first to get through.
+        let relayed = tokio::time::timeout(Duration::from_secs(10), async {
+            while let Some(inbound) = receiver_inbound.recv().await {
+                if let P2pMessage::Block(block) = inbound.message {
+                    return block;
+                }
+            }
+            panic!("the receiver stopped");
+        })
+        .await
+        .expect("the valid block was never relayed");
+        assert_eq!(relayed.nonce, valid.nonce);
+    }
+
+    #[tokio::test]
+    async fn test_header_first_peer_fetches_the_announced_block() {
+        let miner_config = P2pConfig { header_first_relay: true, ..local_config(vec![]) };
+        let (miner, mut miner_inbound, _, miner_address) = spawn_node(miner_config).await;
+        let (follower, mut follower_inbound, _, _) = spawn_node(local_config(vec![miner_address])).await;
+
+        let mut miner_chain = Blockchain::new(1);
+        let mut follower_chain = miner_chain.clone();
+        let block = miner_chain.add_block(FractalType::Sierpinski { depth: 1, seed: 0 }, vec![]).unwrap();
+
+        // The miner answers requests for blocks from its chain, as the main loop does.
+        let miner_answers = miner.clone();
+        tokio::spawn(async move {
+            while let Some(inbound) = miner_inbound.recv().await {
+                if let (Some(_), P2pMessage::GetBlocks { from_index }) = (inbound.source, inbound.message) {
+                    miner_answers.send(P2pMessage::Blocks(miner_chain.blocks_from(from_index))).unwrap();
+                }
+            }
+        });
+
+        tokio::time::sleep(Duration::from_secs(2)).await;
+        miner.send(P2pMessage::Block(block.clone())).unwrap();
+
+        let connected = tokio::time::timeout(Duration::from_secs(10), async {
+            while let Some(inbound) = follower_inbound.recv().await {
+                match inbound.message {
+                    P2pMessage::Block(_) => panic!("the full block was gossiped"),
+                    P2pMessage::BlockHeader(header) => {
+                        assert_eq!(header.hash, block.hash);
+                        follower.send(header_sync::request_for(&header, &follower_chain).unwrap()).unwrap();
+                    }
+                    P2pMessage::Blocks(blocks) => return header_sync::connect(&mut follower_chain, blocks),
+                    _ => {}
+                }
+            }
+            panic!("the follower stopped");
+        })
+        .await
+        .expect("the follower never received the block");
+        assert_eq!(connected, vec![block]);
+    }
+
+    #[test]
+    fn test_gossip_message_carries_source_peer() {
+        let (sender, mut receiver) = mpsc::unbounded_channel::<InboundMessage>();
+        let source = PeerId::random();
+        let data = serde_json::to_vec(&P2pMessage::ChainRequest).unwrap();
+
+        let message_id = gossipsub::MessageId::new(b"id");
+        assert!(forward_gossip_message(&sender, source, Some(message_id.clone()), &data, DEFAULT_MAX_MESSAGE_SIZE, &P2pMetrics::default()));
+
+        let inbound = receiver.try_recv().unwrap();
+        assert_eq!(inbound.source, Some(source));
+        assert_eq!(inbound.message_id, Some(message_id));
+        assert!(matches!(inbound.message, P2pMessage::ChainRequest));
+    }
+
+    #[test]
+    fn test_malformed_gossip_message_is_dropped() {
+        let (sender, mut receiver) = mpsc::unbounded_channel::<InboundMessage>();
+
+        assert!(!forward_gossip_message(&sender, PeerId::random(), None, b"not json", DEFAULT_MAX_MESSAGE_SIZE, &P2pMetrics::default()));
+
+        assert!(receiver.try_recv().is_err());
+    }
+
+    #[test]
+    fn test_oversized_gossip_message_is_dropped_before_decoding() {
+        let (sender, mut receiver) = mpsc::unbounded_channel::<InboundMessage>();
+        let metrics = P2pMetrics::default();
+        let max_message_size = 1024;
+
+        let oversized = vec![b'x'; max_message_size + 1];
+        forward_gossip_message(&sender, PeerId::random(), None, &oversized, max_message_size, &metrics);
+
+        assert!(receiver.try_recv().is_err());
+        assert_eq!(metrics.oversized_messages_rejected.load(Ordering::Relaxed), 1);
+        assert_eq!(metrics.messages_decoded.load(Ordering::Relaxed), 0);
+
+        let data = serde_json::to_vec(&P2pMessage::ChainRequest).unwrap();
+        forward_gossip_message(&sender, PeerId::random(), None, &data, max_message_size, &metrics);
+
+        assert!(receiver.try_recv().is_ok());
+        assert_eq!(metrics.oversized_messages_rejected.load(Ordering::Relaxed), 1);
+        assert_eq!(metrics.messages_decoded.load(Ordering::Relaxed), 1);
+    }
+}
@@ -0,0 +1,216 @@
+use chrono::Utc;
+use libp2p::{Multiaddr, PeerId};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// Where known peers are saved, next to `blockchain.json`.
+pub const PEERS_FILE: &str = "peers.json";
+/// How long a peer stays banned once its misbehavior score reaches the threshold.
+pub const DEFAULT_BAN_DURATION: Duration = Duration::from_secs(24 * 60 * 60);
+/// The misbehavior score at which a peer is banned.
+pub const MISBEHAVIOR_BAN_THRESHOLD: u32 = 100;
+/// The misbehavior points for relaying a block that fails validation.
+pub const INVALID_BLOCK_PENALTY: u32 = 10;
+/// The most addresses remembered for a single peer.
+const MAX_ADDRESSES_PER_PEER: usize = 8;
+
+/// What the node remembers about a peer between restarts.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct PeerRecord {
+    /// Addresses the peer can be dialed at, most recently learned last.
+    pub addresses: Vec<String>,
+    /// When the peer was last connected, in Unix seconds.
+    pub last_seen: Option<i64>,
+    /// The agent version the peer reported through identify.
+    pub user_agent: Option<String>,
+    /// When the peer's ban ends, in Unix seconds.
+    pub banned_until: Option<i64>,
+    /// Penalty points accumulated for bad behaviour, such as sending invalid blocks.
+    pub misbehavior_score: u32,
+}
+
+impl PeerRecord {
+    /// Returns true if the peer is banned at Unix time `now`.
+    pub fn is_banned(&self, now: i64) -> bool {
+        self.banned_until.is_some_and(|until| until > now)
+    }
+}
+
+/// The peers this node has seen, persisted to a JSON file so that learned
+/// addresses and bans survive restarts.
+#[derive(Debug)]
+pub struct PeerStore {
+    path: PathBuf,
+    peers: BTreeMap<String, PeerRecord>,
+}
+
+pub type SharedPeerStore = Arc<Mutex<PeerStore>>;
+
+impl PeerStore {
+    /// Loads the store saved at `path`. A missing or unreadable file gives
+    /// an empty store.
+    pub fn load(path: impl Into<PathBuf>) -> Self {
+        let path = path.into();
+        let peers = match fs::read_to_string(&path) {
+            Ok(contents) => serde_json::from_str(&contents).unwrap_or_else(|e| {
+                tracing::warn!("Ignoring unreadable peer store {}: {}", path.display(), e);
+                BTreeMap::new()
+            }),
+            Err(_) => BTreeMap::new(),
+        };
+        Self { path, peers }
+    }
+
+    /// Writes the store back to the file it was loaded from.
+    pub fn save(&self) -> std::io::Result<()> {
+        let serialized = serde_json::to_string_pretty(&self.peers).unwrap();
+        let mut file = fs::File::create(&self.path)?;
+        file.write_all(serialized.as_bytes())
+    }
+
+    /// The file the store is saved to.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn records(&self) -> &BTreeMap<String, PeerRecord> {
+        &self.peers
+    }
+
+    fn record_mut(&mut self, peer_id: &PeerId) -> &mut PeerRecord {
+        self.peers.entry(peer_id.to_string()).or_default()
+    }
+
+    /// Remembers that `peer_id` can be reached at `address`.
+    pub fn add_address(&mut self, peer_id: &PeerId, address: &Multiaddr) {
+        let addresses = &mut self.record_mut(peer_id).addresses;
+        let address = address.to_string();
+        addresses.retain(|known| *known != address);
+        addresses.push(address);
+        if addresses.len() > MAX_ADDRESSES_PER_PEER {
+            addresses.remove(0);
+        }
+    }
+
+    /// Records that `peer_id` is connected, or has just disconnected.
+    pub fn touch(&mut self, peer_id: &PeerId) {
+        self.record_mut(peer_id).last_seen = Some(Utc::now().timestamp());
+    }
+
+    /// Records the agent version and listen addresses `peer_id` reported.
+    pub fn record_identify(&mut self, peer_id: &PeerId, user_agent: String, listen_addrs: &[Multiaddr]) {
+        self.record_mut(peer_id).user_agent = Some(user_agent);
+        for address in listen_addrs {
+            self.add_address(peer_id, address);
+        }
+    }
+
+    /// Bans `peer_id` for `duration` from now.
+    pub fn ban(&mut self, peer_id: &PeerId, duration: Duration) {
+        let until = Utc::now().timestamp().saturating_add(duration.as_secs().try_into().unwrap_or(i64::MAX));
+        self.record_mut(peer_id).banned_until = Some(until);
+    }
+
+    /// Returns true if `peer_id` is banned at Unix time `now`.
+    pub fn is_banned(&self, peer_id: &PeerId, now: i64) -> bool {
+        self.peers.get(&peer_id.to_string()).is_some_and(|record| record.is_banned(now))
+    }
+
+    /// Adds `points` to the misbehavior score of `peer_id`, banning it for
+    /// `DEFAULT_BAN_DURATION` once the score reaches
+    /// `MISBEHAVIOR_BAN_THRESHOLD`. Returns true if the peer was just banned.
+    pub fn penalize(&mut self, peer_id: &PeerId, points: u32) -> bool {
+        let record = self.record_mut(peer_id);
+        record.misbehavior_score = record.misbehavior_score.saturating_add(points);
+        if record.misbehavior_score < MISBEHAVIOR_BAN_THRESHOLD {
+            return false;
+        }
+        // The score starts over once the ban is served.
+        record.misbehavior_score = 0;
+        self.ban(peer_id, DEFAULT_BAN_DURATION);
+        true
+    }
+
+    /// Returns true if `address` may be dialed at Unix time `now`: it must
+    /// not name, or belong to, a banned peer.
+    pub fn may_dial(&self, address: &Multiaddr, now: i64) -> bool {
+        let named_peer = address.iter().find_map(|protocol| match protocol {
+            libp2p::multiaddr::Protocol::P2p(peer_id) => Some(peer_id),
+            _ => None,
+        });
+        if named_peer.is_some_and(|peer_id| self.is_banned(&peer_id, now)) {
+            return false;
+        }
+        let address = address.to_string();
+        !self.peers.values().any(|record| record.is_banned(now) && record.addresses.contains(&address))
+    }
+
+    /// Returns the known addresses of peers that aren't banned, to dial at startup.
+    pub fn dial_candidates(&self, now: i64) -> Vec<Multiaddr> {
+        self.peers
+            .values()
+            .filter(|record| !record.is_banned(now))
+            .flat_map(|record| &record.addresses)
+            .filter_map(|address| address.parse().ok())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_path(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("sierpchain-{}-{}.json", name, std::process::id()));
+        fs::remove_file(&path).ok();
+        path
+    }
+
+    #[test]
+    fn test_ban_survives_restart_until_expiry() {
+        let path = temp_path("peer-store-ban");
+        let banned = PeerId::random();
+        let friendly = PeerId::random();
+        let banned_address: Multiaddr = "/ip4/10.0.0.1/tcp/4001".parse().unwrap();
+        let friendly_address: Multiaddr = "/ip4/10.0.0.2/tcp/4001".parse().unwrap();
+
+        let mut store = PeerStore::load(&path);
+        store.add_address(&banned, &banned_address);
+        store.add_address(&friendly, &friendly_address);
+        store.record_identify(&friendly, "sierpchain/1.0.0".to_string(), &[]);
+        for _ in 0..9 {
+            assert!(!store.penalize(&banned, 10));
+        }
+        assert!(store.penalize(&banned, 10));
+        store.save().unwrap();
+
+        // A restarted node loads the same file.
+        let store = PeerStore::load(&path);
+        let now = Utc::now().timestamp();
+        assert!(store.is_banned(&banned, now));
+        assert!(!store.may_dial(&banned_address, now));
+        assert!(!store.may_dial(&format!("/ip4/10.0.0.9/tcp/1/p2p/{}", banned).parse().unwrap(), now));
+        assert!(store.may_dial(&friendly_address, now));
+        assert_eq!(store.dial_candidates(now), vec![friendly_address]);
+        assert_eq!(store.records()[&friendly.to_string()].user_agent.as_deref(), Some("sierpchain/1.0.0"));
+
+        // Once the ban expires the peer may be dialed again.
+        let after_ban = now + DEFAULT_BAN_DURATION.as_secs() as i64 + 1;
+        assert!(!store.is_banned(&banned, after_ban));
+        assert!(store.may_dial(&banned_address, after_ban));
+        fs::remove_file(&path).ok();
+    }
+
+    #[test]
+    fn test_unreadable_store_loads_empty() {
+        let path = temp_path("peer-store-corrupt");
+        fs::write(&path, "not json").unwrap();
+        assert!(PeerStore::load(&path).records().is_empty());
+        fs::remove_file(&path).ok();
+    }
+}
@@ -0,0 +1,170 @@
+use libp2p::PeerId;
+
+use crate::api::health::{HealthRegistry, SAFE_MODE_OFF};
+use crate::blockchain::block::Block;
+use crate::blockchain::chain::{Blockchain, ChainError};
+
+/// What became of a chain a peer offered in place of ours.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ChainOffer {
+    /// Fork choice preferred it, and now it is the active chain.
+    Replaced,
+    /// It was shorter than ours, or as long with a tip hash that doesn't
+    /// sort first.
+    NotPreferred,
+    /// Taking it would have disconnected `depth` blocks, more than the node
+    /// allows, so it was refused and the node is in safe mode.
+    TooDeep { depth: u64 },
+    /// The block at `index`, past the blocks it shares with ours, failed
+    /// validation, so the chain was refused and ours left as it was.
+    Invalid { index: u64, error: ChainError },
+}
+
+/// Replaces the active chain with `chain` from `source` if fork choice
+/// prefers it (see `Blockchain::prefers_tip`) and it forks no more than
+/// `max_reorg_depth` blocks below the tip. A deeper fork
+/// is more likely an attack than honest work, so it is refused and the node
+/// enters safe mode: `/health/ready` fails and auto-mining stops until an
+/// operator clears it. The blocks past the fork are validated in full on a
+/// copy of the chain first, so an invalid chain never touches ours or its
+/// UTXO index.
+pub fn offer_chain(
+    blockchain: &mut Blockchain,
+    chain: Vec<Block>,
+    max_reorg_depth: u64,
+    health: &HealthRegistry,
+    source: Option<PeerId>,
+) -> ChainOffer {
+    if !chain.last().is_some_and(|tip| blockchain.prefers_tip(Blockchain::chain_work(&chain), &tip.hash)) {
+        return ChainOffer::NotPreferred;
+    }
+    let depth = blockchain.reorg_depth(&chain);
+    if depth > max_reorg_depth {
+        tracing::error!(
+            target: "journal",
+            ?source,
+            depth,
+            max_reorg_depth,
+            "Refused a chain that would reorg too deep; entering safe mode"
+        );
+        let reason = format!("Refused a reorg of {} blocks from {:?}", depth, source);
+        health.report(SAFE_MODE_OFF, Err(reason));
+        return ChainOffer::TooDeep { depth };
+    }
+    match blockchain.connect_candidate(chain) {
+        Ok(candidate) => {
+            *blockchain = candidate;
+            ChainOffer::Replaced
+        }
+        Err((index, error)) => {
+            tracing::warn!(?source, index, %error, "Refused a chain with an invalid block");
+            ChainOffer::Invalid { index, error }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::health::INDEX_READY;
+    use crate::fractal::FractalType;
+
+    #[test]
+    fn test_deep_reorg_is_refused_in_safe_mode() {
+        let health = HealthRegistry::new();
+        health.report(SAFE_MODE_OFF, Ok(()));
+        let mut ours = Blockchain::new(1);
+        let mut theirs = ours.clone();
+        for _ in 0..3 {
+            ours.add_block(FractalType::Sierpinski { depth: 1, seed: 1 }, vec![]).unwrap();
+        }
+        for _ in 0..4 {
+            theirs.add_block(FractalType::Sierpinski { depth: 1, seed: 2 }, vec![]).unwrap();
+        }
+        assert_eq!(ours.reorg_depth(&theirs.chain), 3);
+        let same_length = ours.chain.clone();
+        assert_eq!(offer_chain(&mut ours, same_length, 2, &health, None), ChainOffer::NotPreferred);
+
+        let tip = ours.chain.last().unwrap().hash.clone();
+        assert_eq!(offer_chain(&mut ours, theirs.chain.clone(), 2, &health, None), ChainOffer::TooDeep { depth: 3 });
+        assert_eq!(ours.chain.last().unwrap().hash, tip);
+        assert!(health.is_failing(SAFE_MODE_OFF));
+
+        // Within the limit the same chain is taken.
+        health.report(SAFE_MODE_OFF, Ok(()));
+        assert_eq!(offer_chain(&mut ours, theirs.chain.clone(), 3, &health, None), ChainOffer::Replaced);
+        assert_eq!(ours.chain, theirs.chain);
+        assert!(health.failing().iter().all(|(check, _)| *check != SAFE_MODE_OFF));
+    }
+
+    #[test]
+    fn test_equal_length_tips_converge_on_the_lower_hash() {
+        let health = HealthRegistry::new();
+        let genesis = Blockchain::new(1);
+        let mut nodes: Vec<Blockchain> = (0..3).map(|_| genesis.clone()).collect();
+        for (seed, node) in nodes.iter_mut().enumerate() {
+            node.add_block(FractalType::Sierpinski { depth: 1, seed: seed as u64 }, vec![]).unwrap();
+        }
+        let lowest = nodes.iter().map(|node| node.chain.clone()).min_by(|a, b| a[1].hash.cmp(&b[1].hash)).unwrap();
+
+        // Every node hears every other's chain, each in its own order.
+        let chains: Vec<Vec<Block>> = nodes.iter().map(|node| node.chain.clone()).collect();
+        for (i, node) in nodes.iter_mut().enumerate() {
+            for offset in 1..chains.len() {
+                let chain = chains[(i + offset) % chains.len()].clone();
+                let lower = chain[1].hash < node.chain[1].hash;
+                assert_eq!(offer_chain(node, chain, 1, &health, None) == ChainOffer::Replaced, lower);
+            }
+        }
+        assert!(nodes.iter().all(|node| node.chain == lowest));
+        assert_eq!(offer_chain(&mut nodes[0], lowest, 1, &health, None), ChainOffer::NotPreferred);
+    }
+
+    #[test]
+    fn test_fabricated_tip_with_a_lower_hash_is_refused() {
+        let health = HealthRegistry::new();
+        let mut ours = Blockchain::new(1);
+        let mut theirs = ours.clone();
+        ours.add_block(FractalType::Sierpinski { depth: 1, seed: 1 }, vec![]).unwrap();
+        theirs.add_block(FractalType::Sierpinski { depth: 1, seed: 2 }, vec![]).unwrap();
+
+        // As much work as ours and a tip hash sorting first, but the hash
+        // isn't the block's.
+        let mut chain = theirs.chain.clone();
+        chain[1].hash = "0".repeat(64);
+        let tip = ours.chain.last().unwrap().hash.clone();
+        assert!(matches!(
+            offer_chain(&mut ours, chain, 1, &health, None),
+            ChainOffer::Invalid { index: 1, .. }
+        ));
+        assert_eq!(ours.chain.last().unwrap().hash, tip);
+    }
+
+    #[test]
+    fn test_chain_with_an_invalid_block_is_refused() {
+        let health = HealthRegistry::new();
+        health.report(INDEX_READY, Ok(()));
+        let mut ours = Blockchain::new(1);
+        let mut theirs = ours.clone();
+        for _ in 0..2 {
+            theirs.add_block(FractalType::Sierpinski { depth: 1, seed: 3 }, vec![]).unwrap();
+        }
+        let mut chain = theirs.chain.clone();
+        let forged = &mut chain[2];
+        forged.hash = "f".repeat(64);
+        let forged_hash = forged.hash.clone();
+
+        let tip = ours.chain.last().unwrap().hash.clone();
+        let supply = ours.utxo_index.supply().clone();
+        assert_eq!(
+            offer_chain(&mut ours, chain, 2, &health, None),
+            ChainOffer::Invalid { index: 2, error: ChainError::PowBelowTarget { hash: forged_hash } },
+        );
+        assert_eq!(ours.chain.last().unwrap().hash, tip);
+        assert_eq!(ours.utxo_index.supply(), &supply);
+        assert!(!health.is_failing(INDEX_READY));
+
+        assert_eq!(offer_chain(&mut ours, theirs.chain.clone(), 2, &health, None), ChainOffer::Replaced);
+        assert_eq!(ours.chain, theirs.chain);
+    }
+}
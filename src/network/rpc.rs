@@ -0,0 +1,306 @@
+use actix::Addr;
+use serde::{Deserialize, Serialize};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use tokio::io::{AsyncBufReadExt, AsyncWrite, AsyncWriteExt, BufReader, Lines};
+use tokio::net::{UnixListener, UnixStream};
+use tokio::sync::{broadcast, mpsc};
+use tokio::time::Duration;
+
+use crate::api::handlers::TransactionPool;
+use crate::api::health::{SharedHealth, P2P_RUNNING};
+use crate::api::websocket::{BroadcastBlock, BroadcastHub, SubscribeBlocks};
+use crate::blockchain::block::Block;
+use crate::blockchain::chain::Blockchain;
+use crate::core::transaction::Transaction;
+use crate::network::p2p::P2pMessage;
+
+/// Where `node --headless` listens for API processes, relative to its
+/// working directory.
+pub const RPC_SOCKET: &str = "sierpchain.sock";
+
+/// How long an API process waits before reconnecting to a node it lost.
+const RECONNECT_DELAY: Duration = Duration::from_secs(1);
+
+/// A call from an API process to the node. Each is one JSON line, answered
+/// with one `RpcResponse` line, except `Subscribe`.
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(tag = "method", rename_all = "snake_case")]
+pub enum RpcRequest {
+    /// The node's whole active chain.
+    GetBlocks,
+    /// Adds a transaction to the node's mempool and gossips it.
+    SubmitTransaction { transaction: Transaction },
+    /// Connects a block mined by the API process and gossips it.
+    SubmitBlock { block: Block },
+    /// Keeps the connection open, sending a `Block` line for every block the
+    /// node connects from then on.
+    Subscribe,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum RpcResponse {
+    Blocks(Vec<Block>),
+    Block(Block),
+    Accepted,
+    Error(String),
+}
+
+/// Parses a `--node-url`, which must name a Unix socket as `unix:<path>`.
+pub fn parse_node_url(url: &str) -> Result<PathBuf, String> {
+    match url.strip_prefix("unix:") {
+        Some(path) if !path.is_empty() => Ok(PathBuf::from(path)),
+        _ => Err(format!("Expected unix:<socket path>, got {}", url)),
+    }
+}
+
+async fn write_line(writer: &mut (impl AsyncWrite + Unpin), message: &impl Serialize) -> io::Result<()> {
+    let mut line = serde_json::to_vec(message)?;
+    line.push(b'\n');
+    writer.write_all(&line).await
+}
+
+async fn read_line<T: for<'de> Deserialize<'de>>(
+    lines: &mut Lines<BufReader<impl tokio::io::AsyncRead + Unpin>>,
+) -> io::Result<Option<T>> {
+    match lines.next_line().await? {
+        Some(line) => Ok(Some(serde_json::from_str(&line)?)),
+        None => Ok(None),
+    }
+}
+
+/// The parts of a headless node the RPC server answers from.
+#[derive(Clone)]
+pub struct RpcNode {
+    pub blockchain: Arc<Mutex<Blockchain>>,
+    pub tx_pool: TransactionPool,
+    pub to_p2p: mpsc::UnboundedSender<P2pMessage>,
+    pub hub: Addr<BroadcastHub>,
+}
+
+impl RpcNode {
+    fn handle(&self, request: RpcRequest) -> RpcResponse {
+        match request {
+            RpcRequest::GetBlocks => RpcResponse::Blocks(self.blockchain.lock().unwrap().chain.clone()),
+            RpcRequest::SubmitTransaction { transaction } => {
+                let blockchain = self.blockchain.lock().unwrap();
+                if let Err(e) = self.tx_pool.lock().unwrap().submit(transaction.clone(), &blockchain) {
+                    return RpcResponse::Error(e.to_string());
+                }
+                self.to_p2p.send(P2pMessage::Transaction(transaction)).unwrap();
+                RpcResponse::Accepted
+            }
+            RpcRequest::SubmitBlock { block } => {
+                let mut blockchain = self.blockchain.lock().unwrap();
+                if let Err(e) = blockchain.add_block_from_network(block.clone()) {
+                    return RpcResponse::Error(e.to_string());
+                }
+                self.tx_pool.lock().unwrap().block_connected(&block, &blockchain);
+                if let Err(e) = blockchain.save_to_file() {
+                    tracing::error!("Failed to save blockchain: {}", e);
+                }
+                self.to_p2p.send(P2pMessage::Block(block.clone())).unwrap();
+                self.hub.do_send(BroadcastBlock { block });
+                RpcResponse::Accepted
+            }
+            RpcRequest::Subscribe => unreachable!("subscriptions are served by the connection"),
+        }
+    }
+
+    async fn serve_connection(self, stream: UnixStream) -> io::Result<()> {
+        let (reader, mut writer) = stream.into_split();
+        let mut lines = BufReader::new(reader).lines();
+        while let Some(line) = lines.next_line().await? {
+            let response = match serde_json::from_str(&line) {
+                Ok(RpcRequest::Subscribe) => return self.serve_subscription(writer).await,
+                Ok(request) => self.handle(request),
+                Err(e) => RpcResponse::Error(format!("Malformed request: {}", e)),
+            };
+            write_line(&mut writer, &response).await?;
+        }
+        Ok(())
+    }
+
+    async fn serve_subscription(self, mut writer: impl AsyncWrite + Unpin) -> io::Result<()> {
+        let Ok(Some(mut blocks)) = self.hub.send(SubscribeBlocks).await else {
+            return write_line(&mut writer, &RpcResponse::Error("Too many subscribers".to_string())).await;
+        };
+        loop {
+            match blocks.recv().await {
+                Ok(block) => write_line(&mut writer, &RpcResponse::Block(block)).await?,
+                // The subscriber notices the gap and fetches the chain again.
+                Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                Err(broadcast::error::RecvError::Closed) => return Ok(()),
+            }
+        }
+    }
+}
+
+/// Answers API processes connecting to `listener` until the node stops.
+pub async fn serve(listener: UnixListener, node: RpcNode) -> io::Result<()> {
+    loop {
+        let (stream, _) = listener.accept().await?;
+        let node = node.clone();
+        tokio::spawn(async move {
+            if let Err(e) = node.serve_connection(stream).await {
+                tracing::debug!("RPC connection closed: {}", e);
+            }
+        });
+    }
+}
+
+/// Binds the node's RPC socket, replacing one left behind by a node that
+/// didn't shut down cleanly.
+pub fn bind(path: &Path) -> io::Result<UnixListener> {
+    if path.exists() {
+        std::fs::remove_file(path)?;
+    }
+    UnixListener::bind(path)
+}
+
+/// A connection to a headless node. Each call opens its own connection.
+#[derive(Clone, Debug)]
+pub struct RpcClient {
+    socket: PathBuf,
+}
+
+impl RpcClient {
+    pub fn new(socket: PathBuf) -> Self {
+        Self { socket }
+    }
+
+    async fn call(&self, request: &RpcRequest) -> io::Result<RpcResponse> {
+        let (reader, mut writer) = UnixStream::connect(&self.socket).await?.into_split();
+        write_line(&mut writer, request).await?;
+        match read_line(&mut BufReader::new(reader).lines()).await? {
+            Some(RpcResponse::Error(e)) => Err(io::Error::other(e)),
+            Some(response) => Ok(response),
+            None => Err(io::Error::new(io::ErrorKind::UnexpectedEof, "The node closed the connection")),
+        }
+    }
+
+    async fn get_blocks(&self) -> io::Result<Vec<Block>> {
+        match self.call(&RpcRequest::GetBlocks).await? {
+            RpcResponse::Blocks(blocks) => Ok(blocks),
+            response => Err(io::Error::other(format!("Unexpected response {:?}", response))),
+        }
+    }
+
+    /// Opens a subscription to the blocks the node connects.
+    async fn subscribe(&self) -> io::Result<Lines<BufReader<UnixStream>>> {
+        let mut stream = UnixStream::connect(&self.socket).await?;
+        write_line(&mut stream, &RpcRequest::Subscribe).await?;
+        Ok(BufReader::new(stream).lines())
+    }
+}
+
+/// What an API process started with `--node-url` keeps in step with the
+/// node: its copy of the chain, and the mempool and WebSocket clients that
+/// follow it.
+pub struct Replica {
+    pub blockchain: Arc<Mutex<Blockchain>>,
+    pub tx_pool: TransactionPool,
+    pub hub: Addr<BroadcastHub>,
+    pub health: SharedHealth,
+}
+
+impl Replica {
+    /// Replaces the local chain with the node's if their tips differ.
+    async fn resync(&self, client: &RpcClient) -> io::Result<()> {
+        let blocks = client.get_blocks().await?;
+        let mut blockchain = self.blockchain.lock().unwrap();
+        if blockchain.chain.last().map(|tip| &tip.hash) == blocks.last().map(|tip| &tip.hash) {
+            return Ok(());
+        }
+        let tip = blocks.last().cloned();
+        blockchain.replace_chain(blocks);
+        if let Err(e) = blockchain.save_to_file() {
+            tracing::error!("Failed to save blockchain: {}", e);
+        }
+        if let Some(block) = tip {
+            self.hub.do_send(BroadcastBlock { block });
+        }
+        Ok(())
+    }
+
+    /// Connects a block the node announced. Returns false if it doesn't
+    /// extend the local tip, so the chain has to be fetched again.
+    fn apply(&self, block: Block) -> bool {
+        let mut blockchain = self.blockchain.lock().unwrap();
+        if blockchain.chain.iter().rev().any(|known| known.hash == block.hash) {
+            return true;
+        }
+        if let Err(e) = blockchain.add_block_from_network(block.clone()) {
+            tracing::debug!("Announced block {} doesn't connect: {}", block.index, e);
+            return false;
+        }
+        self.tx_pool.lock().unwrap().block_connected(&block, &blockchain);
+        if let Err(e) = blockchain.save_to_file() {
+            tracing::error!("Failed to save blockchain: {}", e);
+        }
+        self.hub.do_send(BroadcastBlock { block });
+        true
+    }
+
+    async fn follow_once(&self, client: &RpcClient) -> io::Result<()> {
+        // Subscribe before fetching the chain, so no block falls in between.
+        let mut events = client.subscribe().await?;
+        self.resync(client).await?;
+        self.health.report(P2P_RUNNING, Ok(()));
+        while let Some(event) = read_line(&mut events).await? {
+            match event {
+                RpcResponse::Block(block) => {
+                    if !self.apply(block) {
+                        self.resync(client).await?;
+                    }
+                }
+                RpcResponse::Error(e) => return Err(io::Error::other(e)),
+                response => tracing::warn!("Ignoring unexpected event {:?}", response),
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Keeps `replica` in step with the node behind `client`, reconnecting
+/// whenever the connection drops.
+pub async fn follow(client: RpcClient, replica: Replica) {
+    loop {
+        let reason = match replica.follow_once(&client).await {
+            Ok(()) => "The node closed the subscription".to_string(),
+            Err(e) => e.to_string(),
+        };
+        replica.health.report(P2P_RUNNING, Err(format!("Lost the node at {}: {}", client.socket.display(), reason)));
+        tokio::time::sleep(RECONNECT_DELAY).await;
+    }
+}
+
+/// Hands the blocks and transactions the API process would gossip to the
+/// node instead. Other messages only make sense between peers and are
+/// dropped.
+pub async fn forward(client: RpcClient, mut to_p2p: mpsc::UnboundedReceiver<P2pMessage>) {
+    while let Some(message) = to_p2p.recv().await {
+        let request = match message {
+            P2pMessage::Transaction(transaction) => RpcRequest::SubmitTransaction { transaction },
+            P2pMessage::Block(block) => RpcRequest::SubmitBlock { block },
+            _ => continue,
+        };
+        if let Err(e) = client.call(&request).await {
+            tracing::warn!("The node refused a forwarded message: {}", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_node_urls_must_name_a_unix_socket() {
+        assert_eq!(parse_node_url("unix:/run/sierpchain.sock"), Ok(PathBuf::from("/run/sierpchain.sock")));
+        assert!(parse_node_url("unix:").is_err());
+        assert!(parse_node_url("http://127.0.0.1:8080").is_err());
+    }
+}
@@ -0,0 +1,244 @@
+//! Consensus test vectors: blocks, transactions and a short chain built
+//! from fixed keys and a fixed clock, so that every hash and signature in
+//! them is the same on every run. `tests/consensus_vectors.rs` checks them
+//! against the fixtures committed in `VECTORS_FILE`, and `gen-vectors`
+//! rewrites that file when a consensus change is deliberate.
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::path::Path;
+use std::sync::Arc;
+
+use crate::blockchain::block::Block;
+use crate::blockchain::chain::Blockchain;
+use crate::core::clock::{Clock, MockClock};
+use crate::core::mempool::MempoolPool;
+use crate::core::script::UnlockingScript;
+use crate::core::transaction::{Transaction, TxInput, TxOutput};
+use crate::core::wallet::Wallet;
+use crate::fractal::utils::RngVersion;
+use crate::fractal::FractalType;
+use crate::mining::miner::Miner;
+
+/// The fixture file, relative to the crate root.
+pub const VECTORS_FILE: &str = "tests/fixtures/consensus_vectors.json";
+
+/// The number of blocks in the fixture chain, genesis included.
+pub const CHAIN_LENGTH: usize = 10;
+
+/// When the fixture chain's genesis block is timestamped.
+pub const GENESIS_TIME: i64 = 1_700_000_000;
+
+/// How far apart the fixture chain's blocks are timestamped, in seconds.
+pub const BLOCK_SPACING_SECS: i64 = 60;
+
+/// The difficulty the fixture chain is mined at.
+pub const DIFFICULTY: usize = 1;
+
+/// The wallet that mines the fixture chain and signs its transfers.
+pub const MINER: &str = "miner";
+/// The wallet the fixture transfers pay.
+pub const RECIPIENT: &str = "recipient";
+
+/// Returns the wallet whose signing key is the SHA-256 of `label`.
+pub fn fixed_wallet(label: &str) -> Wallet {
+    Wallet::from_seed(Sha256::digest(format!("sierpchain-vectors:{}", label)).into())
+}
+
+/// Returns a clock stopped at `GENESIS_TIME`.
+pub fn fixed_clock() -> Arc<MockClock> {
+    Arc::new(MockClock::new(GENESIS_TIME))
+}
+
+/// A transaction with the txid it must hash to and the label of the wallet
+/// that signed it.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct TransactionVector {
+    pub name: String,
+    pub signer: String,
+    pub txid: String,
+    pub transaction: Transaction,
+}
+
+/// A block that extends the fixture chain's tip, with the hash it must
+/// hash to.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct BlockVector {
+    pub name: String,
+    pub hash: String,
+    pub block: Block,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ChainVector {
+    pub tip_hash: String,
+    pub blocks: Vec<Block>,
+}
+
+/// Everything the fixture file holds besides its header.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ConsensusVectors {
+    pub transactions: Vec<TransactionVector>,
+    pub blocks: Vec<BlockVector>,
+    pub chain: ChainVector,
+}
+
+/// The fixture file: the vectors under a version that `gen-vectors` bumps
+/// whenever they change, so that the bump shows up in review.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct VectorsFile {
+    pub version: u32,
+    pub vectors: ConsensusVectors,
+}
+
+impl VectorsFile {
+    pub fn load(path: &Path) -> std::io::Result<Self> {
+        Ok(serde_json::from_str(&std::fs::read_to_string(path)?)?)
+    }
+
+    /// Reads just the version of the file at `path`, for when its vectors no
+    /// longer deserialize because their format changed.
+    pub fn load_version(path: &Path) -> Option<u32> {
+        let file: serde_json::Value = serde_json::from_str(&std::fs::read_to_string(path).ok()?).ok()?;
+        file["version"].as_u64()?.try_into().ok()
+    }
+
+    pub fn save(&self, path: &Path) -> std::io::Result<()> {
+        std::fs::write(path, serde_json::to_string_pretty(self)? + "\n")
+    }
+
+    /// Wraps freshly built `vectors`, keeping `previous`'s version if they
+    /// are unchanged and bumping it otherwise.
+    pub fn regenerate(previous: Option<&VectorsFile>, vectors: ConsensusVectors) -> Self {
+        let version = match previous {
+            Some(previous) if previous.vectors == vectors => previous.version,
+            Some(previous) => previous.version + 1,
+            None => 1,
+        };
+        Self { version, vectors }
+    }
+}
+
+/// Builds and signs a transaction from `wallet` spending the `(txid, vout,
+/// value)` outputs in `spent`, paying `amount` to `to` and the rest, less
+/// `fee`, back.
+fn transfer(
+    wallet: &Wallet,
+    spent: &[(String, usize, u64)],
+    to: &str,
+    (amount, fee): (u64, u64),
+    timestamp: i64,
+    expiry_height: Option<u64>,
+) -> Transaction {
+    let inputs = spent
+        .iter()
+        .map(|(txid, vout, _)| TxInput { txid: txid.clone(), vout: *vout, script_sig: UnlockingScript::default(), sequence: 0 })
+        .collect();
+    let value: u64 = spent.iter().map(|(_, _, value)| value).sum();
+    let outputs = vec![
+        TxOutput { value: amount, script_pub_key: to.into() },
+        TxOutput { value: value - amount - fee, script_pub_key: wallet.get_address().into() },
+    ];
+    let mut tx = Transaction::new_at(inputs, outputs, timestamp).with_expiry_height(expiry_height);
+    tx.sign(wallet);
+    tx
+}
+
+/// Mines the next block of `blockchain` with `transactions` after the
+/// coinbase, which pays the fixture miner.
+fn mine(blockchain: &mut Blockchain, clock: &MockClock, fractal_type: FractalType, transactions: Vec<Transaction>) -> Block {
+    clock.advance(BLOCK_SPACING_SECS);
+    let mut pool = MempoolPool::default().with_clock(blockchain.clock.clone()).with_deterministic_order(true);
+    for tx in transactions {
+        pool.add(tx).expect("fixture transactions are valid");
+    }
+    let transactions = Miner::assemble_transactions(blockchain, &mut pool, &fixed_wallet(MINER).get_address());
+    blockchain.add_block(fractal_type, transactions).expect("the fixture chain has a genesis block")
+}
+
+/// Returns a blockchain holding just the fixture genesis block. It mines
+/// with the LCG, so the fixtures go on pinning fractals generated with it.
+fn fixture_blockchain(clock: Arc<MockClock>) -> Blockchain {
+    let mut blockchain = Blockchain::with_clock(DIFFICULTY, clock);
+    blockchain.params.fractal_rng = RngVersion::Lcg;
+    blockchain
+}
+
+/// Rebuilds the fixture chain's blockchain from `blocks`, with a clock
+/// stopped at its tip's timestamp.
+pub fn chain_from(blocks: Vec<Block>) -> (Blockchain, Arc<MockClock>) {
+    let clock = fixed_clock();
+    let mut blockchain = fixture_blockchain(clock.clone());
+    for block in blocks.into_iter().skip(1) {
+        clock.advance(BLOCK_SPACING_SECS);
+        if let Err(e) = blockchain.add_block_from_network(block) {
+            panic!("fixture chain block is invalid: {}", e);
+        }
+    }
+    (blockchain, clock)
+}
+
+/// Builds every vector from scratch.
+pub fn build() -> ConsensusVectors {
+    let miner = fixed_wallet(MINER);
+    let recipient = fixed_wallet(RECIPIENT).get_address();
+    let clock = fixed_clock();
+    let mut blockchain = fixture_blockchain(clock.clone());
+    let mut transactions = Vec::new();
+
+    let sierpinski = FractalType::Sierpinski { depth: 5, seed: 0 };
+    mine(&mut blockchain, &clock, sierpinski.clone(), vec![]);
+    // The rest of the chain pays the recipient from each previous coinbase.
+    while blockchain.chain.len() < CHAIN_LENGTH {
+        let coinbase = &blockchain.chain.last().unwrap().transactions[0];
+        let spent = [(coinbase.id.clone(), 0, coinbase.outputs[0].value)];
+        let tx = transfer(&miner, &spent, &recipient, (1_000, 10), clock.now(), None);
+        transactions.push(TransactionVector {
+            name: format!("transfer_in_block_{}", blockchain.chain.len()),
+            signer: MINER.to_string(),
+            txid: tx.id.clone(),
+            transaction: tx.clone(),
+        });
+        mine(&mut blockchain, &clock, sierpinski.clone(), vec![tx]);
+    }
+
+    // Two inputs, the change of the last two transfers, with an expiry height.
+    let spent: Vec<_> = blockchain.chain[CHAIN_LENGTH - 2..]
+        .iter()
+        .map(|block| (block.transactions[1].id.clone(), 1, block.transactions[1].outputs[1].value))
+        .collect();
+    let multi_input = transfer(&miner, &spent, &recipient, (2_500, 25), clock.now(), Some(CHAIN_LENGTH as u64 + 5));
+    transactions.push(TransactionVector {
+        name: "two_inputs_with_expiry".to_string(),
+        signer: MINER.to_string(),
+        txid: multi_input.id.clone(),
+        transaction: multi_input.clone(),
+    });
+
+    let chain = ChainVector {
+        tip_hash: blockchain.chain.last().unwrap().hash.clone(),
+        blocks: blockchain.chain.clone(),
+    };
+
+    let fractal_types = [
+        ("sierpinski", FractalType::Sierpinski { depth: 4, seed: 0 }),
+        (
+            "mandelbrot",
+            FractalType::mandelbrot(8, 8, -2.0, 1.0, -1.5, 1.5, 20, 0).unwrap(),
+        ),
+        (
+            "julia",
+            FractalType::julia(8, 8, -1.5, 1.5, -1.5, 1.5, -0.7, 0.27015, 20, 0).unwrap(),
+        ),
+    ];
+    let blocks = fractal_types
+        .into_iter()
+        .map(|(name, fractal_type)| {
+            let (mut tip, clock) = chain_from(chain.blocks.clone());
+            let block = mine(&mut tip, &clock, fractal_type, vec![multi_input.clone()]);
+            BlockVector { name: format!("{}_with_transactions", name), hash: block.hash.clone(), block }
+        })
+        .collect();
+
+    ConsensusVectors { transactions, blocks, chain }
+}
@@ -0,0 +1,80 @@
+//! Guards block and transaction hashing against accidental changes: every
+//! hash and signature in the committed fixtures must still come out the
+//! same. If a change is deliberate, regenerate the fixtures with
+//! `cargo run --bin gen-vectors` and commit them with their version bump.
+
+use sierpchain::core::script::{LockingScript, UnlockingScript};
+use sierpchain::vectors::{self, VectorsFile, VECTORS_FILE};
+use std::path::Path;
+
+fn fixtures() -> VectorsFile {
+    VectorsFile::load(&Path::new(env!("CARGO_MANIFEST_DIR")).join(VECTORS_FILE)).expect("the fixtures are readable")
+}
+
+#[test]
+fn test_fixtures_serialize_exactly_as_committed() {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR")).join(VECTORS_FILE);
+    let committed: serde_json::Value = serde_json::from_str(&std::fs::read_to_string(path).unwrap()).unwrap();
+    assert_eq!(serde_json::to_value(fixtures()).unwrap(), committed);
+}
+
+#[test]
+fn test_transaction_ids_and_signatures_match() {
+    for vector in fixtures().vectors.transactions {
+        let tx = &vector.transaction;
+        assert_eq!(tx.calculate_hash(), vector.txid, "{}", vector.name);
+        assert_eq!(tx.id, vector.txid, "{}", vector.name);
+
+        let signer = vectors::fixed_wallet(&vector.signer);
+        let locking = LockingScript::from(signer.get_address());
+        assert_eq!(tx.verify(|_| Some(&locking)), Ok(()), "{}", vector.name);
+        for input in &tx.inputs {
+            let UnlockingScript::SignaturePubKey { pub_key, .. } = &input.script_sig else {
+                panic!("{} has an unsigned input", vector.name);
+            };
+            assert_eq!(pub_key, &hex::encode(signer.get_public_key().as_bytes()), "{}", vector.name);
+        }
+    }
+}
+
+#[test]
+fn test_fixture_chain_replays_to_its_tip() {
+    let chain = fixtures().vectors.chain;
+    assert_eq!(chain.blocks.len(), vectors::CHAIN_LENGTH);
+    for block in &chain.blocks {
+        assert_eq!(block.calculate_hash(), block.hash, "block {}", block.index);
+    }
+
+    // The genesis block is rebuilt from the fixed clock, and every later
+    // block must be accepted on top of it.
+    let (replayed, _) = vectors::chain_from(chain.blocks.clone());
+    assert_eq!(replayed.chain, chain.blocks);
+    assert_eq!(replayed.chain.last().unwrap().hash, chain.tip_hash);
+}
+
+#[test]
+fn test_blocks_of_every_fractal_type_hash_and_connect() {
+    let vectors = fixtures().vectors;
+    let names: Vec<&str> = vectors.blocks.iter().map(|vector| vector.name.as_str()).collect();
+    assert_eq!(names, ["sierpinski_with_transactions", "mandelbrot_with_transactions", "julia_with_transactions"]);
+    for vector in &vectors.blocks {
+        assert_eq!(vector.block.calculate_hash(), vector.hash, "{}", vector.name);
+        assert_eq!(vector.block.hash, vector.hash, "{}", vector.name);
+        assert!(vector.block.transactions.len() > 1, "{} has no transactions besides its coinbase", vector.name);
+
+        let (mut tip, clock) = vectors::chain_from(vectors.chain.blocks.clone());
+        clock.advance(vectors::BLOCK_SPACING_SECS);
+        assert!(tip.add_block_from_network(vector.block.clone()).is_ok(), "{} doesn't connect", vector.name);
+    }
+}
+
+#[test]
+fn test_builders_reproduce_the_fixtures() {
+    let fixtures = fixtures();
+    let rebuilt = vectors::build();
+    assert!(
+        rebuilt == fixtures.vectors,
+        "the consensus vectors changed; if that is deliberate, run `cargo run --bin gen-vectors` and commit the result"
+    );
+    assert_eq!(VectorsFile::regenerate(Some(&fixtures), rebuilt).version, fixtures.version);
+}